@@ -1 +1,766 @@
-fn main() {}
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use clap::{Args, Parser, Subcommand};
+use humansize::{format_size, DECIMAL};
+use time::{Date, OffsetDateTime};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+use parcel_cli::{
+    client::Client,
+    types::{
+        parse_date, EditUpload, ListQuery, TransferAction, Upload, UploadListItem, UploadOrder,
+    },
+};
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Command-line client for a parcel server")]
+struct Cli {
+    /// The URL of the parcel server (e.g. https://parcel.example.com)
+    #[arg(long, env = "PARCEL_SERVER")]
+    server: String,
+
+    /// An API token to authenticate with, instead of a username and password
+    #[arg(long, env = "PARCEL_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+
+    /// The username to sign in with
+    #[arg(
+        short,
+        long,
+        env = "PARCEL_USERNAME",
+        required_unless_present = "token"
+    )]
+    username: Option<String>,
+
+    /// The password to sign in with
+    #[arg(
+        short,
+        long,
+        env = "PARCEL_PASSWORD",
+        hide_env_values = true,
+        required_unless_present = "token"
+    )]
+    password: Option<String>,
+
+    /// The current TOTP code, if the user has 2FA enabled
+    #[arg(long, env = "PARCEL_TOTP")]
+    totp: Option<String>,
+
+    /// Output JSON rather than human readable text
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Enable verbose logging
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Upload one or more files
+    Upload(UploadCommand),
+    /// List uploads
+    List(ListCommand),
+    /// Download an upload
+    Download(DownloadCommand),
+    /// Get the URL at which an upload can be shared
+    Share(ShareCommand),
+    /// Delete one or more uploads
+    Delete(DeleteCommand),
+    /// Edit the settings of an upload
+    Edit(EditCommand),
+    /// Reset the remaining downloads of an upload to its limit
+    Reset(ResetCommand),
+    /// Copy or move an upload to a team
+    Transfer(TransferCommand),
+    /// List the teams you are a member of
+    Teams,
+}
+
+#[derive(Debug, Args)]
+struct UploadCommand {
+    /// The files to upload
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    /// Upload the files to a team (by team ID) rather than your own uploads
+    #[arg(long)]
+    team: Option<String>,
+
+    /// Make the uploads public
+    #[arg(long)]
+    public: bool,
+
+    /// Limit the number of times each upload can be downloaded
+    #[arg(long)]
+    limit: Option<i64>,
+
+    /// The date after which the uploads expire (YYYY-MM-DD)
+    #[arg(long, value_parser = parse_date)]
+    expiry: Option<Date>,
+
+    /// Require a password to download the uploads
+    #[arg(long)]
+    password: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct ListCommand {
+    /// List the uploads of a team (by team ID) rather than your own uploads
+    #[arg(long)]
+    team: Option<String>,
+
+    /// Only list uploads whose filename contains this text
+    #[arg(short, long)]
+    search: Option<String>,
+
+    /// The field to order the uploads by
+    #[arg(long, value_enum)]
+    order: Option<UploadOrder>,
+
+    /// Sort in ascending order
+    #[arg(long, conflicts_with = "desc")]
+    asc: bool,
+
+    /// Sort in descending order
+    #[arg(long)]
+    desc: bool,
+
+    /// The page of results to show, starting from zero
+    #[arg(long, default_value_t = 0)]
+    page: u32,
+}
+
+#[derive(Debug, Args)]
+struct DownloadCommand {
+    /// The ID of the upload
+    id: String,
+
+    /// Where to write the file (defaults to the upload's filename, use '-' for stdout)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct ShareCommand {
+    /// The ID of the upload
+    id: String,
+
+    /// Make the upload public, so it can be downloaded without signing in
+    #[arg(long)]
+    public: bool,
+}
+
+#[derive(Debug, Args)]
+struct DeleteCommand {
+    /// The IDs of the uploads to delete
+    #[arg(required = true)]
+    ids: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+struct EditCommand {
+    /// The ID of the upload
+    id: String,
+
+    /// Change the filename
+    #[arg(long)]
+    filename: Option<String>,
+
+    /// Make the upload public
+    #[arg(long, conflicts_with = "private")]
+    public: bool,
+
+    /// Make the upload private
+    #[arg(long)]
+    private: bool,
+
+    /// Limit the number of times the upload can be downloaded
+    #[arg(long, conflicts_with = "no_limit")]
+    limit: Option<i64>,
+
+    /// Remove the download limit
+    #[arg(long)]
+    no_limit: bool,
+
+    /// The date after which the upload expires (YYYY-MM-DD)
+    #[arg(long, value_parser = parse_date, conflicts_with = "no_expiry")]
+    expiry: Option<Date>,
+
+    /// Remove the expiry date
+    #[arg(long)]
+    no_expiry: bool,
+
+    /// Require a password to download the upload
+    #[arg(long, conflicts_with = "no_password")]
+    password: Option<String>,
+
+    /// Remove the download password
+    #[arg(long)]
+    no_password: bool,
+
+    /// Set a custom URL slug
+    #[arg(long, conflicts_with = "no_slug")]
+    slug: Option<String>,
+
+    /// Remove the custom URL slug
+    #[arg(long)]
+    no_slug: bool,
+}
+
+#[derive(Debug, Args)]
+struct ResetCommand {
+    /// The ID of the upload
+    id: String,
+}
+
+#[derive(Debug, Args)]
+struct TransferCommand {
+    /// The ID of the upload
+    id: String,
+
+    /// The ID of the team to transfer the upload to
+    #[arg(long)]
+    team: String,
+
+    /// Move the upload rather than copying it
+    #[arg(long = "move")]
+    move_upload: bool,
+}
+
+/// Turn a pair of "set" and "clear" arguments into a field of an [`EditUpload`].
+fn set_or_clear<T>(value: Option<T>, clear: bool) -> Option<Option<T>> {
+    if clear {
+        Some(None)
+    } else {
+        value.map(Some)
+    }
+}
+
+fn format_remaining(remaining: Option<i64>, limit: Option<i64>) -> String {
+    match (remaining, limit) {
+        (Some(remaining), Some(limit)) => format!("{remaining} / {limit}"),
+        (Some(remaining), None) => remaining.to_string(),
+        (None, _) => "∞".to_string(),
+    }
+}
+
+fn format_expiry(expiry: Option<Date>) -> String {
+    let Some(expiry) = expiry else {
+        return "Never".to_string();
+    };
+
+    let today = OffsetDateTime::now_utc().date();
+    if expiry == today {
+        return format!("{expiry} (today)");
+    }
+
+    let relative = time_humanize::HumanTime::from((expiry - today).whole_seconds());
+    format!("{expiry} ({relative})")
+}
+
+fn print_json<T: serde::Serialize>(value: &T) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn print_upload(client: &Client, upload: &Upload) {
+    println!("ID:        {}", upload.id);
+    println!("Filename:  {}", upload.filename);
+    println!("Size:      {}", format_size(upload.size as u64, DECIMAL));
+    if let Some(ref hash) = upload.hash {
+        println!("SHA-256:   {hash}");
+    }
+    println!("Public:    {}", if upload.public { "yes" } else { "no" });
+    println!(
+        "Password:  {}",
+        if upload.has_password { "yes" } else { "no" }
+    );
+    println!("Downloads: {}", upload.downloads);
+    println!(
+        "Remaining: {}",
+        format_remaining(upload.remaining, upload.limit)
+    );
+    println!("Expiry:    {}", format_expiry(upload.expiry_date));
+    println!("URL:       {}", client.share_url(upload));
+}
+
+fn print_upload_table(uploads: &[UploadListItem]) {
+    let headers = ["ID", "Filename", "Size", "Downloads", "Remaining", "Expiry"];
+    let rows = uploads
+        .iter()
+        .map(|upload| {
+            [
+                upload.id.clone(),
+                upload.filename.clone(),
+                format_size(upload.size as u64, DECIMAL),
+                upload.downloads.to_string(),
+                format_remaining(upload.remaining, upload.limit),
+                format_expiry(upload.expiry_date),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let mut widths = headers.map(|header| header.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let print_row = |cells: &[&str]| {
+        let line = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    };
+
+    print_row(&headers);
+    for row in &rows {
+        print_row(&row.each_ref().map(String::as_str));
+    }
+}
+
+fn upload(client: &Client, json: bool, command: UploadCommand) -> anyhow::Result<()> {
+    let UploadCommand {
+        files,
+        team,
+        public,
+        limit,
+        expiry,
+        password,
+    } = command;
+
+    let mut uploads = client
+        .upload_files(&files, team.as_deref())
+        .context("failed to upload files")?;
+
+    let edit = EditUpload {
+        public: public.then_some(true),
+        limit: limit.map(Some),
+        expiry_date: expiry.map(Some),
+        password: password.map(Some),
+        ..Default::default()
+    };
+
+    if !edit.is_empty() {
+        for upload in uploads.iter_mut() {
+            *upload = client
+                .edit_upload(&upload.id, &edit)
+                .with_context(|| format!("failed to update upload {}", upload.id))?;
+        }
+    }
+
+    if json {
+        return print_json(&uploads);
+    }
+
+    for upload in &uploads {
+        println!("{}  {}", upload.id, client.share_url(upload));
+    }
+
+    Ok(())
+}
+
+fn list(client: &Client, json: bool, command: ListCommand) -> anyhow::Result<()> {
+    let query = ListQuery {
+        team: command.team,
+        search: command.search,
+        order: command.order,
+        asc: if command.asc {
+            Some(true)
+        } else if command.desc {
+            Some(false)
+        } else {
+            None
+        },
+        page: command.page,
+    };
+
+    let page = client
+        .list_uploads(&query)
+        .context("failed to list uploads")?;
+
+    if json {
+        return print_json(&page);
+    }
+
+    if page.uploads.is_empty() {
+        println!("No uploads found");
+        return Ok(());
+    }
+
+    print_upload_table(&page.uploads);
+    Ok(())
+}
+
+fn download(client: &Client, command: DownloadCommand) -> anyhow::Result<()> {
+    let output = match command.output {
+        Some(output) => output,
+        None => {
+            let upload = client
+                .get_upload(&command.id)
+                .context("failed to get upload")?;
+
+            // Only use the final component of the filename, so that a server cannot make us
+            // write outside of the current directory.
+            std::path::Path::new(&upload.filename)
+                .file_name()
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from(&upload.slug))
+        }
+    };
+
+    let size = if output.as_os_str() == "-" {
+        let mut stdout = std::io::stdout().lock();
+        let size = client
+            .download_upload(&command.id, &mut stdout)
+            .context("failed to download upload")?;
+        stdout.flush()?;
+        size
+    } else {
+        let file = File::create(&output)
+            .with_context(|| format!("failed to create file {}", output.display()))?;
+        let mut writer = BufWriter::new(file);
+        let size = client
+            .download_upload(&command.id, &mut writer)
+            .context("failed to download upload")?;
+        writer.flush()?;
+
+        eprintln!(
+            "Downloaded {} to {}",
+            format_size(size, DECIMAL),
+            output.display()
+        );
+
+        size
+    };
+
+    tracing::debug!(size, "Download complete");
+    Ok(())
+}
+
+fn share(client: &Client, json: bool, command: ShareCommand) -> anyhow::Result<()> {
+    let upload = if command.public {
+        let edit = EditUpload {
+            public: Some(true),
+            ..Default::default()
+        };
+
+        client
+            .edit_upload(&command.id, &edit)
+            .context("failed to make upload public")?
+    } else {
+        client
+            .get_upload(&command.id)
+            .context("failed to get upload")?
+    };
+
+    let url = client.share_url(&upload);
+    if json {
+        return print_json(&serde_json::json!({
+            "id": upload.id,
+            "public": upload.public,
+            "url": url,
+        }));
+    }
+
+    if !upload.public {
+        eprintln!("Note: this upload is not public; use '--public' to allow anyone to download it");
+    }
+
+    println!("{url}");
+    Ok(())
+}
+
+fn delete(client: &Client, json: bool, command: DeleteCommand) -> anyhow::Result<()> {
+    for id in &command.ids {
+        client
+            .delete_upload(id)
+            .with_context(|| format!("failed to delete upload {id}"))?;
+
+        if !json {
+            println!("Deleted {id}");
+        }
+    }
+
+    if json {
+        return print_json(&serde_json::json!({ "deleted": command.ids }));
+    }
+
+    Ok(())
+}
+
+fn edit(client: &Client, json: bool, command: EditCommand) -> anyhow::Result<()> {
+    let edit = EditUpload {
+        filename: command.filename,
+        public: if command.public {
+            Some(true)
+        } else if command.private {
+            Some(false)
+        } else {
+            None
+        },
+        limit: set_or_clear(command.limit, command.no_limit),
+        expiry_date: set_or_clear(command.expiry, command.no_expiry),
+        password: set_or_clear(command.password, command.no_password),
+        custom_slug: set_or_clear(command.slug, command.no_slug),
+    };
+
+    let upload = if edit.is_empty() {
+        client.get_upload(&command.id)
+    } else {
+        client.edit_upload(&command.id, &edit)
+    }
+    .context("failed to edit upload")?;
+
+    if json {
+        return print_json(&upload);
+    }
+
+    print_upload(client, &upload);
+    Ok(())
+}
+
+fn reset(client: &Client, json: bool, command: ResetCommand) -> anyhow::Result<()> {
+    let upload = client
+        .reset_upload(&command.id)
+        .context("failed to reset upload")?;
+
+    if json {
+        return print_json(&upload);
+    }
+
+    print_upload(client, &upload);
+    Ok(())
+}
+
+fn transfer(client: &Client, json: bool, command: TransferCommand) -> anyhow::Result<()> {
+    let action = if command.move_upload {
+        TransferAction::Move
+    } else {
+        TransferAction::Copy
+    };
+
+    let upload = client
+        .transfer_upload(&command.id, &command.team, action)
+        .context("failed to transfer upload")?;
+
+    if json {
+        return print_json(&upload);
+    }
+
+    print_upload(client, &upload);
+    Ok(())
+}
+
+fn teams(client: &Client, json: bool) -> anyhow::Result<()> {
+    let teams = client.list_teams().context("failed to list teams")?;
+
+    if json {
+        return print_json(&teams);
+    }
+
+    if teams.is_empty() {
+        println!("You are not a member of any teams");
+        return Ok(());
+    }
+
+    for team in &teams {
+        println!("{}  {} ({})", team.id, team.name, team.slug);
+    }
+
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    {
+        let fmt = tracing_subscriber::fmt::layer().with_writer(std::io::stderr);
+        let sub = tracing_subscriber::registry()
+            .with(tracing_subscriber::EnvFilter::new(match cli.verbose {
+                0 => std::env::var("RUST_LOG").unwrap_or_else(|_| "warn".into()),
+                1 => "debug".into(),
+                _ => "trace".into(),
+            }))
+            .with(fmt);
+        sub.init();
+    }
+
+    let client = match (cli.token, cli.username, cli.password) {
+        (Some(token), _, _) => Client::with_token(&cli.server, &token),
+        (None, Some(username), Some(password)) => {
+            Client::new(&cli.server, &username, &password, cli.totp)
+        }
+        _ => anyhow::bail!("Either an API token or a username and password are required"),
+    };
+    tracing::debug!(server = client.server(), "Created client");
+
+    match cli.command {
+        Command::Upload(command) => upload(&client, cli.json, command),
+        Command::List(command) => list(&client, cli.json, command),
+        Command::Download(command) => download(&client, command),
+        Command::Share(command) => share(&client, cli.json, command),
+        Command::Delete(command) => delete(&client, cli.json, command),
+        Command::Edit(command) => edit(&client, cli.json, command),
+        Command::Reset(command) => reset(&client, cli.json, command),
+        Command::Transfer(command) => transfer(&client, cli.json, command),
+        Command::Teams => teams(&client, cli.json),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{error::ErrorKind, Parser};
+
+    use super::*;
+
+    const SERVER: [&str; 3] = ["parcel", "--server", "https://parcel.example.com"];
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(SERVER.iter().chain(args))
+    }
+
+    #[test]
+    fn test_credentials() {
+        let cli = parse(&["--token", "secret", "teams"]).unwrap();
+        assert_eq!(cli.token.as_deref(), Some("secret"));
+        assert!(cli.username.is_none());
+
+        let cli = parse(&["-u", "user", "-p", "password", "--totp", "123456", "teams"]).unwrap();
+        assert_eq!(cli.username.as_deref(), Some("user"));
+        assert_eq!(cli.password.as_deref(), Some("password"));
+        assert_eq!(cli.totp.as_deref(), Some("123456"));
+
+        let err = parse(&["-u", "user", "teams"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_upload() {
+        let cli = parse(&[
+            "--token",
+            "secret",
+            "upload",
+            "a.txt",
+            "b.txt",
+            "--public",
+            "--limit",
+            "3",
+            "--expiry",
+            "2030-01-31",
+        ])
+        .unwrap();
+
+        let Command::Upload(command) = cli.command else {
+            panic!("expected upload command");
+        };
+
+        assert_eq!(
+            command.files,
+            [PathBuf::from("a.txt"), PathBuf::from("b.txt")]
+        );
+        assert!(command.public);
+        assert_eq!(command.limit, Some(3));
+        assert_eq!(
+            command.expiry,
+            Some(Date::from_calendar_date(2030, time::Month::January, 31).unwrap())
+        );
+
+        let err = parse(&["--token", "secret", "upload"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::MissingRequiredArgument);
+
+        let err = parse(&[
+            "--token",
+            "secret",
+            "upload",
+            "a.txt",
+            "--expiry",
+            "31/01/2030",
+        ])
+        .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn test_global_flags() {
+        // The output flags can be given after the subcommand.
+        let cli = parse(&["--token", "secret", "list", "--json", "-vv"]).unwrap();
+        assert!(cli.json);
+        assert_eq!(cli.verbose, 2);
+
+        let Command::List(command) = cli.command else {
+            panic!("expected list command");
+        };
+
+        assert_eq!(command.page, 0);
+        assert!(command.order.is_none());
+
+        let err = parse(&["--token", "secret", "list", "--asc", "--desc"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
+
+        let err = parse(&["--token", "secret", "list", "--order", "colour"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidValue);
+    }
+
+    #[test]
+    fn test_edit() {
+        let cli = parse(&[
+            "--token",
+            "secret",
+            "edit",
+            "id",
+            "--limit",
+            "5",
+            "--no-expiry",
+        ])
+        .unwrap();
+        let Command::Edit(command) = cli.command else {
+            panic!("expected edit command");
+        };
+
+        assert_eq!(set_or_clear(command.limit, command.no_limit), Some(Some(5)));
+        assert_eq!(set_or_clear(command.expiry, command.no_expiry), Some(None));
+        assert_eq!(set_or_clear(command.password, command.no_password), None);
+
+        for conflict in [
+            ["--public", "--private"],
+            ["--limit=5", "--no-limit"],
+            ["--password=secret", "--no-password"],
+            ["--slug=report", "--no-slug"],
+        ] {
+            let err =
+                parse(&["--token", "secret", "edit", "id", conflict[0], conflict[1]]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ArgumentConflict, "{conflict:?}");
+        }
+    }
+
+    #[test]
+    fn test_transfer() {
+        let cli = parse(&[
+            "--token", "secret", "transfer", "id", "--team", "team", "--move",
+        ])
+        .unwrap();
+        let Command::Transfer(command) = cli.command else {
+            panic!("expected transfer command");
+        };
+
+        assert_eq!(command.team, "team");
+        assert!(command.move_upload);
+
+        let err = parse(&["--token", "secret", "transfer", "id"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::MissingRequiredArgument);
+    }
+}
@@ -0,0 +1,31 @@
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("{message} (HTTP {status})")]
+    Api { status: u16, message: String },
+    #[error("request failed: {0}")]
+    Transport(String),
+    #[error("invalid response from server: {0}")]
+    InvalidResponse(std::io::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl From<ureq::Error> for ClientError {
+    fn from(err: ureq::Error) -> Self {
+        match err {
+            ureq::Error::Status(status, response) => {
+                let message = response
+                    .into_json::<serde_json::Value>()
+                    .ok()
+                    .and_then(|body| body.get("error")?.as_str().map(ToString::to_string))
+                    .unwrap_or_else(|| "Unexpected response from server".to_string());
+
+                ClientError::Api { status, message }
+            }
+
+            ureq::Error::Transport(transport) => ClientError::Transport(transport.to_string()),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
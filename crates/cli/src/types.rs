@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize, Serializer};
+use time::{Date, OffsetDateTime};
+
+time::serde::format_description!(iso8601_date, Date, "[year]-[month]-[day]");
+
+/// Parse a date in the `YYYY-MM-DD` format used by the API.
+pub fn parse_date(value: &str) -> Result<Date, time::error::Parse> {
+    Date::parse(
+        value,
+        time::macros::format_description!("[year]-[month]-[day]"),
+    )
+}
+
+/// The order in which uploads are listed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadOrder {
+    Filename,
+    Size,
+    Downloads,
+    ExpiryDate,
+    UploadedAt,
+}
+
+impl UploadOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Filename => "filename",
+            Self::Size => "size",
+            Self::Downloads => "downloads",
+            Self::ExpiryDate => "expiry_date",
+            Self::UploadedAt => "uploaded_at",
+        }
+    }
+}
+
+/// An upload, as returned by the API when getting, creating or editing an upload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Upload {
+    pub id: String,
+    pub slug: String,
+    pub filename: String,
+    pub size: i64,
+    pub hash: Option<String>,
+    pub public: bool,
+    pub has_password: bool,
+    pub downloads: i64,
+    pub limit: Option<i64>,
+    pub remaining: Option<i64>,
+    #[serde(with = "iso8601_date::option")]
+    pub expiry_date: Option<Date>,
+    pub custom_slug: Option<String>,
+    pub owner_user: Option<String>,
+    pub owner_team: Option<String>,
+    pub uploaded_by: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub uploaded_at: OffsetDateTime,
+    pub mime_type: Option<String>,
+}
+
+/// An upload, as returned by the API when listing uploads.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadListItem {
+    pub id: String,
+    pub slug: String,
+    pub filename: String,
+    pub size: i64,
+    pub public: bool,
+    pub has_password: bool,
+    pub downloads: i64,
+    pub limit: Option<i64>,
+    pub remaining: Option<i64>,
+    #[serde(with = "iso8601_date::option")]
+    pub expiry_date: Option<Date>,
+    pub custom_slug: Option<String>,
+    pub owner_slug: String,
+    pub uploaded_by_name: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub uploaded_at: OffsetDateTime,
+}
+
+/// A page of uploads.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadPage {
+    pub page: u32,
+    pub per_page: u32,
+    pub uploads: Vec<UploadListItem>,
+}
+
+/// A team that the user is a member of, along with the user's permissions in that team.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Team {
+    pub id: String,
+    pub name: String,
+    pub slug: String,
+    pub limit: Option<i64>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    pub can_edit: bool,
+    pub can_delete: bool,
+    pub can_config: bool,
+}
+
+/// Whether an upload is copied or moved when transferring it to a team.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferAction {
+    Copy,
+    Move,
+}
+
+/// The parameters used when listing uploads.
+#[derive(Debug, Default, Clone)]
+pub struct ListQuery {
+    pub team: Option<String>,
+    pub search: Option<String>,
+    pub order: Option<UploadOrder>,
+    pub asc: Option<bool>,
+    pub page: u32,
+}
+
+/// Changes to make to an upload.
+///
+/// Fields that are `None` are left unchanged. Fields that are `Some(None)` are cleared.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct EditUpload {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<Option<i64>>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_expiry_date"
+    )]
+    pub expiry_date: Option<Option<Date>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub custom_slug: Option<Option<String>>,
+}
+
+impl EditUpload {
+    pub fn is_empty(&self) -> bool {
+        self.filename.is_none()
+            && self.public.is_none()
+            && self.limit.is_none()
+            && self.expiry_date.is_none()
+            && self.password.is_none()
+            && self.custom_slug.is_none()
+    }
+}
+
+fn serialize_expiry_date<S: Serializer>(
+    value: &Option<Option<Date>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    iso8601_date::option::serialize(&value.flatten(), serializer)
+}
@@ -0,0 +1,24 @@
+-- Create a table for the personal API tokens that users can create for non-interactive access.
+CREATE TABLE api_tokens (
+  id TEXT NOT NULL PRIMARY KEY,
+  user TEXT NOT NULL REFERENCES users (id),
+  name TEXT NOT NULL,
+  -- We only store the SHA-256 hash of a token, along with a short prefix to help identify it.
+  token_hash TEXT NOT NULL,
+  prefix TEXT NOT NULL,
+  -- A token is either personal (no team), or restricted to the uploads of a single team.
+  team TEXT REFERENCES teams (id),
+  can_read BOOLEAN NOT NULL,
+  can_upload BOOLEAN NOT NULL,
+  can_delete BOOLEAN NOT NULL,
+  expiry_date DATE,
+  created_at TIMESTAMP NOT NULL,
+  last_used TIMESTAMP,
+  revoked_at TIMESTAMP
+);
+
+-- Tokens are looked up by their hash when authenticating API requests.
+CREATE UNIQUE INDEX api_tokens_token_hash_uindex ON api_tokens (token_hash);
+
+-- Index for listing the tokens that belong to a user.
+CREATE INDEX api_tokens_user_idx ON api_tokens (user);
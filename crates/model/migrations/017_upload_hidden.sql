@@ -0,0 +1,2 @@
+-- Add a column to the 'uploads' table to record when an expired or exhausted upload was hidden
+ALTER TABLE uploads ADD COLUMN hidden_at TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,18 @@
+-- Create a table to track resumable uploads that have been started but not yet completed.
+CREATE TABLE upload_sessions (
+  id TEXT NOT NULL PRIMARY KEY,
+  filename TEXT NOT NULL,
+  -- The total size of the file, and the number of bytes that have been received so far.
+  length BIGINT NOT NULL,
+  "offset" BIGINT NOT NULL,
+  -- As with uploads, a session is owned by either a user or a team.
+  owner_user TEXT REFERENCES users (id),
+  owner_team TEXT REFERENCES teams (id),
+  uploaded_by TEXT NOT NULL REFERENCES users (id),
+  remote_addr TEXT,
+  created_at TIMESTAMP NOT NULL,
+  expires_at TIMESTAMP NOT NULL
+);
+
+-- Index for finding the sessions that have been abandoned.
+CREATE INDEX upload_sessions_expires_at_idx ON upload_sessions (expires_at);
@@ -0,0 +1,24 @@
+-- Create a table for the files in storage, which are shared by all the uploads with the same content.
+CREATE TABLE blobs (
+  -- The key of the file in storage, which is the same as the hash for all but the existing uploads.
+  key TEXT NOT NULL PRIMARY KEY,
+  -- The SHA-256 hash of the content, which is only unknown for files uploaded before blobs existed.
+  hash TEXT,
+  size BIGINT NOT NULL,
+  -- The number of uploads that share this blob.
+  refs BIGINT NOT NULL,
+  created_at TIMESTAMP NOT NULL
+);
+
+-- Blobs are looked up by their hash when a file is uploaded.
+CREATE UNIQUE INDEX blobs_hash_uindex ON blobs (hash);
+
+-- Existing uploads were stored under their slug, so each becomes a blob with that key.
+INSERT INTO blobs (key, hash, size, refs, created_at)
+SELECT slug, NULL, size, 1, uploaded_at FROM uploads;
+
+ALTER TABLE uploads ADD COLUMN blob TEXT REFERENCES blobs (key);
+UPDATE uploads SET blob = slug;
+
+-- Index for finding the uploads that share a blob.
+CREATE INDEX uploads_blob_idx ON uploads (blob);
@@ -0,0 +1,5 @@
+-- Add the SHA-256 hash of the content of each upload, so that it can be verified by recipients.
+ALTER TABLE uploads ADD COLUMN hash TEXT;
+
+-- Uploads that share a blob with a known hash have the same content.
+UPDATE uploads SET hash = (SELECT hash FROM blobs WHERE blobs.key = uploads.blob);
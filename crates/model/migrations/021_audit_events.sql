@@ -0,0 +1,25 @@
+-- Create a table to record security-relevant and data-changing events.
+--
+-- Users, teams and uploads may be deleted after an event is recorded, so none of the columns here
+-- reference other tables.
+CREATE TABLE audit_events (
+  id TEXT NOT NULL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL,
+  -- The user that performed the action, who is the administrator when masquerading.
+  actor TEXT,
+  -- The user that the action was performed as, which differs from the actor when masquerading.
+  effective_user TEXT,
+  action TEXT NOT NULL,
+  -- What the action was performed on, such as 'upload', and its ID.
+  target_kind TEXT,
+  target_id TEXT,
+  -- A JSON object with the details of the change, usually with 'before' and 'after' fields.
+  details TEXT,
+  remote_addr TEXT
+);
+
+-- Index for listing the most recent events.
+CREATE INDEX audit_events_created_at_idx ON audit_events (created_at);
+
+-- Index for finding the events for a particular target.
+CREATE INDEX audit_events_target_idx ON audit_events (target_kind, target_id);
@@ -0,0 +1,22 @@
+-- Create a table to record each download of an upload.
+--
+-- The history of an upload is deleted along with it, but the record of a download is kept when the
+-- user that downloaded it is deleted.
+CREATE TABLE downloads (
+  id TEXT NOT NULL PRIMARY KEY,
+  upload TEXT NOT NULL REFERENCES uploads (id) ON DELETE CASCADE,
+  -- The user that downloaded the upload, if they were signed in.
+  user TEXT REFERENCES users (id) ON DELETE SET NULL,
+  downloaded_at TIMESTAMP NOT NULL,
+  remote_addr TEXT,
+  user_agent TEXT,
+  -- The number of bytes sent to the client, and whether the whole response was sent.
+  bytes_sent BIGINT NOT NULL,
+  completed BOOLEAN NOT NULL
+);
+
+-- Index for listing the downloads of an upload.
+CREATE INDEX downloads_upload_idx ON downloads (upload, downloaded_at);
+
+-- Index for listing the most recent downloads.
+CREATE INDEX downloads_downloaded_at_idx ON downloads (downloaded_at);
@@ -0,0 +1,27 @@
+-- Create a table for share links, which each give access to an upload with their own password,
+-- download limit and expiry date, and which can be revoked separately.
+CREATE TABLE share_links (
+  id TEXT NOT NULL PRIMARY KEY,
+  upload TEXT NOT NULL REFERENCES uploads (id) ON DELETE CASCADE,
+  -- The random token that is used in the URL of the link.
+  token TEXT NOT NULL,
+  -- A name for the link, such as who it was sent to.
+  name TEXT NOT NULL,
+  password TEXT,
+  "limit" BIGINT,
+  remaining BIGINT,
+  expiry_date DATE,
+  downloads BIGINT NOT NULL,
+  created_by TEXT REFERENCES users (id) ON DELETE SET NULL,
+  created_at TIMESTAMP NOT NULL,
+  revoked_at TIMESTAMP
+);
+
+-- Links are looked up by their token when they are followed.
+CREATE UNIQUE INDEX share_links_token_uindex ON share_links (token);
+
+-- Index for listing the links to an upload.
+CREATE INDEX share_links_upload_idx ON share_links (upload);
+
+-- Record which link, if any, was used for each download.
+ALTER TABLE downloads ADD COLUMN share_link TEXT REFERENCES share_links (id) ON DELETE SET NULL;
@@ -0,0 +1,29 @@
+-- Create a table for bundles, which group several uploads together so that they can be shared with
+-- a single link and downloaded together as an archive.
+--
+-- A bundle has its own public flag, password, download limit and expiry date, just as an upload.
+CREATE TABLE bundles (
+  id TEXT NOT NULL PRIMARY KEY,
+  slug TEXT NOT NULL,
+  name TEXT NOT NULL,
+  public BOOLEAN NOT NULL,
+  downloads BIGINT NOT NULL,
+  "limit" BIGINT,
+  remaining BIGINT,
+  expiry_date DATE,
+  password TEXT,
+  owner_user TEXT REFERENCES users (id) ON DELETE CASCADE,
+  owner_team TEXT REFERENCES teams (id) ON DELETE CASCADE,
+  created_by TEXT REFERENCES users (id) ON DELETE SET NULL,
+  created_at TIMESTAMP NOT NULL
+);
+
+-- Bundles are identified by their "slug" in the URL of their share page.
+CREATE UNIQUE INDEX bundles_slug_uindex ON bundles (slug);
+
+-- Add the bundle that an upload belongs to, if any. Deleting a bundle leaves its uploads in place.
+ALTER TABLE uploads
+  ADD COLUMN bundle TEXT REFERENCES bundles (id) ON DELETE SET NULL;
+
+-- Index for listing the uploads in a bundle.
+CREATE INDEX uploads_bundle_idx ON uploads (bundle);
@@ -0,0 +1,31 @@
+-- Create a table for folders, which organise the uploads of a user or team into a hierarchy.
+--
+-- A folder that is shared gives access to the uploads in it, and in any folders inside it, through
+-- its share page.
+CREATE TABLE folders (
+  id TEXT NOT NULL PRIMARY KEY,
+  slug TEXT NOT NULL,
+  name TEXT NOT NULL,
+  -- The folder that contains this folder, or NULL for a folder at the top level.
+  parent TEXT REFERENCES folders (id) ON DELETE CASCADE,
+  public BOOLEAN NOT NULL,
+  owner_user TEXT REFERENCES users (id) ON DELETE CASCADE,
+  owner_team TEXT REFERENCES teams (id) ON DELETE CASCADE,
+  created_by TEXT REFERENCES users (id) ON DELETE SET NULL,
+  created_at TIMESTAMP NOT NULL
+);
+
+-- Folders are identified by their "slug" in the URL of their share page.
+CREATE UNIQUE INDEX folders_slug_uindex ON folders (slug);
+
+-- Indices for listing the folders inside a folder.
+CREATE INDEX folders_parent_idx ON folders (parent);
+CREATE INDEX folders_owner_user_idx ON folders (owner_user);
+CREATE INDEX folders_owner_team_idx ON folders (owner_team);
+
+-- Add the folder that an upload is in, or NULL for an upload at the top level.
+ALTER TABLE uploads
+  ADD COLUMN folder TEXT REFERENCES folders (id) ON DELETE SET NULL;
+
+-- Index for listing the uploads in a folder.
+CREATE INDEX uploads_folder_idx ON uploads (folder);
@@ -0,0 +1,25 @@
+-- Create a table for the tags that users and teams give to their uploads.
+CREATE TABLE upload_tags (
+  upload TEXT NOT NULL REFERENCES uploads (id) ON DELETE CASCADE,
+  tag TEXT NOT NULL,
+  PRIMARY KEY (upload, tag)
+);
+
+-- Index for filtering uploads by tag.
+CREATE INDEX upload_tags_tag_idx ON upload_tags (tag);
+
+-- Create a table for the named filters that users save for their uploads, or for the uploads of a
+-- team, which are shown as tabs above the list of uploads.
+CREATE TABLE saved_filters (
+  id TEXT NOT NULL PRIMARY KEY,
+  name TEXT NOT NULL,
+  -- The filter, encoded as the query string of the upload list.
+  query TEXT NOT NULL,
+  owner_user TEXT REFERENCES users (id) ON DELETE CASCADE,
+  owner_team TEXT REFERENCES teams (id) ON DELETE CASCADE,
+  created_by TEXT REFERENCES users (id) ON DELETE SET NULL,
+  created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX saved_filters_owner_user_idx ON saved_filters (owner_user);
+CREATE INDEX saved_filters_owner_team_idx ON saved_filters (owner_team);
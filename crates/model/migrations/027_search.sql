@@ -0,0 +1,54 @@
+-- Create a full-text index over the uploads, covering the filename, custom slug, tags and any text
+-- that has been extracted from the content of the upload.
+CREATE VIRTUAL TABLE upload_search USING fts5 (
+  upload UNINDEXED,
+  filename,
+  custom_slug,
+  tags,
+  content,
+  tokenize = 'unicode61 remove_diacritics 2'
+);
+
+-- Index the existing uploads. Their content is indexed once the text has been extracted.
+INSERT INTO upload_search (upload, filename, custom_slug, tags, content)
+  SELECT
+    uploads.id,
+    uploads.filename,
+    COALESCE(uploads.custom_slug, ''),
+    COALESCE((SELECT group_concat(upload_tags.tag, ' ') FROM upload_tags
+      WHERE upload_tags.upload = uploads.id), ''),
+    ''
+  FROM uploads;
+
+-- Keep the index up to date as uploads are added, changed and deleted.
+CREATE TRIGGER uploads_search_insert AFTER INSERT ON uploads BEGIN
+  INSERT INTO upload_search (upload, filename, custom_slug, tags, content)
+    VALUES (NEW.id, NEW.filename, COALESCE(NEW.custom_slug, ''), '', '');
+END;
+
+CREATE TRIGGER uploads_search_update AFTER UPDATE OF filename, custom_slug ON uploads BEGIN
+  UPDATE upload_search
+    SET filename = NEW.filename, custom_slug = COALESCE(NEW.custom_slug, '')
+    WHERE upload = NEW.id;
+END;
+
+CREATE TRIGGER uploads_search_delete AFTER DELETE ON uploads BEGIN
+  DELETE FROM upload_search WHERE upload = OLD.id;
+END;
+
+CREATE TRIGGER upload_tags_search_insert AFTER INSERT ON upload_tags BEGIN
+  UPDATE upload_search
+    SET tags = (SELECT group_concat(tag, ' ') FROM upload_tags WHERE upload = NEW.upload)
+    WHERE upload = NEW.upload;
+END;
+
+CREATE TRIGGER upload_tags_search_delete AFTER DELETE ON upload_tags BEGIN
+  UPDATE upload_search
+    SET tags = COALESCE((SELECT group_concat(tag, ' ') FROM upload_tags WHERE upload = OLD.upload), '')
+    WHERE upload = OLD.upload;
+END;
+
+-- Record whether the text of an upload has been extracted for the index, so that the preview
+-- worker knows which uploads still need it. This is also set when no text could be extracted.
+ALTER TABLE uploads
+  ADD COLUMN text_extracted BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,35 @@
+-- Create a table for upload requests, which are links that let anyone upload files to a user or a
+-- team, with their own limits on the files that can be uploaded.
+CREATE TABLE upload_requests (
+  id TEXT NOT NULL PRIMARY KEY,
+  -- The random token that is used in the URL of the request.
+  token TEXT NOT NULL,
+  -- A name for the request, such as who it was sent to.
+  name TEXT NOT NULL,
+  owner_user TEXT REFERENCES users (id) ON DELETE CASCADE,
+  owner_team TEXT REFERENCES teams (id) ON DELETE CASCADE,
+  password TEXT,
+  -- The maximum number of files that can be uploaded through the request.
+  max_files BIGINT,
+  -- The maximum size of each file, in bytes.
+  max_size BIGINT,
+  -- The MIME types that can be uploaded, separated by commas, such as 'image/*,application/pdf'.
+  mime_types TEXT,
+  expiry_date DATE,
+  -- The number of files that have been uploaded through the request.
+  files BIGINT NOT NULL,
+  created_by TEXT REFERENCES users (id) ON DELETE SET NULL,
+  created_at TIMESTAMP NOT NULL,
+  revoked_at TIMESTAMP
+);
+
+-- Requests are looked up by their token when they are followed.
+CREATE UNIQUE INDEX upload_requests_token_uindex ON upload_requests (token);
+
+-- Indices for listing the requests of a user or a team.
+CREATE INDEX upload_requests_owner_user_idx ON upload_requests (owner_user);
+CREATE INDEX upload_requests_owner_team_idx ON upload_requests (owner_team);
+
+-- Record which request, if any, each upload was received through.
+ALTER TABLE uploads
+  ADD COLUMN upload_request TEXT REFERENCES upload_requests (id) ON DELETE SET NULL;
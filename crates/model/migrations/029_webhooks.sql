@@ -0,0 +1,54 @@
+-- Create a table for webhooks, which notify another service when something happens to the uploads
+-- of a user or a team.
+CREATE TABLE webhooks (
+  id TEXT NOT NULL PRIMARY KEY,
+  name TEXT NOT NULL,
+  -- The URL that each event is sent to in a POST request.
+  url TEXT NOT NULL,
+  -- The secret that is used to sign the body of each request.
+  secret TEXT NOT NULL,
+  -- The events that are sent to the webhook, separated by commas, such as 'upload.created'.
+  events TEXT NOT NULL,
+  owner_user TEXT REFERENCES users (id) ON DELETE CASCADE,
+  owner_team TEXT REFERENCES teams (id) ON DELETE CASCADE,
+  created_by TEXT REFERENCES users (id) ON DELETE SET NULL,
+  created_at TIMESTAMP NOT NULL
+);
+
+-- Indices for finding the webhooks of a user or a team.
+CREATE INDEX webhooks_owner_user_idx ON webhooks (owner_user);
+CREATE INDEX webhooks_owner_team_idx ON webhooks (owner_team);
+
+-- Create a table for the deliveries of events to webhooks. Each delivery is queued when the event
+-- happens, and is then retried until it succeeds or runs out of attempts.
+CREATE TABLE webhook_deliveries (
+  id TEXT NOT NULL PRIMARY KEY,
+  webhook TEXT NOT NULL REFERENCES webhooks (id) ON DELETE CASCADE,
+  event TEXT NOT NULL,
+  -- The JSON body that is sent to the webhook.
+  payload TEXT NOT NULL,
+  -- One of 'pending', 'delivered' or 'failed'.
+  status TEXT NOT NULL,
+  attempts INTEGER NOT NULL,
+  -- When the next attempt is due, if the delivery is still pending.
+  next_attempt_at TIMESTAMP,
+  last_attempt_at TIMESTAMP,
+  -- The HTTP status code of the last response, if there was one.
+  response_status INTEGER,
+  -- Why the last attempt failed, if it did.
+  error TEXT,
+  created_at TIMESTAMP NOT NULL
+);
+
+-- The delivery worker looks for pending deliveries that are due.
+CREATE INDEX webhook_deliveries_status_idx ON webhook_deliveries (status, next_attempt_at);
+
+-- The delivery log lists the most recent deliveries of a webhook.
+CREATE INDEX webhook_deliveries_webhook_idx ON webhook_deliveries (webhook, created_at);
+
+-- Record the expiry date that webhooks were last told about, so that each upload is only reported
+-- as expired once, unless its expiry date is changed.
+ALTER TABLE uploads ADD COLUMN notified_expiry DATE;
+
+-- Uploads that have already expired are not reported.
+UPDATE uploads SET notified_expiry = expiry_date WHERE expiry_date < DATE('now');
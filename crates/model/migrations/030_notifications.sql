@@ -0,0 +1,20 @@
+-- Add an email address to each user, which is used to send them notifications when SMTP has been
+-- configured, along with the notifications that they would like to receive.
+ALTER TABLE users ADD COLUMN email TEXT;
+
+-- Notify the user when one of their uploads is downloaded by someone else.
+ALTER TABLE users ADD COLUMN notify_downloads BOOLEAN NOT NULL DEFAULT TRUE;
+
+-- Notify the user when one of their uploads or share links is about to expire.
+ALTER TABLE users ADD COLUMN notify_expiring BOOLEAN NOT NULL DEFAULT TRUE;
+
+-- Notify the user when a file is uploaded through one of their upload requests.
+ALTER TABLE users ADD COLUMN notify_requests BOOLEAN NOT NULL DEFAULT TRUE;
+
+-- Notify the user, if they are an administrator, when an account is locked out.
+ALTER TABLE users ADD COLUMN notify_lockouts BOOLEAN NOT NULL DEFAULT TRUE;
+
+-- Record the expiry date that the owner was last warned about, so that each upload and share link
+-- is only reported as expiring once, unless its expiry date is changed.
+ALTER TABLE uploads ADD COLUMN notified_expiring DATE;
+ALTER TABLE share_links ADD COLUMN notified_expiring DATE;
@@ -0,0 +1,5 @@
+-- The subject identifier of the account at the OpenID Connect provider that a user signs in with,
+-- if they have signed in using single sign-on.
+ALTER TABLE users ADD COLUMN oidc_subject TEXT;
+
+CREATE UNIQUE INDEX users_oidc_subject_uindex ON users (oidc_subject);
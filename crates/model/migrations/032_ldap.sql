@@ -0,0 +1,5 @@
+-- The distinguished name of the entry in the LDAP directory that a user signs in with, if they have
+-- signed in using LDAP. Distinguished names are compared without regard to case.
+ALTER TABLE users ADD COLUMN ldap_dn TEXT;
+
+CREATE UNIQUE INDEX users_ldap_dn_uindex ON users (ldap_dn COLLATE NOCASE);
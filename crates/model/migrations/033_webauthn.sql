@@ -0,0 +1,18 @@
+-- Create a table for the WebAuthn credentials (security keys and passkeys) that users register.
+CREATE TABLE webauthn_credentials (
+  id TEXT NOT NULL PRIMARY KEY,
+  user TEXT NOT NULL REFERENCES users (id),
+  name TEXT NOT NULL,
+  -- The credential ID chosen by the authenticator, in URL-safe base-64.
+  credential_id TEXT NOT NULL,
+  -- The public key and other state of the credential, serialized as JSON.
+  passkey TEXT NOT NULL,
+  created_at TIMESTAMP NOT NULL,
+  last_used TIMESTAMP
+);
+
+-- Credentials are looked up by their credential ID when a user signs in.
+CREATE UNIQUE INDEX webauthn_credentials_credential_id_uindex ON webauthn_credentials (credential_id);
+
+-- Index for listing the credentials that belong to a user.
+CREATE INDEX webauthn_credentials_user_idx ON webauthn_credentials (user);
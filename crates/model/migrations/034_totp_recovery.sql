@@ -0,0 +1,16 @@
+-- Create a table for the single-use recovery codes that let a user sign in without their
+-- authenticator app. We only store the SHA-256 hash of each code.
+CREATE TABLE totp_recovery_codes (
+  id TEXT NOT NULL PRIMARY KEY,
+  user TEXT NOT NULL REFERENCES users (id),
+  code_hash TEXT NOT NULL,
+  created_at TIMESTAMP NOT NULL,
+  used_at TIMESTAMP
+);
+
+-- Index for finding the recovery codes that belong to a user.
+CREATE INDEX totp_recovery_codes_user_idx ON totp_recovery_codes (user);
+
+-- Whether the user must set up two-factor authentication again, such as after an administrator
+-- has reset it for them.
+ALTER TABLE users ADD COLUMN totp_required BOOLEAN NOT NULL DEFAULT FALSE;
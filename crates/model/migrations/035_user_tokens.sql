@@ -0,0 +1,20 @@
+-- Create a table for the single-use tokens that are sent to users in links, such as invitations
+-- for new users to choose their password, and links to reset a forgotten password.
+CREATE TABLE user_tokens (
+  id TEXT NOT NULL PRIMARY KEY,
+  user TEXT NOT NULL REFERENCES users (id),
+  -- What the token lets the user do, such as 'invitation' or 'password_reset'.
+  kind TEXT NOT NULL,
+  -- We only store the SHA-256 hash of a token.
+  token_hash TEXT NOT NULL,
+  created_by TEXT,
+  created_at TIMESTAMP NOT NULL,
+  expires_at TIMESTAMP NOT NULL,
+  used_at TIMESTAMP
+);
+
+-- Tokens are looked up by their hash when a link is followed.
+CREATE UNIQUE INDEX user_tokens_token_hash_uindex ON user_tokens (token_hash);
+
+-- Index for finding the tokens that belong to a user.
+CREATE INDEX user_tokens_user_idx ON user_tokens (user);
@@ -0,0 +1,4 @@
+-- Blobs are marked as being deleted when their last reference is released, and the row is only
+-- deleted once the file is gone from storage. Until then, an upload with the same content waits
+-- rather than sharing a file that is about to be deleted.
+ALTER TABLE blobs ADD COLUMN deleting BOOLEAN NOT NULL DEFAULT FALSE;
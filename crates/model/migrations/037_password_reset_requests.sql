@@ -0,0 +1,11 @@
+-- Create a table to track requests for password reset links, so that they can be rate limited.
+CREATE TABLE password_reset_requests (
+    id TEXT NOT NULL PRIMARY KEY,
+    username TEXT NOT NULL,
+    ip_address TEXT,
+    requested_at TIMESTAMP NOT NULL
+);
+
+-- Index for counting the recent requests from an address.
+CREATE INDEX password_reset_requests_ip_address_requested_at_idx
+    ON password_reset_requests (ip_address, requested_at);
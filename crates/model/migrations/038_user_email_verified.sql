@@ -0,0 +1,8 @@
+-- Record whether the email address of a user is known to belong to them, as it was given by an
+-- administrator or came from the directory or a provider that verified it. Addresses that users
+-- entered themselves cannot be told apart from these, so none of the existing ones are trusted.
+ALTER TABLE users ADD COLUMN email_verified BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Record whether a user existed before they were linked to an account at an OpenID Connect
+-- provider, rather than being created by signing in with it.
+ALTER TABLE users ADD COLUMN oidc_linked BOOLEAN NOT NULL DEFAULT FALSE;
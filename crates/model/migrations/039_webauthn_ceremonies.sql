@@ -0,0 +1,10 @@
+-- Create a table to record the WebAuthn ceremonies that have been started. The state of each
+-- ceremony is kept by the browser, so this makes sure that it can only be used once, and only
+-- until the ceremony expires.
+CREATE TABLE webauthn_ceremonies (
+    id TEXT NOT NULL PRIMARY KEY,
+    started_at TIMESTAMP NOT NULL
+);
+
+-- Index for removing the ceremonies that have expired.
+CREATE INDEX webauthn_ceremonies_started_at_idx ON webauthn_ceremonies (started_at);
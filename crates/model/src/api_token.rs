@@ -0,0 +1,432 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, SqlitePool};
+use time::{Date, OffsetDateTime};
+
+use crate::{
+    team::Team,
+    types::Key,
+    upload::{Upload, UploadPermission},
+    user::User,
+};
+
+/// The prefix that we add to every generated token, so they are easy to recognise.
+const TOKEN_PREFIX: &str = "parcel_";
+
+/// The number of random bytes in a token.
+const TOKEN_BYTES: usize = 32;
+
+/// The number of characters of a token that we keep, so that a user can tell their tokens apart.
+const DISPLAY_PREFIX_LEN: usize = TOKEN_PREFIX.len() + 6;
+
+/// The scopes that can be granted to an API token.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+    /// List, view and download uploads.
+    Read,
+    /// Create new uploads and change existing ones.
+    Upload,
+    /// Delete uploads.
+    Delete,
+}
+
+impl TokenScope {
+    /// Get the scope that is needed to perform the given action on an upload.
+    pub fn for_permission(permission: &UploadPermission) -> Self {
+        match permission {
+            UploadPermission::View | UploadPermission::Download { .. } => Self::Read,
+            UploadPermission::Share
+            | UploadPermission::ResetDownloads
+            | UploadPermission::Edit
+            | UploadPermission::Transfer => Self::Upload,
+            UploadPermission::Delete => Self::Delete,
+        }
+    }
+}
+
+/// A personal API token, which allows a user to access the JSON API without a password.
+#[derive(Debug, FromRow, Serialize)]
+pub struct ApiToken {
+    pub id: Key<ApiToken>,
+    pub user: Key<User>,
+    pub name: String,
+    #[serde(skip)]
+    pub token_hash: String,
+    pub prefix: String,
+    pub team: Option<Key<Team>>,
+    pub can_read: bool,
+    pub can_upload: bool,
+    pub can_delete: bool,
+    pub expiry_date: Option<Date>,
+    pub created_at: OffsetDateTime,
+    pub last_used: Option<OffsetDateTime>,
+    pub revoked_at: Option<OffsetDateTime>,
+}
+
+/// Hash a token for storage or lookup.
+///
+/// Tokens are long and random, so unlike passwords a single fast hash is sufficient.
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+impl ApiToken {
+    /// Create a new token for the given user, returning the token record and the token itself.
+    ///
+    /// The token itself is not stored, so it can only be shown to the user once.
+    pub fn new(
+        user: Key<User>,
+        name: &str,
+        team: Option<Key<Team>>,
+        can_read: bool,
+        can_upload: bool,
+        can_delete: bool,
+        expiry_date: Option<Date>,
+    ) -> (Self, String) {
+        let mut bytes = [0u8; TOKEN_BYTES];
+        OsRng.fill_bytes(&mut bytes);
+
+        let mut token = TOKEN_PREFIX.to_string();
+        for byte in bytes {
+            token.push_str(&format!("{byte:02x}"));
+        }
+
+        let api_token = Self {
+            id: Key::new(),
+            user,
+            name: name.to_string(),
+            token_hash: hash_token(&token),
+            prefix: token[..DISPLAY_PREFIX_LEN].to_string(),
+            team,
+            can_read,
+            can_upload,
+            can_delete,
+            expiry_date,
+            created_at: OffsetDateTime::now_utc(),
+            last_used: None,
+            revoked_at: None,
+        };
+
+        (api_token, token)
+    }
+
+    pub async fn create(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        sqlx::query(
+            "INSERT INTO api_tokens \
+            (id, user, name, token_hash, prefix, team, \
+             can_read, can_upload, can_delete, expiry_date, created_at) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+        )
+        .bind(self.id)
+        .bind(self.user)
+        .bind(&self.name)
+        .bind(&self.token_hash)
+        .bind(&self.prefix)
+        .bind(self.team)
+        .bind(self.can_read)
+        .bind(self.can_upload)
+        .bind(self.can_delete)
+        .bind(self.expiry_date)
+        .bind(self.created_at)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get(pool: &SqlitePool, id: Key<ApiToken>) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as("SELECT * FROM api_tokens WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Find the token record for the given token, as sent by an API client.
+    pub async fn get_by_token(pool: &SqlitePool, token: &str) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as("SELECT * FROM api_tokens WHERE token_hash = $1")
+            .bind(hash_token(token))
+            .fetch_optional(pool)
+            .await
+    }
+
+    pub async fn revoke(&mut self, pool: &SqlitePool) -> sqlx::Result<()> {
+        let now = OffsetDateTime::now_utc();
+        sqlx::query("UPDATE api_tokens SET revoked_at = $1 WHERE id = $2")
+            .bind(now)
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        self.revoked_at = Some(now);
+        Ok(())
+    }
+
+    pub async fn record_last_used(&mut self, pool: &SqlitePool) -> sqlx::Result<()> {
+        let now = OffsetDateTime::now_utc();
+        sqlx::query("UPDATE api_tokens SET last_used = $1 WHERE id = $2")
+            .bind(now)
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        self.last_used = Some(now);
+        Ok(())
+    }
+
+    /// Check if the token has passed its expiry date.
+    ///
+    /// As with uploads, a token remains valid until the end of its expiry date.
+    pub fn is_expired(&self) -> bool {
+        matches!(self.expiry_date, Some(expiry) if expiry < OffsetDateTime::now_utc().date())
+    }
+
+    pub fn has_scope(&self, scope: TokenScope) -> bool {
+        match scope {
+            TokenScope::Read => self.can_read,
+            TokenScope::Upload => self.can_upload,
+            TokenScope::Delete => self.can_delete,
+        }
+    }
+
+    /// Check if the token can reach uploads owned by the given team, or the user's own uploads when
+    /// the team is `None`.
+    pub fn allows_team(&self, team: Option<Key<Team>>) -> bool {
+        self.team == team
+    }
+
+    /// Check if the token can transfer uploads to the given team.
+    ///
+    /// A personal token can transfer the user's uploads to any of their teams, whereas a token that
+    /// is restricted to a team cannot reach any other team.
+    pub fn allows_transfer_to(&self, team: Key<Team>) -> bool {
+        self.team.is_none() || self.team == Some(team)
+    }
+
+    /// Check if the token allows the given action on an upload.
+    ///
+    /// This only checks the scope and reach of the token. The user must still have permission to
+    /// perform the action, as checked by `Upload::can_access`.
+    pub fn permits(&self, upload: &Upload, permission: &UploadPermission) -> bool {
+        if !self.has_scope(TokenScope::for_permission(permission)) {
+            return false;
+        }
+
+        match self.team {
+            Some(team) => upload.owner_team == Some(team),
+            None => upload.owner_user == Some(self.user),
+        }
+    }
+}
+
+/// An API token as shown in a list, along with the name of the team it is restricted to.
+#[derive(Debug, FromRow, Serialize)]
+pub struct ApiTokenList {
+    pub id: Key<ApiToken>,
+    pub name: String,
+    pub prefix: String,
+    pub team: Option<Key<Team>>,
+    pub team_name: Option<String>,
+    pub can_read: bool,
+    pub can_upload: bool,
+    pub can_delete: bool,
+    pub expiry_date: Option<Date>,
+    pub created_at: OffsetDateTime,
+    pub last_used: Option<OffsetDateTime>,
+    pub revoked_at: Option<OffsetDateTime>,
+    pub expired: bool,
+}
+
+impl ApiTokenList {
+    pub async fn get_for_user(pool: &SqlitePool, user: Key<User>) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as(
+            "SELECT \
+                api_tokens.id AS id, \
+                api_tokens.name AS name, \
+                api_tokens.prefix AS prefix, \
+                api_tokens.team AS team, \
+                teams.name AS team_name, \
+                api_tokens.can_read AS can_read, \
+                api_tokens.can_upload AS can_upload, \
+                api_tokens.can_delete AS can_delete, \
+                api_tokens.expiry_date AS expiry_date, \
+                api_tokens.created_at AS created_at, \
+                api_tokens.last_used AS last_used, \
+                api_tokens.revoked_at AS revoked_at, \
+                COALESCE(api_tokens.expiry_date < DATE('now'), FALSE) AS expired \
+            FROM api_tokens \
+            LEFT JOIN teams ON teams.id = api_tokens.team \
+            WHERE api_tokens.user = $1 \
+            ORDER BY api_tokens.created_at DESC",
+        )
+        .bind(user)
+        .fetch_all(pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+    use time::{Duration, OffsetDateTime};
+
+    use super::*;
+    use crate::migration::MIGRATOR;
+
+    fn token(user: Key<User>, team: Option<Key<Team>>, scopes: [bool; 3]) -> ApiToken {
+        let [can_read, can_upload, can_delete] = scopes;
+        ApiToken::new(user, "Test", team, can_read, can_upload, can_delete, None).0
+    }
+
+    fn upload(owner_user: Option<Key<User>>, owner_team: Option<Key<Team>>) -> Upload {
+        Upload {
+            id: Key::new(),
+            slug: "slug".to_string(),
+            blob: "blob".to_string(),
+            hash: None,
+            filename: "test.txt".to_string(),
+            size: 0,
+            public: false,
+            downloads: 0,
+            limit: None,
+            remaining: None,
+            expiry_date: None,
+            password: None,
+            custom_slug: None,
+            owner_team,
+            owner_user,
+            uploaded_by: owner_user,
+            uploaded_at: OffsetDateTime::now_utc(),
+            remote_addr: None,
+            mime_type: None,
+            has_preview: false,
+            preview_error: None,
+            bundle: None,
+            folder: None,
+            text_extracted: false,
+            upload_request: None,
+        }
+    }
+
+    #[test]
+    fn test_scopes() {
+        let user = Key::new();
+        let read_only = token(user, None, [true, false, false]);
+        let own = upload(Some(user), None);
+
+        let download = UploadPermission::Download {
+            with_password: false,
+        };
+
+        assert!(read_only.permits(&own, &UploadPermission::View));
+        assert!(read_only.permits(&own, &download));
+        assert!(!read_only.permits(&own, &UploadPermission::Edit));
+        assert!(!read_only.permits(&own, &UploadPermission::Transfer));
+        assert!(!read_only.permits(&own, &UploadPermission::Delete));
+
+        let upload_only = token(user, None, [false, true, false]);
+        assert!(!upload_only.permits(&own, &UploadPermission::View));
+        assert!(upload_only.permits(&own, &UploadPermission::Edit));
+        assert!(upload_only.permits(&own, &UploadPermission::ResetDownloads));
+        assert!(!upload_only.permits(&own, &UploadPermission::Delete));
+
+        let delete_only = token(user, None, [false, false, true]);
+        assert!(delete_only.permits(&own, &UploadPermission::Delete));
+        assert!(!delete_only.permits(&own, &UploadPermission::Share));
+    }
+
+    #[test]
+    fn test_personal_token() {
+        let user = Key::new();
+        let team = Key::new();
+        let personal = token(user, None, [true, true, true]);
+
+        assert!(personal.allows_team(None));
+        assert!(!personal.allows_team(Some(team)));
+        assert!(personal.permits(&upload(Some(user), None), &UploadPermission::View));
+        assert!(!personal.permits(&upload(Some(Key::new()), None), &UploadPermission::View));
+        assert!(!personal.permits(&upload(None, Some(team)), &UploadPermission::View));
+
+        // The user's own uploads can be transferred to any team, subject to their membership.
+        assert!(personal.allows_transfer_to(team));
+    }
+
+    #[test]
+    fn test_team_token() {
+        let user = Key::new();
+        let team = Key::new();
+        let other_team = Key::new();
+        let restricted = token(user, Some(team), [true, true, true]);
+
+        assert!(restricted.allows_team(Some(team)));
+        assert!(!restricted.allows_team(Some(other_team)));
+        assert!(!restricted.allows_team(None));
+        assert!(restricted.permits(&upload(None, Some(team)), &UploadPermission::Edit));
+        assert!(!restricted.permits(&upload(None, Some(other_team)), &UploadPermission::Edit));
+        assert!(!restricted.permits(&upload(Some(user), None), &UploadPermission::Edit));
+
+        assert!(restricted.allows_transfer_to(team));
+        assert!(!restricted.allows_transfer_to(other_team));
+    }
+
+    #[test]
+    fn test_expiry() {
+        let user = Key::new();
+        let today = OffsetDateTime::now_utc().date();
+
+        let mut api_token = token(user, None, [true, false, false]);
+        assert!(!api_token.is_expired());
+
+        api_token.expiry_date = Some(today);
+        assert!(!api_token.is_expired());
+
+        api_token.expiry_date = Some(today - Duration::days(1));
+        assert!(api_token.is_expired());
+    }
+
+    #[tokio::test]
+    async fn test_revoke() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .expect("connect to database");
+        MIGRATOR.run(&pool).await.expect("run migrations");
+
+        let user = Key::<User>::new();
+        sqlx::query(
+            "INSERT INTO users (id, username, name, password, enabled, admin, created_at) \
+            VALUES ($1, 'user', 'User', '', TRUE, FALSE, $2)",
+        )
+        .bind(user)
+        .bind(OffsetDateTime::now_utc())
+        .execute(&pool)
+        .await
+        .expect("create user");
+
+        let (api_token, secret) = ApiToken::new(user, "Test", None, true, false, false, None);
+        api_token.create(&pool).await.expect("create token");
+
+        let mut found = ApiToken::get_by_token(&pool, &secret)
+            .await
+            .expect("get token")
+            .expect("token exists");
+        assert_eq!(found.id, api_token.id);
+        assert!(found.revoked_at.is_none());
+
+        assert!(ApiToken::get_by_token(&pool, "parcel_not-a-token")
+            .await
+            .expect("get token")
+            .is_none());
+
+        found.revoke(&pool).await.expect("revoke token");
+        assert!(found.revoked_at.is_some());
+
+        let revoked = ApiToken::get(&pool, api_token.id)
+            .await
+            .expect("get token")
+            .expect("token exists");
+        assert!(revoked.revoked_at.is_some());
+    }
+}
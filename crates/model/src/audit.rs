@@ -0,0 +1,319 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, QueryBuilder, Sqlite, SqlitePool};
+use time::{Date, OffsetDateTime};
+
+use crate::{
+    bundle::Bundle, folder::Folder, team::Team, types::Key, upload::Upload,
+    upload_request::UploadRequest, user::User, webhook::Webhook,
+};
+
+/// The kinds of event that are recorded in the audit log.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// The settings of an upload were changed.
+    UploadEdit,
+    /// An upload was deleted.
+    UploadDelete,
+    /// An upload was copied or moved to a team.
+    UploadTransfer,
+    /// A share link to an upload was created.
+    ShareLinkCreate,
+    /// A share link to an upload was revoked.
+    ShareLinkRevoke,
+    /// The settings of a bundle were changed.
+    BundleEdit,
+    /// A bundle was deleted, leaving the uploads that were in it.
+    BundleDelete,
+    /// A folder was created.
+    FolderCreate,
+    /// A folder was renamed, shared or unshared.
+    FolderEdit,
+    /// A folder was moved into another folder.
+    FolderMove,
+    /// A folder was deleted, moving its contents to its parent.
+    FolderDelete,
+    /// An upload request was created.
+    UploadRequestCreate,
+    /// An upload request was revoked.
+    UploadRequestRevoke,
+    /// A webhook was added.
+    WebhookCreate,
+    /// A webhook was deleted.
+    WebhookDelete,
+    /// The permissions of the members of a team were changed.
+    TeamPermissions,
+    /// The members of a team were synchronised with a group in the LDAP directory.
+    TeamSync,
+    /// The details of a user were changed by an administrator.
+    UserEdit,
+    /// A user was enabled by an administrator.
+    UserEnable,
+    /// A user was disabled by an administrator.
+    UserDisable,
+    /// A user was created by signing in with single sign-on or LDAP.
+    UserProvision,
+    /// An existing user was linked to their account at the single sign-on provider or in LDAP.
+    UserLink,
+    /// The security keys and passkeys of a user were removed by an administrator.
+    WebauthnReset,
+    /// The two-factor authentication of a user was reset by an administrator.
+    TotpReset,
+    /// A link for a user to choose a password was created by an administrator.
+    PasswordLink,
+    /// An administrator started masquerading as another user.
+    Masquerade,
+}
+
+impl AuditAction {
+    pub const ALL: &'static [Self] = &[
+        Self::UploadEdit,
+        Self::UploadDelete,
+        Self::UploadTransfer,
+        Self::ShareLinkCreate,
+        Self::ShareLinkRevoke,
+        Self::BundleEdit,
+        Self::BundleDelete,
+        Self::FolderCreate,
+        Self::FolderEdit,
+        Self::FolderMove,
+        Self::FolderDelete,
+        Self::UploadRequestCreate,
+        Self::UploadRequestRevoke,
+        Self::WebhookCreate,
+        Self::WebhookDelete,
+        Self::TeamPermissions,
+        Self::TeamSync,
+        Self::UserEdit,
+        Self::UserEnable,
+        Self::UserDisable,
+        Self::UserProvision,
+        Self::UserLink,
+        Self::WebauthnReset,
+        Self::TotpReset,
+        Self::PasswordLink,
+        Self::Masquerade,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UploadEdit => "upload_edit",
+            Self::UploadDelete => "upload_delete",
+            Self::UploadTransfer => "upload_transfer",
+            Self::ShareLinkCreate => "share_link_create",
+            Self::ShareLinkRevoke => "share_link_revoke",
+            Self::BundleEdit => "bundle_edit",
+            Self::BundleDelete => "bundle_delete",
+            Self::FolderCreate => "folder_create",
+            Self::FolderEdit => "folder_edit",
+            Self::FolderMove => "folder_move",
+            Self::FolderDelete => "folder_delete",
+            Self::UploadRequestCreate => "upload_request_create",
+            Self::UploadRequestRevoke => "upload_request_revoke",
+            Self::WebhookCreate => "webhook_create",
+            Self::WebhookDelete => "webhook_delete",
+            Self::TeamPermissions => "team_permissions",
+            Self::TeamSync => "team_sync",
+            Self::UserEdit => "user_edit",
+            Self::UserEnable => "user_enable",
+            Self::UserDisable => "user_disable",
+            Self::UserProvision => "user_provision",
+            Self::UserLink => "user_link",
+            Self::WebauthnReset => "webauthn_reset",
+            Self::TotpReset => "totp_reset",
+            Self::PasswordLink => "password_link",
+            Self::Masquerade => "masquerade",
+        }
+    }
+}
+
+/// The thing that an audited action was performed on.
+#[derive(Debug, Clone, Copy)]
+pub enum AuditTarget {
+    Upload(Key<Upload>),
+    Bundle(Key<Bundle>),
+    Folder(Key<Folder>),
+    UploadRequest(Key<UploadRequest>),
+    Webhook(Key<Webhook>),
+    Team(Key<Team>),
+    User(Key<User>),
+}
+
+impl AuditTarget {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Upload(_) => "upload",
+            Self::Bundle(_) => "bundle",
+            Self::Folder(_) => "folder",
+            Self::UploadRequest(_) => "upload_request",
+            Self::Webhook(_) => "webhook",
+            Self::Team(_) => "team",
+            Self::User(_) => "user",
+        }
+    }
+
+    fn id(&self) -> String {
+        match self {
+            Self::Upload(id) => id.to_string(),
+            Self::Bundle(id) => id.to_string(),
+            Self::Folder(id) => id.to_string(),
+            Self::UploadRequest(id) => id.to_string(),
+            Self::Webhook(id) => id.to_string(),
+            Self::Team(id) => id.to_string(),
+            Self::User(id) => id.to_string(),
+        }
+    }
+}
+
+/// An event in the audit log.
+#[derive(Debug, FromRow, Serialize)]
+pub struct AuditEvent {
+    pub id: Key<AuditEvent>,
+    pub created_at: OffsetDateTime,
+    pub actor: Option<Key<User>>,
+    pub effective_user: Option<Key<User>>,
+    pub action: AuditAction,
+    pub target_kind: Option<String>,
+    pub target_id: Option<String>,
+    pub details: Option<String>,
+    pub remote_addr: Option<String>,
+}
+
+impl AuditEvent {
+    /// Create a new event, performed by the `actor` as the `effective_user`.
+    pub fn new(
+        actor: Option<Key<User>>,
+        effective_user: Option<Key<User>>,
+        action: AuditAction,
+        target: AuditTarget,
+    ) -> Self {
+        Self {
+            id: Key::new(),
+            created_at: OffsetDateTime::now_utc(),
+            actor,
+            effective_user,
+            action,
+            target_kind: Some(target.kind().to_string()),
+            target_id: Some(target.id()),
+            details: None,
+            remote_addr: None,
+        }
+    }
+
+    pub async fn create(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        sqlx::query(
+            "INSERT INTO audit_events (id, created_at, actor, effective_user, action,
+            target_kind, target_id, details, remote_addr)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        )
+        .bind(self.id)
+        .bind(self.created_at)
+        .bind(self.actor)
+        .bind(self.effective_user)
+        .bind(self.action)
+        .bind(&self.target_kind)
+        .bind(&self.target_id)
+        .bind(&self.details)
+        .bind(&self.remote_addr)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// The criteria for listing events in the audit log.
+#[derive(Debug, Default, Clone)]
+pub struct AuditFilter {
+    pub action: Option<AuditAction>,
+    /// Only include events where the actor or effective user has this username.
+    pub user: Option<String>,
+    /// Only include events for the target with this ID.
+    pub target: Option<String>,
+    pub from: Option<Date>,
+    pub to: Option<Date>,
+}
+
+impl AuditFilter {
+    fn push_conditions<'a>(&'a self, query: &mut QueryBuilder<'a, Sqlite>) {
+        query.push(" WHERE 1 = 1");
+
+        if let Some(action) = self.action {
+            query.push(" AND audit_events.action = ").push_bind(action);
+        }
+
+        if let Some(user) = self.user.as_deref().filter(|user| !user.is_empty()) {
+            query
+                .push(" AND (actor.username = ")
+                .push_bind(user)
+                .push(" OR effective.username = ")
+                .push_bind(user)
+                .push(")");
+        }
+
+        if let Some(target) = self.target.as_deref().filter(|target| !target.is_empty()) {
+            query
+                .push(" AND audit_events.target_id = ")
+                .push_bind(target);
+        }
+
+        if let Some(from) = self.from {
+            query
+                .push(" AND audit_events.created_at >= ")
+                .push_bind(from.midnight().assume_utc());
+        }
+
+        if let Some(to) = self.to {
+            query
+                .push(" AND audit_events.created_at < ")
+                .push_bind(to.next_day().unwrap_or(to).midnight().assume_utc());
+        }
+    }
+}
+
+/// An event in the audit log, with the names of the users involved.
+#[derive(Debug, FromRow, Serialize)]
+pub struct AuditEventList {
+    pub id: Key<AuditEvent>,
+    pub created_at: OffsetDateTime,
+    pub actor: Option<Key<User>>,
+    pub actor_name: Option<String>,
+    pub effective_user: Option<Key<User>>,
+    pub effective_user_name: Option<String>,
+    pub action: AuditAction,
+    pub target_kind: Option<String>,
+    pub target_id: Option<String>,
+    pub details: Option<String>,
+    pub remote_addr: Option<String>,
+}
+
+impl AuditEventList {
+    /// Get the events that match the filter, most recent first.
+    pub async fn get(
+        pool: &SqlitePool,
+        filter: &AuditFilter,
+        offset: u32,
+        limit: u32,
+    ) -> sqlx::Result<Vec<Self>> {
+        let mut query = QueryBuilder::new(
+            "SELECT audit_events.id, audit_events.created_at,
+            audit_events.actor, actor.username AS actor_name,
+            audit_events.effective_user, effective.username AS effective_user_name,
+            audit_events.action, audit_events.target_kind, audit_events.target_id,
+            audit_events.details, audit_events.remote_addr
+            FROM audit_events
+            LEFT JOIN users AS actor ON actor.id = audit_events.actor
+            LEFT JOIN users AS effective ON effective.id = audit_events.effective_user",
+        );
+
+        filter.push_conditions(&mut query);
+        query
+            .push(" ORDER BY audit_events.created_at DESC LIMIT ")
+            .push_bind(limit as i64)
+            .push(" OFFSET ")
+            .push_bind(offset as i64);
+
+        query.build_query_as().fetch_all(pool).await
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+use sqlx::{FromRow, QueryBuilder, SqlitePool};
+use time::OffsetDateTime;
+
+/// A file in storage, which is shared by all of the uploads with the same content.
+///
+/// Blobs are stored under the SHA-256 hash of their content, and count the number of uploads that
+/// refer to them. The file is only deleted from storage once the last reference is released.
+///
+/// Uploads that were created before blobs were introduced are stored under their slug, and the
+/// hash of their content is not known, so they are never shared.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Blob {
+    pub key: String,
+    pub hash: Option<String>,
+    pub size: i64,
+    pub refs: i64,
+    pub created_at: OffsetDateTime,
+    /// Whether the last reference has been released and the file is being deleted from storage.
+    pub deleting: bool,
+}
+
+/// The outcome of taking a reference to a blob by its hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Acquired {
+    /// The blob was created, and the caller must put the file into storage.
+    Created,
+    /// The blob already existed, and the caller shares its file.
+    Shared,
+    /// The blob is being deleted, and the caller must wait for it to be gone before trying again.
+    Deleting,
+}
+
+impl Blob {
+    pub async fn get(pool: &SqlitePool, key: &str) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as("SELECT * FROM blobs WHERE key = $1")
+            .bind(key)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Take a reference to the blob with the given hash, creating the blob if it does not exist.
+    ///
+    /// A blob that is being deleted cannot be shared, as its file may already be gone, and it cannot
+    /// be created again until the deletion has finished.
+    pub async fn acquire(pool: &SqlitePool, hash: &str, size: i64) -> sqlx::Result<Acquired> {
+        let refs: Option<i64> = sqlx::query_scalar(
+            "INSERT INTO blobs (key, hash, size, refs, created_at) \
+            VALUES ($1, $1, $2, 1, $3) \
+            ON CONFLICT (key) DO UPDATE SET refs = refs + 1 WHERE NOT deleting \
+            RETURNING refs",
+        )
+        .bind(hash)
+        .bind(size)
+        .bind(OffsetDateTime::now_utc())
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(match refs {
+            Some(1) => Acquired::Created,
+            Some(_) => Acquired::Shared,
+            None => Acquired::Deleting,
+        })
+    }
+
+    /// Take another reference to an existing blob.
+    pub async fn add_ref(pool: &SqlitePool, key: &str) -> sqlx::Result<()> {
+        let result =
+            sqlx::query("UPDATE blobs SET refs = refs + 1 WHERE key = $1 AND NOT deleting")
+                .bind(key)
+                .execute(pool)
+                .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound);
+        }
+
+        Ok(())
+    }
+
+    /// Release a reference to a blob.
+    ///
+    /// Returns `true` if this was the last reference, in which case the blob is marked as being
+    /// deleted and the caller is responsible for deleting the file from storage and then calling
+    /// [`Blob::delete`].
+    pub async fn release(pool: &SqlitePool, key: &str) -> sqlx::Result<bool> {
+        let deleting: Option<bool> = sqlx::query_scalar(
+            "UPDATE blobs SET refs = refs - 1, deleting = refs <= 1 \
+            WHERE key = $1 AND NOT deleting \
+            RETURNING deleting",
+        )
+        .bind(key)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(deleting.unwrap_or(false))
+    }
+
+    /// Delete a blob that was marked as being deleted, once its file is gone from storage.
+    pub async fn delete(pool: &SqlitePool, key: &str) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM blobs WHERE key = $1 AND deleting")
+            .bind(key)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Get the keys of the blobs that were being deleted when the server was last stopped.
+    pub async fn get_deleting(pool: &SqlitePool) -> sqlx::Result<Vec<String>> {
+        sqlx::query_scalar("SELECT key FROM blobs WHERE deleting")
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Check which keys exist in the database from a list of candidates.
+    /// Returns only the keys that exist.
+    pub async fn get_existing_keys(
+        pool: &SqlitePool,
+        keys: &[String],
+    ) -> sqlx::Result<HashSet<String>> {
+        if keys.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        let mut query = QueryBuilder::new("SELECT key FROM blobs WHERE key IN (");
+        let mut separated = query.separated(", ");
+        for key in keys {
+            separated.push_bind(key);
+        }
+        separated.push_unseparated(")");
+
+        let existing: Vec<String> = query.build_query_scalar().fetch_all(pool).await?;
+        Ok(existing.into_iter().collect())
+    }
+}
+
+/// The hash that the content of a blob is expected to have.
+#[derive(Debug, FromRow)]
+pub struct BlobHash {
+    pub key: String,
+    pub hash: Option<String>,
+}
+
+impl BlobHash {
+    /// Get the expected hash of every blob.
+    ///
+    /// The hash of a blob for older uploads is not known until it has been recorded against its
+    /// uploads, so it is taken from them instead.
+    pub async fn get_all(pool: &SqlitePool) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as(
+            "SELECT key, COALESCE(hash,
+                (SELECT uploads.hash FROM uploads
+                WHERE uploads.blob = blobs.key AND uploads.hash IS NOT NULL
+                LIMIT 1)) AS hash
+            FROM blobs
+            ORDER BY created_at",
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct BlobStats {
+    /// The number of blobs, and their total size.
+    pub count: i64,
+    pub size: i64,
+    /// The number of references to blobs.
+    pub refs: i64,
+    /// The number of bytes saved by sharing blobs between uploads.
+    pub saved: i64,
+}
+
+impl BlobStats {
+    pub async fn get(pool: &SqlitePool) -> sqlx::Result<BlobStats> {
+        sqlx::query_as(
+            "SELECT COUNT(*) AS count, COALESCE(SUM(size), 0) AS size,
+            COALESCE(SUM(refs), 0) AS refs, COALESCE(SUM(size * (refs - 1)), 0) AS saved
+            FROM blobs WHERE NOT deleting",
+        )
+        .fetch_one(pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+    use crate::migration::MIGRATOR;
+
+    #[tokio::test]
+    async fn test_acquire_while_deleting() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .expect("connect to database");
+        MIGRATOR.run(&pool).await.expect("run migrations");
+
+        let acquire = || Blob::acquire(&pool, "hash", 10);
+        assert_eq!(acquire().await.unwrap(), Acquired::Created);
+        assert_eq!(acquire().await.unwrap(), Acquired::Shared);
+
+        assert!(!Blob::release(&pool, "hash").await.unwrap());
+        assert!(Blob::release(&pool, "hash").await.unwrap());
+
+        // The file may already be gone, so the blob can neither be shared nor released again.
+        assert_eq!(acquire().await.unwrap(), Acquired::Deleting);
+        assert!(Blob::add_ref(&pool, "hash").await.is_err());
+        assert!(!Blob::release(&pool, "hash").await.unwrap());
+        assert_eq!(Blob::get_deleting(&pool).await.unwrap(), vec!["hash"]);
+
+        Blob::delete(&pool, "hash").await.unwrap();
+        assert!(Blob::get(&pool, "hash").await.unwrap().is_none());
+        assert_eq!(acquire().await.unwrap(), Acquired::Created);
+    }
+}
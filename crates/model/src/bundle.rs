@@ -0,0 +1,279 @@
+use anyhow::Context;
+use serde::Serialize;
+use sqlx::{FromRow, QueryBuilder, SqlitePool};
+use time::{Date, OffsetDateTime};
+
+use crate::{
+    password::StoredPassword,
+    team::{Team, TeamMember},
+    types::Key,
+    upload::{Upload, UploadOwnership, UploadPermission},
+    user::User,
+};
+
+/// A named group of uploads that are shared with a single link and can be downloaded together.
+///
+/// A bundle has its own public flag, password, download limit and expiry date, which apply when
+/// the bundle is downloaded as a whole. The uploads in the bundle keep their own settings.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Bundle {
+    pub id: Key<Bundle>,
+    pub slug: String,
+    pub name: String,
+    pub public: bool,
+    pub downloads: i64,
+    pub limit: Option<i64>,
+    pub remaining: Option<i64>,
+    pub expiry_date: Option<Date>,
+    #[serde(skip)]
+    pub password: Option<StoredPassword>,
+    pub owner_user: Option<Key<User>>,
+    pub owner_team: Option<Key<Team>>,
+    pub created_by: Option<Key<User>>,
+    pub created_at: OffsetDateTime,
+}
+
+impl Bundle {
+    /// Create a new private bundle, owned by either a user or a team.
+    pub fn new(
+        slug: String,
+        name: &str,
+        owner_user: Option<Key<User>>,
+        owner_team: Option<Key<Team>>,
+        created_by: Key<User>,
+    ) -> Self {
+        Self {
+            id: Key::new(),
+            slug,
+            name: name.to_string(),
+            public: false,
+            downloads: 0,
+            limit: None,
+            remaining: None,
+            expiry_date: None,
+            password: None,
+            owner_user,
+            owner_team,
+            created_by: Some(created_by),
+            created_at: OffsetDateTime::now_utc(),
+        }
+    }
+
+    pub async fn create(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        sqlx::query(
+            "INSERT INTO bundles (id, slug, name, public, downloads, \"limit\", remaining,
+            expiry_date, password, owner_user, owner_team, created_by, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)",
+        )
+        .bind(self.id)
+        .bind(&self.slug)
+        .bind(&self.name)
+        .bind(self.public)
+        .bind(self.downloads)
+        .bind(self.limit)
+        .bind(self.remaining)
+        .bind(self.expiry_date)
+        .bind(&self.password)
+        .bind(self.owner_user)
+        .bind(self.owner_team)
+        .bind(self.created_by)
+        .bind(self.created_at)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn save(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        let count = sqlx::query(
+            "UPDATE bundles SET
+            name = $1,
+            public = $2,
+            \"limit\" = $3,
+            remaining = $4,
+            expiry_date = $5,
+            password = $6
+            WHERE id = $7",
+        )
+        .bind(&self.name)
+        .bind(self.public)
+        .bind(self.limit)
+        .bind(self.remaining)
+        .bind(self.expiry_date)
+        .bind(&self.password)
+        .bind(self.id)
+        .execute(pool)
+        .await?;
+
+        if count.rows_affected() == 0 {
+            Err(sqlx::Error::RowNotFound)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub async fn get(pool: &SqlitePool, id: Key<Bundle>) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as("SELECT * FROM bundles WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    pub async fn get_by_slug(pool: &SqlitePool, slug: &str) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as("SELECT * FROM bundles WHERE slug = $1")
+            .bind(slug)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Get the uploads in the bundle, in order of their filename.
+    pub async fn get_uploads(&self, pool: &SqlitePool) -> sqlx::Result<Vec<Upload>> {
+        sqlx::query_as("SELECT * FROM uploads WHERE bundle = $1 ORDER BY filename")
+            .bind(self.id)
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Add uploads to the bundle.
+    ///
+    /// Uploads that belong to another bundle are moved to this one.
+    pub async fn add_uploads(
+        &self,
+        pool: &SqlitePool,
+        uploads: &[Key<Upload>],
+    ) -> sqlx::Result<()> {
+        let mut query = QueryBuilder::new("UPDATE uploads SET bundle = ");
+        query.push_bind(self.id).push(" WHERE id IN (");
+        let mut separated = query.separated(", ");
+        for id in uploads {
+            separated.push_bind(*id);
+        }
+        separated.push_unseparated(")");
+
+        query.build().execute(pool).await?;
+        Ok(())
+    }
+
+    /// Record that the bundle has been downloaded, reducing the number of remaining downloads if
+    /// the bundle has a download limit.
+    pub async fn record_download(&mut self, pool: &SqlitePool) -> sqlx::Result<()> {
+        let (downloads, remaining) = sqlx::query_as::<_, (i64, Option<i64>)>(
+            "UPDATE bundles SET downloads = downloads + 1, \
+            remaining = CASE WHEN remaining IS NULL THEN NULL ELSE MAX(0, remaining - 1) END \
+            WHERE id = $1 RETURNING downloads, remaining",
+        )
+        .bind(self.id)
+        .fetch_one(pool)
+        .await?;
+
+        self.downloads = downloads;
+        self.remaining = remaining;
+
+        Ok(())
+    }
+
+    pub async fn set_password(&mut self, pool: &SqlitePool, password: &str) -> anyhow::Result<()> {
+        let password = StoredPassword::new(password).context("failed to hash password")?;
+
+        let result = sqlx::query("UPDATE bundles SET password = $1 WHERE id = $2")
+            .bind(&password)
+            .bind(self.id)
+            .execute(pool)
+            .await
+            .context("failed to update bundle password")?;
+
+        if result.rows_affected() == 0 {
+            anyhow::bail!("Bundle not found");
+        }
+
+        self.password = Some(password);
+        Ok(())
+    }
+
+    /// Delete the bundle, leaving the uploads that were in it.
+    pub async fn delete(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        let result = sqlx::query("DELETE FROM bundles WHERE id = $1")
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound);
+        }
+
+        Ok(())
+    }
+
+    pub async fn is_owner(
+        &self,
+        pool: &SqlitePool,
+        user: &User,
+    ) -> sqlx::Result<Option<UploadOwnership>> {
+        if matches!(self.owner_user, Some(owner) if owner == user.id) {
+            return Ok(Some(UploadOwnership::OwnedByUser));
+        }
+
+        if let Some(owner) = self.owner_team {
+            if let Some(membership) =
+                TeamMember::get_for_user_and_team(pool, user.id, owner).await?
+            {
+                return Ok(Some(UploadOwnership::OwnedByTeam(membership)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Check whether the bundle has passed its expiry date or has no remaining downloads.
+    pub fn is_unavailable(&self) -> bool {
+        self.remaining.is_some_and(|remaining| remaining < 1)
+            || self
+                .expiry_date
+                .is_some_and(|expiry| expiry < OffsetDateTime::now_utc().date())
+    }
+
+    /// Check whether a user can access the bundle, with the same rules as for an upload.
+    pub async fn can_access(
+        &self,
+        pool: &SqlitePool,
+        user: Option<&User>,
+        permission: UploadPermission,
+    ) -> sqlx::Result<bool> {
+        if user.map(|user| user.admin).unwrap_or(false) {
+            return Ok(true);
+        }
+
+        let ownership = match user {
+            Some(user) => self.is_owner(pool, user).await?,
+            None => None,
+        };
+
+        Ok(match permission {
+            UploadPermission::View => self.public || ownership.is_some(),
+
+            UploadPermission::Download { with_password } => {
+                if self.public {
+                    !self.is_unavailable() && self.password.is_some() == with_password
+                } else {
+                    ownership.is_some()
+                }
+            }
+
+            UploadPermission::Share
+            | UploadPermission::Transfer
+            | UploadPermission::ResetDownloads
+            | UploadPermission::Edit
+            | UploadPermission::Delete => match ownership {
+                Some(UploadOwnership::OwnedByUser) => true,
+                Some(UploadOwnership::OwnedByTeam(membership)) => {
+                    if permission == UploadPermission::Delete {
+                        membership.can_delete
+                    } else {
+                        membership.can_edit
+                    }
+                }
+                None => false,
+            },
+        })
+    }
+}
@@ -0,0 +1,256 @@
+use serde::Serialize;
+use sqlx::{FromRow, QueryBuilder, Sqlite, SqlitePool};
+use time::{Date, OffsetDateTime};
+
+use crate::{share_link::ShareLink, team::Team, types::Key, upload::Upload, user::User};
+
+/// A record of a single download of an upload.
+#[derive(Debug, FromRow, Serialize)]
+pub struct Download {
+    pub id: Key<Download>,
+    pub upload: Key<Upload>,
+    pub user: Option<Key<User>>,
+    pub downloaded_at: OffsetDateTime,
+    pub remote_addr: Option<String>,
+    pub user_agent: Option<String>,
+    pub bytes_sent: i64,
+    pub completed: bool,
+    /// The share link that was used for the download, if any.
+    pub share_link: Option<Key<ShareLink>>,
+}
+
+impl Download {
+    /// Create a new download of an upload, which has not yet sent any bytes.
+    pub fn new(
+        upload: Key<Upload>,
+        user: Option<Key<User>>,
+        remote_addr: Option<String>,
+        user_agent: Option<String>,
+    ) -> Self {
+        Self {
+            id: Key::new(),
+            upload,
+            user,
+            downloaded_at: OffsetDateTime::now_utc(),
+            remote_addr,
+            user_agent,
+            bytes_sent: 0,
+            completed: false,
+            share_link: None,
+        }
+    }
+
+    pub async fn create(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        sqlx::query(
+            "INSERT INTO downloads (id, upload, user, downloaded_at, remote_addr, user_agent,
+            bytes_sent, completed, share_link)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        )
+        .bind(self.id)
+        .bind(self.upload)
+        .bind(self.user)
+        .bind(self.downloaded_at)
+        .bind(&self.remote_addr)
+        .bind(&self.user_agent)
+        .bind(self.bytes_sent)
+        .bind(self.completed)
+        .bind(self.share_link)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record the number of bytes that were sent for a download, once the response has finished.
+    pub async fn finish(
+        pool: &SqlitePool,
+        id: Key<Download>,
+        bytes_sent: i64,
+        completed: bool,
+    ) -> sqlx::Result<()> {
+        sqlx::query("UPDATE downloads SET bytes_sent = $1, completed = $2 WHERE id = $3")
+            .bind(bytes_sent)
+            .bind(completed)
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// The downloads that are included in the download history and statistics.
+#[derive(Debug, Clone, Copy)]
+pub enum DownloadScope {
+    /// The downloads of all uploads.
+    All,
+    /// The downloads of a single upload.
+    Upload(Key<Upload>),
+    /// The downloads of all the uploads that belong to a team.
+    Team(Key<Team>),
+}
+
+impl DownloadScope {
+    fn push_conditions(&self, query: &mut QueryBuilder<'_, Sqlite>, since: Option<OffsetDateTime>) {
+        query.push(" WHERE 1 = 1");
+
+        match *self {
+            Self::All => {}
+            Self::Upload(upload) => {
+                query.push(" AND downloads.upload = ").push_bind(upload);
+            }
+            Self::Team(team) => {
+                query.push(" AND uploads.owner_team = ").push_bind(team);
+            }
+        }
+
+        if let Some(since) = since {
+            query
+                .push(" AND downloads.downloaded_at >= ")
+                .push_bind(since);
+        }
+    }
+}
+
+/// A download, with the upload and the name of the user that downloaded it.
+#[derive(Debug, FromRow, Serialize)]
+pub struct DownloadList {
+    pub id: Key<Download>,
+    pub upload: Key<Upload>,
+    pub slug: String,
+    pub filename: String,
+    pub user: Option<Key<User>>,
+    pub username: Option<String>,
+    pub downloaded_at: OffsetDateTime,
+    pub remote_addr: Option<String>,
+    pub user_agent: Option<String>,
+    pub bytes_sent: i64,
+    pub completed: bool,
+    pub share_link_name: Option<String>,
+}
+
+impl DownloadList {
+    /// Get the downloads in the scope, most recent first.
+    pub async fn get(
+        pool: &SqlitePool,
+        scope: DownloadScope,
+        offset: u32,
+        limit: u32,
+    ) -> sqlx::Result<Vec<Self>> {
+        let mut query = QueryBuilder::new(
+            "SELECT downloads.id, downloads.upload, uploads.slug, uploads.filename,
+            downloads.user, users.username, downloads.downloaded_at, downloads.remote_addr,
+            downloads.user_agent, downloads.bytes_sent, downloads.completed,
+            share_links.name AS share_link_name
+            FROM downloads
+            JOIN uploads ON uploads.id = downloads.upload
+            LEFT JOIN users ON users.id = downloads.user
+            LEFT JOIN share_links ON share_links.id = downloads.share_link",
+        );
+
+        scope.push_conditions(&mut query, None);
+        query
+            .push(" ORDER BY downloads.downloaded_at DESC LIMIT ")
+            .push_bind(limit as i64)
+            .push(" OFFSET ")
+            .push_bind(offset as i64);
+
+        query.build_query_as().fetch_all(pool).await
+    }
+}
+
+/// The totals for the downloads in a scope.
+#[derive(Debug, FromRow, Serialize)]
+pub struct DownloadStats {
+    pub total: i64,
+    pub completed: i64,
+    pub bytes_sent: i64,
+    /// The number of different addresses that the downloads came from.
+    pub visitors: i64,
+}
+
+impl DownloadStats {
+    /// Get the totals for the downloads in the scope since the given time.
+    pub async fn get(
+        pool: &SqlitePool,
+        scope: DownloadScope,
+        since: OffsetDateTime,
+    ) -> sqlx::Result<Self> {
+        let mut query = QueryBuilder::new(
+            "SELECT COUNT(*) AS total, COALESCE(SUM(downloads.completed), 0) AS completed,
+            COALESCE(SUM(downloads.bytes_sent), 0) AS bytes_sent,
+            COUNT(DISTINCT downloads.remote_addr) AS visitors
+            FROM downloads
+            JOIN uploads ON uploads.id = downloads.upload",
+        );
+
+        scope.push_conditions(&mut query, Some(since));
+        query.build_query_as().fetch_one(pool).await
+    }
+}
+
+/// The number of downloads in a scope on a single day.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct DownloadDay {
+    pub day: Date,
+    pub downloads: i64,
+    pub completed: i64,
+    pub bytes_sent: i64,
+}
+
+impl DownloadDay {
+    /// Get the downloads for each day since the given time.
+    ///
+    /// Days without any downloads are not included.
+    pub async fn get(
+        pool: &SqlitePool,
+        scope: DownloadScope,
+        since: OffsetDateTime,
+    ) -> sqlx::Result<Vec<Self>> {
+        let mut query = QueryBuilder::new(
+            "SELECT date(downloads.downloaded_at) AS day, COUNT(*) AS downloads,
+            SUM(downloads.completed) AS completed, SUM(downloads.bytes_sent) AS bytes_sent
+            FROM downloads
+            JOIN uploads ON uploads.id = downloads.upload",
+        );
+
+        scope.push_conditions(&mut query, Some(since));
+        query.push(" GROUP BY day ORDER BY day");
+
+        query.build_query_as().fetch_all(pool).await
+    }
+}
+
+/// An upload with the number of times it was downloaded.
+#[derive(Debug, FromRow, Serialize)]
+pub struct DownloadTop {
+    pub id: Key<Upload>,
+    pub slug: String,
+    pub filename: String,
+    pub downloads: i64,
+    pub bytes_sent: i64,
+}
+
+impl DownloadTop {
+    /// Get the uploads in the scope that were downloaded the most since the given time.
+    pub async fn get(
+        pool: &SqlitePool,
+        scope: DownloadScope,
+        since: OffsetDateTime,
+        limit: u32,
+    ) -> sqlx::Result<Vec<Self>> {
+        let mut query = QueryBuilder::new(
+            "SELECT uploads.id, uploads.slug, uploads.filename, COUNT(*) AS downloads,
+            SUM(downloads.bytes_sent) AS bytes_sent
+            FROM downloads
+            JOIN uploads ON uploads.id = downloads.upload",
+        );
+
+        scope.push_conditions(&mut query, Some(since));
+        query
+            .push(" GROUP BY uploads.id ORDER BY COUNT(*) DESC LIMIT ")
+            .push_bind(limit as i64);
+
+        query.build_query_as().fetch_all(pool).await
+    }
+}
@@ -0,0 +1,378 @@
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+use time::OffsetDateTime;
+
+use crate::{
+    team::{Team, TeamMember},
+    types::Key,
+    upload::{Upload, UploadOwnership, UploadPermission},
+    user::User,
+};
+
+/// A folder that organises the uploads of a user or team.
+///
+/// Folders can be nested, and a folder that is shared makes the uploads in it, and in any folders
+/// inside it, available from its share page.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Folder {
+    pub id: Key<Folder>,
+    pub slug: String,
+    pub name: String,
+    /// The folder that contains this folder, or `None` for a folder at the top level.
+    pub parent: Option<Key<Folder>>,
+    pub public: bool,
+    pub owner_user: Option<Key<User>>,
+    pub owner_team: Option<Key<Team>>,
+    pub created_by: Option<Key<User>>,
+    pub created_at: OffsetDateTime,
+}
+
+/// A folder along with how deeply it is nested, as used to show all the folders of an owner.
+#[derive(Debug, Serialize)]
+pub struct FolderTreeEntry {
+    pub folder: Folder,
+    pub depth: usize,
+}
+
+impl Folder {
+    /// Create a new folder that is not shared, owned by either a user or a team.
+    pub fn new(
+        slug: String,
+        name: &str,
+        parent: Option<Key<Folder>>,
+        owner_user: Option<Key<User>>,
+        owner_team: Option<Key<Team>>,
+        created_by: Key<User>,
+    ) -> Self {
+        Self {
+            id: Key::new(),
+            slug,
+            name: name.to_string(),
+            parent,
+            public: false,
+            owner_user,
+            owner_team,
+            created_by: Some(created_by),
+            created_at: OffsetDateTime::now_utc(),
+        }
+    }
+
+    pub async fn create(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        sqlx::query(
+            "INSERT INTO folders (id, slug, name, parent, public, owner_user, owner_team,
+            created_by, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        )
+        .bind(self.id)
+        .bind(&self.slug)
+        .bind(&self.name)
+        .bind(self.parent)
+        .bind(self.public)
+        .bind(self.owner_user)
+        .bind(self.owner_team)
+        .bind(self.created_by)
+        .bind(self.created_at)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn save(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        let count = sqlx::query("UPDATE folders SET name = $1, public = $2 WHERE id = $3")
+            .bind(&self.name)
+            .bind(self.public)
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        if count.rows_affected() == 0 {
+            Err(sqlx::Error::RowNotFound)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub async fn get(pool: &SqlitePool, id: Key<Folder>) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as("SELECT * FROM folders WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    pub async fn get_by_slug(pool: &SqlitePool, slug: &str) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as("SELECT * FROM folders WHERE slug = $1")
+            .bind(slug)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Get the folders of a user that are directly inside the given folder, or at the top level.
+    pub async fn get_for_user(
+        pool: &SqlitePool,
+        user: Key<User>,
+        parent: Option<Key<Folder>>,
+    ) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as(
+            "SELECT * FROM folders WHERE owner_user = $1 AND parent IS $2
+            ORDER BY name COLLATE NOCASE",
+        )
+        .bind(user)
+        .bind(parent)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Get the folders of a team that are directly inside the given folder, or at the top level.
+    pub async fn get_for_team(
+        pool: &SqlitePool,
+        team: Key<Team>,
+        parent: Option<Key<Folder>>,
+    ) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as(
+            "SELECT * FROM folders WHERE owner_team = $1 AND parent IS $2
+            ORDER BY name COLLATE NOCASE",
+        )
+        .bind(team)
+        .bind(parent)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Get all of the folders of a user, arranged as a tree.
+    pub async fn get_tree_for_user(
+        pool: &SqlitePool,
+        user: Key<User>,
+    ) -> sqlx::Result<Vec<FolderTreeEntry>> {
+        let folders = sqlx::query_as(
+            "SELECT * FROM folders WHERE owner_user = $1 ORDER BY name COLLATE NOCASE",
+        )
+        .bind(user)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Self::into_tree(folders))
+    }
+
+    /// Get all of the folders of a team, arranged as a tree.
+    pub async fn get_tree_for_team(
+        pool: &SqlitePool,
+        team: Key<Team>,
+    ) -> sqlx::Result<Vec<FolderTreeEntry>> {
+        let folders = sqlx::query_as(
+            "SELECT * FROM folders WHERE owner_team = $1 ORDER BY name COLLATE NOCASE",
+        )
+        .bind(team)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Self::into_tree(folders))
+    }
+
+    /// Arrange folders so that each folder is followed by the folders inside it.
+    fn into_tree(folders: Vec<Self>) -> Vec<FolderTreeEntry> {
+        fn visit(
+            folders: &[Folder],
+            parent: Option<Key<Folder>>,
+            depth: usize,
+            tree: &mut Vec<FolderTreeEntry>,
+        ) {
+            for folder in folders.iter().filter(|folder| folder.parent == parent) {
+                tree.push(FolderTreeEntry {
+                    folder: folder.clone(),
+                    depth,
+                });
+
+                visit(folders, Some(folder.id), depth + 1, tree);
+            }
+        }
+
+        let mut tree = Vec::with_capacity(folders.len());
+        visit(&folders, None, 0, &mut tree);
+        tree
+    }
+
+    /// Get the folders from the top level down to, and including, this folder.
+    pub async fn get_path(&self, pool: &SqlitePool) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as(
+            "WITH RECURSIVE path (id, depth) AS (
+                SELECT $1, 0
+                UNION ALL
+                SELECT folders.parent, path.depth + 1 FROM folders
+                JOIN path ON folders.id = path.id
+                WHERE folders.parent IS NOT NULL
+            )
+            SELECT folders.* FROM folders
+            JOIN path ON folders.id = path.id
+            ORDER BY path.depth DESC",
+        )
+        .bind(self.id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Check whether the given folder is this folder, or is inside this folder at any depth.
+    pub async fn contains(&self, pool: &SqlitePool, folder: Key<Folder>) -> sqlx::Result<bool> {
+        sqlx::query_scalar(
+            "WITH RECURSIVE ancestors (id) AS (
+                SELECT $1
+                UNION ALL
+                SELECT folders.parent FROM folders
+                JOIN ancestors ON folders.id = ancestors.id
+                WHERE folders.parent IS NOT NULL
+            )
+            SELECT EXISTS(SELECT 1 FROM ancestors WHERE id = $2)",
+        )
+        .bind(folder)
+        .bind(self.id)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Check whether this folder, or any folder that contains it, is shared.
+    pub async fn is_shared(&self, pool: &SqlitePool) -> sqlx::Result<bool> {
+        if self.public {
+            return Ok(true);
+        }
+
+        Ok(self
+            .get_path(pool)
+            .await?
+            .iter()
+            .any(|folder| folder.public))
+    }
+
+    /// Get the uploads that are directly inside this folder, in order of their filename.
+    pub async fn get_uploads(&self, pool: &SqlitePool) -> sqlx::Result<Vec<Upload>> {
+        sqlx::query_as(
+            "SELECT * FROM uploads WHERE folder = $1 AND hidden_at IS NULL
+            ORDER BY filename COLLATE NOCASE",
+        )
+        .bind(self.id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Get the folders that are directly inside this folder, in order of their name.
+    pub async fn get_children(&self, pool: &SqlitePool) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as("SELECT * FROM folders WHERE parent = $1 ORDER BY name COLLATE NOCASE")
+            .bind(self.id)
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Move the folder into another folder, or to the top level.
+    pub async fn set_parent(
+        &mut self,
+        pool: &SqlitePool,
+        parent: Option<Key<Folder>>,
+    ) -> sqlx::Result<()> {
+        let count = sqlx::query("UPDATE folders SET parent = $1 WHERE id = $2")
+            .bind(parent)
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        if count.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound);
+        }
+
+        self.parent = parent;
+        Ok(())
+    }
+
+    /// Delete the folder, moving the uploads and folders that were in it into its parent.
+    pub async fn delete(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query("UPDATE folders SET parent = $1 WHERE parent = $2")
+            .bind(self.parent)
+            .bind(self.id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("UPDATE uploads SET folder = $1 WHERE folder = $2")
+            .bind(self.parent)
+            .bind(self.id)
+            .execute(&mut *tx)
+            .await?;
+
+        let result = sqlx::query("DELETE FROM folders WHERE id = $1")
+            .bind(self.id)
+            .execute(&mut *tx)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound);
+        }
+
+        tx.commit().await
+    }
+
+    /// Check whether an upload has the same owner as this folder, and so can be put in it.
+    pub fn same_owner(&self, upload: &Upload) -> bool {
+        self.owner_user == upload.owner_user && self.owner_team == upload.owner_team
+    }
+
+    pub async fn is_owner(
+        &self,
+        pool: &SqlitePool,
+        user: &User,
+    ) -> sqlx::Result<Option<UploadOwnership>> {
+        if matches!(self.owner_user, Some(owner) if owner == user.id) {
+            return Ok(Some(UploadOwnership::OwnedByUser));
+        }
+
+        if let Some(owner) = self.owner_team {
+            if let Some(membership) =
+                TeamMember::get_for_user_and_team(pool, user.id, owner).await?
+            {
+                return Ok(Some(UploadOwnership::OwnedByTeam(membership)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Check whether a user can access the folder.
+    ///
+    /// Anyone can view and download from a folder that is shared, or that is inside a shared
+    /// folder. Otherwise the rules are the same as for an upload.
+    pub async fn can_access(
+        &self,
+        pool: &SqlitePool,
+        user: Option<&User>,
+        permission: UploadPermission,
+    ) -> sqlx::Result<bool> {
+        if user.map(|user| user.admin).unwrap_or(false) {
+            return Ok(true);
+        }
+
+        let ownership = match user {
+            Some(user) => self.is_owner(pool, user).await?,
+            None => None,
+        };
+
+        Ok(match permission {
+            UploadPermission::View | UploadPermission::Download { .. } => {
+                ownership.is_some() || self.is_shared(pool).await?
+            }
+
+            UploadPermission::Share
+            | UploadPermission::Transfer
+            | UploadPermission::ResetDownloads
+            | UploadPermission::Edit
+            | UploadPermission::Delete => match ownership {
+                Some(UploadOwnership::OwnedByUser) => true,
+                Some(UploadOwnership::OwnedByTeam(membership)) => {
+                    if permission == UploadPermission::Delete {
+                        membership.can_delete
+                    } else {
+                        membership.can_edit
+                    }
+                }
+                None => false,
+            },
+        })
+    }
+}
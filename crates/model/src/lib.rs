@@ -1,7 +1,22 @@
+pub mod api_token;
+pub mod audit;
+pub mod blob;
+pub mod bundle;
+pub mod download;
+pub mod folder;
 pub mod login_attempt;
 pub mod migration;
 pub mod password;
+pub mod password_reset_request;
+pub mod recovery_code;
+pub mod saved_filter;
+pub mod share_link;
 pub mod team;
 pub mod types;
 pub mod upload;
+pub mod upload_request;
+pub mod upload_session;
 pub mod user;
+pub mod user_token;
+pub mod webauthn;
+pub mod webhook;
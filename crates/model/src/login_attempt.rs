@@ -55,22 +55,39 @@ impl LoginAttempt {
         Ok(())
     }
 
-    /// Check if an account is currently locked out due to too many failed attempts.
+    /// Record a failed login attempt.
     ///
-    /// Returns `true` if there have been `LOCKOUT_THRESHOLD` or more failed attempts
-    /// within the last `LOCKOUT_WINDOW_SECS` seconds.
-    pub async fn is_locked_out(pool: &SqlitePool, username: &str) -> sqlx::Result<bool> {
+    /// Returns `true` if this attempt is the one that locked out the account, so that the lockout
+    /// can be reported once, rather than for every attempt that is refused while it lasts.
+    pub async fn record_failure(
+        pool: &SqlitePool,
+        username: &str,
+        ip_address: Option<&str>,
+    ) -> sqlx::Result<bool> {
+        Self::record(pool, username, ip_address, false).await?;
+        Ok(Self::count_failures(pool, username).await? == LOCKOUT_THRESHOLD)
+    }
+
+    /// Count the failed attempts for an account within the last `LOCKOUT_WINDOW_SECS` seconds.
+    async fn count_failures(pool: &SqlitePool, username: &str) -> sqlx::Result<i64> {
         let cutoff = OffsetDateTime::now_utc() - time::Duration::seconds(LOCKOUT_WINDOW_SECS);
 
-        let count: i64 = sqlx::query_scalar(
+        sqlx::query_scalar(
             "SELECT COUNT(*) FROM login_attempts \
              WHERE username = $1 AND attempted_at > $2 AND success = 0",
         )
         .bind(username)
         .bind(cutoff)
         .fetch_one(pool)
-        .await?;
+        .await
+    }
 
+    /// Check if an account is currently locked out due to too many failed attempts.
+    ///
+    /// Returns `true` if there have been `LOCKOUT_THRESHOLD` or more failed attempts
+    /// within the last `LOCKOUT_WINDOW_SECS` seconds.
+    pub async fn is_locked_out(pool: &SqlitePool, username: &str) -> sqlx::Result<bool> {
+        let count = Self::count_failures(pool, username).await?;
         let locked = count >= LOCKOUT_THRESHOLD;
 
         if locked {
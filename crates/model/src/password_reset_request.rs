@@ -0,0 +1,81 @@
+use sqlx::{FromRow, SqlitePool};
+use time::OffsetDateTime;
+
+use crate::types::Key;
+
+/// The number of password reset links that can be asked for from one address within the window.
+const REQUEST_LIMIT: i64 = 5;
+
+/// The time window (in seconds) for counting requests.
+const REQUEST_WINDOW_SECS: i64 = 3600; // 1 hour
+
+/// A request for a password reset link, kept so that the forgotten password form cannot be used to
+/// send a flood of email.
+///
+/// Requests are limited by the address that they come from. Each user is also only sent one link
+/// at a time (see
+/// [`UserToken::create_unless_recent`](crate::user_token::UserToken::create_unless_recent)).
+#[derive(Debug, FromRow)]
+pub struct PasswordResetRequest {
+    pub id: Key<PasswordResetRequest>,
+    pub username: String,
+    pub ip_address: Option<String>,
+    pub requested_at: OffsetDateTime,
+}
+
+impl PasswordResetRequest {
+    /// Record a request for a password reset link for the given username or email address.
+    pub async fn record(
+        pool: &SqlitePool,
+        username: &str,
+        ip_address: Option<&str>,
+    ) -> sqlx::Result<()> {
+        sqlx::query(
+            "INSERT INTO password_reset_requests (id, username, ip_address, requested_at) \
+             VALUES ($1, $2, $3, $4)",
+        )
+        .bind(Key::<PasswordResetRequest>::new())
+        .bind(username)
+        .bind(ip_address)
+        .bind(OffsetDateTime::now_utc())
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Check if `REQUEST_LIMIT` or more requests have come from an address within the last
+    /// `REQUEST_WINDOW_SECS` seconds.
+    ///
+    /// Requests whose address is not known are not limited.
+    pub async fn is_rate_limited(
+        pool: &SqlitePool,
+        ip_address: Option<&str>,
+    ) -> sqlx::Result<bool> {
+        let Some(ip_address) = ip_address else {
+            return Ok(false);
+        };
+
+        let cutoff = OffsetDateTime::now_utc() - time::Duration::seconds(REQUEST_WINDOW_SECS);
+        let count: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM password_reset_requests \
+             WHERE ip_address = $1 AND requested_at > $2",
+        )
+        .bind(ip_address)
+        .bind(cutoff)
+        .fetch_one(pool)
+        .await?;
+
+        if count >= REQUEST_LIMIT {
+            tracing::warn!(
+                %ip_address,
+                requests = count,
+                limit = REQUEST_LIMIT,
+                window_secs = REQUEST_WINDOW_SECS,
+                "Too many password reset requests from address"
+            );
+        }
+
+        Ok(count >= REQUEST_LIMIT)
+    }
+}
@@ -0,0 +1,162 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, SqlitePool};
+use time::OffsetDateTime;
+
+use crate::{types::Key, user::User};
+
+/// The number of recovery codes that are generated at once.
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// The characters that a recovery code is made from, leaving out those that are easily confused.
+const RECOVERY_CODE_CHARSET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// The number of characters in a recovery code, not counting the separator.
+const RECOVERY_CODE_LEN: usize = 10;
+
+/// A single-use code that lets a user sign in when they do not have their authenticator app.
+#[derive(Debug, FromRow)]
+pub struct RecoveryCode {
+    pub id: Key<RecoveryCode>,
+    pub user: Key<User>,
+    pub code_hash: String,
+    pub created_at: OffsetDateTime,
+    pub used_at: Option<OffsetDateTime>,
+}
+
+/// Normalise a recovery code as given by the user, and hash it for storage or lookup.
+///
+/// Recovery codes are random, so like API tokens a single fast hash is sufficient.
+fn hash_code(code: &str) -> String {
+    let code = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect::<String>();
+
+    format!("{:x}", Sha256::digest(code.as_bytes()))
+}
+
+/// Check whether the code given by the user has the shape of a recovery code: ten characters from
+/// the recovery code alphabet, ignoring case, with an optional separator between the two groups.
+pub fn is_recovery_code(code: &str) -> bool {
+    let half = RECOVERY_CODE_LEN / 2;
+    let code = match code.as_bytes().get(half) {
+        Some(b'-') => code[..half].to_string() + &code[half + 1..],
+        _ => code.to_string(),
+    };
+
+    code.len() == RECOVERY_CODE_LEN
+        && code
+            .bytes()
+            .all(|c| RECOVERY_CODE_CHARSET.contains(&c.to_ascii_lowercase()))
+}
+
+/// Generate a new recovery code, formatted as two groups of characters.
+fn generate_code() -> String {
+    let mut code = String::with_capacity(RECOVERY_CODE_LEN + 1);
+    for index in 0..RECOVERY_CODE_LEN {
+        if index == RECOVERY_CODE_LEN / 2 {
+            code.push('-');
+        }
+
+        let choice = OsRng.next_u32() as usize % RECOVERY_CODE_CHARSET.len();
+        code.push(RECOVERY_CODE_CHARSET[choice] as char);
+    }
+
+    code
+}
+
+impl RecoveryCode {
+    /// Replace the recovery codes of a user with a new set, returning the new codes.
+    ///
+    /// The codes themselves are not stored, so they can only be shown to the user once.
+    pub async fn generate(pool: &SqlitePool, user: Key<User>) -> sqlx::Result<Vec<String>> {
+        let codes = (0..RECOVERY_CODE_COUNT)
+            .map(|_| generate_code())
+            .collect::<Vec<_>>();
+
+        let now = OffsetDateTime::now_utc();
+        let mut tx = pool.begin().await?;
+
+        sqlx::query("DELETE FROM totp_recovery_codes WHERE user = $1")
+            .bind(user)
+            .execute(&mut *tx)
+            .await?;
+
+        for code in &codes {
+            sqlx::query(
+                "INSERT INTO totp_recovery_codes (id, user, code_hash, created_at) \
+                VALUES ($1, $2, $3, $4)",
+            )
+            .bind(Key::<RecoveryCode>::new())
+            .bind(user)
+            .bind(hash_code(code))
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(codes)
+    }
+
+    /// Use one of the recovery codes of a user, returning `false` if the code is not one of their
+    /// unused codes.
+    pub async fn redeem(pool: &SqlitePool, user: Key<User>, code: &str) -> sqlx::Result<bool> {
+        let result = sqlx::query(
+            "UPDATE totp_recovery_codes SET used_at = $1 \
+            WHERE id = (SELECT id FROM totp_recovery_codes \
+                        WHERE user = $2 AND code_hash = $3 AND used_at IS NULL LIMIT 1)",
+        )
+        .bind(OffsetDateTime::now_utc())
+        .bind(user)
+        .bind(hash_code(code))
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Count the recovery codes of a user that have not yet been used.
+    pub async fn count_remaining(pool: &SqlitePool, user: Key<User>) -> sqlx::Result<i64> {
+        sqlx::query_scalar(
+            "SELECT COUNT(*) FROM totp_recovery_codes WHERE user = $1 AND used_at IS NULL",
+        )
+        .bind(user)
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn delete_for_user(pool: &SqlitePool, user: Key<User>) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM totp_recovery_codes WHERE user = $1")
+            .bind(user)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_recovery_code() {
+        assert!(is_recovery_code(&generate_code()));
+        assert!(is_recovery_code("abcde-23456"));
+        assert!(is_recovery_code("ABCDE23456"));
+
+        // Mistyped TOTP codes are not taken to be recovery codes.
+        assert!(!is_recovery_code("123456"));
+        assert!(!is_recovery_code("123 456"));
+        assert!(!is_recovery_code("12345a"));
+
+        // Characters that are left out of the alphabet, and misplaced separators.
+        assert!(!is_recovery_code("abcde-0o1il"));
+        assert!(!is_recovery_code("abcd-e23456"));
+        assert!(!is_recovery_code("abcde--23456"));
+        assert!(!is_recovery_code("abcdé-23456"));
+    }
+}
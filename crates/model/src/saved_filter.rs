@@ -0,0 +1,104 @@
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+use time::OffsetDateTime;
+
+use crate::{team::Team, types::Key, user::User};
+
+/// A named filter for the list of uploads of a user or team, which is shown as a tab.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct SavedFilter {
+    pub id: Key<SavedFilter>,
+    pub name: String,
+    /// The filter, encoded as the query string of the upload list.
+    pub query: String,
+    pub owner_user: Option<Key<User>>,
+    pub owner_team: Option<Key<Team>>,
+    pub created_by: Option<Key<User>>,
+    pub created_at: OffsetDateTime,
+}
+
+impl SavedFilter {
+    /// Create a new saved filter, owned by either a user or a team.
+    pub fn new(
+        name: &str,
+        query: String,
+        owner_user: Option<Key<User>>,
+        owner_team: Option<Key<Team>>,
+        created_by: Key<User>,
+    ) -> Self {
+        Self {
+            id: Key::new(),
+            name: name.to_string(),
+            query,
+            owner_user,
+            owner_team,
+            created_by: Some(created_by),
+            created_at: OffsetDateTime::now_utc(),
+        }
+    }
+
+    pub async fn create(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        sqlx::query(
+            "INSERT INTO saved_filters (id, name, query, owner_user, owner_team, created_by,
+            created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(self.id)
+        .bind(&self.name)
+        .bind(&self.query)
+        .bind(self.owner_user)
+        .bind(self.owner_team)
+        .bind(self.created_by)
+        .bind(self.created_at)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get(pool: &SqlitePool, id: Key<SavedFilter>) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as("SELECT * FROM saved_filters WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Get the filters that a user has saved for their own uploads.
+    pub async fn get_for_user(pool: &SqlitePool, user: Key<User>) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as(
+            "SELECT * FROM saved_filters WHERE owner_user = $1 ORDER BY name COLLATE NOCASE",
+        )
+        .bind(user)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Get the filters that have been saved for the teams that a user is a member of.
+    pub async fn get_for_teams_of_user(
+        pool: &SqlitePool,
+        user: Key<User>,
+    ) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as(
+            "SELECT saved_filters.* FROM saved_filters
+            JOIN team_members ON team_members.team = saved_filters.owner_team
+            WHERE team_members.user = $1
+            ORDER BY saved_filters.name COLLATE NOCASE",
+        )
+        .bind(user)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn delete(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        let result = sqlx::query("DELETE FROM saved_filters WHERE id = $1")
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            Err(sqlx::Error::RowNotFound)
+        } else {
+            Ok(())
+        }
+    }
+}
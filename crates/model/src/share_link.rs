@@ -0,0 +1,218 @@
+use anyhow::Context;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+use time::{Date, OffsetDateTime};
+
+use crate::{password::StoredPassword, types::Key, upload::Upload, user::User};
+
+/// The number of random bytes in the token of a share link.
+const TOKEN_BYTES: usize = 16;
+
+/// Whether a share link can be used to download its upload.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareLinkStatus {
+    Active,
+    /// The link was revoked by the owner of the upload.
+    Revoked,
+    /// The expiry date of the link or the upload has passed.
+    Expired,
+    /// The link or the upload has no remaining downloads.
+    Exhausted,
+}
+
+/// A link that gives access to an upload, with its own password, download limit and expiry date.
+///
+/// Each upload can have any number of share links, which can be revoked without affecting the
+/// others, or the upload itself. The expiry date and download limit of the upload still apply to
+/// all of its links, so a link can only narrow them.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct ShareLink {
+    pub id: Key<ShareLink>,
+    pub upload: Key<Upload>,
+    pub token: String,
+    pub name: String,
+    #[serde(skip)]
+    pub password: Option<StoredPassword>,
+    pub limit: Option<i64>,
+    pub remaining: Option<i64>,
+    pub expiry_date: Option<Date>,
+    pub downloads: i64,
+    pub created_by: Option<Key<User>>,
+    pub created_at: OffsetDateTime,
+    pub revoked_at: Option<OffsetDateTime>,
+}
+
+impl ShareLink {
+    /// Create a new link to an upload with a random token.
+    pub fn new(
+        upload: Key<Upload>,
+        created_by: Option<Key<User>>,
+        name: &str,
+        limit: Option<i64>,
+        expiry_date: Option<Date>,
+    ) -> Self {
+        let mut bytes = [0u8; TOKEN_BYTES];
+        OsRng.fill_bytes(&mut bytes);
+
+        let mut token = String::with_capacity(TOKEN_BYTES * 2);
+        for byte in bytes {
+            token.push_str(&format!("{byte:02x}"));
+        }
+
+        Self {
+            id: Key::new(),
+            upload,
+            token,
+            name: name.to_string(),
+            password: None,
+            limit,
+            remaining: limit,
+            expiry_date,
+            downloads: 0,
+            created_by,
+            created_at: OffsetDateTime::now_utc(),
+            revoked_at: None,
+        }
+    }
+
+    pub async fn create(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        sqlx::query(
+            "INSERT INTO share_links (id, upload, token, name, password, \"limit\", remaining,
+            expiry_date, downloads, created_by, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+        )
+        .bind(self.id)
+        .bind(self.upload)
+        .bind(&self.token)
+        .bind(&self.name)
+        .bind(&self.password)
+        .bind(self.limit)
+        .bind(self.remaining)
+        .bind(self.expiry_date)
+        .bind(self.downloads)
+        .bind(self.created_by)
+        .bind(self.created_at)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get(pool: &SqlitePool, id: Key<ShareLink>) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as("SELECT * FROM share_links WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    pub async fn get_by_token(pool: &SqlitePool, token: &str) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as("SELECT * FROM share_links WHERE token = $1")
+            .bind(token)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Get all the links to an upload, including those that have been revoked.
+    pub async fn get_for_upload(pool: &SqlitePool, upload: Key<Upload>) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as("SELECT * FROM share_links WHERE upload = $1 ORDER BY created_at")
+            .bind(upload)
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn set_password(&mut self, pool: &SqlitePool, password: &str) -> anyhow::Result<()> {
+        let password = StoredPassword::new(password).context("failed to hash password")?;
+
+        let result = sqlx::query("UPDATE share_links SET password = $1 WHERE id = $2")
+            .bind(&password)
+            .bind(self.id)
+            .execute(pool)
+            .await
+            .context("failed to update share link password")?;
+
+        if result.rows_affected() == 0 {
+            anyhow::bail!("Share link not found");
+        }
+
+        self.password = Some(password);
+        Ok(())
+    }
+
+    /// Revoke the link, so that it can no longer be used.
+    pub async fn revoke(&mut self, pool: &SqlitePool) -> sqlx::Result<()> {
+        let now = OffsetDateTime::now_utc();
+        let result = sqlx::query(
+            "UPDATE share_links SET revoked_at = $1 WHERE id = $2 AND revoked_at IS NULL",
+        )
+        .bind(now)
+        .bind(self.id)
+        .execute(pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound);
+        }
+
+        self.revoked_at = Some(now);
+        Ok(())
+    }
+
+    /// Record that the upload has been downloaded using this link.
+    ///
+    /// This reduces the number of remaining downloads of the link, if it has a limit. The download
+    /// must also be recorded against the upload, which counts every download of the upload however
+    /// it was made.
+    pub async fn record_download(&mut self, pool: &SqlitePool) -> sqlx::Result<()> {
+        let (downloads, remaining) = sqlx::query_as::<_, (i64, Option<i64>)>(
+            "UPDATE share_links SET downloads = downloads + 1, \
+            remaining = CASE WHEN remaining IS NULL THEN NULL ELSE MAX(0, remaining - 1) END \
+            WHERE id = $1 RETURNING downloads, remaining",
+        )
+        .bind(self.id)
+        .fetch_one(pool)
+        .await?;
+
+        self.downloads = downloads;
+        self.remaining = remaining;
+
+        Ok(())
+    }
+
+    /// Get the links that have not been revoked and that will expire within the given number of
+    /// days, and that have not been returned for their current expiry date, marking them so that
+    /// they are not returned again.
+    pub async fn take_expiring(pool: &SqlitePool, days: u32) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as(
+            "UPDATE share_links SET notified_expiring = expiry_date \
+            WHERE revoked_at IS NULL AND expiry_date IS NOT NULL \
+            AND expiry_date >= DATE('now') AND expiry_date <= DATE('now', '+' || $1 || ' days') \
+            AND (notified_expiring IS NULL OR notified_expiring <> expiry_date) \
+            RETURNING *",
+        )
+        .bind(days)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Check whether the link can still be used to download the given upload, and if not, why not.
+    ///
+    /// A link that has not been revoked stops working once the upload itself has expired or has no
+    /// remaining downloads, whatever the expiry date and limit of the link.
+    pub fn status(&self, upload: &Upload) -> ShareLinkStatus {
+        let today = OffsetDateTime::now_utc().date();
+        let expired = |expiry_date: Option<Date>| expiry_date.is_some_and(|expiry| expiry < today);
+        let exhausted = |remaining: Option<i64>| remaining.is_some_and(|remaining| remaining < 1);
+
+        if self.revoked_at.is_some() {
+            ShareLinkStatus::Revoked
+        } else if expired(self.expiry_date) || expired(upload.expiry_date) {
+            ShareLinkStatus::Expired
+        } else if exhausted(self.remaining) || exhausted(upload.remaining) {
+            ShareLinkStatus::Exhausted
+        } else {
+            ShareLinkStatus::Active
+        }
+    }
+}
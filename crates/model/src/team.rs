@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, QueryBuilder, SqlitePool};
 use time::OffsetDateTime;
 
-use super::{types::Key, user::User};
+use super::{saved_filter::SavedFilter, types::Key, user::User};
 
 #[derive(Debug, FromRow, Serialize)]
 pub struct Team {
@@ -92,6 +92,11 @@ impl Team {
     }
 
     pub async fn delete(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM api_tokens WHERE team = $1")
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
         sqlx::query("DELETE FROM team_members WHERE team = $1")
             .bind(self.id)
             .execute(pool)
@@ -309,11 +314,14 @@ pub struct TeamTab {
     pub name: String,
     pub slug: String,
     pub count: i64,
+    /// The filters that have been saved for the team, which are shown after its tab.
+    #[sqlx(skip)]
+    pub filters: Vec<SavedFilter>,
 }
 
 impl TeamTab {
     pub async fn get_for_user(pool: &SqlitePool, user: Key<User>) -> sqlx::Result<Vec<Self>> {
-        sqlx::query_as(
+        let mut tabs: Vec<Self> = sqlx::query_as(
             "SELECT teams.id, teams.name, teams.slug, COUNT(uploads.id) AS count \
             FROM teams \
             LEFT JOIN uploads ON uploads.owner_team = teams.id \
@@ -323,25 +331,42 @@ impl TeamTab {
         )
         .bind(user)
         .fetch_all(pool)
-        .await
+        .await?;
+
+        for filter in SavedFilter::get_for_teams_of_user(pool, user).await? {
+            if let Some(tab) = tabs
+                .iter_mut()
+                .find(|tab| Some(tab.id) == filter.owner_team)
+            {
+                tab.filters.push(filter);
+            }
+        }
+
+        Ok(tabs)
     }
 }
 
 #[derive(Debug, FromRow, Serialize)]
 pub struct HomeTab {
     pub count: i64,
+    /// The filters that the user has saved for their own uploads, which are shown after this tab.
+    #[sqlx(skip)]
+    pub filters: Vec<SavedFilter>,
 }
 
 impl HomeTab {
     pub async fn get_for_user(pool: &SqlitePool, user: Key<User>) -> sqlx::Result<Self> {
-        sqlx::query_as(
+        let mut tab: Self = sqlx::query_as(
             "SELECT COUNT(uploads.id) AS count \
             FROM uploads \
             WHERE uploads.owner_user = $1",
         )
         .bind(user)
         .fetch_one(pool)
-        .await
+        .await?;
+
+        tab.filters = SavedFilter::get_for_user(pool, user).await?;
+        Ok(tab)
     }
 }
 
@@ -1,12 +1,15 @@
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, QueryBuilder, SqlitePool};
+use sqlx::{FromRow, QueryBuilder, Sqlite, SqlitePool};
 use time::{Date, OffsetDateTime};
 
 use super::{
+    bundle::Bundle,
+    folder::Folder,
     password::StoredPassword,
     team::{Team, TeamMember},
     types::Key,
+    upload_request::UploadRequest,
     user::User,
 };
 
@@ -14,6 +17,10 @@ use super::{
 pub struct Upload {
     pub id: Key<Upload>,
     pub slug: String,
+    /// The key of the blob that holds the content of the upload.
+    pub blob: String,
+    /// The hex-encoded SHA-256 hash of the content, which is not known for some older uploads.
+    pub hash: Option<String>,
     pub filename: String,
     pub size: i64,
     pub public: bool,
@@ -32,6 +39,14 @@ pub struct Upload {
     pub mime_type: Option<String>,
     pub has_preview: bool,
     pub preview_error: Option<String>,
+    /// The bundle that the upload belongs to, if any.
+    pub bundle: Option<Key<Bundle>>,
+    /// The folder that the upload is in, or `None` for an upload at the top level.
+    pub folder: Option<Key<Folder>>,
+    /// Whether the text of the upload has been extracted for searching, or there was none.
+    pub text_extracted: bool,
+    /// The upload request that the upload was received through, if any.
+    pub upload_request: Option<Key<UploadRequest>>,
 }
 
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
@@ -84,11 +99,11 @@ impl Upload {
             "INSERT INTO uploads (id, slug, filename, size, public,
             downloads, \"limit\", remaining, expiry_date, password,
             custom_slug, uploaded_by, uploaded_at, remote_addr,
-            owner_team, owner_user)
+            owner_team, owner_user, blob, hash)
             VALUES ($1, $2, $3, $4, $5,
                     0, $6, $7, $8, $9,
                     $10, $11, $12, $13,
-                    $14, $15)
+                    $14, $15, $16, $17)
             RETURNING id",
         )
         .bind(self.id)
@@ -106,6 +121,8 @@ impl Upload {
         .bind(&self.remote_addr)
         .bind(self.owner_team)
         .bind(self.owner_user)
+        .bind(&self.blob)
+        .bind(&self.hash)
         .execute(pool)
         .await?;
 
@@ -125,7 +142,8 @@ impl Upload {
             password = $8,
             custom_slug = $9,
             owner_team = $10,
-            owner_user = $11
+            owner_user = $11,
+            hidden_at = NULL
             WHERE id = $12",
         )
         .bind(&self.filename)
@@ -193,7 +211,7 @@ impl Upload {
     }
 
     /// Delete multiple uploads by their IDs in a single query.
-    /// Returns the slugs of the deleted uploads (for cache cleanup).
+    /// Returns the blobs of the deleted uploads (for cache cleanup).
     pub async fn delete_many(pool: &SqlitePool, ids: &[Key<Upload>]) -> sqlx::Result<Vec<String>> {
         if ids.is_empty() {
             return Ok(Vec::new());
@@ -204,37 +222,67 @@ impl Upload {
         for id in ids {
             separated.push_bind(*id);
         }
-        separated.push_unseparated(") RETURNING slug");
+        separated.push_unseparated(") RETURNING blob");
 
         query.build_query_scalar().fetch_all(pool).await
     }
 
-    pub async fn get_by_slug(pool: &SqlitePool, slug: &str) -> sqlx::Result<Option<Self>> {
-        sqlx::query_as("SELECT * FROM uploads WHERE slug = ?")
-            .bind(slug)
-            .fetch_optional(pool)
-            .await
-    }
-
-    /// Check which slugs exist in the database from a list of candidates.
-    /// Returns only the slugs that exist.
-    pub async fn get_existing_slugs(
+    /// Move multiple uploads into a folder, or to the top level, in a single query.
+    pub async fn move_many(
         pool: &SqlitePool,
-        slugs: &[String],
-    ) -> sqlx::Result<std::collections::HashSet<String>> {
-        if slugs.is_empty() {
-            return Ok(std::collections::HashSet::new());
+        ids: &[Key<Upload>],
+        folder: Option<Key<Folder>>,
+    ) -> sqlx::Result<()> {
+        if ids.is_empty() {
+            return Ok(());
         }
 
-        let mut query = QueryBuilder::new("SELECT slug FROM uploads WHERE slug IN (");
+        let mut query = QueryBuilder::new("UPDATE uploads SET folder = ");
+        query.push_bind(folder).push(" WHERE id IN (");
         let mut separated = query.separated(", ");
-        for slug in slugs {
-            separated.push_bind(slug);
+        for id in ids {
+            separated.push_bind(*id);
         }
         separated.push_unseparated(")");
 
-        let existing: Vec<String> = query.build_query_scalar().fetch_all(pool).await?;
-        Ok(existing.into_iter().collect())
+        query.build().execute(pool).await?;
+        Ok(())
+    }
+
+    /// Get the tags of the upload, in alphabetical order.
+    pub async fn get_tags(&self, pool: &SqlitePool) -> sqlx::Result<Vec<String>> {
+        sqlx::query_scalar("SELECT tag FROM upload_tags WHERE upload = $1 ORDER BY tag")
+            .bind(self.id)
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Replace the tags of the upload.
+    pub async fn set_tags(&self, pool: &SqlitePool, tags: &[String]) -> sqlx::Result<()> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query("DELETE FROM upload_tags WHERE upload = $1")
+            .bind(self.id)
+            .execute(&mut *tx)
+            .await?;
+
+        if !tags.is_empty() {
+            let mut query = QueryBuilder::new("INSERT INTO upload_tags (upload, tag) ");
+            query.push_values(tags, |mut row, tag| {
+                row.push_bind(self.id).push_bind(tag);
+            });
+
+            query.build().execute(&mut *tx).await?;
+        }
+
+        tx.commit().await
+    }
+
+    pub async fn get_by_slug(pool: &SqlitePool, slug: &str) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as("SELECT * FROM uploads WHERE slug = ?")
+            .bind(slug)
+            .fetch_optional(pool)
+            .await
     }
 
     pub async fn get_by_custom_slug(
@@ -300,29 +348,32 @@ impl Upload {
         query.build_query_scalar().fetch_one(pool).await
     }
 
+    /// Record that the upload has been downloaded, reducing the number of remaining downloads if
+    /// the upload has a download limit.
+    ///
+    /// Downloads by one of the owners of the upload are counted, but do not reduce the number of
+    /// remaining downloads, so that an owner checking their upload does not use up the downloads
+    /// that are meant for its recipients.
     pub async fn record_download(
         &mut self,
         pool: &SqlitePool,
         user: Option<&User>,
     ) -> sqlx::Result<()> {
-        let mut query = QueryBuilder::new("UPDATE uploads SET downloads = downloads + 1");
-
-        let public = match user {
-            None => false,
+        let owner = match user {
             Some(user) => self.is_owner(pool, user).await?.is_some(),
+            None => false,
         };
 
-        if public && self.remaining.is_some() {
-            query.push(", remaining = MAX(0, remaining - 1)");
-        }
-
-        query.push(" WHERE id = $1 RETURNING downloads, remaining");
-
-        let (downloads, remaining) = query
-            .build_query_as::<(i64, Option<i64>)>()
-            .bind(self.id)
-            .fetch_one(pool)
-            .await?;
+        let (downloads, remaining) = sqlx::query_as::<_, (i64, Option<i64>)>(
+            "UPDATE uploads SET downloads = downloads + 1, \
+            remaining = CASE WHEN remaining IS NULL OR $2 THEN remaining \
+            ELSE MAX(0, remaining - 1) END \
+            WHERE id = $1 RETURNING downloads, remaining",
+        )
+        .bind(self.id)
+        .bind(owner)
+        .fetch_one(pool)
+        .await?;
 
         self.downloads = downloads;
         self.remaining = remaining;
@@ -330,16 +381,19 @@ impl Upload {
         Ok(())
     }
 
-    pub async fn reset_remaining(&self, pool: &SqlitePool) -> sqlx::Result<()> {
-        let count = sqlx::query("UPDATE uploads SET remaining = \"limit\" WHERE id = $1")
-            .bind(self.id)
-            .execute(pool)
-            .await?;
+    pub async fn reset_remaining(&mut self, pool: &SqlitePool) -> sqlx::Result<()> {
+        let count = sqlx::query(
+            "UPDATE uploads SET remaining = \"limit\", hidden_at = NULL WHERE id = $1",
+        )
+        .bind(self.id)
+        .execute(pool)
+        .await?;
 
         if count.rows_affected() == 0 {
             return Err(sqlx::Error::RowNotFound);
         }
 
+        self.remaining = self.limit;
         Ok(())
     }
 
@@ -436,6 +490,60 @@ impl Upload {
         Ok(())
     }
 
+    /// Record the hash of the uploads that share a blob, for uploads where it was not known.
+    pub async fn set_hash_for_blob(pool: &SqlitePool, blob: &str, hash: &str) -> sqlx::Result<u64> {
+        let result = sqlx::query("UPDATE uploads SET hash = $1 WHERE blob = $2 AND hash IS NULL")
+            .bind(hash)
+            .bind(blob)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Set the text that was extracted from the content of the upload, which is added to the
+    /// search index. The text is `None` when there was no text to extract.
+    pub async fn set_text(&mut self, pool: &SqlitePool, text: Option<&str>) -> sqlx::Result<()> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query("UPDATE upload_search SET content = $1 WHERE upload = $2")
+            .bind(text.unwrap_or_default())
+            .bind(self.id)
+            .execute(&mut *tx)
+            .await?;
+
+        let result = sqlx::query("UPDATE uploads SET text_extracted = TRUE WHERE id = $1")
+            .bind(self.id)
+            .execute(&mut *tx)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound);
+        }
+
+        tx.commit().await?;
+        self.text_extracted = true;
+        Ok(())
+    }
+
+    /// Get the uploads with a known MIME type that have not yet had their text extracted.
+    pub async fn get_all_without_text(
+        pool: &SqlitePool,
+        offset: u32,
+        limit: u32,
+    ) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as(
+            "SELECT * FROM uploads \
+            WHERE NOT text_extracted AND mime_type IS NOT NULL \
+            LIMIT $1 \
+            OFFSET $2",
+        )
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(pool)
+        .await
+    }
+
     pub async fn get_all_without_preview(
         pool: &SqlitePool,
         offset: u32,
@@ -467,19 +575,108 @@ impl Upload {
     }
 
     pub async fn delete_for_user(pool: &SqlitePool, owner: Key<User>) -> sqlx::Result<Vec<String>> {
-        sqlx::query_scalar("DELETE FROM uploads WHERE owner_user = $1 RETURNING slug")
+        sqlx::query_scalar("DELETE FROM uploads WHERE owner_user = $1 RETURNING blob")
             .bind(owner)
             .fetch_all(pool)
             .await
     }
 
     pub async fn delete_for_team(pool: &SqlitePool, owner: Key<Team>) -> sqlx::Result<Vec<String>> {
-        sqlx::query_scalar("DELETE FROM uploads WHERE owner_team = $1 RETURNING slug")
+        sqlx::query_scalar("DELETE FROM uploads WHERE owner_team = $1 RETURNING blob")
             .bind(owner)
             .fetch_all(pool)
             .await
     }
 
+    /// Delete all uploads whose expiry date passed more than `days` days ago.
+    ///
+    /// Returns the blob and size of each deleted upload, so that the cache can be cleaned up.
+    pub async fn delete_expired(pool: &SqlitePool, days: u32) -> sqlx::Result<Vec<(String, i64)>> {
+        sqlx::query_as(
+            "DELETE FROM uploads \
+            WHERE expiry_date IS NOT NULL AND expiry_date < DATE('now', $1) \
+            RETURNING blob, size",
+        )
+        .bind(format!("-{days} days"))
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Delete all uploads that have no remaining downloads.
+    ///
+    /// Returns the blob and size of each deleted upload, so that the cache can be cleaned up.
+    pub async fn delete_exhausted(pool: &SqlitePool) -> sqlx::Result<Vec<(String, i64)>> {
+        sqlx::query_as(
+            "DELETE FROM uploads \
+            WHERE remaining IS NOT NULL AND remaining <= 0 \
+            RETURNING blob, size",
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Hide all uploads whose expiry date passed more than `days` days ago.
+    ///
+    /// Hidden uploads are no longer listed, but remain in the database and the cache until the
+    /// upload is edited or deleted. Returns the number of uploads that were hidden.
+    pub async fn hide_expired(pool: &SqlitePool, days: u32) -> sqlx::Result<u64> {
+        let result = sqlx::query(
+            "UPDATE uploads SET hidden_at = $1 \
+            WHERE hidden_at IS NULL \
+            AND expiry_date IS NOT NULL AND expiry_date < DATE('now', $2)",
+        )
+        .bind(OffsetDateTime::now_utc())
+        .bind(format!("-{days} days"))
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Hide all uploads that have no remaining downloads.
+    ///
+    /// Returns the number of uploads that were hidden.
+    pub async fn hide_exhausted(pool: &SqlitePool) -> sqlx::Result<u64> {
+        let result = sqlx::query(
+            "UPDATE uploads SET hidden_at = $1 \
+            WHERE hidden_at IS NULL \
+            AND remaining IS NOT NULL AND remaining <= 0",
+        )
+        .bind(OffsetDateTime::now_utc())
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Get the uploads that have passed their expiry date since this was last called, marking them
+    /// so that they are not returned again unless their expiry date is changed.
+    pub async fn take_newly_expired(pool: &SqlitePool) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as(
+            "UPDATE uploads SET notified_expiry = expiry_date \
+            WHERE expiry_date IS NOT NULL AND expiry_date < DATE('now') \
+            AND (notified_expiry IS NULL OR notified_expiry <> expiry_date) \
+            RETURNING *",
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Get the uploads that will expire within the given number of days and that have not been
+    /// returned for their current expiry date, marking them so that they are not returned again.
+    pub async fn take_expiring(pool: &SqlitePool, days: u32) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as(
+            "UPDATE uploads SET notified_expiring = expiry_date \
+            WHERE expiry_date IS NOT NULL AND expiry_date >= DATE('now') \
+            AND expiry_date <= DATE('now', '+' || $1 || ' days') \
+            AND (notified_expiring IS NULL OR notified_expiring <> expiry_date) \
+            RETURNING *",
+        )
+        .bind(days)
+        .fetch_all(pool)
+        .await
+    }
+
     pub async fn is_owner(
         &self,
         pool: &SqlitePool,
@@ -645,6 +842,203 @@ impl UploadStats {
     }
 }
 
+/// Which folder to list uploads from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FolderFilter {
+    /// Uploads in any folder, or at the top level.
+    All,
+    /// Uploads that are not in a folder.
+    TopLevel,
+    /// Uploads that are directly inside the given folder.
+    Folder(Key<Folder>),
+}
+
+/// The longest tag that can be given to an upload.
+pub const MAX_TAG_LENGTH: usize = 32;
+
+/// Split a comma-separated list of tags, as entered by a user, into the distinct tags.
+///
+/// Tags are trimmed and converted to lowercase, and empty tags are ignored.
+pub fn parse_tags(input: &str) -> Vec<String> {
+    let mut tags = Vec::new();
+    for tag in input.split(',') {
+        let tag = tag.trim().to_lowercase();
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+
+    tags.sort();
+    tags
+}
+
+/// The family of MIME types that an upload belongs to, used to filter the upload list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MimeFamily {
+    Image,
+    Video,
+    Audio,
+    Text,
+    Document,
+    Archive,
+}
+
+impl MimeFamily {
+    pub const ALL: [MimeFamily; 6] = [
+        Self::Image,
+        Self::Video,
+        Self::Audio,
+        Self::Text,
+        Self::Document,
+        Self::Archive,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Image => "image",
+            Self::Video => "video",
+            Self::Audio => "audio",
+            Self::Text => "text",
+            Self::Document => "document",
+            Self::Archive => "archive",
+        }
+    }
+
+    fn get_condition(&self) -> &'static str {
+        match self {
+            Self::Image => "uploads.mime_type LIKE 'image/%'",
+            Self::Video => "uploads.mime_type LIKE 'video/%'",
+            Self::Audio => "uploads.mime_type LIKE 'audio/%'",
+            Self::Text => "uploads.mime_type LIKE 'text/%'",
+            Self::Document => {
+                "uploads.mime_type IN ('application/pdf', 'application/msword', \
+                'application/rtf') \
+                OR uploads.mime_type LIKE 'application/vnd.openxmlformats-officedocument.%' \
+                OR uploads.mime_type LIKE 'application/vnd.oasis.opendocument.%' \
+                OR uploads.mime_type LIKE 'application/vnd.ms-%'"
+            }
+            Self::Archive => {
+                "uploads.mime_type IN ('application/zip', 'application/gzip', \
+                'application/x-gzip', 'application/x-tar', 'application/x-bzip2', \
+                'application/x-xz', 'application/zstd', 'application/x-7z-compressed', \
+                'application/vnd.rar', 'application/x-rar-compressed')"
+            }
+        }
+    }
+}
+
+/// The criteria for filtering the upload list, in addition to the folder.
+#[derive(Debug, Default, Clone)]
+pub struct UploadFilter {
+    /// Only include uploads with a filename that contains this text.
+    pub search: Option<String>,
+    /// Only include uploads with this tag.
+    pub tag: Option<String>,
+    pub kind: Option<MimeFamily>,
+    pub public: Option<bool>,
+    pub expired: Option<bool>,
+    /// Only include uploads that do, or do not, have a password.
+    pub password: Option<bool>,
+    /// The smallest size of upload to include, in bytes.
+    pub min_size: Option<i64>,
+    /// The largest size of upload to include, in bytes.
+    pub max_size: Option<i64>,
+}
+
+impl UploadFilter {
+    /// Get the full-text search query for the search text, which matches uploads that contain
+    /// every word of the search, or words that start with them.
+    ///
+    /// This is `None` when there is no search, or the search has no words, such as a search for
+    /// punctuation, in which case the filename is searched for the text instead.
+    fn get_match(&self) -> Option<String> {
+        let terms = self
+            .search
+            .as_deref()?
+            .split_whitespace()
+            .filter(|term| term.chars().any(char::is_alphanumeric))
+            .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+            .collect::<Vec<_>>();
+
+        if terms.is_empty() {
+            None
+        } else {
+            Some(terms.join(" "))
+        }
+    }
+
+    /// Check whether the filter would include every upload.
+    pub fn is_empty(&self) -> bool {
+        self.search.is_none()
+            && self.tag.is_none()
+            && self.kind.is_none()
+            && self.public.is_none()
+            && self.expired.is_none()
+            && self.password.is_none()
+            && self.min_size.is_none()
+            && self.max_size.is_none()
+    }
+
+    fn push_conditions<'a>(&'a self, query: &mut QueryBuilder<'a, Sqlite>) {
+        if let Some(search) = &self.search {
+            if self.get_match().is_none() {
+                query
+                    .push(" AND uploads.filename LIKE ")
+                    .push_bind(format!("%{search}%"));
+            }
+        }
+
+        if let Some(tag) = &self.tag {
+            query
+                .push(
+                    " AND EXISTS (SELECT 1 FROM upload_tags \
+                    WHERE upload_tags.upload = uploads.id AND upload_tags.tag = ",
+                )
+                .push_bind(tag)
+                .push(")");
+        }
+
+        if let Some(kind) = self.kind {
+            query.push(" AND (").push(kind.get_condition()).push(")");
+        }
+
+        if let Some(public) = self.public {
+            query.push(" AND uploads.public = ").push_bind(public);
+        }
+
+        match self.expired {
+            Some(true) => {
+                query.push(" AND uploads.expiry_date < DATE('now')");
+            }
+            Some(false) => {
+                query.push(
+                    " AND (uploads.expiry_date IS NULL OR uploads.expiry_date >= DATE('now'))",
+                );
+            }
+            None => {}
+        }
+
+        match self.password {
+            Some(true) => {
+                query.push(" AND uploads.password IS NOT NULL");
+            }
+            Some(false) => {
+                query.push(" AND uploads.password IS NULL");
+            }
+            None => {}
+        }
+
+        if let Some(min_size) = self.min_size {
+            query.push(" AND uploads.size >= ").push_bind(min_size);
+        }
+
+        if let Some(max_size) = self.max_size {
+            query.push(" AND uploads.size <= ").push_bind(max_size);
+        }
+    }
+}
+
 #[derive(Debug, FromRow, Serialize)]
 pub struct UploadList {
     pub id: Key<Upload>,
@@ -662,108 +1056,144 @@ pub struct UploadList {
     pub uploaded_by_id: Option<Key<User>>,
     pub uploaded_by_name: Option<String>,
     pub uploaded_at: OffsetDateTime,
+    pub bundle_slug: Option<String>,
+    pub bundle_name: Option<String>,
+    /// The name of the upload request that the upload was received through, if any.
+    pub request_name: Option<String>,
+    /// The tags of the upload, separated by commas.
+    pub tags: Option<String>,
+    /// The part of the text of the upload that matched the search, if any, with the matching
+    /// words between `\u{2}` and `\u{3}` characters.
+    pub snippet: Option<String>,
 }
 
 impl UploadList {
+    const COLUMNS: &'static str = "SELECT uploads.id, uploads.slug, uploads.filename, \
+        uploads.size, uploads.public, uploads.downloads, \
+        uploads.\"limit\", uploads.remaining, uploads.expiry_date, \
+        uploads.custom_slug, \
+        uploads.password is not null as has_password, \
+        COALESCE(teams.slug, users.username) AS owner_slug, \
+        uploads.uploaded_by AS uploaded_by_id, \
+        uploader.name AS uploaded_by_name, \
+        uploads.uploaded_at, \
+        bundles.slug AS bundle_slug, \
+        bundles.name AS bundle_name, \
+        upload_requests.name AS request_name, \
+        (SELECT group_concat(upload_tags.tag, ',') FROM upload_tags \
+            WHERE upload_tags.upload = uploads.id) AS tags";
+
+    const TABLES: &'static str = " FROM uploads \
+        LEFT JOIN teams ON uploads.owner_team = teams.id \
+        LEFT JOIN users ON uploads.owner_user = users.id \
+        LEFT JOIN users AS uploader ON uploads.uploaded_by = uploader.id \
+        LEFT JOIN bundles ON uploads.bundle = bundles.id \
+        LEFT JOIN upload_requests ON uploads.upload_request = upload_requests.id";
+
+    /// Get a page of the uploads of a user.
+    ///
+    /// The uploads are sorted by the given order, or by how well they match the search when the
+    /// order is `None`.
+    #[allow(clippy::too_many_arguments)]
     pub async fn get_for_user(
         pool: &SqlitePool,
         user: Key<User>,
-        search: Option<&str>,
-        order: UploadOrder,
+        folder: FolderFilter,
+        filter: &UploadFilter,
+        order: Option<UploadOrder>,
         asc: bool,
         offset: u32,
         limit: u32,
     ) -> sqlx::Result<Vec<Self>> {
-        let base_query = format!(
-            "SELECT uploads.id, uploads.slug, uploads.filename, \
-                uploads.size, uploads.public, uploads.downloads, \
-                uploads.\"limit\", uploads.remaining, uploads.expiry_date, \
-                uploads.custom_slug, \
-                uploads.password is not null as has_password, \
-                COALESCE(teams.slug, users.username) AS owner_slug, \
-                uploads.uploaded_by AS uploaded_by_id, \
-                uploader.name AS uploaded_by_name, \
-                uploads.uploaded_at \
-                FROM uploads \
-                LEFT JOIN teams ON uploads.owner_team = teams.id \
-                LEFT JOIN users ON uploads.owner_user = users.id \
-                LEFT JOIN users AS uploader ON uploads.uploaded_by = uploader.id \
-                WHERE uploads.owner_user = $1 {} \
-                ORDER BY {} {} LIMIT {} OFFSET {}",
-            if search.is_some() {
-                "AND (uploads.filename LIKE $2)"
-            } else {
-                ""
-            },
-            order.get_order_field(),
-            if asc { "ASC" } else { "DESC" },
-            limit,
-            offset
-        );
-
-        if let Some(search) = search {
-            let search_pattern = format!("%{search}%");
-            sqlx::query_as(&base_query)
-                .bind(user)
-                .bind(search_pattern)
-                .fetch_all(pool)
-                .await
-        } else {
-            sqlx::query_as(&base_query)
-                .bind(user)
-                .fetch_all(pool)
-                .await
-        }
+        let search = filter.get_match();
+        let mut query = Self::new_query(search.as_deref());
+        query.push(" WHERE uploads.owner_user = ").push_bind(user);
+        Self::push_conditions(&mut query, folder, filter, order, asc, offset, limit);
+        query.build_query_as().fetch_all(pool).await
     }
 
+    /// Get a page of the uploads of a team.
+    ///
+    /// The uploads are sorted by the given order, or by how well they match the search when the
+    /// order is `None`.
+    #[allow(clippy::too_many_arguments)]
     pub async fn get_for_team(
         pool: &SqlitePool,
         team: Key<Team>,
-        search: Option<&str>,
-        order: UploadOrder,
+        folder: FolderFilter,
+        filter: &UploadFilter,
+        order: Option<UploadOrder>,
         asc: bool,
         offset: u32,
         limit: u32,
     ) -> sqlx::Result<Vec<Self>> {
-        let base_query = format!(
-            "SELECT uploads.id, uploads.slug, uploads.filename, \
-                uploads.size, uploads.public, uploads.downloads, \
-                uploads.\"limit\", uploads.remaining, uploads.expiry_date, \
-                uploads.custom_slug, \
-                uploads.password is not null as has_password, \
-                COALESCE(teams.slug, users.username) AS owner_slug, \
-                uploads.uploaded_by AS uploaded_by_id, \
-                uploader.name AS uploaded_by_name, \
-                uploads.uploaded_at \
-                FROM uploads \
-                LEFT JOIN teams ON uploads.owner_team = teams.id \
-                LEFT JOIN users ON uploads.owner_user = users.id \
-                LEFT JOIN users AS uploader ON uploads.uploaded_by = uploader.id \
-                WHERE uploads.owner_team = $1 {} \
-                ORDER BY {} {} LIMIT {} OFFSET {}",
-            if search.is_some() {
-                "AND (uploads.filename LIKE $2)"
-            } else {
-                ""
-            },
-            order.get_order_field(),
-            if asc { "ASC" } else { "DESC" },
-            limit,
-            offset
-        );
+        let search = filter.get_match();
+        let mut query = Self::new_query(search.as_deref());
+        query.push(" WHERE uploads.owner_team = ").push_bind(team);
+        Self::push_conditions(&mut query, folder, filter, order, asc, offset, limit);
+        query.build_query_as().fetch_all(pool).await
+    }
+
+    /// Start the query for the upload list, joining the search index when there is a search.
+    fn new_query(search: Option<&str>) -> QueryBuilder<'_, Sqlite> {
+        let mut query = QueryBuilder::new(Self::COLUMNS);
 
         if let Some(search) = search {
-            let search_pattern = format!("%{search}%");
-            sqlx::query_as(&base_query)
-                .bind(team)
-                .bind(search_pattern)
-                .fetch_all(pool)
-                .await
+            query
+                .push(", snippet(upload_search, 4, char(2), char(3), '…', 16) AS snippet")
+                .push(Self::TABLES)
+                .push(" JOIN upload_search ON upload_search.upload = uploads.id")
+                .push(" AND upload_search MATCH ")
+                .push_bind(search);
         } else {
-            sqlx::query_as(&base_query)
-                .bind(team)
-                .fetch_all(pool)
-                .await
+            query.push(", NULL AS snippet").push(Self::TABLES);
         }
+
+        query
+    }
+
+    fn push_conditions<'a>(
+        query: &mut QueryBuilder<'a, Sqlite>,
+        folder: FolderFilter,
+        filter: &'a UploadFilter,
+        order: Option<UploadOrder>,
+        asc: bool,
+        offset: u32,
+        limit: u32,
+    ) {
+        query.push(" AND uploads.hidden_at IS NULL");
+
+        match folder {
+            FolderFilter::All => {}
+            FolderFilter::TopLevel => {
+                query.push(" AND uploads.folder IS NULL");
+            }
+            FolderFilter::Folder(folder) => {
+                query.push(" AND uploads.folder = ").push_bind(folder);
+            }
+        }
+
+        filter.push_conditions(query);
+
+        match order {
+            Some(order) => {
+                query
+                    .push(" ORDER BY uploads.")
+                    .push(order.get_order_field())
+                    .push(if asc { " ASC" } else { " DESC" });
+            }
+            None if filter.get_match().is_some() => {
+                query.push(" ORDER BY upload_search.rank");
+            }
+            None => {
+                query.push(" ORDER BY uploads.uploaded_at DESC");
+            }
+        }
+
+        query
+            .push(" LIMIT ")
+            .push_bind(limit as i64)
+            .push(" OFFSET ")
+            .push_bind(offset as i64);
     }
 }
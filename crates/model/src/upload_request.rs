@@ -0,0 +1,324 @@
+use anyhow::Context;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+use time::{Date, OffsetDateTime};
+
+use crate::{password::StoredPassword, team::Team, types::Key, upload::Upload, user::User};
+
+/// The number of random bytes in the token of an upload request.
+const TOKEN_BYTES: usize = 16;
+
+/// Whether files can still be uploaded through an upload request.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadRequestStatus {
+    Active,
+    /// The request was revoked by its owner.
+    Revoked,
+    /// The expiry date of the request has passed.
+    Expired,
+    /// The maximum number of files have been uploaded through the request.
+    Exhausted,
+}
+
+/// A link that lets anyone upload files to a user or a team, without signing in.
+///
+/// Each request has its own limits on the number, size and type of the files that can be uploaded
+/// through it, along with an optional password and expiry date.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct UploadRequest {
+    pub id: Key<UploadRequest>,
+    pub token: String,
+    pub name: String,
+    pub owner_user: Option<Key<User>>,
+    pub owner_team: Option<Key<Team>>,
+    #[serde(skip)]
+    pub password: Option<StoredPassword>,
+    /// The maximum number of files that can be uploaded through the request.
+    pub max_files: Option<i64>,
+    /// The maximum size of each file, in bytes.
+    pub max_size: Option<i64>,
+    /// The MIME types that can be uploaded, separated by commas. A type can end with `/*` to allow
+    /// any subtype, such as `image/*`.
+    pub mime_types: Option<String>,
+    pub expiry_date: Option<Date>,
+    /// The number of files that have been uploaded through the request.
+    pub files: i64,
+    pub created_by: Option<Key<User>>,
+    pub created_at: OffsetDateTime,
+    pub revoked_at: Option<OffsetDateTime>,
+}
+
+/// Parse a list of MIME types separated by commas, such as `image/*, application/pdf`.
+///
+/// The types are trimmed and converted to lowercase, and any empty or duplicate types are removed.
+pub fn parse_mime_types(input: &str) -> Vec<String> {
+    let mut types = Vec::new();
+
+    for mime_type in input.split(',') {
+        let mime_type = mime_type.trim().to_lowercase();
+        if !mime_type.is_empty() && !types.contains(&mime_type) {
+            types.push(mime_type);
+        }
+    }
+
+    types
+}
+
+impl UploadRequest {
+    /// Create a new upload request without any limits, owned by either a user or a team.
+    pub fn new(
+        name: &str,
+        owner_user: Option<Key<User>>,
+        owner_team: Option<Key<Team>>,
+        created_by: Key<User>,
+    ) -> Self {
+        let mut bytes = [0u8; TOKEN_BYTES];
+        OsRng.fill_bytes(&mut bytes);
+
+        let mut token = String::with_capacity(TOKEN_BYTES * 2);
+        for byte in bytes {
+            token.push_str(&format!("{byte:02x}"));
+        }
+
+        Self {
+            id: Key::new(),
+            token,
+            name: name.to_string(),
+            owner_user,
+            owner_team,
+            password: None,
+            max_files: None,
+            max_size: None,
+            mime_types: None,
+            expiry_date: None,
+            files: 0,
+            created_by: Some(created_by),
+            created_at: OffsetDateTime::now_utc(),
+            revoked_at: None,
+        }
+    }
+
+    pub async fn create(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        sqlx::query(
+            "INSERT INTO upload_requests (id, token, name, owner_user, owner_team, password,
+            max_files, max_size, mime_types, expiry_date, files, created_by, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)",
+        )
+        .bind(self.id)
+        .bind(&self.token)
+        .bind(&self.name)
+        .bind(self.owner_user)
+        .bind(self.owner_team)
+        .bind(&self.password)
+        .bind(self.max_files)
+        .bind(self.max_size)
+        .bind(&self.mime_types)
+        .bind(self.expiry_date)
+        .bind(self.files)
+        .bind(self.created_by)
+        .bind(self.created_at)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get(pool: &SqlitePool, id: Key<UploadRequest>) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as("SELECT * FROM upload_requests WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    pub async fn get_by_token(pool: &SqlitePool, token: &str) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as("SELECT * FROM upload_requests WHERE token = $1")
+            .bind(token)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Get all the upload requests of a user, including those that have been revoked.
+    pub async fn get_for_user(pool: &SqlitePool, user: Key<User>) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as("SELECT * FROM upload_requests WHERE owner_user = $1 ORDER BY created_at")
+            .bind(user)
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Get all the upload requests of a team, including those that have been revoked.
+    pub async fn get_for_team(pool: &SqlitePool, team: Key<Team>) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as("SELECT * FROM upload_requests WHERE owner_team = $1 ORDER BY created_at")
+            .bind(team)
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn set_password(&mut self, pool: &SqlitePool, password: &str) -> anyhow::Result<()> {
+        let password = StoredPassword::new(password).context("failed to hash password")?;
+
+        let result = sqlx::query("UPDATE upload_requests SET password = $1 WHERE id = $2")
+            .bind(&password)
+            .bind(self.id)
+            .execute(pool)
+            .await
+            .context("failed to update upload request password")?;
+
+        if result.rows_affected() == 0 {
+            anyhow::bail!("Upload request not found");
+        }
+
+        self.password = Some(password);
+        Ok(())
+    }
+
+    /// Revoke the request, so that no more files can be uploaded through it.
+    pub async fn revoke(&mut self, pool: &SqlitePool) -> sqlx::Result<()> {
+        let now = OffsetDateTime::now_utc();
+        let result = sqlx::query(
+            "UPDATE upload_requests SET revoked_at = $1 WHERE id = $2 AND revoked_at IS NULL",
+        )
+        .bind(now)
+        .bind(self.id)
+        .execute(pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound);
+        }
+
+        self.revoked_at = Some(now);
+        Ok(())
+    }
+
+    /// Record that the given uploads were received through this request.
+    ///
+    /// The uploads are marked with the request, and counted towards its maximum number of files.
+    /// The maximum is checked in the same statement that counts the uploads, so that files that are
+    /// uploaded at the same time cannot exceed it between them. Returns `false` without recording
+    /// anything if there are too many files.
+    pub async fn record_uploads(
+        &mut self,
+        pool: &SqlitePool,
+        uploads: &[Key<Upload>],
+    ) -> sqlx::Result<bool> {
+        let mut tx = pool.begin().await?;
+
+        let Some(files) = sqlx::query_scalar(
+            "UPDATE upload_requests SET files = files + $1 \
+            WHERE id = $2 AND (max_files IS NULL OR files + $1 <= max_files) \
+            RETURNING files",
+        )
+        .bind(uploads.len() as i64)
+        .bind(self.id)
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(false);
+        };
+
+        for upload in uploads {
+            sqlx::query("UPDATE uploads SET upload_request = $1 WHERE id = $2")
+                .bind(self.id)
+                .bind(upload)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        self.files = files;
+        Ok(true)
+    }
+
+    /// Get the number of files that can still be uploaded through the request, if it has a limit.
+    pub fn remaining_files(&self) -> Option<i64> {
+        self.max_files
+            .map(|max_files| (max_files - self.files).max(0))
+    }
+
+    /// Check whether a file with the given MIME type can be uploaded through the request.
+    pub fn allows_mime_type(&self, mime_type: &str) -> bool {
+        let Some(ref mime_types) = self.mime_types else {
+            return true;
+        };
+
+        let mime_type = mime_type.to_lowercase();
+        parse_mime_types(mime_types)
+            .iter()
+            .any(|allowed| match allowed.strip_suffix("/*") {
+                Some(prefix) => mime_type
+                    .split_once('/')
+                    .is_some_and(|(kind, _)| kind == prefix),
+                None => *allowed == mime_type,
+            })
+    }
+
+    /// Check whether files can still be uploaded through the request, and if not, why not.
+    pub fn status(&self) -> UploadRequestStatus {
+        if self.revoked_at.is_some() {
+            UploadRequestStatus::Revoked
+        } else if self
+            .expiry_date
+            .is_some_and(|expiry| expiry < OffsetDateTime::now_utc().date())
+        {
+            UploadRequestStatus::Expired
+        } else if self
+            .remaining_files()
+            .is_some_and(|remaining| remaining < 1)
+        {
+            UploadRequestStatus::Exhausted
+        } else {
+            UploadRequestStatus::Active
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+    use crate::migration::MIGRATOR;
+
+    #[tokio::test]
+    async fn test_record_uploads() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .expect("connect to database");
+        MIGRATOR.run(&pool).await.expect("run migrations");
+
+        let user = Key::<User>::new();
+        sqlx::query(
+            "INSERT INTO users (id, username, name, password, enabled, admin, created_at) \
+            VALUES ($1, 'user', 'User', '', TRUE, FALSE, $2)",
+        )
+        .bind(user)
+        .bind(OffsetDateTime::now_utc())
+        .execute(&pool)
+        .await
+        .expect("create user");
+
+        let mut request = UploadRequest::new("Test", Some(user), None, user);
+        request.max_files = Some(3);
+        request.create(&pool).await.expect("create request");
+
+        // Another upload took some of the files since this copy of the request was loaded.
+        let mut other = UploadRequest::get(&pool, request.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(other.record_uploads(&pool, &[Key::new()]).await.unwrap());
+        assert_eq!(request.remaining_files(), Some(3));
+
+        let uploads = [Key::new(), Key::new(), Key::new()];
+        assert!(!request.record_uploads(&pool, &uploads).await.unwrap());
+        assert_eq!(request.files, 0);
+
+        assert!(request.record_uploads(&pool, &uploads[..2]).await.unwrap());
+        assert_eq!(request.files, 3);
+        assert_eq!(request.remaining_files(), Some(0));
+    }
+}
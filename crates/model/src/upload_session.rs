@@ -0,0 +1,197 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+use time::OffsetDateTime;
+
+use crate::{team::Team, types::Key, user::User};
+
+/// A resumable upload that has been started but not yet completed.
+///
+/// The bytes that have been received so far are kept in a file in the temporary directory of the
+/// cache. Once all the bytes have been received, the file is moved into the cache and an upload is
+/// created in its place.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct UploadSession {
+    pub id: Key<UploadSession>,
+    pub filename: String,
+    pub length: i64,
+    pub offset: i64,
+    pub owner_user: Option<Key<User>>,
+    pub owner_team: Option<Key<Team>>,
+    pub uploaded_by: Key<User>,
+    pub remote_addr: Option<String>,
+    pub created_at: OffsetDateTime,
+    pub expires_at: OffsetDateTime,
+}
+
+impl UploadSession {
+    pub fn new(
+        user: &User,
+        team: Option<&Team>,
+        filename: String,
+        length: i64,
+        remote_addr: Option<String>,
+        expiry: Duration,
+    ) -> Self {
+        let now = OffsetDateTime::now_utc();
+
+        Self {
+            id: Key::new(),
+            filename,
+            length,
+            offset: 0,
+            owner_user: if team.is_some() { None } else { Some(user.id) },
+            owner_team: team.map(|team| team.id),
+            uploaded_by: user.id,
+            remote_addr,
+            created_at: now,
+            expires_at: now + expiry,
+        }
+    }
+
+    /// Create the session, reserving its length from the quota of its owner.
+    ///
+    /// When a `limit` is given, the session is only created if its length fits in the space that
+    /// the owner has left. This is checked in the same statement that creates the session, so that
+    /// concurrent uploads cannot exceed the limit between them. Returns `false` if the session
+    /// would exceed the limit.
+    pub async fn create(&self, pool: &SqlitePool, limit: Option<i64>) -> sqlx::Result<bool> {
+        let result = sqlx::query(
+            "INSERT INTO upload_sessions \
+            (id, filename, length, \"offset\", owner_user, owner_team, \
+             uploaded_by, remote_addr, created_at, expires_at) \
+            SELECT $1, $2, $3, $4, $5, $6, $7, $8, $9, $10 \
+            WHERE $11 IS NULL OR $11 >= $3 \
+                + (SELECT COALESCE(SUM(size), 0) FROM uploads \
+                   WHERE owner_user IS $5 AND owner_team IS $6) \
+                + (SELECT COALESCE(SUM(length), 0) FROM upload_sessions \
+                   WHERE owner_user IS $5 AND owner_team IS $6 AND expires_at >= $9)",
+        )
+        .bind(self.id)
+        .bind(&self.filename)
+        .bind(self.length)
+        .bind(self.offset)
+        .bind(self.owner_user)
+        .bind(self.owner_team)
+        .bind(self.uploaded_by)
+        .bind(&self.remote_addr)
+        .bind(self.created_at)
+        .bind(self.expires_at)
+        .bind(limit)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get(pool: &SqlitePool, id: Key<UploadSession>) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as("SELECT * FROM upload_sessions WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at < OffsetDateTime::now_utc()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.offset >= self.length
+    }
+
+    /// Record that more bytes have been received, extending the expiry of the session.
+    ///
+    /// The offset is only changed if it has not been changed by another request since this session
+    /// was loaded. Returns `false` if the offset had already been changed.
+    pub async fn set_offset(
+        &mut self,
+        pool: &SqlitePool,
+        offset: i64,
+        expiry: Duration,
+    ) -> sqlx::Result<bool> {
+        let expires_at = OffsetDateTime::now_utc() + expiry;
+        let result = sqlx::query(
+            "UPDATE upload_sessions SET \"offset\" = $1, expires_at = $2 \
+            WHERE id = $3 AND \"offset\" = $4",
+        )
+        .bind(offset)
+        .bind(expires_at)
+        .bind(self.id)
+        .bind(self.offset)
+        .execute(pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        self.offset = offset;
+        self.expires_at = expires_at;
+        Ok(true)
+    }
+
+    pub async fn delete(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM upload_sessions WHERE id = $1")
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Delete all the sessions that have expired, returning the IDs of the deleted sessions.
+    pub async fn delete_expired(pool: &SqlitePool) -> sqlx::Result<Vec<Key<UploadSession>>> {
+        sqlx::query_scalar("DELETE FROM upload_sessions WHERE expires_at < $1 RETURNING id")
+            .bind(OffsetDateTime::now_utc())
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Delete all the sessions that are owned by or were started by the given user.
+    pub async fn delete_for_user(
+        pool: &SqlitePool,
+        user: Key<User>,
+    ) -> sqlx::Result<Vec<Key<UploadSession>>> {
+        sqlx::query_scalar(
+            "DELETE FROM upload_sessions WHERE owner_user = $1 OR uploaded_by = $1 RETURNING id",
+        )
+        .bind(user)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn delete_for_team(
+        pool: &SqlitePool,
+        team: Key<Team>,
+    ) -> sqlx::Result<Vec<Key<UploadSession>>> {
+        sqlx::query_scalar("DELETE FROM upload_sessions WHERE owner_team = $1 RETURNING id")
+            .bind(team)
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Get the number of bytes that are reserved by the unfinished sessions of the given user.
+    pub async fn reserved_for_user(pool: &SqlitePool, owner: Key<User>) -> sqlx::Result<i64> {
+        sqlx::query_scalar(
+            "SELECT COALESCE(SUM(length), 0) FROM upload_sessions \
+            WHERE owner_user = $1 AND expires_at >= $2",
+        )
+        .bind(owner)
+        .bind(OffsetDateTime::now_utc())
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Get the number of bytes that are reserved by the unfinished sessions of the given team.
+    pub async fn reserved_for_team(pool: &SqlitePool, owner: Key<Team>) -> sqlx::Result<i64> {
+        sqlx::query_scalar(
+            "SELECT COALESCE(SUM(length), 0) FROM upload_sessions \
+            WHERE owner_team = $1 AND expires_at >= $2",
+        )
+        .bind(owner)
+        .bind(OffsetDateTime::now_utc())
+        .fetch_one(pool)
+        .await
+    }
+}
@@ -16,6 +16,8 @@ pub struct User {
     pub password: StoredPassword,
     #[serde(skip)]
     pub totp: Option<String>,
+    /// Whether the user must set up two-factor authentication again before they can continue.
+    pub totp_required: bool,
     pub enabled: bool,
     pub admin: bool,
     pub limit: Option<i64>,
@@ -24,6 +26,26 @@ pub struct User {
     pub last_access: Option<OffsetDateTime>,
     pub default_order: UploadOrder,
     pub default_asc: bool,
+    /// The address that notifications are sent to, if the user has given one.
+    pub email: Option<String>,
+    /// Whether the email address is known to belong to the user, rather than being one that they
+    /// entered themselves.
+    pub email_verified: bool,
+    pub notify_downloads: bool,
+    pub notify_expiring: bool,
+    pub notify_requests: bool,
+    /// Whether an administrator is told when an account is locked out.
+    pub notify_lockouts: bool,
+    /// The subject of the account at the OpenID Connect provider that is linked to this user.
+    #[serde(skip)]
+    pub oidc_subject: Option<String>,
+    /// Whether the user existed before they were linked to their account at the OpenID Connect
+    /// provider, rather than being created when they first signed in with it.
+    #[serde(skip)]
+    pub oidc_linked: bool,
+    /// The distinguished name of the entry in the LDAP directory that is linked to this user.
+    #[serde(skip)]
+    pub ldap_dn: Option<String>,
 }
 
 pub async fn requires_setup(pool: &SqlitePool) -> sqlx::Result<bool> {
@@ -40,8 +62,8 @@ impl User {
             "INSERT INTO users \
             (id, username, name, password, enabled, admin, \
              \"limit\", created_at, created_by, \
-             default_order, default_asc) \
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) \
+             default_order, default_asc, email, email_verified, oidc_subject, ldap_dn) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15) \
             RETURNING id",
         )
         .bind(self.id)
@@ -55,6 +77,10 @@ impl User {
         .bind(self.created_by)
         .bind(self.default_order)
         .bind(self.default_asc)
+        .bind(&self.email)
+        .bind(self.email_verified)
+        .bind(&self.oidc_subject)
+        .bind(&self.ldap_dn)
         .execute(pool)
         .await?;
 
@@ -101,6 +127,52 @@ impl User {
         Ok(())
     }
 
+    /// Set the email address and notification settings of the user.
+    ///
+    /// Changing the email address means that it is no longer known to belong to the user, as they
+    /// can enter any address here.
+    pub async fn set_notifications(
+        &mut self,
+        pool: &SqlitePool,
+        email: Option<&str>,
+        notify_downloads: bool,
+        notify_expiring: bool,
+        notify_requests: bool,
+        notify_lockouts: bool,
+    ) -> sqlx::Result<()> {
+        let email_verified = self.email_verified
+            && matches!((self.email.as_deref(), email), (Some(old), Some(new))
+                if old.eq_ignore_ascii_case(new));
+
+        sqlx::query(
+            "UPDATE users SET \
+                email = $1, \
+                email_verified = $2, \
+                notify_downloads = $3, \
+                notify_expiring = $4, \
+                notify_requests = $5, \
+                notify_lockouts = $6 \
+            WHERE id = $7",
+        )
+        .bind(email)
+        .bind(email_verified)
+        .bind(notify_downloads)
+        .bind(notify_expiring)
+        .bind(notify_requests)
+        .bind(notify_lockouts)
+        .bind(self.id)
+        .execute(pool)
+        .await?;
+
+        self.email = email.map(ToString::to_string);
+        self.email_verified = email_verified;
+        self.notify_downloads = notify_downloads;
+        self.notify_expiring = notify_expiring;
+        self.notify_requests = notify_requests;
+        self.notify_lockouts = notify_lockouts;
+        Ok(())
+    }
+
     pub async fn set_password(&mut self, pool: &SqlitePool, password: &str) -> anyhow::Result<()> {
         let password = StoredPassword::new(password).context("failed to hash password")?;
 
@@ -161,6 +233,68 @@ impl User {
         Ok(())
     }
 
+    pub async fn set_admin(&mut self, pool: &SqlitePool, admin: bool) -> sqlx::Result<()> {
+        sqlx::query("UPDATE users SET admin = $1 WHERE id = $2")
+            .bind(admin)
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        self.admin = admin;
+        Ok(())
+    }
+
+    pub async fn set_email(
+        &mut self,
+        pool: &SqlitePool,
+        email: &str,
+        verified: bool,
+    ) -> sqlx::Result<()> {
+        sqlx::query("UPDATE users SET email = $1, email_verified = $2 WHERE id = $3")
+            .bind(email)
+            .bind(verified)
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        self.email = Some(email.to_string());
+        self.email_verified = verified;
+        Ok(())
+    }
+
+    /// Link an existing user to an account at the OpenID Connect provider.
+    pub async fn set_oidc_subject(&mut self, pool: &SqlitePool, subject: &str) -> sqlx::Result<()> {
+        sqlx::query("UPDATE users SET oidc_subject = $1, oidc_linked = TRUE WHERE id = $2")
+            .bind(subject)
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        self.oidc_subject = Some(subject.to_string());
+        self.oidc_linked = true;
+        Ok(())
+    }
+
+    /// Link the user to an entry in the LDAP directory, replacing their password, as they now sign
+    /// in with the password that they have in the directory.
+    pub async fn set_ldap_dn(
+        &mut self,
+        pool: &SqlitePool,
+        dn: &str,
+        password: StoredPassword,
+    ) -> sqlx::Result<()> {
+        sqlx::query("UPDATE users SET ldap_dn = $1, password = $2 WHERE id = $3")
+            .bind(dn)
+            .bind(&password)
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        self.ldap_dn = Some(dn.to_string());
+        self.password = password;
+        Ok(())
+    }
+
     pub async fn record_last_access(&mut self, pool: &SqlitePool) -> sqlx::Result<()> {
         let now = OffsetDateTime::now_utc();
         sqlx::query("UPDATE users SET last_access = $1 WHERE id = $2")
@@ -187,7 +321,71 @@ impl User {
             .await
     }
 
+    /// Get the user that is linked to an account at the OpenID Connect provider.
+    pub async fn get_by_oidc_subject(
+        pool: &SqlitePool,
+        subject: &str,
+    ) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as("SELECT * FROM users WHERE oidc_subject = ?")
+            .bind(subject)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Get the user that is linked to an entry in the LDAP directory.
+    pub async fn get_by_ldap_dn(pool: &SqlitePool, dn: &str) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as("SELECT * FROM users WHERE ldap_dn = ? COLLATE NOCASE")
+            .bind(dn)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Get the users that are linked to entries in the LDAP directory.
+    pub async fn get_ldap_users(pool: &SqlitePool) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as("SELECT * FROM users WHERE ldap_dn IS NOT NULL")
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Get the users with an email address, ignoring case.
+    pub async fn get_by_email(pool: &SqlitePool, email: &str) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as("SELECT * FROM users WHERE email = ? COLLATE NOCASE")
+            .bind(email)
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Get the enabled administrators that want to be told when an account is locked out.
+    pub async fn get_lockout_recipients(pool: &SqlitePool) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as(
+            "SELECT * FROM users \
+            WHERE admin AND enabled AND notify_lockouts AND email IS NOT NULL",
+        )
+        .fetch_all(pool)
+        .await
+    }
+
     pub async fn delete(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM api_tokens WHERE user = $1")
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        sqlx::query("DELETE FROM webauthn_credentials WHERE user = $1")
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        sqlx::query("DELETE FROM totp_recovery_codes WHERE user = $1")
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        sqlx::query("DELETE FROM user_tokens WHERE user = $1")
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
         sqlx::query("DELETE FROM team_members WHERE user = $1")
             .bind(self.id)
             .execute(pool)
@@ -206,23 +404,46 @@ impl User {
     }
 
     pub async fn set_totp_secret(&mut self, pool: &SqlitePool, secret: &str) -> sqlx::Result<()> {
-        sqlx::query("UPDATE users SET totp = $1 WHERE id = $2")
+        sqlx::query("UPDATE users SET totp = $1, totp_required = FALSE WHERE id = $2")
             .bind(secret)
             .bind(self.id)
             .execute(pool)
             .await?;
 
         self.totp = Some(secret.to_string());
+        self.totp_required = false;
         Ok(())
     }
 
+    /// Remove the TOTP secret of the user, along with their recovery codes.
     pub async fn remove_totp_secret(&mut self, pool: &SqlitePool) -> sqlx::Result<()> {
-        sqlx::query("UPDATE users SET totp = NULL WHERE id = $1")
+        self.clear_totp(pool, false).await
+    }
+
+    /// Remove the TOTP secret and recovery codes of the user, and make them set up two-factor
+    /// authentication again the next time that they sign in.
+    pub async fn reset_totp(&mut self, pool: &SqlitePool) -> sqlx::Result<()> {
+        self.clear_totp(pool, true).await
+    }
+
+    async fn clear_totp(&mut self, pool: &SqlitePool, required: bool) -> sqlx::Result<()> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query("UPDATE users SET totp = NULL, totp_required = $1 WHERE id = $2")
+            .bind(required)
             .bind(self.id)
-            .execute(pool)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM totp_recovery_codes WHERE user = $1")
+            .bind(self.id)
+            .execute(&mut *tx)
             .await?;
 
+        tx.commit().await?;
+
         self.totp = None;
+        self.totp_required = required;
         Ok(())
     }
 
@@ -416,3 +637,71 @@ impl UserList {
         .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+    use crate::migration::MIGRATOR;
+
+    #[tokio::test]
+    async fn test_set_notifications_unverifies_email() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .expect("connect to database");
+        MIGRATOR.run(&pool).await.expect("run migrations");
+
+        let mut user = User {
+            id: Key::new(),
+            username: "user".to_string(),
+            name: "User".to_string(),
+            password: StoredPassword::new("password").expect("hash password"),
+            totp: None,
+            totp_required: false,
+            enabled: true,
+            admin: false,
+            limit: None,
+            created_at: OffsetDateTime::now_utc(),
+            created_by: None,
+            last_access: None,
+            default_order: UploadOrder::UploadedAt,
+            default_asc: false,
+            email: None,
+            email_verified: false,
+            notify_downloads: true,
+            notify_expiring: true,
+            notify_requests: true,
+            notify_lockouts: true,
+            oidc_subject: None,
+            oidc_linked: false,
+            ldap_dn: None,
+        };
+        user.create(&pool).await.expect("create user");
+
+        user.set_email(&pool, "user@example.com", true)
+            .await
+            .expect("set email");
+
+        // Keeping the same address, whatever its case, leaves it verified.
+        user.set_notifications(&pool, Some("User@Example.com"), true, true, true, true)
+            .await
+            .expect("set notifications");
+        assert!(user.email_verified);
+
+        // Entering another address means that it is no longer known to belong to the user.
+        user.set_notifications(&pool, Some("admin@example.com"), true, true, true, true)
+            .await
+            .expect("set notifications");
+        assert!(!user.email_verified);
+
+        let user = User::get(&pool, user.id)
+            .await
+            .expect("get user")
+            .expect("user exists");
+        assert_eq!(user.email.as_deref(), Some("admin@example.com"));
+        assert!(!user.email_verified);
+    }
+}
@@ -0,0 +1,247 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, SqliteConnection, SqlitePool};
+use time::{Duration, OffsetDateTime};
+
+use crate::{types::Key, user::User};
+
+/// The number of random bytes in a token.
+const TOKEN_BYTES: usize = 32;
+
+/// What a user token lets the user do.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum UserTokenKind {
+    /// Choose the password of a new account, and then set up two-factor authentication.
+    Invitation,
+    /// Choose a new password for an account, after the old one was forgotten.
+    PasswordReset,
+}
+
+impl UserTokenKind {
+    /// How long a token of this kind can be used for after it was created.
+    pub fn lifetime(&self) -> Duration {
+        match self {
+            Self::Invitation => Duration::days(7),
+            Self::PasswordReset => Duration::hours(1),
+        }
+    }
+}
+
+/// A single-use token that is sent to a user in a link, such as an invitation or a password reset.
+#[derive(Debug, FromRow, Serialize)]
+pub struct UserToken {
+    pub id: Key<UserToken>,
+    pub user: Key<User>,
+    pub kind: UserTokenKind,
+    #[serde(skip)]
+    pub token_hash: String,
+    pub created_by: Option<Key<User>>,
+    pub created_at: OffsetDateTime,
+    pub expires_at: OffsetDateTime,
+    pub used_at: Option<OffsetDateTime>,
+}
+
+/// Hash a token for storage or lookup.
+///
+/// Tokens are long and random, so unlike passwords a single fast hash is sufficient.
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+impl UserToken {
+    /// Create a new token for the given user, returning the token record and the token itself.
+    ///
+    /// The token itself is not stored, so it can only be sent to the user once.
+    pub fn new(
+        user: Key<User>,
+        kind: UserTokenKind,
+        created_by: Option<Key<User>>,
+    ) -> (Self, String) {
+        let mut bytes = [0u8; TOKEN_BYTES];
+        OsRng.fill_bytes(&mut bytes);
+
+        let mut token = String::with_capacity(TOKEN_BYTES * 2);
+        for byte in bytes {
+            token.push_str(&format!("{byte:02x}"));
+        }
+
+        let now = OffsetDateTime::now_utc();
+        let user_token = Self {
+            id: Key::new(),
+            user,
+            kind,
+            token_hash: hash_token(&token),
+            created_by,
+            created_at: now,
+            expires_at: now + kind.lifetime(),
+            used_at: None,
+        };
+
+        (user_token, token)
+    }
+
+    /// Store the token, replacing any unused tokens of the same kind that the user already has, so
+    /// that only the most recent link works.
+    pub async fn create(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        let mut tx = pool.begin().await?;
+        self.replace(&mut tx).await?;
+        tx.commit().await
+    }
+
+    /// Store the token like [`create`](Self::create), unless the user already has an unused token
+    /// of the same kind that is still valid and was created after `since`. Returns `false` if the
+    /// token was not stored.
+    ///
+    /// This keeps a link that was sent recently from being replaced by somebody else asking for
+    /// another one, and from the user being sent a flood of links.
+    pub async fn create_unless_recent(
+        &self,
+        pool: &SqlitePool,
+        since: OffsetDateTime,
+    ) -> sqlx::Result<bool> {
+        let mut tx = pool.begin().await?;
+
+        let recent: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM user_tokens \
+            WHERE user = $1 AND kind = $2 AND used_at IS NULL AND created_at > $3 \
+            AND expires_at > $4)",
+        )
+        .bind(self.user)
+        .bind(self.kind)
+        .bind(since)
+        .bind(OffsetDateTime::now_utc())
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if recent {
+            return Ok(false);
+        }
+
+        self.replace(&mut tx).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Delete the unused tokens of the same kind that the user has, and insert this one.
+    async fn replace(&self, conn: &mut SqliteConnection) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM user_tokens WHERE user = $1 AND kind = $2 AND used_at IS NULL")
+            .bind(self.user)
+            .bind(self.kind)
+            .execute(&mut *conn)
+            .await?;
+
+        sqlx::query(
+            "INSERT INTO user_tokens \
+            (id, user, kind, token_hash, created_by, created_at, expires_at) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(self.id)
+        .bind(self.user)
+        .bind(self.kind)
+        .bind(&self.token_hash)
+        .bind(self.created_by)
+        .bind(self.created_at)
+        .bind(self.expires_at)
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Find the token of the given kind for a token sent in a link, if it has not been used and
+    /// has not expired.
+    pub async fn get_valid(
+        pool: &SqlitePool,
+        kind: UserTokenKind,
+        token: &str,
+    ) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as(
+            "SELECT * FROM user_tokens \
+            WHERE token_hash = $1 AND kind = $2 AND used_at IS NULL AND expires_at > $3",
+        )
+        .bind(hash_token(token))
+        .bind(kind)
+        .bind(OffsetDateTime::now_utc())
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Mark the token as used, returning `false` if it had already been used.
+    pub async fn mark_used(&mut self, pool: &SqlitePool) -> sqlx::Result<bool> {
+        let now = OffsetDateTime::now_utc();
+        let result =
+            sqlx::query("UPDATE user_tokens SET used_at = $1 WHERE id = $2 AND used_at IS NULL")
+                .bind(now)
+                .bind(self.id)
+                .execute(pool)
+                .await?;
+
+        self.used_at = Some(now);
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+    use crate::migration::MIGRATOR;
+
+    #[tokio::test]
+    async fn test_create_unless_recent() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .expect("connect to database");
+        MIGRATOR.run(&pool).await.expect("run migrations");
+
+        let user = Key::<User>::new();
+        sqlx::query(
+            "INSERT INTO users (id, username, name, password, enabled, admin, created_at) \
+            VALUES ($1, 'user', 'User', '', TRUE, FALSE, $2)",
+        )
+        .bind(user)
+        .bind(OffsetDateTime::now_utc())
+        .execute(&pool)
+        .await
+        .expect("create user");
+
+        let since = OffsetDateTime::now_utc() - Duration::minutes(10);
+        let (first, first_token) = UserToken::new(user, UserTokenKind::PasswordReset, None);
+        assert!(first.create_unless_recent(&pool, since).await.unwrap());
+
+        // The link that was just sent keeps working, rather than being replaced.
+        let (second, _) = UserToken::new(user, UserTokenKind::PasswordReset, None);
+        assert!(!second.create_unless_recent(&pool, since).await.unwrap());
+        let valid = UserToken::get_valid(&pool, UserTokenKind::PasswordReset, &first_token)
+            .await
+            .unwrap();
+        assert_eq!(valid.map(|token| token.id), Some(first.id));
+
+        // Once the link is older than the interval, a new one replaces it.
+        let later = OffsetDateTime::now_utc() + Duration::seconds(1);
+        let (third, third_token) = UserToken::new(user, UserTokenKind::PasswordReset, None);
+        assert!(third.create_unless_recent(&pool, later).await.unwrap());
+        assert!(
+            UserToken::get_valid(&pool, UserTokenKind::PasswordReset, &first_token)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            UserToken::get_valid(&pool, UserTokenKind::PasswordReset, &third_token)
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        // Invitations are not affected by the password reset link.
+        let (invitation, _) = UserToken::new(user, UserTokenKind::Invitation, None);
+        assert!(invitation.create_unless_recent(&pool, since).await.unwrap());
+    }
+}
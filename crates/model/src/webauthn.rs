@@ -0,0 +1,203 @@
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+use time::OffsetDateTime;
+
+use crate::{types::Key, user::User};
+
+/// A WebAuthn credential, such as a security key or a passkey, that a user has registered.
+///
+/// The model does not interpret the credential itself: the server keeps it as JSON, and updates it
+/// when the authenticator reports a change, such as to its signature counter.
+#[derive(Debug, FromRow, Serialize)]
+pub struct WebauthnCredential {
+    pub id: Key<WebauthnCredential>,
+    pub user: Key<User>,
+    pub name: String,
+    pub credential_id: String,
+    #[serde(skip)]
+    pub passkey: String,
+    pub created_at: OffsetDateTime,
+    pub last_used: Option<OffsetDateTime>,
+}
+
+impl WebauthnCredential {
+    pub fn new(user: Key<User>, name: &str, credential_id: String, passkey: String) -> Self {
+        Self {
+            id: Key::new(),
+            user,
+            name: name.to_string(),
+            credential_id,
+            passkey,
+            created_at: OffsetDateTime::now_utc(),
+            last_used: None,
+        }
+    }
+
+    pub async fn create(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        sqlx::query(
+            "INSERT INTO webauthn_credentials \
+            (id, user, name, credential_id, passkey, created_at) \
+            VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(self.id)
+        .bind(self.user)
+        .bind(&self.name)
+        .bind(&self.credential_id)
+        .bind(&self.passkey)
+        .bind(self.created_at)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get(pool: &SqlitePool, id: Key<WebauthnCredential>) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as("SELECT * FROM webauthn_credentials WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    pub async fn get_for_user(pool: &SqlitePool, user: Key<User>) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as(
+            "SELECT * FROM webauthn_credentials WHERE user = $1 ORDER BY created_at DESC",
+        )
+        .bind(user)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Check if the user has registered any credentials, and so must use one to sign in.
+    pub async fn exists_for_user(pool: &SqlitePool, user: Key<User>) -> sqlx::Result<bool> {
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM webauthn_credentials WHERE user = $1)")
+            .bind(user)
+            .fetch_one(pool)
+            .await
+    }
+
+    /// Record that the credential has been used to sign in, along with its updated state.
+    pub async fn record_used(&mut self, pool: &SqlitePool, passkey: String) -> sqlx::Result<()> {
+        let now = OffsetDateTime::now_utc();
+        sqlx::query("UPDATE webauthn_credentials SET passkey = $1, last_used = $2 WHERE id = $3")
+            .bind(&passkey)
+            .bind(now)
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        self.passkey = passkey;
+        self.last_used = Some(now);
+        Ok(())
+    }
+
+    pub async fn delete(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM webauthn_credentials WHERE id = $1")
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Delete all of the credentials of a user, returning how many were deleted.
+    pub async fn delete_for_user(pool: &SqlitePool, user: Key<User>) -> sqlx::Result<u64> {
+        let result = sqlx::query("DELETE FROM webauthn_credentials WHERE user = $1")
+            .bind(user)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// A WebAuthn ceremony, such as registering a credential or signing in with one, that has been
+/// started and not yet finished.
+///
+/// The state of the ceremony is kept by the browser until it responds, so the ceremony is recorded
+/// to make sure that the state can only be used once, and only until the ceremony expires.
+#[derive(Debug, FromRow)]
+pub struct WebauthnCeremony {
+    pub id: Key<WebauthnCeremony>,
+    pub started_at: OffsetDateTime,
+}
+
+impl WebauthnCeremony {
+    /// Start a new ceremony, removing any ceremonies that started before `expired`.
+    pub async fn start(pool: &SqlitePool, expired: OffsetDateTime) -> sqlx::Result<Self> {
+        sqlx::query("DELETE FROM webauthn_ceremonies WHERE started_at < $1")
+            .bind(expired)
+            .execute(pool)
+            .await?;
+
+        let ceremony = Self {
+            id: Key::new(),
+            started_at: OffsetDateTime::now_utc(),
+        };
+
+        sqlx::query("INSERT INTO webauthn_ceremonies (id, started_at) VALUES ($1, $2)")
+            .bind(ceremony.id)
+            .bind(ceremony.started_at)
+            .execute(pool)
+            .await?;
+
+        Ok(ceremony)
+    }
+
+    /// Finish a ceremony, returning `false` if it has already been finished, or if it started
+    /// before `expired`.
+    pub async fn finish(
+        pool: &SqlitePool,
+        id: Key<WebauthnCeremony>,
+        expired: OffsetDateTime,
+    ) -> sqlx::Result<bool> {
+        let started_at = sqlx::query_scalar::<_, OffsetDateTime>(
+            "DELETE FROM webauthn_ceremonies WHERE id = $1 RETURNING started_at",
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(started_at.is_some_and(|started_at| started_at >= expired))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+    use time::Duration;
+
+    use super::*;
+    use crate::migration::MIGRATOR;
+
+    #[tokio::test]
+    async fn test_finish_ceremony_once() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .expect("connect to database");
+        MIGRATOR.run(&pool).await.expect("run migrations");
+
+        let expired = OffsetDateTime::now_utc() - Duration::minutes(5);
+        let ceremony = WebauthnCeremony::start(&pool, expired)
+            .await
+            .expect("start ceremony");
+
+        // A ceremony can only be finished once, so its state cannot be replayed.
+        assert!(WebauthnCeremony::finish(&pool, ceremony.id, expired)
+            .await
+            .expect("finish ceremony"));
+        assert!(!WebauthnCeremony::finish(&pool, ceremony.id, expired)
+            .await
+            .expect("finish ceremony"));
+
+        // A ceremony that started before the expiry cannot be finished at all.
+        let ceremony = WebauthnCeremony::start(&pool, expired)
+            .await
+            .expect("start ceremony");
+        let later = OffsetDateTime::now_utc() + Duration::seconds(1);
+        assert!(!WebauthnCeremony::finish(&pool, ceremony.id, later)
+            .await
+            .expect("finish ceremony"));
+    }
+}
@@ -0,0 +1,349 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, SqlitePool};
+use time::OffsetDateTime;
+
+use crate::{team::Team, types::Key, user::User};
+
+/// The number of random bytes in the secret of a webhook.
+const SECRET_BYTES: usize = 32;
+
+/// The events in the life of an upload that can be sent to a webhook.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+pub enum WebhookEvent {
+    /// An upload was created, by a user or through an upload request.
+    #[serde(rename = "upload.created")]
+    #[sqlx(rename = "upload.created")]
+    UploadCreated,
+    /// An upload was downloaded.
+    #[serde(rename = "upload.downloaded")]
+    #[sqlx(rename = "upload.downloaded")]
+    UploadDownloaded,
+    /// An upload was downloaded for the last time allowed by its download limit.
+    #[serde(rename = "upload.exhausted")]
+    #[sqlx(rename = "upload.exhausted")]
+    UploadExhausted,
+    /// An upload passed its expiry date.
+    #[serde(rename = "upload.expired")]
+    #[sqlx(rename = "upload.expired")]
+    UploadExpired,
+    /// An upload was copied or moved to a team.
+    #[serde(rename = "upload.transferred")]
+    #[sqlx(rename = "upload.transferred")]
+    UploadTransferred,
+    /// A preview was generated for an upload.
+    #[serde(rename = "upload.previewed")]
+    #[sqlx(rename = "upload.previewed")]
+    UploadPreviewed,
+}
+
+impl WebhookEvent {
+    pub const ALL: &'static [Self] = &[
+        Self::UploadCreated,
+        Self::UploadDownloaded,
+        Self::UploadExhausted,
+        Self::UploadExpired,
+        Self::UploadTransferred,
+        Self::UploadPreviewed,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UploadCreated => "upload.created",
+            Self::UploadDownloaded => "upload.downloaded",
+            Self::UploadExhausted => "upload.exhausted",
+            Self::UploadExpired => "upload.expired",
+            Self::UploadTransferred => "upload.transferred",
+            Self::UploadPreviewed => "upload.previewed",
+        }
+    }
+
+    /// A description of the event, for choosing the events that are sent to a webhook.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::UploadCreated => "A file is uploaded",
+            Self::UploadDownloaded => "An upload is downloaded",
+            Self::UploadExhausted => "An upload reaches its download limit",
+            Self::UploadExpired => "An upload expires",
+            Self::UploadTransferred => "An upload is copied or moved to a team",
+            Self::UploadPreviewed => "A preview is generated for an upload",
+        }
+    }
+}
+
+/// A URL that is sent the events for the uploads of a user or a team.
+///
+/// Each event is sent as JSON in the body of a POST request, which is signed using the secret of
+/// the webhook, so that the receiver can check that the request came from us.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Webhook {
+    pub id: Key<Webhook>,
+    pub name: String,
+    pub url: String,
+    pub secret: String,
+    /// The events that are sent to the webhook, separated by commas.
+    pub events: String,
+    pub owner_user: Option<Key<User>>,
+    pub owner_team: Option<Key<Team>>,
+    pub created_by: Option<Key<User>>,
+    pub created_at: OffsetDateTime,
+}
+
+impl Webhook {
+    /// Create a new webhook with a random secret, owned by either a user or a team.
+    pub fn new(
+        name: &str,
+        url: &str,
+        events: &[WebhookEvent],
+        owner_user: Option<Key<User>>,
+        owner_team: Option<Key<Team>>,
+        created_by: Key<User>,
+    ) -> Self {
+        let mut bytes = [0u8; SECRET_BYTES];
+        OsRng.fill_bytes(&mut bytes);
+
+        let mut secret = String::with_capacity(SECRET_BYTES * 2);
+        for byte in bytes {
+            secret.push_str(&format!("{byte:02x}"));
+        }
+
+        let events = events
+            .iter()
+            .map(WebhookEvent::as_str)
+            .collect::<Vec<_>>()
+            .join(",");
+
+        Self {
+            id: Key::new(),
+            name: name.to_string(),
+            url: url.to_string(),
+            secret,
+            events,
+            owner_user,
+            owner_team,
+            created_by: Some(created_by),
+            created_at: OffsetDateTime::now_utc(),
+        }
+    }
+
+    pub async fn create(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        sqlx::query(
+            "INSERT INTO webhooks (id, name, url, secret, events, owner_user, owner_team,
+            created_by, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        )
+        .bind(self.id)
+        .bind(&self.name)
+        .bind(&self.url)
+        .bind(&self.secret)
+        .bind(&self.events)
+        .bind(self.owner_user)
+        .bind(self.owner_team)
+        .bind(self.created_by)
+        .bind(self.created_at)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get(pool: &SqlitePool, id: Key<Webhook>) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as("SELECT * FROM webhooks WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    pub async fn get_for_user(pool: &SqlitePool, user: Key<User>) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as("SELECT * FROM webhooks WHERE owner_user = $1 ORDER BY created_at")
+            .bind(user)
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn get_for_team(pool: &SqlitePool, team: Key<Team>) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as("SELECT * FROM webhooks WHERE owner_team = $1 ORDER BY created_at")
+            .bind(team)
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Get the webhooks of a user or a team that are sent the given event.
+    pub async fn get_subscribed(
+        pool: &SqlitePool,
+        owner_user: Option<Key<User>>,
+        owner_team: Option<Key<Team>>,
+        event: WebhookEvent,
+    ) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as(
+            "SELECT * FROM webhooks \
+            WHERE (owner_user = $1 OR owner_team = $2) \
+            AND ',' || events || ',' LIKE '%,' || $3 || ',%'",
+        )
+        .bind(owner_user)
+        .bind(owner_team)
+        .bind(event.as_str())
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn delete(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM webhooks WHERE id = $1")
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Where a webhook delivery has got to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum WebhookDeliveryStatus {
+    /// The delivery is waiting for its next attempt.
+    Pending,
+    /// The webhook accepted the delivery.
+    Delivered,
+    /// Every attempt at the delivery failed, and it will not be tried again.
+    Failed,
+}
+
+/// The delivery of an event to a webhook.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct WebhookDelivery {
+    pub id: Key<WebhookDelivery>,
+    pub webhook: Key<Webhook>,
+    pub event: WebhookEvent,
+    /// The JSON body that is sent to the webhook.
+    pub payload: String,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: i64,
+    pub next_attempt_at: Option<OffsetDateTime>,
+    pub last_attempt_at: Option<OffsetDateTime>,
+    /// The HTTP status code of the last response, if there was one.
+    pub response_status: Option<i64>,
+    /// Why the last attempt failed, if it did.
+    pub error: Option<String>,
+    pub created_at: OffsetDateTime,
+}
+
+impl WebhookDelivery {
+    /// Create a new delivery of an event to a webhook, which is due to be sent straight away.
+    pub fn new(webhook: Key<Webhook>, event: WebhookEvent, payload: String) -> Self {
+        let now = OffsetDateTime::now_utc();
+
+        Self {
+            id: Key::new(),
+            webhook,
+            event,
+            payload,
+            status: WebhookDeliveryStatus::Pending,
+            attempts: 0,
+            next_attempt_at: Some(now),
+            last_attempt_at: None,
+            response_status: None,
+            error: None,
+            created_at: now,
+        }
+    }
+
+    pub async fn create(&self, pool: &SqlitePool) -> sqlx::Result<()> {
+        sqlx::query(
+            "INSERT INTO webhook_deliveries (id, webhook, event, payload, status, attempts,
+            next_attempt_at, last_attempt_at, response_status, error, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+        )
+        .bind(self.id)
+        .bind(self.webhook)
+        .bind(self.event)
+        .bind(&self.payload)
+        .bind(self.status)
+        .bind(self.attempts)
+        .bind(self.next_attempt_at)
+        .bind(self.last_attempt_at)
+        .bind(self.response_status)
+        .bind(&self.error)
+        .bind(self.created_at)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get the pending deliveries that are due to be attempted, oldest first.
+    pub async fn get_due(pool: &SqlitePool, limit: u32) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as(
+            "SELECT * FROM webhook_deliveries \
+            WHERE status = 'pending' AND next_attempt_at <= $1 \
+            ORDER BY next_attempt_at LIMIT $2",
+        )
+        .bind(OffsetDateTime::now_utc())
+        .bind(limit as i64)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Get the deliveries to a webhook, most recent first.
+    pub async fn get_for_webhook(
+        pool: &SqlitePool,
+        webhook: Key<Webhook>,
+        offset: u32,
+        limit: u32,
+    ) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as(
+            "SELECT * FROM webhook_deliveries WHERE webhook = $1 \
+            ORDER BY created_at DESC LIMIT $2 OFFSET $3",
+        )
+        .bind(webhook)
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Record an attempt at the delivery.
+    ///
+    /// When the attempt failed, the `next_attempt_at` gives the time of the next attempt, or is
+    /// `None` if the delivery should not be tried again.
+    pub async fn record_attempt(
+        &mut self,
+        pool: &SqlitePool,
+        response_status: Option<i64>,
+        error: Option<String>,
+        next_attempt_at: Option<OffsetDateTime>,
+    ) -> sqlx::Result<()> {
+        let status = if error.is_none() {
+            WebhookDeliveryStatus::Delivered
+        } else if next_attempt_at.is_some() {
+            WebhookDeliveryStatus::Pending
+        } else {
+            WebhookDeliveryStatus::Failed
+        };
+
+        let now = OffsetDateTime::now_utc();
+        sqlx::query(
+            "UPDATE webhook_deliveries SET status = $1, attempts = attempts + 1, \
+            next_attempt_at = $2, last_attempt_at = $3, response_status = $4, error = $5 \
+            WHERE id = $6",
+        )
+        .bind(status)
+        .bind(next_attempt_at)
+        .bind(now)
+        .bind(response_status)
+        .bind(&error)
+        .bind(self.id)
+        .execute(pool)
+        .await?;
+
+        self.status = status;
+        self.attempts += 1;
+        self.next_attempt_at = next_attempt_at;
+        self.last_attempt_at = Some(now);
+        self.response_status = response_status;
+        self.error = error;
+
+        Ok(())
+    }
+}
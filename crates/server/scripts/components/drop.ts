@@ -0,0 +1,255 @@
+import { FunctionComponent, VNode } from "preact";
+import { html } from "htm/preact";
+import register from "preact-custom-element";
+import { useState, ProvideState, StateMode, StateAction } from "./upload/state";
+import DropZone from "./upload/components/dropzone";
+import FilesSummary from "./upload/components/summary";
+import FilesList from "./upload/components/list";
+import UploadProgress from "./upload/components/progress";
+import { FileInfo } from "./upload/files";
+import { formatBytes } from "./upload/utils";
+
+// This is a stripped-down version of the upload form, used on the page of an upload request. The
+// person uploading the files has not signed in, so there are no teams, folders or bundles, but the
+// files must be within the limits of the request.
+
+interface DropProps {
+  csrf_token: string;
+  token: string;
+  remaining?: string;
+  max_size?: string;
+  mime_types?: string;
+}
+
+// Get the error message from a failed upload response, if the server sent one.
+function responseError(upload: XMLHttpRequest): string | undefined {
+  try {
+    const body = JSON.parse(upload.responseText);
+    return typeof body.error === "string" ? body.error : undefined;
+  } catch {
+    return undefined;
+  }
+}
+
+// Check whether a file type is one of the allowed types, which can end with "/*" to allow any
+// subtype, such as "image/*".
+function isAllowedType(allowed: string[], type: string): boolean {
+  type = type.toLowerCase();
+  return allowed.some((mime) =>
+    mime.endsWith("/*")
+      ? type.startsWith(mime.substring(0, mime.length - 1))
+      : type === mime,
+  );
+}
+
+// Find the reasons that the chosen files cannot be uploaded through the request, if any. The
+// server checks these as well, but we can save the user from a doomed upload.
+function checkLimits(props: DropProps, files: FileInfo[]): string[] {
+  const problems = [];
+
+  const remaining = props.remaining ? parseInt(props.remaining, 10) : null;
+  if (remaining !== null && files.length > remaining) {
+    problems.push(
+      `Only ${remaining} more ${remaining === 1 ? "file" : "files"} can be uploaded.`,
+    );
+  }
+
+  const maxSize = props.max_size ? parseInt(props.max_size, 10) : null;
+  if (maxSize !== null && files.some((file) => file.size > maxSize)) {
+    problems.push(`Each file must be no larger than ${formatBytes(maxSize)}.`);
+  }
+
+  const allowed = props.mime_types
+    ? props.mime_types.split(",").map((mime) => mime.trim().toLowerCase())
+    : null;
+  if (allowed && files.some((file) => !isAllowedType(allowed, file.type))) {
+    problems.push(`Only these types of file can be uploaded: ${allowed.join(", ")}.`);
+  }
+
+  return problems;
+}
+
+function startUpload(
+  csrf_token: string,
+  token: string,
+  files: FileInfo[],
+  dispatch: (action: StateAction) => void,
+) {
+  const form = new FormData();
+  form.append("csrf_token", csrf_token);
+
+  for (let file of files) {
+    form.append("file", file.file);
+  }
+
+  const upload = new XMLHttpRequest();
+
+  upload.addEventListener("load", () => {
+    if (upload.status >= 200 && upload.status < 300) {
+      dispatch({ type: "complete" });
+    } else {
+      console.error("Upload failed with status:", upload.status, upload.statusText);
+      dispatch({
+        type: "error",
+        event: new ErrorEvent("error", { message: `HTTP ${upload.status}: ${upload.statusText}` }),
+        message: responseError(upload),
+      });
+    }
+  });
+
+  upload.addEventListener("error", (event) => {
+    console.error("Failed to upload file", event);
+    dispatch({ type: "error", event });
+  });
+
+  upload.addEventListener("abort", (event) => {
+    console.warn("Upload was aborted", event);
+    dispatch({ type: "abort", event });
+  });
+
+  upload.upload.addEventListener("progress", (event) => {
+    dispatch({ type: "progress", loaded: event.loaded });
+  });
+
+  upload.open("POST", `/r/${encodeURIComponent(token)}`);
+  upload.send(form);
+  dispatch({ type: "upload", upload });
+}
+
+const DropButtons: FunctionComponent<DropProps> = (props) => {
+  const { state, dispatch } = useState();
+  const problems = checkLimits(props, state.files);
+
+  const onCancelClick = () => {
+    if (state.upload) {
+      state.upload.abort();
+    } else {
+      dispatch({ type: "reset" });
+    }
+  };
+
+  const onUploadClick = () => {
+    startUpload(props.csrf_token, props.token, state.files, dispatch);
+  };
+
+  const buttons = html`
+    <div class="buttons end">
+      <button
+        type="button"
+        class="button hollow ${state.upload && "danger"}"
+        disabled=${state.files.length === 0 && !state.upload}
+        onclick=${onCancelClick}
+      >
+        <span class="icon-x"></span>
+        Cancel${state.upload && " upload"}
+      </button>
+      <button
+        type="button"
+        class="button"
+        disabled=${state.files.length === 0 || state.upload || problems.length > 0}
+        onclick=${onUploadClick}
+      >
+        <span class="icon-upload"></span>
+        Upload file
+      </button>
+    </div>
+  `;
+
+  if (problems.length === 0) {
+    return buttons;
+  }
+
+  return html`
+    <div class="flex flex-col gap-2">
+      ${problems.map(
+        (problem) => html`<div class="text-danger text-sm text-right">${problem}</div>`,
+      )}
+      ${buttons}
+    </div>
+  `;
+};
+
+const DoneButtons: FunctionComponent = () => {
+  const { state, dispatch } = useState();
+
+  // Reload the page after a successful upload, so that the remaining number of files is updated.
+  const onMoreClick = () => {
+    if (state.mode === StateMode.Complete) {
+      window.location.reload();
+    } else {
+      dispatch({ type: "reset" });
+    }
+  };
+
+  return html`
+    <div class="buttons end">
+      <button type="button" class="button hollow" onclick=${onMoreClick}>
+        <span class="icon-rotate-ccw"></span>
+        Upload more
+      </button>
+    </div>
+  `;
+};
+
+const DropBody = () => {
+  const { state } = useState();
+
+  if (state.files.length === 0) {
+    return html`<div></div>`;
+  }
+
+  return html`
+    <div
+      class="border border-gray-300 dark:border-slate-600 rounded-md flex flex-col gap-2 overflow-y-hidden"
+    >
+      ${state.upload
+        ? html` <${UploadProgress} /> `
+        : html` <${FilesSummary} /> `}
+      <div class="overflow-y-scroll px-4 mb-4">
+        <${FilesList} />
+      </div>
+    </div>
+  `;
+};
+
+const DropFormInner: FunctionComponent<DropProps> = (props) => {
+  const { state } = useState();
+
+  let buttons: VNode;
+  switch (state.mode) {
+    case StateMode.Preparing:
+    case StateMode.Uploading:
+      buttons = html`<${DropButtons} ...${props} />`;
+      break;
+
+    case StateMode.Error:
+    case StateMode.Aborted:
+    case StateMode.Complete:
+      buttons = html`<${DoneButtons} />`;
+      break;
+  }
+
+  return html`
+    <div class="grid grid-rows-[max-content_1fr_max-content] gap-4">
+      <${DropZone} />
+      <${DropBody} />
+      ${buttons}
+    </div>
+  `;
+};
+
+const DropForm: FunctionComponent<DropProps> = (props) => {
+  return html`
+      <${ProvideState}>
+        <${DropFormInner} ...${props} />
+      </${ProvideState}>
+  `;
+};
+
+register(DropForm, "parcel-drop-form", [
+  "csrf_token",
+  "token",
+  "remaining",
+  "max_size",
+  "mime_types",
+]);
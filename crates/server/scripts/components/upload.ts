@@ -9,11 +9,24 @@ import FilesList from "./upload/components/list";
 import UploadProgress from "./upload/components/progress";
 import { ParcelModal } from "./modal";
 import { FileInfo } from "./upload/files";
+import { formatBytes } from "./upload/utils";
+
+// Get the error message from a failed upload response, if the server sent one.
+function responseError(upload: XMLHttpRequest): string | undefined {
+  try {
+    const body = JSON.parse(upload.responseText);
+    return typeof body.error === "string" ? body.error : undefined;
+  } catch {
+    return undefined;
+  }
+}
 
 function startUpload(
   modal: ParcelModal,
   csrf_token: string,
   team: string | null,
+  folder: string | null,
+  bundle: string | null,
   files: FileInfo[],
   dispatch: (action: StateAction) => void,
 ) {
@@ -24,6 +37,14 @@ function startUpload(
     form.append("team", team);
   }
 
+  if (folder) {
+    form.append("folder", folder);
+  }
+
+  if (bundle) {
+    form.append("bundle", bundle);
+  }
+
   for (let file of files) {
     form.append("file", file.file);
   }
@@ -39,7 +60,11 @@ function startUpload(
       dispatch({ type: "complete" });
     } else {
       console.error("Upload failed with status:", upload.status, upload.statusText);
-      dispatch({ type: "error", event: new ErrorEvent("error", { message: `HTTP ${upload.status}: ${upload.statusText}` }) });
+      dispatch({
+        type: "error",
+        event: new ErrorEvent("error", { message: `HTTP ${upload.status}: ${upload.statusText}` }),
+        message: responseError(upload),
+      });
     }
   });
 
@@ -68,9 +93,15 @@ function startUpload(
 const UploadButtons: FunctionComponent<{
   csrf_token: string;
   team?: string;
+  folder?: string;
+  remaining?: string;
 }> = (props) => {
   const { state, dispatch } = useState();
 
+  // The server also checks the quota, but we can save the user from a doomed upload.
+  const remaining = props.remaining ? parseInt(props.remaining, 10) : null;
+  const overQuota = remaining !== null && state.totalSize > remaining;
+
   const onCancelClick = (event: MouseEvent) => {
     if (state.upload) {
       state.upload.abort();
@@ -93,13 +124,39 @@ const UploadButtons: FunctionComponent<{
       modal,
       props.csrf_token,
       props.team || null,
+      props.folder || null,
+      state.files.length > 1 ? state.bundle.trim() || null : null,
       state.files,
       dispatch,
     );
   };
 
-  return html`
+  const onBundleInput = (event: InputEvent) => {
+    dispatch({
+      type: "bundle",
+      name: (event.target as HTMLInputElement).value,
+    });
+  };
+
+  // When more than one file is uploaded, they can be grouped into a bundle with a single link.
+  const bundle =
+    state.files.length > 1 && !state.upload
+      ? html`
+          <input
+            type="text"
+            class="field grow"
+            id="upload-bundle-name"
+            placeholder="Bundle name (optional, groups these files under one link)"
+            maxlength="100"
+            value=${state.bundle}
+            oninput=${onBundleInput}
+          />
+        `
+      : null;
+
+  const buttons = html`
     <div class="buttons end">
+      ${bundle}
       <button
         type="button"
         class="button hollow ${state.upload && "danger"}"
@@ -111,7 +168,7 @@ const UploadButtons: FunctionComponent<{
       <button
         type="button"
         class="button"
-        disabled=${state.files.length === 0 || state.upload}
+        disabled=${state.files.length === 0 || state.upload || overQuota}
         onclick=${onUploadClick}
       >
         <span class="icon-upload"></span>
@@ -119,6 +176,19 @@ const UploadButtons: FunctionComponent<{
       </button>
     </div>
   `;
+
+  if (!overQuota) {
+    return buttons;
+  }
+
+  return html`
+    <div class="flex flex-col gap-2">
+      <div class="text-danger text-sm text-right">
+        These files would exceed your remaining storage of ${formatBytes(remaining)}.
+      </div>
+      ${buttons}
+    </div>
+  `;
 };
 
 const CompleteButtons: FunctionComponent = () => {
@@ -148,9 +218,11 @@ const CompleteButtons: FunctionComponent = () => {
   `;
 };
 
-const ErrorButtons: FunctionComponent<{ csrf_token: string; team?: string }> = (
-  props,
-) => {
+const ErrorButtons: FunctionComponent<{
+  csrf_token: string;
+  team?: string;
+  folder?: string;
+}> = (props) => {
   const { state, dispatch } = useState();
 
   const onCancelClick = (event: MouseEvent) => {
@@ -175,6 +247,8 @@ const ErrorButtons: FunctionComponent<{ csrf_token: string; team?: string }> = (
       modal,
       props.csrf_token,
       props.team || null,
+      props.folder || null,
+      state.files.length > 1 ? state.bundle.trim() || null : null,
       state.files,
       dispatch,
     );
@@ -226,6 +300,8 @@ const UploadBody = () => {
 const UploadFormInner: FunctionComponent<{
   csrf_token: string;
   team?: string;
+  folder?: string;
+  remaining?: string;
 }> = (props) => {
   const eventRecv = useRef<HTMLElement>(null);
   const { state, dispatch } = useState();
@@ -279,9 +355,12 @@ const UploadFormInner: FunctionComponent<{
   `;
 };
 
-const UploadForm: FunctionComponent<{ csrf_token: string; team?: string }> = (
-  props,
-) => {
+const UploadForm: FunctionComponent<{
+  csrf_token: string;
+  team?: string;
+  folder?: string;
+  remaining?: string;
+}> = (props) => {
   return html`
       <${ProvideState}>
         <${UploadFormInner} ...${props} />
@@ -289,4 +368,9 @@ const UploadForm: FunctionComponent<{ csrf_token: string; team?: string }> = (
   `;
 };
 
-register(UploadForm, "parcel-upload-form", ["csrf_token", "team"]);
+register(UploadForm, "parcel-upload-form", [
+  "csrf_token",
+  "team",
+  "folder",
+  "remaining",
+]);
@@ -32,7 +32,7 @@ const DropZone = () => {
     case StateMode.Error:
       icon = "icon-octagon-alert";
       title = "Failed to upload files";
-      subtitle = "There was an error uploading your files";
+      subtitle = state.error || "There was an error uploading your files";
       colors = STATE_COLORS.error;
       break;
     case StateMode.Aborted:
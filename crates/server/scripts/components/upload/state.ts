@@ -10,9 +10,10 @@ export type StateAction =
   | { type: "add"; files: File[] }
   | { type: "remove"; index: number }
   | { type: "removeAll" }
+  | { type: "bundle"; name: string }
   | { type: "upload"; upload: XMLHttpRequest }
   | { type: "progress"; loaded: number }
-  | { type: "error"; event: Event }
+  | { type: "error"; event: Event; message?: string }
   | { type: "abort"; event: Event }
   | { type: "complete" }
   | { type: "reset" };
@@ -32,6 +33,8 @@ export interface State {
   dragHint: string | null;
   files: FileInfo[];
   totalSize: number;
+  // The name of the bundle to group the files into, if more than one file is uploaded.
+  bundle: string;
   upload: XMLHttpRequest | null;
   uploadedBytes: number;
   uploadProgress: number;
@@ -46,6 +49,7 @@ function createInitialState(): State {
     dragHint: null,
     files: [],
     totalSize: 0,
+    bundle: "",
     upload: null,
     uploadedBytes: 0,
     uploadProgress: 0,
@@ -133,6 +137,13 @@ function reduceStateAction(state: State, action: StateAction): State {
       };
     }
 
+    case "bundle": {
+      return {
+        ...state,
+        bundle: action.name,
+      };
+    }
+
     case "upload": {
       return {
         ...state,
@@ -154,7 +165,7 @@ function reduceStateAction(state: State, action: StateAction): State {
         ...state,
         mode: StateMode.Error,
         upload: null,
-        error: "There was an error uploading files",
+        error: action.message || "There was an error uploading files",
       };
     }
 
@@ -161,12 +161,18 @@ function setupDropIndicator() {
 
     const team = getTeamIdentifier();
 
+    // Files that are dropped onto a folder's list of uploads are put into that folder.
+    const folder = document.getElementById("upload-list-container")?.dataset
+      .folder;
+
     // There is no form, present, so we need to load one. We can do that with HTMX. We tell the
     // upload form not to bother animating in.
     htmx
       .ajax(
         "get",
-        "/uploads/new?immediate=true" + (team ? `&team=${team}` : ""),
+        "/uploads/new?immediate=true" +
+          (team ? `&team=${team}` : "") +
+          (folder ? `&folder=${folder}` : ""),
         {
           target: "body",
           swap: "beforeend",
@@ -230,6 +236,7 @@ function setupParcelChangeEvent() {
       const page = row.dataset.page;
       const order = row.dataset.order;
       const asc = row.dataset.asc;
+      const folder = row.dataset.folder;
 
       const params = new URLSearchParams();
 
@@ -241,6 +248,31 @@ function setupParcelChangeEvent() {
         params.set("asc", asc);
       }
 
+      if (folder) {
+        params.set("folder", folder);
+      }
+
+      // Keep the search and filters, so that the row is found on the same page.
+      const search = document.getElementById(
+        "upload-search",
+      ) as HTMLInputElement | null;
+
+      if (search && search.value) {
+        params.set("search", search.value);
+      }
+
+      const filters = document.getElementById(
+        "upload-filters",
+      ) as HTMLFormElement | null;
+
+      if (filters) {
+        new FormData(filters).forEach((value, name) => {
+          if (typeof value === "string" && value) {
+            params.set(name, value);
+          }
+        });
+      }
+
       htmx.ajax(
         "get",
         (team ? `/teams/${team}` : "") +
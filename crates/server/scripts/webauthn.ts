@@ -0,0 +1,205 @@
+// Registering and using security keys and passkeys with WebAuthn.
+//
+// The server sends its challenges as JSON, where the binary values are encoded as URL-safe base-64.
+// These need to be decoded before they are given to the browser, and the credential that the
+// browser gives back needs to be encoded in the same way before it is sent to the server. Once a
+// ceremony has finished, the server tells us where to go next.
+
+interface Continue {
+  redirect: string;
+}
+
+function decode(value: string): ArrayBuffer {
+  const base64 = value.replace(/-/g, "+").replace(/_/g, "/");
+  const binary = atob(base64.padEnd(Math.ceil(base64.length / 4) * 4, "="));
+  const bytes = new Uint8Array(binary.length);
+  for (let i = 0; i < binary.length; i++) {
+    bytes[i] = binary.charCodeAt(i);
+  }
+
+  return bytes.buffer;
+}
+
+function encode(value: ArrayBuffer): string {
+  let binary = "";
+  for (const byte of new Uint8Array(value)) {
+    binary += String.fromCharCode(byte);
+  }
+
+  return btoa(binary)
+    .replace(/\+/g, "-")
+    .replace(/\//g, "_")
+    .replace(/=+$/, "");
+}
+
+async function post<T>(url: string, body: object): Promise<T> {
+  const response = await fetch(url, {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(body),
+  });
+
+  if (!response.ok) {
+    throw new Error(
+      `Request to ${url} failed with status ${response.status}`,
+    );
+  }
+
+  return response.json();
+}
+
+function decodeDescriptors(
+  descriptors: any[] | undefined,
+): PublicKeyCredentialDescriptor[] | undefined {
+  return descriptors?.map((descriptor) => ({
+    ...descriptor,
+    id: decode(descriptor.id),
+  }));
+}
+
+async function registerCredential(token: string, name: string) {
+  const { publicKey } = await post<{ publicKey: any }>(
+    "/user/settings/webauthn/challenge",
+    { token },
+  );
+
+  const credential = (await navigator.credentials.create({
+    publicKey: {
+      ...publicKey,
+      challenge: decode(publicKey.challenge),
+      user: { ...publicKey.user, id: decode(publicKey.user.id) },
+      excludeCredentials: decodeDescriptors(publicKey.excludeCredentials),
+    },
+  })) as PublicKeyCredential;
+
+  const response = credential.response as AuthenticatorAttestationResponse;
+  const { redirect } = await post<Continue>("/user/settings/webauthn", {
+    token,
+    name,
+    credential: {
+      id: credential.id,
+      rawId: encode(credential.rawId),
+      type: credential.type,
+      response: {
+        attestationObject: encode(response.attestationObject),
+        clientDataJSON: encode(response.clientDataJSON),
+      },
+      extensions: credential.getClientExtensionResults(),
+    },
+  });
+
+  window.location.assign(redirect);
+}
+
+async function useCredential(url: string, token: string) {
+  const { publicKey } = await post<{ publicKey: any }>(`${url}/challenge`, {
+    token,
+  });
+
+  const credential = (await navigator.credentials.get({
+    publicKey: {
+      ...publicKey,
+      challenge: decode(publicKey.challenge),
+      allowCredentials: decodeDescriptors(publicKey.allowCredentials),
+    },
+  })) as PublicKeyCredential;
+
+  const response = credential.response as AuthenticatorAssertionResponse;
+  const { redirect } = await post<Continue>(url, {
+    token,
+    credential: {
+      id: credential.id,
+      rawId: encode(credential.rawId),
+      type: credential.type,
+      response: {
+        authenticatorData: encode(response.authenticatorData),
+        clientDataJSON: encode(response.clientDataJSON),
+        signature: encode(response.signature),
+        userHandle: response.userHandle ? encode(response.userHandle) : null,
+      },
+      extensions: credential.getClientExtensionResults(),
+    },
+  });
+
+  window.location.assign(redirect);
+}
+
+function showError(message: string) {
+  const element = document.getElementById("webauthn-error");
+  if (element) {
+    element.textContent = message;
+    element.classList.remove("hidden");
+  }
+}
+
+function failed(error: unknown) {
+  console.error("WebAuthn ceremony failed:", error);
+
+  // The browser reports a cancelled or timed out prompt as not allowed.
+  if (error instanceof DOMException && error.name === "NotAllowedError") {
+    showError("Your security key was not used. Please try again.");
+  } else {
+    showError(
+      "Something went wrong with your security key. Please try again.",
+    );
+  }
+}
+
+function setupButton(id: string, url: string) {
+  const button = document.getElementById(id) as HTMLButtonElement | null;
+  if (!button) {
+    return;
+  }
+
+  button.classList.remove("hidden");
+  button.addEventListener("click", () => {
+    button.disabled = true;
+    useCredential(url, button.dataset.token || "").catch((error) => {
+      button.disabled = false;
+      failed(error);
+    });
+  });
+}
+
+function setupRegistration() {
+  const form = document.getElementById(
+    "webauthn-form",
+  ) as HTMLFormElement | null;
+  if (!form) {
+    return;
+  }
+
+  form.classList.remove("hidden");
+  form.addEventListener("submit", (event) => {
+    event.preventDefault();
+
+    const data = new FormData(form);
+    const button = form.querySelector<HTMLButtonElement>("button[type=submit]");
+    if (button) {
+      button.disabled = true;
+    }
+
+    registerCredential(
+      String(data.get("token") || ""),
+      String(data.get("name") || ""),
+    ).catch((error) => {
+      if (button) {
+        button.disabled = false;
+      }
+
+      failed(error);
+    });
+  });
+}
+
+// Browsers without WebAuthn keep the buttons and forms hidden.
+if (window.PublicKeyCredential) {
+  setupButton("passkey-signin", "/user/signin/passkey");
+  setupButton("webauthn-signin", "/user/signin/webauthn");
+  setupRegistration();
+} else if (
+  document.getElementById("webauthn-form") ||
+  document.getElementById("webauthn-signin")
+) {
+  showError("Your browser does not support security keys or passkeys.");
+}
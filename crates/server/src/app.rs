@@ -11,19 +11,29 @@ use crate::{env::Env, workers::previews::PreviewWorker};
 
 mod extractors {
     pub mod admin;
+    pub mod api;
+    pub mod audit;
+    pub mod token;
     pub mod user;
 }
 
 pub mod errors;
 pub mod templates;
 
-mod handlers {
+pub(crate) mod handlers {
     pub mod admin;
+    pub mod api;
+    pub mod bundles;
+    pub mod filters;
+    pub mod folders;
     pub mod index;
+    pub mod links;
+    pub mod requests;
     pub mod teams;
     pub mod uploads;
     pub mod users;
     pub mod utils;
+    pub mod webhooks;
 
     #[cfg(debug_assertions)]
     pub mod debug;
@@ -75,19 +85,27 @@ pub fn create_app(
     #[cfg(debug_assertions)]
     let static_ep = StaticFilesEndpoint::new(format!("{}/static", env!("CARGO_MANIFEST_DIR")));
 
+    let api_ep = handlers::api::create_api();
+
     let routes = add_debug_routes(define_routes!({
         *"/static" { static_ep }
+        *"/api/v1" { api_ep }
 
         "/"                             handlers::index::index                  GET
         "/tab"                          handlers::index::tab                    GET
         "/uploads/delete"               handlers::uploads::delete                   POST
         "/uploads/list"                 handlers::uploads::list                 GET
         "/uploads/list/:page"           handlers::uploads::page                 GET
+        "/uploads/move"                 handlers::uploads::move_uploads         GET POST
         "/uploads/new"                  handlers::uploads::new                  GET POST
         "/uploads/:id"                  handlers::uploads::upload               GET      DELETE
         "/uploads/:id/download"         handlers::uploads::download             GET POST
+        "/uploads/:id/downloads"        handlers::uploads::history              GET
+        "/uploads/:id/downloads/:page"  handlers::uploads::history_page         GET
         "/uploads/:id/edit"             handlers::uploads::edit                 GET POST
         "/uploads/:id/edit/slug"        handlers::uploads::check_slug               POST
+        "/uploads/:id/links"            handlers::uploads::links                    POST
+        "/uploads/:id/links/:link/revoke" handlers::uploads::revoke_link            POST
         "/uploads/:id/preview"          handlers::uploads::preview              GET
         "/uploads/:id/preview/error"    handlers::uploads::preview_error                 DELETE
         "/uploads/:id/public"           handlers::uploads::public                   POST
@@ -95,24 +113,66 @@ pub fn create_app(
         "/uploads/:id/share"            handlers::uploads::share                GET
         "/uploads/:id/transfer"         handlers::uploads::transfer             GET POST
         "/uploads/:owner/:slug"         handlers::uploads::custom_upload        GET
+        "/bundles/:id"                  handlers::bundles::bundle               GET      DELETE
+        "/bundles/:id/download"         handlers::bundles::download             GET POST
+        "/bundles/:id/edit"             handlers::bundles::edit                 GET POST
+        "/filters/new"                  handlers::filters::new                  GET POST
+        "/filters/:id"                  handlers::filters::filter                        DELETE
+        "/folders/new"                  handlers::folders::new                  GET POST
+        "/folders/:id"                  handlers::folders::folder               GET      DELETE
+        "/folders/:id/edit"             handlers::folders::edit                 GET POST
+        "/folders/:id/files/:file"      handlers::folders::file                 GET
+        "/folders/:id/move"             handlers::folders::move_folder          GET POST
+        "/requests"                     handlers::requests::requests            GET POST
+        "/requests/:id/revoke"          handlers::requests::revoke                  POST
+        "/r/:token"                     handlers::requests::request             GET POST
+        "/r/:token/unlock"              handlers::requests::unlock                  POST
+        "/webhooks"                     handlers::webhooks::webhooks            GET POST
+        "/webhooks/:id"                 handlers::webhooks::webhook             GET      DELETE
+        "/webhooks/:id/deliveries/:page" handlers::webhooks::deliveries_page    GET
+        "/s/:token"                     handlers::links::link                   GET
+        "/s/:token/download"            handlers::links::link_download          GET POST
         "/teams/:id"                    handlers::teams::team                   GET
+        "/teams/:id/downloads"          handlers::teams::downloads::downloads   GET
         "/teams/:id/settings"           handlers::teams::settings::settings     GET POST
         "/teams/:id/settings/slug"      handlers::teams::settings::check_slug       POST
         "/teams/:id/tab"                handlers::teams::tab                    GET
         "/teams/:id/uploads/list"       handlers::teams::uploads::list          GET
         "/teams/:id/uploads/list/:page" handlers::teams::uploads::page          GET
         "/user/signin"                  handlers::users::signin                 GET POST
+        "/user/signin/oidc"             handlers::users::signin_oidc            GET
+        "/user/signin/oidc/callback"    handlers::users::signin_oidc_callback   GET
+        "/user/signin/passkey"          handlers::users::signin_passkey             POST
+        "/user/signin/passkey/challenge" handlers::users::signin_passkey_challenge  POST
         "/user/signin/totp"             handlers::users::signin_totp            GET POST
+        "/user/signin/webauthn"         handlers::users::signin_webauthn            POST
+        "/user/signin/webauthn/challenge" handlers::users::signin_webauthn_challenge POST
         "/user/signout"                 handlers::users::signout                GET
+        "/user/invitation/:token"       handlers::users::invitation             GET POST
+        "/user/password/forgot"         handlers::users::forgot_password        GET POST
+        "/user/password/reset/:token"   handlers::users::reset_password         GET POST
         "/user/settings"                handlers::users::settings               GET POST
         "/user/settings/password"       handlers::users::password                   POST
+        "/user/settings/notifications"  handlers::users::notifications              POST
         "/user/settings/totp"           handlers::users::setup_totp             GET POST
         "/user/settings/totp/remove"    handlers::users::remove_totp            GET POST
+        "/user/settings/totp/recovery"  handlers::users::recovery_codes             POST
+        "/user/settings/tokens"         handlers::users::api_tokens                 POST
+        "/user/settings/tokens/:id/revoke" handlers::users::revoke_api_token        POST
+        "/user/settings/webauthn"       handlers::users::webauthn                   POST
+        "/user/settings/webauthn/challenge" handlers::users::webauthn_challenge     POST
+        "/user/settings/webauthn/:id/remove" handlers::users::remove_webauthn       POST
         "/admin"                        handlers::admin::admin                  GET
         "/admin/setup"                  handlers::admin::setup::setup           GET POST
         "/admin/uploads"                handlers::admin::uploads::uploads       GET
         "/admin/uploads/page/:page"     handlers::admin::uploads::uploads_page  GET
         "/admin/uploads/cache"          handlers::admin::uploads::cache         GET POST DELETE
+        "/admin/uploads/cache/verify"   handlers::admin::uploads::cache_verify      POST
+        "/admin/downloads"              handlers::admin::downloads::downloads   GET
+        "/admin/downloads/page/:page"   handlers::admin::downloads::downloads_page GET
+        "/admin/audit"                  handlers::admin::audit::audit           GET
+        "/admin/audit/page/:page"       handlers::admin::audit::audit_page      GET
+        "/admin/audit/export"           handlers::admin::audit::audit_export    GET
         "/admin/users"                  handlers::admin::users::users           GET
         "/admin/users/page/:page"       handlers::admin::users::users_page      GET
         "/admin/users/new"              handlers::admin::users::new             GET POST
@@ -122,6 +182,10 @@ pub fn create_app(
         "/admin/users/:id/enable"       handlers::admin::users::enable_user         POST
         "/admin/users/:id/masquerade"   handlers::admin::users::masquerade      GET
         "/admin/users/:id/username"     handlers::admin::users::check_username      POST
+        "/admin/users/:id/link"         handlers::admin::users::user_link           POST
+        "/admin/users/:id/tokens/:token/revoke" handlers::admin::users::revoke_api_token POST
+        "/admin/users/:id/totp/reset"   handlers::admin::users::reset_totp          POST
+        "/admin/users/:id/webauthn/reset" handlers::admin::users::reset_webauthn    POST
         "/admin/teams"                  handlers::admin::teams::teams           GET
         "/admin/teams/page/:page"       handlers::admin::teams::teams_page      GET
         "/admin/teams/new"              handlers::admin::teams::new             GET POST
@@ -135,11 +199,13 @@ pub fn create_app(
     Ok(routes
         .with(NormalizePath::new(TrailingSlash::Trim))
         .catch_error(errors::NotSignedInError::handle)
+        .catch_error(errors::TotpRequiredError::handle)
         .catch_error(errors::CsrfError::handle)
         .catch_error(errors::handle_404)
         .catch_all_error(errors::handle_500)
         .data(env)
         .data(preview)
+        .data(cookie_key.clone())
         .with({
             let cors = Cors::new();
             if cors_origins.is_empty() {
@@ -26,6 +26,25 @@ impl ResponseError for NotSignedInError {
     }
 }
 
+#[derive(Debug, thiserror::Error)]
+#[error("Two-factor authentication required")]
+pub struct TotpRequiredError;
+
+impl TotpRequiredError {
+    pub async fn handle(self) -> impl IntoResponse {
+        Response::builder()
+            .status(StatusCode::SEE_OTHER)
+            .header("location", "/user/settings")
+            .body("You need to set up <a href=\"/user/settings\">two-factor authentication</a>")
+    }
+}
+
+impl ResponseError for TotpRequiredError {
+    fn status(&self) -> StatusCode {
+        StatusCode::FORBIDDEN
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 #[error("CSRF detected")]
 pub struct CsrfError;
@@ -51,6 +70,42 @@ impl ResponseError for CsrfError {
     }
 }
 
+#[derive(Debug, thiserror::Error)]
+#[error("This upload would exceed the storage limit ({remaining} bytes remaining)")]
+pub struct QuotaExceededError {
+    pub remaining: i64,
+}
+
+impl ResponseError for QuotaExceededError {
+    fn status(&self) -> StatusCode {
+        StatusCode::PAYLOAD_TOO_LARGE
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("This file is larger than the maximum size of {max_size} bytes")]
+pub struct FileTooLargeError {
+    pub max_size: i64,
+}
+
+impl ResponseError for FileTooLargeError {
+    fn status(&self) -> StatusCode {
+        StatusCode::PAYLOAD_TOO_LARGE
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Files of type '{mime_type}' cannot be uploaded for this request")]
+pub struct FileTypeNotAllowedError {
+    pub mime_type: String,
+}
+
+impl ResponseError for FileTypeNotAllowedError {
+    fn status(&self) -> StatusCode {
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    }
+}
+
 pub async fn handle_404(_: NotFoundError) -> impl IntoResponse {
     match render_template("errors/404.html", default_context(TemplateEnv::default())).await {
         Ok(html) => html
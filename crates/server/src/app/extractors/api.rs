@@ -0,0 +1,294 @@
+use poem::{
+    error::InternalServerError,
+    http::StatusCode,
+    web::{
+        headers::{
+            authorization::{Basic, Bearer},
+            Authorization, HeaderMapExt,
+        },
+        RealIp,
+    },
+    FromRequest, Request, RequestBody,
+};
+use serde::Serialize;
+
+use parcel_model::{
+    api_token::{ApiToken, TokenScope},
+    login_attempt::LoginAttempt,
+    team::Team,
+    types::Key,
+    upload::{Upload, UploadPermission},
+    user::User,
+    webauthn::WebauthnCredential,
+};
+
+use crate::{
+    app::extractors::token::TokenUser,
+    env::Env,
+    notifications::record_failed_login,
+    utils::{get_client_ip, verify_totp_code},
+};
+
+/// The header in which API clients send a TOTP code for users that have 2FA enabled.
+pub const TOTP_HEADER: &str = "X-Parcel-TOTP";
+
+/// A user that has authenticated against the JSON API.
+///
+/// The API does not use the session cookie. Instead, clients either send a personal API token in
+/// an `Authorization: Bearer` header (see [`TokenUser`]), or send the username and password with
+/// each request using HTTP Basic authentication, along with the current TOTP code in the
+/// [`TOTP_HEADER`] if the user has 2FA enabled. Users whose only second factor is a security key
+/// must use an API token, as must users that are linked to the LDAP directory, whose password is
+/// only checked when they sign in. Users whose 2FA has been reset by an administrator cannot use
+/// the API at all until they have set it up again. Failed password attempts count towards the same
+/// lockout as the sign in form.
+///
+/// When a token was used, it is retained so that handlers can check its scopes. Requests using a
+/// password are not restricted.
+#[derive(Serialize)]
+#[serde(transparent)]
+pub struct ApiUser(pub User, #[serde(skip)] pub Option<ApiToken>);
+
+impl std::ops::Deref for ApiUser {
+    type Target = User;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+fn unauthorized(message: &'static str) -> poem::Error {
+    poem::Error::from_string(message, StatusCode::UNAUTHORIZED)
+}
+
+/// The error given to a user whose two-factor authentication has been reset, who cannot use the API
+/// until they have set it up again.
+pub fn totp_required() -> poem::Error {
+    poem::Error::from_string(
+        "Two-factor authentication must be set up again before using the API",
+        StatusCode::FORBIDDEN,
+    )
+}
+
+fn token_forbidden() -> poem::Error {
+    poem::Error::from_string(
+        "The API token does not allow this request",
+        StatusCode::FORBIDDEN,
+    )
+}
+
+impl ApiUser {
+    /// The team that the API token is restricted to, if any.
+    pub fn token_team(&self) -> Option<Key<Team>> {
+        self.1.as_ref().and_then(|token| token.team)
+    }
+
+    /// Make sure that the API token, if one was used, has been granted the given scope.
+    pub fn require_scope(&self, scope: TokenScope) -> poem::Result<()> {
+        match self.1 {
+            Some(ref token) if !token.has_scope(scope) => {
+                tracing::info!(%token.id, ?scope, "API token does not have the required scope");
+                Err(token_forbidden())
+            }
+
+            _ => Ok(()),
+        }
+    }
+
+    /// Make sure that the API token, if one was used, can reach the uploads of the given team, or
+    /// the user's own uploads when the team is `None`.
+    pub fn require_team(&self, team: Option<Key<Team>>) -> poem::Result<()> {
+        match self.1 {
+            Some(ref token) if !token.allows_team(team) => {
+                tracing::info!(%token.id, ?team, "API token is not valid for this owner");
+                Err(token_forbidden())
+            }
+
+            _ => Ok(()),
+        }
+    }
+
+    /// Make sure that the API token, if one was used, can transfer uploads to the given team.
+    pub fn require_transfer_to(&self, team: Key<Team>) -> poem::Result<()> {
+        match self.1 {
+            Some(ref token) if !token.allows_transfer_to(team) => {
+                tracing::info!(%token.id, %team, "API token cannot transfer uploads to this team");
+                Err(token_forbidden())
+            }
+
+            _ => Ok(()),
+        }
+    }
+
+    /// Make sure that the API token, if one was used, allows the given action on the upload.
+    pub fn require_permits(
+        &self,
+        upload: &Upload,
+        permission: &UploadPermission,
+    ) -> poem::Result<()> {
+        match self.1 {
+            Some(ref token) if !token.permits(upload, permission) => {
+                tracing::info!(%token.id, %upload.id, "API token does not permit access to upload");
+                Err(token_forbidden())
+            }
+
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<'r> FromRequest<'r> for ApiUser {
+    async fn from_request(
+        request: &'r Request,
+        request_body: &mut RequestBody,
+    ) -> poem::Result<Self> {
+        let Some(env) = request.data::<Env>() else {
+            tracing::error!("Env not found in request data - middleware misconfigured");
+            return Err(poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR));
+        };
+
+        if request
+            .headers()
+            .typed_get::<Authorization<Bearer>>()
+            .is_some()
+        {
+            let TokenUser(user, token) = TokenUser::from_request(request, request_body).await?;
+            return Ok(ApiUser(user, Some(token)));
+        }
+
+        let Some(Authorization(credentials)) =
+            request.headers().typed_get::<Authorization<Basic>>()
+        else {
+            tracing::debug!("API request without credentials");
+            return Err(unauthorized("Authentication required"));
+        };
+
+        if env.password_login_disabled() {
+            tracing::info!("API request with a password when password login is disabled");
+            return Err(unauthorized(
+                "Signing in with a password is disabled; use an API token",
+            ));
+        }
+
+        let username = credentials.username();
+        let password = credentials.password();
+        let real_ip = RealIp::from_request_without_body(request).await?;
+        let client_ip = get_client_ip(env.trust_proxy, &real_ip, request.remote_addr());
+        let client_ip_str = client_ip.map(|ip| ip.to_string());
+
+        if LoginAttempt::is_locked_out(&env.pool, username)
+            .await
+            .map_err(|err| {
+                tracing::error!(?err, %username, "Failed to check lockout status");
+                InternalServerError(err)
+            })?
+        {
+            return Err(poem::Error::from_string(
+                "Too many failed attempts. Please try again in a few minutes.",
+                StatusCode::TOO_MANY_REQUESTS,
+            ));
+        }
+
+        let user = User::get_by_username(&env.pool, username)
+            .await
+            .map_err(|err| {
+                tracing::error!(?username, ?err, "Failed to get user by username");
+                InternalServerError(err)
+            })?;
+
+        let mut user = match user {
+            // Users that are linked to the LDAP directory do not have a password of their own.
+            Some(user) if user.ldap_dn.is_none() && user.verify_password(password) => user,
+            _ => {
+                tracing::info!(?username, "Invalid API credentials");
+                record_failed_login(env, username, client_ip_str.as_deref()).await;
+                return Err(unauthorized("Invalid username or password"));
+            }
+        };
+
+        if user.password.needs_migrating() {
+            tracing::info!(%user.id, ?username, "Migrating password hash");
+            user.set_password(&env.pool, password).await?;
+        }
+
+        if !user.enabled {
+            tracing::info!(?username, "User is disabled");
+            return Err(poem::Error::from_string(
+                "Your account is disabled",
+                StatusCode::FORBIDDEN,
+            ));
+        }
+
+        if user.totp_required && user.totp.is_none() {
+            tracing::info!(%user.id, ?username, "API request from user who must set up 2FA");
+            return Err(totp_required());
+        }
+
+        if let Some(ref secret) = user.totp {
+            let Some(code) = request.header(TOTP_HEADER) else {
+                tracing::info!(%user.id, ?username, "API request requires TOTP code");
+                return Err(unauthorized("A TOTP code is required"));
+            };
+
+            if !verify_totp_code(secret, code) {
+                tracing::info!(%user.id, ?username, "Invalid TOTP code in API request");
+                record_failed_login(env, username, client_ip_str.as_deref()).await;
+                return Err(unauthorized("The TOTP code was incorrect"));
+            }
+        } else if env.passkeys.is_some()
+            && WebauthnCredential::exists_for_user(&env.pool, user.id)
+                .await
+                .map_err(|err| {
+                    tracing::error!(%user.id, ?err, "Failed to check for WebAuthn credentials");
+                    InternalServerError(err)
+                })?
+        {
+            // A security key cannot be used with Basic authentication, so these users need a token.
+            tracing::info!(%user.id, ?username, "API request from user with only WebAuthn");
+            return Err(unauthorized("An API token is required for this account"));
+        }
+
+        user.record_last_access(&env.pool).await.map_err(|err| {
+            tracing::error!("Failed to update last access for user {}: {err}", user.id);
+            InternalServerError(err)
+        })?;
+
+        Ok(ApiUser(user, None))
+    }
+}
+
+/// A user that has authenticated against the JSON API and is an administrator.
+///
+/// When an API token is used, it must have the read scope.
+pub struct ApiAdmin(pub User);
+
+impl std::ops::Deref for ApiAdmin {
+    type Target = User;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<'r> FromRequest<'r> for ApiAdmin {
+    async fn from_request(
+        request: &'r Request,
+        request_body: &mut RequestBody,
+    ) -> poem::Result<Self> {
+        let api_user = ApiUser::from_request(request, request_body).await?;
+        api_user.require_scope(TokenScope::Read)?;
+
+        let ApiUser(user, _) = api_user;
+        if user.admin {
+            Ok(ApiAdmin(user))
+        } else {
+            tracing::warn!(
+                "Non-admin user {:?} ({}) attempted to access admin-only API",
+                user.username,
+                user.id
+            );
+
+            Err(poem::Error::from_status(StatusCode::FORBIDDEN))
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use poem::{http::StatusCode, session::Session, web::RealIp, FromRequest, Request, RequestBody};
+
+use parcel_model::{
+    audit::{AuditAction, AuditEvent, AuditTarget},
+    types::Key,
+    user::User,
+};
+
+use crate::{env::Env, utils::get_client_ip};
+
+/// Records events in the audit log for the current request.
+///
+/// When an administrator is masquerading as another user, the session holds a stack of the users
+/// that the masquerade was started from. The first of these is recorded as the actor, so that the
+/// audit log shows who actually performed the action.
+pub struct Auditor {
+    masquerader: Option<Key<User>>,
+    remote_addr: Option<String>,
+}
+
+impl<'r> FromRequest<'r> for Auditor {
+    async fn from_request(
+        request: &'r Request,
+        request_body: &mut RequestBody,
+    ) -> poem::Result<Self> {
+        let Some(env) = request.data::<Env>() else {
+            tracing::error!("Env not found in request data - middleware misconfigured");
+            return Err(poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR));
+        };
+
+        let session = <&Session>::from_request(request, request_body).await?;
+        let masquerader = session
+            .get::<Vec<Key<User>>>("masquerade_stack")
+            .and_then(|stack| stack.first().copied());
+
+        let real_ip = RealIp::from_request(request, request_body).await?;
+        let remote_addr = get_client_ip(env.trust_proxy, &real_ip, request.remote_addr());
+
+        Ok(Self {
+            masquerader,
+            remote_addr: remote_addr.map(|addr| addr.to_string()),
+        })
+    }
+}
+
+impl Auditor {
+    /// Record an action performed by the given user.
+    ///
+    /// Failing to record an event does not fail the request, as the action has already been
+    /// performed by the time it is recorded.
+    pub async fn record(
+        &self,
+        env: &Env,
+        user: &User,
+        action: AuditAction,
+        target: AuditTarget,
+        details: Option<serde_json::Value>,
+    ) {
+        let mut event = AuditEvent::new(
+            Some(self.masquerader.unwrap_or(user.id)),
+            Some(user.id),
+            action,
+            target,
+        );
+
+        event.details = details.map(|details| details.to_string());
+        event.remote_addr = self.remote_addr.clone();
+
+        tracing::info!(
+            actor = ?event.actor,
+            user = %user.id,
+            ?action,
+            ?target,
+            "Recording audit event"
+        );
+
+        if let Err(err) = event.create(&env.pool).await {
+            tracing::error!(?err, ?action, ?target, "Failed to record audit event");
+        }
+    }
+}
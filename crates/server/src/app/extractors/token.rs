@@ -0,0 +1,102 @@
+use poem::{
+    error::InternalServerError,
+    http::StatusCode,
+    web::headers::{authorization::Bearer, Authorization, HeaderMapExt},
+    FromRequest, Request, RequestBody,
+};
+
+use parcel_model::{api_token::ApiToken, user::User};
+
+use crate::{app::extractors::api::totp_required, env::Env};
+
+/// A user that has authenticated with a personal API token.
+///
+/// The token is sent in an `Authorization: Bearer` header. Tokens that have been revoked or have
+/// passed their expiry date are rejected, as are tokens belonging to a disabled user or to a user
+/// who must set up two-factor authentication again. The scopes of the token are not checked here:
+/// that is left to the handlers.
+pub struct TokenUser(pub User, pub ApiToken);
+
+impl std::ops::Deref for TokenUser {
+    type Target = User;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+fn unauthorized(message: &'static str) -> poem::Error {
+    poem::Error::from_string(message, StatusCode::UNAUTHORIZED)
+}
+
+impl<'r> FromRequest<'r> for TokenUser {
+    async fn from_request(request: &'r Request, _: &mut RequestBody) -> poem::Result<Self> {
+        let Some(env) = request.data::<Env>() else {
+            tracing::error!("Env not found in request data - middleware misconfigured");
+            return Err(poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR));
+        };
+
+        let Some(Authorization(bearer)) = request.headers().typed_get::<Authorization<Bearer>>()
+        else {
+            tracing::debug!("Request without a bearer token");
+            return Err(unauthorized("Authentication required"));
+        };
+
+        let Some(mut token) = ApiToken::get_by_token(&env.pool, bearer.token())
+            .await
+            .map_err(|err| {
+                tracing::error!(?err, "Failed to get API token");
+                InternalServerError(err)
+            })?
+        else {
+            tracing::info!("Unrecognized API token");
+            return Err(unauthorized("Invalid API token"));
+        };
+
+        if token.revoked_at.is_some() {
+            tracing::info!(%token.id, %token.user, "API token has been revoked");
+            return Err(unauthorized("The API token has been revoked"));
+        }
+
+        if token.is_expired() {
+            tracing::info!(%token.id, %token.user, "API token has expired");
+            return Err(unauthorized("The API token has expired"));
+        }
+
+        let Some(mut user) = User::get(&env.pool, token.user).await.map_err(|err| {
+            tracing::error!(?err, %token.user, "Failed to get user for API token");
+            InternalServerError(err)
+        })?
+        else {
+            tracing::error!(%token.id, %token.user, "User for API token not found");
+            return Err(unauthorized("Invalid API token"));
+        };
+
+        if !user.enabled {
+            tracing::info!(%user.id, username = ?user.username, "User is disabled");
+            return Err(poem::Error::from_string(
+                "Your account is disabled",
+                StatusCode::FORBIDDEN,
+            ));
+        }
+
+        // The tokens of a user whose two-factor authentication has been reset are not used until
+        // they have set it up again, as the reset may be because their account was compromised.
+        if user.totp_required && user.totp.is_none() {
+            tracing::info!(%user.id, username = ?user.username, "User must set up 2FA");
+            return Err(totp_required());
+        }
+
+        token.record_last_used(&env.pool).await.map_err(|err| {
+            tracing::error!(?err, %token.id, "Failed to update last use of API token");
+            InternalServerError(err)
+        })?;
+
+        user.record_last_access(&env.pool).await.map_err(|err| {
+            tracing::error!("Failed to update last access for user {}: {err}", user.id);
+            InternalServerError(err)
+        })?;
+
+        Ok(TokenUser(user, token))
+    }
+}
@@ -3,7 +3,7 @@ use serde::Serialize;
 
 use parcel_model::{types::Key, user::User};
 
-use crate::app::errors::NotSignedInError;
+use crate::app::errors::{NotSignedInError, TotpRequiredError};
 
 #[derive(Serialize)]
 #[serde(transparent)]
@@ -55,6 +55,16 @@ impl<'r> FromRequest<'r> for SessionUser {
             return Err(NotSignedInError.into());
         }
 
+        // A user whose two-factor authentication has been reset can only reach their settings,
+        // where they can set it up again.
+        if user.totp_required
+            && user.totp.is_none()
+            && !request.uri().path().starts_with("/user/settings")
+        {
+            tracing::info!("User {:?} ({user_id}) must set up 2FA", user.username);
+            return Err(TotpRequiredError.into());
+        }
+
         // As the user is valid, we can set a 'last seen' variable in the session. This will
         // have the effect of updating the cookie we send to the user, which will keep the
         // session alive.
@@ -15,6 +15,8 @@ use crate::{
     env::Env,
 };
 
+pub mod audit;
+pub mod downloads;
 pub mod setup;
 pub mod teams;
 pub mod uploads;
@@ -0,0 +1,193 @@
+use minijinja::context;
+use poem::{
+    error::InternalServerError,
+    handler,
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    web::{Data, Html, Path, Query},
+    IntoResponse, Response,
+};
+use serde::{Deserialize, Serialize};
+use time::{macros::format_description, Date};
+
+use parcel_model::audit::{AuditAction, AuditEventList, AuditFilter};
+
+use crate::{
+    app::{
+        extractors::admin::SessionAdmin,
+        templates::{authorized_context, render_template},
+    },
+    env::Env,
+};
+
+/// The number of events shown in each page of the audit log.
+const PAGE_SIZE: u32 = 50;
+
+/// The number of events fetched at a time when exporting the audit log.
+const EXPORT_BATCH_SIZE: u32 = 1000;
+
+/// The filter for the audit log, as given in the query string.
+///
+/// The fields are all strings, as the filter form sends empty strings for the fields that are not
+/// filled in, and these are ignored.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AuditQuery {
+    #[serde(default)]
+    action: String,
+    #[serde(default)]
+    user: String,
+    #[serde(default)]
+    target: String,
+    #[serde(default)]
+    from: String,
+    #[serde(default)]
+    to: String,
+}
+
+impl AuditQuery {
+    fn filter(&self) -> AuditFilter {
+        let format = format_description!("[year]-[month]-[day]");
+        let parse_date = |value: &str| Date::parse(value.trim(), &format).ok();
+
+        AuditFilter {
+            action: AuditAction::ALL
+                .iter()
+                .find(|action| action.as_str() == self.action)
+                .copied(),
+            user: Some(self.user.trim().to_string()).filter(|user| !user.is_empty()),
+            target: Some(self.target.trim().to_string()).filter(|target| !target.is_empty()),
+            from: parse_date(&self.from),
+            to: parse_date(&self.to),
+        }
+    }
+}
+
+#[handler]
+pub async fn get_audit(
+    env: Data<&Env>,
+    SessionAdmin(admin): SessionAdmin,
+    Query(query): Query<AuditQuery>,
+) -> poem::Result<Html<String>> {
+    let events = AuditEventList::get(&env.pool, &query.filter(), 0, PAGE_SIZE)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "Failed to get audit events");
+            InternalServerError(err)
+        })?;
+
+    let actions = AuditAction::ALL
+        .iter()
+        .map(|action| action.as_str())
+        .collect::<Vec<_>>();
+
+    render_template(
+        "admin/audit.html",
+        context! {
+            events,
+            actions,
+            query,
+            page => 0,
+            ..authorized_context(&env, &admin)
+        },
+    )
+    .await
+}
+
+#[handler]
+pub async fn get_audit_page(
+    env: Data<&Env>,
+    SessionAdmin(admin): SessionAdmin,
+    Path(page): Path<u32>,
+    Query(query): Query<AuditQuery>,
+) -> poem::Result<Html<String>> {
+    let events = AuditEventList::get(&env.pool, &query.filter(), page * PAGE_SIZE, PAGE_SIZE)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, page, "Failed to get page of audit events");
+            InternalServerError(err)
+        })?;
+
+    render_template(
+        "admin/audit/page.html",
+        context! {
+            events,
+            query,
+            page,
+            ..authorized_context(&env, &admin)
+        },
+    )
+    .await
+}
+
+/// Quote a field for a CSV file, if it needs to be.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[handler]
+pub async fn get_audit_export(
+    env: Data<&Env>,
+    SessionAdmin(admin): SessionAdmin,
+    Query(query): Query<AuditQuery>,
+) -> poem::Result<Response> {
+    let filter = query.filter();
+    let mut csv = String::from(
+        "time,action,actor_id,actor,user_id,user,target_kind,target_id,remote_addr,details\r\n",
+    );
+
+    let mut offset = 0;
+    loop {
+        let events = AuditEventList::get(&env.pool, &filter, offset, EXPORT_BATCH_SIZE)
+            .await
+            .map_err(|err| {
+                tracing::error!(?err, offset, "Failed to get audit events for export");
+                InternalServerError(err)
+            })?;
+
+        for event in &events {
+            let fields = [
+                event.created_at.to_string(),
+                event.action.as_str().to_string(),
+                event.actor.map(|id| id.to_string()).unwrap_or_default(),
+                event.actor_name.clone().unwrap_or_default(),
+                event
+                    .effective_user
+                    .map(|id| id.to_string())
+                    .unwrap_or_default(),
+                event.effective_user_name.clone().unwrap_or_default(),
+                event.target_kind.clone().unwrap_or_default(),
+                event.target_id.clone().unwrap_or_default(),
+                event.remote_addr.clone().unwrap_or_default(),
+                event.details.clone().unwrap_or_default(),
+            ];
+
+            let row = fields
+                .iter()
+                .map(|field| csv_field(field))
+                .collect::<Vec<_>>()
+                .join(",");
+
+            csv.push_str(&row);
+            csv.push_str("\r\n");
+        }
+
+        if (events.len() as u32) < EXPORT_BATCH_SIZE {
+            break;
+        }
+
+        offset += EXPORT_BATCH_SIZE;
+    }
+
+    tracing::info!(%admin.id, ?filter, "Exported audit log");
+
+    Ok(csv
+        .with_header(CONTENT_TYPE, "text/csv; charset=utf-8")
+        .with_header(
+            CONTENT_DISPOSITION,
+            "attachment; filename=\"parcel-audit.csv\"",
+        )
+        .into_response())
+}
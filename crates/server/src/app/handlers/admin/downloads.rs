@@ -0,0 +1,84 @@
+use minijinja::context;
+use poem::{
+    error::InternalServerError,
+    handler,
+    web::{Data, Html, Path},
+};
+use time::{Duration, OffsetDateTime};
+
+use parcel_model::download::{DownloadList, DownloadScope, DownloadTop};
+
+use crate::{
+    app::{
+        extractors::admin::SessionAdmin,
+        handlers::utils::get_download_activity,
+        templates::{authorized_context, render_template},
+    },
+    env::Env,
+};
+
+/// The number of downloads shown in each page of the recent downloads.
+const PAGE_SIZE: u32 = 50;
+
+#[handler]
+pub async fn get_downloads(
+    env: Data<&Env>,
+    SessionAdmin(admin): SessionAdmin,
+) -> poem::Result<Html<String>> {
+    let activity = get_download_activity(&env, DownloadScope::All).await?;
+
+    let since = OffsetDateTime::now_utc() - Duration::days(30);
+    let top = DownloadTop::get(&env.pool, DownloadScope::All, since, 10)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "Failed to get most downloaded uploads");
+            InternalServerError(err)
+        })?;
+
+    let downloads = DownloadList::get(&env.pool, DownloadScope::All, 0, PAGE_SIZE)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "Failed to get recent downloads");
+            InternalServerError(err)
+        })?;
+
+    render_template(
+        "admin/downloads.html",
+        context! {
+            activity,
+            top,
+            downloads,
+            page => 0,
+            page_url => "/admin/downloads/page",
+            show_upload => true,
+            ..authorized_context(&env, &admin)
+        },
+    )
+    .await
+}
+
+#[handler]
+pub async fn get_downloads_page(
+    env: Data<&Env>,
+    SessionAdmin(admin): SessionAdmin,
+    Path(page): Path<u32>,
+) -> poem::Result<Html<String>> {
+    let downloads = DownloadList::get(&env.pool, DownloadScope::All, page * PAGE_SIZE, PAGE_SIZE)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, page, "Failed to get page of recent downloads");
+            InternalServerError(err)
+        })?;
+
+    render_template(
+        "downloads/page.html",
+        context! {
+            downloads,
+            page,
+            page_url => "/admin/downloads/page",
+            show_upload => true,
+            ..authorized_context(&env, &admin)
+        },
+    )
+    .await
+}
@@ -129,6 +129,7 @@ pub async fn post_setup(
         name,
         password: StoredPassword::new(&password)?,
         totp: None,
+        totp_required: false,
         enabled: true,
         admin: true,
         limit: None,
@@ -137,6 +138,15 @@ pub async fn post_setup(
         last_access: Some(now),
         default_order: UploadOrder::UploadedAt,
         default_asc: false,
+        email: None,
+        email_verified: false,
+        notify_downloads: true,
+        notify_expiring: true,
+        notify_requests: true,
+        notify_lockouts: true,
+        oidc_subject: None,
+        oidc_linked: false,
+        ldap_dn: None,
     };
 
     admin.create(&env.pool).await.map_err(|err| {
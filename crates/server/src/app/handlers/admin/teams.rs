@@ -14,6 +14,7 @@ use parcel_model::{
     team::{Team, TeamList},
     types::Key,
     upload::Upload,
+    upload_session::UploadSession,
     user::User,
 };
 
@@ -21,7 +22,7 @@ use crate::{
     app::{
         errors::CsrfError,
         extractors::admin::SessionAdmin,
-        handlers::utils::delete_upload_cache_by_slug,
+        handlers::utils::{delete_upload_session_file, release_blob},
         templates::{authorized_context, render_template},
     },
     env::Env,
@@ -477,15 +478,26 @@ pub async fn delete_team(
         return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
     };
 
-    let upload_slugs = Upload::delete_for_team(&env.pool, team_id)
+    let upload_blobs = Upload::delete_for_team(&env.pool, team_id)
         .await
         .map_err(|err| {
             tracing::error!(?err, %team_id, "Failed to delete team uploads");
             InternalServerError(err)
         })?;
 
-    for slug in upload_slugs {
-        delete_upload_cache_by_slug(&env, &slug).await;
+    for blob in upload_blobs {
+        release_blob(&env, &blob).await;
+    }
+
+    let session_ids = UploadSession::delete_for_team(&env.pool, team_id)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %team_id, "Failed to delete team upload sessions");
+            InternalServerError(err)
+        })?;
+
+    for session_id in session_ids {
+        delete_upload_session_file(&env, session_id).await;
     }
 
     team.delete(&env.pool).await.map_err(|err| {
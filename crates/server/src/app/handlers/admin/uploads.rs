@@ -8,7 +8,12 @@ use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use time::{Date, OffsetDateTime};
 
-use parcel_model::{types::Key, upload::Upload, user::User};
+use parcel_model::{
+    blob::{Blob, BlobHash, BlobStats},
+    types::Key,
+    upload::Upload,
+    user::User,
+};
 
 use crate::{
     app::{
@@ -17,6 +22,7 @@ use crate::{
         templates::{authorized_context, render_template},
     },
     env::Env,
+    storage::{HashReader, StoredFile},
 };
 
 #[derive(FromRow, Serialize)]
@@ -127,9 +133,9 @@ pub async fn get_cache(
 
 trait WithCacheFiles: Default {
     #[allow(clippy::result_large_err)]
-    fn valid_cache_file(&mut self, entry: std::fs::DirEntry) -> poem::Result<()>;
+    async fn valid_cache_file(&mut self, env: &Env, file: StoredFile) -> poem::Result<()>;
     #[allow(clippy::result_large_err)]
-    fn invalid_cache_file(&mut self, entry: std::fs::DirEntry) -> poem::Result<()>;
+    async fn invalid_cache_file(&mut self, env: &Env, file: StoredFile) -> poem::Result<()>;
 }
 
 #[derive(Debug, Default, Serialize)]
@@ -145,28 +151,14 @@ struct CacheFilesSummary {
 }
 
 impl WithCacheFiles for CacheFilesSummary {
-    fn valid_cache_file(&mut self, entry: std::fs::DirEntry) -> poem::Result<()> {
-        self.valid_total += entry
-            .metadata()
-            .map_err(|err| {
-                tracing::error!(err = ?err, "Failed to read cache directory entry metadata");
-                InternalServerError(err)
-            })?
-            .len();
-
+    async fn valid_cache_file(&mut self, _env: &Env, file: StoredFile) -> poem::Result<()> {
+        self.valid_total += file.size;
         self.valid_count += 1;
         Ok(())
     }
 
-    fn invalid_cache_file(&mut self, entry: std::fs::DirEntry) -> poem::Result<()> {
-        self.invalid_total += entry
-            .metadata()
-            .map_err(|err| {
-                tracing::error!(err = ?err, "Failed to read cache directory entry metadata");
-                InternalServerError(err)
-            })?
-            .len();
-
+    async fn invalid_cache_file(&mut self, _env: &Env, file: StoredFile) -> poem::Result<()> {
+        self.invalid_total += file.size;
         self.invalid_count += 1;
         Ok(())
     }
@@ -181,24 +173,17 @@ struct CacheFilesCleanup {
 }
 
 impl WithCacheFiles for CacheFilesCleanup {
-    fn valid_cache_file(&mut self, _entry: std::fs::DirEntry) -> poem::Result<()> {
+    async fn valid_cache_file(&mut self, _env: &Env, _file: StoredFile) -> poem::Result<()> {
         Ok(())
     }
 
-    fn invalid_cache_file(&mut self, entry: std::fs::DirEntry) -> poem::Result<()> {
-        self.removed_total += entry
-            .metadata()
-            .map_err(|err| {
-                tracing::error!(err = ?err, "Failed to read cache directory entry metadata");
-                InternalServerError(err)
-            })?
-            .len();
-
-        std::fs::remove_file(entry.path()).map_err(|err| {
-            tracing::error!(err = ?err, "Failed to remove cache file");
+    async fn invalid_cache_file(&mut self, env: &Env, file: StoredFile) -> poem::Result<()> {
+        env.storage.delete(&file.key).await.map_err(|err| {
+            tracing::error!(err = ?err, key = ?file.key, "Failed to remove cache file");
             InternalServerError(err)
         })?;
 
+        self.removed_total += file.size;
         self.removed_count += 1;
         Ok(())
     }
@@ -210,50 +195,41 @@ where
 {
     let mut result = T::default();
 
-    let dir = std::fs::read_dir(&env.cache_dir).map_err(|err| {
-        tracing::error!(err = ?err, dir = ?env.cache_dir, "Failed to read cache directory");
+    let files = env.storage.list().await.map_err(|err| {
+        tracing::error!(err = ?err, "Failed to list files in storage");
         InternalServerError(err)
     })?;
 
-    // First pass: collect all entries and their filenames
-    let mut entries: Vec<(std::fs::DirEntry, String)> = Vec::new();
-    let mut slugs: Vec<String> = Vec::new();
-
-    for entry in dir {
-        let entry = entry.map_err(|err| {
-            tracing::error!(err = ?err, "Failed to read cache directory entry");
-            InternalServerError(err)
-        })?;
-
-        let filename = entry.file_name().into_string().map_err(|filename| {
-            tracing::error!(filename = ?filename, "Failed to convert filename to string");
-            poem::Error::from_status(poem::http::StatusCode::INTERNAL_SERVER_ERROR)
-        })?;
+    // First pass: collect all files and their blob keys
+    let mut entries: Vec<(StoredFile, String)> = Vec::new();
+    let mut keys: Vec<String> = Vec::new();
 
-        // Extract base slug (remove .preview suffix if present)
-        let slug = filename
+    for file in files {
+        // Extract base blob key (remove .preview suffix if present)
+        let key = file
+            .key
             .strip_suffix(".preview")
-            .unwrap_or(&filename)
+            .unwrap_or(&file.key)
             .to_string();
 
-        slugs.push(slug.clone());
-        entries.push((entry, slug));
+        keys.push(key.clone());
+        entries.push((file, key));
     }
 
-    // Batch fetch all existing slugs in a single query
-    let existing_slugs = Upload::get_existing_slugs(&env.pool, &slugs)
+    // Batch fetch all existing blob keys in a single query
+    let existing_keys = Blob::get_existing_keys(&env.pool, &keys)
         .await
         .map_err(|err| {
-            tracing::error!(err = ?err, "Failed to fetch existing upload slugs");
+            tracing::error!(err = ?err, "Failed to fetch existing blob keys");
             InternalServerError(err)
         })?;
 
-    // Second pass: categorize entries based on HashSet membership
-    for (entry, slug) in entries {
-        if existing_slugs.contains(&slug) {
-            result.valid_cache_file(entry)?;
+    // Second pass: categorize files based on HashSet membership
+    for (file, key) in entries {
+        if existing_keys.contains(&key) {
+            result.valid_cache_file(env, file).await?;
         } else {
-            result.invalid_cache_file(entry)?;
+            result.invalid_cache_file(env, file).await?;
         }
     }
 
@@ -279,10 +255,16 @@ pub async fn post_cache(
     }
 
     let summary = find_cache_files::<CacheFilesSummary>(*env).await?;
+    let dedup = BlobStats::get(&env.pool).await.map_err(|err| {
+        tracing::error!(err = ?err, "Failed to get blob statistics");
+        InternalServerError(err)
+    })?;
+
     render_template(
         "admin/uploads/cache.html",
         context! {
             summary,
+            dedup,
             csrf_token => next_token.0,
             ..authorized_context(&env, &admin)
         },
@@ -314,3 +296,90 @@ pub async fn delete_cache(
     )
     .await
 }
+
+#[derive(Debug, Default, Serialize)]
+struct CacheVerification {
+    #[serde(rename = "verifiedCount")]
+    verified_count: u64,
+    #[serde(rename = "recordedCount")]
+    recorded_count: u64,
+    missing: Vec<String>,
+    corrupt: Vec<String>,
+}
+
+/// Compute the hash of a file in storage, returning `None` if the file is missing.
+async fn hash_stored_file(env: &Env, key: &str) -> std::io::Result<Option<String>> {
+    let reader = match env.storage.read(key).await {
+        Ok(reader) => reader,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let mut reader = HashReader::new(reader);
+    tokio::io::copy(&mut reader, &mut tokio::io::sink()).await?;
+    Ok(Some(reader.finish()))
+}
+
+#[handler]
+pub async fn post_cache_verify(
+    env: Data<&Env>,
+    SessionAdmin(admin): SessionAdmin,
+    next_token: &CsrfToken,
+    csrf_verifier: &CsrfVerifier,
+    Form(CacheParams { csrf_token }): Form<CacheParams>,
+) -> poem::Result<Html<String>> {
+    if !csrf_verifier.is_valid(&csrf_token) {
+        tracing::error!("CSRF token is invalid in cache management");
+        return Err(CsrfError.into());
+    }
+
+    let blobs = BlobHash::get_all(&env.pool).await.map_err(|err| {
+        tracing::error!(err = ?err, "Failed to get blob hashes");
+        InternalServerError(err)
+    })?;
+
+    let mut verification = CacheVerification::default();
+    for blob in blobs {
+        let Some(hash) = hash_stored_file(&env, &blob.key).await.map_err(|err| {
+            tracing::error!(err = ?err, key = ?blob.key, "Failed to read cache file");
+            InternalServerError(err)
+        })?
+        else {
+            tracing::warn!(key = ?blob.key, "Cache file is missing");
+            verification.missing.push(blob.key);
+            continue;
+        };
+
+        match blob.hash {
+            Some(expected) if expected == hash => verification.verified_count += 1,
+
+            Some(expected) => {
+                tracing::warn!(key = ?blob.key, ?expected, ?hash, "Cache file is corrupt");
+                verification.corrupt.push(blob.key);
+            }
+
+            // The hash of files for older uploads is not known, so we record it for their uploads,
+            // against which the file is verified in future.
+            None => {
+                Upload::set_hash_for_blob(&env.pool, &blob.key, &hash)
+                    .await
+                    .map_err(|err| {
+                        tracing::error!(err = ?err, key = ?blob.key, "Failed to record upload hash");
+                        InternalServerError(err)
+                    })?;
+
+                verification.recorded_count += 1;
+            }
+        }
+    }
+
+    render_template(
+        "admin/uploads/cache.html",
+        context! {
+            verification,
+            csrf_token => next_token.0,
+            ..authorized_context(&env, &admin)
+        },
+    )
+    .await
+}
@@ -10,32 +10,40 @@ use poem::{
     IntoResponse, Response,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use time::OffsetDateTime;
-use validator::{Validate, ValidationError, ValidationErrors};
+use validator::{Validate, ValidateEmail, ValidationError, ValidationErrors};
 
 use parcel_model::{
+    api_token::{ApiToken, ApiTokenList},
+    audit::{AuditAction, AuditTarget},
     password::StoredPassword,
     team::{Team, TeamMember, TeamSelect},
     types::Key,
     upload::{Upload, UploadOrder},
+    upload_session::UploadSession,
     user::{User, UserList},
+    user_token::{UserToken, UserTokenKind},
+    webauthn::WebauthnCredential,
 };
 
 use crate::{
     app::{
         errors::CsrfError,
-        extractors::admin::SessionAdmin,
-        handlers::utils::delete_upload_cache_by_slug,
+        extractors::{admin::SessionAdmin, audit::Auditor},
+        handlers::utils::{delete_upload_session_file, release_blob},
         templates::{authorized_context, render_template},
     },
     env::Env,
-    utils::{SessionExt, SizeUnit, ValidationErrorsExt},
+    notifications::send_user_link,
+    utils::{unusable_password, SessionExt, SizeUnit, ValidationErrorsExt},
 };
 
 #[handler]
 pub async fn get_users(
     env: Data<&Env>,
     csrf_token: &CsrfToken,
+    session: &Session,
     SessionAdmin(admin): SessionAdmin,
 ) -> poem::Result<Response> {
     let users = UserList::get_with_pagination(&env.pool, 0, 50).await.map_err(|err| {
@@ -48,6 +56,7 @@ pub async fn get_users(
         context! {
             users,
             csrf_token => csrf_token.0,
+            password_link => session.take::<PasswordLink>("password_link"),
             page => 0,
             ..authorized_context(&env, &admin)
         },
@@ -95,6 +104,7 @@ pub async fn get_new(
         context! {
             token => token.0,
             teams,
+            password_login => !env.password_login_disabled(),
             ..authorized_context(&env, &admin)
         },
     )
@@ -108,8 +118,9 @@ pub struct NewUserForm {
     username: String,
     #[validate(length(min = 3, max = 100))]
     name: String,
-    #[validate(length(min = 8))]
-    password: String,
+    password: Option<String>,
+    email: String,
+    invite: Option<String>,
     admin: Option<String>,
     enabled: Option<String>,
     limit: Option<i64>,
@@ -137,6 +148,8 @@ impl From<TeamMember> for TeamPermissionStruct {
 #[handler]
 pub async fn post_new(
     env: Data<&Env>,
+    session: &Session,
+    auditor: Auditor,
     next_token: &CsrfToken,
     verifier: &CsrfVerifier,
     SessionAdmin(auth): SessionAdmin,
@@ -157,6 +170,25 @@ pub async fn post_new(
         errors.add("username", slug_error);
     }
 
+    // An invited user chooses their own password, so the administrator does not need to give one.
+    let invite = form.invite.as_deref() == Some("on") && !env.password_login_disabled();
+    let password = form.password.as_deref().unwrap_or_default();
+    if !invite && password.chars().count() < 8 {
+        errors.add(
+            "password",
+            ValidationError::new("length")
+                .with_message("Passwords must be at least eight characters in length".into()),
+        );
+    }
+
+    let email = Some(form.email.trim()).filter(|email| !email.is_empty());
+    if email.is_some_and(|email| !email.validate_email()) {
+        errors.add(
+            "email",
+            ValidationError::new("email").with_message("The email address is not valid".into()),
+        );
+    }
+
     if Team::slug_exists(&env.pool, None, &form.username)
         .await
         .map_err(|err| {
@@ -197,10 +229,13 @@ pub async fn post_new(
                 errors,
                 teams,
                 token => next_token.0,
+                password_login => !env.password_login_disabled(),
                 form => context! {
                     username => &form.username,
                     name => &form.name,
                     password => &form.password,
+                    email => &form.email,
+                    invite,
                     admin => form.admin.as_deref() == Some("on"),
                     enabled => form.enabled.as_deref() == Some("on"),
                     limit => form.limit,
@@ -216,6 +251,7 @@ pub async fn post_new(
         .into_response());
     }
 
+    let email = email.map(str::to_string);
     let NewUserForm {
         username,
         name,
@@ -239,12 +275,18 @@ pub async fn post_new(
 
     let limit = limit.and_then(|limit| limit_unit.map(|unit| limit * unit.to_bytes()));
 
+    let password = match password {
+        Some(password) if !invite => password,
+        _ => unusable_password(),
+    };
+
     let user = User {
         id: Key::new(),
         username,
         name,
         password: StoredPassword::new(&password)?,
         totp: None,
+        totp_required: false,
         enabled,
         admin,
         limit,
@@ -253,6 +295,16 @@ pub async fn post_new(
         last_access: None,
         default_order: UploadOrder::UploadedAt,
         default_asc: false,
+        // An address given by an administrator is trusted to belong to the user.
+        email_verified: email.is_some(),
+        email,
+        notify_downloads: true,
+        notify_expiring: true,
+        notify_requests: true,
+        notify_lockouts: true,
+        oidc_subject: None,
+        oidc_linked: false,
+        ldap_dn: None,
     };
 
     user.create(&env.pool).await.map_err(|err| {
@@ -278,9 +330,130 @@ pub async fn post_new(
             })?;
     }
 
+    if invite {
+        let password_link = create_password_link(&env, &auditor, &user, &auth).await?;
+        session.set("password_link", password_link);
+    }
+
     Ok(Redirect::see_other("/admin/users").into_response())
 }
 
+/// A link that lets a user choose a password, which is shown to the administrator that created it
+/// so that they can pass it on if it was not sent by email.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordLink {
+    username: String,
+    kind: UserTokenKind,
+    url: String,
+    emailed: bool,
+}
+
+/// Create a link for a user to choose a password, sending it to them if possible.
+///
+/// Users that have never signed in are sent a new invitation, and other users a password reset.
+async fn create_password_link(
+    env: &Env,
+    auditor: &Auditor,
+    user: &User,
+    admin: &User,
+) -> poem::Result<PasswordLink> {
+    let kind = if user.last_access.is_none() {
+        UserTokenKind::Invitation
+    } else {
+        UserTokenKind::PasswordReset
+    };
+
+    let (user_token, token) = UserToken::new(user.id, kind, Some(admin.id));
+    user_token.create(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, user_id = %user.id, ?kind, "Failed to create user token");
+        InternalServerError(err)
+    })?;
+
+    let url = match kind {
+        UserTokenKind::Invitation => format!("/user/invitation/{token}"),
+        UserTokenKind::PasswordReset => format!("/user/password/reset/{token}"),
+    };
+
+    let emailed = send_user_link(env, user, kind, &url);
+    tracing::info!(
+        admin_id = %admin.id, user_id = %user.id, ?kind, emailed,
+        "Administrator created a link for user to choose a password"
+    );
+
+    auditor
+        .record(
+            env,
+            admin,
+            AuditAction::PasswordLink,
+            AuditTarget::User(user.id),
+            Some(json!({ "kind": kind, "emailed": emailed })),
+        )
+        .await;
+
+    Ok(PasswordLink {
+        username: user.username.clone(),
+        kind,
+        url,
+        emailed,
+    })
+}
+
+/// Check if an administrator can create a link for a user to choose a password.
+///
+/// Users that are linked to the LDAP directory change their password there.
+fn allow_password_link(env: &Env, user: &User) -> bool {
+    user.ldap_dn.is_none() && !env.password_login_disabled()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordLinkForm {
+    token: String,
+}
+
+#[handler]
+pub async fn post_user_link(
+    env: Data<&Env>,
+    SessionAdmin(admin): SessionAdmin,
+    auditor: Auditor,
+    next_token: &CsrfToken,
+    verifier: &CsrfVerifier,
+    Path(user_id): Path<Key<User>>,
+    Form(PasswordLinkForm { token }): Form<PasswordLinkForm>,
+) -> poem::Result<Html<String>> {
+    if !verifier.is_valid(&token) {
+        tracing::error!("Invalid CSRF token in password link request");
+        return Err(CsrfError.into());
+    }
+
+    let Some(user) = User::get(&env.pool, user_id).await.map_err(|err| {
+        tracing::error!(err = ?err, user_id = %user_id, "Failed to get user");
+        InternalServerError(err)
+    })?
+    else {
+        tracing::error!(user_id = %user_id, "Unrecognized user ID");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    if !allow_password_link(&env, &user) {
+        tracing::error!(user_id = %user_id, "Cannot create a password link for user");
+        return Err(poem::Error::from_status(StatusCode::BAD_REQUEST));
+    }
+
+    let password_link = create_password_link(&env, &auditor, &user, &admin).await?;
+
+    render_template(
+        "admin/users/password.html",
+        context! {
+            token => next_token.0,
+            allow_password_link => true,
+            password_link,
+            user,
+            ..authorized_context(&env, &admin)
+        },
+    )
+    .await
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CheckUsernameForm {
     token: String,
@@ -360,13 +533,31 @@ pub async fn get_user(
             InternalServerError(err)
         })?;
 
+    let api_tokens = ApiTokenList::get_for_user(&env.pool, user_id)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, user_id = %user_id, "Failed to get user's API tokens");
+            InternalServerError(err)
+        })?;
+
+    let webauthn_credentials = WebauthnCredential::get_for_user(&env.pool, user_id)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, user_id = %user_id, "Failed to get user's WebAuthn credentials");
+            InternalServerError(err)
+        })?;
+
     render_template(
         "admin/users/form.html",
         context! {
             token => token.0,
+            has_totp => user.totp.is_some(),
+            allow_password_link => allow_password_link(&env, &user),
             user,
             teams,
             membership,
+            api_tokens,
+            webauthn_credentials,
             ..authorized_context(&env, &admin)
         },
     )
@@ -391,6 +582,7 @@ pub struct EditUserForm {
 pub async fn post_user(
     env: Data<&Env>,
     SessionAdmin(auth): SessionAdmin,
+    auditor: Auditor,
     Path(user_id): Path<Key<User>>,
     next_token: &CsrfToken,
     verifier: &CsrfVerifier,
@@ -461,13 +653,31 @@ pub async fn post_user(
             InternalServerError(err)
         })?;
 
+        let api_tokens = ApiTokenList::get_for_user(&env.pool, user_id)
+            .await
+            .map_err(|err| {
+                tracing::error!(?err, user_id = %user_id, "Failed to get user's API tokens");
+                InternalServerError(err)
+            })?;
+
+        let webauthn_credentials = WebauthnCredential::get_for_user(&env.pool, user_id)
+            .await
+            .map_err(|err| {
+                tracing::error!(?err, user_id = %user_id, "Failed to get user's WebAuthn credentials");
+                InternalServerError(err)
+            })?;
+
         return Ok(render_template(
             "admin/users/form.html",
             context! {
                 errors,
                 teams,
+                has_totp => user.totp.is_some(),
+                allow_password_link => allow_password_link(&env, &user),
                 user,
                 membership,
+                api_tokens,
+                webauthn_credentials,
                 token => next_token.0,
                 form => context! {
                     username => &form.username,
@@ -511,6 +721,13 @@ pub async fn post_user(
     // Override the 'enabled' selection if the user being edited is the same as the admin
     let enabled = user.id == auth.id || enabled;
 
+    let before = user_audit_details(&user);
+    let action = match (user.enabled, enabled) {
+        (false, true) => AuditAction::UserEnable,
+        (true, false) => AuditAction::UserDisable,
+        _ => AuditAction::UserEdit,
+    };
+
     user.update(&env.pool, &username, &name, admin, enabled, limit)
         .await
         .map_err(|err| {
@@ -523,6 +740,16 @@ pub async fn post_user(
         "Updated user"
     );
 
+    auditor
+        .record(
+            &env,
+            &auth,
+            action,
+            AuditTarget::User(user.id),
+            Some(json!({ "before": before, "after": user_audit_details(&user) })),
+        )
+        .await;
+
     // Remove the user from all their current team memberships in one query.
     tracing::info!(user_id = %user_id, "Removing user from all teams");
     user.leave_all_teams(&env.pool).await.map_err(|err| {
@@ -549,6 +776,17 @@ pub async fn post_user(
     Ok(Redirect::see_other("/admin/users").into_response())
 }
 
+/// Describe the details of a user that an administrator can change, for the audit log.
+fn user_audit_details(user: &User) -> serde_json::Value {
+    json!({
+        "username": user.username,
+        "name": user.name,
+        "admin": user.admin,
+        "enabled": user.enabled,
+        "limit": user.limit,
+    })
+}
+
 #[derive(Debug, Deserialize)]
 pub struct DeleteUserQuery {
     csrf_token: String,
@@ -576,15 +814,26 @@ pub async fn delete_user(
         return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
     };
 
-    let upload_slugs = Upload::delete_for_user(&env.pool, user_id)
+    let upload_blobs = Upload::delete_for_user(&env.pool, user_id)
         .await
         .map_err(|err| {
             tracing::error!(err = ?err, user_id = %user_id, "Failed to delete users uploads");
             InternalServerError(err)
         })?;
 
-    for slug in upload_slugs {
-        delete_upload_cache_by_slug(&env, &slug).await;
+    for blob in upload_blobs {
+        release_blob(&env, &blob).await;
+    }
+
+    let session_ids = UploadSession::delete_for_user(&env.pool, user_id)
+        .await
+        .map_err(|err| {
+            tracing::error!(err = ?err, user_id = %user_id, "Failed to delete users upload sessions");
+            InternalServerError(err)
+        })?;
+
+    for session_id in session_ids {
+        delete_upload_session_file(&env, session_id).await;
     }
 
     user.delete(&env.pool).await.map_err(|err| {
@@ -718,12 +967,221 @@ pub async fn post_enable_user(
     Ok(Redirect::see_other("/admin/users"))
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RevokeApiTokenForm {
+    token: String,
+}
+
+#[handler]
+pub async fn post_revoke_api_token(
+    env: Data<&Env>,
+    SessionAdmin(admin): SessionAdmin,
+    next_token: &CsrfToken,
+    verifier: &CsrfVerifier,
+    Path((user_id, token_id)): Path<(Key<User>, Key<ApiToken>)>,
+    Form(RevokeApiTokenForm { token }): Form<RevokeApiTokenForm>,
+) -> poem::Result<Html<String>> {
+    if !verifier.is_valid(&token) {
+        tracing::error!("Invalid CSRF token in revoke API token request");
+        return Err(CsrfError.into());
+    }
+
+    let Some(user) = User::get(&env.pool, user_id).await.map_err(|err| {
+        tracing::error!(err = ?err, user_id = %user_id, "Failed to get user");
+        InternalServerError(err)
+    })?
+    else {
+        tracing::error!(user_id = %user_id, "Unrecognized user ID");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    let Some(mut api_token) = ApiToken::get(&env.pool, token_id)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, token_id = %token_id, "Failed to get API token");
+            InternalServerError(err)
+        })?
+        .filter(|api_token| api_token.user == user_id)
+    else {
+        tracing::error!(user_id = %user_id, token_id = %token_id, "Unrecognized API token ID");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    if api_token.revoked_at.is_none() {
+        api_token.revoke(&env.pool).await.map_err(|err| {
+            tracing::error!(?err, token_id = %token_id, "Failed to revoke API token");
+            InternalServerError(err)
+        })?;
+
+        tracing::info!(
+            admin_id = %admin.id, user_id = %user_id, token_id = %token_id,
+            "Administrator revoked API token"
+        );
+    }
+
+    let api_tokens = ApiTokenList::get_for_user(&env.pool, user_id)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, user_id = %user_id, "Failed to get user's API tokens");
+            InternalServerError(err)
+        })?;
+
+    render_template(
+        "admin/users/tokens.html",
+        context! {
+            token => next_token.0,
+            user,
+            api_tokens,
+            ..authorized_context(&env, &admin)
+        },
+    )
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetWebauthnForm {
+    token: String,
+}
+
+#[handler]
+pub async fn post_reset_webauthn(
+    env: Data<&Env>,
+    SessionAdmin(admin): SessionAdmin,
+    auditor: Auditor,
+    next_token: &CsrfToken,
+    verifier: &CsrfVerifier,
+    Path(user_id): Path<Key<User>>,
+    Form(ResetWebauthnForm { token }): Form<ResetWebauthnForm>,
+) -> poem::Result<Html<String>> {
+    if !verifier.is_valid(&token) {
+        tracing::error!("Invalid CSRF token in reset WebAuthn request");
+        return Err(CsrfError.into());
+    }
+
+    let Some(user) = User::get(&env.pool, user_id).await.map_err(|err| {
+        tracing::error!(err = ?err, user_id = %user_id, "Failed to get user");
+        InternalServerError(err)
+    })?
+    else {
+        tracing::error!(user_id = %user_id, "Unrecognized user ID");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    let removed = WebauthnCredential::delete_for_user(&env.pool, user_id)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, user_id = %user_id, "Failed to delete WebAuthn credentials");
+            InternalServerError(err)
+        })?;
+
+    tracing::info!(
+        admin_id = %admin.id, user_id = %user_id, removed,
+        "Administrator reset WebAuthn credentials"
+    );
+
+    if removed > 0 {
+        auditor
+            .record(
+                &env,
+                &admin,
+                AuditAction::WebauthnReset,
+                AuditTarget::User(user_id),
+                Some(json!({ "removed": removed })),
+            )
+            .await;
+    }
+
+    render_template(
+        "admin/users/webauthn.html",
+        context! {
+            token => next_token.0,
+            user,
+            webauthn_credentials => Vec::<WebauthnCredential>::new(),
+            ..authorized_context(&env, &admin)
+        },
+    )
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetTotpForm {
+    token: String,
+}
+
+#[handler]
+pub async fn post_reset_totp(
+    env: Data<&Env>,
+    SessionAdmin(admin): SessionAdmin,
+    auditor: Auditor,
+    next_token: &CsrfToken,
+    verifier: &CsrfVerifier,
+    Path(user_id): Path<Key<User>>,
+    Form(ResetTotpForm { token }): Form<ResetTotpForm>,
+) -> poem::Result<Html<String>> {
+    if !verifier.is_valid(&token) {
+        tracing::error!("Invalid CSRF token in reset TOTP request");
+        return Err(CsrfError.into());
+    }
+
+    let Some(mut user) = User::get(&env.pool, user_id).await.map_err(|err| {
+        tracing::error!(err = ?err, user_id = %user_id, "Failed to get user");
+        InternalServerError(err)
+    })?
+    else {
+        tracing::error!(user_id = %user_id, "Unrecognized user ID");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    user.reset_totp(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, user_id = %user_id, "Failed to reset TOTP");
+        InternalServerError(err)
+    })?;
+
+    tracing::info!(
+        admin_id = %admin.id, user_id = %user_id,
+        "Administrator reset two-factor authentication"
+    );
+
+    auditor
+        .record(
+            &env,
+            &admin,
+            AuditAction::TotpReset,
+            AuditTarget::User(user_id),
+            None,
+        )
+        .await;
+
+    render_template(
+        "admin/users/totp.html",
+        context! {
+            token => next_token.0,
+            has_totp => false,
+            user,
+            ..authorized_context(&env, &admin)
+        },
+    )
+    .await
+}
+
 #[handler]
 pub async fn get_masquerade(
+    env: Data<&Env>,
     Path(user_id): Path<Key<User>>,
     SessionAdmin(admin): SessionAdmin,
+    auditor: Auditor,
     session: &Session,
 ) -> poem::Result<Redirect> {
+    auditor
+        .record(
+            &env,
+            &admin,
+            AuditAction::Masquerade,
+            AuditTarget::User(user_id),
+            None,
+        )
+        .await;
+
     let mut stack = session
         .take::<Vec<Key<User>>>("masquerade_stack")
         .unwrap_or_default();
@@ -0,0 +1,59 @@
+use poem::{
+    http::{header::WWW_AUTHENTICATE, StatusCode},
+    web::Json,
+    Endpoint, EndpointExt, IntoResponse, Response,
+};
+use poem_route_macro::define_routes;
+use serde_json::json;
+
+mod teams;
+mod tus;
+mod uploads;
+mod users;
+
+time::serde::format_description!(iso8601_date, Date, "[year]-[month]-[day]");
+
+/// Create the routes for the JSON API, which is nested under `/api/v1`.
+///
+/// Authorization follows the same rules as the HTML routes, using `Upload::can_access` and team
+/// membership. Unlike the rest of the application, errors from these routes are always returned as
+/// a JSON object with an `error` field, rather than an HTML error page.
+pub fn create_api() -> impl Endpoint {
+    let tus_ep = tus::create_tus();
+
+    define_routes!({
+        *"/tus" { tus_ep }
+
+        "/user"                 users::me               GET
+        "/users"                users::users            GET
+        "/teams"                teams::teams            GET
+        "/teams/:id"            teams::team             GET
+        "/teams/:id/uploads"    teams::uploads          GET
+        "/uploads"              uploads::uploads        GET POST
+        "/uploads/:id"          uploads::upload         GET PATCH DELETE
+        "/uploads/:id/download" uploads::download       GET
+        "/uploads/:id/reset"    uploads::reset              POST
+        "/uploads/:id/transfer" uploads::transfer           POST
+    })
+    .catch_all_error(handle_error)
+}
+
+async fn handle_error(error: poem::Error) -> Response {
+    let status = error.status();
+
+    let message = if status.is_server_error() {
+        tracing::error!("Internal server error in API: {:?}", error);
+        "Internal server error".to_string()
+    } else {
+        error.to_string()
+    };
+
+    let response = Json(json!({ "error": message })).with_status(status);
+    if status == StatusCode::UNAUTHORIZED {
+        response
+            .with_header(WWW_AUTHENTICATE, "Basic realm=\"parcel\"")
+            .into_response()
+    } else {
+        response.into_response()
+    }
+}
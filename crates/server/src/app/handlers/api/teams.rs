@@ -0,0 +1,140 @@
+use poem::{
+    error::InternalServerError,
+    handler,
+    http::StatusCode,
+    web::{Data, Json, Path, Query},
+};
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use parcel_model::{
+    api_token::TokenScope,
+    team::{Team, TeamMember},
+    types::Key,
+    upload::UploadStats,
+};
+
+use crate::{
+    app::{extractors::api::ApiUser, handlers::utils::get_team_for_member},
+    env::Env,
+};
+
+use super::uploads::{list_uploads, ListQuery, UploadPage};
+
+#[derive(Debug, Serialize)]
+pub struct TeamItem {
+    id: Key<Team>,
+    name: String,
+    slug: String,
+    limit: Option<i64>,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    can_edit: bool,
+    can_delete: bool,
+    can_config: bool,
+}
+
+impl TeamItem {
+    fn new(team: Team, membership: &TeamMember) -> Self {
+        Self {
+            id: team.id,
+            name: team.name,
+            slug: team.slug,
+            limit: team.limit,
+            created_at: team.created_at,
+            can_edit: membership.can_edit,
+            can_delete: membership.can_delete,
+            can_config: membership.can_config,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TeamDetail {
+    #[serde(flatten)]
+    team: TeamItem,
+    usage: UploadStats,
+}
+
+#[handler]
+pub async fn get_teams(env: Data<&Env>, user: ApiUser) -> poem::Result<Json<Vec<TeamItem>>> {
+    user.require_scope(TokenScope::Read)?;
+    let token_team = user.token_team();
+
+    let (teams, memberships) = tokio::join!(
+        Team::get_for_user(&env.pool, user.id),
+        TeamMember::get_for_user(&env.pool, user.id)
+    );
+
+    let teams = teams.map_err(|err| {
+        tracing::error!(%user.id, ?err, "Unable to get teams for user");
+        InternalServerError(err)
+    })?;
+
+    let memberships = memberships.map_err(|err| {
+        tracing::error!(%user.id, ?err, "Unable to get team memberships for user");
+        InternalServerError(err)
+    })?;
+
+    Ok(Json(
+        teams
+            .into_iter()
+            .filter(|team| team.enabled)
+            .filter(|team| token_team.is_none_or(|token_team| token_team == team.id))
+            .filter_map(|team| {
+                let membership = memberships.iter().find(|member| member.team == team.id)?;
+                Some(TeamItem::new(team, membership))
+            })
+            .collect(),
+    ))
+}
+
+#[handler]
+pub async fn get_team(
+    env: Data<&Env>,
+    user: ApiUser,
+    Path(team_id): Path<Key<Team>>,
+) -> poem::Result<Json<TeamDetail>> {
+    user.require_scope(TokenScope::Read)?;
+    user.require_team(Some(team_id))?;
+
+    let team = get_team_for_member(&env, &user, team_id).await?;
+    let Some(membership) = TeamMember::get_for_user_and_team(&env.pool, user.id, team.id)
+        .await
+        .map_err(|err| {
+            tracing::error!(%user.id, %team.id, ?err, "Unable to get team membership");
+            InternalServerError(err)
+        })?
+    else {
+        tracing::error!(%user.id, %team.id, "User is not a member of team");
+        return Err(poem::Error::from_status(StatusCode::FORBIDDEN));
+    };
+
+    let usage = UploadStats::get_for_team(&env.pool, team.id)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %team.id, "Failed to get upload stats for team");
+            InternalServerError(err)
+        })?;
+
+    Ok(Json(TeamDetail {
+        team: TeamItem::new(team, &membership),
+        usage,
+    }))
+}
+
+#[handler]
+pub async fn get_uploads(
+    env: Data<&Env>,
+    user: ApiUser,
+    Path(team_id): Path<Key<Team>>,
+    Query(query): Query<ListQuery>,
+) -> poem::Result<Json<UploadPage>> {
+    user.require_scope(TokenScope::Read)?;
+    user.require_team(Some(team_id))?;
+
+    let team = get_team_for_member(&env, &user, team_id).await?;
+    list_uploads(&env, &user, Some(&team), query)
+        .await
+        .map(Json)
+}
@@ -0,0 +1,533 @@
+//! Resumable uploads using the [tus] protocol
+//!
+//! An upload is created with a `POST` request that gives the total size of the file in the
+//! `Upload-Length` header. The file name, and optionally the ID of the team that will own the
+//! upload, are given in the `Upload-Metadata` header as the `filename` and `team` keys. The
+//! response contains the URL of the new upload session in the `Location` header.
+//!
+//! The bytes of the file are then sent in one or more `PATCH` requests. If a `PATCH` request is
+//! interrupted, the client can send a `HEAD` request to find out how many bytes were received and
+//! resume from that offset. Once all of the bytes have been received, the file is moved into the
+//! cache and an upload is created. The ID of the new upload is returned in the `Parcel-Upload`
+//! header of the final `PATCH` response.
+//!
+//! We support the `creation`, `expiration` and `termination` extensions. Sessions that receive no
+//! bytes for the configured `--upload-session-expiry` are removed by the reaper worker.
+//!
+//! [tus]: https://tus.io/protocols/resumable-upload
+
+use std::collections::HashMap;
+
+use base64::Engine;
+use poem::{
+    error::InternalServerError,
+    handler,
+    http::{HeaderMap, StatusCode},
+    middleware::SetHeader,
+    web::{Data, Path, RealIp},
+    Body, Endpoint, EndpointExt, Response, Route, RouteMethod,
+};
+use time::{macros::format_description, OffsetDateTime};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use parcel_model::{
+    api_token::TokenScope, team::Team, types::Key, upload::Upload, upload_session::UploadSession,
+};
+
+use crate::{
+    app::{
+        errors::QuotaExceededError,
+        extractors::api::ApiUser,
+        handlers::utils::{
+            delete_upload_session_file, discard_pending_uploads, get_remaining_quota,
+            get_team_for_member, hash_and_store_blob, insert_pending_uploads, upload_session_path,
+            PendingUpload,
+        },
+    },
+    env::Env,
+    workers::previews::PreviewWorker,
+};
+
+/// The version of the tus protocol that we support.
+const TUS_VERSION: &str = "1.0.0";
+
+/// The tus extensions that we support.
+const TUS_EXTENSIONS: &str = "creation,expiration,termination";
+
+/// The content type of the body of a `PATCH` request.
+const OFFSET_OCTET_STREAM: &str = "application/offset+octet-stream";
+
+/// Create the routes for resumable uploads, which are nested under `/api/v1/tus`.
+///
+/// These routes are built by hand, rather than with `define_routes!`, as tus needs the `HEAD` and
+/// `OPTIONS` methods.
+pub fn create_tus() -> impl Endpoint {
+    Route::new()
+        .at("/", RouteMethod::new().options(options_tus).post(post_tus))
+        .at(
+            "/:id",
+            RouteMethod::new()
+                .head(head_session)
+                .patch(patch_session)
+                .delete(delete_session),
+        )
+        .with(SetHeader::new().overriding("Tus-Resumable", TUS_VERSION))
+}
+
+fn check_tus_version(headers: &HeaderMap) -> poem::Result<()> {
+    let version = headers
+        .get("Tus-Resumable")
+        .and_then(|value| value.to_str().ok());
+
+    if version != Some(TUS_VERSION) {
+        tracing::info!(?version, "Unsupported tus protocol version");
+        return Err(poem::Error::from_string(
+            format!("Only version {TUS_VERSION} of the tus protocol is supported"),
+            StatusCode::PRECONDITION_FAILED,
+        ));
+    }
+
+    Ok(())
+}
+
+/// Parse a numeric header, such as `Upload-Length` or `Upload-Offset`.
+fn parse_size_header(headers: &HeaderMap, name: &str) -> poem::Result<i64> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<i64>().ok())
+        .filter(|value| *value >= 0)
+        .ok_or_else(|| {
+            poem::Error::from_string(
+                format!("A valid '{name}' header is required"),
+                StatusCode::BAD_REQUEST,
+            )
+        })
+}
+
+/// Parse the `Upload-Metadata` header, which is a comma-separated list of keys and base64-encoded
+/// values.
+fn parse_metadata(headers: &HeaderMap) -> poem::Result<HashMap<String, String>> {
+    let Some(metadata) = headers.get("Upload-Metadata") else {
+        return Ok(HashMap::new());
+    };
+
+    let invalid =
+        || poem::Error::from_string("Invalid 'Upload-Metadata' header", StatusCode::BAD_REQUEST);
+
+    let metadata = metadata.to_str().map_err(|_| invalid())?;
+    let mut result = HashMap::new();
+
+    for pair in metadata
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+    {
+        let (key, value) = match pair.split_once(' ') {
+            Some((key, value)) => {
+                let value = base64::engine::general_purpose::STANDARD
+                    .decode(value.trim())
+                    .map_err(|_| invalid())?;
+                (key, String::from_utf8(value).map_err(|_| invalid())?)
+            }
+
+            None => (pair, String::new()),
+        };
+
+        result.insert(key.to_string(), value);
+    }
+
+    Ok(result)
+}
+
+/// Format the expiry of a session for the `Upload-Expires` header.
+fn format_expires(expires_at: OffsetDateTime) -> String {
+    let format = format_description!(
+        "[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT"
+    );
+
+    expires_at
+        .to_offset(time::UtcOffset::UTC)
+        .format(&format)
+        .unwrap_or_default()
+}
+
+/// Get the session with the given ID, making sure that it belongs to the user.
+async fn get_session(
+    env: &Env,
+    user: &ApiUser,
+    id: Key<UploadSession>,
+) -> poem::Result<UploadSession> {
+    let Some(session) = UploadSession::get(&env.pool, id)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %id, "Unable to get upload session");
+            InternalServerError(err)
+        })?
+        .filter(|session| session.uploaded_by == user.id)
+    else {
+        tracing::error!(%id, %user.id, "Unable to find upload session");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    user.require_scope(TokenScope::Upload)?;
+    user.require_team(session.owner_team)?;
+
+    if session.is_expired() {
+        tracing::info!(%id, "Upload session has expired");
+        return Err(poem::Error::from_string(
+            "The upload session has expired",
+            StatusCode::GONE,
+        ));
+    }
+
+    Ok(session)
+}
+
+#[handler]
+pub async fn options_tus() -> Response {
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header("Tus-Version", TUS_VERSION)
+        .header("Tus-Extension", TUS_EXTENSIONS)
+        .finish()
+}
+
+#[handler]
+pub async fn post_tus(
+    env: Data<&Env>,
+    preview: Data<&PreviewWorker>,
+    RealIp(ip): RealIp,
+    user: ApiUser,
+    headers: &HeaderMap,
+) -> poem::Result<Response> {
+    check_tus_version(headers)?;
+
+    if headers.contains_key("Upload-Defer-Length") {
+        return Err(poem::Error::from_string(
+            "Deferring the upload length is not supported",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    let length = parse_size_header(headers, "Upload-Length")?;
+    let mut metadata = parse_metadata(headers)?;
+
+    let filename = metadata
+        .remove("filename")
+        .or_else(|| metadata.remove("name"))
+        .filter(|filename| !filename.is_empty())
+        .unwrap_or_else(|| "unnamed.ext".to_string());
+
+    let team = match metadata.get("team").filter(|team| !team.is_empty()) {
+        Some(team) => Some(team.parse::<Key<Team>>().map_err(|_| {
+            tracing::error!(?team, "Invalid team ID in upload metadata");
+            poem::Error::from_string("Invalid team ID", StatusCode::BAD_REQUEST)
+        })?),
+
+        // A token that is restricted to a team uploads to that team by default.
+        None => user.token_team(),
+    };
+
+    user.require_scope(TokenScope::Upload)?;
+    user.require_team(team)?;
+
+    let team = if let Some(team_id) = team {
+        Some(get_team_for_member(&env, &user, team_id).await?)
+    } else {
+        None
+    };
+
+    // The whole of the file is counted against the quota when the session is created, so that
+    // the upload cannot fail once the bytes have been sent. This is checked again as the session
+    // is created, in case another upload takes the space in the meantime.
+    if let Some(remaining) = get_remaining_quota(&env, &user, team.as_ref()).await? {
+        if length > remaining {
+            tracing::info!(
+                length,
+                remaining,
+                "Resumable upload exceeds remaining quota"
+            );
+            return Err(QuotaExceededError { remaining }.into());
+        }
+    }
+
+    let remote_addr = ip.as_ref().map(ToString::to_string);
+    let session = UploadSession::new(
+        &user,
+        team.as_ref(),
+        filename,
+        length,
+        remote_addr,
+        env.upload_session_expiry,
+    );
+
+    let path = upload_session_path(&env, session.id);
+    tokio::fs::File::create(&path).await.map_err(|err| {
+        tracing::error!(?err, ?path, "Unable to create file for upload session");
+        InternalServerError(err)
+    })?;
+
+    let limit = match team {
+        Some(ref team) => team.limit,
+        None => user.limit,
+    };
+
+    match session.create(&env.pool, limit).await {
+        Ok(true) => {}
+
+        // Another upload took the space between checking the quota and creating the session.
+        Ok(false) => {
+            delete_upload_session_file(&env, session.id).await;
+            let remaining = get_remaining_quota(&env, &user, team.as_ref())
+                .await?
+                .unwrap_or_default();
+            tracing::info!(
+                length,
+                remaining,
+                "Resumable upload exceeds remaining quota"
+            );
+            return Err(QuotaExceededError { remaining }.into());
+        }
+
+        Err(err) => {
+            tracing::error!(?err, %session.id, "Unable to create upload session");
+            delete_upload_session_file(&env, session.id).await;
+            return Err(InternalServerError(err));
+        }
+    }
+
+    tracing::info!(%session.id, %user.id, length, team = ?session.owner_team, "Created upload session");
+
+    let mut response = Response::builder()
+        .status(StatusCode::CREATED)
+        .header("Location", format!("/api/v1/tus/{}", session.id));
+
+    // An empty file has nothing to send, so we can complete the upload straight away.
+    if session.is_complete() {
+        let upload_id = complete_session(&env, &preview, &user, session).await?;
+        response = response.header("Parcel-Upload", upload_id.to_string());
+    } else {
+        response = response.header("Upload-Expires", format_expires(session.expires_at));
+    }
+
+    Ok(response.finish())
+}
+
+#[handler]
+pub async fn head_session(
+    env: Data<&Env>,
+    user: ApiUser,
+    headers: &HeaderMap,
+    Path(id): Path<Key<UploadSession>>,
+) -> poem::Result<Response> {
+    check_tus_version(headers)?;
+    let session = get_session(&env, &user, id).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Upload-Offset", session.offset)
+        .header("Upload-Length", session.length)
+        .header("Upload-Expires", format_expires(session.expires_at))
+        .header("Cache-Control", "no-store")
+        .finish())
+}
+
+#[handler]
+pub async fn patch_session(
+    env: Data<&Env>,
+    preview: Data<&PreviewWorker>,
+    user: ApiUser,
+    headers: &HeaderMap,
+    Path(id): Path<Key<UploadSession>>,
+    body: Body,
+) -> poem::Result<Response> {
+    check_tus_version(headers)?;
+
+    let content_type = headers
+        .get("Content-Type")
+        .and_then(|value| value.to_str().ok());
+    if content_type != Some(OFFSET_OCTET_STREAM) {
+        return Err(poem::Error::from_string(
+            format!("The request body must be '{OFFSET_OCTET_STREAM}'"),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        ));
+    }
+
+    let offset = parse_size_header(headers, "Upload-Offset")?;
+    let mut session = get_session(&env, &user, id).await?;
+
+    if offset != session.offset {
+        tracing::info!(%id, offset, expected = session.offset, "Mismatched upload offset");
+        return Err(poem::Error::from_string(
+            "The 'Upload-Offset' does not match the offset of the upload",
+            StatusCode::CONFLICT,
+        ));
+    }
+
+    let path = upload_session_path(&env, id);
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, ?path, "Unable to open file for upload session");
+            InternalServerError(err)
+        })?;
+
+    // We write at the offset, rather than appending, so that a request that races with another
+    // for the same offset doesn't leave the file with duplicate bytes.
+    file.seek(std::io::SeekFrom::Start(offset as u64))
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, ?path, offset, "Unable to seek in upload session file");
+            InternalServerError(err)
+        })?;
+
+    // Any bytes beyond the length of the upload are ignored.
+    let mut body = body
+        .into_async_read()
+        .take((session.length - offset) as u64);
+
+    // Copy the body into the file ourselves, rather than with `tokio::io::copy`, so that we know
+    // how many bytes were written if the client disconnects part way through.
+    let mut received = 0;
+    let mut buffer = vec![0; 64 * 1024];
+    let read_error = loop {
+        let count = match body.read(&mut buffer).await {
+            Ok(0) => break None,
+            Ok(count) => count,
+            Err(err) => break Some(err),
+        };
+
+        file.write_all(&buffer[..count]).await.map_err(|err| {
+            tracing::error!(?err, ?path, "Unable to write to upload session file");
+            InternalServerError(err)
+        })?;
+
+        received += count as i64;
+    };
+
+    file.flush().await.map_err(|err| {
+        tracing::error!(?err, ?path, "Unable to flush upload session file");
+        InternalServerError(err)
+    })?;
+
+    let updated = session
+        .set_offset(&env.pool, offset + received, env.upload_session_expiry)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %id, "Unable to update upload session offset");
+            InternalServerError(err)
+        })?;
+
+    if !updated {
+        tracing::info!(%id, offset, "Upload session was changed by another request");
+        return Err(poem::Error::from_string(
+            "The upload was changed by another request",
+            StatusCode::CONFLICT,
+        ));
+    }
+
+    if let Some(err) = read_error {
+        tracing::info!(?err, %id, offset = session.offset, "Upload session interrupted");
+        return Err(poem::Error::from_string(
+            "Failed to receive the upload",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    tracing::info!(%id, received, offset = session.offset, "Received bytes for upload session");
+
+    let mut response = Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header("Upload-Offset", session.offset);
+
+    if session.is_complete() {
+        let upload_id = complete_session(&env, &preview, &user, session).await?;
+        response = response.header("Parcel-Upload", upload_id.to_string());
+    } else {
+        response = response.header("Upload-Expires", format_expires(session.expires_at));
+    }
+
+    Ok(response.finish())
+}
+
+#[handler]
+pub async fn delete_session(
+    env: Data<&Env>,
+    user: ApiUser,
+    headers: &HeaderMap,
+    Path(id): Path<Key<UploadSession>>,
+) -> poem::Result<Response> {
+    check_tus_version(headers)?;
+    let session = get_session(&env, &user, id).await?;
+
+    session.delete(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %id, "Unable to delete upload session");
+        InternalServerError(err)
+    })?;
+
+    delete_upload_session_file(&env, id).await;
+    tracing::info!(%id, %user.id, "Terminated upload session");
+
+    Ok(Response::builder().status(StatusCode::NO_CONTENT).finish())
+}
+
+/// Move the file for a completed session into storage, and create the upload.
+async fn complete_session(
+    env: &Env,
+    preview: &PreviewWorker,
+    user: &ApiUser,
+    session: UploadSession,
+) -> poem::Result<Key<Upload>> {
+    let team = if let Some(team_id) = session.owner_team {
+        Some(get_team_for_member(env, user, team_id).await?)
+    } else {
+        None
+    };
+
+    let session_path = upload_session_path(env, session.id);
+    let blob = hash_and_store_blob(env, &session_path, session.length).await?;
+
+    let pending = PendingUpload {
+        id: Key::new(),
+        slug: nanoid::nanoid!(),
+        hash: Some(blob.clone()),
+        blob,
+        filename: session.filename.clone(),
+        size: session.length,
+    };
+
+    let pending = std::slice::from_ref(&pending);
+    let upload_ids = match insert_pending_uploads(
+        env,
+        user,
+        team.as_ref(),
+        session.remote_addr.clone(),
+        pending,
+        true,
+    )
+    .await
+    {
+        Ok(upload_ids) => upload_ids,
+        Err(err) => {
+            discard_pending_uploads(env, pending).await;
+            return Err(err);
+        }
+    };
+
+    session.delete(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %session.id, "Unable to delete completed upload session");
+        InternalServerError(err)
+    })?;
+
+    let upload_id = pending[0].id;
+    tracing::info!(%session.id, upload = %upload_id, size = session.length, "Completed upload session");
+
+    if let Err(err) = preview.generate_previews(upload_ids).await {
+        tracing::error!(?err, "Failed to send preview generation command");
+    }
+
+    Ok(upload_id)
+}
@@ -0,0 +1,630 @@
+use poem::{
+    error::InternalServerError,
+    handler,
+    http::StatusCode,
+    web::{Data, Json, Multipart, Path, Query, RealIp},
+    IntoResponse, Request, Response,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use time::{Date, OffsetDateTime};
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use parcel_model::{
+    api_token::TokenScope,
+    audit::{AuditAction, AuditTarget},
+    password::StoredPassword,
+    team::Team,
+    types::Key,
+    upload::{FolderFilter, Upload, UploadFilter, UploadList, UploadOrder, UploadPermission},
+    user::User,
+};
+
+use crate::{
+    app::{
+        extractors::{api::ApiUser, audit::Auditor},
+        handlers::{
+            uploads::{send_download, transfer_upload, TransferAction},
+            utils::{
+                cache_upload_field, check_permission, delete_upload_cache, discard_pending_uploads,
+                get_remaining_quota, get_team_for_member, get_upload_by_id, insert_pending_uploads,
+                upload_audit_details, upload_change_details,
+            },
+        },
+    },
+    env::Env,
+    workers::previews::PreviewWorker,
+};
+
+use super::iso8601_date;
+
+/// The number of uploads returned in each page of the upload list.
+const PAGE_SIZE: u32 = 50;
+
+#[derive(Debug, Serialize)]
+pub struct UploadItem {
+    id: Key<Upload>,
+    slug: String,
+    filename: String,
+    size: i64,
+    hash: Option<String>,
+    public: bool,
+    has_password: bool,
+    downloads: i64,
+    limit: Option<i64>,
+    remaining: Option<i64>,
+    #[serde(with = "iso8601_date::option")]
+    expiry_date: Option<Date>,
+    custom_slug: Option<String>,
+    owner_user: Option<Key<User>>,
+    owner_team: Option<Key<Team>>,
+    uploaded_by: Option<Key<User>>,
+    #[serde(with = "time::serde::rfc3339")]
+    uploaded_at: OffsetDateTime,
+    mime_type: Option<String>,
+}
+
+impl From<Upload> for UploadItem {
+    fn from(upload: Upload) -> Self {
+        Self {
+            id: upload.id,
+            slug: upload.slug,
+            filename: upload.filename,
+            size: upload.size,
+            hash: upload.hash,
+            public: upload.public,
+            has_password: upload.password.is_some(),
+            downloads: upload.downloads,
+            limit: upload.limit,
+            remaining: upload.remaining,
+            expiry_date: upload.expiry_date,
+            custom_slug: upload.custom_slug,
+            owner_user: upload.owner_user,
+            owner_team: upload.owner_team,
+            uploaded_by: upload.uploaded_by,
+            uploaded_at: upload.uploaded_at,
+            mime_type: upload.mime_type,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadListItem {
+    id: Key<Upload>,
+    slug: String,
+    filename: String,
+    size: i64,
+    public: bool,
+    has_password: bool,
+    downloads: i64,
+    limit: Option<i64>,
+    remaining: Option<i64>,
+    #[serde(with = "iso8601_date::option")]
+    expiry_date: Option<Date>,
+    custom_slug: Option<String>,
+    owner_slug: String,
+    uploaded_by_name: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    uploaded_at: OffsetDateTime,
+}
+
+impl From<UploadList> for UploadListItem {
+    fn from(upload: UploadList) -> Self {
+        Self {
+            id: upload.id,
+            slug: upload.slug,
+            filename: upload.filename,
+            size: upload.size,
+            public: upload.public,
+            has_password: upload.has_password,
+            downloads: upload.downloads,
+            limit: upload.limit,
+            remaining: upload.remaining,
+            expiry_date: upload.expiry_date,
+            custom_slug: upload.custom_slug,
+            owner_slug: upload.owner_slug,
+            uploaded_by_name: upload.uploaded_by_name,
+            uploaded_at: upload.uploaded_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadPage {
+    page: u32,
+    per_page: u32,
+    uploads: Vec<UploadListItem>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListQuery {
+    #[serde(default)]
+    search: String,
+    order: Option<UploadOrder>,
+    asc: Option<bool>,
+    #[serde(default)]
+    page: u32,
+}
+
+/// List a page of uploads belonging to either the given team or the user.
+pub async fn list_uploads(
+    env: &Env,
+    user: &User,
+    team: Option<&Team>,
+    query: ListQuery,
+) -> poem::Result<UploadPage> {
+    let search = query.search.trim();
+    let filter = UploadFilter {
+        search: (!search.is_empty()).then(|| search.to_string()),
+        ..UploadFilter::default()
+    };
+
+    // Search results are sorted by how well they match, unless another order is given.
+    let order = if query.order.is_none() && filter.search.is_some() {
+        None
+    } else {
+        Some(query.order.unwrap_or(user.default_order))
+    };
+    let asc = query.asc.unwrap_or(user.default_asc);
+    let offset = PAGE_SIZE * query.page;
+
+    let uploads = if let Some(team) = team {
+        UploadList::get_for_team(
+            &env.pool,
+            team.id,
+            FolderFilter::All,
+            &filter,
+            order,
+            asc,
+            offset,
+            PAGE_SIZE,
+        )
+        .await
+        .map_err(|err| {
+            tracing::error!(%team.id, ?err, "Unable to get uploads for team");
+            InternalServerError(err)
+        })?
+    } else {
+        UploadList::get_for_user(
+            &env.pool,
+            user.id,
+            FolderFilter::All,
+            &filter,
+            order,
+            asc,
+            offset,
+            PAGE_SIZE,
+        )
+        .await
+        .map_err(|err| {
+            tracing::error!(%user.id, ?err, "Unable to get uploads for user");
+            InternalServerError(err)
+        })?
+    };
+
+    Ok(UploadPage {
+        page: query.page,
+        per_page: PAGE_SIZE,
+        uploads: uploads.into_iter().map(UploadListItem::from).collect(),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TeamQuery {
+    team: Option<Key<Team>>,
+}
+
+#[handler]
+pub async fn get_uploads(
+    env: Data<&Env>,
+    user: ApiUser,
+    Query(TeamQuery { team }): Query<TeamQuery>,
+    Query(query): Query<ListQuery>,
+) -> poem::Result<Json<UploadPage>> {
+    // A token that is restricted to a team lists that team's uploads by default.
+    let team = team.or(user.token_team());
+    user.require_scope(TokenScope::Read)?;
+    user.require_team(team)?;
+
+    let team = if let Some(team_id) = team {
+        Some(get_team_for_member(&env, &user, team_id).await?)
+    } else {
+        None
+    };
+
+    list_uploads(&env, &user, team.as_ref(), query)
+        .await
+        .map(Json)
+}
+
+#[handler]
+pub async fn post_uploads(
+    env: Data<&Env>,
+    preview: Data<&PreviewWorker>,
+    RealIp(ip): RealIp,
+    user: ApiUser,
+    Query(TeamQuery { team }): Query<TeamQuery>,
+    mut form: Multipart,
+) -> poem::Result<Response> {
+    // A token that is restricted to a team uploads to that team by default.
+    let team = team.or(user.token_team());
+    user.require_scope(TokenScope::Upload)?;
+    user.require_team(team)?;
+
+    let team = if let Some(team_id) = team {
+        Some(get_team_for_member(&env, &user, team_id).await?)
+    } else {
+        None
+    };
+
+    let mut quota = get_remaining_quota(&env, &user, team.as_ref()).await?;
+    let mut uploads = Vec::new();
+    loop {
+        let field = match form.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(err) => {
+                tracing::error!(?err, "Failed to read multipart field");
+                discard_pending_uploads(&env, &uploads).await;
+                return Err(err.into());
+            }
+        };
+
+        if field.name() != Some("file") {
+            tracing::info!(field_name = ?field.name(), "Ignoring unrecognized field");
+            continue;
+        }
+
+        match cache_upload_field(&env, field, &mut quota, None).await {
+            Ok(Some(upload)) => uploads.push(upload),
+            result => {
+                discard_pending_uploads(&env, &uploads).await;
+                result?;
+
+                return Err(poem::Error::from_string(
+                    "Failed to receive uploaded file",
+                    StatusCode::BAD_REQUEST,
+                ));
+            }
+        }
+    }
+
+    if uploads.is_empty() {
+        return Err(poem::Error::from_string(
+            "No files were provided in the 'file' field",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    let remote_addr = ip.as_ref().map(ToString::to_string);
+    let upload_ids = match insert_pending_uploads(
+        &env,
+        &user,
+        team.as_ref(),
+        remote_addr,
+        &uploads,
+        false,
+    )
+    .await
+    {
+        Ok(upload_ids) => upload_ids,
+        Err(err) => {
+            discard_pending_uploads(&env, &uploads).await;
+            return Err(err);
+        }
+    };
+
+    let created = Upload::get_many(&env.pool, &upload_ids)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "Unable to fetch created uploads");
+            InternalServerError(err)
+        })?;
+
+    if let Err(err) = preview.generate_previews(upload_ids).await {
+        tracing::error!(?err, "Failed to send preview generation command");
+    }
+
+    Ok(Json(
+        created
+            .into_iter()
+            .map(UploadItem::from)
+            .collect::<Vec<_>>(),
+    )
+    .with_status(StatusCode::CREATED)
+    .into_response())
+}
+
+#[handler]
+pub async fn get_upload(
+    env: Data<&Env>,
+    user: ApiUser,
+    Path(id): Path<Key<Upload>>,
+) -> poem::Result<Json<UploadItem>> {
+    let upload = get_upload_by_id(&env, id).await?;
+    user.require_permits(&upload, &UploadPermission::View)?;
+    check_permission(&env, &upload, Some(&user.0), UploadPermission::View).await?;
+    Ok(Json(upload.into()))
+}
+
+/// Deserialize a field that is present in the request body, so that an explicit `null` can be
+/// told apart from a missing field when used with `#[serde(default)]`.
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExpiryDate(#[serde(with = "iso8601_date")] Date);
+
+/// Changes to an upload, where a missing field is left unchanged and a `null` clears the value.
+#[derive(Debug, Deserialize, Validate)]
+pub struct EditUpload {
+    #[validate(length(min = 1, max = 255))]
+    filename: Option<String>,
+    public: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    limit: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    expiry_date: Option<Option<ExpiryDate>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    password: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    #[validate(length(min = 3, max = 100))]
+    custom_slug: Option<Option<String>>,
+}
+
+#[handler]
+pub async fn patch_upload(
+    env: Data<&Env>,
+    user: ApiUser,
+    auditor: Auditor,
+    Path(id): Path<Key<Upload>>,
+    Json(edit): Json<EditUpload>,
+) -> poem::Result<Response> {
+    let mut upload = get_upload_by_id(&env, id).await?;
+    user.require_permits(&upload, &UploadPermission::Edit)?;
+    check_permission(&env, &upload, Some(&user.0), UploadPermission::Edit).await?;
+
+    let mut errors = match edit.validate() {
+        Ok(()) => ValidationErrors::new(),
+        Err(errors) => errors,
+    };
+
+    if let Some(Some(ref custom_slug)) = edit.custom_slug {
+        if upload.custom_slug.as_ref() != Some(custom_slug) {
+            let exists = if let Some(owner_user) = upload.owner_user {
+                Upload::custom_slug_exists(&env.pool, owner_user, Some(id), custom_slug).await
+            } else if let Some(owner_team) = upload.owner_team {
+                Upload::custom_team_slug_exists(&env.pool, owner_team, Some(id), custom_slug).await
+            } else {
+                tracing::error!("Upload has no owner");
+                return Err(poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR));
+            }
+            .map_err(|err| {
+                tracing::error!(upload = %id, ?err, "Unable to check if custom slug exists");
+                InternalServerError(err)
+            })?;
+
+            if exists {
+                errors.add(
+                    "custom_slug",
+                    ValidationError::new("duplicate_slug")
+                        .with_message("An upload with this custom slug already exists".into()),
+                );
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Ok(Json(serde_json::json!({
+            "error": "Validation failed",
+            "fields": errors,
+        }))
+        .with_status(StatusCode::UNPROCESSABLE_ENTITY)
+        .into_response());
+    }
+
+    let before = upload.clone();
+    let EditUpload {
+        filename,
+        public,
+        limit,
+        expiry_date,
+        password,
+        custom_slug,
+    } = edit;
+
+    if let Some(filename) = filename {
+        upload.filename = filename;
+    }
+
+    if let Some(public) = public {
+        upload.public = public;
+    }
+
+    if let Some(limit) = limit {
+        upload.remaining = if upload.limit == limit {
+            upload.remaining.or(limit)
+        } else {
+            limit
+        };
+
+        upload.limit = limit;
+    }
+
+    if let Some(expiry_date) = expiry_date {
+        upload.expiry_date = expiry_date.map(|ExpiryDate(date)| date);
+    }
+
+    if let Some(password) = password {
+        upload.password = match password {
+            Some(ref password) => Some(StoredPassword::new(password)?),
+            None => None,
+        };
+    }
+
+    if let Some(custom_slug) = custom_slug {
+        upload.custom_slug = custom_slug;
+    }
+
+    tracing::info!(
+        upload = %id,
+        filename = ?upload.filename,
+        public = upload.public,
+        limit = ?upload.limit,
+        remaining = ?upload.remaining,
+        expiry = ?upload.expiry_date,
+        custom_slug = ?upload.custom_slug,
+        "Updating upload via API");
+
+    upload.save(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %upload.id, "Failed to save upload");
+        InternalServerError(err)
+    })?;
+
+    auditor
+        .record(
+            &env,
+            &user,
+            AuditAction::UploadEdit,
+            AuditTarget::Upload(upload.id),
+            Some(upload_change_details(&before, &upload)),
+        )
+        .await;
+
+    Ok(Json(UploadItem::from(upload)).into_response())
+}
+
+#[handler]
+pub async fn delete_upload(
+    env: Data<&Env>,
+    user: ApiUser,
+    auditor: Auditor,
+    Path(id): Path<Key<Upload>>,
+) -> poem::Result<StatusCode> {
+    let upload = get_upload_by_id(&env, id).await?;
+    user.require_permits(&upload, &UploadPermission::Delete)?;
+    check_permission(&env, &upload, Some(&user.0), UploadPermission::Delete).await?;
+
+    upload.delete(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %upload.id, "Unable to delete upload");
+        InternalServerError(err)
+    })?;
+
+    delete_upload_cache(&env, &upload).await;
+
+    auditor
+        .record(
+            &env,
+            &user,
+            AuditAction::UploadDelete,
+            AuditTarget::Upload(upload.id),
+            Some(serde_json::json!({ "before": upload_audit_details(&upload) })),
+        )
+        .await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[handler]
+pub async fn get_download(
+    env: Data<&Env>,
+    request: &Request,
+    user: ApiUser,
+    Path(id): Path<Key<Upload>>,
+) -> poem::Result<Response> {
+    let upload = get_upload_by_id(&env, id).await?;
+    user.require_permits(
+        &upload,
+        &UploadPermission::Download {
+            with_password: false,
+        },
+    )?;
+
+    // Owners of an upload can always download it, even when it has a password, has expired or has
+    // no remaining downloads. Everyone else is subject to the same checks as the download page.
+    let owner = upload.is_owner(&env.pool, &user).await.map_err(|err| {
+        tracing::error!(?err, %upload.id, %user.id, "Failed to check upload ownership");
+        InternalServerError(err)
+    })?;
+
+    if owner.is_none() {
+        check_permission(
+            &env,
+            &upload,
+            Some(&user.0),
+            UploadPermission::Download {
+                with_password: false,
+            },
+        )
+        .await?;
+    }
+
+    send_download(&env, upload, None, Some(&user.0), request).await
+}
+
+#[handler]
+pub async fn post_reset(
+    env: Data<&Env>,
+    user: ApiUser,
+    auditor: Auditor,
+    Path(id): Path<Key<Upload>>,
+) -> poem::Result<Json<UploadItem>> {
+    let mut upload = get_upload_by_id(&env, id).await?;
+    user.require_permits(&upload, &UploadPermission::ResetDownloads)?;
+    check_permission(
+        &env,
+        &upload,
+        Some(&user.0),
+        UploadPermission::ResetDownloads,
+    )
+    .await?;
+
+    tracing::info!(%upload.id, "Resetting upload download stats via API");
+    let before = upload.clone();
+    upload.reset_remaining(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %upload.id, "Failed to reset upload remaining downloads");
+        InternalServerError(err)
+    })?;
+
+    auditor
+        .record(
+            &env,
+            &user,
+            AuditAction::UploadEdit,
+            AuditTarget::Upload(upload.id),
+            Some(upload_change_details(&before, &upload)),
+        )
+        .await;
+
+    let upload = get_upload_by_id(&env, id).await?;
+    Ok(Json(upload.into()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransferRequest {
+    team: Key<Team>,
+    action: TransferAction,
+}
+
+#[handler]
+pub async fn post_transfer(
+    env: Data<&Env>,
+    user: ApiUser,
+    auditor: Auditor,
+    Path(id): Path<Key<Upload>>,
+    Json(TransferRequest { team, action }): Json<TransferRequest>,
+) -> poem::Result<Json<UploadItem>> {
+    let upload = get_upload_by_id(&env, id).await?;
+    user.require_permits(&upload, &UploadPermission::Transfer)?;
+    check_permission(&env, &upload, Some(&user.0), UploadPermission::Transfer).await?;
+
+    // The user must also be a member of the team, which is checked by `transfer_upload`.
+    user.require_transfer_to(team)?;
+
+    let upload = transfer_upload(&env, &auditor, &user, upload, team, action).await?;
+    Ok(Json(upload.into()))
+}
@@ -0,0 +1,131 @@
+use poem::{
+    error::InternalServerError,
+    handler,
+    web::{Data, Json, Query},
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use parcel_model::{
+    api_token::TokenScope,
+    types::Key,
+    upload::{UploadOrder, UploadStats},
+    user::{User, UserList},
+};
+
+use crate::{
+    app::extractors::api::{ApiAdmin, ApiUser},
+    env::Env,
+};
+
+/// The number of users returned in each page of the user list.
+const PAGE_SIZE: u32 = 50;
+
+#[derive(Debug, Serialize)]
+pub struct CurrentUser {
+    id: Key<User>,
+    username: String,
+    name: String,
+    admin: bool,
+    has_totp: bool,
+    limit: Option<i64>,
+    default_order: UploadOrder,
+    default_asc: bool,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    usage: UploadStats,
+}
+
+#[handler]
+pub async fn get_me(env: Data<&Env>, api_user: ApiUser) -> poem::Result<Json<CurrentUser>> {
+    api_user.require_scope(TokenScope::Read)?;
+
+    let ApiUser(user, _) = api_user;
+    let usage = UploadStats::get_for_user(&env.pool, user.id)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %user.id, "Failed to get upload stats for user");
+            InternalServerError(err)
+        })?;
+
+    Ok(Json(CurrentUser {
+        id: user.id,
+        username: user.username,
+        name: user.name,
+        admin: user.admin,
+        has_totp: user.totp.is_some(),
+        limit: user.limit,
+        default_order: user.default_order,
+        default_asc: user.default_asc,
+        created_at: user.created_at,
+        usage,
+    }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserListItem {
+    id: Key<User>,
+    username: String,
+    name: String,
+    enabled: bool,
+    admin: bool,
+    has_totp: bool,
+    limit: Option<i64>,
+    team_count: i64,
+    upload_total: i64,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    last_access: Option<OffsetDateTime>,
+}
+
+impl From<UserList> for UserListItem {
+    fn from(user: UserList) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            name: user.name,
+            enabled: user.enabled,
+            admin: user.admin,
+            has_totp: user.has_totp,
+            limit: user.limit,
+            team_count: user.team_count,
+            upload_total: user.upload_total,
+            created_at: user.created_at,
+            last_access: user.last_access,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserPage {
+    page: u32,
+    per_page: u32,
+    users: Vec<UserListItem>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsersQuery {
+    #[serde(default)]
+    page: u32,
+}
+
+#[handler]
+pub async fn get_users(
+    env: Data<&Env>,
+    ApiAdmin(admin): ApiAdmin,
+    Query(UsersQuery { page }): Query<UsersQuery>,
+) -> poem::Result<Json<UserPage>> {
+    let users = UserList::get_with_pagination(&env.pool, PAGE_SIZE * page, PAGE_SIZE)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %admin.id, "Failed to get list of users");
+            InternalServerError(err)
+        })?;
+
+    Ok(Json(UserPage {
+        page,
+        per_page: PAGE_SIZE,
+        users: users.into_iter().map(UserListItem::from).collect(),
+    }))
+}
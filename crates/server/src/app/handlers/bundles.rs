@@ -0,0 +1,165 @@
+use minijinja::context;
+use poem::{
+    error::InternalServerError,
+    handler,
+    session::Session,
+    web::{CsrfToken, CsrfVerifier, Data, Html, Path, Query},
+    IntoResponse, Response,
+};
+use serde::Deserialize;
+use serde_json::json;
+use time::OffsetDateTime;
+
+use parcel_model::{
+    audit::{AuditAction, AuditTarget},
+    bundle::Bundle,
+    team::{Team, TeamMember},
+    types::Key,
+    upload::UploadPermission,
+};
+
+use crate::{
+    app::{
+        errors::CsrfError,
+        extractors::{audit::Auditor, user::SessionUser},
+        handlers::utils::{
+            bundle_audit_details, check_bundle_permission, get_bundle_by_id, get_bundle_by_slug,
+        },
+        templates::{authorized_context, default_context, render_template},
+    },
+    env::Env,
+    utils::SessionExt,
+};
+
+mod download;
+mod edit;
+
+pub use download::{get_download, post_download};
+pub use edit::{get_edit, post_edit};
+
+#[handler]
+pub async fn get_bundle(
+    env: Data<&Env>,
+    session: &Session,
+    user: Option<SessionUser>,
+    csrf_token: &CsrfToken,
+    Path(slug): Path<String>,
+) -> poem::Result<Html<String>> {
+    let bundle = get_bundle_by_slug(&env, &slug).await?;
+    check_bundle_permission(&env, &bundle, user.as_deref(), UploadPermission::View).await?;
+
+    let owner = if let Some(SessionUser(user)) = &user {
+        bundle
+            .is_owner(&env.pool, user)
+            .await
+            .map_err(|err| {
+                tracing::error!(?err, %bundle.id, %user.id, "Failed to check bundle ownership");
+                InternalServerError(err)
+            })?
+            .is_some()
+    } else {
+        false
+    };
+
+    let membership = match (&user, bundle.owner_team) {
+        (Some(SessionUser(user)), Some(team_id)) => {
+            TeamMember::get_for_user_and_team(&env.pool, user.id, team_id)
+                .await
+                .map_err(|err| {
+                    tracing::error!(?err, %user.id, %team_id, "Failed to get team membership");
+                    InternalServerError(err)
+                })?
+        }
+
+        _ => None,
+    };
+
+    let team = if let Some(team_id) = bundle.owner_team {
+        Team::get(&env.pool, team_id).await.map_err(|err| {
+            tracing::error!(?err, %team_id, "Unable to get team by ID");
+            InternalServerError(err)
+        })?
+    } else {
+        None
+    };
+
+    let uploads = bundle.get_uploads(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %bundle.id, "Unable to get uploads in bundle");
+        InternalServerError(err)
+    })?;
+
+    let exhausted = bundle.remaining.is_some_and(|remaining| remaining < 1);
+    let expired = bundle
+        .expiry_date
+        .is_some_and(|expiry| expiry < OffsetDateTime::now_utc().date());
+    let can_download = !uploads.is_empty() && !exhausted && !expired;
+    let total_size = uploads.iter().map(|upload| upload.size).sum::<i64>();
+
+    render_template(
+        "bundles/view.html",
+        context! {
+            exhausted,
+            expired,
+            has_password => bundle.password.is_some(),
+            bundle,
+            uploads,
+            total_size,
+            team,
+            membership,
+            owner,
+            can_download,
+            csrf_token => csrf_token.0,
+            error => session.take::<String>("download_error"),
+            ..if let Some(SessionUser(user)) = &user {
+                authorized_context(&env, user)
+            } else {
+                default_context(&env)
+            }
+        },
+    )
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteBundleQuery {
+    csrf_token: String,
+}
+
+/// Delete a bundle, leaving the uploads that were in it.
+#[handler]
+pub async fn delete_bundle(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    auditor: Auditor,
+    csrf_verifier: &CsrfVerifier,
+    Path(id): Path<Key<Bundle>>,
+    Query(DeleteBundleQuery { csrf_token }): Query<DeleteBundleQuery>,
+) -> poem::Result<Response> {
+    if !csrf_verifier.is_valid(&csrf_token) {
+        tracing::warn!(%user.id, %id, "CSRF token verification failed for bundle deletion");
+        return Err(CsrfError.into());
+    }
+
+    let bundle = get_bundle_by_id(&env, id).await?;
+    check_bundle_permission(&env, &bundle, Some(&user), UploadPermission::Delete).await?;
+
+    bundle.delete(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %bundle.id, "Unable to delete bundle");
+        InternalServerError(err)
+    })?;
+
+    tracing::info!(%bundle.id, "Deleted bundle");
+    auditor
+        .record(
+            &env,
+            &user,
+            AuditAction::BundleDelete,
+            AuditTarget::Bundle(bundle.id),
+            Some(json!({ "before": bundle_audit_details(&bundle) })),
+        )
+        .await;
+
+    Ok(Html("")
+        .with_header("HX-Trigger", "parcelBundleDeleted")
+        .into_response())
+}
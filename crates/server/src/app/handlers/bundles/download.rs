@@ -0,0 +1,152 @@
+use poem::{
+    error::InternalServerError,
+    handler,
+    http::{
+        header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE},
+        Method, StatusCode,
+    },
+    session::Session,
+    web::{CsrfVerifier, Data, Form, Path, Redirect},
+    Body, IntoResponse, Request, Response,
+};
+use serde::Deserialize;
+
+use parcel_model::{bundle::Bundle, upload::UploadPermission};
+
+use crate::{
+    app::{
+        errors::CsrfError,
+        extractors::user::SessionUser,
+        handlers::{
+            uploads::content_disposition_filename,
+            utils::{check_bundle_permission, get_bundle_by_slug},
+        },
+    },
+    archive::{ZipArchive, ZipEntry},
+    env::Env,
+};
+
+/// Send all of the uploads in a bundle to the client as a ZIP archive.
+///
+/// The archive is built from the files in storage as it is sent, so nothing is written to disk.
+/// A download of the bundle is counted against the bundle's own download limit, rather than the
+/// limits of the uploads in it.
+async fn send_bundle(env: &Env, mut bundle: Bundle, request: &Request) -> poem::Result<Response> {
+    let uploads = bundle.get_uploads(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %bundle.id, "Unable to get uploads in bundle");
+        InternalServerError(err)
+    })?;
+
+    if uploads.is_empty() {
+        tracing::error!(%bundle.id, "Bundle has no uploads to download");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    }
+
+    let entries = uploads
+        .into_iter()
+        .map(|upload| ZipEntry {
+            name: upload.filename,
+            key: upload.blob,
+            size: upload.size as u64,
+            modified: upload.uploaded_at,
+        })
+        .collect::<Vec<_>>();
+
+    let archive = ZipArchive::new(entries);
+    let length = archive.len();
+
+    let builder = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/zip")
+        .header(CONTENT_LENGTH, length)
+        .header(
+            CONTENT_DISPOSITION,
+            content_disposition_filename(&format!("{}.zip", bundle.name)),
+        );
+
+    if request.method() == Method::HEAD {
+        return Ok(builder.finish());
+    }
+
+    bundle.record_download(&env.pool).await.map_err(|err| {
+        tracing::error!(%bundle.id, ?err, "Unable to record download of bundle");
+        InternalServerError(err)
+    })?;
+
+    tracing::info!(%bundle.id, length, "Sending bundle archive to client");
+    Ok(builder.body(Body::from_async_read(archive.stream(env.clone()))))
+}
+
+#[handler]
+pub async fn get_download(
+    env: Data<&Env>,
+    request: &Request,
+    user: Option<SessionUser>,
+    Path(slug): Path<String>,
+) -> poem::Result<Response> {
+    let bundle = get_bundle_by_slug(&env, &slug).await?;
+    check_bundle_permission(
+        &env,
+        &bundle,
+        user.as_deref(),
+        UploadPermission::Download {
+            with_password: false,
+        },
+    )
+    .await?;
+
+    send_bundle(&env, bundle, request).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DownloadForm {
+    csrf_token: String,
+    password: String,
+}
+
+#[handler]
+pub async fn post_download(
+    env: Data<&Env>,
+    request: &Request,
+    session: &Session,
+    user: Option<SessionUser>,
+    verifier: &CsrfVerifier,
+    Path(slug): Path<String>,
+    Form(DownloadForm {
+        csrf_token,
+        password,
+    }): Form<DownloadForm>,
+) -> poem::Result<Response> {
+    if !verifier.is_valid(&csrf_token) {
+        tracing::error!("CSRF token is invalid in bundle download");
+        return Err(CsrfError.into());
+    }
+
+    let mut bundle = get_bundle_by_slug(&env, &slug).await?;
+    check_bundle_permission(
+        &env,
+        &bundle,
+        user.as_deref(),
+        UploadPermission::Download {
+            with_password: true,
+        },
+    )
+    .await?;
+
+    let Some(ref hash) = bundle.password else {
+        return Ok(Redirect::see_other(format!("/bundles/{slug}/download")).into_response());
+    };
+
+    if !hash.verify(&password) {
+        tracing::error!(%bundle.id, "Invalid password provided for bundle");
+        session.set("download_error", "Incorrect password");
+        return Ok(Redirect::see_other(format!("/bundles/{slug}")).into_response());
+    }
+
+    if hash.needs_migrating() {
+        tracing::info!(%bundle.id, "Migrating bundle password hash");
+        bundle.set_password(&env.pool, &password).await?;
+    }
+
+    send_bundle(&env, bundle, request).await
+}
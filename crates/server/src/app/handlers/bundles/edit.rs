@@ -0,0 +1,196 @@
+use minijinja::context;
+use poem::{
+    error::InternalServerError,
+    handler,
+    http::StatusCode,
+    web::{CsrfToken, CsrfVerifier, Data, Form, Html, Path},
+    IntoResponse, Response,
+};
+use serde::Deserialize;
+use serde_json::json;
+use time::Date;
+use validator::{Validate, ValidationErrors};
+
+use parcel_model::{
+    audit::{AuditAction, AuditTarget},
+    bundle::Bundle,
+    password::StoredPassword,
+    types::Key,
+    upload::UploadPermission,
+};
+
+use crate::{
+    app::{
+        errors::CsrfError,
+        extractors::{audit::Auditor, user::SessionUser},
+        handlers::utils::{bundle_audit_details, check_bundle_permission, get_bundle_by_id},
+        templates::{authorized_context, render_template},
+    },
+    env::Env,
+    utils::ValidationErrorsExt,
+};
+
+#[handler]
+pub async fn get_edit(
+    env: Data<&Env>,
+    token: &CsrfToken,
+    SessionUser(user): SessionUser,
+    Path(id): Path<Key<Bundle>>,
+) -> poem::Result<Html<String>> {
+    let bundle = get_bundle_by_id(&env, id).await?;
+    check_bundle_permission(&env, &bundle, Some(&user), UploadPermission::Edit).await?;
+
+    render_template(
+        "bundles/edit.html",
+        context! {
+            token => token.0,
+            now => time::OffsetDateTime::now_utc(),
+            has_password => bundle.password.is_some(),
+            bundle,
+            ..authorized_context(&env, &user)
+        },
+    )
+    .await
+}
+
+time::serde::format_description!(iso8601_date, Date, "[year]-[month]-[day]");
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct BundleEditForm {
+    token: String,
+    #[validate(length(min = 1, max = 100))]
+    name: String,
+    public: Option<String>,
+    limit: Option<i64>,
+    #[serde(default, with = "iso8601_date::option")]
+    expiry_date: Option<Date>,
+    has_password: Option<String>,
+    change_password: Option<String>,
+    password: Option<String>,
+}
+
+#[handler]
+pub async fn post_edit(
+    env: Data<&Env>,
+    next_token: &CsrfToken,
+    verifier: &CsrfVerifier,
+    SessionUser(user): SessionUser,
+    auditor: Auditor,
+    Path(id): Path<Key<Bundle>>,
+    Form(form): Form<BundleEditForm>,
+) -> poem::Result<Response> {
+    if !verifier.is_valid(&form.token) {
+        tracing::error!("CSRF token is invalid in bundle edit");
+        return Err(CsrfError.into());
+    }
+
+    let mut bundle = get_bundle_by_id(&env, id).await?;
+    check_bundle_permission(&env, &bundle, Some(&user), UploadPermission::Edit).await?;
+
+    let mut errors = ValidationErrors::new();
+    if let Err(form_errors) = form.validate() {
+        errors.merge(form_errors);
+    }
+
+    if !errors.is_empty() {
+        return Ok(render_template(
+            "bundles/edit.html",
+            context! {
+                errors,
+                token => next_token.0,
+                now => time::OffsetDateTime::now_utc(),
+                has_password => bundle.password.is_some(),
+                form => context!{
+                    name => &form.name,
+                    public => form.public.as_deref() == Some("on"),
+                    limit => form.limit,
+                    expiry_date => form.expiry_date,
+                    has_password => form.has_password.as_deref() == Some("on"),
+                    change_password => form.change_password.as_deref() == Some("on"),
+                    password => &form.password,
+                },
+                bundle,
+                ..authorized_context(&env, &user)
+            },
+        )
+        .await?
+        .with_header("HX-Retarget", "#bundle-form")
+        .with_header("HX-Reselect", "#bundle-form")
+        .into_response());
+    }
+
+    let before = bundle.clone();
+    let BundleEditForm {
+        name,
+        public,
+        limit,
+        expiry_date,
+        has_password,
+        password,
+        ..
+    } = form;
+
+    let public = public.as_deref() == Some("on");
+
+    let remaining = if bundle.limit == limit {
+        bundle.remaining.or(limit)
+    } else {
+        limit
+    };
+
+    let has_password = has_password.as_deref() == Some("on");
+    if has_password {
+        if let Some(ref password) = password {
+            bundle.password = Some(StoredPassword::new(password)?);
+        } else if bundle.password.is_none() {
+            tracing::error!("Password is required but not provided");
+            return Err(poem::Error::from_status(StatusCode::BAD_REQUEST));
+        }
+    } else {
+        bundle.password = None;
+    }
+
+    tracing::info!(
+        bundle = %id,
+        name = ?name,
+        limit = ?limit,
+        remaining = ?remaining,
+        expiry = ?expiry_date,
+        has_password = ?has_password,
+        new_password = password.is_some(),
+        "Updating bundle");
+
+    bundle.name = name;
+    bundle.public = public;
+    bundle.limit = limit;
+    bundle.remaining = remaining;
+    bundle.expiry_date = expiry_date;
+
+    bundle.save(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %bundle.id, "Failed to save bundle");
+        InternalServerError(err)
+    })?;
+
+    auditor
+        .record(
+            &env,
+            &user,
+            AuditAction::BundleEdit,
+            AuditTarget::Bundle(bundle.id),
+            Some(json!({
+                "before": bundle_audit_details(&before),
+                "after": bundle_audit_details(&bundle),
+            })),
+        )
+        .await;
+
+    Ok(Html("")
+        .with_header(
+            "HX-Trigger",
+            json!({
+                "parcelBundleChanged": id,
+            })
+            .to_string(),
+        )
+        .into_response())
+}
@@ -3,18 +3,47 @@ use base64::Engine;
 use poem::{
     error::InternalServerError,
     http::StatusCode,
-    web::{Data, Json},
+    web::{Data, Json, Path},
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 use time::OffsetDateTime;
 
-use parcel_model::{password::StoredPassword, team::Team, types::Key, upload::Upload, user::User};
+use parcel_model::{
+    blob::Blob, password::StoredPassword, team::Team, types::Key, upload::Upload, user::User,
+};
 
-use crate::env::Env;
+use crate::{
+    app::handlers::utils::{hash_and_store_blob, new_temp_path},
+    env::Env,
+    workers::ldap::sync_teams,
+};
 
 async fn empty_tables(pool: &SqlitePool) -> poem::Result<()> {
-    const TABLE_NAMES: &[&str] = &["uploads", "team_members", "teams", "users"];
+    const TABLE_NAMES: &[&str] = &[
+        "audit_events",
+        "downloads",
+        "share_links",
+        "upload_tags",
+        "saved_filters",
+        "uploads",
+        "upload_requests",
+        "webhook_deliveries",
+        "webhooks",
+        "bundles",
+        "folders",
+        "blobs",
+        "upload_sessions",
+        "api_tokens",
+        "user_tokens",
+        "password_reset_requests",
+        "webauthn_ceremonies",
+        "webauthn_credentials",
+        "totp_recovery_codes",
+        "team_members",
+        "teams",
+        "users",
+    ];
 
     for table_name in TABLE_NAMES.iter() {
         sqlx::query(&format!("DELETE FROM {table_name}"))
@@ -47,6 +76,8 @@ struct InitialUser {
     #[serde(default, rename = "passwordHash")]
     password_hash: Option<String>,
     admin: bool,
+    #[serde(default)]
+    limit: Option<i64>,
 }
 
 #[poem::handler]
@@ -61,12 +92,13 @@ async fn initial_users(env: Data<&Env>, Json(users): Json<Vec<InitialUser>>) ->
             StoredPassword::new(&user.password).context("failed to hash password for user")?
         };
 
-        sqlx::query("INSERT INTO users (id, username, name, password, enabled, admin, created_at) VALUES (?, ?, ?, ?, 1, ?, ?)")
+        sqlx::query("INSERT INTO users (id, username, name, password, enabled, admin, \"limit\", created_at) VALUES (?, ?, ?, ?, 1, ?, ?, ?)")
             .bind(Key::<User>::new())
             .bind(&user.username)
             .bind(&user.name)
             .bind(hash)
             .bind(user.admin)
+            .bind(user.limit)
             .bind(OffsetDateTime::now_utc())
             .execute(&env.pool)
             .await.map_err(|err| {
@@ -203,15 +235,19 @@ async fn post_uploads(
             })?;
 
         let slug = nanoid::nanoid!();
-        let path = env.cache_dir.join(&slug);
+        let path = new_temp_path(&env);
         tokio::fs::write(&path, &content).await.map_err(|err| {
             tracing::error!(?path, ?err, "Failed to write file");
             InternalServerError(err)
         })?;
 
+        let blob = hash_and_store_blob(&env, &path, content.len() as i64).await?;
+
         let upload = Upload {
             id: Key::new(),
             slug,
+            hash: Some(blob.clone()),
+            blob,
             filename,
             size: content.len() as i64,
             public: false,
@@ -229,6 +265,10 @@ async fn post_uploads(
             mime_type: None,
             has_preview: false,
             preview_error: None,
+            bundle: None,
+            folder: None,
+            text_extracted: false,
+            upload_request: None,
         };
 
         upload.create(&env.pool).await.map_err(|err| {
@@ -242,6 +282,42 @@ async fn post_uploads(
     Ok(Json(result))
 }
 
+#[derive(Debug, Serialize)]
+struct BlobState {
+    /// The number of references to the blob, or `None` if there is no blob with the key.
+    refs: Option<i64>,
+    /// Whether the file for the blob is in storage.
+    stored: bool,
+}
+
+/// Check whether a blob is still in the database and in storage.
+#[poem::handler]
+async fn get_blob(env: Data<&Env>, Path(key): Path<String>) -> poem::Result<Json<BlobState>> {
+    let blob = Blob::get(&env.pool, &key).await.map_err(|err| {
+        tracing::error!(?err, ?key, "Failed to get blob");
+        InternalServerError(err)
+    })?;
+
+    let stored = env.storage.exists(&key).await.map_err(|err| {
+        tracing::error!(?err, ?key, "Failed to check if blob is in storage");
+        InternalServerError(err)
+    })?;
+
+    Ok(Json(BlobState {
+        refs: blob.map(|blob| blob.refs),
+        stored,
+    }))
+}
+
+/// Synchronise teams with LDAP groups now, rather than waiting for the LDAP worker.
+#[poem::handler]
+async fn ldap_sync(env: Data<&Env>) -> poem::Result<()> {
+    sync_teams(&env).await.map_err(|err| {
+        tracing::error!(?err, "Failed to synchronise teams with LDAP groups");
+        poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR)
+    })
+}
+
 pub fn add_debug_routes(app: poem::Route) -> poem::Route {
     use poem::{get, post};
 
@@ -249,4 +325,6 @@ pub fn add_debug_routes(app: poem::Route) -> poem::Route {
         .at("/debug/initial-users", post(initial_users))
         .at("/debug/initial-teams", post(post_initial_teams))
         .at("/debug/uploads", post(post_uploads))
+        .at("/debug/blobs/:key", get(get_blob))
+        .at("/debug/ldap-sync", get(ldap_sync))
 }
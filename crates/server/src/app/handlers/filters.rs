@@ -0,0 +1,223 @@
+use minijinja::context;
+use poem::{
+    error::InternalServerError,
+    handler,
+    http::StatusCode,
+    web::{CsrfToken, CsrfVerifier, Data, Form, Html, Path, Query},
+    IntoResponse, Response,
+};
+use serde::Deserialize;
+use validator::Validate;
+
+use parcel_model::{
+    saved_filter::SavedFilter,
+    team::{Team, TeamMember},
+    types::Key,
+    user::User,
+};
+
+use crate::{
+    app::{
+        errors::CsrfError,
+        extractors::user::SessionUser,
+        handlers::{uploads::ListQuery, utils::get_team_for_member},
+        templates::{authorized_context, render_template},
+    },
+    env::Env,
+};
+
+/// Get the team that a saved filter will belong to, checking that the user can edit its uploads.
+async fn get_team_for_filter(
+    env: &Env,
+    user: &User,
+    team: Option<Key<Team>>,
+) -> poem::Result<Option<Team>> {
+    let Some(team_id) = team else {
+        return Ok(None);
+    };
+
+    let team = get_team_for_member(env, user, team_id).await?;
+    check_can_edit(env, user, &team).await?;
+    Ok(Some(team))
+}
+
+async fn check_can_edit(env: &Env, user: &User, team: &Team) -> poem::Result<()> {
+    let membership = TeamMember::get_for_user_and_team(&env.pool, user.id, team.id)
+        .await
+        .map_err(|err| {
+            tracing::error!(%user.id, %team.id, ?err, "Unable to get team membership");
+            InternalServerError(err)
+        })?;
+
+    if !membership.is_some_and(|membership| membership.can_edit) {
+        tracing::error!(%user.id, %team.id, "User cannot save filters for team");
+        return Err(poem::Error::from_status(StatusCode::FORBIDDEN));
+    }
+
+    Ok(())
+}
+
+/// Encode the filters of the upload list as a query string, leaving out the folder, the order,
+/// and the saved filter that is currently shown.
+fn encode_filter(query: ListQuery) -> poem::Result<String> {
+    let query = ListQuery {
+        order: None,
+        asc: None,
+        folder: None,
+        saved: None,
+        ..query
+    };
+
+    if query.get_filter().is_empty() {
+        tracing::error!("Cannot save a filter that includes every upload");
+        return Err(poem::Error::from_status(StatusCode::BAD_REQUEST));
+    }
+
+    serde_html_form::to_string(&query).map_err(|err| {
+        tracing::error!(?err, "Unable to encode filter");
+        InternalServerError(err)
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewFilterQuery {
+    #[serde(default)]
+    team: Option<Key<Team>>,
+}
+
+#[handler]
+pub async fn get_new(
+    env: Data<&Env>,
+    token: &CsrfToken,
+    SessionUser(user): SessionUser,
+    Query(NewFilterQuery { team }): Query<NewFilterQuery>,
+    Query(query): Query<ListQuery>,
+) -> poem::Result<Html<String>> {
+    let team = get_team_for_filter(&env, &user, team).await?;
+    let query = encode_filter(query)?;
+
+    render_template(
+        "filters/new.html",
+        context! {
+            token => token.0,
+            team,
+            query,
+            ..authorized_context(&env, &user)
+        },
+    )
+    .await
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct NewFilterForm {
+    token: String,
+    #[validate(length(min = 1, max = 50))]
+    name: String,
+    #[serde(default)]
+    team: Option<Key<Team>>,
+    /// The filter to save, encoded as the query string of the upload list.
+    query: String,
+}
+
+#[handler]
+pub async fn post_new(
+    env: Data<&Env>,
+    next_token: &CsrfToken,
+    verifier: &CsrfVerifier,
+    SessionUser(user): SessionUser,
+    Form(form): Form<NewFilterForm>,
+) -> poem::Result<Response> {
+    if !verifier.is_valid(&form.token) {
+        tracing::error!("CSRF token is invalid in new filter form");
+        return Err(CsrfError.into());
+    }
+
+    let team = get_team_for_filter(&env, &user, form.team).await?;
+
+    let query = serde_html_form::from_str::<ListQuery>(&form.query).map_err(|err| {
+        tracing::error!(?err, query = ?form.query, "Invalid filter in form data");
+        poem::Error::from_status(StatusCode::BAD_REQUEST)
+    })?;
+
+    let query = encode_filter(query)?;
+
+    if let Err(errors) = form.validate() {
+        return Ok(render_template(
+            "filters/new.html",
+            context! {
+                errors,
+                token => next_token.0,
+                form => context! {
+                    name => &form.name,
+                },
+                team,
+                query,
+                ..authorized_context(&env, &user)
+            },
+        )
+        .await?
+        .with_header("HX-Retarget", "#filter-form")
+        .with_header("HX-Reselect", "#filter-form")
+        .into_response());
+    }
+
+    let filter = SavedFilter::new(
+        form.name.trim(),
+        query,
+        if team.is_none() { Some(user.id) } else { None },
+        team.map(|team| team.id),
+        user.id,
+    );
+
+    filter.create(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %user.id, "Unable to create saved filter");
+        InternalServerError(err)
+    })?;
+
+    tracing::info!(%filter.id, ?filter.owner_team, "Saved filter");
+    Ok(Html("").with_header("HX-Refresh", "true").into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteFilterQuery {
+    csrf_token: String,
+}
+
+#[handler]
+pub async fn delete_filter(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    csrf_verifier: &CsrfVerifier,
+    Path(id): Path<Key<SavedFilter>>,
+    Query(DeleteFilterQuery { csrf_token }): Query<DeleteFilterQuery>,
+) -> poem::Result<Response> {
+    if !csrf_verifier.is_valid(&csrf_token) {
+        tracing::warn!(%user.id, %id, "CSRF token verification failed for filter deletion");
+        return Err(CsrfError.into());
+    }
+
+    let Some(filter) = SavedFilter::get(&env.pool, id).await.map_err(|err| {
+        tracing::error!(?err, %id, "Unable to get saved filter by ID");
+        InternalServerError(err)
+    })?
+    else {
+        tracing::error!(%id, "Saved filter not found");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    if let Some(team_id) = filter.owner_team {
+        let team = get_team_for_member(&env, &user, team_id).await?;
+        check_can_edit(&env, &user, &team).await?;
+    } else if filter.owner_user != Some(user.id) {
+        tracing::error!(%user.id, %filter.id, "User does not own saved filter");
+        return Err(poem::Error::from_status(StatusCode::FORBIDDEN));
+    }
+
+    filter.delete(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %filter.id, "Unable to delete saved filter");
+        InternalServerError(err)
+    })?;
+
+    tracing::info!(%filter.id, "Deleted saved filter");
+    Ok(Html("").with_header("HX-Refresh", "true").into_response())
+}
@@ -0,0 +1,232 @@
+use minijinja::context;
+use poem::{
+    error::InternalServerError,
+    handler,
+    http::StatusCode,
+    web::{CsrfVerifier, Data, Html, Path, Query},
+    IntoResponse, Request, Response,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use time::OffsetDateTime;
+
+use parcel_model::{
+    audit::{AuditAction, AuditTarget},
+    folder::Folder,
+    team::Team,
+    types::Key,
+    upload::{Upload, UploadPermission},
+};
+
+use crate::{
+    app::{
+        errors::CsrfError,
+        extractors::{audit::Auditor, user::SessionUser},
+        handlers::{
+            uploads::send_download,
+            utils::{
+                check_folder_permission, folder_audit_details, get_folder_by_id,
+                get_folder_by_slug, get_upload_by_id,
+            },
+        },
+        templates::{authorized_context, default_context, render_template},
+    },
+    env::Env,
+};
+
+mod edit;
+mod new;
+
+pub use edit::{get_edit, get_move_folder, post_edit, post_move_folder};
+pub use new::{get_new, post_new};
+
+/// Check whether an upload in a shared folder can be downloaded by someone who does not own it.
+///
+/// Uploads that have a password, have reached their download limit, or have expired can only be
+/// downloaded from their own page.
+fn is_available(upload: &Upload) -> bool {
+    let exhausted = upload.remaining.is_some_and(|remaining| remaining < 1);
+    let expired = upload
+        .expiry_date
+        .is_some_and(|expiry| expiry < OffsetDateTime::now_utc().date());
+
+    !exhausted && !expired && upload.password.is_none()
+}
+
+async fn is_folder_owner(
+    env: &Env,
+    folder: &Folder,
+    user: Option<&SessionUser>,
+) -> poem::Result<bool> {
+    let Some(SessionUser(user)) = user else {
+        return Ok(false);
+    };
+
+    if user.admin {
+        return Ok(true);
+    }
+
+    Ok(folder
+        .is_owner(&env.pool, user)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %folder.id, %user.id, "Failed to check folder ownership");
+            InternalServerError(err)
+        })?
+        .is_some())
+}
+
+#[derive(Debug, Serialize)]
+struct FolderFile {
+    upload: Upload,
+    available: bool,
+}
+
+#[handler]
+pub async fn get_folder(
+    env: Data<&Env>,
+    user: Option<SessionUser>,
+    Path(slug): Path<String>,
+) -> poem::Result<Html<String>> {
+    let folder = get_folder_by_slug(&env, &slug).await?;
+    check_folder_permission(&env, &folder, user.as_deref(), UploadPermission::View).await?;
+
+    let owner = is_folder_owner(&env, &folder, user.as_ref()).await?;
+
+    let team = if let Some(team_id) = folder.owner_team {
+        Team::get(&env.pool, team_id).await.map_err(|err| {
+            tracing::error!(?err, %team_id, "Unable to get team by ID");
+            InternalServerError(err)
+        })?
+    } else {
+        None
+    };
+
+    let mut path = folder.get_path(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %folder.id, "Unable to get path to folder");
+        InternalServerError(err)
+    })?;
+
+    // Only show the folders that have been shared to anyone that does not own the folder.
+    if !owner {
+        if let Some(index) = path.iter().position(|folder| folder.public) {
+            path.drain(..index);
+        }
+    }
+
+    let folders = folder.get_children(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %folder.id, "Unable to get folders in folder");
+        InternalServerError(err)
+    })?;
+
+    let files = folder
+        .get_uploads(&env.pool)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %folder.id, "Unable to get uploads in folder");
+            InternalServerError(err)
+        })?
+        .into_iter()
+        .map(|upload| FolderFile {
+            available: is_available(&upload),
+            upload,
+        })
+        .collect::<Vec<_>>();
+
+    let total_size = files.iter().map(|file| file.upload.size).sum::<i64>();
+
+    render_template(
+        "folders/view.html",
+        context! {
+            folder,
+            path,
+            folders,
+            files,
+            total_size,
+            team,
+            owner,
+            ..if let Some(SessionUser(user)) = &user {
+                authorized_context(&env, user)
+            } else {
+                default_context(&env)
+            }
+        },
+    )
+    .await
+}
+
+/// Download an upload from a folder, which allows anyone that can see a shared folder to
+/// download the files in it.
+#[handler]
+pub async fn get_file(
+    env: Data<&Env>,
+    request: &Request,
+    user: Option<SessionUser>,
+    Path((slug, file)): Path<(String, Key<Upload>)>,
+) -> poem::Result<Response> {
+    let folder = get_folder_by_slug(&env, &slug).await?;
+    check_folder_permission(
+        &env,
+        &folder,
+        user.as_deref(),
+        UploadPermission::Download {
+            with_password: false,
+        },
+    )
+    .await?;
+
+    let upload = get_upload_by_id(&env, file).await?;
+    if upload.folder != Some(folder.id) {
+        tracing::error!(%folder.id, %upload.id, "Upload is not in the folder");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    }
+
+    if !is_available(&upload) && !is_folder_owner(&env, &folder, user.as_ref()).await? {
+        tracing::error!(%folder.id, %upload.id, "Upload cannot be downloaded from the folder");
+        return Err(poem::Error::from_status(StatusCode::FORBIDDEN));
+    }
+
+    send_download(&env, upload, None, user.as_deref(), request).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteFolderQuery {
+    csrf_token: String,
+}
+
+/// Delete a folder, moving the uploads and folders that were in it into its parent.
+#[handler]
+pub async fn delete_folder(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    auditor: Auditor,
+    csrf_verifier: &CsrfVerifier,
+    Path(id): Path<Key<Folder>>,
+    Query(DeleteFolderQuery { csrf_token }): Query<DeleteFolderQuery>,
+) -> poem::Result<Response> {
+    if !csrf_verifier.is_valid(&csrf_token) {
+        tracing::warn!(%user.id, %id, "CSRF token verification failed for folder deletion");
+        return Err(CsrfError.into());
+    }
+
+    let folder = get_folder_by_id(&env, id).await?;
+    check_folder_permission(&env, &folder, Some(&user), UploadPermission::Delete).await?;
+
+    folder.delete(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %folder.id, "Unable to delete folder");
+        InternalServerError(err)
+    })?;
+
+    tracing::info!(%folder.id, "Deleted folder");
+    auditor
+        .record(
+            &env,
+            &user,
+            AuditAction::FolderDelete,
+            AuditTarget::Folder(folder.id),
+            Some(json!({ "before": folder_audit_details(&folder) })),
+        )
+        .await;
+
+    Ok(Html("").with_header("HX-Refresh", "true").into_response())
+}
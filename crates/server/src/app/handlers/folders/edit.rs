@@ -0,0 +1,259 @@
+use minijinja::context;
+use poem::{
+    error::InternalServerError,
+    handler,
+    http::StatusCode,
+    web::{CsrfToken, CsrfVerifier, Data, Form, Html, Path},
+    IntoResponse, Response,
+};
+use serde::Deserialize;
+use serde_json::json;
+use validator::Validate;
+
+use parcel_model::{
+    audit::{AuditAction, AuditTarget},
+    folder::{Folder, FolderTreeEntry},
+    types::Key,
+    upload::UploadPermission,
+};
+
+use crate::{
+    app::{
+        errors::CsrfError,
+        extractors::{audit::Auditor, user::SessionUser},
+        handlers::utils::{check_folder_permission, folder_audit_details, get_folder_by_id},
+        templates::{authorized_context, render_template},
+    },
+    env::Env,
+};
+
+#[handler]
+pub async fn get_edit(
+    env: Data<&Env>,
+    token: &CsrfToken,
+    SessionUser(user): SessionUser,
+    Path(id): Path<Key<Folder>>,
+) -> poem::Result<Html<String>> {
+    let folder = get_folder_by_id(&env, id).await?;
+    check_folder_permission(&env, &folder, Some(&user), UploadPermission::Edit).await?;
+
+    render_template(
+        "folders/edit.html",
+        context! {
+            token => token.0,
+            folder,
+            ..authorized_context(&env, &user)
+        },
+    )
+    .await
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct FolderEditForm {
+    token: String,
+    #[validate(length(min = 1, max = 100))]
+    name: String,
+    public: Option<String>,
+}
+
+#[handler]
+pub async fn post_edit(
+    env: Data<&Env>,
+    next_token: &CsrfToken,
+    verifier: &CsrfVerifier,
+    SessionUser(user): SessionUser,
+    auditor: Auditor,
+    Path(id): Path<Key<Folder>>,
+    Form(form): Form<FolderEditForm>,
+) -> poem::Result<Response> {
+    if !verifier.is_valid(&form.token) {
+        tracing::error!("CSRF token is invalid in folder edit");
+        return Err(CsrfError.into());
+    }
+
+    let mut folder = get_folder_by_id(&env, id).await?;
+    check_folder_permission(&env, &folder, Some(&user), UploadPermission::Edit).await?;
+
+    if let Err(errors) = form.validate() {
+        return Ok(render_template(
+            "folders/edit.html",
+            context! {
+                errors,
+                token => next_token.0,
+                form => context! {
+                    name => &form.name,
+                    public => form.public.as_deref() == Some("on"),
+                },
+                folder,
+                ..authorized_context(&env, &user)
+            },
+        )
+        .await?
+        .with_header("HX-Retarget", "#folder-form")
+        .with_header("HX-Reselect", "#folder-form")
+        .into_response());
+    }
+
+    let before = folder.clone();
+    let public = form.public.as_deref() == Some("on");
+
+    tracing::info!(folder = %id, name = ?form.name, public, "Updating folder");
+    folder.name = form.name.trim().to_string();
+    folder.public = public;
+
+    folder.save(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %folder.id, "Failed to save folder");
+        InternalServerError(err)
+    })?;
+
+    auditor
+        .record(
+            &env,
+            &user,
+            AuditAction::FolderEdit,
+            AuditTarget::Folder(folder.id),
+            Some(json!({
+                "before": folder_audit_details(&before),
+                "after": folder_audit_details(&folder),
+            })),
+        )
+        .await;
+
+    Ok(Html("").with_header("HX-Refresh", "true").into_response())
+}
+
+/// Get the folders that a folder could be moved into: every other folder with the same owner,
+/// except for the folders inside it.
+async fn get_destinations(env: &Env, folder: &Folder) -> poem::Result<Vec<FolderTreeEntry>> {
+    let tree = if let Some(team) = folder.owner_team {
+        Folder::get_tree_for_team(&env.pool, team).await
+    } else if let Some(user) = folder.owner_user {
+        Folder::get_tree_for_user(&env.pool, user).await
+    } else {
+        Ok(Vec::new())
+    }
+    .map_err(|err| {
+        tracing::error!(?err, %folder.id, "Unable to get folders");
+        InternalServerError(err)
+    })?;
+
+    let mut skip_below = None;
+    Ok(tree
+        .into_iter()
+        .filter(|entry| {
+            if let Some(depth) = skip_below {
+                if entry.depth > depth {
+                    return false;
+                }
+
+                skip_below = None;
+            }
+
+            if entry.folder.id == folder.id {
+                skip_below = Some(entry.depth);
+                return false;
+            }
+
+            true
+        })
+        .collect())
+}
+
+#[handler]
+pub async fn get_move_folder(
+    env: Data<&Env>,
+    token: &CsrfToken,
+    SessionUser(user): SessionUser,
+    Path(id): Path<Key<Folder>>,
+) -> poem::Result<Html<String>> {
+    let folder = get_folder_by_id(&env, id).await?;
+    check_folder_permission(&env, &folder, Some(&user), UploadPermission::Edit).await?;
+
+    let destinations = get_destinations(&env, &folder).await?;
+
+    render_template(
+        "folders/move.html",
+        context! {
+            token => token.0,
+            folder,
+            destinations,
+            ..authorized_context(&env, &user)
+        },
+    )
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoveFolderForm {
+    token: String,
+    /// The folder to move into, which is empty to move the folder to the top level.
+    #[serde(default)]
+    parent: String,
+}
+
+#[handler]
+pub async fn post_move_folder(
+    env: Data<&Env>,
+    verifier: &CsrfVerifier,
+    SessionUser(user): SessionUser,
+    auditor: Auditor,
+    Path(id): Path<Key<Folder>>,
+    Form(form): Form<MoveFolderForm>,
+) -> poem::Result<Response> {
+    if !verifier.is_valid(&form.token) {
+        tracing::error!("CSRF token is invalid in folder move");
+        return Err(CsrfError.into());
+    }
+
+    let mut folder = get_folder_by_id(&env, id).await?;
+    check_folder_permission(&env, &folder, Some(&user), UploadPermission::Edit).await?;
+
+    let parent = if form.parent.is_empty() {
+        None
+    } else {
+        let parent_id = form.parent.parse::<Key<Folder>>().map_err(|_| {
+            tracing::error!(parent = ?form.parent, "Invalid folder ID in form data");
+            poem::Error::from_status(StatusCode::BAD_REQUEST)
+        })?;
+
+        let parent = get_folder_by_id(&env, parent_id).await?;
+        if parent.owner_user != folder.owner_user || parent.owner_team != folder.owner_team {
+            tracing::error!(%folder.id, %parent.id, "Folders do not have the same owner");
+            return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+        }
+
+        let cycle = folder.contains(&env.pool, parent.id).await.map_err(|err| {
+            tracing::error!(?err, %folder.id, %parent.id, "Unable to check folder hierarchy");
+            InternalServerError(err)
+        })?;
+
+        if cycle {
+            tracing::error!(%folder.id, %parent.id, "Cannot move a folder into itself");
+            return Err(poem::Error::from_status(StatusCode::BAD_REQUEST));
+        }
+
+        Some(parent.id)
+    };
+
+    let before = folder.clone();
+    folder.set_parent(&env.pool, parent).await.map_err(|err| {
+        tracing::error!(?err, %folder.id, ?parent, "Unable to move folder");
+        InternalServerError(err)
+    })?;
+
+    tracing::info!(%folder.id, ?parent, "Moved folder");
+    auditor
+        .record(
+            &env,
+            &user,
+            AuditAction::FolderMove,
+            AuditTarget::Folder(folder.id),
+            Some(json!({
+                "before": folder_audit_details(&before),
+                "after": folder_audit_details(&folder),
+            })),
+        )
+        .await;
+
+    Ok(Html("").with_header("HX-Refresh", "true").into_response())
+}
@@ -0,0 +1,154 @@
+use minijinja::context;
+use poem::{
+    error::InternalServerError,
+    handler,
+    web::{CsrfToken, CsrfVerifier, Data, Form, Html, Query},
+    IntoResponse, Response,
+};
+use serde::Deserialize;
+use serde_json::json;
+use validator::Validate;
+
+use parcel_model::{
+    audit::{AuditAction, AuditTarget},
+    folder::Folder,
+    team::Team,
+    types::Key,
+    user::User,
+};
+
+use crate::{
+    app::{
+        errors::CsrfError,
+        extractors::{audit::Auditor, user::SessionUser},
+        handlers::utils::{folder_audit_details, get_folder_for_owner, get_team_for_member},
+        templates::{authorized_context, render_template},
+    },
+    env::Env,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct NewFolderQuery {
+    #[serde(default)]
+    team: Option<Key<Team>>,
+    #[serde(default)]
+    parent: Option<Key<Folder>>,
+}
+
+/// Get the team that a new folder will belong to, and the folder that it will be created in.
+async fn get_team_and_parent(
+    env: &Env,
+    user: &User,
+    team: Option<Key<Team>>,
+    parent: Option<Key<Folder>>,
+) -> poem::Result<(Option<Team>, Option<Folder>)> {
+    let team = if let Some(team_id) = team {
+        Some(get_team_for_member(env, user, team_id).await?)
+    } else {
+        None
+    };
+
+    let parent = if let Some(parent_id) = parent {
+        Some(get_folder_for_owner(env, user, team.as_ref(), parent_id).await?)
+    } else {
+        None
+    };
+
+    Ok((team, parent))
+}
+
+#[handler]
+pub async fn get_new(
+    env: Data<&Env>,
+    token: &CsrfToken,
+    SessionUser(user): SessionUser,
+    Query(NewFolderQuery { team, parent }): Query<NewFolderQuery>,
+) -> poem::Result<Html<String>> {
+    let (team, parent) = get_team_and_parent(&env, &user, team, parent).await?;
+
+    render_template(
+        "folders/new.html",
+        context! {
+            token => token.0,
+            team,
+            parent,
+            ..authorized_context(&env, &user)
+        },
+    )
+    .await
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct NewFolderForm {
+    token: String,
+    #[validate(length(min = 1, max = 100))]
+    name: String,
+    #[serde(default)]
+    team: Option<Key<Team>>,
+    #[serde(default)]
+    parent: Option<Key<Folder>>,
+}
+
+#[handler]
+pub async fn post_new(
+    env: Data<&Env>,
+    next_token: &CsrfToken,
+    verifier: &CsrfVerifier,
+    SessionUser(user): SessionUser,
+    auditor: Auditor,
+    Form(form): Form<NewFolderForm>,
+) -> poem::Result<Response> {
+    if !verifier.is_valid(&form.token) {
+        tracing::error!("CSRF token is invalid in new folder form");
+        return Err(CsrfError.into());
+    }
+
+    let (team, parent) = get_team_and_parent(&env, &user, form.team, form.parent).await?;
+
+    if let Err(errors) = form.validate() {
+        return Ok(render_template(
+            "folders/new.html",
+            context! {
+                errors,
+                token => next_token.0,
+                form => context! {
+                    name => &form.name,
+                },
+                team,
+                parent,
+                ..authorized_context(&env, &user)
+            },
+        )
+        .await?
+        .with_header("HX-Retarget", "#folder-form")
+        .with_header("HX-Reselect", "#folder-form")
+        .into_response());
+    }
+
+    let folder = Folder::new(
+        nanoid::nanoid!(),
+        form.name.trim(),
+        parent.map(|parent| parent.id),
+        if team.is_none() { Some(user.id) } else { None },
+        team.map(|team| team.id),
+        user.id,
+    );
+
+    folder.create(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %user.id, "Unable to create folder");
+        InternalServerError(err)
+    })?;
+
+    tracing::info!(%folder.id, ?folder.parent, "Created folder");
+    auditor
+        .record(
+            &env,
+            &user,
+            AuditAction::FolderCreate,
+            AuditTarget::Folder(folder.id),
+            Some(json!({ "after": folder_audit_details(&folder) })),
+        )
+        .await;
+
+    Ok(Html("").with_header("HX-Refresh", "true").into_response())
+}
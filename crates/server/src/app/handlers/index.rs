@@ -13,6 +13,7 @@ use parcel_model::{
 use crate::{
     app::{
         extractors::user::SessionUser,
+        handlers::utils::get_folder_listing,
         templates::{authorized_context, render_template},
     },
     env::Env,
@@ -53,8 +54,9 @@ pub async fn get_index(
     let uploads = UploadList::get_for_user(
         &env.pool,
         user.id,
-        query.get_search(),
-        query.order.unwrap_or(user.default_order),
+        query.get_folder_filter(),
+        &query.get_filter(),
+        query.get_order(&user),
         query.asc.unwrap_or(user.default_asc),
         0,
         50,
@@ -65,6 +67,8 @@ pub async fn get_index(
         poem::error::InternalServerError(err)
     })?;
 
+    let listing = get_folder_listing(&env, &user, None, query.folder).await?;
+
     render_template(
         "index.html",
         minijinja::context! {
@@ -73,6 +77,7 @@ pub async fn get_index(
             tabs,
             stats,
             uploads,
+            listing,
             csrf_token => csrf_token.0,
             page => 0,
             limit => user.limit,
@@ -113,8 +118,9 @@ pub async fn get_tab(
     let uploads = UploadList::get_for_user(
         &env.pool,
         user.id,
-        query.get_search(),
-        query.order.unwrap_or(user.default_order),
+        query.get_folder_filter(),
+        &query.get_filter(),
+        query.get_order(&user),
         query.asc.unwrap_or(user.default_asc),
         0,
         50,
@@ -125,6 +131,8 @@ pub async fn get_tab(
         poem::error::InternalServerError(err)
     })?;
 
+    let listing = get_folder_listing(&env, &user, None, query.folder).await?;
+
     Ok(render_template(
         "tab.html",
         minijinja::context! {
@@ -133,6 +141,7 @@ pub async fn get_tab(
             tabs,
             stats,
             uploads,
+            listing,
             page => 0,
             limit => user.limit,
             ..authorized_context(&env, &user)
@@ -0,0 +1,148 @@
+use minijinja::context;
+use poem::{
+    error::InternalServerError,
+    handler,
+    http::StatusCode,
+    session::Session,
+    web::{CsrfToken, CsrfVerifier, Data, Form, Html, Path, Redirect},
+    IntoResponse, Request, Response,
+};
+use serde::Deserialize;
+
+use parcel_model::{
+    share_link::{ShareLink, ShareLinkStatus},
+    upload::Upload,
+};
+
+use crate::{
+    app::{
+        errors::CsrfError,
+        extractors::user::SessionUser,
+        handlers::{uploads::send_download, utils::get_upload_by_id},
+        templates::{authorized_context, default_context, render_template},
+    },
+    env::Env,
+    utils::SessionExt,
+};
+
+async fn get_link_by_token(env: &Env, token: &str) -> poem::Result<ShareLink> {
+    let Some(link) = ShareLink::get_by_token(&env.pool, token)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "Unable to get share link by token");
+            InternalServerError(err)
+        })?
+    else {
+        tracing::error!("Unable to find share link with given token");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    Ok(link)
+}
+
+/// Make sure that a share link can still be used to download its upload.
+fn check_link_active(link: &ShareLink, upload: &Upload) -> poem::Result<()> {
+    let status = link.status(upload);
+    if status != ShareLinkStatus::Active {
+        tracing::error!(%link.id, ?status, "Share link cannot be used to download");
+        return Err(poem::Error::from_status(StatusCode::FORBIDDEN));
+    }
+
+    Ok(())
+}
+
+#[handler]
+pub async fn get_link(
+    env: Data<&Env>,
+    session: &Session,
+    user: Option<SessionUser>,
+    csrf_token: &CsrfToken,
+    Path(token): Path<String>,
+) -> poem::Result<Html<String>> {
+    let link = get_link_by_token(&env, &token).await?;
+    let upload = get_upload_by_id(&env, link.upload).await?;
+    let status = link.status(&upload);
+
+    render_template(
+        "links/view.html",
+        context! {
+            status,
+            upload,
+            has_password => link.password.is_some(),
+            link,
+            csrf_token => csrf_token.0,
+            error => session.take::<String>("download_error"),
+            ..if let Some(user) = &user {
+                authorized_context(&env, user)
+            } else {
+                default_context(&env)
+            }
+        },
+    )
+    .await
+}
+
+#[handler]
+pub async fn get_link_download(
+    env: Data<&Env>,
+    request: &Request,
+    user: Option<SessionUser>,
+    Path(token): Path<String>,
+) -> poem::Result<Response> {
+    let link = get_link_by_token(&env, &token).await?;
+    let upload = get_upload_by_id(&env, link.upload).await?;
+    check_link_active(&link, &upload)?;
+
+    if link.password.is_some() {
+        tracing::error!(%link.id, "Share link requires a password");
+        return Ok(Redirect::see_other(format!("/s/{token}")).into_response());
+    }
+
+    send_download(&env, upload, Some(link), user.as_deref(), request).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LinkDownloadForm {
+    csrf_token: String,
+    password: String,
+}
+
+#[handler]
+pub async fn post_link_download(
+    env: Data<&Env>,
+    request: &Request,
+    session: &Session,
+    user: Option<SessionUser>,
+    verifier: &CsrfVerifier,
+    Path(token): Path<String>,
+    Form(LinkDownloadForm {
+        csrf_token,
+        password,
+    }): Form<LinkDownloadForm>,
+) -> poem::Result<Response> {
+    if !verifier.is_valid(&csrf_token) {
+        tracing::error!("CSRF token is invalid in share link download");
+        return Err(CsrfError.into());
+    }
+
+    let mut link = get_link_by_token(&env, &token).await?;
+    let upload = get_upload_by_id(&env, link.upload).await?;
+    check_link_active(&link, &upload)?;
+
+    let Some(ref hash) = link.password else {
+        return Ok(Redirect::see_other(format!("/s/{token}/download")).into_response());
+    };
+
+    if !hash.verify(&password) {
+        tracing::error!(%link.id, "Invalid password provided for share link");
+        session.set("download_error", "Incorrect password");
+        return Ok(Redirect::see_other(format!("/s/{token}")).into_response());
+    }
+
+    if hash.needs_migrating() {
+        tracing::info!(%link.id, "Migrating share link password hash");
+        link.set_password(&env.pool, &password).await?;
+    }
+
+    send_download(&env, upload, Some(link), user.as_deref(), request).await
+}
@@ -0,0 +1,318 @@
+use minijinja::context;
+use poem::{
+    error::InternalServerError,
+    handler,
+    http::StatusCode,
+    web::{CsrfToken, CsrfVerifier, Data, Form, Html, Path, Query},
+};
+use serde::Deserialize;
+use serde_json::json;
+use time::{Date, OffsetDateTime};
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use parcel_model::{
+    audit::{AuditAction, AuditTarget},
+    password::StoredPassword,
+    team::{Team, TeamMember},
+    types::Key,
+    upload_request::{parse_mime_types, UploadRequest},
+    user::User,
+};
+
+use crate::{
+    app::{
+        errors::CsrfError,
+        extractors::{audit::Auditor, user::SessionUser},
+        handlers::utils::get_team_for_member,
+        templates::{authorized_context, render_template},
+    },
+    env::Env,
+};
+
+mod upload;
+
+pub use upload::{get_request, post_request, post_unlock};
+
+/// The maximum file size of a request is given in megabytes in the form.
+const MEGABYTE: i64 = 1024 * 1024;
+
+/// Describe an upload request for the audit log.
+fn request_audit_details(request: &UploadRequest) -> serde_json::Value {
+    json!({
+        "name": request.name,
+        "max_files": request.max_files,
+        "max_size": request.max_size,
+        "mime_types": request.mime_types,
+        "expiry_date": request.expiry_date,
+        "has_password": request.password.is_some(),
+        "owner_user": request.owner_user,
+        "owner_team": request.owner_team,
+    })
+}
+
+/// Get the team that the upload requests are for, checking that the user can edit its uploads.
+async fn get_team_for_requests(
+    env: &Env,
+    user: &User,
+    team: Option<Key<Team>>,
+) -> poem::Result<Option<Team>> {
+    let Some(team_id) = team else {
+        return Ok(None);
+    };
+
+    let team = get_team_for_member(env, user, team_id).await?;
+    let membership = TeamMember::get_for_user_and_team(&env.pool, user.id, team.id)
+        .await
+        .map_err(|err| {
+            tracing::error!(%user.id, %team.id, ?err, "Unable to get team membership");
+            InternalServerError(err)
+        })?;
+
+    if !membership.is_some_and(|membership| membership.can_edit) {
+        tracing::error!(%user.id, %team.id, "User cannot manage upload requests for team");
+        return Err(poem::Error::from_status(StatusCode::FORBIDDEN));
+    }
+
+    Ok(Some(team))
+}
+
+/// Render the modal that lists the upload requests of a user or team.
+///
+/// The `form` is given when creating a new upload request failed, along with the validation errors.
+async fn render_requests(
+    env: &Env,
+    user: &User,
+    csrf_token: &CsrfToken,
+    team: Option<Team>,
+    immediate: bool,
+    form: Option<(minijinja::Value, ValidationErrors)>,
+) -> poem::Result<Html<String>> {
+    let requests = match team {
+        Some(ref team) => UploadRequest::get_for_team(&env.pool, team.id).await,
+        None => UploadRequest::get_for_user(&env.pool, user.id).await,
+    }
+    .map_err(|err| {
+        tracing::error!(?err, %user.id, "Unable to get upload requests");
+        InternalServerError(err)
+    })?
+    .into_iter()
+    .map(|request| {
+        let status = request.status();
+        let remaining = request.remaining_files();
+        context! { request, status, remaining }
+    })
+    .collect::<Vec<_>>();
+
+    let (form, errors) = form.unzip();
+
+    render_template(
+        "requests/list.html",
+        context! {
+            team,
+            immediate,
+            requests,
+            form,
+            errors,
+            now => OffsetDateTime::now_utc(),
+            csrf_token => csrf_token.0,
+            ..authorized_context(env, user)
+        },
+    )
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RequestsQuery {
+    #[serde(default)]
+    team: Option<Key<Team>>,
+    #[serde(default)]
+    immediate: bool,
+}
+
+#[handler]
+pub async fn get_requests(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    csrf_token: &CsrfToken,
+    Query(RequestsQuery { team, immediate }): Query<RequestsQuery>,
+) -> poem::Result<Html<String>> {
+    let team = get_team_for_requests(&env, &user, team).await?;
+    render_requests(&env, &user, csrf_token, team, immediate, None).await
+}
+
+fn validate_mime_types(mime_types: &str) -> Result<(), ValidationError> {
+    let valid = |part: &str| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '-' | '_'))
+    };
+
+    for mime_type in parse_mime_types(mime_types) {
+        let Some((kind, subtype)) = mime_type.split_once('/') else {
+            return Err(ValidationError::new("invalid_mime_type")
+                .with_message(format!("'{mime_type}' is not a valid file type").into()));
+        };
+
+        if !valid(kind) || (subtype != "*" && !valid(subtype)) {
+            return Err(ValidationError::new("invalid_mime_type")
+                .with_message(format!("'{mime_type}' is not a valid file type").into()));
+        }
+    }
+
+    Ok(())
+}
+
+time::serde::format_description!(iso8601_date, Date, "[year]-[month]-[day]");
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct NewRequestForm {
+    csrf_token: String,
+    #[serde(default)]
+    team: Option<Key<Team>>,
+    #[validate(length(min = 1, max = 100))]
+    name: String,
+    #[validate(range(min = 1, message = "A request must allow at least one file"))]
+    max_files: Option<i64>,
+    /// The maximum size of each file, in megabytes.
+    #[validate(range(min = 1, message = "The maximum file size must be at least 1 MB"))]
+    max_size: Option<i64>,
+    #[validate(custom(function = "validate_mime_types"))]
+    mime_types: Option<String>,
+    #[serde(default, with = "iso8601_date::option")]
+    expiry_date: Option<Date>,
+    #[validate(length(min = 1, message = "The password cannot be empty"))]
+    password: Option<String>,
+}
+
+#[handler]
+pub async fn post_requests(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    auditor: Auditor,
+    csrf_token: &CsrfToken,
+    csrf_verifier: &CsrfVerifier,
+    Form(form): Form<NewRequestForm>,
+) -> poem::Result<Html<String>> {
+    if !csrf_verifier.is_valid(&form.csrf_token) {
+        tracing::warn!(%user.id, "CSRF verification failed for upload request creation");
+        return Err(CsrfError.into());
+    }
+
+    let team = get_team_for_requests(&env, &user, form.team).await?;
+
+    if let Err(errors) = form.validate() {
+        let form = context! {
+            name => form.name,
+            max_files => form.max_files,
+            max_size => form.max_size,
+            mime_types => form.mime_types,
+            expiry_date => form.expiry_date,
+            has_password => form.password.is_some(),
+        };
+
+        return render_requests(&env, &user, csrf_token, team, true, Some((form, errors))).await;
+    }
+
+    let mut request = UploadRequest::new(
+        form.name.trim(),
+        if team.is_none() { Some(user.id) } else { None },
+        team.as_ref().map(|team| team.id),
+        user.id,
+    );
+
+    request.max_files = form.max_files;
+    request.max_size = form
+        .max_size
+        .map(|max_size| max_size.saturating_mul(MEGABYTE));
+    request.mime_types = form
+        .mime_types
+        .as_deref()
+        .map(parse_mime_types)
+        .filter(|mime_types| !mime_types.is_empty())
+        .map(|mime_types| mime_types.join(","));
+    request.expiry_date = form.expiry_date;
+
+    if let Some(password) = form.password {
+        request.password = Some(StoredPassword::new(&password).map_err(|err| {
+            tracing::error!(?err, %user.id, "Failed to hash upload request password");
+            poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR)
+        })?);
+    }
+
+    request.create(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %user.id, "Failed to create upload request");
+        InternalServerError(err)
+    })?;
+
+    tracing::info!(%request.id, ?request.owner_team, "Created upload request");
+    auditor
+        .record(
+            &env,
+            &user,
+            AuditAction::UploadRequestCreate,
+            AuditTarget::UploadRequest(request.id),
+            Some(request_audit_details(&request)),
+        )
+        .await;
+
+    render_requests(&env, &user, csrf_token, team, true, None).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevokeRequestForm {
+    csrf_token: String,
+}
+
+#[handler]
+pub async fn post_revoke(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    auditor: Auditor,
+    csrf_token: &CsrfToken,
+    csrf_verifier: &CsrfVerifier,
+    Path(id): Path<Key<UploadRequest>>,
+    Form(RevokeRequestForm {
+        csrf_token: form_token,
+    }): Form<RevokeRequestForm>,
+) -> poem::Result<Html<String>> {
+    if !csrf_verifier.is_valid(&form_token) {
+        tracing::warn!(%id, "CSRF verification failed for upload request revocation");
+        return Err(CsrfError.into());
+    }
+
+    let Some(mut request) = UploadRequest::get(&env.pool, id).await.map_err(|err| {
+        tracing::error!(?err, %id, "Unable to get upload request by ID");
+        InternalServerError(err)
+    })?
+    else {
+        tracing::error!(%id, "Upload request not found");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    let team = get_team_for_requests(&env, &user, request.owner_team).await?;
+    if team.is_none() && request.owner_user != Some(user.id) {
+        tracing::error!(%user.id, %request.id, "User does not own upload request");
+        return Err(poem::Error::from_status(StatusCode::FORBIDDEN));
+    }
+
+    if request.revoked_at.is_none() {
+        request.revoke(&env.pool).await.map_err(|err| {
+            tracing::error!(?err, %request.id, "Failed to revoke upload request");
+            InternalServerError(err)
+        })?;
+
+        tracing::info!(%request.id, "Revoked upload request");
+        auditor
+            .record(
+                &env,
+                &user,
+                AuditAction::UploadRequestRevoke,
+                AuditTarget::UploadRequest(request.id),
+                Some(request_audit_details(&request)),
+            )
+            .await;
+    }
+
+    render_requests(&env, &user, csrf_token, team, true, None).await
+}
@@ -0,0 +1,402 @@
+use esbuild_bundle::javascript;
+use minijinja::context;
+use poem::{
+    error::InternalServerError,
+    handler,
+    http::StatusCode,
+    session::Session,
+    web::{
+        CsrfToken, CsrfVerifier, Data, Form, Html, Json, Multipart, Path, RealIp, Redirect,
+        RemoteAddr,
+    },
+    IntoResponse, Response,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use parcel_model::{
+    team::Team,
+    types::Key,
+    upload::Upload,
+    upload_request::{UploadRequest, UploadRequestStatus},
+    user::User,
+};
+
+use crate::{
+    app::{
+        errors::{CsrfError, FileTooLargeError, FileTypeNotAllowedError, QuotaExceededError},
+        extractors::user::SessionUser,
+        handlers::utils::{
+            cache_upload_field, discard_pending_uploads, get_remaining_team_quota,
+            get_remaining_user_quota, insert_uploads, release_blob,
+        },
+        templates::{authorized_context, default_context, render_template},
+    },
+    env::Env,
+    notifications::notify_request_upload,
+    utils::{get_client_ip, SessionExt},
+    workers::previews::PreviewWorker,
+};
+
+/// The user or team that receives the files uploaded through an upload request.
+enum RequestOwner {
+    User(User),
+    Team(Team),
+}
+
+impl RequestOwner {
+    fn name(&self) -> &str {
+        match self {
+            Self::User(user) => &user.name,
+            Self::Team(team) => &team.name,
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        match self {
+            Self::User(user) => user.enabled,
+            Self::Team(team) => team.enabled,
+        }
+    }
+
+    fn limit(&self) -> Option<i64> {
+        match self {
+            Self::User(user) => user.limit,
+            Self::Team(team) => team.limit,
+        }
+    }
+}
+
+async fn get_request_by_token(env: &Env, token: &str) -> poem::Result<UploadRequest> {
+    let Some(request) = UploadRequest::get_by_token(&env.pool, token)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "Unable to get upload request by token");
+            InternalServerError(err)
+        })?
+    else {
+        tracing::error!("Unable to find upload request with given token");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    Ok(request)
+}
+
+async fn get_request_owner(env: &Env, request: &UploadRequest) -> poem::Result<RequestOwner> {
+    if let Some(team_id) = request.owner_team {
+        let Some(team) = Team::get(&env.pool, team_id).await.map_err(|err| {
+            tracing::error!(?err, %team_id, "Unable to get team by ID");
+            InternalServerError(err)
+        })?
+        else {
+            tracing::error!(%request.id, %team_id, "Upload request team not found");
+            return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+        };
+
+        return Ok(RequestOwner::Team(team));
+    }
+
+    let Some(user_id) = request.owner_user else {
+        tracing::error!(%request.id, "Upload request has no owner");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    let Some(user) = User::get(&env.pool, user_id).await.map_err(|err| {
+        tracing::error!(?err, %user_id, "Unable to get user by ID");
+        InternalServerError(err)
+    })?
+    else {
+        tracing::error!(%request.id, %user_id, "Upload request user not found");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    Ok(RequestOwner::User(user))
+}
+
+/// The session key that records that the password of an upload request has been given.
+fn unlock_key(request: &UploadRequest) -> String {
+    format!("upload_request_{}", request.id)
+}
+
+/// Check whether files can be uploaded through the request by this session, which requires the
+/// password of the request to have been given, if it has one.
+fn is_unlocked(session: &Session, request: &UploadRequest) -> bool {
+    request.password.is_none() || session.get::<bool>(&unlock_key(request)).unwrap_or(false)
+}
+
+#[handler]
+pub async fn get_request(
+    env: Data<&Env>,
+    session: &Session,
+    user: Option<SessionUser>,
+    csrf_token: &CsrfToken,
+    Path(token): Path<String>,
+) -> poem::Result<Html<String>> {
+    let request = get_request_by_token(&env, &token).await?;
+    let owner = get_request_owner(&env, &request).await?;
+    let status = request.status();
+
+    render_template(
+        "requests/view.html",
+        context! {
+            status,
+            owner_name => owner.name(),
+            owner_enabled => owner.enabled(),
+            unlocked => is_unlocked(session, &request),
+            remaining => request.remaining_files(),
+            request,
+            csrf_token => csrf_token.0,
+            error => session.take::<String>("request_error"),
+            drop_js => javascript!("$CARGO_MANIFEST_DIR/scripts/components/drop.ts"),
+            ..if let Some(user) = &user {
+                authorized_context(&env, user)
+            } else {
+                default_context(&env)
+            }
+        },
+    )
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UnlockForm {
+    csrf_token: String,
+    password: String,
+}
+
+#[handler]
+pub async fn post_unlock(
+    env: Data<&Env>,
+    session: &Session,
+    verifier: &CsrfVerifier,
+    Path(token): Path<String>,
+    Form(UnlockForm {
+        csrf_token,
+        password,
+    }): Form<UnlockForm>,
+) -> poem::Result<Redirect> {
+    if !verifier.is_valid(&csrf_token) {
+        tracing::error!("CSRF token is invalid in upload request password form");
+        return Err(CsrfError.into());
+    }
+
+    let mut request = get_request_by_token(&env, &token).await?;
+
+    if let Some(ref hash) = request.password {
+        if !hash.verify(&password) {
+            tracing::error!(%request.id, "Invalid password provided for upload request");
+            session.set("request_error", "Incorrect password");
+            return Ok(Redirect::see_other(format!("/r/{token}")));
+        }
+
+        if hash.needs_migrating() {
+            tracing::info!(%request.id, "Migrating upload request password hash");
+            request.set_password(&env.pool, &password).await?;
+        }
+
+        session.set(&unlock_key(&request), true);
+    }
+
+    Ok(Redirect::see_other(format!("/r/{token}")))
+}
+
+/// Delete uploads that were inserted for an upload request, but which could not be recorded
+/// against it.
+async fn discard_uploads(env: &Env, upload_ids: &[Key<Upload>]) {
+    match Upload::delete_many(&env.pool, upload_ids).await {
+        Ok(blobs) => {
+            for blob in blobs {
+                release_blob(env, &blob).await;
+            }
+        }
+        Err(err) => {
+            tracing::error!(?err, "Unable to delete uploads for upload request");
+        }
+    }
+}
+
+/// Build the response that tells the drop form why the files were rejected.
+fn rejection(status: StatusCode, message: impl ToString) -> Response {
+    Json(json!({ "error": message.to_string() }))
+        .with_status(status)
+        .into_response()
+}
+
+#[handler]
+#[allow(clippy::too_many_arguments)]
+pub async fn post_request(
+    env: Data<&Env>,
+    preview: Data<&PreviewWorker>,
+    session: &Session,
+    real_ip: RealIp,
+    remote_addr: &RemoteAddr,
+    csrf_verifier: &CsrfVerifier,
+    Path(token): Path<String>,
+    mut form: Multipart,
+) -> poem::Result<Response> {
+    let mut request = get_request_by_token(&env, &token).await?;
+
+    let status = request.status();
+    if status != UploadRequestStatus::Active {
+        tracing::error!(%request.id, ?status, "Upload request cannot be used to upload");
+        return Ok(rejection(
+            StatusCode::FORBIDDEN,
+            "This upload request is no longer accepting files",
+        ));
+    }
+
+    if !is_unlocked(session, &request) {
+        tracing::error!(%request.id, "Upload request requires a password");
+        return Err(poem::Error::from_status(StatusCode::UNAUTHORIZED));
+    }
+
+    let owner = get_request_owner(&env, &request).await?;
+    if !owner.enabled() {
+        tracing::error!(%request.id, "Owner of upload request is disabled");
+        return Ok(rejection(
+            StatusCode::FORBIDDEN,
+            "This upload request is no longer accepting files",
+        ));
+    }
+
+    let mut quota = match owner {
+        RequestOwner::User(ref user) => get_remaining_user_quota(&env, user).await?,
+        RequestOwner::Team(ref team) => get_remaining_team_quota(&env, team).await?,
+    };
+
+    let mut seen_csrf = false;
+    let mut uploads = Vec::new();
+    let remaining = request.remaining_files();
+
+    loop {
+        let field = match form.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(err) => {
+                tracing::error!(?err, "Failed to read multipart field");
+                discard_pending_uploads(&env, &uploads).await;
+                return Err(err.into());
+            }
+        };
+
+        if field.name() == Some("csrf_token") {
+            if !csrf_verifier.is_valid(&field.text().await?) {
+                tracing::error!("CSRF token is invalid in upload request form");
+                discard_pending_uploads(&env, &uploads).await;
+                return Err(CsrfError.into());
+            }
+
+            seen_csrf = true;
+        } else if field.name() == Some("file") {
+            if remaining.is_some_and(|remaining| uploads.len() as i64 >= remaining) {
+                tracing::info!(%request.id, "Too many files for upload request");
+                discard_pending_uploads(&env, &uploads).await;
+                return Ok(rejection(
+                    StatusCode::BAD_REQUEST,
+                    "Too many files were uploaded for this request",
+                ));
+            }
+
+            match cache_upload_field(&env, field, &mut quota, Some(&request)).await {
+                Ok(Some(upload)) => uploads.push(upload),
+                Ok(None) => {
+                    tracing::info!(%request.id, "Failed to receive file for upload request");
+                }
+                Err(err) => {
+                    discard_pending_uploads(&env, &uploads).await;
+
+                    if let Some(err) = err.downcast_ref::<FileTooLargeError>() {
+                        return Ok(rejection(StatusCode::PAYLOAD_TOO_LARGE, err));
+                    }
+
+                    if let Some(err) = err.downcast_ref::<FileTypeNotAllowedError>() {
+                        return Ok(rejection(StatusCode::UNSUPPORTED_MEDIA_TYPE, err));
+                    }
+
+                    // Don't tell an anonymous uploader how much space the owner has left.
+                    if err.is::<QuotaExceededError>() {
+                        return Ok(rejection(
+                            StatusCode::PAYLOAD_TOO_LARGE,
+                            "There is not enough space to receive these files",
+                        ));
+                    }
+
+                    return Err(err);
+                }
+            }
+        } else {
+            tracing::info!(field_name = ?field.name(), "Ignoring unrecognized field");
+        }
+    }
+
+    if !seen_csrf {
+        tracing::error!("CSRF token was not seen in upload request form");
+        discard_pending_uploads(&env, &uploads).await;
+        return Err(poem::Error::from_status(StatusCode::UNAUTHORIZED));
+    }
+
+    let client_ip = get_client_ip(env.trust_proxy, &real_ip, remote_addr);
+    let remote_addr = client_ip.map(|ip| ip.to_string());
+    let upload_ids = match insert_uploads(
+        &env,
+        request.owner_user,
+        request.owner_team,
+        None,
+        remote_addr,
+        &uploads,
+        owner.limit(),
+    )
+    .await
+    {
+        Ok(upload_ids) => upload_ids,
+        Err(err) => {
+            discard_pending_uploads(&env, &uploads).await;
+
+            if err.is::<QuotaExceededError>() {
+                return Ok(rejection(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    "There is not enough space to receive these files",
+                ));
+            }
+
+            return Err(err);
+        }
+    };
+
+    // Other files may have been uploaded through the request at the same time, so the maximum
+    // number of files is checked again as the uploads are recorded.
+    let recorded = match request.record_uploads(&env.pool, &upload_ids).await {
+        Ok(recorded) => recorded,
+        Err(err) => {
+            tracing::error!(?err, %request.id, "Unable to record uploads for upload request");
+            discard_uploads(&env, &upload_ids).await;
+            return Err(InternalServerError(err));
+        }
+    };
+
+    if !recorded {
+        tracing::info!(%request.id, "Too many files for upload request");
+        discard_uploads(&env, &upload_ids).await;
+        return Ok(rejection(
+            StatusCode::BAD_REQUEST,
+            "Too many files were uploaded for this request",
+        ));
+    }
+
+    tracing::info!(%request.id, files = upload_ids.len(), "Received files through upload request");
+
+    if !uploads.is_empty() {
+        let filenames = uploads
+            .iter()
+            .map(|upload| upload.filename.as_str())
+            .collect::<Vec<_>>();
+        notify_request_upload(&env, &request, &filenames).await;
+    }
+
+    // Trigger preview generation but don't fail the request if it errors.
+    if let Err(err) = preview.generate_previews(upload_ids).await {
+        tracing::error!(?err, "Failed to send preview generation command");
+    }
+
+    Ok(Json(()).into_response())
+}
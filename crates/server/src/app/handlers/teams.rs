@@ -15,6 +15,7 @@ use parcel_model::{
 use crate::{
     app::{
         extractors::user::SessionUser,
+        handlers::utils::get_folder_listing,
         templates::{authorized_context, render_template},
     },
     env::Env,
@@ -22,6 +23,7 @@ use crate::{
 
 use super::uploads::ListQuery;
 
+pub mod downloads;
 pub mod settings;
 pub mod uploads;
 
@@ -76,8 +78,9 @@ pub async fn get_team(
     let uploads = UploadList::get_for_team(
         &env.pool,
         team.id,
-        query.get_search(),
-        query.order.unwrap_or(user.default_order),
+        query.get_folder_filter(),
+        &query.get_filter(),
+        query.get_order(&user),
         query.asc.unwrap_or(user.default_asc),
         0,
         50,
@@ -88,6 +91,8 @@ pub async fn get_team(
         InternalServerError(err)
     })?;
 
+    let listing = get_folder_listing(&env, &user, Some(&team), query.folder).await?;
+
     render_template(
         "team.html",
         minijinja::context! {
@@ -98,6 +103,7 @@ pub async fn get_team(
             membership,
             stats,
             uploads,
+            listing,
             page => 0,
             limit => team.limit,
             index_js => javascript!("$CARGO_MANIFEST_DIR/scripts/index.ts"),
@@ -158,8 +164,9 @@ pub async fn get_tab(
     let uploads = UploadList::get_for_team(
         &env.pool,
         team.id,
-        query.get_search(),
-        query.order.unwrap_or(user.default_order),
+        query.get_folder_filter(),
+        &query.get_filter(),
+        query.get_order(&user),
         query.asc.unwrap_or(user.default_asc),
         0,
         50,
@@ -170,6 +177,8 @@ pub async fn get_tab(
         InternalServerError(err)
     })?;
 
+    let listing = get_folder_listing(&env, &user, Some(&team), query.folder).await?;
+
     Ok(render_template(
         "tab.html",
         minijinja::context! {
@@ -180,6 +189,7 @@ pub async fn get_tab(
             membership,
             stats,
             uploads,
+            listing,
             page => 0,
             limit => team.limit,
             ..authorized_context(&env, &user)
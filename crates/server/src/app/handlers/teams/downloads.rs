@@ -0,0 +1,60 @@
+use minijinja::context;
+use poem::{
+    error::InternalServerError,
+    web::{Data, Html, Path},
+};
+use time::{Duration, OffsetDateTime};
+
+use parcel_model::{
+    download::{DownloadList, DownloadScope, DownloadTop},
+    team::Team,
+    types::Key,
+};
+
+use crate::{
+    app::{
+        extractors::user::SessionUser,
+        handlers::utils::{get_download_activity, get_team_for_member},
+        templates::{authorized_context, render_template},
+    },
+    env::Env,
+};
+
+#[poem::handler]
+pub async fn get_downloads(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    Path(team_id): Path<Key<Team>>,
+) -> poem::Result<Html<String>> {
+    let team = get_team_for_member(&env, &user, team_id).await?;
+    let scope = DownloadScope::Team(team.id);
+    let activity = get_download_activity(&env, scope).await?;
+
+    let since = OffsetDateTime::now_utc() - Duration::days(30);
+    let top = DownloadTop::get(&env.pool, scope, since, 10)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %team.id, "Unable to get most downloaded uploads of team");
+            InternalServerError(err)
+        })?;
+
+    let downloads = DownloadList::get(&env.pool, scope, 0, 20)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %team.id, "Unable to get recent downloads of team");
+            InternalServerError(err)
+        })?;
+
+    render_template(
+        "teams/downloads.html",
+        context! {
+            team,
+            activity,
+            top,
+            downloads,
+            show_upload => true,
+            ..authorized_context(&env, &user)
+        },
+    )
+    .await
+}
@@ -7,9 +7,11 @@ use poem::{
     IntoResponse,
 };
 use serde::Deserialize;
+use serde_json::json;
 use validator::{Validate, ValidationError, ValidationErrors};
 
 use parcel_model::{
+    audit::{AuditAction, AuditTarget},
     team::{Team, TeamMember},
     types::Key,
     user::User,
@@ -18,7 +20,7 @@ use parcel_model::{
 use crate::{
     app::{
         errors::CsrfError,
-        extractors::user::SessionUser,
+        extractors::{audit::Auditor, user::SessionUser},
         handlers::admin::users::TeamPermissionStruct,
         templates::{authorized_context, render_template},
     },
@@ -91,6 +93,7 @@ pub struct SettingsForm {
 pub async fn post_settings(
     env: Data<&Env>,
     SessionUser(user): SessionUser,
+    auditor: Auditor,
     next_token: &CsrfToken,
     verifier: &CsrfVerifier,
     Path(id): Path<Key<Team>>,
@@ -242,6 +245,42 @@ pub async fn post_settings(
                 tracing::error!(?err, "Failed to update team member permissions");
                 InternalServerError(err)
             })?;
+
+        let before = current_members
+            .iter()
+            .map(|member| {
+                let permissions = json!({
+                    "edit": member.can_edit,
+                    "delete": member.can_delete,
+                    "config": member.can_config,
+                });
+
+                (member.user.to_string(), permissions)
+            })
+            .collect::<serde_json::Map<_, _>>();
+
+        let after = permission_updates
+            .iter()
+            .map(|(user_id, edit, delete, config)| {
+                let permissions = json!({
+                    "edit": edit,
+                    "delete": delete,
+                    "config": config,
+                });
+
+                (user_id.to_string(), permissions)
+            })
+            .collect::<serde_json::Map<_, _>>();
+
+        auditor
+            .record(
+                &env,
+                &user,
+                AuditAction::TeamPermissions,
+                AuditTarget::Team(team.id),
+                Some(json!({ "before": before, "after": after })),
+            )
+            .await;
     }
 
     Ok(Html("")
@@ -14,7 +14,7 @@ use parcel_model::{
 use crate::{
     app::{
         extractors::user::SessionUser,
-        handlers::uploads::ListQuery,
+        handlers::{uploads::ListQuery, utils::get_folder_listing},
         templates::{authorized_context, render_template},
     },
     env::Env,
@@ -72,8 +72,9 @@ pub async fn get_list(
     let uploads = UploadList::get_for_team(
         &env.pool,
         team.id,
-        query.get_search(),
-        query.order.unwrap_or(user.default_order),
+        query.get_folder_filter(),
+        &query.get_filter(),
+        query.get_order(&user),
         query.asc.unwrap_or(user.default_asc),
         0,
         50,
@@ -84,6 +85,8 @@ pub async fn get_list(
         InternalServerError(err)
     })?;
 
+    let listing = get_folder_listing(&env, &user, Some(&team), query.folder).await?;
+
     render_template(
         "uploads/list.html",
         context! {
@@ -93,6 +96,7 @@ pub async fn get_list(
             tabs,
             stats,
             uploads,
+            listing,
             query,
             csrf_token => csrf_token.0,
             page => 0,
@@ -133,8 +137,9 @@ pub async fn get_page(
     let uploads = UploadList::get_for_team(
         &env.pool,
         team.id,
-        query.get_search(),
-        query.order.unwrap_or(user.default_order),
+        query.get_folder_filter(),
+        &query.get_filter(),
+        query.get_order(&user),
         query.asc.unwrap_or(user.default_asc),
         50 * page,
         50,
@@ -8,6 +8,7 @@ use serde::Deserialize;
 use serde_json::json;
 
 use parcel_model::{
+    audit::{AuditAction, AuditTarget},
     types::Key,
     upload::{Upload, UploadPermission},
 };
@@ -15,24 +16,30 @@ use parcel_model::{
 use crate::{
     app::{
         errors::CsrfError,
-        extractors::user::SessionUser,
-        handlers::utils::{check_permission, get_upload_by_id},
+        extractors::{audit::Auditor, user::SessionUser},
+        handlers::utils::{check_permission, get_upload_by_id, upload_change_details},
     },
     env::Env,
 };
 
 mod download;
 mod edit;
+mod history;
+mod links;
 mod list;
 mod new;
 mod transfer;
 mod upload;
 
-pub use download::{get_download, post_download};
+pub use download::{content_disposition_filename, get_download, post_download, send_download};
 pub use edit::{get_edit, post_check_slug, post_edit};
-pub use list::{get_list, get_page, post_delete, ListQuery};
+pub use history::{get_history, get_history_page};
+pub use links::{post_links, post_revoke_link};
+pub use list::{
+    get_list, get_move_uploads, get_page, post_delete, post_move_uploads, ListQuery,
+};
 pub use new::{get_new, post_new};
-pub use transfer::{get_transfer, post_transfer};
+pub use transfer::{get_transfer, post_transfer, transfer_upload, TransferAction};
 pub use upload::{
     delete_preview_error, delete_upload, get_custom_upload, get_preview, get_share, get_upload,
 };
@@ -47,6 +54,7 @@ pub struct MakePublicQuery {
 pub async fn post_public(
     env: Data<&Env>,
     SessionUser(user): SessionUser,
+    auditor: Auditor,
     csrf_verifier: &CsrfVerifier,
     Path(id): Path<Key<Upload>>,
     Form(MakePublicQuery { public, csrf_token }): Form<MakePublicQuery>,
@@ -60,6 +68,7 @@ pub async fn post_public(
     check_permission(&env, &upload, Some(&user), UploadPermission::Edit).await?;
 
     tracing::info!(%upload.id, public, "Setting upload public state");
+    let before = upload.clone();
     upload
         .set_public(&env.pool, public)
         .await
@@ -68,6 +77,16 @@ pub async fn post_public(
             InternalServerError(err)
         })?;
 
+    auditor
+        .record(
+            &env,
+            &user,
+            AuditAction::UploadEdit,
+            AuditTarget::Upload(upload.id),
+            Some(upload_change_details(&before, &upload)),
+        )
+        .await;
+
     Ok(Html("")
         .with_header(
             "HX-Trigger",
@@ -88,6 +107,7 @@ pub struct ResetForm {
 pub async fn post_reset(
     env: Data<&Env>,
     SessionUser(user): SessionUser,
+    auditor: Auditor,
     csrf_verifier: &CsrfVerifier,
     Path(id): Path<Key<Upload>>,
     Form(ResetForm { csrf_token }): Form<ResetForm>,
@@ -101,6 +121,7 @@ pub async fn post_reset(
     check_permission(&env, &upload, Some(&user), UploadPermission::ResetDownloads).await?;
 
     tracing::info!(%upload.id, "Resetting upload download stats");
+    let before = upload.clone();
     upload
         .reset_remaining(&env.pool)
         .await
@@ -109,6 +130,16 @@ pub async fn post_reset(
             InternalServerError(err)
         })?;
 
+    auditor
+        .record(
+            &env,
+            &user,
+            AuditAction::UploadEdit,
+            AuditTarget::Upload(upload.id),
+            Some(upload_change_details(&before, &upload)),
+        )
+        .await;
+
     Ok(Html("")
         .with_header(
             "HX-Trigger",
@@ -1,19 +1,41 @@
+use std::{
+    io::Cursor,
+    ops::Bound,
+    pin::Pin,
+    task::{Context, Poll},
+    time::SystemTime,
+};
+
+use base64::Engine;
 use poem::{
     error::InternalServerError,
     handler,
     http::{
-        header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE},
-        StatusCode,
+        header::{ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE, USER_AGENT},
+        HeaderMap, Method, StatusCode,
     },
     session::Session,
-    web::{CsrfVerifier, Data, Form, Path, Redirect},
-    Body, IntoResponse, Response,
+    web::{
+        headers::{
+            ContentRange, ETag, HeaderMapExt, IfModifiedSince, IfNoneMatch, IfRange, LastModified,
+            Range,
+        },
+        CsrfVerifier, Data, Form, Path, RealIp, Redirect,
+    },
+    Body, FromRequest, IntoResponse, Request, Response,
 };
 use serde::Deserialize;
+use serde_json::json;
+use sqlx::SqlitePool;
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
 
 use parcel_model::{
+    download::Download,
+    share_link::ShareLink,
+    types::Key,
     upload::{Upload, UploadPermission},
     user::User,
+    webhook::WebhookEvent,
 };
 
 use crate::{
@@ -23,13 +45,26 @@ use crate::{
         handlers::utils::{check_permission, get_upload_by_slug},
     },
     env::Env,
+    notifications::notify_download,
+    utils::get_client_ip,
+    workers::webhooks::queue_upload_event,
 };
 
+/// Encode a hex-encoded SHA-256 hash as base64, as used in the `Digest` and `Repr-Digest` headers.
+fn digest_base64(hash: &str) -> Option<String> {
+    let bytes = (0..hash.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(hash.get(index..index + 2)?, 16).ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
 /// Builds a Content-Disposition header value with a safely encoded filename.
 ///
 /// This function properly escapes the filename to prevent header injection attacks
 /// and uses RFC 5987 encoding for non-ASCII characters.
-fn content_disposition_filename(filename: &str) -> String {
+pub fn content_disposition_filename(filename: &str) -> String {
     // Check if filename contains only ASCII characters
     let is_ascii = filename.chars().all(|c| c.is_ascii() && c != '"' && c != '\\');
 
@@ -62,48 +97,381 @@ fn content_disposition_filename(filename: &str) -> String {
     }
 }
 
-async fn send_download(
+/// Counts the bytes of a response body as they are sent, and records them against a download when
+/// the body is dropped, whether or not the whole body was sent.
+struct DownloadTracker<R> {
+    inner: R,
+    download: Option<(SqlitePool, Key<Download>)>,
+    sent: u64,
+    length: u64,
+}
+
+impl<R> DownloadTracker<R> {
+    fn new(inner: R, download: Option<(SqlitePool, Key<Download>)>, length: u64) -> Self {
+        Self {
+            inner,
+            download,
+            sent: 0,
+            length,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for DownloadTracker<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        self.sent += (buf.filled().len() - before) as u64;
+        result
+    }
+}
+
+impl<R> Drop for DownloadTracker<R> {
+    fn drop(&mut self) {
+        let Some((pool, id)) = self.download.take() else {
+            return;
+        };
+
+        let sent = self.sent;
+        let completed = sent >= self.length;
+        if !completed {
+            tracing::info!(%id, sent, length = self.length, "Download was not completed");
+        }
+
+        tokio::spawn(async move {
+            if let Err(err) = Download::finish(&pool, id, sent as i64, completed).await {
+                tracing::error!(%id, ?err, "Unable to record bytes sent for download");
+            }
+        });
+    }
+}
+
+/// The maximum number of ranges that we will send in a single `multipart/byteranges` response.
+///
+/// Requests for more ranges than this are answered with the whole file.
+const MAX_RANGES: usize = 16;
+
+/// Get the byte ranges requested by the `Range` header, as inclusive start and end offsets.
+///
+/// Returns `Ok(None)` when the whole file should be sent: there is no `Range` header, the `If-Range`
+/// header shows that the client's copy is out of date, or there are too many ranges. Returns an
+/// error if none of the ranges can be satisfied.
+fn requested_ranges(
+    headers: &HeaderMap,
+    etag: &ETag,
+    last_modified: &LastModified,
+    length: u64,
+) -> Result<Option<Vec<(u64, u64)>>, ()> {
+    let Some(range) = headers.typed_get::<Range>() else {
+        return Ok(None);
+    };
+
+    if let Some(if_range) = headers.typed_get::<IfRange>() {
+        if if_range.is_modified(Some(etag), Some(last_modified)) {
+            return Ok(None);
+        }
+    }
+
+    let ranges = range
+        .satisfiable_ranges(length)
+        .filter_map(|(start, end)| {
+            let start = match start {
+                Bound::Included(start) => start,
+                Bound::Excluded(start) => start + 1,
+                Bound::Unbounded => 0,
+            };
+
+            let end = match end {
+                Bound::Included(end) => end.min(length.saturating_sub(1)),
+                Bound::Excluded(end) => end.min(length).saturating_sub(1),
+                Bound::Unbounded => length.saturating_sub(1),
+            };
+
+            (start < length && start <= end).then_some((start, end))
+        })
+        .collect::<Vec<_>>();
+
+    if ranges.is_empty() {
+        return Err(());
+    }
+
+    if ranges.len() > MAX_RANGES {
+        return Ok(None);
+    }
+
+    Ok(Some(ranges))
+}
+
+/// Send the file for an upload to the client.
+///
+/// This supports conditional requests using the `If-None-Match` and `If-Modified-Since` headers,
+/// and partial requests using the `Range` and `If-Range` headers.
+///
+/// A download is only recorded when the response includes the first or the last byte of the file,
+/// so a video player that seeks within a file does not use up the remaining downloads, while a
+/// client cannot fetch the whole file without doing so. A client that resumes an interrupted
+/// download is counted again. Responses to `HEAD` requests and `304 Not Modified` responses are not
+/// recorded.
+///
+/// Each recorded download is also added to the download history of the upload, along with the
+/// client's address and user agent, the signed-in user (if any), and the number of bytes that were
+/// actually sent.
+///
+/// When the download is made through a share link, it is counted against the limit of the link as
+/// well as the upload's own limit, which applies to every download of the upload other than those
+/// made by its owners. Downloads by an owner are recorded, but do not reduce the remaining
+/// downloads.
+pub async fn send_download(
     env: &Env,
     mut upload: Upload,
+    mut link: Option<ShareLink>,
     user: Option<&User>,
+    request: &Request,
 ) -> poem::Result<Response> {
-    let path = env.cache_dir.join(&upload.slug);
-    tracing::info!(upload = %upload.id, path = ?path, "Opening file for upload");
-    let file = tokio::fs::File::open(&path).await.map_err(|err| {
-        tracing::error!(%upload.id, ?err, ?path, "Unable to open file");
+    let length = env.storage.size(&upload.blob).await.map_err(|err| {
+        tracing::error!(%upload.id, ?err, ?upload.slug, "Unable to get size of file");
         InternalServerError(err)
     })?;
 
-    let meta = file.metadata().await.map_err(|err| {
-        tracing::error!(%upload.id, ?err, ?path, "Unable to get metadata for file");
-        InternalServerError(err)
-    })?;
-
-    upload
-        .record_download(&env.pool, user)
-        .await
+    // The file for an upload never changes, so the slug and size are enough for a strong ETag.
+    let etag = format!("\"{}-{length:x}\"", upload.slug)
+        .parse::<ETag>()
         .map_err(|err| {
-            tracing::error!(%upload.id, ?err, ?upload.slug, "Unable to record download");
+            tracing::error!(%upload.id, ?err, "Unable to create ETag for upload");
+            poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+    let last_modified = LastModified::from(SystemTime::from(upload.uploaded_at));
+
+    let headers = request.headers();
+    let not_modified = match headers.typed_get::<IfNoneMatch>() {
+        Some(if_none_match) => !if_none_match.precondition_passes(&etag),
+        None => headers
+            .typed_get::<IfModifiedSince>()
+            .is_some_and(|since| !since.is_modified(upload.uploaded_at.into())),
+    };
+
+    if not_modified {
+        tracing::info!(%upload.id, "Upload has not been modified");
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .typed_header(etag)
+            .typed_header(last_modified)
+            .finish());
+    }
+
+    let Ok(ranges) = requested_ranges(headers, &etag, &last_modified, length) else {
+        tracing::info!(%upload.id, length, "Unsatisfiable range requested");
+        return Ok(Response::builder()
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .typed_header(ContentRange::unsatisfied_bytes(length))
+            .finish());
+    };
+
+    // Any response that reaches either end of the file is counted, so that the whole file cannot
+    // be fetched in pieces without using up a download.
+    let is_download = ranges.as_ref().is_none_or(|ranges| {
+        ranges
+            .iter()
+            .any(|&(start, end)| start == 0 || end + 1 >= length)
+    });
+
+    let download = if is_download && request.method() != Method::HEAD {
+        let previous_remaining = upload.remaining;
+        upload
+            .record_download(&env.pool, user)
+            .await
+            .map_err(|err| {
+                tracing::error!(%upload.id, ?err, ?upload.slug, "Unable to record download");
+                InternalServerError(err)
+            })?;
+
+        if let Some(ref mut link) = link {
+            link.record_download(&env.pool).await.map_err(|err| {
+                tracing::error!(%upload.id, %link.id, ?err, "Unable to record download of link");
+                InternalServerError(err)
+            })?;
+        }
+
+        let real_ip = RealIp::from_request_without_body(request).await?;
+        let remote_addr = get_client_ip(env.trust_proxy, &real_ip, request.remote_addr());
+        let user_agent = headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(ToString::to_string);
+
+        let mut download = Download::new(
+            upload.id,
+            user.map(|user| user.id),
+            remote_addr.map(|addr| addr.to_string()),
+            user_agent,
+        );
+
+        download.share_link = link.as_ref().map(|link| link.id);
+
+        download.create(&env.pool).await.map_err(|err| {
+            tracing::error!(%upload.id, ?err, "Unable to add download to history");
             InternalServerError(err)
         })?;
 
-    tracing::info!(%upload.id, ?meta, "Sending file to client");
+        queue_upload_event(
+            env,
+            WebhookEvent::UploadDownloaded,
+            &upload,
+            Some(json!({
+                "download": {
+                    "id": download.id,
+                    "user": download.user,
+                    "remote_addr": download.remote_addr,
+                    "user_agent": download.user_agent,
+                    "share_link": link.as_ref().map(|link| json!({
+                        "id": link.id,
+                        "name": link.name,
+                    })),
+                },
+            })),
+        )
+        .await;
+
+        if previous_remaining.is_some_and(|remaining| remaining > 0) && upload.remaining == Some(0)
+        {
+            queue_upload_event(env, WebhookEvent::UploadExhausted, &upload, None).await;
+        }
+
+        notify_download(
+            env,
+            &upload,
+            link.as_ref(),
+            user,
+            download.remote_addr.as_deref(),
+        )
+        .await;
+
+        Some((env.pool.clone(), download.id))
+    } else {
+        None
+    };
+
+    let mime_type = upload
+        .mime_type
+        .as_deref()
+        .unwrap_or("application/octet-stream");
 
     let mut builder = Response::builder()
-        .status(StatusCode::OK)
-        .header(CONTENT_DISPOSITION, content_disposition_filename(&upload.filename))
-        .header(CONTENT_LENGTH, meta.len());
+        .header(
+            CONTENT_DISPOSITION,
+            content_disposition_filename(&upload.filename),
+        )
+        .header(ACCEPT_RANGES, "bytes")
+        .typed_header(etag)
+        .typed_header(last_modified);
 
-    if let Some(ref mime_type) = upload.mime_type {
-        builder = builder.header(CONTENT_TYPE, mime_type);
+    // The digest is of the whole file, so it is sent with partial responses too. We send both the
+    // `Repr-Digest` header from RFC 9530 and the older `Digest` header from RFC 3230.
+    if let Some(digest) = upload.hash.as_deref().and_then(digest_base64) {
+        builder = builder
+            .header("Repr-Digest", format!("sha-256=:{digest}:"))
+            .header("Digest", format!("sha-256={digest}"));
     }
 
-    Ok(builder.body(Body::from_async_read(file)))
+    let open_error = |err| {
+        tracing::error!(%upload.id, ?err, ?upload.slug, "Unable to read file");
+        InternalServerError(err)
+    };
+
+    match ranges.as_deref() {
+        None => {
+            tracing::info!(%upload.id, length, "Sending file to client");
+
+            let file = env.storage.read(&upload.blob).await.map_err(open_error)?;
+
+            let mut builder = builder
+                .status(StatusCode::OK)
+                .header(CONTENT_LENGTH, length);
+            if let Some(ref mime_type) = upload.mime_type {
+                builder = builder.header(CONTENT_TYPE, mime_type);
+            }
+
+            let body = DownloadTracker::new(file, download, length);
+            Ok(builder.body(Body::from_async_read(body)))
+        }
+
+        Some(&[(start, end)]) => {
+            tracing::info!(%upload.id, start, end, "Sending range of file to client");
+
+            let reader = env
+                .storage
+                .read_range(&upload.blob, start, end)
+                .await
+                .map_err(open_error)?;
+            Ok(builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(CONTENT_TYPE, mime_type)
+                .header(CONTENT_LENGTH, end - start + 1)
+                .typed_header(
+                    ContentRange::bytes(start..=end, length)
+                        .map_err(|_| poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?,
+                )
+                .body(Body::from_async_read(DownloadTracker::new(
+                    reader,
+                    download,
+                    end - start + 1,
+                ))))
+        }
+
+        Some(ranges) => {
+            tracing::info!(%upload.id, ?ranges, "Sending ranges of file to client");
+
+            // Each range is sent as a part of a `multipart/byteranges` body, which we build by
+            // chaining together the part headers and readers for each range of the file.
+            let boundary = nanoid::nanoid!(32);
+            let mut body: Box<dyn AsyncRead + Send + Unpin> = Box::new(tokio::io::empty());
+            let mut body_length = 0;
+
+            for &(start, end) in ranges {
+                let part_header = format!(
+                    "--{boundary}\r\n\
+                    Content-Type: {mime_type}\r\n\
+                    Content-Range: bytes {start}-{end}/{length}\r\n\r\n"
+                );
+
+                let reader = env
+                    .storage
+                    .read_range(&upload.blob, start, end)
+                    .await
+                    .map_err(open_error)?;
+                body_length += part_header.len() as u64 + (end - start + 1) + 2;
+                body = Box::new(
+                    body.chain(Cursor::new(part_header))
+                        .chain(reader)
+                        .chain(&b"\r\n"[..]),
+                );
+            }
+
+            let closing = format!("--{boundary}--\r\n");
+            body_length += closing.len() as u64;
+            body = Box::new(body.chain(Cursor::new(closing)));
+            let body = DownloadTracker::new(body, download, body_length);
+
+            Ok(builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(
+                    CONTENT_TYPE,
+                    format!("multipart/byteranges; boundary={boundary}"),
+                )
+                .header(CONTENT_LENGTH, body_length)
+                .body(Body::from_async_read(body)))
+        }
+    }
 }
 
 #[handler]
 pub async fn get_download(
     env: Data<&Env>,
+    request: &Request,
     user: Option<SessionUser>,
     Path(slug): Path<String>,
 ) -> poem::Result<Response> {
@@ -118,7 +486,7 @@ pub async fn get_download(
     )
     .await?;
 
-    send_download(&env, upload, user.as_deref()).await
+    send_download(&env, upload, None, user.as_deref(), request).await
 }
 
 #[derive(Debug, Deserialize)]
@@ -130,6 +498,7 @@ pub struct DownloadForm {
 #[handler]
 pub async fn post_download(
     env: Data<&Env>,
+    request: &Request,
     session: &Session,
     user: Option<SessionUser>,
     verifier: &CsrfVerifier,
@@ -170,5 +539,5 @@ pub async fn post_download(
         upload.set_password(&env.pool, &password).await?;
     }
 
-    send_download(&env, upload, user.as_deref()).await
+    send_download(&env, upload, None, user.as_deref(), request).await
 }
@@ -12,16 +12,17 @@ use time::Date;
 use validator::{Validate, ValidationError, ValidationErrors};
 
 use parcel_model::{
+    audit::{AuditAction, AuditTarget},
     password::StoredPassword,
     types::Key,
-    upload::{Upload, UploadPermission},
+    upload::{parse_tags, Upload, UploadPermission, MAX_TAG_LENGTH},
 };
 
 use crate::{
     app::{
         errors::CsrfError,
-        extractors::user::SessionUser,
-        handlers::utils::{check_permission, get_upload_by_id},
+        extractors::{audit::Auditor, user::SessionUser},
+        handlers::utils::{check_permission, get_upload_by_id, upload_change_details},
         templates::{authorized_context, render_template},
     },
     env::Env,
@@ -38,12 +39,15 @@ pub async fn get_edit(
     let upload = get_upload_by_id(&env, id).await?;
     check_permission(&env, &upload, Some(&user), UploadPermission::Edit).await?;
 
+    let tags = get_tags(&env, &upload).await?;
+
     render_template(
         "uploads/edit.html",
         context! {
             token => token.0,
             now => time::OffsetDateTime::now_utc(),
             upload,
+            tags => tags.join(", "),
             has_password => upload.password.is_some(),
             ..authorized_context(&env, &user)
         },
@@ -51,6 +55,13 @@ pub async fn get_edit(
     .await
 }
 
+async fn get_tags(env: &Env, upload: &Upload) -> poem::Result<Vec<String>> {
+    upload.get_tags(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %upload.id, "Unable to get tags for upload");
+        InternalServerError(err)
+    })
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CheckSlugForm {
     token: String,
@@ -114,6 +125,9 @@ pub struct UploadEditForm {
     password: Option<String>,
     #[validate(length(min = 3, max = 100))]
     custom_slug: Option<String>,
+    /// The tags of the upload, separated by commas.
+    #[serde(default)]
+    tags: String,
 }
 
 #[handler]
@@ -122,6 +136,7 @@ pub async fn post_edit(
     next_token: &CsrfToken,
     verifier: &CsrfVerifier,
     SessionUser(user): SessionUser,
+    auditor: Auditor,
     Path(id): Path<Key<Upload>>,
     Form(form): Form<UploadEditForm>,
 ) -> poem::Result<Response> {
@@ -163,6 +178,16 @@ pub async fn post_edit(
         }
     }
 
+    let tags = parse_tags(&form.tags);
+    if tags.iter().any(|tag| tag.chars().count() > MAX_TAG_LENGTH) {
+        errors.add(
+            "tags",
+            ValidationError::new("tag_length").with_message(
+                format!("Tags cannot be longer than {MAX_TAG_LENGTH} characters").into(),
+            ),
+        );
+    }
+
     if !errors.is_empty() {
         return Ok(render_template(
             "uploads/edit.html",
@@ -180,6 +205,7 @@ pub async fn post_edit(
                     change_password => form.change_password.as_deref() == Some("on"),
                     password => &form.password,
                     custom_slug => &form.custom_slug,
+                    tags => &form.tags,
                 },
                 upload,
                 ..authorized_context(&env, &user)
@@ -191,6 +217,8 @@ pub async fn post_edit(
         .into_response());
     }
 
+    let before = upload.clone();
+    let before_tags = get_tags(&env, &upload).await?;
     let UploadEditForm {
         filename,
         public,
@@ -231,6 +259,7 @@ pub async fn post_edit(
         has_password = ?has_password,
         new_password = password.is_some(),
         custom_slug = ?custom_slug,
+        tags = ?tags,
         "Updating upload");
 
     upload.filename = filename;
@@ -245,6 +274,27 @@ pub async fn post_edit(
         InternalServerError(err)
     })?;
 
+    upload.set_tags(&env.pool, &tags).await.map_err(|err| {
+        tracing::error!(?err, %upload.id, "Failed to set tags of upload");
+        InternalServerError(err)
+    })?;
+
+    let mut details = upload_change_details(&before, &upload);
+    if before_tags != tags {
+        details["before"]["tags"] = json!(before_tags);
+        details["after"]["tags"] = json!(tags);
+    }
+
+    auditor
+        .record(
+            &env,
+            &user,
+            AuditAction::UploadEdit,
+            AuditTarget::Upload(upload.id),
+            Some(details),
+        )
+        .await;
+
     Ok(Html("")
         .with_header(
             "HX-Trigger",
@@ -0,0 +1,107 @@
+use minijinja::context;
+use poem::{
+    error::InternalServerError,
+    handler,
+    http::StatusCode,
+    web::{Data, Html, Path},
+};
+
+use parcel_model::{
+    download::{DownloadList, DownloadScope},
+    types::Key,
+    upload::Upload,
+    user::User,
+};
+
+use crate::{
+    app::{
+        extractors::user::SessionUser,
+        handlers::utils::{get_download_activity, get_upload_by_id},
+        templates::{authorized_context, render_template},
+    },
+    env::Env,
+};
+
+/// The number of downloads shown in each page of the download history.
+const PAGE_SIZE: u32 = 50;
+
+/// Get an upload, making sure that the user is allowed to see its download history.
+///
+/// Only the owner of an upload, or the members of the team that owns it, can see who downloaded it.
+async fn get_owned_upload(env: &Env, user: &User, id: Key<Upload>) -> poem::Result<Upload> {
+    let upload = get_upload_by_id(env, id).await?;
+    let owner = upload.is_owner(&env.pool, user).await.map_err(|err| {
+        tracing::error!(?err, %upload.id, %user.id, "Failed to check upload ownership");
+        InternalServerError(err)
+    })?;
+
+    if owner.is_none() {
+        tracing::error!(%upload.id, %user.id, "User tried to view downloads of upload they do not own");
+        return Err(poem::Error::from_status(StatusCode::FORBIDDEN));
+    }
+
+    Ok(upload)
+}
+
+#[handler]
+pub async fn get_history(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    Path(id): Path<Key<Upload>>,
+) -> poem::Result<Html<String>> {
+    let upload = get_owned_upload(&env, &user, id).await?;
+    let scope = DownloadScope::Upload(upload.id);
+    let activity = get_download_activity(&env, scope).await?;
+    let downloads = DownloadList::get(&env.pool, scope, 0, PAGE_SIZE)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %upload.id, "Unable to get downloads of upload");
+            InternalServerError(err)
+        })?;
+
+    let page_url = format!("/uploads/{}/downloads", upload.id);
+
+    render_template(
+        "uploads/history.html",
+        context! {
+            upload,
+            activity,
+            downloads,
+            page => 0,
+            page_url,
+            ..authorized_context(&env, &user)
+        },
+    )
+    .await
+}
+
+#[handler]
+pub async fn get_history_page(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    Path((id, page)): Path<(Key<Upload>, u32)>,
+) -> poem::Result<Html<String>> {
+    let upload = get_owned_upload(&env, &user, id).await?;
+    let downloads = DownloadList::get(
+        &env.pool,
+        DownloadScope::Upload(upload.id),
+        page * PAGE_SIZE,
+        PAGE_SIZE,
+    )
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, %upload.id, page, "Unable to get page of downloads of upload");
+        InternalServerError(err)
+    })?;
+
+    render_template(
+        "downloads/page.html",
+        context! {
+            downloads,
+            page,
+            page_url => format!("/uploads/{}/downloads", upload.id),
+            ..authorized_context(&env, &user)
+        },
+    )
+    .await
+}
@@ -0,0 +1,177 @@
+use minijinja::context;
+use poem::{
+    error::InternalServerError,
+    handler,
+    http::StatusCode,
+    web::{CsrfToken, CsrfVerifier, Data, Form, Html, Path},
+};
+use serde::Deserialize;
+use serde_json::json;
+use time::Date;
+use validator::Validate;
+
+use parcel_model::{
+    audit::{AuditAction, AuditTarget},
+    password::StoredPassword,
+    share_link::ShareLink,
+    types::Key,
+    upload::{Upload, UploadPermission},
+};
+
+use crate::{
+    app::{
+        errors::CsrfError,
+        extractors::{audit::Auditor, user::SessionUser},
+        handlers::utils::{check_permission, get_upload_by_id},
+    },
+    env::Env,
+};
+
+use super::upload::render_share;
+
+/// Describe a share link for the audit log.
+fn link_audit_details(link: &ShareLink) -> serde_json::Value {
+    json!({
+        "id": link.id,
+        "name": link.name,
+        "limit": link.limit,
+        "expiry_date": link.expiry_date,
+        "has_password": link.password.is_some(),
+    })
+}
+
+time::serde::format_description!(iso8601_date, Date, "[year]-[month]-[day]");
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct NewLinkForm {
+    csrf_token: String,
+    #[validate(length(min = 1, max = 100))]
+    name: String,
+    #[validate(range(min = 1, message = "A share link must allow at least one download"))]
+    limit: Option<i64>,
+    #[serde(default, with = "iso8601_date::option")]
+    expiry_date: Option<Date>,
+    #[validate(length(min = 1, message = "The password cannot be empty"))]
+    password: Option<String>,
+}
+
+#[handler]
+pub async fn post_links(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    auditor: Auditor,
+    csrf_token: &CsrfToken,
+    csrf_verifier: &CsrfVerifier,
+    Path(id): Path<Key<Upload>>,
+    Form(form): Form<NewLinkForm>,
+) -> poem::Result<Html<String>> {
+    if !csrf_verifier.is_valid(&form.csrf_token) {
+        tracing::warn!(%id, "CSRF verification failed for share link creation");
+        return Err(CsrfError.into());
+    }
+
+    let upload = get_upload_by_id(&env, id).await?;
+    check_permission(&env, &upload, Some(&user), UploadPermission::Share).await?;
+
+    if let Err(errors) = form.validate() {
+        let form = context! {
+            name => form.name,
+            limit => form.limit,
+            expiry_date => form.expiry_date,
+            has_password => form.password.is_some(),
+        };
+
+        return render_share(&env, &user, csrf_token, upload, true, Some((form, errors))).await;
+    }
+
+    let mut link = ShareLink::new(
+        upload.id,
+        Some(user.id),
+        form.name.trim(),
+        form.limit,
+        form.expiry_date,
+    );
+
+    if let Some(password) = form.password {
+        link.password = Some(StoredPassword::new(&password).map_err(|err| {
+            tracing::error!(?err, %upload.id, "Failed to hash share link password");
+            poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR)
+        })?);
+    }
+
+    link.create(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %upload.id, "Failed to create share link");
+        InternalServerError(err)
+    })?;
+
+    tracing::info!(%upload.id, %link.id, "Created share link");
+    auditor
+        .record(
+            &env,
+            &user,
+            AuditAction::ShareLinkCreate,
+            AuditTarget::Upload(upload.id),
+            Some(json!({ "link": link_audit_details(&link) })),
+        )
+        .await;
+
+    render_share(&env, &user, csrf_token, upload, true, None).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevokeLinkForm {
+    csrf_token: String,
+}
+
+#[handler]
+pub async fn post_revoke_link(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    auditor: Auditor,
+    csrf_token: &CsrfToken,
+    csrf_verifier: &CsrfVerifier,
+    Path((id, link_id)): Path<(Key<Upload>, Key<ShareLink>)>,
+    Form(RevokeLinkForm {
+        csrf_token: form_token,
+    }): Form<RevokeLinkForm>,
+) -> poem::Result<Html<String>> {
+    if !csrf_verifier.is_valid(&form_token) {
+        tracing::warn!(%id, %link_id, "CSRF verification failed for share link revocation");
+        return Err(CsrfError.into());
+    }
+
+    let upload = get_upload_by_id(&env, id).await?;
+    check_permission(&env, &upload, Some(&user), UploadPermission::Share).await?;
+
+    let Some(mut link) = ShareLink::get(&env.pool, link_id)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %link_id, "Unable to get share link by ID");
+            InternalServerError(err)
+        })?
+        .filter(|link| link.upload == upload.id)
+    else {
+        tracing::error!(%upload.id, %link_id, "Unable to find share link for upload");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    if link.revoked_at.is_none() {
+        link.revoke(&env.pool).await.map_err(|err| {
+            tracing::error!(?err, %link.id, "Failed to revoke share link");
+            InternalServerError(err)
+        })?;
+
+        tracing::info!(%upload.id, %link.id, "Revoked share link");
+        auditor
+            .record(
+                &env,
+                &user,
+                AuditAction::ShareLinkRevoke,
+                AuditTarget::Upload(upload.id),
+                Some(json!({ "link": link_audit_details(&link) })),
+            )
+            .await;
+    }
+
+    render_share(&env, &user, csrf_token, upload, true, None).await
+}
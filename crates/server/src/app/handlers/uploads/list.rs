@@ -7,18 +7,29 @@ use poem::{
     IntoResponse,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 
 use parcel_model::{
-    team::{HomeTab, TeamMember, TeamTab},
+    audit::{AuditAction, AuditTarget},
+    folder::Folder,
+    saved_filter::SavedFilter,
+    team::{HomeTab, Team, TeamMember, TeamTab},
     types::Key,
-    upload::{Upload, UploadList, UploadOrder, UploadStats},
+    upload::{
+        FolderFilter, MimeFamily, Upload, UploadFilter, UploadList, UploadOrder, UploadPermission,
+        UploadStats,
+    },
+    user::User,
 };
 
 use crate::{
     app::{
         errors::CsrfError,
-        extractors::user::SessionUser,
-        handlers::utils::delete_upload_cache_by_slug,
+        extractors::{audit::Auditor, user::SessionUser},
+        handlers::utils::{
+            get_folder_by_id, get_folder_listing, get_team_for_member, release_blob,
+            upload_audit_details, upload_change_details,
+        },
         templates::{authorized_context, render_template},
     },
     env::Env,
@@ -26,19 +37,94 @@ use crate::{
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ListQuery {
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub search: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub tag: String,
+    /// The family of MIME types to include, such as `image` or `archive`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub kind: String,
+    /// Whether to include only public (`yes`) or private (`no`) uploads.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub public: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub expired: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub password: String,
+    /// The smallest size of upload to include, in megabytes.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub min_size: String,
+    /// The largest size of upload to include, in megabytes.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub max_size: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub order: Option<UploadOrder>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub asc: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub folder: Option<Key<Folder>>,
+    /// The saved filter that is being shown, if any, which is highlighted in the tabs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub saved: Option<Key<SavedFilter>>,
+}
+
+/// Parse a filter for a yes or no question, where anything else matches every upload.
+fn parse_flag(value: &str) -> Option<bool> {
+    match value {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parse a size in megabytes into a number of bytes.
+fn parse_size(value: &str) -> Option<i64> {
+    let size = value.trim().parse::<f64>().ok()?;
+    if size.is_finite() && size >= 0.0 {
+        Some((size * 1024.0 * 1024.0) as i64)
+    } else {
+        None
+    }
 }
 
 impl ListQuery {
-    pub fn get_search(&self) -> Option<&str> {
+    pub fn get_filter(&self) -> UploadFilter {
         let search = self.search.trim();
-        if search.is_empty() {
+        let tag = self.tag.trim().to_lowercase();
+
+        UploadFilter {
+            search: (!search.is_empty()).then(|| search.to_string()),
+            tag: (!tag.is_empty()).then_some(tag),
+            kind: MimeFamily::ALL
+                .into_iter()
+                .find(|kind| kind.as_str() == self.kind),
+            public: parse_flag(&self.public),
+            expired: parse_flag(&self.expired),
+            password: parse_flag(&self.password),
+            min_size: parse_size(&self.min_size),
+            max_size: parse_size(&self.max_size),
+        }
+    }
+
+    /// Get the order of the uploads: search results are sorted by how well they match, unless
+    /// another order has been chosen.
+    pub fn get_order(&self, user: &User) -> Option<UploadOrder> {
+        if self.order.is_none() && !self.search.trim().is_empty() {
             None
         } else {
-            Some(search)
+            Some(self.order.unwrap_or(user.default_order))
+        }
+    }
+
+    /// Get the folder to list uploads from: searches and filters include the uploads in every
+    /// folder.
+    pub fn get_folder_filter(&self) -> FolderFilter {
+        if !self.get_filter().is_empty() {
+            FolderFilter::All
+        } else if let Some(folder) = self.folder {
+            FolderFilter::Folder(folder)
+        } else {
+            FolderFilter::TopLevel
         }
     }
 }
@@ -50,6 +136,8 @@ pub async fn get_list(
     csrf_token: &CsrfToken,
     Query(query): Query<ListQuery>,
 ) -> poem::Result<Html<String>> {
+    let filter = query.get_filter();
+
     // Execute all database queries in parallel
     let (has_teams_result, home_result, tabs_result, stats_result, uploads_result) = tokio::join!(
         user.has_teams(&env.pool),
@@ -59,8 +147,9 @@ pub async fn get_list(
         UploadList::get_for_user(
             &env.pool,
             user.id,
-            query.get_search(),
-            query.order.unwrap_or(user.default_order),
+            query.get_folder_filter(),
+            &filter,
+            query.get_order(&user),
             query.asc.unwrap_or(user.default_asc),
             0,
             50,
@@ -93,6 +182,8 @@ pub async fn get_list(
         InternalServerError(err)
     })?;
 
+    let listing = get_folder_listing(&env, &user, None, query.folder).await?;
+
     render_template(
         "uploads/list.html",
         context! {
@@ -100,6 +191,7 @@ pub async fn get_list(
             tabs,
             stats,
             uploads,
+            listing,
             has_teams,
             query,
             csrf_token => csrf_token.0,
@@ -111,60 +203,39 @@ pub async fn get_list(
     .await
 }
 
-#[handler]
-pub async fn post_delete(
-    env: Data<&Env>,
-    SessionUser(user): SessionUser,
-    csrf_verifier: &CsrfVerifier,
-    Form(form): Form<Vec<(String, String)>>,
-) -> poem::Result<impl IntoResponse> {
-    let csrf_token = form
-        .iter()
+/// Get the CSRF token from the form data of a bulk action.
+fn get_csrf_token(form: &[(String, String)]) -> poem::Result<&str> {
+    form.iter()
         .find(|(name, _)| name == "csrf_token")
-        .map(|(_, token)| token)
+        .map(|(_, token)| token.as_str())
         .ok_or_else(|| {
             tracing::error!("CSRF token not found in form data");
             poem::Error::from_status(StatusCode::BAD_REQUEST)
-        })?;
-
-    if !csrf_verifier.is_valid(csrf_token) {
-        tracing::error!("Invalid CSRF token in form data");
-        return Err(CsrfError.into());
-    }
+        })
+}
 
-    let ids: Vec<Key<Upload>> = form
-        .into_iter()
+/// Get the IDs of the uploads that were selected for a bulk action.
+fn get_selected(form: &[(String, String)]) -> poem::Result<Vec<Key<Upload>>> {
+    form.iter()
         .filter(|(name, _)| name == "selected")
         .map(|(_, id)| id.parse::<Key<Upload>>())
         .collect::<Result<Vec<_>, _>>()
         .map_err(|_| {
             tracing::error!("Invalid upload ID in form data");
             poem::Error::from_status(StatusCode::BAD_REQUEST)
-        })?;
-
-    if ids.is_empty() {
-        return Ok(Html("").with_header("HX-Refresh", "true"));
-    }
-
-    // Batch fetch all uploads in a single query
-    let uploads = Upload::get_many(&env.pool, &ids).await.map_err(|err| {
-        tracing::error!(?err, "Unable to fetch uploads for bulk delete");
-        InternalServerError(err)
-    })?;
-
-    // Verify all requested IDs were found
-    if uploads.len() != ids.len() {
-        tracing::error!(
-            requested = ids.len(),
-            found = uploads.len(),
-            "Some uploads not found for bulk delete"
-        );
-        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
-    }
+        })
+}
 
+/// Check that a user has permission to delete or edit every one of the uploads in a bulk action.
+async fn check_uploads_permission(
+    env: &Env,
+    user: &User,
+    uploads: &[Upload],
+    permission: UploadPermission,
+) -> poem::Result<()> {
     // Pre-fetch user's team memberships once for permission checking
     let team_memberships = if user.admin {
-        Vec::new() // Admin can delete anything, no need to fetch
+        Vec::new() // Admin can do anything, no need to fetch
     } else {
         TeamMember::get_for_user(&env.pool, user.id)
             .await
@@ -175,36 +246,80 @@ pub async fn post_delete(
     };
 
     // Check permissions for each upload in-memory
-    let mut ids_to_delete = Vec::with_capacity(uploads.len());
-    for upload in &uploads {
-        let can_delete = if user.admin {
+    for upload in uploads {
+        let granted = if user.admin {
             true
         } else if upload.owner_user == Some(user.id) {
             // User owns the upload directly
             true
         } else if let Some(team_id) = upload.owner_team {
-            // Check if user is a member of the team with delete permission
-            team_memberships
-                .iter()
-                .any(|m| m.team == team_id && m.can_delete)
+            // Check if user is a member of the team with the needed permission
+            team_memberships.iter().any(|m| {
+                m.team == team_id
+                    && if permission == UploadPermission::Delete {
+                        m.can_delete
+                    } else {
+                        m.can_edit
+                    }
+            })
         } else {
             false
         };
 
-        if !can_delete {
+        if !granted {
             tracing::error!(
                 upload = %upload.id,
                 user = %user.id,
-                "User tried to delete upload without permission"
+                ?permission,
+                "User tried to change upload without permission"
             );
             return Err(poem::Error::from_status(StatusCode::FORBIDDEN));
         }
+    }
+
+    Ok(())
+}
 
-        ids_to_delete.push(upload.id);
+#[handler]
+pub async fn post_delete(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    auditor: Auditor,
+    csrf_verifier: &CsrfVerifier,
+    Form(form): Form<Vec<(String, String)>>,
+) -> poem::Result<impl IntoResponse> {
+    let csrf_token = get_csrf_token(&form)?;
+    if !csrf_verifier.is_valid(csrf_token) {
+        tracing::error!("Invalid CSRF token in form data");
+        return Err(CsrfError.into());
     }
 
+    let ids = get_selected(&form)?;
+    if ids.is_empty() {
+        return Ok(Html("").with_header("HX-Refresh", "true"));
+    }
+
+    // Batch fetch all uploads in a single query
+    let uploads = Upload::get_many(&env.pool, &ids).await.map_err(|err| {
+        tracing::error!(?err, "Unable to fetch uploads for bulk delete");
+        InternalServerError(err)
+    })?;
+
+    // Verify all requested IDs were found
+    if uploads.len() != ids.len() {
+        tracing::error!(
+            requested = ids.len(),
+            found = uploads.len(),
+            "Some uploads not found for bulk delete"
+        );
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    }
+
+    check_uploads_permission(&env, &user, &uploads, UploadPermission::Delete).await?;
+    let ids_to_delete = uploads.iter().map(|upload| upload.id).collect::<Vec<_>>();
+
     // Batch delete all uploads in a single query
-    let deleted_slugs = Upload::delete_many(&env.pool, &ids_to_delete)
+    let deleted_blobs = Upload::delete_many(&env.pool, &ids_to_delete)
         .await
         .map_err(|err| {
             tracing::error!(?err, "Unable to delete uploads");
@@ -212,8 +327,160 @@ pub async fn post_delete(
         })?;
 
     // Delete cache files for each deleted upload
-    for slug in deleted_slugs {
-        delete_upload_cache_by_slug(&env, &slug).await;
+    for blob in deleted_blobs {
+        release_blob(&env, &blob).await;
+    }
+
+    for upload in &uploads {
+        auditor
+            .record(
+                &env,
+                &user,
+                AuditAction::UploadDelete,
+                AuditTarget::Upload(upload.id),
+                Some(json!({ "before": upload_audit_details(upload) })),
+            )
+            .await;
+    }
+
+    Ok(Html("").with_header("HX-Refresh", "true"))
+}
+
+/// Get the uploads selected for a bulk move, checking that the user can edit all of them.
+async fn get_uploads_to_move(
+    env: &Env,
+    user: &User,
+    ids: &[Key<Upload>],
+) -> poem::Result<Vec<Upload>> {
+    let uploads = Upload::get_many(&env.pool, ids).await.map_err(|err| {
+        tracing::error!(?err, "Unable to fetch uploads for bulk move");
+        InternalServerError(err)
+    })?;
+
+    if uploads.len() != ids.len() {
+        tracing::error!(
+            requested = ids.len(),
+            found = uploads.len(),
+            "Some uploads not found for bulk move"
+        );
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    }
+
+    check_uploads_permission(env, user, &uploads, UploadPermission::Edit).await?;
+    Ok(uploads)
+}
+
+#[handler]
+pub async fn get_move_uploads(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    csrf_token: &CsrfToken,
+    Query(query): Query<Vec<(String, String)>>,
+) -> poem::Result<Html<String>> {
+    let ids = get_selected(&query)?;
+    let uploads = get_uploads_to_move(&env, &user, &ids).await?;
+
+    let team = query
+        .iter()
+        .find(|(name, _)| name == "team")
+        .map(|(_, team)| team.parse::<Key<Team>>())
+        .transpose()
+        .map_err(|_| {
+            tracing::error!("Invalid team ID in query");
+            poem::Error::from_status(StatusCode::BAD_REQUEST)
+        })?;
+
+    let team = match team {
+        Some(team_id) => Some(get_team_for_member(&env, &user, team_id).await?),
+        None => None,
+    };
+
+    let folders = match team {
+        Some(ref team) => Folder::get_tree_for_team(&env.pool, team.id).await,
+        None => Folder::get_tree_for_user(&env.pool, user.id).await,
+    }
+    .map_err(|err| {
+        tracing::error!(?err, %user.id, "Unable to get folders");
+        InternalServerError(err)
+    })?;
+
+    render_template(
+        "uploads/move.html",
+        context! {
+            uploads,
+            folders,
+            team,
+            csrf_token => csrf_token.0,
+            ..authorized_context(&env, &user)
+        },
+    )
+    .await
+}
+
+/// Move the selected uploads into a folder, or to the top level.
+#[handler]
+pub async fn post_move_uploads(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    auditor: Auditor,
+    csrf_verifier: &CsrfVerifier,
+    Form(form): Form<Vec<(String, String)>>,
+) -> poem::Result<impl IntoResponse> {
+    let csrf_token = get_csrf_token(&form)?;
+    if !csrf_verifier.is_valid(csrf_token) {
+        tracing::error!("Invalid CSRF token in form data");
+        return Err(CsrfError.into());
+    }
+
+    let ids = get_selected(&form)?;
+    if ids.is_empty() {
+        return Ok(Html("").with_header("HX-Refresh", "true"));
+    }
+
+    let uploads = get_uploads_to_move(&env, &user, &ids).await?;
+
+    let folder = match form.iter().find(|(name, _)| name == "folder") {
+        Some((_, id)) if !id.is_empty() => {
+            let id = id.parse::<Key<Folder>>().map_err(|_| {
+                tracing::error!("Invalid folder ID in form data");
+                poem::Error::from_status(StatusCode::BAD_REQUEST)
+            })?;
+
+            Some(get_folder_by_id(&env, id).await?)
+        }
+
+        _ => None,
+    };
+
+    if let Some(ref folder) = folder {
+        if let Some(upload) = uploads.iter().find(|upload| !folder.same_owner(upload)) {
+            tracing::error!(%folder.id, %upload.id, "Upload does not have the same owner as folder");
+            return Err(poem::Error::from_status(StatusCode::BAD_REQUEST));
+        }
+    }
+
+    let folder = folder.map(|folder| folder.id);
+    Upload::move_many(&env.pool, &ids, folder)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, ?folder, "Unable to move uploads");
+            InternalServerError(err)
+        })?;
+
+    tracing::info!(?folder, uploads = ids.len(), "Moved uploads");
+    for upload in &uploads {
+        let mut after = upload.clone();
+        after.folder = folder;
+
+        auditor
+            .record(
+                &env,
+                &user,
+                AuditAction::UploadEdit,
+                AuditTarget::Upload(upload.id),
+                Some(upload_change_details(upload, &after)),
+            )
+            .await;
     }
 
     Ok(Html("").with_header("HX-Refresh", "true"))
@@ -234,8 +501,9 @@ pub async fn get_page(
     let uploads = UploadList::get_for_user(
         &env.pool,
         user.id,
-        query.get_search(),
-        query.order.unwrap_or(user.default_order),
+        query.get_folder_filter(),
+        &query.get_filter(),
+        query.get_order(&user),
         query.asc.unwrap_or(user.default_asc),
         50 * page,
         50,
@@ -5,25 +5,21 @@ use poem::{
     handler,
     http::StatusCode,
     web::{CsrfToken, CsrfVerifier, Data, Html, Json, Multipart, Query, RealIp},
+    IntoResponse, Response,
 };
-use serde::{Deserialize, Serialize};
-use time::OffsetDateTime;
+use serde::Deserialize;
+use serde_json::json;
 
-use parcel_model::{team::Team, types::Key, upload::Upload};
-
-/// Represents a pending upload before it's inserted into the database.
-#[derive(Debug, Serialize)]
-struct PendingUpload {
-    id: Key<Upload>,
-    slug: String,
-    filename: String,
-    size: i64,
-}
+use parcel_model::{folder::Folder, team::Team, types::Key, upload::Upload};
 
 use crate::{
     app::{
-        errors::CsrfError,
+        errors::{CsrfError, QuotaExceededError},
         extractors::user::SessionUser,
+        handlers::utils::{
+            cache_upload_field, create_bundle, discard_pending_uploads, get_folder_for_owner,
+            get_remaining_quota, get_team_for_member, insert_pending_uploads,
+        },
         templates::{authorized_context, render_template},
     },
     env::Env,
@@ -36,6 +32,8 @@ pub struct NewQuery {
     immediate: bool,
     #[serde(default)]
     team: Option<Key<Team>>,
+    #[serde(default)]
+    folder: Option<Key<Folder>>,
 }
 
 #[handler]
@@ -43,38 +41,33 @@ pub async fn get_new(
     env: Data<&Env>,
     csrf_token: &CsrfToken,
     SessionUser(user): SessionUser,
-    Query(NewQuery { immediate, team }): Query<NewQuery>,
+    Query(NewQuery {
+        immediate,
+        team,
+        folder,
+    }): Query<NewQuery>,
 ) -> poem::Result<Html<String>> {
     let team = if let Some(team_id) = team {
-        let Some(team) = Team::get(&env.pool, team_id).await.map_err(|err| {
-            tracing::error!(%team_id, ?err, "Unable to get team by ID");
-            InternalServerError(err)
-        })?
-        else {
-            tracing::error!(%team_id, "Team not found");
-            return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
-        };
-
-        let is_member = user.is_member_of(&env.pool, team.id).await.map_err(|err| {
-            tracing::error!(%user.id, %team.id, "Unable to check if user is member of team");
-            InternalServerError(err)
-        })?;
-
-        if !is_member {
-            tracing::error!(%user.id, %team.id, "User is not a member of team");
-            return Err(poem::Error::from_status(StatusCode::FORBIDDEN));
-        }
+        Some(get_team_for_member(&env, &user, team_id).await?)
+    } else {
+        None
+    };
 
-        Some(team)
+    let folder = if let Some(folder_id) = folder {
+        Some(get_folder_for_owner(&env, &user, team.as_ref(), folder_id).await?)
     } else {
         None
     };
 
+    let remaining = get_remaining_quota(&env, &user, team.as_ref()).await?;
+
     render_template(
         "uploads/new.html",
         context! {
             immediate,
             team,
+            folder,
+            remaining,
             csrf_token => csrf_token.0,
             upload_js => javascript!("$CARGO_MANIFEST_DIR/scripts/components/upload.ts"),
             ..authorized_context(&env, &user)
@@ -91,11 +84,14 @@ pub async fn post_new(
     SessionUser(user): SessionUser,
     csrf_verifier: &CsrfVerifier,
     mut form: Multipart,
-) -> poem::Result<Json<()>> {
+) -> poem::Result<Response> {
     let mut seen_csrf = false;
     let mut uploads = Vec::new();
     let mut failures = Vec::new();
     let mut team = None;
+    let mut bundle = None;
+    let mut folder = None;
+    let mut quota = get_remaining_quota(&env, &user, None).await?;
 
     while let Ok(Some(field)) = form.next_field().await {
         if field.name() == Some("csrf_token") {
@@ -111,6 +107,13 @@ pub async fn post_new(
                 return Err(poem::Error::from_status(StatusCode::BAD_REQUEST));
             }
 
+            // The quota depends on the team, so we need to see the team before any files.
+            if !uploads.is_empty() || !failures.is_empty() {
+                tracing::error!("Team field after file fields in upload form");
+                discard_pending_uploads(&env, &uploads).await;
+                return Err(poem::Error::from_status(StatusCode::BAD_REQUEST));
+            }
+
             let team_id = field.text().await.map_err(|err| {
                 tracing::error!(?err, "Unable to read team field");
                 InternalServerError(err)
@@ -121,77 +124,54 @@ pub async fn post_new(
                 poem::Error::from_status(StatusCode::BAD_REQUEST)
             })?;
 
-            team = Team::get(&env.pool, team_id.into()).await.map_err(|err| {
-                tracing::error!(?err, "Unable to get team");
+            let member_of = get_team_for_member(&env, &user, team_id.into()).await?;
+            quota = get_remaining_quota(&env, &user, Some(&member_of)).await?;
+            team = Some(member_of);
+        } else if field.name() == Some("bundle") {
+            let name = field.text().await.map_err(|err| {
+                tracing::error!(?err, "Unable to read bundle field");
                 InternalServerError(err)
             })?;
 
-            match team {
-                None => {
-                    tracing::error!(team_id = ?team_id, "Team not found");
-                    return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
-                }
-
-                Some(ref team) => {
-                    let is_member = user.is_member_of(&env.pool, team.id).await.map_err(|err| {
-                        tracing::error!(?err, "Unable to check if user is member of team");
-                        InternalServerError(err)
-                    })?;
-
-                    if !is_member {
-                        tracing::error!(team_id = ?team.id, "User is not a member of team");
-                        return Err(poem::Error::from_status(StatusCode::FORBIDDEN));
-                    }
-                }
+            let name = name.trim();
+            if name.chars().count() > 100 {
+                tracing::error!("Bundle name in upload form is too long");
+                discard_pending_uploads(&env, &uploads).await;
+                return Err(poem::Error::from_status(StatusCode::BAD_REQUEST));
             }
-        } else if field.name() == Some("file") {
-            let filename = field
-                .file_name()
-                .map(ToString::to_string)
-                .unwrap_or_else(|| "unnamed.ext".to_string());
 
-            let (slug, path) = {
-                loop {
-                    let slug = nanoid::nanoid!();
-                    let path = env.cache_dir.join(&slug);
-
-                    if !path.exists() {
-                        break (slug, path);
-                    }
+            bundle = Some(name.to_string()).filter(|name| !name.is_empty());
+        } else if field.name() == Some("folder") {
+            let folder_id = field.text().await.map_err(|err| {
+                tracing::error!(?err, "Unable to read folder field");
+                InternalServerError(err)
+            })?;
 
-                    tracing::info!(?slug, ?path, "Slug already exists, generating a new one");
-                }
+            let Ok(folder_id) = folder_id.parse::<Key<Folder>>() else {
+                tracing::error!("Unable to parse folder ID");
+                discard_pending_uploads(&env, &uploads).await;
+                return Err(poem::Error::from_status(StatusCode::BAD_REQUEST));
             };
 
-            let mut field = field.into_async_read();
-
-            {
-                let mut file = tokio::fs::File::create(&path).await.map_err(|err| {
-                    tracing::error!(?err, ?path, "Unable to create file");
-                    InternalServerError(err)
-                })?;
+            folder = Some(folder_id);
+        } else if field.name() == Some("file") {
+            let filename = field.file_name().map(ToString::to_string);
+            match cache_upload_field(&env, field, &mut quota, None).await {
+                Ok(Some(upload)) => uploads.push(upload),
+                Ok(None) => failures.push(filename),
+                Err(err) => {
+                    discard_pending_uploads(&env, &uploads).await;
+
+                    // Tell the upload component why the upload was rejected.
+                    if let Some(err) = err.downcast_ref::<QuotaExceededError>() {
+                        return Ok(Json(json!({ "error": err.to_string() }))
+                            .with_status(StatusCode::PAYLOAD_TOO_LARGE)
+                            .into_response());
+                    }
 
-                if let Err(err) = tokio::io::copy(&mut field, &mut file).await {
-                    tracing::error!(?err, ?path, "Unable to copy from stream to file");
-                    failures.push(filename.clone());
-                    continue;
+                    return Err(err);
                 }
             }
-
-            let meta = tokio::fs::metadata(&path).await.map_err(|err| {
-                tracing::error!(?err, ?path, "Unable to get metadata for file");
-                InternalServerError(err)
-            })?;
-
-            let size = meta.len() as i64;
-            tracing::info!(?slug, size, "Upload to cache complete");
-
-            uploads.push(PendingUpload {
-                id: Key::<Upload>::new(),
-                slug,
-                filename,
-                size,
-            });
         } else {
             tracing::info!(field_name = ?field.name(), "Ignoring unrecognized field");
         }
@@ -199,59 +179,62 @@ pub async fn post_new(
 
     if !seen_csrf {
         tracing::error!("CSRF token was not seen in upload form");
-
-        for upload in &uploads {
-            let path = env.cache_dir.join(&upload.slug);
-            tracing::info!(?path, slug = ?upload.slug, "Deleting cached upload");
-            if let Err(err) = tokio::fs::remove_file(&path).await {
-                tracing::error!(?path, ?err, slug = ?upload.slug, "Failed to delete cached upload");
-            }
-        }
-
+        discard_pending_uploads(&env, &uploads).await;
         return Err(poem::Error::from_status(StatusCode::UNAUTHORIZED));
     }
 
-    let owner_user = match team.as_ref() {
-        Some(_) => None,
-        None => Some(user.id),
+    // The folder must belong to the same user or team as the uploads.
+    let folder = if let Some(folder_id) = folder {
+        match get_folder_for_owner(&env, &user, team.as_ref(), folder_id).await {
+            Ok(folder) => Some(folder),
+            Err(err) => {
+                discard_pending_uploads(&env, &uploads).await;
+                return Err(err);
+            }
+        }
+    } else {
+        None
     };
 
-    let owner_team = team.as_ref().map(|team| team.id);
     let remote_addr = ip.as_ref().map(ToString::to_string);
-
-    let upload_ids: Vec<Key<Upload>> = sqlx::query_scalar(
-        "\
-        WITH data AS ( \
-            SELECT value ->> 'id' AS id, \
-                   value ->> 'slug' AS slug, \
-                   value ->> 'filename' AS filename, \
-                   (value ->> 'size') AS size \
-            FROM json_each($1)) \
-        INSERT INTO uploads \
-        (id, slug, filename, size, public, downloads, \
-         owner_user, owner_team, \
-         uploaded_at, uploaded_by, remote_addr) \
-        SELECT data.id, data.slug, data.filename, data.size, 0, 0, \
-               $2, $3, \
-               $4, $5, $6 \
-        FROM data \
-        RETURNING id",
+    let upload_ids = match insert_pending_uploads(
+        &env,
+        &user,
+        team.as_ref(),
+        remote_addr,
+        &uploads,
+        false,
     )
-    .bind(serde_json::to_string(&uploads).map_err(|err| {
-        tracing::error!(?err, "Failed to serialize uploads to JSON");
-        InternalServerError(err)
-    })?)
-    .bind(owner_user)
-    .bind(owner_team)
-    .bind(OffsetDateTime::now_utc())
-    .bind(user.id)
-    .bind(remote_addr)
-    .fetch_all(&env.pool)
     .await
-    .map_err(|err| {
-        tracing::error!(?err, "Unable to insert uploads");
-        InternalServerError(err)
-    })?;
+    {
+        Ok(upload_ids) => upload_ids,
+        Err(err) => {
+            discard_pending_uploads(&env, &uploads).await;
+
+            // Other uploads may have used up the quota while these files were being received.
+            if let Some(err) = err.downcast_ref::<QuotaExceededError>() {
+                return Ok(Json(json!({ "error": err.to_string() }))
+                    .with_status(StatusCode::PAYLOAD_TOO_LARGE)
+                    .into_response());
+            }
+
+            return Err(err);
+        }
+    };
+
+    if let Some(folder) = folder {
+        Upload::move_many(&env.pool, &upload_ids, Some(folder.id))
+            .await
+            .map_err(|err| {
+                tracing::error!(?err, %folder.id, "Unable to put uploads into folder");
+                InternalServerError(err)
+            })?;
+    }
+
+    // When more than one file was uploaded with a bundle name, group the uploads into a bundle.
+    if let Some(name) = bundle.filter(|_| upload_ids.len() > 1) {
+        create_bundle(&env, &user, team.as_ref(), &name, &upload_ids).await?;
+    }
 
     // Trigger preview generation but don't fail the request if it errors.
     // The upload was successful - preview generation is a background enhancement.
@@ -259,5 +242,5 @@ pub async fn post_new(
         tracing::error!(?err, "Failed to send preview generation command");
     }
 
-    Ok(Json(()))
+    Ok(Json(()).into_response())
 }
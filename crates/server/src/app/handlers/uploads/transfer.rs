@@ -7,21 +7,30 @@ use poem::{
     IntoResponse, Response,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 
 use parcel_model::{
+    audit::{AuditAction, AuditTarget},
+    blob::Blob,
     team::Team,
     types::Key,
     upload::{Upload, UploadPermission},
+    user::User,
+    webhook::WebhookEvent,
 };
 
 use crate::{
     app::{
         errors::CsrfError,
-        extractors::user::SessionUser,
-        handlers::utils::{check_permission, get_upload_by_id},
+        extractors::{audit::Auditor, user::SessionUser},
+        handlers::utils::{
+            check_permission, get_team_for_member, get_upload_by_id, release_blob,
+            upload_audit_details,
+        },
         templates::{authorized_context, render_template},
     },
     env::Env,
+    workers::webhooks::queue_upload_event,
 };
 
 #[handler]
@@ -67,7 +76,9 @@ pub async fn get_transfer(
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq)]
 pub enum TransferAction {
+    #[serde(alias = "copy")]
     Copy,
+    #[serde(alias = "move")]
     Move,
 }
 
@@ -78,41 +89,21 @@ pub struct TransferForm {
     action: TransferAction,
 }
 
-#[handler]
-pub async fn post_transfer(
-    env: Data<&Env>,
-    SessionUser(user): SessionUser,
-    csrf_verifier: &CsrfVerifier,
-    Path(upload_id): Path<Key<Upload>>,
-    Form(form): Form<TransferForm>,
-) -> poem::Result<Response> {
-    let upload = get_upload_by_id(&env, upload_id).await?;
-    check_permission(&env, &upload, Some(&user), UploadPermission::Transfer).await?;
-
-    if !csrf_verifier.is_valid(&form.csrf_token) {
-        tracing::error!(%user.id, %upload_id, "CSRF token verification failed");
-        return Err(CsrfError.into());
-    }
-
-    let Some(team) = Team::get(&env.pool, form.team).await.map_err(|err| {
-        tracing::error!(%user.id, %upload_id, %err, "Failed to get team");
-        InternalServerError(err)
-    })?
-    else {
-        tracing::error!(%user.id, %upload_id, "Team not found");
-        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
-    };
+/// Copy or move an upload to a team that the user is a member of, returning the new upload.
+///
+/// The caller is expected to have checked that the user has the `Transfer` permission.
+pub async fn transfer_upload(
+    env: &Env,
+    auditor: &Auditor,
+    user: &User,
+    upload: Upload,
+    team_id: Key<Team>,
+    action: TransferAction,
+) -> poem::Result<Upload> {
+    let upload_id = upload.id;
 
     // Make sure that the user is a member of the team we're targeting.
-    let is_member = user.is_member_of(&env.pool, team.id).await.map_err(|err| {
-        tracing::error!(%user.id, %upload_id, %err, "Failed to check if user is a member of the team");
-        InternalServerError(err)
-    })?;
-
-    if !is_member {
-        tracing::error!(%user.id, %upload_id, "User is not a member of the team");
-        return Err(poem::Error::from_status(StatusCode::FORBIDDEN));
-    }
+    let team = get_team_for_member(env, user, team_id).await?;
 
     // Make sure that the user is not trying to transfer the upload to a team that already has an
     // upload with the same custom slug.
@@ -134,39 +125,98 @@ pub async fn post_transfer(
     new_upload.slug = nanoid::nanoid!();
     new_upload.owner_user = None;
     new_upload.owner_team = Some(team.id);
+    new_upload.bundle = None;
+    new_upload.folder = None;
 
-    // If we're moving the upload, then we can delete the old one from the database and then rename
-    // the cache file to the new slug. Otherwise we'll copy the cache file to the new slug.
-    if form.action == TransferAction::Move {
-        if let Err(err) = tokio::fs::rename(
-            env.cache_dir.join(&upload.slug),
-            env.cache_dir.join(&new_upload.slug),
-        )
-        .await
-        {
-            tracing::error!(%user.id, %upload_id, %err, "Failed to move cache file");
-            return Err(InternalServerError(err));
-        }
+    // The tags are removed along with the upload when moving it, so they need to be read first.
+    let tags = upload.get_tags(&env.pool).await.map_err(|err| {
+        tracing::error!(%user.id, %upload_id, %err, "Failed to get tags of upload");
+        InternalServerError(err)
+    })?;
 
+    // Both uploads share the same blob, so nothing needs to change in storage. If we're moving the
+    // upload, then the new upload takes over the reference of the old one, which we can delete from
+    // the database. Otherwise the new upload takes another reference to the blob.
+    if action == TransferAction::Move {
         upload.delete(&env.pool).await.map_err(|err| {
             tracing::error!(%user.id, %upload_id, %err, "Failed to delete upload");
             InternalServerError(err)
         })?;
-    } else if let Err(err) = tokio::fs::copy(
-        env.cache_dir.join(&upload.slug),
-        env.cache_dir.join(&new_upload.slug),
-    )
-    .await
-    {
-        tracing::error!(%user.id, %upload_id, %err, "Failed to copy cache file");
+    } else if let Err(err) = Blob::add_ref(&env.pool, &upload.blob).await {
+        tracing::error!(%user.id, %upload_id, %err, "Failed to add reference to blob");
         return Err(InternalServerError(err));
     }
 
     // Save the new upload to the database.
-    new_upload.create(&env.pool).await.map_err(|err| {
+    if let Err(err) = new_upload.create(&env.pool).await {
         tracing::error!(%user.id, %upload_id, %err, "Failed to save new upload");
-        InternalServerError(err)
-    })?;
+        release_blob(env, &new_upload.blob).await;
+        return Err(InternalServerError(err));
+    }
+
+    if let Err(err) = new_upload.set_tags(&env.pool, &tags).await {
+        tracing::error!(%user.id, %upload_id, %err, "Failed to copy tags to new upload");
+        return Err(InternalServerError(err));
+    }
+
+    auditor
+        .record(
+            env,
+            user,
+            AuditAction::UploadTransfer,
+            AuditTarget::Upload(upload_id),
+            Some(json!({
+                "action": action,
+                "new_upload": new_upload.id,
+                "before": upload_audit_details(&upload),
+                "after": upload_audit_details(&new_upload),
+            })),
+        )
+        .await;
+
+    // Both the previous owner and the team that received the upload are told about the transfer,
+    // each with their own upload.
+    let details = json!({
+        "action": action,
+        "previous_upload": upload_id,
+        "new_upload": new_upload.id,
+        "team": team.id,
+    });
+
+    if upload.owner_team != new_upload.owner_team {
+        let event = WebhookEvent::UploadTransferred;
+        queue_upload_event(env, event, &upload, Some(details.clone())).await;
+    }
+
+    queue_upload_event(
+        env,
+        WebhookEvent::UploadTransferred,
+        &new_upload,
+        Some(details),
+    )
+    .await;
+
+    Ok(new_upload)
+}
+
+#[handler]
+pub async fn post_transfer(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    auditor: Auditor,
+    csrf_verifier: &CsrfVerifier,
+    Path(upload_id): Path<Key<Upload>>,
+    Form(form): Form<TransferForm>,
+) -> poem::Result<Response> {
+    let upload = get_upload_by_id(&env, upload_id).await?;
+    check_permission(&env, &upload, Some(&user), UploadPermission::Transfer).await?;
+
+    if !csrf_verifier.is_valid(&form.csrf_token) {
+        tracing::error!(%user.id, %upload_id, "CSRF token verification failed");
+        return Err(CsrfError.into());
+    }
+
+    transfer_upload(&env, &auditor, &user, upload, form.team, form.action).await?;
 
     Ok(Html("").with_header("HX-Refresh", "true").into_response())
 }
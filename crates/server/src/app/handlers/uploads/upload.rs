@@ -12,9 +12,13 @@ use poem::{
     IntoResponse,
 };
 use serde::Deserialize;
+use serde_json::json;
 use time::OffsetDateTime;
+use validator::ValidationErrors;
 
 use parcel_model::{
+    audit::{AuditAction, AuditTarget},
+    share_link::ShareLink,
     team::{HomeTab, Team, TeamMember, TeamTab},
     types::Key,
     upload::{Upload, UploadPermission, UploadStats},
@@ -24,9 +28,10 @@ use parcel_model::{
 use crate::{
     app::{
         errors::CsrfError,
-        extractors::user::SessionUser,
+        extractors::{audit::Auditor, user::SessionUser},
         handlers::utils::{
             check_permission, delete_upload_cache, get_upload_by_id, get_upload_by_slug,
+            upload_audit_details,
         },
         templates::{authorized_context, default_context, render_template},
     },
@@ -187,6 +192,7 @@ pub struct DeleteUploadQuery {
 pub async fn delete_upload(
     env: Data<&Env>,
     SessionUser(user): SessionUser,
+    auditor: Auditor,
     csrf_verifier: &CsrfVerifier,
     Path(id): Path<Key<Upload>>,
     Query(DeleteUploadQuery { csrf_token }): Query<DeleteUploadQuery>,
@@ -206,6 +212,16 @@ pub async fn delete_upload(
 
     delete_upload_cache(&env, &upload).await;
 
+    auditor
+        .record(
+            &env,
+            &user,
+            AuditAction::UploadDelete,
+            AuditTarget::Upload(upload.id),
+            Some(json!({ "before": upload_audit_details(&upload) })),
+        )
+        .await;
+
     let (stats, limit, team) = match (upload.owner_user, upload.owner_team) {
         (Some(user_id), None) => {
             if user_id != user.id {
@@ -294,7 +310,20 @@ pub async fn get_share(
 ) -> poem::Result<Html<String>> {
     let upload = get_upload_by_id(&env, id).await?;
     check_permission(&env, &upload, Some(&user), UploadPermission::Share).await?;
+    render_share(&env, &user, csrf_token, upload, immediate, None).await
+}
 
+/// Render the modal for sharing an upload, which includes the share links to the upload.
+///
+/// The `form` is given when creating a new share link failed, along with the validation errors.
+pub(super) async fn render_share(
+    env: &Env,
+    user: &User,
+    csrf_token: &CsrfToken,
+    upload: Upload,
+    immediate: bool,
+    form: Option<(minijinja::Value, ValidationErrors)>,
+) -> poem::Result<Html<String>> {
     let team = if let Some(team_id) = upload.owner_team {
         Team::get(&env.pool, team_id).await.map_err(|err| {
             tracing::error!(?err, team_id = %team_id, "Unable to get team by ID");
@@ -304,14 +333,33 @@ pub async fn get_share(
         None
     };
 
+    let links = ShareLink::get_for_upload(&env.pool, upload.id)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %upload.id, "Unable to get share links for upload");
+            InternalServerError(err)
+        })?
+        .into_iter()
+        .map(|link| {
+            let status = link.status(&upload);
+            context! { link, status }
+        })
+        .collect::<Vec<_>>();
+
+    let (form, errors) = form.unzip();
+
     render_template(
         "uploads/share.html",
         context! {
             upload,
             immediate,
             team,
+            links,
+            form,
+            errors,
+            now => OffsetDateTime::now_utc(),
             csrf_token => csrf_token.0,
-            ..authorized_context(&env, &user)
+            ..authorized_context(env, user)
         },
     )
     .await
@@ -331,21 +379,21 @@ pub async fn get_preview(
         return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
     }
 
-    let path = env.cache_dir.join(format!("{}.preview", upload.slug));
-    let file = tokio::fs::File::open(&path).await.map_err(|err| {
-        tracing::error!(%upload.id, ?err, ?path, "Unable to open file");
+    let key = format!("{}.preview", upload.blob);
+    let size = env.storage.size(&key).await.map_err(|err| {
+        tracing::error!(%upload.id, ?err, ?key, "Unable to get size of preview");
         InternalServerError(err)
     })?;
 
-    let meta = file.metadata().await.map_err(|err| {
-        tracing::error!(%upload.id, ?err, ?path, "Unable to get metadata for file");
+    let file = env.storage.read(&key).await.map_err(|err| {
+        tracing::error!(%upload.id, ?err, ?key, "Unable to open preview");
         InternalServerError(err)
     })?;
 
     Ok(poem::Response::builder()
         .status(StatusCode::OK)
         .header(CONTENT_TYPE, "image/png")
-        .header(CONTENT_LENGTH, meta.len())
+        .header(CONTENT_LENGTH, size)
         .body(poem::Body::from_async_read(file)))
 }
 
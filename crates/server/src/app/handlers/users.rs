@@ -1,8 +1,22 @@
 mod auth;
+mod oidc;
+mod password;
 mod settings;
+mod tokens;
+mod webauthn;
 
 pub use auth::{get_signin, get_signin_totp, get_signout, post_signin, post_signin_totp};
+pub use oidc::{get_signin_oidc, get_signin_oidc_callback};
+pub use password::{
+    get_forgot_password, get_invitation, get_reset_password, post_forgot_password, post_invitation,
+    post_reset_password,
+};
 pub use settings::{
-    get_remove_totp, get_settings, get_setup_totp, post_password, post_remove_totp, post_settings,
-    post_setup_totp,
+    get_remove_totp, get_settings, get_setup_totp, post_notifications, post_password,
+    post_recovery_codes, post_remove_totp, post_settings, post_setup_totp,
+};
+pub use tokens::{post_api_tokens, post_revoke_api_token};
+pub use webauthn::{
+    post_remove_webauthn, post_signin_passkey, post_signin_passkey_challenge, post_signin_webauthn,
+    post_signin_webauthn_challenge, post_webauthn, post_webauthn_challenge,
 };
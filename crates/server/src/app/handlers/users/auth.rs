@@ -1,57 +1,51 @@
-use std::net::IpAddr;
-
+use esbuild_bundle::javascript;
 use minijinja::context;
 use poem::{
     error::InternalServerError,
     handler,
     session::Session,
     web::{CsrfToken, CsrfVerifier, Data, Form, RealIp, Redirect, RemoteAddr},
-    Addr, IntoResponse, Response,
+    IntoResponse, Response,
 };
 use serde::Deserialize;
+use serde_json::json;
+use time::OffsetDateTime;
 
 use parcel_model::{
+    audit::{AuditAction, AuditTarget},
     login_attempt::LoginAttempt,
+    password::StoredPassword,
+    recovery_code::{is_recovery_code, RecoveryCode},
     types::Key,
+    upload::UploadOrder,
     user::{requires_setup, User},
+    webauthn::WebauthnCredential,
 };
 
 use crate::{
     app::{
         errors::CsrfError,
+        extractors::audit::Auditor,
         templates::{default_context, render_template},
     },
     env::Env,
-    utils::SessionExt,
+    ldap::{LdapEntry, LdapLink},
+    notifications::{can_send_links, record_failed_login},
+    utils::{get_client_ip, unusable_password, SessionExt},
 };
 
-/// Get the client IP address, respecting the `trust_proxy` setting.
-///
-/// When `trust_proxy` is true, uses proxy headers (X-Forwarded-For, etc.).
-/// When false, uses only the direct peer address to prevent IP spoofing.
-fn get_client_ip(trust_proxy: bool, real_ip: &RealIp, remote_addr: &RemoteAddr) -> Option<IpAddr> {
-    if trust_proxy {
-        // RealIp already checks proxy headers and falls back to peer address
-        real_ip.0
-    } else {
-        // Only use the direct peer address, ignore proxy headers
-        match &remote_addr.0 {
-            Addr::SocketAddr(addr) => Some(addr.ip()),
-            _ => None,
-        }
-    }
-}
-
 #[handler]
 pub async fn get_signin(
     env: Data<&Env>,
     token: &CsrfToken,
     session: &Session,
 ) -> poem::Result<Response> {
-    let setup = requires_setup(&env.pool).await.map_err(|err| {
-        tracing::error!(error = ?err, "Failed to check if setup is required");
-        InternalServerError(err)
-    })?;
+    // Without password login, the first administrator is created by signing in with single sign-on.
+    let setup = !env.password_login_disabled()
+        && requires_setup(&env.pool).await.map_err(|err| {
+            tracing::error!(error = ?err, "Failed to check if setup is required");
+            InternalServerError(err)
+        })?;
 
     if setup {
         return Ok(Redirect::see_other("/admin/setup").into_response());
@@ -61,7 +55,13 @@ pub async fn get_signin(
         "user/signin.html",
         context! {
             token => token.0,
+            success => session.take::<String>("success"),
             error => session.take::<String>("error"),
+            oidc => env.oidc.as_ref().map(|oidc| oidc.provider_name.as_str()),
+            password_login => !env.password_login_disabled(),
+            forgot_password => can_send_links(&env) && !env.password_login_disabled(),
+            passkeys => env.passkeys.is_some() && !env.password_login_disabled(),
+            webauthn_js => javascript!("$CARGO_MANIFEST_DIR/scripts/webauthn.ts"),
             ..default_context(&env)
         },
     )
@@ -80,6 +80,7 @@ pub struct SignInForm {
 pub async fn post_signin(
     env: Data<&Env>,
     session: &Session,
+    auditor: Auditor,
     verifier: &CsrfVerifier,
     real_ip: RealIp,
     remote_addr: &RemoteAddr,
@@ -94,6 +95,15 @@ pub async fn post_signin(
         return Err(CsrfError.into());
     }
 
+    if env.password_login_disabled() {
+        tracing::info!(
+            ?username,
+            "Attempt to sign in with a password when it is disabled"
+        );
+        session.set("error", "Signing in with a password is disabled");
+        return Ok(Redirect::see_other("/user/signin"));
+    }
+
     let client_ip = get_client_ip(env.trust_proxy, &real_ip, remote_addr);
     let client_ip_str = client_ip.map(|ip| ip.to_string());
 
@@ -120,31 +130,48 @@ pub async fn post_signin(
         })?;
 
     let mut user = match user {
-        Some(user) => user,
-        None => {
-            tracing::info!(?username, "User not found");
-            // Record failed attempt even for non-existent users (prevents username enumeration timing)
-            LoginAttempt::record(&env.pool, &username, client_ip_str.as_deref(), false)
-                .await
-                .ok();
-            session.set("error", "Invalid username or password");
-            return Ok(Redirect::see_other("/user/signin"));
+        // Users that are linked to the LDAP directory only sign in with their password there.
+        Some(mut user) if user.ldap_dn.is_none() && user.verify_password(&password) => {
+            if user.password.needs_migrating() {
+                tracing::info!(%user.id, ?username, "Migrating password hash");
+                user.set_password(&env.pool, &password).await?;
+            }
+
+            user
         }
-    };
 
-    if !user.verify_password(&password) {
-        tracing::info!(?username, "Invalid password");
-        LoginAttempt::record(&env.pool, &username, client_ip_str.as_deref(), false)
-            .await
-            .ok();
-        session.set("error", "Invalid username or password");
-        return Ok(Redirect::see_other("/user/signin"));
-    }
-
-    if user.password.needs_migrating() {
-        tracing::info!(%user.id, ?username, "Migrating password hash");
-        user.set_password(&env.pool, &password).await?;
-    }
+        user => {
+            let entry = match env.ldap {
+                Some(ref ldap) => match ldap.authenticate(&username, &password).await {
+                    Ok(entry) => entry,
+                    Err(err) => {
+                        tracing::error!(?err, ?username, "Failed to authenticate with LDAP");
+                        session.set(
+                            "error",
+                            "Unable to sign in at the moment. Please try again later.",
+                        );
+                        return Ok(Redirect::see_other("/user/signin"));
+                    }
+                },
+                None => None,
+            };
+
+            let user = match entry {
+                Some(entry) => signin_ldap(&env, &auditor, user, &username, entry).await?,
+                None => None,
+            };
+
+            let Some(user) = user else {
+                tracing::info!(?username, "Invalid username or password");
+                // Record failed attempt even for non-existent users (prevents username enumeration timing)
+                record_failed_login(&env, &username, client_ip_str.as_deref()).await;
+                session.set("error", "Invalid username or password");
+                return Ok(Redirect::see_other("/user/signin"));
+            };
+
+            user
+        }
+    };
 
     if !user.enabled {
         tracing::info!(?username, "User is disabled");
@@ -152,8 +179,8 @@ pub async fn post_signin(
         return Ok(Redirect::see_other("/user/signin"));
     }
 
-    if user.totp.is_some() {
-        tracing::info!(%user.id, ?username, "User requires TOTP");
+    if user.totp.is_some() || has_webauthn(&env, &user).await? {
+        tracing::info!(%user.id, ?username, "User requires a second factor");
         session.set("_authenticating", user.id);
         // Store username in session for TOTP lockout checks
         session.set("_authenticating_username", username);
@@ -177,6 +204,119 @@ pub async fn post_signin(
     }
 }
 
+/// Sign in a user that the LDAP directory has accepted, linking the user with the same username or
+/// creating a new user the first time that they sign in.
+///
+/// Returns `None` if there is a user with the same username that cannot be linked, because linking
+/// is turned off, they are an administrator, or they are already linked to another entry.
+async fn signin_ldap(
+    env: &Env,
+    auditor: &Auditor,
+    user: Option<User>,
+    username: &str,
+    entry: LdapEntry,
+) -> poem::Result<Option<User>> {
+    if let Some(user) = User::get_by_ldap_dn(&env.pool, &entry.dn)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, dn = ?entry.dn, "Failed to get user by LDAP DN");
+            InternalServerError(err)
+        })?
+    {
+        return Ok(Some(user));
+    }
+
+    if let Some(mut user) = user {
+        if user.ldap_dn.is_some() {
+            tracing::warn!(%user.id, dn = ?entry.dn, "User is linked to another LDAP entry");
+            return Ok(None);
+        }
+
+        let link = env.ldap.as_ref().map(|ldap| ldap.link).unwrap_or_default();
+        if link == LdapLink::None {
+            tracing::warn!(%user.id, dn = ?entry.dn, "Not linking existing user to LDAP entry");
+            return Ok(None);
+        }
+
+        // An entry in the directory that shares a username with an administrator must not be able
+        // to take over their account.
+        if user.admin {
+            tracing::warn!(%user.id, dn = ?entry.dn, "Not linking administrator to LDAP entry");
+            return Ok(None);
+        }
+
+        let password = StoredPassword::new(&unusable_password())?;
+        user.set_ldap_dn(&env.pool, &entry.dn, password)
+            .await
+            .map_err(|err| {
+                tracing::error!(%user.id, ?err, "Failed to link user to LDAP entry");
+                InternalServerError(err)
+            })?;
+
+        tracing::info!(%user.id, dn = ?entry.dn, "Linked user to LDAP entry");
+        auditor
+            .record(
+                env,
+                &user,
+                AuditAction::UserLink,
+                AuditTarget::User(user.id),
+                Some(json!({ "dn": entry.dn })),
+            )
+            .await;
+
+        return Ok(Some(user));
+    }
+
+    let user = User {
+        id: Key::new(),
+        username: username.to_string(),
+        name: entry.name.unwrap_or_else(|| username.to_string()),
+        password: StoredPassword::new(&unusable_password())?,
+        totp: None,
+        totp_required: false,
+        enabled: true,
+        admin: false,
+        limit: None,
+        created_at: OffsetDateTime::now_utc(),
+        created_by: None,
+        last_access: None,
+        default_order: UploadOrder::UploadedAt,
+        default_asc: false,
+        email_verified: entry.email.is_some(),
+        email: entry.email,
+        notify_downloads: true,
+        notify_expiring: true,
+        notify_requests: true,
+        notify_lockouts: true,
+        oidc_subject: None,
+        oidc_linked: false,
+        ldap_dn: Some(entry.dn),
+    };
+
+    user.create(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, ?user.username, "Failed to create user for LDAP");
+        InternalServerError(err)
+    })?;
+
+    tracing::info!(%user.id, ?user.username, "Created user from LDAP");
+    auditor
+        .record(
+            env,
+            &user,
+            AuditAction::UserProvision,
+            AuditTarget::User(user.id),
+            Some(json!({
+                "username": user.username,
+                "name": user.name,
+                "admin": user.admin,
+                "dn": user.ldap_dn,
+            })),
+        )
+        .await;
+
+    Ok(Some(user))
+}
+
 #[handler]
 pub async fn get_signout(session: &Session) -> poem::Result<Redirect> {
     let mut stack = session
@@ -192,23 +332,52 @@ pub async fn get_signout(session: &Session) -> poem::Result<Redirect> {
     Ok(Redirect::see_other("/"))
 }
 
+/// Check if a user can use a security key or passkey as their second factor.
+async fn has_webauthn(env: &Env, user: &User) -> poem::Result<bool> {
+    if env.passkeys.is_none() {
+        return Ok(false);
+    }
+
+    WebauthnCredential::exists_for_user(&env.pool, user.id)
+        .await
+        .map_err(|err| {
+            tracing::error!(%user.id, ?err, "Failed to check for WebAuthn credentials");
+            InternalServerError(err)
+        })
+}
+
 #[handler]
 pub async fn get_signin_totp(
     env: Data<&Env>,
     token: &CsrfToken,
     session: &Session,
 ) -> poem::Result<Response> {
-    if session.get::<Key<User>>("_authenticating").is_none() {
+    let Some(user_id) = session.get::<Key<User>>("_authenticating") else {
         tracing::error!("User not authenticating");
         session.set("error", "You need to sign in first");
         return Ok(Redirect::see_other("/user/signin").into_response());
-    }
+    };
+
+    let Some(user) = User::get(&env.pool, user_id).await.map_err(|err| {
+        tracing::error!(%user_id, ?err, "Failed to get user by ID");
+        InternalServerError(err)
+    })?
+    else {
+        tracing::error!(%user_id, "User not found");
+        session.remove("_authenticating");
+        session.remove("_authenticating_username");
+        session.set("error", "You need to sign in first");
+        return Ok(Redirect::see_other("/user/signin").into_response());
+    };
 
     Ok(render_template(
         "user/totp.html",
         context! {
             token => token.0,
             error => session.take::<String>("error"),
+            totp => user.totp.is_some(),
+            webauthn => has_webauthn(&env, &user).await?,
+            webauthn_js => javascript!("$CARGO_MANIFEST_DIR/scripts/webauthn.ts"),
             ..default_context(&env)
         },
     )
@@ -289,25 +458,17 @@ pub async fn post_signin_totp(
     };
 
     let totp = code.trim();
-    if !totp.chars().all(|c| c.is_ascii_digit()) {
-        tracing::error!(
-            %user.id,
-            "TOTP code provided was not a sequence of ASCII digits"
-        );
-
-        session.set(
-            "totp_error",
-            "🤨 Your TOTP code should be a sequence of six numbers. Try again.",
-        );
-
-        return Ok(Redirect::see_other("/user/signin/totp"));
+    if is_recovery_code(totp) {
+        return signin_recovery_code(&env, session, &user, &username, client_ip_str, totp).await;
     }
 
-    if totp.len() != 6 {
+    // Anything else that is not six digits is a mistyped code, which is not counted as a failed
+    // attempt to sign in.
+    if totp.len() != 6 || !totp.chars().all(|c| c.is_ascii_digit()) {
         tracing::error!(
             %user.id,
             length = totp.len(),
-            "Incorrect number of digits provided for TOTP code (expected 6)"
+            "TOTP code provided was not six digits"
         );
 
         session.set(
@@ -353,9 +514,7 @@ pub async fn post_signin_totp(
 
         // Record failed TOTP attempt (shared counter with password)
         if !username.is_empty() {
-            LoginAttempt::record(&env.pool, &username, client_ip_str.as_deref(), false)
-                .await
-                .ok();
+            record_failed_login(&env, &username, client_ip_str.as_deref()).await;
         }
 
         session.set(
@@ -385,3 +544,54 @@ pub async fn post_signin_totp(
         Ok(Redirect::see_other(if user.admin { "/admin" } else { "/" }))
     }
 }
+
+/// Finish signing in with one of the user's recovery codes, in place of a TOTP code.
+async fn signin_recovery_code(
+    env: &Env,
+    session: &Session,
+    user: &User,
+    username: &str,
+    client_ip: Option<String>,
+    code: &str,
+) -> poem::Result<Redirect> {
+    let redeemed = RecoveryCode::redeem(&env.pool, user.id, code)
+        .await
+        .map_err(|err| {
+            tracing::error!(%user.id, ?err, "Failed to redeem recovery code");
+            InternalServerError(err)
+        })?;
+
+    if !redeemed {
+        tracing::error!(%user.id, "Recovery code provided was not recognised");
+
+        // Record failed recovery code attempt (shared counter with password)
+        if !username.is_empty() {
+            record_failed_login(env, username, client_ip.as_deref()).await;
+        }
+
+        session.set(
+            "error",
+            "🤨 That recovery code is incorrect or has already been used. Please try again.",
+        );
+
+        return Ok(Redirect::see_other("/user/signin/totp"));
+    }
+
+    if !username.is_empty() {
+        LoginAttempt::record(&env.pool, username, client_ip.as_deref(), true)
+            .await
+            .ok();
+    }
+
+    session.remove("_authenticating");
+    session.remove("_authenticating_username");
+    session.set("user_id", user.id);
+
+    tracing::info!(%user.id, "User signed in with a recovery code");
+
+    if let Some(destination) = session.take::<String>("destination") {
+        Ok(Redirect::see_other(destination))
+    } else {
+        Ok(Redirect::see_other(if user.admin { "/admin" } else { "/" }))
+    }
+}
@@ -0,0 +1,433 @@
+use std::time::Duration;
+
+use minijinja::context;
+use poem::{
+    error::InternalServerError,
+    handler,
+    http::StatusCode,
+    session::Session,
+    web::{
+        cookie::{Cookie, CookieJar, CookieKey, SameSite},
+        Data, Query, RealIp, Redirect, RemoteAddr,
+    },
+    IntoResponse, Response,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use time::OffsetDateTime;
+
+use parcel_model::{
+    audit::{AuditAction, AuditTarget},
+    login_attempt::LoginAttempt,
+    password::StoredPassword,
+    types::Key,
+    upload::UploadOrder,
+    user::{requires_setup, User},
+};
+
+use crate::{
+    app::{
+        extractors::audit::Auditor,
+        templates::{default_context, render_template},
+    },
+    env::Env,
+    oidc::{Oidc, OidcIdentity, OidcLink, PendingSignIn},
+    utils::{get_client_ip, unusable_password},
+};
+
+/// The cookie that keeps the secrets of a sign in until the user returns from the provider.
+///
+/// The session cookie is `SameSite=Strict`, so it is not sent when the provider redirects back to
+/// Parcel. This cookie is `SameSite=Lax` instead, and is encrypted with the same key.
+const PENDING_COOKIE: &str = "parcel-oidc";
+const PENDING_COOKIE_PATH: &str = "/user/signin/oidc";
+
+/// How long the user has to sign in at the provider.
+const PENDING_EXPIRY: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Deserialize, Serialize)]
+struct PendingCookie {
+    #[serde(flatten)]
+    pending: PendingSignIn,
+    destination: Option<String>,
+}
+
+fn get_oidc(env: &Env) -> poem::Result<&Oidc> {
+    env.oidc.as_ref().ok_or_else(|| {
+        tracing::error!("Single sign-on has not been configured");
+        poem::Error::from_status(StatusCode::NOT_FOUND)
+    })
+}
+
+#[handler]
+pub async fn get_signin_oidc(
+    env: Data<&Env>,
+    session: &Session,
+    cookie_jar: &CookieJar,
+    cookie_key: Data<&CookieKey>,
+) -> poem::Result<Redirect> {
+    let oidc = get_oidc(&env)?;
+    let (url, pending) = oidc.start();
+
+    let mut cookie = Cookie::new(
+        PENDING_COOKIE,
+        PendingCookie {
+            pending,
+            destination: session.get::<String>("destination"),
+        },
+    );
+
+    cookie.set_path(PENDING_COOKIE_PATH);
+    cookie.set_http_only(true);
+    cookie.set_same_site(SameSite::Lax);
+    cookie.set_max_age(PENDING_EXPIRY);
+    cookie_jar.private_with_key(&cookie_key).add(cookie);
+
+    tracing::info!("Redirecting to OpenID Connect provider");
+    Ok(Redirect::see_other(url))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+/// Send the browser on to a page once it has returned from the provider.
+///
+/// The session cookie is `SameSite=Strict`, so the browser will not send it with any redirect that
+/// started at the provider. Instead, a page of our own sends the browser on, which makes the next
+/// request a same-site one.
+async fn continue_to(env: &Env, destination: &str) -> poem::Result<Response> {
+    render_template(
+        "user/signin-oidc.html",
+        context! {
+            destination,
+            ..default_context(env)
+        },
+    )
+    .await
+    .map(IntoResponse::into_response)
+}
+
+/// Send the user back to the sign in page with an error.
+async fn signin_error(
+    env: &Env,
+    session: &Session,
+    error: impl Into<String>,
+) -> poem::Result<Response> {
+    session.set("error", error.into());
+    continue_to(env, "/user/signin").await
+}
+
+#[handler]
+#[allow(clippy::too_many_arguments)]
+pub async fn get_signin_oidc_callback(
+    env: Data<&Env>,
+    session: &Session,
+    auditor: Auditor,
+    cookie_jar: &CookieJar,
+    cookie_key: Data<&CookieKey>,
+    real_ip: RealIp,
+    remote_addr: &RemoteAddr,
+    Query(query): Query<CallbackQuery>,
+) -> poem::Result<Response> {
+    let oidc = get_oidc(&env)?;
+
+    let pending = cookie_jar
+        .private_with_key(&cookie_key)
+        .get(PENDING_COOKIE)
+        .and_then(|cookie| cookie.value::<PendingCookie>().ok());
+
+    // The secrets can only be used once, so remove the cookie whatever happens next.
+    let mut removal = Cookie::named(PENDING_COOKIE);
+    removal.set_path(PENDING_COOKIE_PATH);
+    removal.make_removal();
+    cookie_jar.add(removal);
+
+    let Some(PendingCookie {
+        pending,
+        destination,
+    }) = pending
+    else {
+        tracing::error!("Returned from OpenID Connect provider without a pending sign in");
+        return signin_error(&env, session, "Your sign in has expired. Please try again.").await;
+    };
+
+    if let Some(error) = query.error {
+        tracing::info!(?error, description = ?query.error_description, "Provider refused sign in");
+        return signin_error(
+            &env,
+            session,
+            format!("Unable to sign in with {}", oidc.provider_name),
+        )
+        .await;
+    }
+
+    if !query
+        .state
+        .as_deref()
+        .is_some_and(|state| pending.is_valid_state(state))
+    {
+        tracing::error!("State returned by OpenID Connect provider does not match");
+        return signin_error(&env, session, "Your sign in has expired. Please try again.").await;
+    }
+
+    let Some(code) = query.code else {
+        tracing::error!("OpenID Connect provider did not return an authorization code");
+        return Err(poem::Error::from_status(StatusCode::BAD_REQUEST));
+    };
+
+    let identity = match oidc.finish(code, pending).await {
+        Ok(identity) => identity,
+        Err(err) => {
+            tracing::error!(?err, "Failed to sign in with OpenID Connect");
+            return signin_error(
+                &env,
+                session,
+                format!("Unable to sign in with {}", oidc.provider_name),
+            )
+            .await;
+        }
+    };
+
+    let mut user = match find_user(&env, oidc, &identity, &auditor).await? {
+        Ok(user) => user,
+        Err(error) => return signin_error(&env, session, error).await,
+    };
+
+    if !user.enabled {
+        tracing::info!(%user.id, "User is disabled");
+        return signin_error(&env, session, "Your account is disabled").await;
+    }
+
+    // Linking an existing user does not show that they should be an administrator, so the groups
+    // can only take away their rights. Another administrator has to make them an administrator.
+    let admin = oidc
+        .is_admin(&identity)
+        .map(|admin| admin && (user.admin || !user.oidc_linked));
+
+    if let Some(admin) = admin {
+        if admin != user.admin {
+            tracing::info!(%user.id, admin, "Updating admin flag from groups");
+            user.set_admin(&env.pool, admin).await.map_err(|err| {
+                tracing::error!(%user.id, ?err, "Failed to set admin flag of user");
+                InternalServerError(err)
+            })?;
+
+            auditor
+                .record(
+                    &env,
+                    &user,
+                    AuditAction::UserEdit,
+                    AuditTarget::User(user.id),
+                    Some(json!({ "admin": admin, "groups": identity.groups })),
+                )
+                .await;
+        }
+    }
+
+    if let (None, Some(email)) = (&user.email, &identity.email) {
+        user.set_email(&env.pool, email, identity.email_verified)
+            .await
+            .map_err(|err| {
+                tracing::error!(%user.id, ?err, "Failed to set email of user");
+                InternalServerError(err)
+            })?;
+    }
+
+    let client_ip = get_client_ip(env.trust_proxy, &real_ip, remote_addr);
+    LoginAttempt::record(
+        &env.pool,
+        &user.username,
+        client_ip.map(|ip| ip.to_string()).as_deref(),
+        true,
+    )
+    .await
+    .ok();
+
+    session.remove("_authenticating");
+    session.set("user_id", user.id);
+
+    tracing::info!(%user.id, subject = ?identity.subject, "User signed in with OpenID Connect");
+
+    let destination =
+        destination.unwrap_or_else(|| if user.admin { "/admin" } else { "/" }.to_string());
+
+    continue_to(&env, &destination).await
+}
+
+/// Find the user that has signed in, linking an existing user or creating a new one if this is the
+/// first time that they have signed in.
+///
+/// Returns an error message for the user if they cannot be signed in.
+async fn find_user(
+    env: &Env,
+    oidc: &Oidc,
+    identity: &OidcIdentity,
+    auditor: &Auditor,
+) -> poem::Result<Result<User, String>> {
+    if let Some(user) = User::get_by_oidc_subject(&env.pool, &identity.subject)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, subject = ?identity.subject, "Failed to get user by subject");
+            InternalServerError(err)
+        })?
+    {
+        return Ok(Ok(user));
+    }
+
+    if let Some(mut user) = find_linked_user(env, oidc.link, identity).await? {
+        user.set_oidc_subject(&env.pool, &identity.subject)
+            .await
+            .map_err(|err| {
+                tracing::error!(%user.id, ?err, "Failed to link user to OpenID Connect subject");
+                InternalServerError(err)
+            })?;
+
+        tracing::info!(%user.id, subject = ?identity.subject, "Linked user to OpenID Connect");
+        auditor
+            .record(
+                env,
+                &user,
+                AuditAction::UserLink,
+                AuditTarget::User(user.id),
+                Some(json!({ "subject": identity.subject, "link": format!("{:?}", oidc.link) })),
+            )
+            .await;
+
+        return Ok(Ok(user));
+    }
+
+    let username = identity
+        .username
+        .as_ref()
+        .or(identity.email.as_ref())
+        .unwrap_or(&identity.subject)
+        .clone();
+
+    if User::get_by_username(&env.pool, &username)
+        .await
+        .map_err(|err| {
+            tracing::error!(?username, ?err, "Failed to get user by username");
+            InternalServerError(err)
+        })?
+        .is_some()
+    {
+        tracing::error!(
+            ?username,
+            "Username of new OpenID Connect user is already taken"
+        );
+        return Ok(Err(format!(
+            "An account with the username '{username}' already exists. Ask an administrator to \
+            link it to your account at {}.",
+            oidc.provider_name
+        )));
+    }
+
+    // Like the setup page, the first user is made an administrator, unless the groups say otherwise.
+    let first = requires_setup(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, "Failed to check if setup is required");
+        InternalServerError(err)
+    })?;
+
+    let user = User {
+        id: Key::new(),
+        name: identity.name.clone().unwrap_or_else(|| username.clone()),
+        username,
+        password: StoredPassword::new(&unusable_password())?,
+        totp: None,
+        totp_required: false,
+        enabled: true,
+        admin: oidc.is_admin(identity).unwrap_or(first),
+        limit: None,
+        created_at: OffsetDateTime::now_utc(),
+        created_by: None,
+        last_access: None,
+        default_order: UploadOrder::UploadedAt,
+        default_asc: false,
+        email: identity.email.clone(),
+        email_verified: identity.email.is_some() && identity.email_verified,
+        notify_downloads: true,
+        notify_expiring: true,
+        notify_requests: true,
+        notify_lockouts: true,
+        oidc_subject: Some(identity.subject.clone()),
+        oidc_linked: false,
+        ldap_dn: None,
+    };
+
+    user.create(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, ?user.username, "Failed to create user for OpenID Connect");
+        InternalServerError(err)
+    })?;
+
+    tracing::info!(%user.id, ?user.username, "Created user from OpenID Connect");
+    auditor
+        .record(
+            env,
+            &user,
+            AuditAction::UserProvision,
+            AuditTarget::User(user.id),
+            Some(json!({
+                "username": user.username,
+                "name": user.name,
+                "admin": user.admin,
+                "subject": identity.subject,
+            })),
+        )
+        .await;
+
+    Ok(Ok(user))
+}
+
+/// Find an existing user to link to the account at the provider, if they have not been linked to
+/// another account already.
+async fn find_linked_user(
+    env: &Env,
+    link: OidcLink,
+    identity: &OidcIdentity,
+) -> poem::Result<Option<User>> {
+    let user = match link {
+        OidcLink::None => None,
+
+        OidcLink::Email => {
+            let Some(email) = identity.email.as_ref().filter(|_| identity.email_verified) else {
+                return Ok(None);
+            };
+
+            let mut users = User::get_by_email(&env.pool, email).await.map_err(|err| {
+                tracing::error!(?email, ?err, "Failed to get users by email");
+                InternalServerError(err)
+            })?;
+
+            // Anyone can enter an address in their settings, so only an address that is known to
+            // belong to the user can be trusted to link them.
+            users.retain(|user| user.email_verified);
+
+            // An address shared by more than one user cannot say which of them to link.
+            if users.len() == 1 {
+                users.pop()
+            } else {
+                None
+            }
+        }
+
+        OidcLink::Username => {
+            let Some(ref username) = identity.username else {
+                return Ok(None);
+            };
+
+            User::get_by_username(&env.pool, username)
+                .await
+                .map_err(|err| {
+                    tracing::error!(?username, ?err, "Failed to get user by username");
+                    InternalServerError(err)
+                })?
+        }
+    };
+
+    Ok(user.filter(|user| user.oidc_subject.is_none()))
+}
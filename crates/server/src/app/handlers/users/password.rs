@@ -0,0 +1,410 @@
+use minijinja::context;
+use poem::{
+    error::InternalServerError,
+    handler,
+    http::StatusCode,
+    session::Session,
+    web::{CsrfToken, CsrfVerifier, Data, Form, Html, Path, RealIp, Redirect, RemoteAddr},
+    IntoResponse, Response,
+};
+use serde::Deserialize;
+use time::{Duration, OffsetDateTime};
+use validator::Validate;
+
+use parcel_model::{
+    password_reset_request::PasswordResetRequest,
+    user::User,
+    user_token::{UserToken, UserTokenKind},
+};
+
+use crate::{
+    app::{
+        errors::CsrfError,
+        templates::{default_context, render_template},
+    },
+    env::Env,
+    notifications::{can_send_links, send_user_link},
+    utils::get_client_ip,
+};
+
+/// How long after a password reset link is sent before the user can be sent another one.
+const RESET_RESEND_INTERVAL: Duration = Duration::minutes(10);
+
+/// Get the user that a token in a link was sent to, if the token is valid and the user can still
+/// choose a password.
+async fn get_token_user(
+    env: &Env,
+    kind: UserTokenKind,
+    token: &str,
+) -> poem::Result<Option<(UserToken, User)>> {
+    let Some(user_token) = UserToken::get_valid(&env.pool, kind, token)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, ?kind, "Failed to get user token");
+            InternalServerError(err)
+        })?
+    else {
+        return Ok(None);
+    };
+
+    let user = User::get(&env.pool, user_token.user)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, user_id = %user_token.user, "Failed to get user for token");
+            InternalServerError(err)
+        })?
+        .filter(|user| user.enabled && user.ldap_dn.is_none());
+
+    Ok(user.map(|user| (user_token, user)))
+}
+
+async fn render_choose_password(
+    env: &Env,
+    csrf_token: &CsrfToken,
+    kind: UserTokenKind,
+    user: Option<&User>,
+    error: Option<&str>,
+) -> poem::Result<Html<String>> {
+    render_template(
+        "user/choose-password.html",
+        context! {
+            token => csrf_token.0,
+            invitation => kind == UserTokenKind::Invitation,
+            user,
+            error,
+            ..default_context(env)
+        },
+    )
+    .await
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ChoosePasswordForm {
+    token: String,
+    #[validate(length(min = 8, message = "Password must be at least 8 characters"))]
+    password: String,
+    confirm: String,
+}
+
+/// Set the password of the user that a token was sent to, using up the token.
+///
+/// Returns the user if their password was set. Otherwise, returns the user and the error to show
+/// them on the form, or `None` if the link can no longer be used.
+async fn choose_password(
+    env: &Env,
+    kind: UserTokenKind,
+    token: &str,
+    form: &ChoosePasswordForm,
+) -> poem::Result<Result<User, Option<(User, &'static str)>>> {
+    let Some((mut user_token, mut user)) = get_token_user(env, kind, token).await? else {
+        return Ok(Err(None));
+    };
+
+    if let Err(errors) = form.validate() {
+        tracing::warn!(%user.id, ?errors, "Password validation failed");
+        return Ok(Err(Some((user, "Password must be at least 8 characters"))));
+    }
+
+    if form.password != form.confirm {
+        return Ok(Err(Some((user, "The passwords do not match"))));
+    }
+
+    // Mark the token as used first, so that the same link cannot set the password twice.
+    if !user_token.mark_used(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %user_token.id, "Failed to mark user token as used");
+        InternalServerError(err)
+    })? {
+        return Ok(Err(None));
+    }
+
+    user.set_password(&env.pool, &form.password)
+        .await
+        .map_err(|err| {
+            tracing::error!(%user.id, ?user.username, ?err, "Failed to set password");
+            err
+        })?;
+
+    Ok(Ok(user))
+}
+
+#[handler]
+pub async fn get_invitation(
+    env: Data<&Env>,
+    csrf_token: &CsrfToken,
+    Path(token): Path<String>,
+) -> poem::Result<Html<String>> {
+    if env.password_login_disabled() {
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    }
+
+    let user = get_token_user(&env, UserTokenKind::Invitation, &token)
+        .await?
+        .map(|(_, user)| user);
+
+    render_choose_password(
+        &env,
+        csrf_token,
+        UserTokenKind::Invitation,
+        user.as_ref(),
+        None,
+    )
+    .await
+}
+
+#[handler]
+pub async fn post_invitation(
+    env: Data<&Env>,
+    session: &Session,
+    csrf_token: &CsrfToken,
+    verifier: &CsrfVerifier,
+    Path(token): Path<String>,
+    Form(form): Form<ChoosePasswordForm>,
+) -> poem::Result<Response> {
+    if !verifier.is_valid(&form.token) {
+        tracing::error!("Invalid CSRF token in invitation form");
+        return Err(CsrfError.into());
+    }
+
+    if env.password_login_disabled() {
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    }
+
+    let user = match choose_password(&env, UserTokenKind::Invitation, &token, &form).await? {
+        Ok(user) => user,
+        Err(failed) => {
+            return render_choose_password(
+                &env,
+                csrf_token,
+                UserTokenKind::Invitation,
+                failed.as_ref().map(|(user, _)| user),
+                failed.as_ref().map(|(_, error)| *error),
+            )
+            .await
+            .map(IntoResponse::into_response);
+        }
+    };
+
+    tracing::info!(%user.id, ?user.username, "User accepted invitation");
+
+    // The invitation has shown that the link reached the user, so they are signed in straight away
+    // and asked to set up two-factor authentication.
+    session.clear();
+    session.set("user_id", user.id);
+    session.set("setup_totp", true);
+    session.set(
+        "settings_success",
+        "Welcome to Parcel! Your password has been set.",
+    );
+
+    Ok(Redirect::see_other("/user/settings").into_response())
+}
+
+#[handler]
+pub async fn get_forgot_password(
+    env: Data<&Env>,
+    csrf_token: &CsrfToken,
+) -> poem::Result<Html<String>> {
+    if env.password_login_disabled() || !can_send_links(&env) {
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    }
+
+    render_template(
+        "user/forgot-password.html",
+        context! {
+            token => csrf_token.0,
+            ..default_context(&env)
+        },
+    )
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ForgotPasswordForm {
+    token: String,
+    username: String,
+}
+
+#[handler]
+pub async fn post_forgot_password(
+    env: Data<&Env>,
+    csrf_token: &CsrfToken,
+    verifier: &CsrfVerifier,
+    real_ip: RealIp,
+    remote_addr: &RemoteAddr,
+    Form(ForgotPasswordForm { token, username }): Form<ForgotPasswordForm>,
+) -> poem::Result<Html<String>> {
+    if !verifier.is_valid(&token) {
+        tracing::error!("Invalid CSRF token in forgot password form");
+        return Err(CsrfError.into());
+    }
+
+    if env.password_login_disabled() || !can_send_links(&env) {
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    }
+
+    let client_ip = get_client_ip(env.trust_proxy, &real_ip, remote_addr);
+    let client_ip_str = client_ip.map(|ip| ip.to_string());
+
+    if PasswordResetRequest::is_rate_limited(&env.pool, client_ip_str.as_deref())
+        .await
+        .map_err(|err| {
+            tracing::error!(
+                ?err,
+                ?client_ip,
+                "Failed to check password reset rate limit"
+            );
+            InternalServerError(err)
+        })?
+    {
+        return render_template(
+            "user/forgot-password.html",
+            context! {
+                token => csrf_token.0,
+                error => "Too many password resets have been asked for. Please try again later.",
+                ..default_context(&env)
+            },
+        )
+        .await;
+    }
+
+    let username = username.trim();
+    PasswordResetRequest::record(&env.pool, username, client_ip_str.as_deref())
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %username, "Failed to record password reset request");
+            InternalServerError(err)
+        })?;
+
+    let user = User::get_by_username(&env.pool, username)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %username, "Failed to get user by username");
+            InternalServerError(err)
+        })?;
+
+    // Several users can share an email address, so each of them is sent a link.
+    let users = if let Some(user) = user {
+        vec![user]
+    } else if username.contains('@') {
+        User::get_by_email(&env.pool, username)
+            .await
+            .map_err(|err| {
+                tracing::error!(?err, %username, "Failed to get users by email");
+                InternalServerError(err)
+            })?
+    } else {
+        Vec::new()
+    };
+
+    // Users that are linked to the LDAP directory change their password there.
+    for user in users {
+        if !user.enabled || user.ldap_dn.is_some() || user.email.is_none() {
+            tracing::info!(%user.id, ?user.username, "Not sending password reset link");
+            continue;
+        }
+
+        // A link that was sent recently is left to work, rather than being replaced by another.
+        let (user_token, token) = UserToken::new(user.id, UserTokenKind::PasswordReset, None);
+        let since = OffsetDateTime::now_utc() - RESET_RESEND_INTERVAL;
+        if !user_token
+            .create_unless_recent(&env.pool, since)
+            .await
+            .map_err(|err| {
+                tracing::error!(?err, %user.id, "Failed to create password reset token");
+                InternalServerError(err)
+            })?
+        {
+            tracing::info!(%user.id, ?user.username, "Password reset link was sent recently");
+            continue;
+        }
+
+        send_user_link(
+            &env,
+            &user,
+            UserTokenKind::PasswordReset,
+            &format!("/user/password/reset/{token}"),
+        );
+
+        tracing::info!(%user.id, ?user.username, "Sent password reset link");
+    }
+
+    // The response is the same whether or not an account was found, so that this form cannot be
+    // used to find out which accounts exist.
+    render_template(
+        "user/forgot-password.html",
+        context! {
+            token => csrf_token.0,
+            sent => true,
+            ..default_context(&env)
+        },
+    )
+    .await
+}
+
+#[handler]
+pub async fn get_reset_password(
+    env: Data<&Env>,
+    csrf_token: &CsrfToken,
+    Path(token): Path<String>,
+) -> poem::Result<Html<String>> {
+    if env.password_login_disabled() {
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    }
+
+    let user = get_token_user(&env, UserTokenKind::PasswordReset, &token)
+        .await?
+        .map(|(_, user)| user);
+
+    render_choose_password(
+        &env,
+        csrf_token,
+        UserTokenKind::PasswordReset,
+        user.as_ref(),
+        None,
+    )
+    .await
+}
+
+#[handler]
+pub async fn post_reset_password(
+    env: Data<&Env>,
+    session: &Session,
+    csrf_token: &CsrfToken,
+    verifier: &CsrfVerifier,
+    Path(token): Path<String>,
+    Form(form): Form<ChoosePasswordForm>,
+) -> poem::Result<Response> {
+    if !verifier.is_valid(&form.token) {
+        tracing::error!("Invalid CSRF token in reset password form");
+        return Err(CsrfError.into());
+    }
+
+    if env.password_login_disabled() {
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    }
+
+    let user = match choose_password(&env, UserTokenKind::PasswordReset, &token, &form).await? {
+        Ok(user) => user,
+        Err(failed) => {
+            return render_choose_password(
+                &env,
+                csrf_token,
+                UserTokenKind::PasswordReset,
+                failed.as_ref().map(|(user, _)| user),
+                failed.as_ref().map(|(_, error)| *error),
+            )
+            .await
+            .map(IntoResponse::into_response);
+        }
+    };
+
+    tracing::info!(%user.id, ?user.username, "User reset their password");
+
+    // Two-factor authentication still applies, so the user signs in as usual with their new password.
+    session.set(
+        "success",
+        "Your password has been changed. You can now sign in with your new password.",
+    );
+
+    Ok(Redirect::see_other("/user/signin").into_response())
+}
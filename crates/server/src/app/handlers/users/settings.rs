@@ -1,3 +1,4 @@
+use esbuild_bundle::javascript;
 use fast_qr::{
     convert::{svg::SvgBuilder, Builder, Shape},
     QRBuilder,
@@ -12,9 +13,16 @@ use poem::{
 };
 use rand::Rng;
 use serde::Deserialize;
-use validator::Validate;
-
-use parcel_model::{upload::UploadOrder, user::User};
+use validator::{Validate, ValidateEmail};
+
+use parcel_model::{
+    api_token::ApiTokenList,
+    recovery_code::RecoveryCode,
+    team::Team,
+    upload::UploadOrder,
+    user::User,
+    webauthn::WebauthnCredential,
+};
 
 use crate::{
     app::{
@@ -33,14 +41,66 @@ pub async fn get_settings(
     session: &Session,
     token: &CsrfToken,
 ) -> poem::Result<Html<String>> {
+    let (api_tokens, teams, webauthn_credentials) = tokio::join!(
+        ApiTokenList::get_for_user(&env.pool, user.id),
+        Team::get_for_user(&env.pool, user.id),
+        WebauthnCredential::get_for_user(&env.pool, user.id)
+    );
+
+    let api_tokens = api_tokens.map_err(|err| {
+        tracing::error!(%user.id, ?err, "Failed to get API tokens for user");
+        InternalServerError(err)
+    })?;
+
+    let teams = teams
+        .map_err(|err| {
+            tracing::error!(%user.id, ?err, "Failed to get teams for user");
+            InternalServerError(err)
+        })?
+        .into_iter()
+        .filter(|team| team.enabled)
+        .collect::<Vec<_>>();
+
+    let webauthn_credentials = webauthn_credentials.map_err(|err| {
+        tracing::error!(%user.id, ?err, "Failed to get WebAuthn credentials for user");
+        InternalServerError(err)
+    })?;
+
+    let recovery_codes_remaining = RecoveryCode::count_remaining(&env.pool, user.id)
+        .await
+        .map_err(|err| {
+            tracing::error!(%user.id, ?err, "Failed to count recovery codes for user");
+            InternalServerError(err)
+        })?;
+
+    // A user that has just accepted an invitation is taken straight to setting up their
+    // authenticator app.
+    let setup_totp = session.take::<bool>("setup_totp").unwrap_or_default() && user.totp.is_none();
+
     render_template(
         "user/settings.html",
         context! {
             token => token.0,
+            api_tokens,
+            teams,
+            webauthn_credentials,
+            recovery_codes_remaining,
             settings_error => session.take::<String>("settings_error"),
             settings_success => session.take::<String>("settings_success"),
             password_error => session.take::<String>("password_error"),
             password_success => session.take::<String>("password_success"),
+            api_token_error => session.take::<String>("api_token_error"),
+            api_token_success => session.take::<String>("api_token_success"),
+            new_api_token => session.take::<String>("new_api_token"),
+            recovery_codes => session.take::<Vec<String>>("recovery_codes"),
+            setup_totp,
+            notifications_error => session.take::<String>("notifications_error"),
+            notifications_success => session.take::<String>("notifications_success"),
+            webauthn_error => session.take::<String>("webauthn_error"),
+            webauthn_success => session.take::<String>("webauthn_success"),
+            email_enabled => env.mailer.is_some(),
+            webauthn_enabled => env.passkeys.is_some(),
+            webauthn_js => javascript!("$CARGO_MANIFEST_DIR/scripts/webauthn.ts"),
             ..authorized_context(&env, &user)
         },
     )
@@ -142,6 +202,66 @@ pub async fn post_settings(
     Ok(Redirect::see_other("/user/settings"))
 }
 
+#[derive(Debug, Deserialize)]
+pub struct NotificationsForm {
+    token: String,
+    email: String,
+    notify_downloads: Option<String>,
+    notify_expiring: Option<String>,
+    notify_requests: Option<String>,
+    notify_lockouts: Option<String>,
+}
+
+#[handler]
+pub async fn post_notifications(
+    env: Data<&Env>,
+    SessionUser(mut user): SessionUser,
+    verifier: &CsrfVerifier,
+    session: &Session,
+    Form(form): Form<NotificationsForm>,
+) -> poem::Result<Redirect> {
+    if !verifier.is_valid(&form.token) {
+        tracing::error!("Invalid CSRF token in notifications form");
+        return Err(CsrfError.into());
+    }
+
+    let email = Some(form.email.trim()).filter(|email| !email.is_empty());
+    if email.is_some_and(|email| !email.validate_email()) {
+        session.set("notifications_error", "The email address is not valid");
+        return Ok(Redirect::see_other("/user/settings"));
+    }
+
+    // Only administrators are told about lockouts, so leave the setting alone for other users.
+    let notify_lockouts = if user.admin {
+        form.notify_lockouts.as_deref() == Some("on")
+    } else {
+        user.notify_lockouts
+    };
+
+    tracing::info!(%user.id, has_email = email.is_some(), "Changing notification settings");
+
+    user.set_notifications(
+        &env.pool,
+        email,
+        form.notify_downloads.as_deref() == Some("on"),
+        form.notify_expiring.as_deref() == Some("on"),
+        form.notify_requests.as_deref() == Some("on"),
+        notify_lockouts,
+    )
+    .await
+    .map_err(|err| {
+        tracing::error!(%user.id, ?err, "Failed to set notification settings");
+        InternalServerError(err)
+    })?;
+
+    session.set(
+        "notifications_success",
+        "Your notification settings have been updated successfully",
+    );
+
+    Ok(Redirect::see_other("/user/settings"))
+}
+
 #[derive(Debug, Deserialize, Validate)]
 pub struct PasswordForm {
     token: String,
@@ -357,7 +477,15 @@ pub async fn post_setup_totp(
             InternalServerError(err)
         })?;
 
+    let recovery_codes = RecoveryCode::generate(&env.pool, user.id)
+        .await
+        .map_err(|err| {
+            tracing::error!(%user.id, ?err, "Failed to generate recovery codes");
+            InternalServerError(err)
+        })?;
+
     session.remove("totp_secret");
+    session.set("recovery_codes", recovery_codes);
     session.set(
         "password_success",
         "Two-factor authentication has been enabled successfully. Well done 👍",
@@ -368,6 +496,47 @@ pub async fn post_setup_totp(
         .into_response())
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RecoveryCodesForm {
+    token: String,
+}
+
+#[handler]
+pub async fn post_recovery_codes(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    verifier: &CsrfVerifier,
+    session: &Session,
+    Form(RecoveryCodesForm { token }): Form<RecoveryCodesForm>,
+) -> poem::Result<Redirect> {
+    if !verifier.is_valid(&token) {
+        tracing::error!("Invalid CSRF token in recovery codes form");
+        return Err(CsrfError.into());
+    }
+
+    if user.totp.is_none() {
+        tracing::error!(%user.id, "Cannot generate recovery codes without TOTP");
+        session.set(
+            "password_error",
+            "Recovery codes can only be used with two-factor authentication",
+        );
+
+        return Ok(Redirect::see_other("/user/settings"));
+    }
+
+    let recovery_codes = RecoveryCode::generate(&env.pool, user.id)
+        .await
+        .map_err(|err| {
+            tracing::error!(%user.id, ?err, "Failed to generate recovery codes");
+            InternalServerError(err)
+        })?;
+
+    tracing::info!(%user.id, "Regenerated recovery codes");
+    session.set("recovery_codes", recovery_codes);
+
+    Ok(Redirect::see_other("/user/settings"))
+}
+
 #[handler]
 pub async fn get_remove_totp(
     env: Data<&Env>,
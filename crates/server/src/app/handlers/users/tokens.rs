@@ -0,0 +1,170 @@
+use poem::{
+    error::InternalServerError,
+    handler,
+    http::StatusCode,
+    session::Session,
+    web::{CsrfVerifier, Data, Form, Path, Redirect},
+};
+use serde::Deserialize;
+use time::{Date, OffsetDateTime};
+
+use parcel_model::{api_token::ApiToken, team::Team, types::Key};
+
+use crate::{
+    app::{errors::CsrfError, extractors::user::SessionUser},
+    env::Env,
+};
+
+time::serde::format_description!(iso8601_date, Date, "[year]-[month]-[day]");
+
+#[derive(Debug, Deserialize)]
+pub struct NewApiTokenForm {
+    token: String,
+    name: String,
+    team: Option<String>,
+    can_read: Option<String>,
+    can_upload: Option<String>,
+    can_delete: Option<String>,
+    #[serde(default, with = "iso8601_date::option")]
+    expiry_date: Option<Date>,
+}
+
+#[handler]
+pub async fn post_api_tokens(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    verifier: &CsrfVerifier,
+    session: &Session,
+    Form(form): Form<NewApiTokenForm>,
+) -> poem::Result<Redirect> {
+    if !verifier.is_valid(&form.token) {
+        tracing::error!("Invalid CSRF token in new API token form");
+        return Err(CsrfError.into());
+    }
+
+    let name = form.name.trim();
+    if name.is_empty() || name.len() > 100 {
+        session.set(
+            "api_token_error",
+            "Token names must be between 1 and 100 characters",
+        );
+        return Ok(Redirect::see_other("/user/settings"));
+    }
+
+    let can_read = form.can_read.as_deref() == Some("on");
+    let can_upload = form.can_upload.as_deref() == Some("on");
+    let can_delete = form.can_delete.as_deref() == Some("on");
+
+    if !can_read && !can_upload && !can_delete {
+        session.set(
+            "api_token_error",
+            "Tokens must be granted at least one scope",
+        );
+        return Ok(Redirect::see_other("/user/settings"));
+    }
+
+    if matches!(form.expiry_date, Some(date) if date < OffsetDateTime::now_utc().date()) {
+        session.set("api_token_error", "The expiry date cannot be in the past");
+        return Ok(Redirect::see_other("/user/settings"));
+    }
+
+    let team = match form.team.as_deref() {
+        None | Some("") => None,
+        Some(team) => {
+            let Ok(team_id) = team.parse::<Key<Team>>() else {
+                tracing::error!(%user.id, ?team, "Invalid team ID in new API token form");
+                return Err(poem::Error::from_status(StatusCode::BAD_REQUEST));
+            };
+
+            let is_member = user.is_member_of(&env.pool, team_id).await.map_err(|err| {
+                tracing::error!(%user.id, %team_id, ?err, "Failed to check team membership");
+                InternalServerError(err)
+            })?;
+
+            if !is_member {
+                tracing::error!(%user.id, %team_id, "User is not a member of team");
+                return Err(poem::Error::from_status(StatusCode::FORBIDDEN));
+            }
+
+            Some(team_id)
+        }
+    };
+
+    let (api_token, token) = ApiToken::new(
+        user.id,
+        name,
+        team,
+        can_read,
+        can_upload,
+        can_delete,
+        form.expiry_date,
+    );
+
+    api_token.create(&env.pool).await.map_err(|err| {
+        tracing::error!(%user.id, ?err, "Failed to create API token");
+        InternalServerError(err)
+    })?;
+
+    tracing::info!(
+        %user.id,
+        token_id = %api_token.id,
+        team = ?team,
+        can_read,
+        can_upload,
+        can_delete,
+        expiry = ?api_token.expiry_date,
+        "Created API token"
+    );
+
+    // The token is only ever shown to the user once, on the next render of their settings.
+    session.set("new_api_token", token);
+    Ok(Redirect::see_other("/user/settings"))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevokeApiTokenForm {
+    token: String,
+}
+
+#[handler]
+pub async fn post_revoke_api_token(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    verifier: &CsrfVerifier,
+    session: &Session,
+    Path(token_id): Path<Key<ApiToken>>,
+    Form(form): Form<RevokeApiTokenForm>,
+) -> poem::Result<Redirect> {
+    if !verifier.is_valid(&form.token) {
+        tracing::error!("Invalid CSRF token in revoke API token form");
+        return Err(CsrfError.into());
+    }
+
+    let Some(mut api_token) = ApiToken::get(&env.pool, token_id)
+        .await
+        .map_err(|err| {
+            tracing::error!(%token_id, ?err, "Failed to get API token");
+            InternalServerError(err)
+        })?
+        .filter(|api_token| api_token.user == user.id)
+    else {
+        tracing::error!(%user.id, %token_id, "Unrecognized API token ID");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    if api_token.revoked_at.is_none() {
+        api_token.revoke(&env.pool).await.map_err(|err| {
+            tracing::error!(%token_id, ?err, "Failed to revoke API token");
+            InternalServerError(err)
+        })?;
+
+        tracing::info!(%user.id, %token_id, "Revoked API token");
+    }
+
+    session.set(
+        "api_token_success",
+        format!("The API token '{}' has been revoked", api_token.name),
+    );
+
+    Ok(Redirect::see_other("/user/settings"))
+}
@@ -0,0 +1,636 @@
+use std::time::Duration;
+
+use poem::{
+    error::InternalServerError,
+    handler,
+    http::StatusCode,
+    session::Session,
+    web::{
+        cookie::{Cookie, CookieJar, CookieKey, SameSite},
+        CsrfVerifier, Data, Form, Json, Path, RealIp, Redirect, RemoteAddr,
+    },
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+use webauthn_rs::prelude::{
+    CreationChallengeResponse, DiscoverableAuthentication, PasskeyAuthentication,
+    PasskeyRegistration, PublicKeyCredential, RegisterPublicKeyCredential,
+    RequestChallengeResponse,
+};
+
+use parcel_model::{
+    login_attempt::LoginAttempt,
+    types::Key,
+    user::User,
+    webauthn::{WebauthnCeremony, WebauthnCredential},
+};
+
+use crate::{
+    app::{errors::CsrfError, extractors::user::SessionUser},
+    env::Env,
+    notifications::record_failed_login,
+    utils::{get_client_ip, SessionExt},
+    webauthn::Passkeys,
+};
+
+/// The cookie that keeps the state of a WebAuthn ceremony until the browser responds.
+///
+/// The state includes the credentials that the user can sign in with, which can be too large to
+/// keep in the session cookie alongside everything else, so it has a cookie of its own. It is
+/// encrypted with the same key as the session. Each ceremony is also recorded by the server, so that
+/// a copy of the cookie cannot be used again, or after the ceremony has expired.
+const CEREMONY_COOKIE: &str = "parcel-webauthn";
+const CEREMONY_COOKIE_PATH: &str = "/user";
+
+/// How long the user has to respond to their authenticator.
+const CEREMONY_EXPIRY: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Ceremony {
+    /// Registering a new credential for a user.
+    Register {
+        user: Key<User>,
+        state: PasskeyRegistration,
+    },
+    /// Using a credential as a second factor, once the user has given their password.
+    Authenticate {
+        user: Key<User>,
+        state: PasskeyAuthentication,
+    },
+    /// Signing in without a password, with a passkey that the authenticator chooses.
+    Discover { state: DiscoverableAuthentication },
+}
+
+/// The state of a ceremony, along with the ID of the ceremony that the server recorded.
+#[derive(Debug, Deserialize, Serialize)]
+struct CeremonyCookie {
+    id: Key<WebauthnCeremony>,
+    ceremony: Ceremony,
+}
+
+fn get_passkeys(env: &Env) -> poem::Result<&Passkeys> {
+    env.passkeys.as_ref().ok_or_else(|| {
+        tracing::error!("WebAuthn has not been configured");
+        poem::Error::from_status(StatusCode::NOT_FOUND)
+    })
+}
+
+async fn start_ceremony(
+    env: &Env,
+    cookie_jar: &CookieJar,
+    cookie_key: &CookieKey,
+    ceremony: Ceremony,
+) -> poem::Result<()> {
+    let expired = OffsetDateTime::now_utc() - CEREMONY_EXPIRY;
+    let WebauthnCeremony { id, .. } =
+        WebauthnCeremony::start(&env.pool, expired)
+            .await
+            .map_err(|err| {
+                tracing::error!(?err, "Failed to record WebAuthn ceremony");
+                InternalServerError(err)
+            })?;
+
+    let mut cookie = Cookie::new(CEREMONY_COOKIE, CeremonyCookie { id, ceremony });
+    cookie.set_path(CEREMONY_COOKIE_PATH);
+    cookie.set_http_only(true);
+    cookie.set_same_site(SameSite::Strict);
+    cookie.set_max_age(CEREMONY_EXPIRY);
+    cookie_jar.private_with_key(cookie_key).add(cookie);
+    Ok(())
+}
+
+/// Take the state of the ceremony that the browser is responding to.
+///
+/// A challenge can only be answered once, so the cookie is removed whatever happens next. The
+/// browser is only asked to forget the cookie, so the server checks that the ceremony has not
+/// already been finished and has not expired.
+async fn take_ceremony(
+    env: &Env,
+    cookie_jar: &CookieJar,
+    cookie_key: &CookieKey,
+) -> poem::Result<Option<Ceremony>> {
+    let cookie = cookie_jar
+        .private_with_key(cookie_key)
+        .get(CEREMONY_COOKIE)
+        .and_then(|cookie| cookie.value::<CeremonyCookie>().ok());
+
+    let mut removal = Cookie::named(CEREMONY_COOKIE);
+    removal.set_path(CEREMONY_COOKIE_PATH);
+    removal.make_removal();
+    cookie_jar.add(removal);
+
+    let Some(CeremonyCookie { id, ceremony }) = cookie else {
+        return Ok(None);
+    };
+
+    let expired = OffsetDateTime::now_utc() - CEREMONY_EXPIRY;
+    let current = WebauthnCeremony::finish(&env.pool, id, expired)
+        .await
+        .map_err(|err| {
+            tracing::error!(%id, ?err, "Failed to finish WebAuthn ceremony");
+            InternalServerError(err)
+        })?;
+
+    if !current {
+        tracing::warn!(%id, "WebAuthn ceremony has already been finished or has expired");
+        return Ok(None);
+    }
+
+    Ok(Some(ceremony))
+}
+
+/// Tell the browser where to go once a ceremony has finished.
+fn continue_to(destination: impl Into<String>) -> Json<Value> {
+    Json(json!({ "redirect": destination.into() }))
+}
+
+/// Finish signing in a user, giving the page that they should be sent to.
+fn complete_signin(session: &Session, user: &User) -> String {
+    session.remove("_authenticating");
+    session.remove("_authenticating_username");
+    session.set("user_id", user.id);
+
+    session
+        .take::<String>("destination")
+        .unwrap_or_else(|| if user.admin { "/admin" } else { "/" }.to_string())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChallengeRequest {
+    token: String,
+}
+
+#[handler]
+pub async fn post_webauthn_challenge(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    verifier: &CsrfVerifier,
+    cookie_jar: &CookieJar,
+    cookie_key: Data<&CookieKey>,
+    Json(ChallengeRequest { token }): Json<ChallengeRequest>,
+) -> poem::Result<Json<CreationChallengeResponse>> {
+    if !verifier.is_valid(&token) {
+        tracing::error!("Invalid CSRF token in WebAuthn registration request");
+        return Err(CsrfError.into());
+    }
+
+    let passkeys = get_passkeys(&env)?;
+    let existing = WebauthnCredential::get_for_user(&env.pool, user.id)
+        .await
+        .map_err(|err| {
+            tracing::error!(%user.id, ?err, "Failed to get WebAuthn credentials for user");
+            InternalServerError(err)
+        })?;
+
+    let (challenge, state) = passkeys
+        .start_registration(&user, &existing)
+        .map_err(|err| {
+            tracing::error!(%user.id, ?err, "Failed to start WebAuthn registration");
+            poem::Error::from(err)
+        })?;
+
+    start_ceremony(
+        &env,
+        cookie_jar,
+        &cookie_key,
+        Ceremony::Register {
+            user: user.id,
+            state,
+        },
+    )
+    .await?;
+
+    Ok(Json(challenge))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterRequest {
+    token: String,
+    name: String,
+    credential: RegisterPublicKeyCredential,
+}
+
+#[handler]
+pub async fn post_webauthn(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    verifier: &CsrfVerifier,
+    session: &Session,
+    cookie_jar: &CookieJar,
+    cookie_key: Data<&CookieKey>,
+    Json(RegisterRequest {
+        token,
+        name,
+        credential,
+    }): Json<RegisterRequest>,
+) -> poem::Result<Json<Value>> {
+    if !verifier.is_valid(&token) {
+        tracing::error!("Invalid CSRF token in WebAuthn registration");
+        return Err(CsrfError.into());
+    }
+
+    let passkeys = get_passkeys(&env)?;
+
+    let Some(Ceremony::Register {
+        user: user_id,
+        state,
+    }) = take_ceremony(&env, cookie_jar, &cookie_key).await?
+    else {
+        tracing::error!(%user.id, "No WebAuthn registration in progress");
+        session.set(
+            "webauthn_error",
+            "Registering your security key took too long. Please try again.",
+        );
+        return Ok(continue_to("/user/settings"));
+    };
+
+    if user_id != user.id {
+        tracing::error!(%user.id, %user_id, "WebAuthn registration was started by another user");
+        return Err(poem::Error::from_status(StatusCode::BAD_REQUEST));
+    }
+
+    let name = name.trim();
+    if name.is_empty() || name.len() > 100 {
+        session.set(
+            "webauthn_error",
+            "Security key names must be between 1 and 100 characters",
+        );
+        return Ok(continue_to("/user/settings"));
+    }
+
+    let credential = match passkeys.finish_registration(user.id, name, &credential, &state) {
+        Ok(credential) => credential,
+        Err(err) => {
+            tracing::error!(%user.id, ?err, "Failed to register WebAuthn credential");
+            session.set(
+                "webauthn_error",
+                "Your security key could not be registered. Please try again.",
+            );
+            return Ok(continue_to("/user/settings"));
+        }
+    };
+
+    credential.create(&env.pool).await.map_err(|err| {
+        tracing::error!(%user.id, ?err, "Failed to create WebAuthn credential");
+        InternalServerError(err)
+    })?;
+
+    tracing::info!(%user.id, credential_id = %credential.id, "Registered WebAuthn credential");
+
+    session.set(
+        "webauthn_success",
+        format!("The security key '{}' has been registered", credential.name),
+    );
+
+    Ok(continue_to("/user/settings"))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemoveWebauthnForm {
+    token: String,
+}
+
+#[handler]
+pub async fn post_remove_webauthn(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    verifier: &CsrfVerifier,
+    session: &Session,
+    Path(credential_id): Path<Key<WebauthnCredential>>,
+    Form(RemoveWebauthnForm { token }): Form<RemoveWebauthnForm>,
+) -> poem::Result<Redirect> {
+    if !verifier.is_valid(&token) {
+        tracing::error!("Invalid CSRF token in remove WebAuthn credential form");
+        return Err(CsrfError.into());
+    }
+
+    let Some(credential) = WebauthnCredential::get(&env.pool, credential_id)
+        .await
+        .map_err(|err| {
+            tracing::error!(%credential_id, ?err, "Failed to get WebAuthn credential");
+            InternalServerError(err)
+        })?
+        .filter(|credential| credential.user == user.id)
+    else {
+        tracing::error!(%user.id, %credential_id, "Unrecognized WebAuthn credential ID");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    credential.delete(&env.pool).await.map_err(|err| {
+        tracing::error!(%credential_id, ?err, "Failed to delete WebAuthn credential");
+        InternalServerError(err)
+    })?;
+
+    tracing::info!(%user.id, %credential_id, "Removed WebAuthn credential");
+
+    session.set(
+        "webauthn_success",
+        format!("The security key '{}' has been removed", credential.name),
+    );
+
+    Ok(Redirect::see_other("/user/settings"))
+}
+
+#[handler]
+pub async fn post_signin_webauthn_challenge(
+    env: Data<&Env>,
+    session: &Session,
+    verifier: &CsrfVerifier,
+    cookie_jar: &CookieJar,
+    cookie_key: Data<&CookieKey>,
+    Json(ChallengeRequest { token }): Json<ChallengeRequest>,
+) -> poem::Result<Json<RequestChallengeResponse>> {
+    if !verifier.is_valid(&token) {
+        tracing::error!("Invalid CSRF token in WebAuthn sign in request");
+        return Err(CsrfError.into());
+    }
+
+    let passkeys = get_passkeys(&env)?;
+
+    let Some(user_id) = session.get::<Key<User>>("_authenticating") else {
+        tracing::error!("User not authenticating");
+        return Err(poem::Error::from_status(StatusCode::UNAUTHORIZED));
+    };
+
+    let credentials = WebauthnCredential::get_for_user(&env.pool, user_id)
+        .await
+        .map_err(|err| {
+            tracing::error!(%user_id, ?err, "Failed to get WebAuthn credentials for user");
+            InternalServerError(err)
+        })?;
+
+    let (challenge, state) = passkeys.start_authentication(&credentials).map_err(|err| {
+        tracing::error!(%user_id, ?err, "Failed to start WebAuthn authentication");
+        poem::Error::from(err)
+    })?;
+
+    start_ceremony(
+        &env,
+        cookie_jar,
+        &cookie_key,
+        Ceremony::Authenticate {
+            user: user_id,
+            state,
+        },
+    )
+    .await?;
+
+    Ok(Json(challenge))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SignInRequest {
+    token: String,
+    credential: PublicKeyCredential,
+}
+
+#[handler]
+#[allow(clippy::too_many_arguments)]
+pub async fn post_signin_webauthn(
+    env: Data<&Env>,
+    session: &Session,
+    verifier: &CsrfVerifier,
+    cookie_jar: &CookieJar,
+    cookie_key: Data<&CookieKey>,
+    real_ip: RealIp,
+    remote_addr: &RemoteAddr,
+    Json(SignInRequest { token, credential }): Json<SignInRequest>,
+) -> poem::Result<Json<Value>> {
+    if !verifier.is_valid(&token) {
+        tracing::error!("Invalid CSRF token in WebAuthn sign in");
+        return Err(CsrfError.into());
+    }
+
+    let passkeys = get_passkeys(&env)?;
+
+    let Some(user_id) = session.get::<Key<User>>("_authenticating") else {
+        tracing::error!("User not authenticating");
+        session.set("error", "You need to sign in first");
+        return Ok(continue_to("/user/signin"));
+    };
+
+    let state = match take_ceremony(&env, cookie_jar, &cookie_key).await? {
+        Some(Ceremony::Authenticate { user, state }) if user == user_id => state,
+        _ => {
+            tracing::error!(%user_id, "No WebAuthn authentication in progress");
+            session.set(
+                "error",
+                "Using your security key took too long. Please try again.",
+            );
+            return Ok(continue_to("/user/signin/totp"));
+        }
+    };
+
+    // Get the username from session for lockout checks (shared counter with password)
+    let username = session
+        .get::<String>("_authenticating_username")
+        .unwrap_or_default();
+
+    let client_ip = get_client_ip(env.trust_proxy, &real_ip, remote_addr);
+    let client_ip_str = client_ip.map(|ip| ip.to_string());
+
+    if !username.is_empty()
+        && LoginAttempt::is_locked_out(&env.pool, &username)
+            .await
+            .map_err(|err| {
+                tracing::error!(?err, %username, "Failed to check lockout status");
+                InternalServerError(err)
+            })?
+    {
+        session.remove("_authenticating");
+        session.remove("_authenticating_username");
+        session.set(
+            "error",
+            "Too many failed attempts. Please try again in a few minutes.",
+        );
+        return Ok(continue_to("/user/signin"));
+    }
+
+    let Some(user) = User::get(&env.pool, user_id).await.map_err(|err| {
+        tracing::error!(%user_id, ?err, "Failed to get user by ID");
+        InternalServerError(err)
+    })?
+    else {
+        tracing::error!(%user_id, "User not found");
+        session.remove("_authenticating");
+        session.remove("_authenticating_username");
+        session.set("error", "You need to sign in first");
+        return Ok(continue_to("/user/signin"));
+    };
+
+    let credentials = WebauthnCredential::get_for_user(&env.pool, user.id)
+        .await
+        .map_err(|err| {
+            tracing::error!(%user.id, ?err, "Failed to get WebAuthn credentials for user");
+            InternalServerError(err)
+        })?;
+
+    let (mut used, passkey) = match passkeys.finish_authentication(credentials, &credential, &state)
+    {
+        Ok(used) => used,
+        Err(err) => {
+            tracing::error!(%user.id, ?err, "Failed to verify WebAuthn credential");
+
+            // Record failed attempt (shared counter with password)
+            if !username.is_empty() {
+                record_failed_login(&env, &username, client_ip_str.as_deref()).await;
+            }
+
+            session.set(
+                "error",
+                "🤨 Your security key was not accepted. Please try again.",
+            );
+            return Ok(continue_to("/user/signin/totp"));
+        }
+    };
+
+    used.record_used(&env.pool, passkey).await.map_err(|err| {
+        tracing::error!(%user.id, ?err, "Failed to update WebAuthn credential");
+        InternalServerError(err)
+    })?;
+
+    // Record successful login
+    if !username.is_empty() {
+        LoginAttempt::record(&env.pool, &username, client_ip_str.as_deref(), true)
+            .await
+            .ok();
+    }
+
+    tracing::info!(%user.id, credential_id = %used.id, "User signed in after WebAuthn");
+    Ok(continue_to(complete_signin(session, &user)))
+}
+
+#[handler]
+pub async fn post_signin_passkey_challenge(
+    env: Data<&Env>,
+    verifier: &CsrfVerifier,
+    cookie_jar: &CookieJar,
+    cookie_key: Data<&CookieKey>,
+    Json(ChallengeRequest { token }): Json<ChallengeRequest>,
+) -> poem::Result<Json<RequestChallengeResponse>> {
+    if !verifier.is_valid(&token) {
+        tracing::error!("Invalid CSRF token in passkey sign in request");
+        return Err(CsrfError.into());
+    }
+
+    let passkeys = get_passkeys(&env)?;
+    if env.password_login_disabled() {
+        tracing::error!("Attempt to sign in with a passkey when only single sign-on is allowed");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    }
+
+    let (challenge, state) = passkeys.start_discoverable().map_err(|err| {
+        tracing::error!(?err, "Failed to start passkey authentication");
+        poem::Error::from(err)
+    })?;
+
+    start_ceremony(&env, cookie_jar, &cookie_key, Ceremony::Discover { state }).await?;
+    Ok(Json(challenge))
+}
+
+#[handler]
+#[allow(clippy::too_many_arguments)]
+pub async fn post_signin_passkey(
+    env: Data<&Env>,
+    session: &Session,
+    verifier: &CsrfVerifier,
+    cookie_jar: &CookieJar,
+    cookie_key: Data<&CookieKey>,
+    real_ip: RealIp,
+    remote_addr: &RemoteAddr,
+    Json(SignInRequest { token, credential }): Json<SignInRequest>,
+) -> poem::Result<Json<Value>> {
+    if !verifier.is_valid(&token) {
+        tracing::error!("Invalid CSRF token in passkey sign in");
+        return Err(CsrfError.into());
+    }
+
+    let passkeys = get_passkeys(&env)?;
+    if env.password_login_disabled() {
+        tracing::error!("Attempt to sign in with a passkey when only single sign-on is allowed");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    }
+
+    let Some(Ceremony::Discover { state }) = take_ceremony(&env, cookie_jar, &cookie_key).await?
+    else {
+        tracing::error!("No passkey authentication in progress");
+        session.set(
+            "error",
+            "Using your passkey took too long. Please try again.",
+        );
+        return Ok(continue_to("/user/signin"));
+    };
+
+    let client_ip = get_client_ip(env.trust_proxy, &real_ip, remote_addr);
+    let client_ip_str = client_ip.map(|ip| ip.to_string());
+
+    let user_id = match passkeys.identify_discoverable(&credential) {
+        Ok(user_id) => user_id,
+        Err(err) => {
+            tracing::info!(?err, "Passkey did not identify a user");
+            session.set("error", "Your passkey was not recognised");
+            return Ok(continue_to("/user/signin"));
+        }
+    };
+
+    let Some(user) = User::get(&env.pool, user_id).await.map_err(|err| {
+        tracing::error!(%user_id, ?err, "Failed to get user by ID");
+        InternalServerError(err)
+    })?
+    else {
+        tracing::info!(%user_id, "Passkey belongs to an unknown user");
+        session.set("error", "Your passkey was not recognised");
+        return Ok(continue_to("/user/signin"));
+    };
+
+    if LoginAttempt::is_locked_out(&env.pool, &user.username)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %user.username, "Failed to check lockout status");
+            InternalServerError(err)
+        })?
+    {
+        session.set(
+            "error",
+            "Too many failed attempts. Please try again in a few minutes.",
+        );
+        return Ok(continue_to("/user/signin"));
+    }
+
+    let credentials = WebauthnCredential::get_for_user(&env.pool, user.id)
+        .await
+        .map_err(|err| {
+            tracing::error!(%user.id, ?err, "Failed to get WebAuthn credentials for user");
+            InternalServerError(err)
+        })?;
+
+    let (mut used, passkey) = match passkeys.finish_discoverable(credentials, &credential, state) {
+        Ok(used) => used,
+        Err(err) => {
+            tracing::error!(%user.id, ?err, "Failed to verify passkey");
+            record_failed_login(&env, &user.username, client_ip_str.as_deref()).await;
+            session.set("error", "Your passkey was not recognised");
+            return Ok(continue_to("/user/signin"));
+        }
+    };
+
+    if !user.enabled {
+        tracing::info!(%user.id, "User is disabled");
+        session.set("error", "Your account is disabled");
+        return Ok(continue_to("/user/signin"));
+    }
+
+    used.record_used(&env.pool, passkey).await.map_err(|err| {
+        tracing::error!(%user.id, ?err, "Failed to update WebAuthn credential");
+        InternalServerError(err)
+    })?;
+
+    LoginAttempt::record(&env.pool, &user.username, client_ip_str.as_deref(), true)
+        .await
+        .ok();
+
+    tracing::info!(%user.id, credential_id = %used.id, "User signed in with passkey");
+    Ok(continue_to(complete_signin(session, &user)))
+}
@@ -1,12 +1,41 @@
-use poem::{error::InternalServerError, http::StatusCode};
+use std::path::{Path, PathBuf};
+
+use poem::{error::InternalServerError, http::StatusCode, web::Field};
+use serde::Serialize;
+use serde_json::json;
+use time::{Duration, OffsetDateTime};
+use tokio::io::AsyncReadExt;
 
 use parcel_model::{
+    blob::{Acquired, Blob},
+    bundle::Bundle,
+    download::{DownloadDay, DownloadScope, DownloadStats},
+    folder::Folder,
+    team::Team,
     types::Key,
-    upload::{Upload, UploadPermission},
+    upload::{Upload, UploadPermission, UploadStats},
+    upload_request::UploadRequest,
+    upload_session::UploadSession,
     user::User,
 };
 
-use crate::env::Env;
+use crate::{
+    app::errors::{FileTooLargeError, FileTypeNotAllowedError, QuotaExceededError},
+    env::Env,
+    storage::{hash_file, HashReader},
+    workers::{previews::detect_mime_type, webhooks::queue_uploads_created},
+};
+
+/// Represents a pending upload before it's inserted into the database.
+#[derive(Debug, Serialize)]
+pub struct PendingUpload {
+    pub id: Key<Upload>,
+    pub slug: String,
+    pub blob: String,
+    pub hash: Option<String>,
+    pub filename: String,
+    pub size: i64,
+}
 
 pub async fn get_upload_by_id(env: &Env, id: Key<Upload>) -> poem::Result<Upload> {
     let Some(upload) = Upload::get(&env.pool, id).await.map_err(|err| {
@@ -34,6 +63,29 @@ pub async fn get_upload_by_slug(env: &Env, slug: &str) -> poem::Result<Upload> {
     Ok(upload)
 }
 
+pub async fn get_team_for_member(env: &Env, user: &User, team_id: Key<Team>) -> poem::Result<Team> {
+    let Some(team) = Team::get(&env.pool, team_id).await.map_err(|err| {
+        tracing::error!(%team_id, ?err, "Unable to get team by ID");
+        InternalServerError(err)
+    })?
+    else {
+        tracing::error!(%team_id, "Team not found");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    let is_member = user.is_member_of(&env.pool, team.id).await.map_err(|err| {
+        tracing::error!(%user.id, %team.id, ?err, "Unable to check if user is member of team");
+        InternalServerError(err)
+    })?;
+
+    if !is_member {
+        tracing::error!(%user.id, %team.id, "User is not a member of team");
+        return Err(poem::Error::from_status(StatusCode::FORBIDDEN));
+    }
+
+    Ok(team)
+}
+
 pub async fn check_permission(
     env: &Env,
     upload: &Upload,
@@ -58,34 +110,743 @@ pub async fn check_permission(
     Ok(())
 }
 
+pub async fn get_bundle_by_id(env: &Env, id: Key<Bundle>) -> poem::Result<Bundle> {
+    let Some(bundle) = Bundle::get(&env.pool, id).await.map_err(|err| {
+        tracing::error!(?err, %id, "Unable to get bundle by ID");
+        InternalServerError(err)
+    })?
+    else {
+        tracing::error!(%id, "Unable to find bundle with given ID");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    Ok(bundle)
+}
+
+pub async fn get_bundle_by_slug(env: &Env, slug: &str) -> poem::Result<Bundle> {
+    let Some(bundle) = Bundle::get_by_slug(&env.pool, slug).await.map_err(|err| {
+        tracing::error!(?err, ?slug, "Unable to get bundle by slug");
+        InternalServerError(err)
+    })?
+    else {
+        tracing::error!(?slug, "Unable to find bundle with given slug");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    Ok(bundle)
+}
+
+pub async fn check_bundle_permission(
+    env: &Env,
+    bundle: &Bundle,
+    user: Option<&User>,
+    permission: UploadPermission,
+) -> poem::Result<()> {
+    let granted = bundle
+        .can_access(&env.pool, user, permission)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, bundle = %bundle.id, "Error checking bundle permission");
+            InternalServerError(err)
+        })?;
+
+    if !granted {
+        let uid = user.map(|u| u.id);
+        tracing::error!(bundle = %bundle.id, ?permission, user = ?uid,
+            "User tried to access bundle without permission");
+        return Err(poem::Error::from_status(StatusCode::FORBIDDEN));
+    }
+
+    Ok(())
+}
+
+/// Group newly inserted uploads into a bundle with the given name.
+pub async fn create_bundle(
+    env: &Env,
+    user: &User,
+    team: Option<&Team>,
+    name: &str,
+    uploads: &[Key<Upload>],
+) -> poem::Result<Bundle> {
+    let bundle = Bundle::new(
+        nanoid::nanoid!(),
+        name,
+        team.is_none().then_some(user.id),
+        team.map(|team| team.id),
+        user.id,
+    );
+
+    bundle.create(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, "Unable to create bundle");
+        InternalServerError(err)
+    })?;
+
+    bundle
+        .add_uploads(&env.pool, uploads)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %bundle.id, "Unable to add uploads to bundle");
+            InternalServerError(err)
+        })?;
+
+    tracing::info!(%bundle.id, uploads = uploads.len(), "Created bundle");
+    Ok(bundle)
+}
+
+pub async fn get_folder_by_id(env: &Env, id: Key<Folder>) -> poem::Result<Folder> {
+    let Some(folder) = Folder::get(&env.pool, id).await.map_err(|err| {
+        tracing::error!(?err, %id, "Unable to get folder by ID");
+        InternalServerError(err)
+    })?
+    else {
+        tracing::error!(%id, "Unable to find folder with given ID");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    Ok(folder)
+}
+
+pub async fn get_folder_by_slug(env: &Env, slug: &str) -> poem::Result<Folder> {
+    let Some(folder) = Folder::get_by_slug(&env.pool, slug).await.map_err(|err| {
+        tracing::error!(?err, ?slug, "Unable to get folder by slug");
+        InternalServerError(err)
+    })?
+    else {
+        tracing::error!(?slug, "Unable to find folder with given slug");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    Ok(folder)
+}
+
+pub async fn check_folder_permission(
+    env: &Env,
+    folder: &Folder,
+    user: Option<&User>,
+    permission: UploadPermission,
+) -> poem::Result<()> {
+    let granted = folder
+        .can_access(&env.pool, user, permission)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, folder = %folder.id, "Error checking folder permission");
+            InternalServerError(err)
+        })?;
+
+    if !granted {
+        let uid = user.map(|u| u.id);
+        tracing::error!(folder = %folder.id, ?permission, user = ?uid,
+            "User tried to access folder without permission");
+        return Err(poem::Error::from_status(StatusCode::FORBIDDEN));
+    }
+
+    Ok(())
+}
+
+/// Get a folder that uploads or other folders are being put into, making sure that it belongs to
+/// the given team, or to the user if there is no team.
+pub async fn get_folder_for_owner(
+    env: &Env,
+    user: &User,
+    team: Option<&Team>,
+    id: Key<Folder>,
+) -> poem::Result<Folder> {
+    let folder = get_folder_by_id(env, id).await?;
+
+    let owned = match team {
+        Some(team) => folder.owner_team == Some(team.id),
+        None => folder.owner_user == Some(user.id),
+    };
+
+    if !owned {
+        tracing::error!(%folder.id, %user.id, team = ?team.map(|team| team.id),
+            "Folder does not belong to the user or team");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    }
+
+    Ok(folder)
+}
+
+/// The folder that is shown in a list of uploads, along with the folders from the top level down
+/// to it, and the folders inside it.
+#[derive(Debug, Default, Serialize)]
+pub struct FolderListing {
+    pub folder: Option<Folder>,
+    pub path: Vec<Folder>,
+    pub folders: Vec<Folder>,
+}
+
+pub async fn get_folder_listing(
+    env: &Env,
+    user: &User,
+    team: Option<&Team>,
+    folder: Option<Key<Folder>>,
+) -> poem::Result<FolderListing> {
+    let folder = match folder {
+        Some(id) => Some(get_folder_for_owner(env, user, team, id).await?),
+        None => None,
+    };
+
+    let path = match folder {
+        Some(ref folder) => folder.get_path(&env.pool).await.map_err(|err| {
+            tracing::error!(?err, %folder.id, "Unable to get path to folder");
+            InternalServerError(err)
+        })?,
+        None => Vec::new(),
+    };
+
+    let parent = folder.as_ref().map(|folder| folder.id);
+    let folders = match team {
+        Some(team) => Folder::get_for_team(&env.pool, team.id, parent).await,
+        None => Folder::get_for_user(&env.pool, user.id, parent).await,
+    }
+    .map_err(|err| {
+        tracing::error!(?err, ?parent, "Unable to get folders");
+        InternalServerError(err)
+    })?;
+
+    Ok(FolderListing {
+        folder,
+        path,
+        folders,
+    })
+}
+
+/// Describe a folder for the audit log.
+pub fn folder_audit_details(folder: &Folder) -> serde_json::Value {
+    json!({
+        "name": folder.name,
+        "parent": folder.parent,
+        "public": folder.public,
+        "owner_user": folder.owner_user,
+        "owner_team": folder.owner_team,
+    })
+}
+
+/// Describe the settings of a bundle for the audit log.
+pub fn bundle_audit_details(bundle: &Bundle) -> serde_json::Value {
+    json!({
+        "name": bundle.name,
+        "public": bundle.public,
+        "has_password": bundle.password.is_some(),
+        "limit": bundle.limit,
+        "remaining": bundle.remaining,
+        "expiry_date": bundle.expiry_date.map(|date| date.to_string()),
+        "owner_user": bundle.owner_user,
+        "owner_team": bundle.owner_team,
+    })
+}
+
+/// Describe the settings of an upload for the audit log.
+pub fn upload_audit_details(upload: &Upload) -> serde_json::Value {
+    json!({
+        "filename": upload.filename,
+        "public": upload.public,
+        "has_password": upload.password.is_some(),
+        "limit": upload.limit,
+        "remaining": upload.remaining,
+        "expiry_date": upload.expiry_date.map(|date| date.to_string()),
+        "custom_slug": upload.custom_slug,
+        "folder": upload.folder,
+        "owner_user": upload.owner_user,
+        "owner_team": upload.owner_team,
+    })
+}
+
+/// Describe a change to the settings of an upload for the audit log.
+pub fn upload_change_details(before: &Upload, after: &Upload) -> serde_json::Value {
+    json!({
+        "before": upload_audit_details(before),
+        "after": upload_audit_details(after),
+    })
+}
+
 pub async fn delete_upload_cache(env: &Env, upload: &Upload) {
-    let path = env.cache_dir.join(&upload.slug);
-    tracing::info!(?path, %upload.id, "Deleting cached upload");
-    if let Err(err) = tokio::fs::remove_file(&path).await {
-        tracing::error!(?path, ?err, %upload.id, "Failed to delete cached upload");
+    release_blob(env, &upload.blob).await;
+}
+
+/// Release a reference to a blob, deleting the file and its preview from storage if this was the
+/// last upload that referred to it.
+pub async fn release_blob(env: &Env, key: &str) {
+    let deleted = match Blob::release(&env.pool, key).await {
+        Ok(deleted) => deleted,
+        Err(err) => {
+            tracing::error!(?key, ?err, "Failed to release blob");
+            return;
+        }
+    };
+
+    if !deleted {
+        tracing::info!(?key, "Blob is still referenced by other uploads");
+        return;
+    }
+
+    delete_blob(env, key).await;
+}
+
+/// Delete the file and preview of a blob that is marked as being deleted, and then the blob itself.
+///
+/// The blob is only deleted once its files are gone, so that an upload with the same content does
+/// not store its file under the key while the old one is still being deleted.
+pub async fn delete_blob(env: &Env, key: &str) {
+    tracing::info!(?key, "Deleting cached upload");
+    if let Err(err) = env.storage.delete(key).await {
+        tracing::error!(?key, ?err, "Failed to delete cached upload");
+    }
+
+    let preview_key = format!("{key}.preview");
+    if env.storage.exists(&preview_key).await.unwrap_or(false) {
+        tracing::info!(key = ?preview_key, "Deleting cached upload preview");
+        if let Err(err) = env.storage.delete(&preview_key).await {
+            tracing::error!(key = ?preview_key, err = ?err, "Failed to delete cached upload preview");
+        }
+    }
+
+    if let Err(err) = Blob::delete(&env.pool, key).await {
+        tracing::error!(?key, ?err, "Failed to delete blob");
+    }
+}
+
+/// Move a file from the temporary directory into storage, returning the key of its blob.
+///
+/// If there is already a blob with the same hash, the upload shares that blob and the file is
+/// deleted. Either way, the caller holds a reference to the blob that must be released if the
+/// upload is not created.
+pub async fn store_blob(env: &Env, path: &Path, hash: &str, size: i64) -> poem::Result<String> {
+    // A blob with the same hash that is being deleted is given a few seconds to finish.
+    let mut attempts = 0;
+    let created = loop {
+        let acquired = Blob::acquire(&env.pool, hash, size).await.map_err(|err| {
+            tracing::error!(?err, ?hash, "Unable to acquire blob");
+            InternalServerError(err)
+        })?;
+
+        match acquired {
+            Acquired::Created => break true,
+            Acquired::Shared => break false,
+            Acquired::Deleting if attempts < 50 => {
+                tracing::info!(?hash, "Waiting for blob to be deleted");
+                attempts += 1;
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            }
+            Acquired::Deleting => {
+                tracing::error!(?hash, "Blob is still being deleted");
+                remove_partial_upload(path).await;
+                return Err(poem::Error::from_status(StatusCode::SERVICE_UNAVAILABLE));
+            }
+        }
+    };
+
+    if !created {
+        tracing::info!(?hash, size, "Upload shares an existing blob");
+        remove_partial_upload(path).await;
+        return Ok(hash.to_string());
+    }
+
+    if let Err(err) = env.storage.store(path, hash).await {
+        tracing::error!(?err, ?path, ?hash, "Unable to move upload into storage");
+        remove_partial_upload(path).await;
+        release_blob(env, hash).await;
+        return Err(InternalServerError(err));
     }
 
-    if upload.has_preview {
-        let preview_path = env.cache_dir.join(format!("{}.preview", upload.slug));
-        tracing::info!(?preview_path, %upload.id, "Deleting cached upload preview");
-        if let Err(err) = tokio::fs::remove_file(&preview_path).await {
-            tracing::error!(?preview_path, ?err, %upload.id, "Failed to delete cached upload preview");
+    Ok(hash.to_string())
+}
+
+/// Hash a file in the temporary directory and move it into storage, returning the key of its blob.
+///
+/// Blobs that are stored here are keyed by their hash, so the key is also the hash of the file.
+pub async fn hash_and_store_blob(env: &Env, path: &Path, size: i64) -> poem::Result<String> {
+    let hash = hash_file(path).await.map_err(|err| {
+        tracing::error!(?err, ?path, "Unable to hash file");
+        InternalServerError(err)
+    })?;
+
+    store_blob(env, path, &hash, size).await
+}
+
+/// Get the number of bytes that can still be uploaded to the given team, or to the user when there
+/// is no team.
+///
+/// Space that has been set aside for resumable uploads that have not yet completed is counted as
+/// used. Returns `None` when the owner of the uploads has no limit.
+pub async fn get_remaining_quota(
+    env: &Env,
+    user: &User,
+    team: Option<&Team>,
+) -> poem::Result<Option<i64>> {
+    match team {
+        Some(team) => get_remaining_team_quota(env, team).await,
+        None => get_remaining_user_quota(env, user).await,
+    }
+}
+
+/// Get the number of bytes that can still be uploaded by a user to their own uploads.
+pub async fn get_remaining_user_quota(env: &Env, user: &User) -> poem::Result<Option<i64>> {
+    let Some(limit) = user.limit else {
+        return Ok(None);
+    };
+
+    get_remaining_owner_quota(env, Some(user.id), None, limit)
+        .await
+        .map(Some)
+}
+
+/// Get the number of bytes that can still be uploaded to a team.
+pub async fn get_remaining_team_quota(env: &Env, team: &Team) -> poem::Result<Option<i64>> {
+    let Some(limit) = team.limit else {
+        return Ok(None);
+    };
+
+    get_remaining_owner_quota(env, None, Some(team.id), limit)
+        .await
+        .map(Some)
+}
+
+/// Get the number of bytes that can still be uploaded to the given team, or to the user when there
+/// is no team, out of the given limit.
+async fn get_remaining_owner_quota(
+    env: &Env,
+    owner_user: Option<Key<User>>,
+    owner_team: Option<Key<Team>>,
+    limit: i64,
+) -> poem::Result<i64> {
+    let (stats, reserved) = match (owner_team, owner_user) {
+        (Some(team_id), _) => tokio::join!(
+            UploadStats::get_for_team(&env.pool, team_id),
+            UploadSession::reserved_for_team(&env.pool, team_id)
+        ),
+        (None, Some(user_id)) => tokio::join!(
+            UploadStats::get_for_user(&env.pool, user_id),
+            UploadSession::reserved_for_user(&env.pool, user_id)
+        ),
+        (None, None) => {
+            tracing::error!("Uploads have no owner");
+            return Err(poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR));
         }
+    };
+
+    let stats = stats.map_err(|err| {
+        tracing::error!(?owner_user, ?owner_team, ?err, "Unable to get upload stats");
+        InternalServerError(err)
+    })?;
+
+    let reserved = reserved.map_err(|err| {
+        tracing::error!(
+            ?owner_user,
+            ?owner_team,
+            ?err,
+            "Unable to get reserved size"
+        );
+        InternalServerError(err)
+    })?;
+
+    Ok((limit - stats.size - reserved).max(0))
+}
+
+async fn remove_partial_upload(path: &std::path::Path) {
+    tracing::info!(?path, "Deleting partial upload");
+    if let Err(err) = tokio::fs::remove_file(path).await {
+        tracing::error!(?path, ?err, "Failed to delete partial upload");
     }
 }
 
-pub async fn delete_upload_cache_by_slug(env: &Env, slug: &str) {
-    let path = env.cache_dir.join(slug);
-    tracing::info!(path = ?path, "Deleting cached upload");
+/// Get the path of a new file in the temporary directory, into which a file can be received before
+/// it is moved into storage.
+pub fn new_temp_path(env: &Env) -> PathBuf {
+    env.cache_dir.join("temp").join(nanoid::nanoid!())
+}
+
+/// Get the path of the file that holds the bytes received so far for a resumable upload.
+pub fn upload_session_path(env: &Env, id: Key<UploadSession>) -> PathBuf {
+    env.cache_dir.join("temp").join(id.to_string())
+}
+
+pub async fn delete_upload_session_file(env: &Env, id: Key<UploadSession>) {
+    let path = upload_session_path(env, id);
+    tracing::info!(?path, %id, "Deleting partial resumable upload");
     if let Err(err) = tokio::fs::remove_file(&path).await {
-        tracing::error!(path = ?path, err = ?err, "Failed to delete cached upload");
+        tracing::error!(?path, ?err, %id, "Failed to delete partial resumable upload");
+    }
+}
+
+/// Stream a multipart file field into a new file in storage.
+///
+/// If a `quota` is given, it is the number of bytes that can still be uploaded. The quota is
+/// reduced by the size of the file, and if the file would exceed the quota the upload is stopped
+/// part way through with a [`QuotaExceededError`]. This only saves receiving the rest of a file
+/// that cannot fit: the quota is checked again when the uploads are inserted, which also accounts
+/// for any other uploads that were received at the same time.
+///
+/// If the file is uploaded through an upload `request`, it must be no larger than the maximum size
+/// of the request, and a larger file is stopped part way through with a [`FileTooLargeError`]. If
+/// the request only allows some types of file, the type is found from the contents of the file
+/// rather than the type given by the client, and any other type is rejected with a
+/// [`FileTypeNotAllowedError`].
+///
+/// Returns `None` if the stream could not be copied to the file, such as when the client
+/// disconnects part way through an upload. In all of these cases the partial file is deleted.
+pub async fn cache_upload_field(
+    env: &Env,
+    field: Field,
+    quota: &mut Option<i64>,
+    request: Option<&UploadRequest>,
+) -> poem::Result<Option<PendingUpload>> {
+    let filename = field
+        .file_name()
+        .map(ToString::to_string)
+        .unwrap_or_else(|| "unnamed.ext".to_string());
+
+    let slug = nanoid::nanoid!();
+    let path = new_temp_path(env);
+    let max_size = request.and_then(|request| request.max_size);
+
+    // Read at most one byte more than the remaining quota or the largest allowed file, so that we
+    // can tell when an upload would exceed them without having to read the rest of the stream.
+    let take = (*quota)
+        .into_iter()
+        .chain(max_size)
+        .min()
+        .map_or(u64::MAX, |limit| limit as u64 + 1);
+    let mut field = HashReader::new(field.into_async_read().take(take));
+
+    let size = {
+        let mut file = tokio::fs::File::create(&path).await.map_err(|err| {
+            tracing::error!(?err, ?path, "Unable to create file");
+            InternalServerError(err)
+        })?;
+
+        match tokio::io::copy(&mut field, &mut file).await {
+            Ok(size) => size as i64,
+            Err(err) => {
+                tracing::error!(?err, ?path, "Unable to copy from stream to file");
+                remove_partial_upload(&path).await;
+                return Ok(None);
+            }
+        }
+    };
+
+    if let Some(max_size) = max_size.filter(|max_size| size > *max_size) {
+        tracing::info!(?slug, max_size, "Upload exceeds maximum file size");
+        remove_partial_upload(&path).await;
+        return Err(FileTooLargeError { max_size }.into());
+    }
+
+    if let Some(remaining) = quota {
+        if size > *remaining {
+            tracing::info!(
+                ?slug,
+                remaining = *remaining,
+                "Upload exceeds remaining quota"
+            );
+            remove_partial_upload(&path).await;
+            return Err(QuotaExceededError {
+                remaining: *remaining,
+            }
+            .into());
+        }
+
+        *remaining -= size;
     }
 
-    let preview_path = env.cache_dir.join(format!("{slug}.preview"));
-    if preview_path.exists() {
-        tracing::info!(path = ?preview_path, "Deleting cached upload preview");
-        if let Err(err) = tokio::fs::remove_file(&preview_path).await {
-            tracing::error!(path = ?preview_path, err = ?err, "Failed to delete cached upload preview");
+    if let Some(request) = request.filter(|request| request.mime_types.is_some()) {
+        let mime_type = match detect_mime_type(&path).await {
+            Ok(mime_type) => mime_type.unwrap_or_else(|| "application/octet-stream".to_string()),
+            Err(err) => {
+                tracing::error!(?err, ?path, "Unable to detect MIME type of upload");
+                remove_partial_upload(&path).await;
+                return Err(InternalServerError(err));
+            }
+        };
+
+        if !request.allows_mime_type(&mime_type) {
+            tracing::info!(?slug, ?mime_type, "File type not allowed by upload request");
+            remove_partial_upload(&path).await;
+            return Err(FileTypeNotAllowedError { mime_type }.into());
         }
     }
+
+    let hash = field.finish();
+    let blob = store_blob(env, &path, &hash, size).await?;
+    tracing::info!(?slug, ?hash, size, "Upload to cache complete");
+
+    Ok(Some(PendingUpload {
+        id: Key::<Upload>::new(),
+        slug,
+        blob,
+        hash: Some(hash),
+        filename,
+        size,
+    }))
+}
+
+/// Release the blobs for uploads that are not going to be inserted into the database.
+pub async fn discard_pending_uploads(env: &Env, uploads: &[PendingUpload]) {
+    for upload in uploads {
+        tracing::info!(slug = ?upload.slug, blob = ?upload.blob, "Discarding pending upload");
+        release_blob(env, &upload.blob).await;
+    }
+}
+
+/// Insert the pending uploads into the database, owned by either the given team or the user.
+///
+/// Unless `reserved` is set, the uploads must fit in the remaining quota of their owner. Resumable
+/// uploads set aside their space when the session is created, so they are not checked again.
+pub async fn insert_pending_uploads(
+    env: &Env,
+    user: &User,
+    team: Option<&Team>,
+    remote_addr: Option<String>,
+    uploads: &[PendingUpload],
+    reserved: bool,
+) -> poem::Result<Vec<Key<Upload>>> {
+    let (owner_user, owner_team, limit) = match team {
+        Some(team) => (None, Some(team.id), team.limit),
+        None => (Some(user.id), None, user.limit),
+    };
+
+    insert_uploads(
+        env,
+        owner_user,
+        owner_team,
+        Some(user.id),
+        remote_addr,
+        uploads,
+        limit.filter(|_| !reserved),
+    )
+    .await
+}
+
+/// Insert the pending uploads into the database for the given owner.
+///
+/// The `uploaded_by` user is `None` for uploads that were received through an upload request,
+/// where the person uploading the files has not signed in.
+///
+/// When a `limit` is given, the uploads are only inserted if they fit in the space that the owner
+/// has left, including any space reserved by resumable uploads. This is checked in the same
+/// statement that inserts the uploads, so that concurrent uploads cannot exceed the limit between
+/// them. Otherwise, none of the uploads are inserted and a [`QuotaExceededError`] is returned.
+///
+/// An `upload.created` event is queued for each upload, for any webhooks of the owner.
+pub async fn insert_uploads(
+    env: &Env,
+    owner_user: Option<Key<User>>,
+    owner_team: Option<Key<Team>>,
+    uploaded_by: Option<Key<User>>,
+    remote_addr: Option<String>,
+    uploads: &[PendingUpload],
+    limit: Option<i64>,
+) -> poem::Result<Vec<Key<Upload>>> {
+    let upload_ids: Vec<Key<Upload>> = sqlx::query_scalar(
+        "\
+        WITH data AS ( \
+            SELECT value ->> 'id' AS id, \
+                   value ->> 'slug' AS slug, \
+                   value ->> 'blob' AS blob, \
+                   value ->> 'hash' AS hash, \
+                   value ->> 'filename' AS filename, \
+                   (value ->> 'size') AS size \
+            FROM json_each($1)) \
+        INSERT INTO uploads \
+        (id, slug, blob, hash, filename, size, public, downloads, \
+         owner_user, owner_team, \
+         uploaded_at, uploaded_by, remote_addr) \
+        SELECT data.id, data.slug, data.blob, data.hash, data.filename, data.size, 0, 0, \
+               $2, $3, \
+               $4, $5, $6 \
+        FROM data \
+        WHERE $7 IS NULL OR $7 >= (SELECT COALESCE(SUM(size), 0) FROM data) \
+            + (SELECT COALESCE(SUM(size), 0) FROM uploads \
+               WHERE owner_user IS $2 AND owner_team IS $3) \
+            + (SELECT COALESCE(SUM(length), 0) FROM upload_sessions \
+               WHERE owner_user IS $2 AND owner_team IS $3 AND expires_at >= $4) \
+        RETURNING id",
+    )
+    .bind(serde_json::to_string(uploads).map_err(|err| {
+        tracing::error!(?err, "Failed to serialize uploads to JSON");
+        InternalServerError(err)
+    })?)
+    .bind(owner_user)
+    .bind(owner_team)
+    .bind(OffsetDateTime::now_utc())
+    .bind(uploaded_by)
+    .bind(remote_addr)
+    .bind(limit)
+    .fetch_all(&env.pool)
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "Unable to insert uploads");
+        InternalServerError(err)
+    })?;
+
+    if let Some(limit) = limit.filter(|_| upload_ids.is_empty() && !uploads.is_empty()) {
+        let remaining = get_remaining_owner_quota(env, owner_user, owner_team, limit).await?;
+        tracing::info!(
+            ?owner_user,
+            ?owner_team,
+            remaining,
+            "Uploads exceed remaining quota"
+        );
+
+        return Err(QuotaExceededError { remaining }.into());
+    }
+
+    queue_uploads_created(env, owner_user, owner_team, &upload_ids).await;
+    Ok(upload_ids)
+}
+
+/// The number of days of downloads that are shown in the download charts.
+const DOWNLOAD_CHART_DAYS: i64 = 30;
+
+/// The downloads in a scope over the last [`DOWNLOAD_CHART_DAYS`] days, used to draw a chart.
+#[derive(Debug, Serialize)]
+pub struct DownloadActivity {
+    pub stats: DownloadStats,
+    /// The downloads on each day, including the days without any downloads.
+    pub days: Vec<DownloadDay>,
+    /// The most downloads on any one day, which is used to scale the chart.
+    pub peak: i64,
+}
+
+pub async fn get_download_activity(
+    env: &Env,
+    scope: DownloadScope,
+) -> poem::Result<DownloadActivity> {
+    let today = OffsetDateTime::now_utc().date();
+    let first = today - Duration::days(DOWNLOAD_CHART_DAYS - 1);
+    let since = first.midnight().assume_utc();
+
+    let stats = DownloadStats::get(&env.pool, scope, since)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, ?scope, "Unable to get download statistics");
+            InternalServerError(err)
+        })?;
+
+    let recorded = DownloadDay::get(&env.pool, scope, since)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, ?scope, "Unable to get downloads for each day");
+            InternalServerError(err)
+        })?;
+
+    let days = (0..DOWNLOAD_CHART_DAYS)
+        .map(|offset| {
+            let day = first + Duration::days(offset);
+            recorded
+                .iter()
+                .find(|recorded| recorded.day == day)
+                .cloned()
+                .unwrap_or(DownloadDay {
+                    day,
+                    downloads: 0,
+                    completed: 0,
+                    bytes_sent: 0,
+                })
+        })
+        .collect::<Vec<_>>();
+
+    let peak = days.iter().map(|day| day.downloads).max().unwrap_or(0);
+
+    Ok(DownloadActivity { stats, days, peak })
 }
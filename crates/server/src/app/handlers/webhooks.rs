@@ -0,0 +1,359 @@
+use minijinja::context;
+use poem::{
+    error::InternalServerError,
+    handler,
+    http::StatusCode,
+    web::{CsrfToken, CsrfVerifier, Data, Html, Path, Query},
+};
+use serde::Deserialize;
+use serde_json::json;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use parcel_model::{
+    audit::{AuditAction, AuditTarget},
+    team::{Team, TeamMember},
+    types::Key,
+    user::User,
+    webhook::{Webhook, WebhookDelivery, WebhookEvent},
+};
+
+use crate::{
+    app::{
+        errors::CsrfError,
+        extractors::{audit::Auditor, user::SessionUser},
+        handlers::utils::get_team_for_member,
+        templates::{authorized_context, render_template},
+    },
+    env::Env,
+    workers::webhooks::is_allowed_url,
+};
+
+/// The number of deliveries shown in each page of the delivery log of a webhook.
+const PAGE_SIZE: u32 = 50;
+
+/// Describe a webhook for the audit log.
+fn webhook_audit_details(webhook: &Webhook) -> serde_json::Value {
+    json!({
+        "name": webhook.name,
+        "url": webhook.url,
+        "events": webhook.events,
+        "owner_user": webhook.owner_user,
+        "owner_team": webhook.owner_team,
+    })
+}
+
+/// Get the team that the webhooks are for, checking that the user can configure the team.
+async fn get_team_for_webhooks(
+    env: &Env,
+    user: &User,
+    team: Option<Key<Team>>,
+) -> poem::Result<Option<Team>> {
+    let Some(team_id) = team else {
+        return Ok(None);
+    };
+
+    let team = get_team_for_member(env, user, team_id).await?;
+    let membership = TeamMember::get_for_user_and_team(&env.pool, user.id, team.id)
+        .await
+        .map_err(|err| {
+            tracing::error!(%user.id, %team.id, ?err, "Unable to get team membership");
+            InternalServerError(err)
+        })?;
+
+    if !membership.is_some_and(|membership| membership.can_config) {
+        tracing::error!(%user.id, %team.id, "User cannot manage webhooks for team");
+        return Err(poem::Error::from_status(StatusCode::FORBIDDEN));
+    }
+
+    Ok(Some(team))
+}
+
+/// Get a webhook, checking that the user owns it or can configure the team that owns it.
+async fn get_owned_webhook(
+    env: &Env,
+    user: &User,
+    id: Key<Webhook>,
+) -> poem::Result<(Webhook, Option<Team>)> {
+    let Some(webhook) = Webhook::get(&env.pool, id).await.map_err(|err| {
+        tracing::error!(?err, %id, "Unable to get webhook by ID");
+        InternalServerError(err)
+    })?
+    else {
+        tracing::error!(%id, "Webhook not found");
+        return Err(poem::Error::from_status(StatusCode::NOT_FOUND));
+    };
+
+    let team = get_team_for_webhooks(env, user, webhook.owner_team).await?;
+    if team.is_none() && webhook.owner_user != Some(user.id) {
+        tracing::error!(%user.id, %webhook.id, "User does not own webhook");
+        return Err(poem::Error::from_status(StatusCode::FORBIDDEN));
+    }
+
+    Ok((webhook, team))
+}
+
+/// Render the modal that lists the webhooks of a user or team.
+///
+/// The `form` is given when adding a new webhook failed, along with the validation errors.
+async fn render_webhooks(
+    env: &Env,
+    user: &User,
+    csrf_token: &CsrfToken,
+    team: Option<Team>,
+    immediate: bool,
+    form: Option<(minijinja::Value, ValidationErrors)>,
+) -> poem::Result<Html<String>> {
+    let webhooks = match team {
+        Some(ref team) => Webhook::get_for_team(&env.pool, team.id).await,
+        None => Webhook::get_for_user(&env.pool, user.id).await,
+    }
+    .map_err(|err| {
+        tracing::error!(?err, %user.id, "Unable to get webhooks");
+        InternalServerError(err)
+    })?;
+
+    let events = WebhookEvent::ALL
+        .iter()
+        .map(|event| {
+            context! {
+                name => event.as_str(),
+                description => event.description(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let (form, errors) = form.unzip();
+
+    render_template(
+        "webhooks/list.html",
+        context! {
+            team,
+            immediate,
+            webhooks,
+            events,
+            form,
+            errors,
+            csrf_token => csrf_token.0,
+            ..authorized_context(env, user)
+        },
+    )
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebhooksQuery {
+    #[serde(default)]
+    team: Option<Key<Team>>,
+    #[serde(default)]
+    immediate: bool,
+}
+
+#[handler]
+pub async fn get_webhooks(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    csrf_token: &CsrfToken,
+    Query(WebhooksQuery { team, immediate }): Query<WebhooksQuery>,
+) -> poem::Result<Html<String>> {
+    let team = get_team_for_webhooks(&env, &user, team).await?;
+    render_webhooks(&env, &user, csrf_token, team, immediate, None).await
+}
+
+fn validate_webhook_url(url: &str) -> Result<(), ValidationError> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(ValidationError::new("invalid_webhook_url")
+            .with_message("The URL of a webhook must use HTTP or HTTPS".into()));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct NewWebhookForm {
+    csrf_token: String,
+    #[serde(default)]
+    team: Option<Key<Team>>,
+    #[validate(length(min = 1, max = 100))]
+    name: String,
+    #[validate(url(message = "The URL is not valid"))]
+    #[validate(custom(function = "validate_webhook_url"))]
+    url: String,
+    #[serde(default)]
+    #[validate(length(min = 1, message = "Choose at least one event to send to the webhook"))]
+    events: Vec<WebhookEvent>,
+}
+
+#[handler]
+pub async fn post_webhooks(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    auditor: Auditor,
+    csrf_token: &CsrfToken,
+    csrf_verifier: &CsrfVerifier,
+    body: String,
+) -> poem::Result<Html<String>> {
+    // The events are sent as repeated fields, which the `Form` extractor does not support.
+    let form = serde_html_form::from_str::<NewWebhookForm>(&body).map_err(|err| {
+        tracing::error!(?err, %user.id, "Unable to parse new webhook form");
+        poem::Error::from_status(StatusCode::BAD_REQUEST)
+    })?;
+
+    if !csrf_verifier.is_valid(&form.csrf_token) {
+        tracing::warn!(%user.id, "CSRF verification failed for webhook creation");
+        return Err(CsrfError.into());
+    }
+
+    let team = get_team_for_webhooks(&env, &user, form.team).await?;
+
+    // Whether the URL is on a private network depends on the hosts that have been allowed, so it is
+    // checked here rather than by the validator.
+    let mut errors = form.validate().err().unwrap_or_default();
+    if !is_allowed_url(&env.webhook_allowed_hosts, form.url.trim()) {
+        errors.add(
+            "url",
+            ValidationError::new("private_webhook_url")
+                .with_message("Webhooks cannot be sent to a private network".into()),
+        );
+    }
+
+    if !errors.is_empty() {
+        let form = context! {
+            name => form.name,
+            url => form.url,
+            events => form.events.iter().map(WebhookEvent::as_str).collect::<Vec<_>>(),
+        };
+
+        return render_webhooks(&env, &user, csrf_token, team, true, Some((form, errors))).await;
+    }
+
+    let webhook = Webhook::new(
+        form.name.trim(),
+        form.url.trim(),
+        &form.events,
+        if team.is_none() { Some(user.id) } else { None },
+        team.as_ref().map(|team| team.id),
+        user.id,
+    );
+
+    webhook.create(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %user.id, "Failed to create webhook");
+        InternalServerError(err)
+    })?;
+
+    tracing::info!(%webhook.id, ?webhook.owner_team, "Created webhook");
+    auditor
+        .record(
+            &env,
+            &user,
+            AuditAction::WebhookCreate,
+            AuditTarget::Webhook(webhook.id),
+            Some(webhook_audit_details(&webhook)),
+        )
+        .await;
+
+    render_webhooks(&env, &user, csrf_token, team, true, None).await
+}
+
+#[handler]
+pub async fn get_webhook(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    Path(id): Path<Key<Webhook>>,
+) -> poem::Result<Html<String>> {
+    let (webhook, team) = get_owned_webhook(&env, &user, id).await?;
+    let deliveries = WebhookDelivery::get_for_webhook(&env.pool, webhook.id, 0, PAGE_SIZE)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %webhook.id, "Unable to get deliveries of webhook");
+            InternalServerError(err)
+        })?;
+
+    let page_url = format!("/webhooks/{}/deliveries", webhook.id);
+
+    render_template(
+        "webhooks/view.html",
+        context! {
+            webhook,
+            team,
+            deliveries,
+            page => 0,
+            page_url,
+            ..authorized_context(&env, &user)
+        },
+    )
+    .await
+}
+
+#[handler]
+pub async fn get_deliveries_page(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    Path((id, page)): Path<(Key<Webhook>, u32)>,
+) -> poem::Result<Html<String>> {
+    let (webhook, _) = get_owned_webhook(&env, &user, id).await?;
+    let deliveries = WebhookDelivery::get_for_webhook(
+        &env.pool,
+        webhook.id,
+        page * PAGE_SIZE,
+        PAGE_SIZE,
+    )
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, %webhook.id, page, "Unable to get page of webhook deliveries");
+        InternalServerError(err)
+    })?;
+
+    render_template(
+        "webhooks/page.html",
+        context! {
+            deliveries,
+            page,
+            page_url => format!("/webhooks/{}/deliveries", webhook.id),
+            ..authorized_context(&env, &user)
+        },
+    )
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteWebhookQuery {
+    csrf_token: String,
+}
+
+#[handler]
+pub async fn delete_webhook(
+    env: Data<&Env>,
+    SessionUser(user): SessionUser,
+    auditor: Auditor,
+    csrf_token: &CsrfToken,
+    csrf_verifier: &CsrfVerifier,
+    Path(id): Path<Key<Webhook>>,
+    Query(DeleteWebhookQuery {
+        csrf_token: query_token,
+    }): Query<DeleteWebhookQuery>,
+) -> poem::Result<Html<String>> {
+    if !csrf_verifier.is_valid(&query_token) {
+        tracing::warn!(%user.id, %id, "CSRF verification failed for webhook deletion");
+        return Err(CsrfError.into());
+    }
+
+    let (webhook, team) = get_owned_webhook(&env, &user, id).await?;
+
+    webhook.delete(&env.pool).await.map_err(|err| {
+        tracing::error!(?err, %webhook.id, "Failed to delete webhook");
+        InternalServerError(err)
+    })?;
+
+    tracing::info!(%webhook.id, "Deleted webhook");
+    auditor
+        .record(
+            &env,
+            &user,
+            AuditAction::WebhookDelete,
+            AuditTarget::Webhook(webhook.id),
+            Some(webhook_audit_details(&webhook)),
+        )
+        .await;
+
+    render_webhooks(&env, &user, csrf_token, team, true, None).await
+}
@@ -1,6 +1,6 @@
 use minijinja::{
     value::{Kwargs, ValueKind},
-    Environment, Error, ErrorKind, Value,
+    Environment, Error, ErrorKind, HtmlEscape, Value,
 };
 use serde::{de::value::SeqDeserializer, Deserialize};
 use time::{
@@ -240,6 +240,23 @@ fn filter_script_bundle(name: String) -> Result<String, Error> {
     Ok(format!("{PREFIX}{name}{EXTENSION}"))
 }
 
+/// Filter to highlight the matches in a search snippet.
+///
+/// The snippets produced by the search index mark the start and end of each match with the `STX`
+/// and `ETX` control characters. The snippet is escaped and these markers are replaced with `<mark>`
+/// elements. If the snippet does not contain any matches, an empty string is returned.
+fn filter_highlight(value: String) -> Value {
+    if !value.contains('\u{2}') {
+        return Value::from("");
+    }
+
+    let escaped = HtmlEscape(&value).to_string();
+    let marked = escaped
+        .replace('\u{2}', "<mark>")
+        .replace('\u{3}', "</mark>");
+    Value::from_safe_string(marked)
+}
+
 fn filter_nearest_unit(value: usize, kwargs: Kwargs) -> Result<String, Error> {
     // Given the size in bytes, return the nearest unit (MB, GB, etc.).
     static UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB", "PB", "EB"];
@@ -304,6 +321,7 @@ pub(super) fn add_to_environment(environment: &mut Environment) {
     environment.add_filter("filesizeformat", filter_filesizeformat);
     environment.add_filter("nearest_unit", filter_nearest_unit);
     environment.add_filter("script_bundle", filter_script_bundle);
+    environment.add_filter("highlight", filter_highlight);
     environment.add_test("past", test_past);
     environment.add_test("future", test_future);
     environment.add_function("unit_multiplier", func_unit_multiplier);
@@ -0,0 +1,468 @@
+//! Streaming ZIP archives
+//!
+//! Bundles of uploads are downloaded as a single ZIP archive that is built on the fly from the
+//! files in storage, without writing the archive anywhere first. The files are already compressed
+//! more often than not, so they are stored in the archive without compression. This means that the
+//! layout, and therefore the length, of the archive is known before any of it is sent: only the
+//! CRC-32 of each file has to be computed as it is read, which is written in a data descriptor
+//! after the file.
+//!
+//! ZIP64 records are only used when they are needed, which is when a file or the archive is larger
+//! than 4 GiB, or when there are more than 65,535 files.
+
+use std::{
+    collections::HashSet,
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use time::OffsetDateTime;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream, ReadBuf};
+
+use crate::{env::Env, storage::Storage};
+
+/// The size of the buffer between the task that writes the archive and the response body.
+const PIPE_SIZE: usize = 64 * 1024;
+
+/// Values at or above this limit must be written in a ZIP64 extra field.
+const ZIP64_LIMIT: u64 = 0xFFFF_FFFF;
+
+/// General purpose flags: sizes and CRC are in the data descriptor (bit 3), names are UTF-8 (bit 11).
+const FLAGS: u16 = 0x0808;
+
+/// A file to include in an archive.
+#[derive(Debug, Clone)]
+pub struct ZipEntry {
+    /// The name of the file in the archive.
+    pub name: String,
+    /// The key of the file in storage.
+    pub key: String,
+    pub size: u64,
+    pub modified: OffsetDateTime,
+}
+
+impl ZipEntry {
+    fn zip64(&self, offset: u64) -> bool {
+        self.size >= ZIP64_LIMIT || offset >= ZIP64_LIMIT
+    }
+
+    fn version(zip64: bool) -> u16 {
+        if zip64 {
+            45
+        } else {
+            20
+        }
+    }
+
+    /// The time and date of the file in MS-DOS format.
+    fn dos_datetime(&self) -> (u16, u16) {
+        let modified = self.modified;
+        if modified.year() < 1980 {
+            return (0, (1 << 5) | 1);
+        }
+
+        let time = ((modified.hour() as u16) << 11)
+            | ((modified.minute() as u16) << 5)
+            | (modified.second() as u16 / 2);
+        let date = (((modified.year() - 1980).min(127) as u16) << 9)
+            | ((u8::from(modified.month()) as u16) << 5)
+            | modified.day() as u16;
+
+        (time, date)
+    }
+
+    fn local_header(&self, offset: u64) -> Vec<u8> {
+        let zip64 = self.zip64(offset);
+        let (time, date) = self.dos_datetime();
+        let mut header = Vec::with_capacity(50 + self.name.len());
+
+        header.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        header.extend_from_slice(&Self::version(zip64).to_le_bytes());
+        header.extend_from_slice(&FLAGS.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&time.to_le_bytes());
+        header.extend_from_slice(&date.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+
+        let size = if zip64 { 0xFFFF_FFFFu32 } else { 0 };
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&(self.name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&(if zip64 { 20u16 } else { 0 }).to_le_bytes());
+        header.extend_from_slice(self.name.as_bytes());
+
+        if zip64 {
+            header.extend_from_slice(&0x0001u16.to_le_bytes());
+            header.extend_from_slice(&16u16.to_le_bytes());
+            header.extend_from_slice(&0u64.to_le_bytes());
+            header.extend_from_slice(&0u64.to_le_bytes());
+        }
+
+        header
+    }
+
+    fn data_descriptor(&self, offset: u64, crc: u32) -> Vec<u8> {
+        let mut descriptor = Vec::with_capacity(24);
+        descriptor.extend_from_slice(&0x0807_4b50u32.to_le_bytes());
+        descriptor.extend_from_slice(&crc.to_le_bytes());
+
+        if self.zip64(offset) {
+            descriptor.extend_from_slice(&self.size.to_le_bytes());
+            descriptor.extend_from_slice(&self.size.to_le_bytes());
+        } else {
+            descriptor.extend_from_slice(&(self.size as u32).to_le_bytes());
+            descriptor.extend_from_slice(&(self.size as u32).to_le_bytes());
+        }
+
+        descriptor
+    }
+
+    fn central_header(&self, offset: u64, crc: u32) -> Vec<u8> {
+        let zip64 = self.zip64(offset);
+        let (time, date) = self.dos_datetime();
+        let mut header = Vec::with_capacity(74 + self.name.len());
+
+        header.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        header.extend_from_slice(&Self::version(zip64).to_le_bytes());
+        header.extend_from_slice(&Self::version(zip64).to_le_bytes());
+        header.extend_from_slice(&FLAGS.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&time.to_le_bytes());
+        header.extend_from_slice(&date.to_le_bytes());
+        header.extend_from_slice(&crc.to_le_bytes());
+
+        let (size, local_offset) = if zip64 {
+            (0xFFFF_FFFFu32, 0xFFFF_FFFFu32)
+        } else {
+            (self.size as u32, offset as u32)
+        };
+
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&(self.name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&(if zip64 { 28u16 } else { 0 }).to_le_bytes());
+        // File comment length, disk number, internal and external attributes.
+        header.extend_from_slice(&[0; 10]);
+        header.extend_from_slice(&local_offset.to_le_bytes());
+        header.extend_from_slice(self.name.as_bytes());
+
+        if zip64 {
+            header.extend_from_slice(&0x0001u16.to_le_bytes());
+            header.extend_from_slice(&24u16.to_le_bytes());
+            header.extend_from_slice(&self.size.to_le_bytes());
+            header.extend_from_slice(&self.size.to_le_bytes());
+            header.extend_from_slice(&offset.to_le_bytes());
+        }
+
+        header
+    }
+}
+
+/// A ZIP archive of files in storage, which is written as it is read.
+#[derive(Debug)]
+pub struct ZipArchive {
+    entries: Vec<ZipEntry>,
+    /// The offset of the local header of each entry.
+    offsets: Vec<u64>,
+    /// The offset of the central directory.
+    directory_offset: u64,
+    length: u64,
+}
+
+impl ZipArchive {
+    /// Lay out an archive of the given files.
+    ///
+    /// Any files with the same name are renamed, so that every file in the archive has a unique
+    /// name.
+    pub fn new(mut entries: Vec<ZipEntry>) -> Self {
+        let mut names = HashSet::new();
+        for entry in &mut entries {
+            entry.name = unique_name(&mut names, &entry.name);
+        }
+
+        let mut offsets = Vec::with_capacity(entries.len());
+        let mut offset = 0;
+        for entry in &entries {
+            offsets.push(offset);
+            offset += entry.local_header(offset).len() as u64
+                + entry.size
+                + entry.data_descriptor(offset, 0).len() as u64;
+        }
+
+        let directory_offset = offset;
+        let directory_size = entries
+            .iter()
+            .zip(&offsets)
+            .map(|(entry, offset)| entry.central_header(*offset, 0).len() as u64)
+            .sum::<u64>();
+
+        let mut archive = Self {
+            entries,
+            offsets,
+            directory_offset,
+            length: 0,
+        };
+
+        archive.length = directory_offset
+            + directory_size
+            + archive.end_of_directory(directory_size).len() as u64;
+        archive
+    }
+
+    /// The length of the whole archive in bytes.
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn end_of_directory(&self, directory_size: u64) -> Vec<u8> {
+        let count = self.entries.len() as u64;
+        let zip64 = count >= 0xFFFF
+            || directory_size >= ZIP64_LIMIT
+            || self.directory_offset >= ZIP64_LIMIT;
+        let mut record = Vec::with_capacity(98);
+
+        if zip64 {
+            let end_offset = self.directory_offset + directory_size;
+
+            // The ZIP64 end of central directory record.
+            record.extend_from_slice(&0x0606_4b50u32.to_le_bytes());
+            record.extend_from_slice(&44u64.to_le_bytes());
+            record.extend_from_slice(&45u16.to_le_bytes());
+            record.extend_from_slice(&45u16.to_le_bytes());
+            record.extend_from_slice(&0u32.to_le_bytes());
+            record.extend_from_slice(&0u32.to_le_bytes());
+            record.extend_from_slice(&count.to_le_bytes());
+            record.extend_from_slice(&count.to_le_bytes());
+            record.extend_from_slice(&directory_size.to_le_bytes());
+            record.extend_from_slice(&self.directory_offset.to_le_bytes());
+
+            // The ZIP64 end of central directory locator.
+            record.extend_from_slice(&0x0706_4b50u32.to_le_bytes());
+            record.extend_from_slice(&0u32.to_le_bytes());
+            record.extend_from_slice(&end_offset.to_le_bytes());
+            record.extend_from_slice(&1u32.to_le_bytes());
+        }
+
+        record.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        record.extend_from_slice(&0u16.to_le_bytes());
+        record.extend_from_slice(&0u16.to_le_bytes());
+
+        let count = count.min(0xFFFF) as u16;
+        record.extend_from_slice(&count.to_le_bytes());
+        record.extend_from_slice(&count.to_le_bytes());
+        record.extend_from_slice(&(directory_size.min(ZIP64_LIMIT) as u32).to_le_bytes());
+        record.extend_from_slice(&(self.directory_offset.min(ZIP64_LIMIT) as u32).to_le_bytes());
+        record.extend_from_slice(&0u16.to_le_bytes());
+
+        record
+    }
+
+    /// Start writing the archive in the background, returning a reader for its contents.
+    ///
+    /// If a file cannot be read from storage, or does not have the expected size, the archive is
+    /// cut short, so that the client sees an incomplete download rather than a corrupt archive.
+    pub fn stream(self, env: Env) -> DuplexStream {
+        let (reader, writer) = tokio::io::duplex(PIPE_SIZE);
+
+        tokio::spawn(async move {
+            if let Err(err) = self.write(&env.storage, writer).await {
+                tracing::info!(?err, "Stopped writing archive");
+            }
+        });
+
+        reader
+    }
+
+    async fn write<W: AsyncWrite + Unpin>(
+        self,
+        storage: &Storage,
+        mut writer: W,
+    ) -> io::Result<()> {
+        let mut crcs = Vec::with_capacity(self.entries.len());
+
+        for (entry, &offset) in self.entries.iter().zip(&self.offsets) {
+            writer.write_all(&entry.local_header(offset)).await?;
+
+            let reader = storage.read(&entry.key).await.inspect_err(|err| {
+                tracing::error!(key = ?entry.key, ?err, "Unable to open file for archive");
+            })?;
+
+            let mut reader = CrcReader::new(reader.take(entry.size));
+            let copied = tokio::io::copy(&mut reader, &mut writer).await?;
+            if copied != entry.size {
+                tracing::error!(
+                    key = ?entry.key,
+                    copied,
+                    size = entry.size,
+                    "File in storage is shorter than expected"
+                );
+
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+
+            let crc = reader.finish();
+            writer
+                .write_all(&entry.data_descriptor(offset, crc))
+                .await?;
+            crcs.push(crc);
+        }
+
+        let mut directory_size = 0;
+        for ((entry, &offset), &crc) in self.entries.iter().zip(&self.offsets).zip(&crcs) {
+            let header = entry.central_header(offset, crc);
+            directory_size += header.len() as u64;
+            writer.write_all(&header).await?;
+        }
+
+        writer
+            .write_all(&self.end_of_directory(directory_size))
+            .await?;
+        writer.shutdown().await
+    }
+}
+
+/// Make a file name safe to use in an archive, and different from the names already used.
+fn unique_name(names: &mut HashSet<String>, name: &str) -> String {
+    let name = name.replace(['/', '\\'], "_");
+    let name = if name.is_empty() {
+        "file".to_string()
+    } else {
+        name
+    };
+
+    let (stem, extension) = match name.rfind('.') {
+        Some(index) if index > 0 => name.split_at(index),
+        _ => (name.as_str(), ""),
+    };
+
+    let mut candidate = name.clone();
+    let mut counter = 1;
+    while names.contains(&candidate) {
+        counter += 1;
+        candidate = format!("{stem} ({counter}){extension}");
+    }
+
+    names.insert(candidate.clone());
+    candidate
+}
+
+/// A reader that computes the CRC-32 of the bytes that are read through it.
+struct CrcReader<R> {
+    inner: R,
+    hasher: crc32fast::Hasher,
+}
+
+impl<R> CrcReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: crc32fast::Hasher::new(),
+        }
+    }
+
+    fn finish(self) -> u32 {
+        self.hasher.finalize()
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CrcReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let start = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        self.hasher.update(&buf.filled()[start..]);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Cursor, Read},
+        path::PathBuf,
+    };
+
+    use super::*;
+    use crate::storage::LocalStorage;
+
+    /// Put each of the given files into a new local storage directory, returning the storage and
+    /// the entries for an archive of the files.
+    async fn store_files(files: &[(&str, &[u8])]) -> (PathBuf, Storage, Vec<ZipEntry>) {
+        let root = std::env::temp_dir().join(format!("parcel-archive-{}", nanoid::nanoid!()));
+        tokio::fs::create_dir_all(&root).await.unwrap();
+
+        let mut entries = Vec::new();
+        for (index, (name, content)) in files.iter().enumerate() {
+            let key = format!("file-{index}");
+            tokio::fs::write(root.join(&key), content).await.unwrap();
+            entries.push(ZipEntry {
+                name: name.to_string(),
+                key,
+                size: content.len() as u64,
+                modified: OffsetDateTime::now_utc(),
+            });
+        }
+
+        let storage = Storage::Local(LocalStorage::new(root.clone()));
+        (root, storage, entries)
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let files: [(&str, &[u8]); 4] = [
+            ("report.txt", b"The first report.\n"),
+            ("report.txt", b"A second report with the same name.\n"),
+            ("notes/today.md", b"# Today\n"),
+            ("", b""),
+        ];
+
+        let (root, storage, entries) = store_files(&files).await;
+        let archive = ZipArchive::new(entries);
+        let length = archive.len();
+
+        let mut bytes = Vec::new();
+        archive.write(&storage, &mut bytes).await.unwrap();
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+        assert_eq!(bytes.len() as u64, length);
+
+        let names = ["report.txt", "report (2).txt", "notes_today.md", "file"];
+        let mut zip = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(zip.len(), files.len());
+
+        for (index, ((_, content), name)) in files.iter().zip(names).enumerate() {
+            let mut file = zip.by_index(index).unwrap();
+            assert_eq!(file.name(), name);
+            assert_eq!(file.size(), content.len() as u64);
+            assert_eq!(file.crc32(), crc32fast::hash(content));
+
+            // Reading the file also checks its CRC-32 against the one in the archive.
+            let mut read = Vec::new();
+            file.read_to_end(&mut read).unwrap();
+            assert_eq!(read, *content);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_short_file() {
+        let (root, storage, mut entries) = store_files(&[("short.txt", b"Too short")]).await;
+        entries[0].size += 10;
+
+        let archive = ZipArchive::new(entries);
+        let length = archive.len();
+
+        let mut bytes = Vec::new();
+        let result = archive.write(&storage, &mut bytes).await;
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert!((bytes.len() as u64) < length);
+    }
+}
@@ -3,6 +3,14 @@ use std::path::PathBuf;
 use base64::Engine;
 use clap::Parser;
 
+use crate::{
+    ldap::{LdapLink, LdapTeam},
+    mail::SmtpSecurity,
+    oidc::OidcLink,
+    storage::StorageBackend,
+    workers::reaper::RetentionPolicy,
+};
+
 #[derive(Debug, Parser)]
 #[command(author, about, long_about = None)]
 pub struct Args {
@@ -22,6 +30,38 @@ pub struct Args {
     #[arg(long, default_value = "./cache", env)]
     pub cache_dir: PathBuf,
 
+    /// Where to store uploaded files.
+    #[arg(long, value_enum, default_value_t = StorageBackend::Local, env)]
+    pub storage: StorageBackend,
+
+    /// Name of the S3 bucket in which to store uploaded files.
+    #[arg(long, env)]
+    pub s3_bucket: Option<String>,
+
+    /// Region of the S3 bucket.
+    #[arg(long, default_value = "us-east-1", env)]
+    pub s3_region: String,
+
+    /// URL of the S3-compatible service, if not AWS S3 (such as 'http://localhost:9000').
+    #[arg(long, env)]
+    pub s3_endpoint: Option<String>,
+
+    /// Access key ID for the S3 bucket.
+    #[arg(long, env)]
+    pub s3_access_key_id: Option<String>,
+
+    /// Secret access key for the S3 bucket.
+    #[arg(long, env)]
+    pub s3_secret_access_key: Option<String>,
+
+    /// Prefix for the keys of the objects in the S3 bucket.
+    #[arg(long, env)]
+    pub s3_prefix: Option<String>,
+
+    /// Use path-style URLs for the S3 bucket, which most S3-compatible services require.
+    #[arg(long, env)]
+    pub s3_path_style: bool,
+
     /// Cookie secret (must be 32-bytes, base64-encoded).
     #[arg(long, env)]
     pub cookie_secret: Option<String>,
@@ -42,6 +82,172 @@ pub struct Args {
     #[arg(long, env)]
     pub max_preview_size: Option<u64>,
 
+    /// How long an unfinished resumable upload is kept after the last bytes were received.
+    #[arg(long, default_value = "1d", env)]
+    pub upload_session_expiry: humantime::Duration,
+
+    /// Interval at which the reaper worker checks for expired and exhausted uploads.
+    #[arg(long, default_value = "1h", env)]
+    pub reaper_interval: humantime::Duration,
+
+    /// Interval at which the webhook worker sends the webhook deliveries that are due.
+    #[arg(long, default_value = "10s", env)]
+    pub webhook_delivery_interval: humantime::Duration,
+
+    /// Host(s) that webhooks can be sent to even though they are on a private network, such as
+    /// `localhost` or `10.0.0.5`. Can be specified multiple times.
+    #[arg(
+        long = "webhook-allowed-host",
+        env = "WEBHOOK_ALLOWED_HOSTS",
+        value_delimiter = ','
+    )]
+    pub webhook_allowed_hosts: Vec<String>,
+
+    /// What to do with uploads that have passed their expiry date.
+    #[arg(long, value_enum, default_value_t = RetentionPolicy::Keep, env)]
+    pub expired_retention: RetentionPolicy,
+
+    /// Number of days after the expiry date before an expired upload is hidden or deleted.
+    #[arg(long, default_value_t = 0, env)]
+    pub expired_retention_days: u32,
+
+    /// What to do with uploads that have no remaining downloads.
+    #[arg(long, value_enum, default_value_t = RetentionPolicy::Keep, env)]
+    pub exhausted_retention: RetentionPolicy,
+
+    /// Host name of the SMTP server used to send email notifications. No email is sent if this is
+    /// not given.
+    #[arg(long, env)]
+    pub smtp_host: Option<String>,
+
+    /// Port of the SMTP server, if not the usual port for the security mode.
+    #[arg(long, env)]
+    pub smtp_port: Option<u16>,
+
+    /// How to secure the connection to the SMTP server.
+    #[arg(long, value_enum, default_value_t = SmtpSecurity::StartTls, env)]
+    pub smtp_security: SmtpSecurity,
+
+    /// Username for the SMTP server.
+    #[arg(long, env)]
+    pub smtp_username: Option<String>,
+
+    /// Password for the SMTP server.
+    #[arg(long, env)]
+    pub smtp_password: Option<String>,
+
+    /// Address that email is sent from (such as 'Parcel <parcel@example.com>').
+    #[arg(long, env)]
+    pub smtp_from: Option<String>,
+
+    /// Public URL of Parcel (such as 'https://parcel.example.com'), used for links in email and
+    /// for returning from single sign-on.
+    #[arg(long, env)]
+    pub base_url: Option<String>,
+
+    /// Issuer URL of the OpenID Connect provider. Single sign-on is disabled if this is not given.
+    #[arg(long, env)]
+    pub oidc_issuer: Option<String>,
+
+    /// Client ID registered with the OpenID Connect provider.
+    #[arg(long, env)]
+    pub oidc_client_id: Option<String>,
+
+    /// Client secret registered with the OpenID Connect provider, if it is a confidential client.
+    #[arg(long, env)]
+    pub oidc_client_secret: Option<String>,
+
+    /// Scopes to request from the OpenID Connect provider, in addition to 'openid'.
+    #[arg(long, env, value_delimiter = ',', default_value = "email,profile")]
+    pub oidc_scopes: Vec<String>,
+
+    /// Claim of the ID token that gives the username of new users.
+    #[arg(long, env, default_value = "preferred_username")]
+    pub oidc_username_claim: String,
+
+    /// Claim of the ID token that lists the groups of the user.
+    #[arg(long, env, default_value = "groups")]
+    pub oidc_groups_claim: String,
+
+    /// Group whose members are made administrators. When given, users that are not in the group
+    /// are not administrators.
+    #[arg(long, env)]
+    pub oidc_admin_group: Option<String>,
+
+    /// How to link existing users the first time that they sign in with single sign-on.
+    #[arg(long, value_enum, default_value_t = OidcLink::None, env)]
+    pub oidc_link: OidcLink,
+
+    /// Name of the OpenID Connect provider shown on the sign in page.
+    #[arg(long, env, default_value = "single sign-on")]
+    pub oidc_provider_name: String,
+
+    /// Only allow users to sign in with single sign-on, and not with a password.
+    #[arg(long, env)]
+    pub disable_password_login: bool,
+
+    /// URL of the LDAP server (such as 'ldaps://ldap.example.com'). LDAP is not used if this is
+    /// not given.
+    #[arg(long, env)]
+    pub ldap_url: Option<String>,
+
+    /// Upgrade the connection to the LDAP server using StartTLS.
+    #[arg(long, env)]
+    pub ldap_starttls: bool,
+
+    /// DN to bind as when searching the LDAP directory. Searches are anonymous if this is not given.
+    #[arg(long, env)]
+    pub ldap_bind_dn: Option<String>,
+
+    /// Password for the DN that searches the LDAP directory.
+    #[arg(long, env)]
+    pub ldap_bind_password: Option<String>,
+
+    /// Base DN under which users are found in the LDAP directory.
+    #[arg(long, env)]
+    pub ldap_user_base_dn: Option<String>,
+
+    /// Filter that finds a user in the LDAP directory, where '{username}' is replaced by the
+    /// username that they sign in with.
+    #[arg(long, env, default_value = "(uid={username})")]
+    pub ldap_user_filter: String,
+
+    /// Attribute of a user in the LDAP directory that gives the name of new users.
+    #[arg(long, env, default_value = "cn")]
+    pub ldap_name_attribute: String,
+
+    /// Attribute of a user in the LDAP directory that gives their email address.
+    #[arg(long, env, default_value = "mail")]
+    pub ldap_email_attribute: String,
+
+    /// Base DN under which groups are found in the LDAP directory. Defaults to the base DN of users.
+    #[arg(long, env)]
+    pub ldap_group_base_dn: Option<String>,
+
+    /// Filter that finds a group in the LDAP directory, where '{group}' is replaced by the name of
+    /// the group.
+    #[arg(long, env, default_value = "(&(objectClass=groupOfNames)(cn={group}))")]
+    pub ldap_group_filter: String,
+
+    /// Attribute of a group in the LDAP directory that lists the DNs of its members.
+    #[arg(long, env, default_value = "member")]
+    pub ldap_member_attribute: String,
+
+    /// How to link existing users the first time that they sign in with LDAP. Administrators are
+    /// never linked.
+    #[arg(long, value_enum, default_value_t = LdapLink::None, env)]
+    pub ldap_link: LdapLink,
+
+    /// Groups in the LDAP directory whose members are synchronised with teams, each given as
+    /// 'group=team-slug:permissions', where the permissions are any of 'edit', 'delete' and
+    /// 'config' separated by commas. Multiple groups are separated by semicolons.
+    #[arg(long, env, value_delimiter = ';')]
+    pub ldap_teams: Vec<LdapTeam>,
+
+    /// Interval at which the members of teams are synchronised with groups in the LDAP directory.
+    #[arg(long, default_value = "15m", env)]
+    pub ldap_sync_interval: humantime::Duration,
+
     /// Allowed CORS origin(s). Can be specified multiple times. If not specified, CORS is disabled
     /// and only same-origin requests are allowed.
     #[arg(long = "cors-origin", env = "CORS_ORIGINS", value_delimiter = ',')]
@@ -29,6 +29,15 @@ async fn main() -> anyhow::Result<()> {
     tracing::info!("Starting preview generation worker");
     let (preview, worker) = workers::previews::start_worker(env.clone()).await?;
 
+    tracing::info!("Starting reaper worker");
+    let (reaper, reaper_worker) = workers::reaper::start_worker(env.clone()).await?;
+
+    tracing::info!("Starting webhook worker");
+    let (webhooks, webhook_worker) = workers::webhooks::start_worker(env.clone()).await?;
+
+    tracing::info!("Starting LDAP worker");
+    let (ldap, ldap_worker) = workers::ldap::start_worker(env.clone()).await?;
+
     let app = create_app(env, preview.clone(), cookie_key.as_deref(), &args.cors_origins)
         .context("failed to create application")?;
     Server::new(TcpListener::bind("0.0.0.0:3000"))
@@ -43,5 +52,29 @@ async fn main() -> anyhow::Result<()> {
         .await
         .context("failed to join preview generation worker")?;
 
+    reaper
+        .stop()
+        .await
+        .context("failed to stop reaper worker")?;
+    reaper_worker
+        .await
+        .context("failed to join reaper worker")?;
+
+    webhooks
+        .stop()
+        .await
+        .context("failed to stop webhook worker")?;
+    webhook_worker
+        .await
+        .context("failed to join webhook worker")?;
+
+    ldap
+        .stop()
+        .await
+        .context("failed to stop LDAP worker")?;
+    ldap_worker
+        .await
+        .context("failed to join LDAP worker")?;
+
     Ok(())
 }
@@ -7,7 +7,10 @@ use sqlx::{
 
 use parcel_model::migration::MIGRATOR;
 
-use crate::args::Args;
+use crate::{
+    args::Args, ldap::Ldap, mail::Mailer, oidc::Oidc, storage::Storage, webauthn::Passkeys,
+    workers::reaper::RetentionPolicy,
+};
 
 pub struct Env {
     inner: Arc<Inner>,
@@ -32,6 +35,10 @@ pub struct Inner {
     pub pool: SqlitePool,
     pub config_dir: PathBuf,
     pub cache_dir: PathBuf,
+
+    /// Where the files for uploads and their previews are kept.
+    pub storage: Storage,
+
     pub analytics_domain: Option<String>,
     pub plausible_script: Option<String>,
 
@@ -45,13 +52,55 @@ pub struct Inner {
     /// might change this value later.
     pub max_preview_size: Option<u64>,
 
+    /// How long an unfinished resumable upload is kept after the last bytes were received.
+    pub upload_session_expiry: Duration,
+
+    /// The interval at which the reaper worker checks for expired and exhausted uploads.
+    pub reaper_interval: Duration,
+
+    /// The interval at which the webhook worker sends the webhook deliveries that are due.
+    pub webhook_delivery_interval: Duration,
+
+    /// The hosts that webhooks can be sent to even though they are on a private network.
+    pub webhook_allowed_hosts: Vec<String>,
+
+    /// What the reaper does with uploads that have passed their expiry date.
+    pub expired_retention: RetentionPolicy,
+
+    /// The number of days after the expiry date of an upload before the reaper acts on it.
+    pub expired_retention_days: u32,
+
+    /// What the reaper does with uploads that have no remaining downloads.
+    pub exhausted_retention: RetentionPolicy,
+
     /// Whether to trust proxy headers (X-Forwarded-For, etc.) for client IP detection.
     pub trust_proxy: bool,
+
+    /// Sends email notifications, if an SMTP server has been configured.
+    pub mailer: Option<Mailer>,
+
+    /// Signs users in with an OpenID Connect provider, if single sign-on has been configured.
+    pub oidc: Option<Oidc>,
+
+    /// Signs users in with an LDAP directory, if an LDAP server has been configured.
+    pub ldap: Option<Ldap>,
+
+    /// Registers and checks security keys and passkeys, if the base URL has been configured.
+    pub passkeys: Option<Passkeys>,
+}
+
+impl Inner {
+    /// Whether users can only sign in with single sign-on, and not with a username and password.
+    pub fn password_login_disabled(&self) -> bool {
+        self.oidc
+            .as_ref()
+            .is_some_and(|oidc| oidc.disable_password_login)
+    }
 }
 
 impl Env {
     pub async fn new(
-        Args {
+        args @ Args {
             db,
             config_dir,
             cache_dir,
@@ -59,10 +108,17 @@ impl Env {
             plausible_script,
             preview_generation_interval,
             max_preview_size,
+            upload_session_expiry,
+            reaper_interval,
+            webhook_delivery_interval,
+            webhook_allowed_hosts,
+            expired_retention,
+            expired_retention_days,
+            exhausted_retention,
             trust_proxy,
             ..
         }: &Args,
-    ) -> sqlx::Result<Self> {
+    ) -> anyhow::Result<Self> {
         let config_dir = config_dir.clone();
         if !config_dir.exists() {
             tracing::warn!("Config directory {config_dir:?} does not exist");
@@ -80,6 +136,12 @@ impl Env {
             std::fs::create_dir_all(&temp_dir)?;
         }
 
+        let storage = Storage::new(args, temp_dir)?;
+        let mailer = Mailer::new(args)?;
+        let oidc = Oidc::new(args).await?;
+        let ldap = Ldap::new(args)?;
+        let passkeys = Passkeys::new(args)?;
+
         tracing::info!(?db, "Creating SQLite connection pool");
         let opts = SqliteConnectOptions::from_str(db)?
             .create_if_missing(true)
@@ -96,16 +158,35 @@ impl Env {
         let plausible_script = plausible_script.clone();
         let preview_generation_interval = Duration::from(*preview_generation_interval);
         let max_preview_size = *max_preview_size;
+        let upload_session_expiry = Duration::from(*upload_session_expiry);
+        let reaper_interval = Duration::from(*reaper_interval);
+        let webhook_delivery_interval = Duration::from(*webhook_delivery_interval);
+        let webhook_allowed_hosts = webhook_allowed_hosts.clone();
+        let expired_retention = *expired_retention;
+        let expired_retention_days = *expired_retention_days;
+        let exhausted_retention = *exhausted_retention;
         let trust_proxy = *trust_proxy;
         let inner = Inner {
             pool,
             config_dir,
             cache_dir,
+            storage,
             analytics_domain,
             plausible_script,
             preview_generation_interval,
             max_preview_size,
+            upload_session_expiry,
+            reaper_interval,
+            webhook_delivery_interval,
+            webhook_allowed_hosts,
+            expired_retention,
+            expired_retention_days,
+            exhausted_retention,
             trust_proxy,
+            mailer,
+            oidc,
+            ldap,
+            passkeys,
         };
         let inner = Arc::new(inner);
 
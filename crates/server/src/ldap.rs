@@ -0,0 +1,342 @@
+//! Authentication and team membership with LDAP
+//!
+//! When an LDAP server has been configured, users can sign in with the username and password that
+//! they have in the directory. Users are found with a search under the user base DN, which is made
+//! as the bind DN if one is given, or anonymously otherwise. The password is then checked by
+//! binding as the DN of the entry that was found.
+//!
+//! The entry is mapped to a [`User`](parcel_model::user::User) when the user signs in:
+//!
+//! 1. A user that has signed in before is found by the DN of their entry.
+//! 2. Otherwise, if the [`LdapLink`] setting allows it, an existing user with the same username
+//!    that is not yet linked to the directory is linked to it. Their own password is replaced, as
+//!    they now sign in with the directory. Administrators are never linked, so that an entry in the
+//!    directory cannot take over their account.
+//! 3. Otherwise, a new user is created from the attributes of the entry, unless there is already a
+//!    user with the same username.
+//!
+//! Users that are not linked to the directory still sign in with their own password, which is
+//! checked before the directory is asked.
+//!
+//! Groups in the directory can also be mapped to teams, each with the permissions that members of
+//! the group are given. The members of these teams are synchronised with the groups periodically by
+//! the LDAP worker (see [`crate::workers::ldap`]).
+
+use std::{collections::HashSet, str::FromStr, time::Duration};
+
+use anyhow::Context;
+use ldap3::{ldap_escape, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+
+use crate::{args::Args, utils::validate_slug};
+
+/// How long to wait for the LDAP server to accept a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The result code that the LDAP server gives when a password is wrong.
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// How to link an existing user the first time that they sign in with LDAP.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LdapLink {
+    /// Never link existing users, so that only new users can sign in with LDAP.
+    #[default]
+    None,
+    /// Link the user with the same username, unless they are an administrator.
+    Username,
+}
+
+/// A group in the LDAP directory whose members are synchronised with a team.
+#[derive(Debug, Clone)]
+pub struct LdapTeam {
+    /// The name of the group, which is substituted into the group filter.
+    pub group: String,
+    /// The slug of the team, which is created if it does not exist.
+    pub slug: String,
+    pub can_edit: bool,
+    pub can_delete: bool,
+    pub can_config: bool,
+}
+
+impl FromStr for LdapTeam {
+    type Err = anyhow::Error;
+
+    /// Parse a mapping in the form `group=team-slug:edit,delete,config`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (group, team) = value
+            .rsplit_once('=')
+            .with_context(|| format!("expected 'group=team-slug' in '{value}'"))?;
+        let (slug, permissions) = team.split_once(':').unwrap_or((team, ""));
+
+        let group = group.trim();
+        let slug = slug.trim();
+        if group.is_empty() || slug.is_empty() {
+            anyhow::bail!("expected 'group=team-slug' in '{value}'");
+        }
+
+        validate_slug(slug).with_context(|| format!("invalid team slug '{slug}'"))?;
+
+        let mut team = Self {
+            group: group.to_string(),
+            slug: slug.to_string(),
+            can_edit: false,
+            can_delete: false,
+            can_config: false,
+        };
+
+        for permission in permissions.split(',').map(str::trim) {
+            match permission {
+                "" => {}
+                "edit" => team.can_edit = true,
+                "delete" => team.can_delete = true,
+                "config" => team.can_config = true,
+                _ => anyhow::bail!("unknown team permission '{permission}'"),
+            }
+        }
+
+        Ok(team)
+    }
+}
+
+/// The entry of a user in the LDAP directory.
+#[derive(Debug)]
+pub struct LdapEntry {
+    pub dn: String,
+    pub name: Option<String>,
+    pub email: Option<String>,
+}
+
+pub struct Ldap {
+    url: String,
+    starttls: bool,
+    bind_dn: Option<String>,
+    bind_password: Option<String>,
+    user_base_dn: String,
+    user_filter: String,
+    name_attribute: String,
+    email_attribute: String,
+    group_base_dn: String,
+    group_filter: String,
+    member_attribute: String,
+
+    /// How to link existing users the first time that they sign in.
+    pub link: LdapLink,
+
+    /// The groups whose members are synchronised with teams.
+    pub teams: Vec<LdapTeam>,
+
+    /// The interval at which the members of teams are synchronised with groups.
+    pub sync_interval: Duration,
+}
+
+/// Get the first value of an attribute of an entry, ignoring the case of the attribute name.
+fn first_value(entry: &SearchEntry, attribute: &str) -> Option<String> {
+    entry
+        .attrs
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(attribute))
+        .and_then(|(_, values)| values.first())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+impl Ldap {
+    /// Create the authenticator from the LDAP settings, or `None` if no LDAP server has been
+    /// configured.
+    pub fn new(
+        Args {
+            ldap_url,
+            ldap_starttls,
+            ldap_bind_dn,
+            ldap_bind_password,
+            ldap_user_base_dn,
+            ldap_user_filter,
+            ldap_name_attribute,
+            ldap_email_attribute,
+            ldap_group_base_dn,
+            ldap_group_filter,
+            ldap_member_attribute,
+            ldap_link,
+            ldap_teams,
+            ldap_sync_interval,
+            ..
+        }: &Args,
+    ) -> anyhow::Result<Option<Self>> {
+        let Some(url) = ldap_url else {
+            return Ok(None);
+        };
+
+        let user_base_dn = ldap_user_base_dn
+            .clone()
+            .context("a user base DN must be given when using LDAP")?;
+
+        if !ldap_user_filter.contains("{username}") {
+            anyhow::bail!("the LDAP user filter must contain '{{username}}'");
+        }
+
+        if !ldap_teams.is_empty() && !ldap_group_filter.contains("{group}") {
+            anyhow::bail!("the LDAP group filter must contain '{{group}}'");
+        }
+
+        tracing::info!(%url, teams = ldap_teams.len(), "Using LDAP for authentication");
+
+        Ok(Some(Self {
+            url: url.clone(),
+            starttls: *ldap_starttls,
+            bind_dn: ldap_bind_dn.clone(),
+            bind_password: ldap_bind_password.clone(),
+            group_base_dn: ldap_group_base_dn
+                .clone()
+                .unwrap_or_else(|| user_base_dn.clone()),
+            user_base_dn,
+            user_filter: ldap_user_filter.clone(),
+            name_attribute: ldap_name_attribute.clone(),
+            email_attribute: ldap_email_attribute.clone(),
+            group_filter: ldap_group_filter.clone(),
+            member_attribute: ldap_member_attribute.clone(),
+            link: *ldap_link,
+            teams: ldap_teams.clone(),
+            sync_interval: Duration::from(*ldap_sync_interval),
+        }))
+    }
+
+    /// Connect to the LDAP server, binding as the bind DN if one was given.
+    async fn connect(&self) -> anyhow::Result<ldap3::Ldap> {
+        let settings = LdapConnSettings::new()
+            .set_conn_timeout(CONNECT_TIMEOUT)
+            .set_starttls(self.starttls);
+
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &self.url)
+            .await
+            .with_context(|| format!("failed to connect to LDAP server at {}", self.url))?;
+        ldap3::drive!(conn);
+
+        if let Some(ref bind_dn) = self.bind_dn {
+            ldap.simple_bind(bind_dn, self.bind_password.as_deref().unwrap_or_default())
+                .await
+                .and_then(|result| result.success())
+                .with_context(|| format!("failed to bind to LDAP server as {bind_dn}"))?;
+        }
+
+        Ok(ldap)
+    }
+
+    /// Find the entry of a user in the directory by their username.
+    async fn find_user(
+        &self,
+        ldap: &mut ldap3::Ldap,
+        username: &str,
+    ) -> anyhow::Result<Option<LdapEntry>> {
+        let filter = self
+            .user_filter
+            .replace("{username}", &ldap_escape(username));
+
+        let (mut entries, _) = ldap
+            .search(
+                &self.user_base_dn,
+                Scope::Subtree,
+                &filter,
+                vec![self.name_attribute.as_str(), self.email_attribute.as_str()],
+            )
+            .await
+            .and_then(|result| result.success())
+            .context("failed to search for user in LDAP directory")?;
+
+        // A filter that matches more than one entry cannot say which of them is signing in.
+        if entries.len() > 1 {
+            tracing::warn!(%username, count = entries.len(), "LDAP user filter is ambiguous");
+            return Ok(None);
+        }
+
+        let Some(entry) = entries.pop() else {
+            return Ok(None);
+        };
+
+        let entry = SearchEntry::construct(entry);
+        Ok(Some(LdapEntry {
+            name: first_value(&entry, &self.name_attribute),
+            email: first_value(&entry, &self.email_attribute),
+            dn: entry.dn,
+        }))
+    }
+
+    /// Check the username and password of a user with the directory, returning their entry, or
+    /// `None` if the directory does not accept them.
+    pub async fn authenticate(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> anyhow::Result<Option<LdapEntry>> {
+        // Binding with an empty password is an unauthenticated bind, which many servers accept.
+        if password.is_empty() {
+            return Ok(None);
+        }
+
+        let mut ldap = self.connect().await?;
+        let Some(entry) = self.find_user(&mut ldap, username).await? else {
+            ldap.unbind().await.ok();
+            return Ok(None);
+        };
+
+        let result = ldap
+            .simple_bind(&entry.dn, password)
+            .await
+            .context("failed to bind to LDAP server as user")?;
+        ldap.unbind().await.ok();
+
+        if result.rc == INVALID_CREDENTIALS {
+            return Ok(None);
+        }
+
+        result
+            .success()
+            .context("failed to bind to LDAP server as user")?;
+
+        Ok(Some(entry))
+    }
+
+    /// Get the DNs of the members of each group that is synchronised with a team, in lower case.
+    ///
+    /// A group that cannot be found is given as `None`, so that its team is left alone rather than
+    /// having all of its members removed.
+    pub async fn get_team_members(
+        &self,
+    ) -> anyhow::Result<Vec<(&LdapTeam, Option<HashSet<String>>)>> {
+        let mut ldap = self.connect().await?;
+        let mut groups = Vec::with_capacity(self.teams.len());
+
+        for team in &self.teams {
+            let filter = self
+                .group_filter
+                .replace("{group}", &ldap_escape(&team.group));
+            let (entries, _) = ldap
+                .search(
+                    &self.group_base_dn,
+                    Scope::Subtree,
+                    &filter,
+                    vec![self.member_attribute.as_str()],
+                )
+                .await
+                .and_then(|result| result.success())
+                .with_context(|| format!("failed to search for LDAP group '{}'", team.group))?;
+
+            let Some(entry) = entries.into_iter().next() else {
+                tracing::warn!(group = %team.group, "LDAP group not found");
+                groups.push((team, None));
+                continue;
+            };
+
+            let members = SearchEntry::construct(entry)
+                .attrs
+                .into_iter()
+                .filter(|(name, _)| name.eq_ignore_ascii_case(&self.member_attribute))
+                .flat_map(|(_, values)| values)
+                .map(|dn| dn.to_lowercase())
+                .collect();
+
+            groups.push((team, Some(members)));
+        }
+
+        ldap.unbind().await.ok();
+        Ok(groups)
+    }
+}
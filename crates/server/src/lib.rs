@@ -1,9 +1,19 @@
 pub mod app;
+pub mod archive;
 pub mod args;
 pub mod env;
+pub mod ldap;
+pub mod mail;
+pub mod notifications;
+pub mod oidc;
+pub mod storage;
 pub mod utils;
+pub mod webauthn;
 
 pub mod workers {
+    pub mod ldap;
     pub mod previews;
+    pub mod reaper;
+    pub mod webhooks;
 }
 
@@ -0,0 +1,139 @@
+//! Sending of email
+//!
+//! When an SMTP server has been configured, Parcel can send notifications to users by email, such
+//! as when one of their uploads is downloaded. Users give their email address, and choose which
+//! notifications they want, in their account settings. Without an SMTP server, no email is sent.
+//!
+//! The connection to the SMTP server can be secured in one of three ways:
+//!
+//! 1. `starttls` connects in plain text and then upgrades the connection, usually on port 587. This
+//!    is the default.
+//! 2. `tls` connects using TLS from the start, usually on port 465.
+//! 3. `none` does not secure the connection at all. This is only meant for testing against a local
+//!    SMTP sink, such as [Mailpit](https://mailpit.axllent.org/).
+//!
+//! Email is sent in the background, so a slow or broken SMTP server never holds up a request.
+
+use anyhow::Context;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+use crate::args::Args;
+
+/// How to secure the connection to the SMTP server.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SmtpSecurity {
+    /// Do not secure the connection.
+    None,
+    /// Upgrade the connection using STARTTLS.
+    #[default]
+    #[value(name = "starttls")]
+    StartTls,
+    /// Connect using TLS.
+    Tls,
+}
+
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    base_url: Option<String>,
+}
+
+impl Mailer {
+    /// Create the mailer from the SMTP settings, or `None` if no SMTP server has been configured.
+    pub fn new(
+        Args {
+            smtp_host,
+            smtp_port,
+            smtp_security,
+            smtp_username,
+            smtp_password,
+            smtp_from,
+            base_url,
+            ..
+        }: &Args,
+    ) -> anyhow::Result<Option<Self>> {
+        let Some(host) = smtp_host else {
+            return Ok(None);
+        };
+
+        let from = smtp_from
+            .as_deref()
+            .context("a from address must be given when using SMTP")?
+            .parse::<Mailbox>()
+            .context("the from address for SMTP is not valid")?;
+
+        let mut builder = match smtp_security {
+            SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+            SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+                .context("failed to configure STARTTLS for SMTP")?,
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+                .context("failed to configure TLS for SMTP")?,
+        };
+
+        if let Some(port) = smtp_port {
+            builder = builder.port(*port);
+        }
+
+        if let Some(username) = smtp_username {
+            let password = smtp_password.clone().unwrap_or_default();
+            builder = builder.credentials(Credentials::new(username.clone(), password));
+        }
+
+        tracing::info!(%host, port = ?smtp_port, ?smtp_security, "Sending email using SMTP");
+
+        Ok(Some(Self {
+            transport: builder.build(),
+            from,
+            base_url: base_url
+                .as_deref()
+                .map(|url| url.trim_end_matches('/').to_string()),
+        }))
+    }
+
+    /// Get the full URL of a page, for linking to it in an email, if the base URL is known.
+    pub fn url(&self, path: &str) -> Option<String> {
+        self.base_url
+            .as_ref()
+            .map(|base_url| format!("{base_url}{path}"))
+    }
+
+    /// Send a plain text email in the background.
+    ///
+    /// Failing to send the email is logged, but is not reported to the caller.
+    pub fn send(&self, to: &str, subject: &str, body: String) {
+        let to = match to.parse::<Mailbox>() {
+            Ok(to) => to,
+            Err(err) => {
+                tracing::error!(?err, %to, "Invalid email address for notification");
+                return;
+            }
+        };
+
+        let message = match Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(body)
+        {
+            Ok(message) => message,
+            Err(err) => {
+                tracing::error!(?err, %subject, "Failed to build email");
+                return;
+            }
+        };
+
+        let transport = self.transport.clone();
+        let subject = subject.to_string();
+        tokio::spawn(async move {
+            match transport.send(message).await {
+                Ok(_) => tracing::info!(%subject, "Sent email"),
+                Err(err) => tracing::error!(?err, %subject, "Failed to send email"),
+            }
+        });
+    }
+}
@@ -0,0 +1,345 @@
+//! Email notifications
+//!
+//! When an SMTP server has been configured (see [`crate::mail`]), users that have given an email
+//! address in their account settings are sent notifications for the events that they have chosen:
+//!
+//! 1. One of their uploads is downloaded by someone else.
+//! 2. One of their uploads or share links will expire within [`EXPIRING_NOTICE_DAYS`] days.
+//! 3. A file is uploaded through one of their upload requests.
+//! 4. An account is locked out after too many failed attempts to sign in, which is only sent to
+//!    administrators.
+//!
+//! Users are also sent the links that let them choose a password, when they are invited to create
+//! an account or have forgotten their password (see [`send_user_link`]). These are not optional,
+//! and when they cannot be sent, an administrator can pass the link on instead.
+//!
+//! Uploads that are owned by a team are reported to the user that uploaded them, and upload
+//! requests that are owned by a team are reported to the user that created the request.
+//!
+//! Failing to send a notification is logged, but never fails the request that caused it.
+
+use anyhow::Context;
+
+use parcel_model::{
+    login_attempt::LoginAttempt, share_link::ShareLink, types::Key, upload::Upload,
+    upload_request::UploadRequest, user::User, user_token::UserTokenKind,
+};
+
+use crate::env::Env;
+
+/// The number of days before the expiry date of an upload or share link that the owner is warned.
+const EXPIRING_NOTICE_DAYS: u32 = 1;
+
+/// Get the user that a notification is for, if they are enabled, have an email address and have
+/// chosen to receive the notification.
+async fn get_recipient(
+    env: &Env,
+    user: Option<Key<User>>,
+    wants: impl Fn(&User) -> bool,
+) -> Option<(User, String)> {
+    let user = match User::get(&env.pool, user?).await {
+        Ok(user) => user?,
+        Err(err) => {
+            tracing::error!(?err, ?user, "Failed to get user for notification");
+            return None;
+        }
+    };
+
+    if !user.enabled || !wants(&user) {
+        return None;
+    }
+
+    let email = user.email.clone()?;
+    Some((user, email))
+}
+
+/// The user that is told about an upload, which is the owner of the upload, or the user that
+/// uploaded it if it is owned by a team.
+fn upload_recipient(upload: &Upload) -> Option<Key<User>> {
+    upload.owner_user.or(upload.uploaded_by)
+}
+
+/// Add a link to a page to the end of the body of an email, if the base URL is known.
+fn with_link(env: &Env, mut body: String, path: &str) -> String {
+    if let Some(url) = env.mailer.as_ref().and_then(|mailer| mailer.url(path)) {
+        body.push_str(&format!("\n\n{url}"));
+    }
+
+    body.push('\n');
+    body
+}
+
+/// Tell the owner of an upload that it was downloaded, unless they downloaded it themselves.
+pub async fn notify_download(
+    env: &Env,
+    upload: &Upload,
+    link: Option<&ShareLink>,
+    user: Option<&User>,
+    remote_addr: Option<&str>,
+) {
+    let Some(ref mailer) = env.mailer else {
+        return;
+    };
+
+    let recipient = upload_recipient(upload);
+    if recipient.is_some() && user.map(|user| user.id) == recipient {
+        return;
+    }
+
+    let Some((recipient, email)) =
+        get_recipient(env, recipient, |recipient| recipient.notify_downloads).await
+    else {
+        return;
+    };
+
+    let who = match user {
+        Some(user) => user.name.clone(),
+        None => "someone who was not signed in".to_string(),
+    };
+
+    let mut body = format!(
+        "Hi {},\n\nYour file '{}' was downloaded by {who}",
+        recipient.name, upload.filename
+    );
+
+    if let Some(remote_addr) = remote_addr {
+        body.push_str(&format!(" from {remote_addr}"));
+    }
+
+    if let Some(link) = link {
+        body.push_str(&format!(", using the share link '{}'", link.name));
+    }
+
+    body.push('.');
+    if let Some(remaining) = link.map_or(upload.remaining, |link| link.remaining) {
+        body.push_str(&format!(" It can be downloaded {remaining} more times."));
+    }
+
+    mailer.send(
+        &email,
+        &format!("'{}' was downloaded", upload.filename),
+        with_link(env, body, &format!("/uploads/{}", upload.slug)),
+    );
+}
+
+/// Tell the owner of an upload request that files were uploaded through it.
+pub async fn notify_request_upload(env: &Env, request: &UploadRequest, filenames: &[&str]) {
+    let Some(ref mailer) = env.mailer else {
+        return;
+    };
+
+    let recipient = request.owner_user.or(request.created_by);
+    let Some((recipient, email)) =
+        get_recipient(env, recipient, |recipient| recipient.notify_requests).await
+    else {
+        return;
+    };
+
+    let mut body = format!(
+        "Hi {},\n\nThese files were uploaded through your upload request '{}':\n",
+        recipient.name, request.name
+    );
+
+    for filename in filenames {
+        body.push_str(&format!("\n- {filename}"));
+    }
+
+    let subject = match filenames {
+        [filename] => format!("'{filename}' was uploaded to '{}'", request.name),
+        _ => format!(
+            "{} files were uploaded to '{}'",
+            filenames.len(),
+            request.name
+        ),
+    };
+
+    mailer.send(&email, &subject, with_link(env, body, "/"));
+}
+
+/// Warn the owners of the uploads and share links that are about to expire.
+///
+/// Each upload and share link is only reported once for each expiry date, so this can be called
+/// periodically.
+pub async fn notify_expiring(env: &Env) -> anyhow::Result<()> {
+    let Some(ref mailer) = env.mailer else {
+        return Ok(());
+    };
+
+    let uploads = Upload::take_expiring(&env.pool, EXPIRING_NOTICE_DAYS)
+        .await
+        .context("failed to get expiring uploads")?;
+
+    for upload in &uploads {
+        let Some(expiry_date) = upload.expiry_date else {
+            continue;
+        };
+
+        let Some((recipient, email)) = get_recipient(env, upload_recipient(upload), |recipient| {
+            recipient.notify_expiring
+        })
+        .await
+        else {
+            continue;
+        };
+
+        let body = format!(
+            "Hi {},\n\nYour file '{}' expires on {expiry_date}, after which it can no longer be \
+            downloaded. You can change the expiry date from the upload page.",
+            recipient.name, upload.filename
+        );
+
+        mailer.send(
+            &email,
+            &format!("'{}' is about to expire", upload.filename),
+            with_link(env, body, &format!("/uploads/{}", upload.slug)),
+        );
+    }
+
+    let links = ShareLink::take_expiring(&env.pool, EXPIRING_NOTICE_DAYS)
+        .await
+        .context("failed to get expiring share links")?;
+
+    for link in &links {
+        let Some(expiry_date) = link.expiry_date else {
+            continue;
+        };
+
+        let upload = match Upload::get(&env.pool, link.upload).await {
+            Ok(Some(upload)) => upload,
+            Ok(None) => continue,
+            Err(err) => {
+                tracing::error!(?err, %link.id, "Failed to get upload of expiring share link");
+                continue;
+            }
+        };
+
+        let Some((recipient, email)) = get_recipient(env, upload_recipient(&upload), |recipient| {
+            recipient.notify_expiring
+        })
+        .await
+        else {
+            continue;
+        };
+
+        let body = format!(
+            "Hi {},\n\nThe share link '{}' to your file '{}' expires on {expiry_date}, after which \
+            it can no longer be used. You can create a new link from the upload page.",
+            recipient.name, link.name, upload.filename
+        );
+
+        mailer.send(
+            &email,
+            &format!("The share link '{}' is about to expire", link.name),
+            with_link(env, body, &format!("/uploads/{}", upload.slug)),
+        );
+    }
+
+    if !uploads.is_empty() || !links.is_empty() {
+        tracing::info!(
+            uploads = uploads.len(),
+            links = links.len(),
+            "Sent notifications for expiring uploads and share links"
+        );
+    }
+
+    Ok(())
+}
+
+/// Record a failed attempt to sign in, telling the administrators if it locked out the account.
+pub async fn record_failed_login(env: &Env, username: &str, ip_address: Option<&str>) {
+    let locked_out = match LoginAttempt::record_failure(&env.pool, username, ip_address).await {
+        Ok(locked_out) => locked_out,
+        Err(err) => {
+            tracing::error!(?err, %username, "Failed to record failed login attempt");
+            return;
+        }
+    };
+
+    let Some(ref mailer) = env.mailer else {
+        return;
+    };
+
+    if !locked_out {
+        return;
+    }
+
+    let admins = match User::get_lockout_recipients(&env.pool).await {
+        Ok(admins) => admins,
+        Err(err) => {
+            tracing::error!(?err, "Failed to get administrators to notify of lockout");
+            return;
+        }
+    };
+
+    for admin in admins {
+        let Some(ref email) = admin.email else {
+            continue;
+        };
+
+        let body = format!(
+            "Hi {},\n\nThe account '{username}' has been locked out after too many failed attempts \
+            to sign in. The last attempt came from {}. The account will be unlocked automatically \
+            after a few minutes.",
+            admin.name,
+            ip_address.unwrap_or("an unknown address")
+        );
+
+        mailer.send(
+            email,
+            &format!("The account '{username}' has been locked out"),
+            with_link(env, body, "/admin/users"),
+        );
+    }
+}
+
+/// Check if links to choose a password can be sent by email, which needs both an SMTP server and a
+/// base URL to link to.
+pub fn can_send_links(env: &Env) -> bool {
+    env.mailer
+        .as_ref()
+        .is_some_and(|mailer| mailer.url("/").is_some())
+}
+
+/// Send a user the link to a page where they can choose a password, returning whether an email
+/// was sent.
+///
+/// Nothing is sent if the user has no email address, or if links cannot be sent at all (see
+/// [`can_send_links`]).
+pub fn send_user_link(env: &Env, user: &User, kind: UserTokenKind, path: &str) -> bool {
+    let Some(ref mailer) = env.mailer else {
+        return false;
+    };
+
+    let (Some(email), Some(url)) = (user.email.as_deref(), mailer.url(path)) else {
+        return false;
+    };
+
+    let (subject, body) = match kind {
+        UserTokenKind::Invitation => (
+            "You have been invited to Parcel",
+            format!(
+                "Hi {},\n\nAn account with the username '{}' has been created for you. Follow the \
+                link below to choose your password. The link can only be used once, and expires in \
+                {} days.",
+                user.name,
+                user.username,
+                kind.lifetime().whole_days()
+            ),
+        ),
+        UserTokenKind::PasswordReset => (
+            "Reset your Parcel password",
+            format!(
+                "Hi {},\n\nSomeone asked to reset the password of your account '{}'. If this was \
+                you, follow the link below to choose a new password. The link can only be used \
+                once, and expires in {} minutes. If you did not ask for this, you can ignore this \
+                email.",
+                user.name,
+                user.username,
+                kind.lifetime().whole_minutes()
+            ),
+        ),
+    };
+
+    mailer.send(email, subject, format!("{body}\n\n{url}\n"));
+    true
+}
@@ -0,0 +1,304 @@
+//! Single sign-on with OpenID Connect
+//!
+//! When an issuer has been configured, users can sign in through an OpenID Connect provider using
+//! the authorization code flow with PKCE. The provider is discovered when Parcel starts, using the
+//! `/.well-known/openid-configuration` document of the issuer.
+//!
+//! The claims of the ID token are mapped to a [`User`](parcel_model::user::User) when the user
+//! returns from the provider:
+//!
+//! 1. A user that has signed in before is found by the subject (`sub`) of the ID token.
+//! 2. Otherwise, an existing user can be linked by their email address or by their username,
+//!    depending on the [`OidcLink`] setting. This is off by default.
+//! 3. Otherwise, a new user is created from the claims.
+//!
+//! When an admin group is configured, the admin flag of the user is updated from the group claim
+//! each time that they sign in. The group can take away the rights of a user that was linked, but
+//! only another administrator can make them an administrator.
+
+use std::collections::HashMap;
+
+use anyhow::Context;
+use openidconnect::{
+    core::{
+        CoreAuthDisplay, CoreAuthPrompt, CoreAuthenticationFlow, CoreErrorResponseType,
+        CoreGenderClaim, CoreJsonWebKey, CoreJweContentEncryptionAlgorithm,
+        CoreJwsSigningAlgorithm, CoreProviderMetadata, CoreRevocableToken,
+        CoreRevocationErrorResponse, CoreTokenIntrospectionResponse, CoreTokenType,
+    },
+    reqwest, AdditionalClaims, AuthorizationCode, Client, ClientId, ClientSecret, CsrfToken,
+    EmptyExtraTokenFields, EndpointMaybeSet, EndpointNotSet, EndpointSet, IdTokenFields, IssuerUrl,
+    Nonce, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope, StandardErrorResponse,
+    StandardTokenResponse, TokenResponse,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::args::Args;
+
+/// How to link an existing user the first time that they sign in with single sign-on.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OidcLink {
+    /// Never link existing users, always creating a new user.
+    #[default]
+    None,
+    /// Link the user with the same email address, if both the provider and Parcel have verified it.
+    Email,
+    /// Link the user with the same username.
+    Username,
+}
+
+/// The claims of an ID token that are not standard claims, such as the groups of the user.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ExtraClaims {
+    #[serde(flatten)]
+    claims: HashMap<String, Value>,
+}
+
+impl AdditionalClaims for ExtraClaims {}
+
+type OidcTokenResponse = StandardTokenResponse<
+    IdTokenFields<
+        ExtraClaims,
+        EmptyExtraTokenFields,
+        CoreGenderClaim,
+        CoreJweContentEncryptionAlgorithm,
+        CoreJwsSigningAlgorithm,
+    >,
+    CoreTokenType,
+>;
+
+type OidcClient = Client<
+    ExtraClaims,
+    CoreAuthDisplay,
+    CoreGenderClaim,
+    CoreJweContentEncryptionAlgorithm,
+    CoreJsonWebKey,
+    CoreAuthPrompt,
+    StandardErrorResponse<CoreErrorResponseType>,
+    OidcTokenResponse,
+    CoreTokenIntrospectionResponse,
+    CoreRevocableToken,
+    CoreRevocationErrorResponse,
+    EndpointSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointMaybeSet,
+    EndpointMaybeSet,
+>;
+
+/// The secrets of a sign in that has been started, which are kept in a cookie until the user
+/// returns from the provider.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PendingSignIn {
+    state: String,
+    nonce: String,
+    verifier: String,
+}
+
+impl PendingSignIn {
+    /// Check that the state returned by the provider is the one that was sent.
+    pub fn is_valid_state(&self, state: &str) -> bool {
+        self.state == state
+    }
+}
+
+/// The user that the provider has authenticated, taken from the claims of the ID token.
+#[derive(Debug)]
+pub struct OidcIdentity {
+    pub subject: String,
+    pub username: Option<String>,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub groups: Vec<String>,
+}
+
+pub struct Oidc {
+    client: OidcClient,
+    http_client: reqwest::Client,
+    scopes: Vec<String>,
+    username_claim: String,
+    groups_claim: String,
+
+    /// Members of this group are made administrators, and other users are not.
+    admin_group: Option<String>,
+
+    /// How to link existing users the first time that they sign in.
+    pub link: OidcLink,
+
+    /// The name of the provider shown on the sign in page.
+    pub provider_name: String,
+
+    /// Whether users can only sign in with single sign-on.
+    pub disable_password_login: bool,
+}
+
+impl Oidc {
+    /// Discover the provider and create the client, or `None` if no issuer has been configured.
+    pub async fn new(
+        Args {
+            oidc_issuer,
+            oidc_client_id,
+            oidc_client_secret,
+            oidc_scopes,
+            oidc_username_claim,
+            oidc_groups_claim,
+            oidc_admin_group,
+            oidc_link,
+            oidc_provider_name,
+            disable_password_login,
+            base_url,
+            ..
+        }: &Args,
+    ) -> anyhow::Result<Option<Self>> {
+        let Some(issuer) = oidc_issuer else {
+            if *disable_password_login {
+                anyhow::bail!("password login can only be disabled when single sign-on is used");
+            }
+
+            return Ok(None);
+        };
+
+        let client_id = oidc_client_id
+            .clone()
+            .context("a client ID must be given when using single sign-on")?;
+        let base_url = base_url
+            .as_deref()
+            .context("the base URL must be given when using single sign-on")?;
+        let redirect_url = RedirectUrl::new(format!(
+            "{}/user/signin/oidc/callback",
+            base_url.trim_end_matches('/')
+        ))
+        .context("the base URL is not valid")?;
+
+        // Following redirects would allow the provider to make Parcel send requests elsewhere.
+        let http_client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .context("failed to create HTTP client for single sign-on")?;
+
+        let issuer_url = IssuerUrl::new(issuer.clone()).context("the issuer URL is not valid")?;
+        let metadata = CoreProviderMetadata::discover_async(issuer_url, &http_client)
+            .await
+            .with_context(|| format!("failed to discover OpenID Connect provider at {issuer}"))?;
+
+        let client = OidcClient::from_provider_metadata(
+            metadata,
+            ClientId::new(client_id),
+            oidc_client_secret.clone().map(ClientSecret::new),
+        )
+        .set_redirect_uri(redirect_url);
+
+        tracing::info!(%issuer, "Using OpenID Connect for single sign-on");
+
+        Ok(Some(Self {
+            client,
+            http_client,
+            scopes: oidc_scopes.clone(),
+            username_claim: oidc_username_claim.clone(),
+            groups_claim: oidc_groups_claim.clone(),
+            admin_group: oidc_admin_group.clone(),
+            link: *oidc_link,
+            provider_name: oidc_provider_name.clone(),
+            disable_password_login: *disable_password_login,
+        }))
+    }
+
+    /// Start signing in, returning the URL of the provider to send the user to, along with the
+    /// secrets to keep until they return.
+    pub fn start(&self) -> (String, PendingSignIn) {
+        let (challenge, verifier) = PkceCodeChallenge::new_random_sha256();
+
+        let mut request = self.client.authorize_url(
+            CoreAuthenticationFlow::AuthorizationCode,
+            CsrfToken::new_random,
+            Nonce::new_random,
+        );
+
+        for scope in &self.scopes {
+            request = request.add_scope(Scope::new(scope.clone()));
+        }
+
+        let (url, state, nonce) = request.set_pkce_challenge(challenge).url();
+
+        let pending = PendingSignIn {
+            state: state.secret().clone(),
+            nonce: nonce.secret().clone(),
+            verifier: verifier.secret().clone(),
+        };
+
+        (url.to_string(), pending)
+    }
+
+    /// Exchange the authorization code for an ID token, and verify it.
+    pub async fn finish(
+        &self,
+        code: String,
+        pending: PendingSignIn,
+    ) -> anyhow::Result<OidcIdentity> {
+        let response = self
+            .client
+            .exchange_code(AuthorizationCode::new(code))
+            .context("the provider does not have a token endpoint")?
+            .set_pkce_verifier(PkceCodeVerifier::new(pending.verifier))
+            .request_async(&self.http_client)
+            .await
+            .context("failed to exchange authorization code")?;
+
+        let id_token = response
+            .id_token()
+            .context("the provider did not return an ID token")?;
+        let claims = id_token
+            .claims(&self.client.id_token_verifier(), &Nonce::new(pending.nonce))
+            .context("the ID token is not valid")?;
+
+        let claims = serde_json::to_value(claims).context("failed to read ID token claims")?;
+        self.identity(&claims)
+    }
+
+    /// Map the claims of an ID token to the identity of the user.
+    fn identity(&self, claims: &Value) -> anyhow::Result<OidcIdentity> {
+        let string_claim = |name: &str| {
+            claims
+                .get(name)
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(ToString::to_string)
+        };
+
+        let subject = string_claim("sub").context("the ID token does not have a subject")?;
+
+        // Providers differ in whether groups are sent as a list or, for a single group, a string.
+        let groups = match claims.get(&self.groups_claim) {
+            Some(Value::Array(groups)) => groups
+                .iter()
+                .filter_map(Value::as_str)
+                .map(ToString::to_string)
+                .collect(),
+            Some(Value::String(group)) => vec![group.clone()],
+            _ => Vec::new(),
+        };
+
+        Ok(OidcIdentity {
+            subject,
+            username: string_claim(&self.username_claim),
+            name: string_claim("name"),
+            email: string_claim("email"),
+            email_verified: claims
+                .get("email_verified")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            groups,
+        })
+    }
+
+    /// Whether the user should be an administrator, or `None` if no admin group is configured.
+    pub fn is_admin(&self, identity: &OidcIdentity) -> Option<bool> {
+        self.admin_group
+            .as_ref()
+            .map(|group| identity.groups.contains(group))
+    }
+}
@@ -0,0 +1,210 @@
+//! Storage of uploaded files
+//!
+//! The files for uploads, and the previews generated for them, are kept in a storage backend.
+//! Uploads with the same content share the same file, which is identified by the key of its blob:
+//! the SHA-256 hash of the content for the file itself, and the key followed by `.preview` for the
+//! preview image. See [`parcel_model::blob::Blob`] for how these shared files are tracked.
+//!
+//! There are two backends:
+//!
+//! 1. `local` keeps the files in the cache directory, which is the default.
+//! 2. `s3` keeps the files in a bucket of an S3-compatible object store, such as AWS S3 or MinIO.
+//!
+//! Regardless of the backend, files are always received into the `temp` directory of the cache
+//! directory before they are moved into storage. This means that the size of an upload can be
+//! checked against the quota before it is stored, and that a partially received upload never
+//! appears in storage.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::args::Args;
+
+mod local;
+mod s3;
+
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+/// Which storage backend to keep uploaded files in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum StorageBackend {
+    /// Keep files in the cache directory.
+    #[default]
+    Local,
+    /// Keep files in an S3-compatible object store.
+    S3,
+}
+
+/// A reader for the contents of a stored file.
+pub type StorageReader = Box<dyn AsyncRead + Send + Unpin>;
+
+/// A reader that computes the SHA-256 hash of the bytes that are read through it.
+pub struct HashReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R> HashReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Get the hex-encoded hash of the bytes that have been read.
+    pub fn finish(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for HashReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let start = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        self.hasher.update(&buf.filled()[start..]);
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Compute the hex-encoded SHA-256 hash of a file on the local filesystem.
+pub async fn hash_file(path: &Path) -> io::Result<String> {
+    let file = tokio::fs::File::open(path).await?;
+    let mut reader = HashReader::new(file);
+    tokio::io::copy(&mut reader, &mut tokio::io::sink()).await?;
+    Ok(reader.finish())
+}
+
+/// A file in storage, as found by [`Storage::list`].
+#[derive(Debug, Clone)]
+pub struct StoredFile {
+    pub key: String,
+    pub size: u64,
+}
+
+/// A stored file that is available on the local filesystem.
+///
+/// Some things, such as the commands that generate previews, need a path to a file rather than a
+/// stream of bytes. When the storage backend does not keep files locally, the file is downloaded to
+/// the temporary directory, and it is deleted again when this is dropped.
+#[derive(Debug)]
+pub struct LocalFile {
+    path: PathBuf,
+    temporary: bool,
+}
+
+impl LocalFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for LocalFile {
+    fn drop(&mut self) {
+        if self.temporary {
+            if let Err(err) = std::fs::remove_file(&self.path) {
+                tracing::error!(path = ?self.path, ?err, "Failed to delete temporary copy of file");
+            }
+        }
+    }
+}
+
+pub enum Storage {
+    Local(LocalStorage),
+    S3(S3Storage),
+}
+
+impl Storage {
+    pub fn new(args: &Args, temp_dir: PathBuf) -> anyhow::Result<Self> {
+        Ok(match args.storage {
+            StorageBackend::Local => Self::Local(LocalStorage::new(args.cache_dir.clone())),
+            StorageBackend::S3 => Self::S3(S3Storage::new(args, temp_dir)?),
+        })
+    }
+
+    /// Check whether a file exists in storage.
+    pub async fn exists(&self, key: &str) -> io::Result<bool> {
+        match self.size(key).await {
+            Ok(_) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Get the size of a file in storage.
+    pub async fn size(&self, key: &str) -> io::Result<u64> {
+        match self {
+            Self::Local(local) => local.size(key).await,
+            Self::S3(s3) => s3.size(key).await,
+        }
+    }
+
+    /// Read the whole of a file from storage.
+    pub async fn read(&self, key: &str) -> io::Result<StorageReader> {
+        match self {
+            Self::Local(local) => local.read(key).await,
+            Self::S3(s3) => s3.read(key).await,
+        }
+    }
+
+    /// Read the bytes from `start` to `end` (inclusive) of a file in storage.
+    pub async fn read_range(&self, key: &str, start: u64, end: u64) -> io::Result<StorageReader> {
+        match self {
+            Self::Local(local) => local.read_range(key, start, end).await,
+            Self::S3(s3) => s3.read_range(key, start, end).await,
+        }
+    }
+
+    /// Move a file from the local filesystem into storage.
+    ///
+    /// The file at `path` is expected to be in the temporary directory, and is removed once it has
+    /// been stored.
+    pub async fn store(&self, path: &Path, key: &str) -> io::Result<()> {
+        match self {
+            Self::Local(local) => local.store(path, key).await,
+            Self::S3(s3) => s3.store(path, key).await,
+        }
+    }
+
+    pub async fn delete(&self, key: &str) -> io::Result<()> {
+        match self {
+            Self::Local(local) => local.delete(key).await,
+            Self::S3(s3) => s3.delete(key).await,
+        }
+    }
+
+    /// List all of the files in storage.
+    pub async fn list(&self) -> io::Result<Vec<StoredFile>> {
+        match self {
+            Self::Local(local) => local.list().await,
+            Self::S3(s3) => s3.list().await,
+        }
+    }
+
+    /// Make a file in storage available on the local filesystem.
+    pub async fn fetch(&self, key: &str) -> io::Result<LocalFile> {
+        match self {
+            Self::Local(local) => Ok(LocalFile {
+                path: local.path(key),
+                temporary: false,
+            }),
+
+            Self::S3(s3) => Ok(LocalFile {
+                path: s3.fetch(key).await?,
+                temporary: true,
+            }),
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Storage of files in the cache directory.
+
+use std::{
+    io::{self, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use super::{StorageReader, StoredFile};
+
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    pub fn path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+
+    pub async fn size(&self, key: &str) -> io::Result<u64> {
+        let meta = tokio::fs::metadata(self.path(key)).await?;
+        Ok(meta.len())
+    }
+
+    pub async fn read(&self, key: &str) -> io::Result<StorageReader> {
+        let file = tokio::fs::File::open(self.path(key)).await?;
+        Ok(Box::new(file))
+    }
+
+    pub async fn read_range(&self, key: &str, start: u64, end: u64) -> io::Result<StorageReader> {
+        let mut file = tokio::fs::File::open(self.path(key)).await?;
+        file.seek(SeekFrom::Start(start)).await?;
+        Ok(Box::new(file.take(end - start + 1)))
+    }
+
+    pub async fn store(&self, path: &Path, key: &str) -> io::Result<()> {
+        // The temporary directory is inside the cache directory, so this is always a rename.
+        tokio::fs::rename(path, self.path(key)).await
+    }
+
+    pub async fn delete(&self, key: &str) -> io::Result<()> {
+        tokio::fs::remove_file(self.path(key)).await
+    }
+
+    pub async fn list(&self) -> io::Result<Vec<StoredFile>> {
+        let mut files = Vec::new();
+        let mut dir = tokio::fs::read_dir(&self.root).await?;
+
+        while let Some(entry) = dir.next_entry().await? {
+            let meta = entry.metadata().await?;
+
+            // Skip over the temporary directory.
+            if !meta.is_file() {
+                continue;
+            }
+
+            let key = entry.file_name().into_string().map_err(|filename| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("file name {filename:?} is not valid UTF-8"),
+                )
+            })?;
+
+            files.push(StoredFile {
+                key,
+                size: meta.len(),
+            });
+        }
+
+        Ok(files)
+    }
+}
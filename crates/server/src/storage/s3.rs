@@ -0,0 +1,352 @@
+//! Storage of files in an S3-compatible object store.
+
+use std::{
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use aws_sdk_s3::{
+    config::{Credentials, Region},
+    error::{DisplayErrorContext, SdkError},
+    primitives::{ByteStream, Length},
+    types::{CompletedMultipartUpload, CompletedPart},
+    Client,
+};
+
+use super::{StorageReader, StoredFile};
+use crate::args::Args;
+
+/// The size of the parts that larger files are sent in.
+///
+/// Files up to this size are sent in a single request, and larger files are sent in parts using a
+/// multipart upload. An upload can have at most 10,000 parts, so the part size is increased for
+/// files larger than 160 GiB.
+const PART_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The maximum number of parts in a multipart upload.
+const MAX_PARTS: u64 = 10_000;
+
+pub struct S3Storage {
+    client: Client,
+    bucket: String,
+    prefix: String,
+    temp_dir: PathBuf,
+}
+
+/// Convert an error from the S3 client into an I/O error, so that missing objects can be treated
+/// the same way as missing files.
+fn into_io_error<E>(err: SdkError<E>) -> io::Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let kind = match err.raw_response() {
+        Some(response) if response.status().as_u16() == 404 => io::ErrorKind::NotFound,
+        _ => io::ErrorKind::Other,
+    };
+
+    io::Error::new(kind, DisplayErrorContext(&err).to_string())
+}
+
+impl S3Storage {
+    pub fn new(
+        Args {
+            s3_bucket,
+            s3_region,
+            s3_endpoint,
+            s3_access_key_id,
+            s3_secret_access_key,
+            s3_prefix,
+            s3_path_style,
+            ..
+        }: &Args,
+        temp_dir: PathBuf,
+    ) -> anyhow::Result<Self> {
+        let bucket = s3_bucket
+            .clone()
+            .context("an S3 bucket must be given when using S3 storage")?;
+        let access_key_id = s3_access_key_id
+            .clone()
+            .context("an S3 access key ID must be given when using S3 storage")?;
+        let secret_access_key = s3_secret_access_key
+            .clone()
+            .context("an S3 secret access key must be given when using S3 storage")?;
+
+        let credentials = Credentials::new(access_key_id, secret_access_key, None, None, "parcel");
+        let mut config = aws_sdk_s3::Config::builder()
+            .behavior_version_latest()
+            .region(Region::new(s3_region.clone()))
+            .credentials_provider(credentials)
+            .force_path_style(*s3_path_style);
+
+        if let Some(endpoint) = s3_endpoint {
+            config = config.endpoint_url(endpoint);
+        }
+
+        // Keys are always separated from the prefix with a slash, whether or not the prefix was
+        // given with one.
+        let prefix = match s3_prefix.as_deref().map(|prefix| prefix.trim_matches('/')) {
+            Some(prefix) if !prefix.is_empty() => format!("{prefix}/"),
+            _ => String::new(),
+        };
+
+        tracing::info!(?bucket, ?prefix, endpoint = ?s3_endpoint, "Using S3 storage");
+
+        Ok(Self {
+            client: Client::from_conf(config.build()),
+            bucket,
+            prefix,
+            temp_dir,
+        })
+    }
+
+    fn object_key(&self, key: &str) -> String {
+        format!("{}{key}", self.prefix)
+    }
+
+    pub async fn size(&self, key: &str) -> io::Result<u64> {
+        let output = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .send()
+            .await
+            .map_err(into_io_error)?;
+
+        Ok(output.content_length().unwrap_or_default() as u64)
+    }
+
+    pub async fn read(&self, key: &str) -> io::Result<StorageReader> {
+        let output = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .send()
+            .await
+            .map_err(into_io_error)?;
+
+        Ok(Box::new(output.body.into_async_read()))
+    }
+
+    pub async fn read_range(&self, key: &str, start: u64, end: u64) -> io::Result<StorageReader> {
+        let output = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .range(format!("bytes={start}-{end}"))
+            .send()
+            .await
+            .map_err(into_io_error)?;
+
+        Ok(Box::new(output.body.into_async_read()))
+    }
+
+    pub async fn store(&self, path: &Path, key: &str) -> io::Result<()> {
+        let size = tokio::fs::metadata(path).await?.len();
+
+        if size <= PART_SIZE {
+            let body = ByteStream::from_path(path)
+                .await
+                .map_err(io::Error::other)?;
+            self.client
+                .put_object()
+                .bucket(&self.bucket)
+                .key(self.object_key(key))
+                .content_length(size as i64)
+                .body(body)
+                .send()
+                .await
+                .map_err(into_io_error)?;
+        } else {
+            self.multipart(
+                key,
+                size,
+                |upload_id, part_number, offset, length| async move {
+                    let body = ByteStream::read_from()
+                        .path(path)
+                        .offset(offset)
+                        .length(Length::Exact(length))
+                        .build()
+                        .await
+                        .map_err(io::Error::other)?;
+
+                    let output = self
+                        .client
+                        .upload_part()
+                        .bucket(&self.bucket)
+                        .key(self.object_key(key))
+                        .upload_id(upload_id)
+                        .part_number(part_number)
+                        .content_length(length as i64)
+                        .body(body)
+                        .send()
+                        .await
+                        .map_err(into_io_error)?;
+
+                    Ok(output.e_tag)
+                },
+            )
+            .await?;
+        }
+
+        tokio::fs::remove_file(path).await
+    }
+
+    pub async fn delete(&self, key: &str) -> io::Result<()> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .send()
+            .await
+            .map_err(into_io_error)?;
+
+        Ok(())
+    }
+
+    pub async fn list(&self) -> io::Result<Vec<StoredFile>> {
+        let mut files = Vec::new();
+        let mut continuation_token = None;
+
+        loop {
+            let output = self
+                .client
+                .list_objects_v2()
+                .bucket(&self.bucket)
+                .prefix(&self.prefix)
+                .set_continuation_token(continuation_token)
+                .send()
+                .await
+                .map_err(into_io_error)?;
+
+            for object in output.contents() {
+                let Some(key) = object.key().and_then(|key| key.strip_prefix(&self.prefix)) else {
+                    continue;
+                };
+
+                files.push(StoredFile {
+                    key: key.to_string(),
+                    size: object.size().unwrap_or_default() as u64,
+                });
+            }
+
+            continuation_token = output.next_continuation_token;
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Download a file to the temporary directory, returning the path to the downloaded file.
+    pub async fn fetch(&self, key: &str) -> io::Result<PathBuf> {
+        let path = self.temp_dir.join(format!("{}-{key}", nanoid::nanoid!()));
+        let mut reader = self.read(key).await?;
+        let mut file = tokio::fs::File::create(&path).await?;
+
+        if let Err(err) = tokio::io::copy(&mut reader, &mut file).await {
+            if let Err(err) = tokio::fs::remove_file(&path).await {
+                tracing::error!(?path, ?err, "Failed to delete partial copy of file");
+            }
+
+            return Err(err);
+        }
+
+        Ok(path)
+    }
+
+    /// Create an object from a number of parts using a multipart upload.
+    ///
+    /// The `send_part` function is called for each part with the ID of the multipart upload, the
+    /// number of the part, and the offset and length of the part. It returns the ETag of the part.
+    /// If any part fails, the multipart upload is aborted so that the parts are not left behind.
+    async fn multipart<F, Fut>(&self, key: &str, size: u64, mut send_part: F) -> io::Result<()>
+    where
+        F: FnMut(String, i32, u64, u64) -> Fut,
+        Fut: Future<Output = io::Result<Option<String>>>,
+    {
+        let object_key = self.object_key(key);
+        let output = self
+            .client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(&object_key)
+            .send()
+            .await
+            .map_err(into_io_error)?;
+
+        let Some(upload_id) = output.upload_id else {
+            return Err(io::Error::other("no upload ID for multipart upload"));
+        };
+
+        let part_size = PART_SIZE.max(size.div_ceil(MAX_PARTS));
+        let mut parts = Vec::new();
+        let mut offset = 0;
+
+        while offset < size {
+            let part_number = parts.len() as i32 + 1;
+            let length = part_size.min(size - offset);
+
+            match send_part(upload_id.clone(), part_number, offset, length).await {
+                Ok(e_tag) => parts.push(
+                    CompletedPart::builder()
+                        .part_number(part_number)
+                        .set_e_tag(e_tag)
+                        .build(),
+                ),
+
+                Err(err) => {
+                    tracing::error!(?err, key = object_key, part_number, "Failed to send part");
+                    self.abort_multipart(&object_key, upload_id).await;
+                    return Err(err);
+                }
+            }
+
+            offset += length;
+        }
+
+        let result = self
+            .client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(&object_key)
+            .upload_id(&upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+            .await;
+
+        if let Err(err) = result {
+            self.abort_multipart(&object_key, upload_id).await;
+            return Err(into_io_error(err));
+        }
+
+        Ok(())
+    }
+
+    async fn abort_multipart(&self, object_key: &str, upload_id: String) {
+        if let Err(err) = self
+            .client
+            .abort_multipart_upload()
+            .bucket(&self.bucket)
+            .key(object_key)
+            .upload_id(upload_id)
+            .send()
+            .await
+        {
+            tracing::error!(
+                err = %DisplayErrorContext(&err),
+                key = object_key,
+                "Failed to abort multipart upload"
+            );
+        }
+    }
+}
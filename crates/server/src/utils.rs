@@ -1,4 +1,11 @@
-use poem::session::Session;
+use std::net::IpAddr;
+
+use poem::{
+    session::Session,
+    web::{RealIp, RemoteAddr},
+    Addr,
+};
+use rand::{distr::Alphanumeric, Rng};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use validator::{ValidationError, ValidationErrors, ValidationErrorsKind};
 
@@ -14,6 +21,65 @@ impl SessionExt for Session {
     }
 }
 
+/// Get the client IP address, respecting the `trust_proxy` setting.
+///
+/// When `trust_proxy` is true, uses proxy headers (X-Forwarded-For, etc.).
+/// When false, uses only the direct peer address to prevent IP spoofing.
+pub fn get_client_ip(
+    trust_proxy: bool,
+    real_ip: &RealIp,
+    remote_addr: &RemoteAddr,
+) -> Option<IpAddr> {
+    if trust_proxy {
+        // RealIp already checks proxy headers and falls back to peer address
+        real_ip.0
+    } else {
+        // Only use the direct peer address, ignore proxy headers
+        match &remote_addr.0 {
+            Addr::SocketAddr(addr) => Some(addr.ip()),
+            _ => None,
+        }
+    }
+}
+
+/// Check a six-digit TOTP code against a user's base-32 encoded TOTP secret.
+pub fn verify_totp_code(secret: &str, code: &str) -> bool {
+    let code = code.trim();
+    if code.len() != 6 || !code.chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+
+    let Some(decoded_secret) = base32::decode(base32::Alphabet::Rfc4648 { padding: true }, secret)
+    else {
+        tracing::error!("TOTP secret was not valid base-32");
+        return false;
+    };
+
+    let seconds = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let expected = totp_lite::totp_custom::<totp_lite::Sha1>(
+        totp_lite::DEFAULT_STEP,
+        6,
+        &decoded_secret[..],
+        seconds,
+    );
+
+    code == expected
+}
+
+/// Generate a password that nobody knows, for a user that signs in through single sign-on or LDAP
+/// rather than with a password of their own.
+pub fn unusable_password() -> String {
+    rand::rng()
+        .sample_iter(Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
 pub fn validate_slug(slug: &str) -> Result<(), ValidationError> {
     if slug
         .chars()
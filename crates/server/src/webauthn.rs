@@ -0,0 +1,202 @@
+//! Security keys and passkeys with WebAuthn
+//!
+//! Users can register any number of WebAuthn credentials, each of which is either a security key or
+//! a passkey kept by their device or password manager. A credential can be used in two ways:
+//!
+//! 1. As a second factor, in place of a TOTP code, once the user has given their password.
+//! 2. To sign in without a password, when the credential is a passkey that the authenticator can
+//!    find by itself.
+//!
+//! Either way, the authenticator must verify the user, such as with a PIN or a fingerprint, so a
+//! stolen security key is not enough to sign in.
+//!
+//! Credentials are bound to the domain that they were registered with, so WebAuthn is only
+//! available when the base URL of Parcel has been configured.
+
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use webauthn_rs::prelude::{
+    AuthenticationResult, CreationChallengeResponse, DiscoverableAuthentication, DiscoverableKey,
+    Passkey, PasskeyAuthentication, PasskeyRegistration, PublicKeyCredential,
+    RegisterPublicKeyCredential, RequestChallengeResponse, Url, Webauthn, WebauthnBuilder,
+};
+
+use parcel_model::{types::Key, user::User, webauthn::WebauthnCredential};
+
+use crate::args::Args;
+
+pub struct Passkeys {
+    webauthn: Webauthn,
+}
+
+/// Get the credential ID of a passkey, as it is stored in the database.
+fn credential_id(passkey: &Passkey) -> String {
+    URL_SAFE_NO_PAD.encode(passkey.cred_id())
+}
+
+/// Parse the stored passkeys of some credentials, skipping any that cannot be parsed.
+fn parse_passkeys(credentials: &[WebauthnCredential]) -> Vec<Passkey> {
+    credentials
+        .iter()
+        .filter_map(|credential| match serde_json::from_str(&credential.passkey) {
+            Ok(passkey) => Some(passkey),
+            Err(err) => {
+                tracing::error!(?err, %credential.id, "Failed to parse stored WebAuthn credential");
+                None
+            }
+        })
+        .collect()
+}
+
+/// Find the credential that was used to sign in, and update its stored passkey from the result.
+fn used_credential(
+    credentials: Vec<WebauthnCredential>,
+    result: &AuthenticationResult,
+) -> anyhow::Result<(WebauthnCredential, String)> {
+    let used = URL_SAFE_NO_PAD.encode(result.cred_id());
+    let credential = credentials
+        .into_iter()
+        .find(|credential| credential.credential_id == used)
+        .context("the credential that was used is not registered")?;
+
+    let mut passkey = serde_json::from_str::<Passkey>(&credential.passkey)
+        .context("failed to parse stored WebAuthn credential")?;
+    passkey.update_credential(result);
+
+    let passkey = serde_json::to_string(&passkey).context("failed to serialize passkey")?;
+    Ok((credential, passkey))
+}
+
+impl Passkeys {
+    /// Create the relying party from the base URL, or `None` if no base URL has been configured.
+    pub fn new(Args { base_url, .. }: &Args) -> anyhow::Result<Option<Self>> {
+        let Some(base_url) = base_url else {
+            tracing::info!("WebAuthn is not available, as no base URL has been configured");
+            return Ok(None);
+        };
+
+        let origin = Url::parse(base_url).context("the base URL is not valid")?;
+        let rp_id = origin
+            .host_str()
+            .context("the base URL must have a host name")?
+            .to_string();
+
+        let webauthn = WebauthnBuilder::new(&rp_id, &origin)
+            .and_then(|builder| builder.rp_name("Parcel").build())
+            .context("failed to configure WebAuthn")?;
+
+        tracing::info!(%rp_id, "Using WebAuthn for security keys and passkeys");
+        Ok(Some(Self { webauthn }))
+    }
+
+    /// Start registering a new credential for a user, excluding the credentials they already have.
+    pub fn start_registration(
+        &self,
+        user: &User,
+        existing: &[WebauthnCredential],
+    ) -> anyhow::Result<(CreationChallengeResponse, PasskeyRegistration)> {
+        let exclude = parse_passkeys(existing)
+            .iter()
+            .map(|passkey| passkey.cred_id().clone())
+            .collect();
+
+        self.webauthn
+            .start_passkey_registration(user.id.into(), &user.username, &user.name, Some(exclude))
+            .context("failed to start WebAuthn registration")
+    }
+
+    /// Finish registering a credential, giving the new credential to store.
+    pub fn finish_registration(
+        &self,
+        user: Key<User>,
+        name: &str,
+        response: &RegisterPublicKeyCredential,
+        state: &PasskeyRegistration,
+    ) -> anyhow::Result<WebauthnCredential> {
+        let passkey = self
+            .webauthn
+            .finish_passkey_registration(response, state)
+            .context("failed to verify WebAuthn registration")?;
+
+        Ok(WebauthnCredential::new(
+            user,
+            name,
+            credential_id(&passkey),
+            serde_json::to_string(&passkey).context("failed to serialize passkey")?,
+        ))
+    }
+
+    /// Start signing in with one of the given credentials of a user.
+    pub fn start_authentication(
+        &self,
+        credentials: &[WebauthnCredential],
+    ) -> anyhow::Result<(RequestChallengeResponse, PasskeyAuthentication)> {
+        let passkeys = parse_passkeys(credentials);
+        if passkeys.is_empty() {
+            anyhow::bail!("the user has no usable WebAuthn credentials");
+        }
+
+        self.webauthn
+            .start_passkey_authentication(&passkeys)
+            .context("failed to start WebAuthn authentication")
+    }
+
+    /// Finish signing in with one of the credentials of a user, giving the credential that was used
+    /// along with its updated passkey.
+    pub fn finish_authentication(
+        &self,
+        credentials: Vec<WebauthnCredential>,
+        response: &PublicKeyCredential,
+        state: &PasskeyAuthentication,
+    ) -> anyhow::Result<(WebauthnCredential, String)> {
+        let result = self
+            .webauthn
+            .finish_passkey_authentication(response, state)
+            .context("failed to verify WebAuthn authentication")?;
+
+        used_credential(credentials, &result)
+    }
+
+    /// Start signing in without a username, where the authenticator chooses the passkey.
+    pub fn start_discoverable(
+        &self,
+    ) -> anyhow::Result<(RequestChallengeResponse, DiscoverableAuthentication)> {
+        self.webauthn
+            .start_discoverable_authentication()
+            .context("failed to start WebAuthn authentication")
+    }
+
+    /// Find the user that a passkey chosen by the authenticator belongs to.
+    pub fn identify_discoverable(
+        &self,
+        response: &PublicKeyCredential,
+    ) -> anyhow::Result<Key<User>> {
+        let (user, _) = self
+            .webauthn
+            .identify_discoverable_authentication(response)
+            .context("failed to identify user from WebAuthn response")?;
+
+        Ok(Key::from(user))
+    }
+
+    /// Finish signing in with a passkey chosen by the authenticator, giving the credential that was
+    /// used along with its updated passkey.
+    pub fn finish_discoverable(
+        &self,
+        credentials: Vec<WebauthnCredential>,
+        response: &PublicKeyCredential,
+        state: DiscoverableAuthentication,
+    ) -> anyhow::Result<(WebauthnCredential, String)> {
+        let keys = parse_passkeys(&credentials)
+            .iter()
+            .map(DiscoverableKey::from)
+            .collect::<Vec<_>>();
+
+        let result = self
+            .webauthn
+            .finish_discoverable_authentication(response, state, &keys)
+            .context("failed to verify WebAuthn authentication")?;
+
+        used_credential(credentials, &result)
+    }
+}
@@ -0,0 +1,255 @@
+//! Synchronisation of teams with LDAP groups
+//!
+//! When groups in the LDAP directory have been mapped to teams (see [`crate::ldap`]), this worker
+//! periodically makes the members of each team match the members of its group:
+//!
+//! 1. A team that does not exist is created, named after its group.
+//! 2. Users that are in the group, and that have signed in with LDAP, are added to the team with the
+//!    permissions given for the group. Members whose permissions differ are updated.
+//! 3. Users that have signed in with LDAP, but that are no longer in the group, are removed from the
+//!    team.
+//!
+//! Members of a team that do not sign in with LDAP are left alone, so a team can have members that
+//! are managed by hand alongside those that come from the directory. Each change to a team is
+//! recorded in the audit log.
+
+use std::collections::HashMap;
+
+use anyhow::Context;
+use serde_json::json;
+use time::OffsetDateTime;
+use tokio::{sync::mpsc::Sender, task::JoinHandle};
+
+use parcel_model::{
+    audit::{AuditAction, AuditEvent, AuditTarget},
+    team::{Team, TeamMember},
+    types::Key,
+    user::User,
+};
+
+use crate::{env::Env, ldap::LdapTeam};
+
+pub enum LdapCommand {
+    Stop,
+}
+
+#[derive(Debug, Clone)]
+pub struct LdapWorker {
+    sender: Sender<LdapCommand>,
+}
+
+impl LdapWorker {
+    pub async fn stop(self) -> anyhow::Result<()> {
+        self.sender
+            .send(LdapCommand::Stop)
+            .await
+            .context("failed to send stop command to LDAP worker")?;
+        Ok(())
+    }
+}
+
+pub async fn start_worker(env: Env) -> anyhow::Result<(LdapWorker, JoinHandle<()>)> {
+    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+
+    // Without any groups mapped to teams, there is nothing to do but wait to be stopped.
+    let interval = env
+        .ldap
+        .as_ref()
+        .filter(|ldap| !ldap.teams.is_empty())
+        .map(|ldap| ldap.sync_interval);
+
+    let task = tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                Some(command) = rx.recv() => {
+                    match command {
+                        LdapCommand::Stop => {
+                            tracing::info!("Stopping LDAP worker");
+                            break;
+                        }
+                    }
+                },
+
+                _ = tokio::time::sleep(interval.unwrap_or_default()), if interval.is_some() => {
+                    if let Err(err) = sync_teams(&env).await {
+                        tracing::error!("Failed to synchronise teams with LDAP groups: {:?}", err);
+                    }
+                },
+            }
+        }
+    });
+
+    Ok((LdapWorker { sender: tx }, task))
+}
+
+/// Synchronise the members of each team that is mapped to an LDAP group.
+pub async fn sync_teams(env: &Env) -> anyhow::Result<()> {
+    let Some(ref ldap) = env.ldap else {
+        return Ok(());
+    };
+
+    if ldap.teams.is_empty() {
+        return Ok(());
+    }
+
+    let groups = ldap.get_team_members().await?;
+    let users = User::get_ldap_users(&env.pool)
+        .await
+        .context("failed to get users that sign in with LDAP")?;
+
+    // Distinguished names are compared without regard to case.
+    let users = users
+        .iter()
+        .filter_map(|user| Some((user.ldap_dn.as_ref()?.to_lowercase(), user)))
+        .collect::<HashMap<_, _>>();
+
+    for (mapping, members) in groups {
+        let Some(members) = members else {
+            continue;
+        };
+
+        let wanted = members
+            .iter()
+            .filter_map(|dn| users.get(dn).copied())
+            .collect::<Vec<_>>();
+
+        if let Err(err) = sync_team(env, mapping, &wanted, &users).await {
+            tracing::error!(
+                ?err,
+                group = %mapping.group,
+                team = %mapping.slug,
+                "Failed to synchronise team with LDAP group"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the team that is mapped to a group, creating it if it does not exist.
+async fn get_or_create_team(env: &Env, mapping: &LdapTeam) -> anyhow::Result<Team> {
+    if let Some(team) = Team::get_by_slug(&env.pool, &mapping.slug)
+        .await
+        .context("failed to get team by slug")?
+    {
+        return Ok(team);
+    }
+
+    let team = Team {
+        id: Key::new(),
+        name: mapping.group.clone(),
+        slug: mapping.slug.clone(),
+        limit: None,
+        enabled: true,
+        created_at: OffsetDateTime::now_utc(),
+        created_by: None,
+    };
+
+    team.create(&env.pool)
+        .await
+        .context("failed to create team for LDAP group")?;
+
+    tracing::info!(
+        %team.id,
+        group = %mapping.group,
+        slug = %mapping.slug,
+        "Created team for LDAP group"
+    );
+    Ok(team)
+}
+
+/// Make the members of a team that sign in with LDAP match the members of its group.
+async fn sync_team(
+    env: &Env,
+    mapping: &LdapTeam,
+    wanted: &[&User],
+    ldap_users: &HashMap<String, &User>,
+) -> anyhow::Result<()> {
+    let team = get_or_create_team(env, mapping).await?;
+    let current = TeamMember::get_for_team(&env.pool, team.id)
+        .await
+        .context("failed to get team members")?;
+
+    let permissions = (mapping.can_edit, mapping.can_delete, mapping.can_config);
+    let mut added = Vec::new();
+    let mut updated = Vec::new();
+    let mut changed = Vec::new();
+    let mut removed = Vec::new();
+
+    for user in wanted {
+        match current.iter().find(|member| member.user == user.id) {
+            Some(member) => {
+                if (member.can_edit, member.can_delete, member.can_config) != permissions {
+                    updated.push((
+                        user.id,
+                        mapping.can_edit,
+                        mapping.can_delete,
+                        mapping.can_config,
+                    ));
+                    changed.push(user.username.as_str());
+                }
+            }
+
+            None => {
+                user.join_team(
+                    &env.pool,
+                    team.id,
+                    mapping.can_edit,
+                    mapping.can_delete,
+                    mapping.can_config,
+                )
+                .await
+                .context("failed to add user to team")?;
+                added.push(user.username.as_str());
+            }
+        }
+    }
+
+    TeamMember::batch_update_permissions(&env.pool, team.id, &updated)
+        .await
+        .context("failed to update permissions of team members")?;
+
+    for user in ldap_users.values() {
+        let is_member = current.iter().any(|member| member.user == user.id);
+        if is_member && !wanted.iter().any(|wanted| wanted.id == user.id) {
+            user.leave_team(&env.pool, team.id)
+                .await
+                .context("failed to remove user from team")?;
+            removed.push(user.username.as_str());
+        }
+    }
+
+    if added.is_empty() && updated.is_empty() && removed.is_empty() {
+        return Ok(());
+    }
+
+    tracing::info!(
+        %team.id,
+        added = added.len(),
+        updated = updated.len(),
+        removed = removed.len(),
+        "Synchronised team with LDAP group"
+    );
+
+    let mut event = AuditEvent::new(
+        None,
+        None,
+        AuditAction::TeamSync,
+        AuditTarget::Team(team.id),
+    );
+    event.details = Some(
+        json!({
+            "group": mapping.group,
+            "added": added,
+            "updated": changed,
+            "removed": removed,
+        })
+        .to_string(),
+    );
+
+    if let Err(err) = event.create(&env.pool).await {
+        tracing::error!(?err, %team.id, "Failed to record audit event for team synchronisation");
+    }
+
+    Ok(())
+}
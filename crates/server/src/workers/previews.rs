@@ -21,15 +21,20 @@
 //!    upload.
 //! 3. If the worker sees that the upload is bigger than the configured maximum size for previews,
 //!    it will skip the upload.
+//!
+//! Alongside the preview, the worker also extracts the text of documents (such as PDFs and office
+//! documents) for the full-text search index, using the `extractors` from the configuration. Once
+//! this has been attempted the `text_extracted` flag is set, whether or not any text was found, so
+//! that the extraction is not repeated.
 
 use std::sync::Arc;
 
 use anyhow::Context;
 use tokio::{process::Command, sync::mpsc::Sender, task::JoinHandle};
 
-use parcel_model::{types::Key, upload::Upload};
+use parcel_model::{types::Key, upload::Upload, webhook::WebhookEvent};
 
-use crate::env::Env;
+use crate::{env::Env, storage::LocalFile, workers::webhooks::queue_upload_event};
 
 mod config;
 
@@ -103,9 +108,13 @@ pub async fn start_worker(env: Env) -> anyhow::Result<(PreviewWorker, JoinHandle
 
                 _ = tokio::time::sleep(env.preview_generation_interval) => {
                     let config = Arc::clone(&config);
-                    if let Err(e) = scan_for_uploads(config, env.clone()).await {
+                    if let Err(e) = scan_for_uploads(Arc::clone(&config), env.clone()).await {
                         tracing::error!("Failed to scan for uploads to generate previews: {}", e);
                     }
+
+                    if let Err(e) = scan_for_text(config, env.clone()).await {
+                        tracing::error!("Failed to scan for uploads to extract text: {}", e);
+                    }
                 },
             }
         }
@@ -129,8 +138,9 @@ async fn scan_for_uploads(config: Arc<config::PreviewConfig>, env: Env) -> anyho
         let count = uploads.len() as u32;
         tracing::info!("Found {count} uploads that need preview generation");
 
-        for upload in uploads {
-            generate_preview(&config, &env, upload).await;
+        for mut upload in uploads {
+            generate_preview(&config, &env, &mut upload).await;
+            extract_text(&config, &env, &mut upload).await;
         }
 
         if count < SCAN_MAX_SIZE {
@@ -142,24 +152,51 @@ async fn scan_for_uploads(config: Arc<config::PreviewConfig>, env: Env) -> anyho
     }
 }
 
+async fn scan_for_text(config: Arc<config::PreviewConfig>, env: Env) -> anyhow::Result<()> {
+    let mut offset = 0;
+
+    loop {
+        let uploads = Upload::get_all_without_text(&env.pool, offset, SCAN_MAX_SIZE).await?;
+        if uploads.is_empty() {
+            tracing::info!("No uploads found that need text extraction");
+            return Ok(());
+        }
+
+        let count = uploads.len() as u32;
+        tracing::info!("Found {count} uploads that need text extraction");
+
+        for mut upload in uploads {
+            extract_text(&config, &env, &mut upload).await;
+        }
+
+        if count < SCAN_MAX_SIZE {
+            tracing::info!("Processed all uploads that needed text extraction");
+            return Ok(());
+        }
+
+        offset += count;
+    }
+}
+
 async fn generate_previews(
     config: Arc<config::PreviewConfig>,
     env: Env,
     uploads: Vec<Key<Upload>>,
 ) -> anyhow::Result<()> {
     for id in uploads {
-        let Some(upload) = Upload::get(&env.pool, id).await? else {
+        let Some(mut upload) = Upload::get(&env.pool, id).await? else {
             tracing::warn!("Upload with ID {} not found, skipping", id);
             continue;
         };
 
-        generate_preview(&config, &env, upload).await;
+        generate_preview(&config, &env, &mut upload).await;
+        extract_text(&config, &env, &mut upload).await;
     }
 
     Ok(())
 }
 
-async fn generate_preview(config: &config::PreviewConfig, env: &Env, mut upload: Upload) {
+async fn generate_preview(config: &config::PreviewConfig, env: &Env, upload: &mut Upload) {
     if upload.has_preview {
         tracing::info!("Upload {} already has a preview, skipping", upload.id);
         return;
@@ -177,8 +214,15 @@ async fn generate_preview(config: &config::PreviewConfig, env: &Env, mut upload:
         }
     }
 
+    // The file is only fetched from storage when it is needed, as it may need to be downloaded.
+    let mut input = None;
+
     if upload.mime_type.is_none() {
-        if let Err(err) = ascertain_mime_type(env, &mut upload).await {
+        let Some(file) = fetch_upload(env, upload).await else {
+            return;
+        };
+
+        if let Err(err) = ascertain_mime_type(env, upload, file.path()).await {
             tracing::error!(
                 "Failed to ascertain MIME type for upload {}: {}",
                 upload.id,
@@ -186,6 +230,8 @@ async fn generate_preview(config: &config::PreviewConfig, env: &Env, mut upload:
             );
             return;
         }
+
+        input = Some(file);
     }
 
     let Some(ref mime_type) = upload.mime_type else {
@@ -226,25 +272,221 @@ async fn generate_preview(config: &config::PreviewConfig, env: &Env, mut upload:
         return;
     }
 
-    if !previewer.run_commands(env, &mut upload).await {
+    // Uploads with the same content share a preview, so there is nothing to generate if another
+    // upload of the same blob already has one.
+    let preview_key = format!("{}.preview", upload.blob);
+    match env.storage.exists(&preview_key).await {
+        Ok(true) => {
+            tracing::info!(
+                "Upload {} shares a preview with blob {}, skipping generation",
+                upload.id,
+                upload.blob
+            );
+
+            mark_previewed(env, upload).await;
+            return;
+        }
+
+        Ok(false) => {}
+
+        Err(err) => {
+            tracing::error!(
+                "Failed to check for existing preview for upload {}: {}",
+                upload.id,
+                err
+            );
+            return;
+        }
+    }
+
+    let input = match input {
+        Some(input) => input,
+        None => match fetch_upload(env, upload).await {
+            Some(input) => input,
+            None => return,
+        },
+    };
+
+    // The preview is written to the temporary directory, and then moved into storage once all the
+    // commands have succeeded.
+    let output = env
+        .cache_dir
+        .join("temp")
+        .join(format!("{}.preview", upload.slug));
+
+    if let Err(error_message) = previewer
+        .run_commands(env, upload, input.path(), &output)
+        .await
+    {
         tracing::warn!("Previewer failed to run commands for upload {}", upload.id);
+        upload
+            .set_preview_error(&env.pool, error_message)
+            .await
+            .unwrap_or_else(|err| {
+                tracing::error!(
+                    "Failed to set preview error for upload {}: {}",
+                    upload.id,
+                    err
+                );
+            });
+
+        if output.exists() {
+            if let Err(err) = tokio::fs::remove_file(&output).await {
+                tracing::error!(
+                    "Failed to delete partial preview for upload {}: {}",
+                    upload.id,
+                    err
+                );
+            }
+        }
+
+        return;
+    }
+
+    if let Err(err) = env.storage.store(&output, &preview_key).await {
+        tracing::error!(
+            "Failed to move preview for upload {} into storage: {}",
+            upload.id,
+            err
+        );
         return;
     }
 
+    mark_previewed(env, upload).await;
+}
+
+/// Record that an upload has a preview, and queue the `upload.previewed` event for any webhooks.
+async fn mark_previewed(env: &Env, upload: &mut Upload) {
+    if let Err(err) = upload.set_has_preview(&env.pool, true).await {
+        tracing::error!(
+            "Failed to set has_preview for upload {}: {}",
+            upload.id,
+            err
+        );
+        return;
+    }
+
+    queue_upload_event(env, WebhookEvent::UploadPreviewed, upload, None).await;
+}
+
+/// The maximum number of bytes of extracted text that are added to the search index.
+const MAX_TEXT_LENGTH: usize = 1024 * 1024;
+
+async fn extract_text(config: &config::PreviewConfig, env: &Env, upload: &mut Upload) {
+    if upload.text_extracted {
+        return;
+    }
+
+    let Some(ref mime_type) = upload.mime_type else {
+        tracing::warn!(
+            "Upload {} has no MIME type, skipping text extraction",
+            upload.id
+        );
+        return;
+    };
+
+    let extractor = config
+        .find_extractor(mime_type)
+        .filter(|extractor| !extractor.is_empty());
+    let too_large = env
+        .max_preview_size
+        .is_some_and(|max_preview_size| upload.size > max_preview_size as i64);
+
+    let text = match extractor {
+        Some(_) if too_large => {
+            tracing::info!(
+                "Upload {} is too large for text extraction ({} bytes), skipping",
+                upload.id,
+                upload.size
+            );
+
+            None
+        }
+
+        Some(extractor) => run_extractor(env, extractor, upload).await,
+        None => None,
+    };
+
     upload
-        .set_has_preview(&env.pool, true)
+        .set_text(&env.pool, text.as_deref())
         .await
         .unwrap_or_else(|err| {
             tracing::error!(
-                "Failed to set has_preview for upload {}: {}",
+                "Failed to set extracted text for upload {}: {}",
                 upload.id,
                 err
             );
         });
 }
 
-async fn ascertain_mime_type(env: &Env, upload: &mut Upload) -> anyhow::Result<()> {
-    let path = env.cache_dir.join(&upload.slug);
+async fn run_extractor(
+    env: &Env,
+    extractor: &config::Previewer,
+    upload: &Upload,
+) -> Option<String> {
+    let input = fetch_upload(env, upload).await?;
+
+    // The text is written to the temporary directory, and then read into the search index once all
+    // the commands have succeeded.
+    let output = env
+        .cache_dir
+        .join("temp")
+        .join(format!("{}.txt", upload.slug));
+
+    let result = extractor
+        .run_commands(env, upload, input.path(), &output)
+        .await;
+
+    let text = match result {
+        Ok(()) => match tokio::fs::read(&output).await {
+            Ok(mut text) => {
+                text.truncate(MAX_TEXT_LENGTH);
+                Some(String::from_utf8_lossy(&text).into_owned())
+            }
+
+            Err(err) => {
+                tracing::error!(
+                    "Failed to read extracted text for upload {}: {}",
+                    upload.id,
+                    err
+                );
+                None
+            }
+        },
+
+        Err(_) => {
+            tracing::warn!("Extractor failed to run commands for upload {}", upload.id);
+            None
+        }
+    };
+
+    if output.exists() {
+        if let Err(err) = tokio::fs::remove_file(&output).await {
+            tracing::error!(
+                "Failed to delete extracted text for upload {}: {}",
+                upload.id,
+                err
+            );
+        }
+    }
+
+    text
+}
+
+async fn fetch_upload(env: &Env, upload: &Upload) -> Option<LocalFile> {
+    match env.storage.fetch(&upload.blob).await {
+        Ok(file) => Some(file),
+        Err(err) => {
+            tracing::error!("Failed to fetch file for upload {}: {}", upload.id, err);
+            None
+        }
+    }
+}
+
+/// Find the MIME type of a file from its contents, using the `file` command.
+///
+/// Returns `None` if the type could not be found.
+pub async fn detect_mime_type(path: &std::path::Path) -> std::io::Result<Option<String>> {
     let output = Command::new("file")
         .arg("--mime-type")
         .arg("-b")
@@ -253,10 +495,18 @@ async fn ascertain_mime_type(env: &Env, upload: &mut Upload) -> anyhow::Result<(
         .await?;
     let mime = String::from_utf8_lossy(&output.stdout).trim().to_string();
 
-    if mime.is_empty() {
+    Ok(Some(mime).filter(|mime| !mime.is_empty()))
+}
+
+async fn ascertain_mime_type(
+    env: &Env,
+    upload: &mut Upload,
+    path: &std::path::Path,
+) -> anyhow::Result<()> {
+    let Some(mime) = detect_mime_type(path).await? else {
         tracing::warn!("Failed to ascertain MIME type for upload {}", upload.id);
         return Ok(());
-    }
+    };
 
     tracing::info!("Ascertained MIME type for upload {}: {}", upload.id, mime);
     upload.set_mime_type(&env.pool, mime.as_str()).await?;
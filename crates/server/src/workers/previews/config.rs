@@ -12,6 +12,10 @@ use crate::env::Env;
 #[derive(Debug, Default, Deserialize)]
 pub struct PreviewConfig {
     previewers: Vec<Previewer>,
+    /// The commands used to extract the text of an upload for the search index. These have the
+    /// same form as the previewers, except that the `output` is the extracted text.
+    #[serde(default)]
+    extractors: Vec<Previewer>,
 }
 
 impl PreviewConfig {
@@ -34,6 +38,13 @@ impl PreviewConfig {
             .filter(|previewer| previewer.is_enabled())
             .find(|previewer| previewer.matcher.matches(mime_type))
     }
+
+    pub fn find_extractor(&self, mime_type: &str) -> Option<&Previewer> {
+        self.extractors
+            .iter()
+            .filter(|extractor| extractor.is_enabled())
+            .find(|extractor| extractor.matcher.matches(mime_type))
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -57,20 +68,29 @@ impl Previewer {
         self.commands.is_empty()
     }
 
-    pub async fn run_commands(&self, env: &Env, upload: &mut Upload) -> bool {
+    /// Run the commands to process the file at `input`, writing the result to `output`.
+    ///
+    /// If a command fails, the error message is returned.
+    pub async fn run_commands(
+        &self,
+        env: &Env,
+        upload: &Upload,
+        input: &Path,
+        output: &Path,
+    ) -> Result<(), String> {
         for command in &self.commands {
-            if !command.run_command(env, upload).await {
+            if let Err(err) = command.run_command(env, upload, input, output).await {
                 tracing::warn!(
                     "Command failed for upload {} with previewer {:?}",
                     upload.id,
                     self.matcher
                 );
 
-                return false;
+                return Err(err);
             }
         }
 
-        true
+        Ok(())
     }
 }
 
@@ -128,15 +148,21 @@ impl PreviewerCommand {
         }
     }
 
-    fn build_command(&self, env: &Env, upload: &Upload) -> Option<Command> {
+    fn build_command(
+        &self,
+        env: &Env,
+        upload: &Upload,
+        input: &Path,
+        output: &Path,
+    ) -> Option<Command> {
         let Some(cmd) = self.select_command() else {
             tracing::warn!("No command found for platform {}", std::env::consts::OS);
             return None;
         };
 
-        let input = env.cache_dir.join(&upload.slug);
+        let input = input.to_path_buf();
         let input_base = upload.slug.clone();
-        let output = env.cache_dir.join(format!("{}.preview", upload.slug));
+        let output = output.to_path_buf();
         let temp_dir = env.cache_dir.join("temp");
 
         let context = move |var: &str| -> Result<Option<Cow<'static, str>>, std::env::VarError> {
@@ -174,14 +200,20 @@ impl PreviewerCommand {
         Some(command)
     }
 
-    async fn run_command(&self, env: &Env, upload: &mut Upload) -> bool {
-        let Some(mut command) = self.build_command(env, upload) else {
+    async fn run_command(
+        &self,
+        env: &Env,
+        upload: &Upload,
+        input: &Path,
+        output: &Path,
+    ) -> Result<(), String> {
+        let Some(mut command) = self.build_command(env, upload, input, output) else {
             tracing::warn!(
                 "Failed to build command for previewer for upload {}",
                 upload.id
             );
 
-            return false;
+            return Err("Failed to build preview command".to_string());
         };
 
         let output = match command.output().await {
@@ -194,20 +226,7 @@ impl PreviewerCommand {
                     err
                 );
 
-                let error_message = format!("Failed to execute preview command: {err}");
-
-                upload
-                    .set_preview_error(&env.pool, error_message)
-                    .await
-                    .unwrap_or_else(|err| {
-                        tracing::error!(
-                            "Failed to set preview error for upload {}: {}",
-                            upload.id,
-                            err
-                        );
-                    });
-
-                return false;
+                return Err(format!("Failed to execute preview command: {err}"));
             }
         };
 
@@ -221,18 +240,7 @@ impl PreviewerCommand {
                 error_message
             );
 
-            upload
-                .set_preview_error(&env.pool, error_message)
-                .await
-                .unwrap_or_else(|err| {
-                    tracing::error!(
-                        "Failed to set preview error for upload {}: {}",
-                        upload.id,
-                        err
-                    );
-                });
-
-            return false;
+            return Err(error_message);
         }
 
         tracing::info!(
@@ -240,7 +248,6 @@ impl PreviewerCommand {
             upload.id
         );
 
-        true
+        Ok(())
     }
 }
-
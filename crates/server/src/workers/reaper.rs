@@ -0,0 +1,229 @@
+//! Removal of expired and exhausted uploads
+//!
+//! Once an upload has passed its expiry date, or has no remaining downloads, it can no longer be
+//! downloaded. This worker periodically applies the configured retention policy to these uploads,
+//! so that they do not stay in the database and the cache forever.
+//!
+//! Expired and exhausted uploads each have their own policy:
+//!
+//! 1. `keep` leaves the upload alone, which is the default.
+//! 2. `hide` removes the upload from the upload lists, but keeps it in the database and the cache.
+//!    Editing the upload or resetting its downloads will make it visible again.
+//! 3. `delete` removes the upload from the database and deletes the cached file and preview.
+//!
+//! Expired uploads are only acted upon once the configured number of days has passed since their
+//! expiry date, giving the owner the opportunity to extend it.
+//!
+//! Regardless of the retention policy, the worker also removes resumable upload sessions that have
+//! been abandoned, along with the partial files that were received for them.
+//!
+//! Before applying the retention policy, the worker also queues the `upload.expired` webhook event
+//! for each upload that has passed its expiry date since the last time it ran, and warns the owners
+//! of uploads and share links that are about to expire by email.
+//!
+//! When the worker starts, it finishes deleting any blobs that were still being deleted when the
+//! server was stopped, as uploads with the same content would otherwise wait for them forever.
+
+use anyhow::Context;
+use tokio::{sync::mpsc::Sender, task::JoinHandle};
+
+use parcel_model::{
+    blob::Blob, upload::Upload, upload_session::UploadSession, webhook::WebhookEvent,
+};
+
+use crate::{
+    app::handlers::utils::{delete_blob, delete_upload_session_file, release_blob},
+    env::Env,
+    notifications::notify_expiring,
+    workers::webhooks::queue_upload_event,
+};
+
+/// What to do with an upload that can no longer be downloaded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RetentionPolicy {
+    /// Leave the upload alone.
+    #[default]
+    Keep,
+    /// Hide the upload from the upload lists.
+    Hide,
+    /// Delete the upload and its cached files.
+    Delete,
+}
+
+pub enum ReaperCommand {
+    Stop,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReaperWorker {
+    sender: Sender<ReaperCommand>,
+}
+
+impl ReaperWorker {
+    pub async fn stop(self) -> anyhow::Result<()> {
+        self.sender
+            .send(ReaperCommand::Stop)
+            .await
+            .context("failed to send stop command to reaper worker")?;
+        Ok(())
+    }
+}
+
+pub async fn start_worker(env: Env) -> anyhow::Result<(ReaperWorker, JoinHandle<()>)> {
+    if env.expired_retention == RetentionPolicy::Keep
+        && env.exhausted_retention == RetentionPolicy::Keep
+    {
+        tracing::info!("Retention policy keeps all uploads; the reaper will only remove abandoned upload sessions");
+    }
+
+    // This runs before the server accepts any uploads, so nothing can be waiting on these blobs.
+    let deleting = Blob::get_deleting(&env.pool)
+        .await
+        .context("failed to get blobs that were being deleted")?;
+    if !deleting.is_empty() {
+        tracing::info!(count = deleting.len(), "Finishing deletion of blobs");
+        for key in deleting {
+            delete_blob(&env, &key).await;
+        }
+    }
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+
+    let task = tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                Some(command) = rx.recv() => {
+                    match command {
+                        ReaperCommand::Stop => {
+                            tracing::info!("Stopping reaper worker");
+                            break;
+                        }
+                    }
+                },
+
+                _ = tokio::time::sleep(env.reaper_interval) => {
+                    if let Err(err) = reap_upload_sessions(&env).await {
+                        tracing::error!("Failed to reap abandoned upload sessions: {}", err);
+                    }
+
+                    if let Err(err) = notify_expired_uploads(&env).await {
+                        tracing::error!("Failed to queue webhooks for expired uploads: {}", err);
+                    }
+
+                    if let Err(err) = notify_expiring(&env).await {
+                        tracing::error!("Failed to send notifications for expiring uploads: {}", err);
+                    }
+
+                    if let Err(err) = reap_uploads(&env).await {
+                        tracing::error!("Failed to reap expired and exhausted uploads: {}", err);
+                    }
+                },
+            }
+        }
+    });
+
+    Ok((ReaperWorker { sender: tx }, task))
+}
+
+async fn reap_upload_sessions(env: &Env) -> anyhow::Result<()> {
+    let deleted = UploadSession::delete_expired(&env.pool)
+        .await
+        .context("failed to delete expired upload sessions")?;
+
+    if deleted.is_empty() {
+        return Ok(());
+    }
+
+    let count = deleted.len();
+    for id in deleted {
+        delete_upload_session_file(env, id).await;
+    }
+
+    tracing::info!(count, "Deleted abandoned upload sessions");
+    Ok(())
+}
+
+async fn notify_expired_uploads(env: &Env) -> anyhow::Result<()> {
+    let uploads = Upload::take_newly_expired(&env.pool)
+        .await
+        .context("failed to get newly expired uploads")?;
+
+    for upload in &uploads {
+        queue_upload_event(env, WebhookEvent::UploadExpired, upload, None).await;
+    }
+
+    if !uploads.is_empty() {
+        tracing::info!(count = uploads.len(), "Queued webhooks for expired uploads");
+    }
+
+    Ok(())
+}
+
+async fn reap_uploads(env: &Env) -> anyhow::Result<()> {
+    let days = env.expired_retention_days;
+    match env.expired_retention {
+        RetentionPolicy::Keep => {}
+
+        RetentionPolicy::Hide => {
+            let count = Upload::hide_expired(&env.pool, days)
+                .await
+                .context("failed to hide expired uploads")?;
+
+            if count > 0 {
+                tracing::info!(count, days, "Hid expired uploads");
+            }
+        }
+
+        RetentionPolicy::Delete => {
+            let deleted = Upload::delete_expired(&env.pool, days)
+                .await
+                .context("failed to delete expired uploads")?;
+
+            if !deleted.is_empty() {
+                let (count, size) = delete_cached_uploads(env, deleted).await;
+                tracing::info!(count, size, days, "Deleted expired uploads");
+            }
+        }
+    }
+
+    match env.exhausted_retention {
+        RetentionPolicy::Keep => {}
+
+        RetentionPolicy::Hide => {
+            let count = Upload::hide_exhausted(&env.pool)
+                .await
+                .context("failed to hide exhausted uploads")?;
+
+            if count > 0 {
+                tracing::info!(count, "Hid exhausted uploads");
+            }
+        }
+
+        RetentionPolicy::Delete => {
+            let deleted = Upload::delete_exhausted(&env.pool)
+                .await
+                .context("failed to delete exhausted uploads")?;
+
+            if !deleted.is_empty() {
+                let (count, size) = delete_cached_uploads(env, deleted).await;
+                tracing::info!(count, size, "Deleted exhausted uploads");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Delete the cached files for the given uploads, returning the number of uploads and the total
+/// size of the uploads that were reclaimed.
+async fn delete_cached_uploads(env: &Env, uploads: Vec<(String, i64)>) -> (usize, i64) {
+    let count = uploads.len();
+    let mut size = 0;
+
+    for (blob, upload_size) in uploads {
+        release_blob(env, &blob).await;
+        size += upload_size;
+    }
+
+    (count, size)
+}
@@ -0,0 +1,459 @@
+//! Delivery of webhooks
+//!
+//! Users and teams can add webhooks that are sent events for their uploads, such as when an upload
+//! is created or downloaded. When an event happens, a delivery is queued in the database for each
+//! webhook that is subscribed to it, with the JSON body that will be sent. Queueing a delivery does
+//! not wait for it to be sent, so a slow or broken webhook never holds up a request.
+//!
+//! This worker periodically sends the deliveries that are due. Each delivery is sent as a POST
+//! request, along with these headers:
+//!
+//! 1. `X-Parcel-Event` gives the name of the event, such as `upload.created`.
+//! 2. `X-Parcel-Delivery` gives the ID of the delivery, which is the same for every attempt.
+//! 3. `X-Parcel-Signature` gives the HMAC-SHA256 of the body, using the secret of the webhook,
+//!    in the form `sha256=<hex>`.
+//!
+//! Any `2xx` response counts as a successful delivery. Otherwise the delivery is tried again after
+//! a delay that doubles with each attempt, until it has been tried [`MAX_ATTEMPTS`] times, after
+//! which it is marked as failed. Each attempt is recorded in the delivery log of the webhook, with
+//! the status of the response but not its body.
+//!
+//! Webhooks are not sent to the loopback interface, or to private or link-local networks, so that
+//! they cannot be used to reach services that are not meant to be public. The address in the URL
+//! is checked when the webhook is added, and again before each delivery. Host names are checked as
+//! they are resolved, so that a name cannot resolve to a public address when the webhook is added
+//! and a private one later. Redirects are not followed, as they could lead anywhere. Hosts that
+//! are known to be safe can be allowed with `WEBHOOK_ALLOWED_HOSTS`.
+
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use serde_json::json;
+use sha2::Sha256;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::{sync::mpsc::Sender, task::JoinHandle};
+
+use parcel_model::{
+    team::Team,
+    types::Key,
+    upload::Upload,
+    user::User,
+    webhook::{Webhook, WebhookDelivery, WebhookEvent},
+};
+
+use crate::env::Env;
+
+/// The number of times that a delivery is attempted before it is marked as failed.
+const MAX_ATTEMPTS: i64 = 8;
+
+/// The delay before the second attempt at a delivery, which doubles for each attempt after that.
+const RETRY_DELAY: time::Duration = time::Duration::seconds(30);
+
+/// How long to wait for a webhook to respond.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The number of due deliveries that are fetched from the database at a time.
+const BATCH_SIZE: u32 = 20;
+
+pub enum WebhookCommand {
+    Stop,
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookWorker {
+    sender: Sender<WebhookCommand>,
+}
+
+impl WebhookWorker {
+    pub async fn stop(self) -> anyhow::Result<()> {
+        self.sender
+            .send(WebhookCommand::Stop)
+            .await
+            .context("failed to send stop command to webhook worker")?;
+        Ok(())
+    }
+}
+
+/// Check whether an address is on the public internet, rather than on the loopback interface, or
+/// on a private or link-local network.
+fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast())
+        }
+
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_address(IpAddr::V4(ip));
+            }
+
+            // Unique local addresses are in `fc00::/7` and link-local addresses in `fe80::/10`.
+            let prefix = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || prefix & 0xfe00 == 0xfc00
+                || prefix & 0xffc0 == 0xfe80)
+        }
+    }
+}
+
+fn is_allowed_host(allowed_hosts: &[String], host: &str) -> bool {
+    allowed_hosts
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(host))
+}
+
+/// Check whether webhooks can be sent to a URL, which must not be on a private network unless its
+/// host is one of the `allowed_hosts`.
+///
+/// Only an address in the URL can be checked here, as a host name could resolve to a different
+/// address by the time the webhook is sent. Host names are checked as they are resolved instead.
+pub fn is_allowed_url(allowed_hosts: &[String], url: &str) -> bool {
+    let Ok(url) = reqwest::Url::parse(url) else {
+        return false;
+    };
+
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if is_allowed_host(allowed_hosts, host) {
+        return true;
+    }
+
+    match host.parse::<IpAddr>() {
+        Ok(ip) => is_public_address(ip),
+        Err(_) => host != "localhost" && !host.ends_with(".localhost"),
+    }
+}
+
+/// Resolves the host names of webhooks, leaving out any addresses that are on a private network
+/// unless the host is allowed.
+struct PublicResolver {
+    allowed_hosts: Vec<String>,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let allowed = is_allowed_host(&self.allowed_hosts, name.as_str());
+
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs = tokio::net::lookup_host((host, 0))
+                .await?
+                .filter(|addr| allowed || is_public_address(addr.ip()))
+                .collect::<Vec<_>>();
+
+            if addrs.is_empty() {
+                return Err(format!("{host} does not resolve to a public address").into());
+            }
+
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+pub async fn start_worker(env: Env) -> anyhow::Result<(WebhookWorker, JoinHandle<()>)> {
+    let resolver = PublicResolver {
+        allowed_hosts: env.webhook_allowed_hosts.clone(),
+    };
+
+    let client = reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .user_agent(concat!("parcel/", env!("CARGO_PKG_VERSION")))
+        .redirect(reqwest::redirect::Policy::none())
+        .dns_resolver(Arc::new(resolver))
+        .build()
+        .context("failed to create HTTP client for webhooks")?;
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+
+    let task = tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                Some(command) = rx.recv() => {
+                    match command {
+                        WebhookCommand::Stop => {
+                            tracing::info!("Stopping webhook worker");
+                            break;
+                        }
+                    }
+                },
+
+                _ = tokio::time::sleep(env.webhook_delivery_interval) => {
+                    if let Err(err) = deliver_due(&env, &client).await {
+                        tracing::error!("Failed to send due webhook deliveries: {}", err);
+                    }
+                },
+            }
+        }
+    });
+
+    Ok((WebhookWorker { sender: tx }, task))
+}
+
+/// Describe an upload in the body of a webhook delivery.
+fn upload_details(upload: &Upload) -> serde_json::Value {
+    json!({
+        "id": upload.id,
+        "slug": upload.slug,
+        "filename": upload.filename,
+        "size": upload.size,
+        "mime_type": upload.mime_type,
+        "hash": upload.hash,
+        "public": upload.public,
+        "downloads": upload.downloads,
+        "limit": upload.limit,
+        "remaining": upload.remaining,
+        "expiry_date": upload.expiry_date.map(|date| date.to_string()),
+        "owner_user": upload.owner_user,
+        "owner_team": upload.owner_team,
+        "uploaded_by": upload.uploaded_by,
+        "uploaded_at": upload.uploaded_at.format(&Rfc3339).ok(),
+        "remote_addr": upload.remote_addr,
+    })
+}
+
+/// Queue a delivery of an event to each of the given webhooks.
+async fn queue_deliveries(
+    env: &Env,
+    webhooks: &[Webhook],
+    event: WebhookEvent,
+    upload: &Upload,
+    details: Option<&serde_json::Value>,
+) {
+    let mut payload = json!({
+        "event": event,
+        "created_at": OffsetDateTime::now_utc().format(&Rfc3339).ok(),
+        "upload": upload_details(upload),
+    });
+
+    if let Some(details) = details {
+        payload["details"] = details.clone();
+    }
+
+    let payload = payload.to_string();
+    for webhook in webhooks {
+        let delivery = WebhookDelivery::new(webhook.id, event, payload.clone());
+        if let Err(err) = delivery.create(&env.pool).await {
+            tracing::error!(?err, %webhook.id, %upload.id, ?event, "Failed to queue webhook delivery");
+        }
+    }
+}
+
+/// Get the webhooks of a user or team that are subscribed to an event.
+async fn get_subscribed(
+    env: &Env,
+    owner_user: Option<Key<User>>,
+    owner_team: Option<Key<Team>>,
+    event: WebhookEvent,
+) -> Vec<Webhook> {
+    Webhook::get_subscribed(&env.pool, owner_user, owner_team, event)
+        .await
+        .unwrap_or_else(|err| {
+            tracing::error!(
+                ?err,
+                ?owner_user,
+                ?owner_team,
+                ?event,
+                "Failed to get webhooks"
+            );
+            Vec::new()
+        })
+}
+
+/// Queue an event for an upload, to be sent to each webhook of the owner of the upload that is
+/// subscribed to the event.
+///
+/// Failing to queue an event does not fail the request, as whatever caused the event has already
+/// happened by the time it is queued.
+pub async fn queue_upload_event(
+    env: &Env,
+    event: WebhookEvent,
+    upload: &Upload,
+    details: Option<serde_json::Value>,
+) {
+    let webhooks = get_subscribed(env, upload.owner_user, upload.owner_team, event).await;
+    if !webhooks.is_empty() {
+        queue_deliveries(env, &webhooks, event, upload, details.as_ref()).await;
+    }
+}
+
+/// Queue an `upload.created` event for each of the given uploads, which all have the same owner.
+pub async fn queue_uploads_created(
+    env: &Env,
+    owner_user: Option<Key<User>>,
+    owner_team: Option<Key<Team>>,
+    uploads: &[Key<Upload>],
+) {
+    let event = WebhookEvent::UploadCreated;
+    let webhooks = get_subscribed(env, owner_user, owner_team, event).await;
+    if webhooks.is_empty() {
+        return;
+    }
+
+    for id in uploads {
+        match Upload::get(&env.pool, *id).await {
+            Ok(Some(upload)) => queue_deliveries(env, &webhooks, event, &upload, None).await,
+            Ok(None) => tracing::warn!(%id, "Upload not found when queueing webhook deliveries"),
+            Err(err) => tracing::error!(?err, %id, "Failed to get upload for webhook deliveries"),
+        }
+    }
+}
+
+async fn deliver_due(env: &Env, client: &reqwest::Client) -> anyhow::Result<()> {
+    loop {
+        let deliveries = WebhookDelivery::get_due(&env.pool, BATCH_SIZE)
+            .await
+            .context("failed to get due webhook deliveries")?;
+
+        let count = deliveries.len() as u32;
+        for delivery in deliveries {
+            deliver(env, client, delivery).await?;
+        }
+
+        if count < BATCH_SIZE {
+            return Ok(());
+        }
+    }
+}
+
+/// Sign the body of a delivery with the secret of the webhook.
+fn sign(secret: &str, body: &str) -> anyhow::Result<String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .context("failed to create HMAC for webhook signature")?;
+    mac.update(body.as_bytes());
+    Ok(format!("sha256={:x}", mac.finalize().into_bytes()))
+}
+
+/// Get the time of the next attempt at a delivery that has failed the given number of times, or
+/// `None` if it should not be tried again.
+fn next_attempt_at(attempts: i64) -> Option<OffsetDateTime> {
+    if attempts >= MAX_ATTEMPTS {
+        return None;
+    }
+
+    let delay = RETRY_DELAY * 2i32.pow((attempts - 1).clamp(0, 30) as u32);
+    Some(OffsetDateTime::now_utc() + delay)
+}
+
+async fn deliver(
+    env: &Env,
+    client: &reqwest::Client,
+    mut delivery: WebhookDelivery,
+) -> anyhow::Result<()> {
+    let Some(webhook) = Webhook::get(&env.pool, delivery.webhook)
+        .await
+        .context("failed to get webhook for delivery")?
+    else {
+        tracing::warn!(%delivery.id, "Webhook for delivery no longer exists");
+        return Ok(());
+    };
+
+    let (response_status, error) = if is_allowed_url(&env.webhook_allowed_hosts, &webhook.url) {
+        send(client, &webhook, &delivery).await?
+    } else {
+        let error = "Webhooks cannot be sent to a private network".to_string();
+        (None, Some(error))
+    };
+
+    let next_attempt_at = if error.is_some() {
+        next_attempt_at(delivery.attempts + 1)
+    } else {
+        None
+    };
+
+    if let Some(ref error) = error {
+        tracing::warn!(%delivery.id, %webhook.id, attempts = delivery.attempts + 1, ?next_attempt_at, "{error}");
+    } else {
+        tracing::info!(%delivery.id, %webhook.id, event = ?delivery.event, "Delivered webhook");
+    }
+
+    delivery
+        .record_attempt(&env.pool, response_status, error, next_attempt_at)
+        .await
+        .context("failed to record webhook delivery attempt")?;
+
+    Ok(())
+}
+
+/// Send a delivery to its webhook, returning the status of the response, if there was one, and the
+/// error if the delivery failed.
+async fn send(
+    client: &reqwest::Client,
+    webhook: &Webhook,
+    delivery: &WebhookDelivery,
+) -> anyhow::Result<(Option<i64>, Option<String>)> {
+    let signature = sign(&webhook.secret, &delivery.payload)?;
+    let result = client
+        .post(&webhook.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("X-Parcel-Event", delivery.event.as_str())
+        .header("X-Parcel-Delivery", delivery.id.to_string())
+        .header("X-Parcel-Signature", signature)
+        .body(delivery.payload.clone())
+        .send()
+        .await;
+
+    Ok(match result {
+        Ok(response) if response.status().is_success() => {
+            (Some(response.status().as_u16() as i64), None)
+        }
+
+        // The body of the response is not kept, as it could contain anything that the host sends.
+        Ok(response) => {
+            let status = response.status();
+            let error = format!("Webhook responded with {status}");
+            (Some(status.as_u16() as i64), Some(error))
+        }
+
+        Err(err) => (None, Some(format!("Failed to send request: {err}"))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_allowed_url;
+
+    #[test]
+    fn test_is_allowed_url() {
+        let allowed = vec!["127.0.0.1".to_string(), "build.internal".to_string()];
+
+        for url in [
+            "https://example.com/webhook",
+            "http://93.184.216.34/webhook",
+            "http://[2606:2800:220:1::]/webhook",
+            "http://127.0.0.1:9/webhook",
+            "http://Build.Internal/webhook",
+        ] {
+            assert!(is_allowed_url(&allowed, url), "{url} should be allowed");
+        }
+
+        for url in [
+            "http://localhost/webhook",
+            "http://app.localhost/webhook",
+            "http://127.0.0.2/webhook",
+            "http://10.0.0.5/webhook",
+            "http://192.168.1.1/webhook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://0.0.0.0/webhook",
+            "http://[::1]/webhook",
+            "http://[::ffff:10.0.0.5]/webhook",
+            "http://[fd00::1]/webhook",
+            "http://[fe80::1]/webhook",
+            "not a url",
+        ] {
+            assert!(
+                !is_allowed_url(&allowed, url),
+                "{url} should not be allowed"
+            );
+        }
+    }
+}
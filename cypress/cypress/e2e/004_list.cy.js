@@ -342,6 +342,7 @@ describe("File List", () => {
       .should("not.be.disabled")
       .clear()
       .type("10");
+    cy.get(".modal > .content input[name='public']").check();
     cy.get(".modal > .content .buttons > button:first")
       .should("contain", "Save changes")
       .click();
@@ -350,12 +351,15 @@ describe("File List", () => {
       "#uploads-table > .uploads-table-row:nth-child(2) > :nth-child(5)"
     ).should("contain", "10 / 10");
 
-    // Download the file once, which should reduce the download limit
+    // Download the file anonymously, which should reduce the download limit
 
-    cy.get("#uploads-table .dropdown-button").click();
-    cy.get("a[href$='/download']").click();
+    cy.get("@upload").then((upload) => {
+      cy.clearCookies();
+      cy.request(`/uploads/${upload.slug}/download`);
+      cy.login(users.user);
+    });
 
-    cy.get("#upload-list-refresh").click();
+    cy.visit("/");
 
     cy.get(
       "#uploads-table > .uploads-table-row:nth-child(2) > :nth-child(5)"
@@ -373,12 +377,15 @@ describe("File List", () => {
       "#uploads-table > .uploads-table-row:nth-child(2) > :nth-child(5)"
     ).should("contain", "10 / 10");
 
-    // Download the file once more, which should reduce the download limit again
+    // Download the file anonymously again, which should reduce the download limit again
 
-    cy.get("#uploads-table .dropdown-button").click();
-    cy.get("a[href$='/download']").click();
+    cy.get("@upload").then((upload) => {
+      cy.clearCookies();
+      cy.request(`/uploads/${upload.slug}/download`);
+      cy.login(users.user);
+    });
 
-    cy.get("#upload-list-refresh").click();
+    cy.visit("/");
 
     cy.get(
       "#uploads-table > .uploads-table-row:nth-child(2) > :nth-child(5)"
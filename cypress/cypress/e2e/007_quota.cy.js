@@ -0,0 +1,70 @@
+import users from "../fixtures/users.json";
+
+describe("Storage Quotas", () => {
+  beforeEach(() => {
+    // Give the user a limit of 100 bytes.
+    cy.request("POST", "/debug/initial-users", [
+      users.admin,
+      { ...users.user, limit: 100 },
+    ]).then((response) => {
+      expect(response.status).to.eq(200);
+    });
+
+    cy.login(users.user);
+  });
+
+  it("Shows the remaining quota", () => {
+    cy.visit("/");
+    cy.get("#upload-stats-usage").should("contain", "0 B");
+    cy.get("#upload-stats-remaining").should("contain", "100 B remaining");
+
+    cy.upload({ filename: "test-file.txt", owner: "user" });
+
+    cy.visit("/");
+    cy.get("#upload-stats-usage").should("contain", "69 B");
+    cy.get("#upload-stats-remaining").should("contain", "31 B remaining");
+  });
+
+  it("Prevents uploads that exceed the quota", () => {
+    cy.upload({ filename: "test-file.txt", owner: "user" });
+    cy.visit("/");
+
+    cy.contains("button", "Upload").click();
+    cy.get(".modal > .content").should("be.visible");
+
+    cy.get(".modal > .content")
+      .contains("Drop files")
+      .selectFile("cypress/uploads/test-file.txt", {
+        action: "drag-drop",
+      });
+
+    // Wait for the upload to register
+    cy.wait(1000);
+
+    cy.get(".modal > .content").should(
+      "contain",
+      "These files would exceed your remaining storage"
+    );
+    cy.contains("button", "Upload file").should("be.disabled");
+  });
+
+  it("Allows uploads within the quota", () => {
+    cy.upload({ filename: "test-file.txt", owner: "user" });
+    cy.visit("/");
+
+    cy.contains("button", "Upload").click();
+    cy.get(".modal > .content").should("be.visible");
+
+    cy.get(".modal > .content")
+      .contains("Drop files")
+      .selectFile("cypress/uploads/test-file-2.txt", {
+        action: "drag-drop",
+      });
+
+    // Wait for the upload to register
+    cy.wait(1000);
+
+    cy.contains("button", "Upload file").should("be.enabled").click();
+    cy.get(".modal > .content").should("contain", "Upload complete");
+  });
+});
@@ -0,0 +1,116 @@
+import users from "../fixtures/users.json";
+
+const auth = { username: users.user.username, password: users.user.password };
+
+function startUpload(length, filename = "resumable.txt") {
+  return cy.request({
+    method: "POST",
+    url: "/api/v1/tus",
+    auth,
+    headers: {
+      "Tus-Resumable": "1.0.0",
+      "Upload-Length": String(length),
+      "Upload-Metadata": `filename ${btoa(filename)}`,
+    },
+  });
+}
+
+function sendChunk(location, offset, body, options = {}) {
+  return cy.request({
+    method: "PATCH",
+    url: location,
+    auth,
+    headers: {
+      "Tus-Resumable": "1.0.0",
+      "Upload-Offset": String(offset),
+      "Content-Type": "application/offset+octet-stream",
+    },
+    body,
+    ...options,
+  });
+}
+
+describe("Resumable Uploads", () => {
+  beforeEach(() => {
+    cy.initialUsers();
+  });
+
+  it("Advertises the supported protocol", () => {
+    cy.request({ method: "OPTIONS", url: "/api/v1/tus" }).then((response) => {
+      expect(response.status).to.eq(204);
+      expect(response.headers["tus-version"]).to.eq("1.0.0");
+      expect(response.headers["tus-extension"]).to.contain("creation");
+      expect(response.headers["tus-extension"]).to.contain("termination");
+    });
+  });
+
+  it("Completes an upload sent in several requests", () => {
+    startUpload(11).then((response) => {
+      expect(response.status).to.eq(201);
+      const location = response.headers["location"];
+
+      sendChunk(location, 0, "Hello").then((response) => {
+        expect(response.status).to.eq(204);
+        expect(response.headers["upload-offset"]).to.eq("5");
+      });
+
+      cy.request({
+        method: "HEAD",
+        url: location,
+        auth,
+        headers: { "Tus-Resumable": "1.0.0" },
+      }).then((response) => {
+        expect(response.headers["upload-offset"]).to.eq("5");
+        expect(response.headers["upload-length"]).to.eq("11");
+      });
+
+      sendChunk(location, 5, " world").then((response) => {
+        expect(response.status).to.eq(204);
+        expect(response.headers["upload-offset"]).to.eq("11");
+
+        const id = response.headers["parcel-upload"];
+        cy.request({ url: `/api/v1/uploads/${id}`, auth }).then((response) => {
+          expect(response.body.filename).to.eq("resumable.txt");
+          expect(response.body.size).to.eq(11);
+        });
+      });
+    });
+  });
+
+  it("Rejects a request at the wrong offset", () => {
+    startUpload(11).then((response) => {
+      const location = response.headers["location"];
+
+      sendChunk(location, 5, "world", { failOnStatusCode: false }).then(
+        (response) => {
+          expect(response.status).to.eq(409);
+        }
+      );
+    });
+  });
+
+  it("Terminates an upload", () => {
+    startUpload(11).then((response) => {
+      const location = response.headers["location"];
+
+      cy.request({
+        method: "DELETE",
+        url: location,
+        auth,
+        headers: { "Tus-Resumable": "1.0.0" },
+      }).then((response) => {
+        expect(response.status).to.eq(204);
+      });
+
+      cy.request({
+        method: "HEAD",
+        url: location,
+        auth,
+        headers: { "Tus-Resumable": "1.0.0" },
+        failOnStatusCode: false,
+      }).then((response) => {
+        expect(response.status).to.eq(404);
+      });
+    });
+  });
+});
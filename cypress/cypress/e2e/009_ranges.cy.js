@@ -0,0 +1,161 @@
+import users from "../fixtures/users.json";
+
+const auth = { username: users.user.username, password: users.user.password };
+
+describe("Partial Downloads", () => {
+  beforeEach(() => {
+    cy.initialUsers();
+
+    cy.upload({ filename: "test-file.txt", owner: "user" }).then((upload) => {
+      cy.request({
+        method: "PATCH",
+        url: `/api/v1/uploads/${upload.id}`,
+        auth,
+        body: { limit: 10 },
+      });
+
+      cy.wrap(upload).as("upload");
+    });
+  });
+
+  it("Sends a single range", function () {
+    cy.request({
+      url: `/api/v1/uploads/${this.upload.id}/download`,
+      auth,
+      headers: { Range: "bytes=0-3" },
+    }).then((response) => {
+      expect(response.status).to.eq(206);
+      expect(response.headers["accept-ranges"]).to.eq("bytes");
+      expect(response.headers["content-range"]).to.eq("bytes 0-3/69");
+      expect(response.body).to.eq("This");
+    });
+  });
+
+  it("Sends multiple ranges", function () {
+    cy.request({
+      url: `/api/v1/uploads/${this.upload.id}/download`,
+      auth,
+      headers: { Range: "bytes=0-3,10-13" },
+    }).then((response) => {
+      expect(response.status).to.eq(206);
+      expect(response.headers["content-type"]).to.contain(
+        "multipart/byteranges"
+      );
+      expect(response.body).to.contain("Content-Range: bytes 0-3/69");
+      expect(response.body).to.contain("Content-Range: bytes 10-13/69");
+    });
+  });
+
+  it("Rejects unsatisfiable ranges", function () {
+    cy.request({
+      url: `/api/v1/uploads/${this.upload.id}/download`,
+      auth,
+      headers: { Range: "bytes=100-200" },
+      failOnStatusCode: false,
+    }).then((response) => {
+      expect(response.status).to.eq(416);
+      expect(response.headers["content-range"]).to.eq("bytes */69");
+    });
+  });
+
+  it("Supports conditional requests", function () {
+    cy.request({
+      url: `/api/v1/uploads/${this.upload.id}/download`,
+      auth,
+    }).then((response) => {
+      expect(response.status).to.eq(200);
+      const etag = response.headers["etag"];
+      expect(etag).to.exist;
+
+      cy.request({
+        url: `/api/v1/uploads/${this.upload.id}/download`,
+        auth,
+        headers: { "If-None-Match": etag },
+      }).then((response) => {
+        expect(response.status).to.eq(304);
+      });
+    });
+  });
+
+  it("Only counts requests that reach either end of the file", function () {
+    cy.request({
+      method: "PATCH",
+      url: `/api/v1/uploads/${this.upload.id}`,
+      auth,
+      body: { public: true },
+    });
+
+    cy.request({
+      url: `/uploads/${this.upload.slug}/download`,
+      headers: { Range: "bytes=10-19" },
+    });
+
+    cy.request({ url: `/api/v1/uploads/${this.upload.id}`, auth })
+      .its("body.remaining")
+      .should("eq", 10);
+
+    cy.request({
+      url: `/uploads/${this.upload.slug}/download`,
+      headers: { Range: "bytes=10-" },
+    });
+
+    cy.request({ url: `/api/v1/uploads/${this.upload.id}`, auth })
+      .its("body.remaining")
+      .should("eq", 9);
+
+    cy.request(`/uploads/${this.upload.slug}/download`);
+
+    cy.request({ url: `/api/v1/uploads/${this.upload.id}`, auth })
+      .its("body.remaining")
+      .should("eq", 8);
+  });
+
+  it("Does not count the owner's downloads against the limit", function () {
+    cy.request({
+      url: `/api/v1/uploads/${this.upload.id}/download`,
+      auth,
+      headers: { Range: "bytes=0-3" },
+    });
+
+    cy.request({
+      url: `/api/v1/uploads/${this.upload.id}/download`,
+      auth,
+      headers: { Range: "bytes=10-" },
+    });
+
+    cy.request({ url: `/api/v1/uploads/${this.upload.id}/download`, auth });
+
+    cy.request({ url: `/api/v1/uploads/${this.upload.id}`, auth }).then(
+      (response) => {
+        expect(response.body.downloads).to.eq(3);
+        expect(response.body.remaining).to.eq(10);
+      }
+    );
+  });
+
+  it("Refuses ranges once the download limit is reached", function () {
+    cy.request({
+      method: "PATCH",
+      url: `/api/v1/uploads/${this.upload.id}`,
+      auth,
+      body: { public: true, limit: 2 },
+    });
+
+    for (let attempt = 0; attempt < 2; attempt++) {
+      cy.request({
+        url: `/uploads/${this.upload.slug}/download`,
+        headers: { Range: "bytes=1-" },
+      })
+        .its("status")
+        .should("eq", 206);
+    }
+
+    cy.request({
+      url: `/uploads/${this.upload.slug}/download`,
+      headers: { Range: "bytes=1-" },
+      failOnStatusCode: false,
+    })
+      .its("status")
+      .should("eq", 403);
+  });
+});
@@ -0,0 +1,42 @@
+import users from "../fixtures/users.json";
+
+const auth = { username: users.user.username, password: users.user.password };
+
+describe("Deduplication", () => {
+  beforeEach(() => {
+    cy.initialUsers();
+
+    cy.upload({ filename: "test-file.txt", owner: "user" }).as("first");
+    cy.upload({ filename: "test-file.txt", owner: "user" }).as("second");
+  });
+
+  it("Downloads both copies of the same file", function () {
+    expect(this.first.slug).to.not.eq(this.second.slug);
+
+    for (const upload of [this.first, this.second]) {
+      cy.request({
+        url: `/api/v1/uploads/${upload.id}/download`,
+        auth,
+      }).then((response) => {
+        expect(response.status).to.eq(200);
+        expect(response.body).to.have.length(69);
+      });
+    }
+  });
+
+  it("Keeps the file until the last upload is deleted", function () {
+    cy.request({
+      method: "DELETE",
+      url: `/api/v1/uploads/${this.first.id}`,
+      auth,
+    });
+
+    cy.request({
+      url: `/api/v1/uploads/${this.second.id}/download`,
+      auth,
+    }).then((response) => {
+      expect(response.status).to.eq(200);
+      expect(response.body).to.have.length(69);
+    });
+  });
+});
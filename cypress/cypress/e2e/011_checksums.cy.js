@@ -0,0 +1,36 @@
+import users from "../fixtures/users.json";
+
+const auth = { username: users.user.username, password: users.user.password };
+
+const HASH = "d6435a5d5ed5190c5e872a3814788f0d27c4a5811f421e62de259425072a3c68";
+const DIGEST = "1kNaXV7VGQxehyo4FHiPDSfEpYEfQh5i3iWUJQcqPGg=";
+
+describe("Checksums", () => {
+  beforeEach(() => {
+    cy.initialUsers();
+    cy.upload({ filename: "test-file.txt", owner: "user" }).as("upload");
+  });
+
+  it("Records the checksum of an upload", function () {
+    cy.request({ url: `/api/v1/uploads/${this.upload.id}`, auth })
+      .its("body.hash")
+      .should("eq", HASH);
+  });
+
+  it("Sends the checksum with downloads", function () {
+    cy.request({
+      url: `/api/v1/uploads/${this.upload.id}/download`,
+      auth,
+    }).then((response) => {
+      expect(response.status).to.eq(200);
+      expect(response.headers["repr-digest"]).to.eq(`sha-256=:${DIGEST}:`);
+      expect(response.headers["digest"]).to.eq(`sha-256=${DIGEST}`);
+    });
+  });
+
+  it("Shows the checksum on the upload page", function () {
+    cy.login(users.user);
+    cy.visit(`/uploads/${this.upload.slug}`);
+    cy.contains(HASH).should("be.visible");
+  });
+});
@@ -0,0 +1,64 @@
+import users from "../fixtures/users.json";
+
+const auth = { username: users.user.username, password: users.user.password };
+
+describe("Audit log", () => {
+  beforeEach(() => {
+    cy.initialUsers();
+    cy.upload({ filename: "test-file.txt", owner: "user" }).as("upload");
+  });
+
+  it("Records changes to an upload", function () {
+    cy.request({
+      method: "PATCH",
+      url: `/api/v1/uploads/${this.upload.id}`,
+      auth,
+      body: { filename: "renamed-file.txt" },
+    });
+
+    cy.login(users.admin);
+    cy.visit(`/admin/audit?target=${this.upload.id}`);
+    cy.get("table tbody tr").first().should("contain.text", "Upload edit");
+    cy.get("table tbody tr").first().should("contain.text", "renamed-file.txt");
+  });
+
+  it("Filters the audit log by action", function () {
+    cy.request({
+      method: "DELETE",
+      url: `/api/v1/uploads/${this.upload.id}`,
+      auth,
+    });
+
+    cy.login(users.admin);
+    cy.visit("/admin/audit?action=upload_edit");
+    cy.get("table tbody").should("not.contain.text", "Upload delete");
+    cy.visit("/admin/audit?action=upload_delete");
+    cy.get("table tbody tr").first().should("contain.text", "Upload delete");
+  });
+
+  it("Exports the audit log as CSV", function () {
+    cy.request({
+      method: "DELETE",
+      url: `/api/v1/uploads/${this.upload.id}`,
+      auth,
+    });
+
+    cy.login(users.admin);
+    cy.request("/admin/audit/export").then((response) => {
+      expect(response.status).to.eq(200);
+      expect(response.headers["content-type"]).to.contain("text/csv");
+
+      const lines = response.body.trim().split("\r\n");
+      expect(lines[0]).to.match(/^time,action,/);
+      expect(lines[1]).to.contain("upload_delete");
+      expect(lines[1]).to.contain(this.upload.id);
+    });
+  });
+
+  it("Is only available to administrators", () => {
+    cy.login(users.user);
+    cy.request({ url: "/admin/audit", failOnStatusCode: false })
+      .its("status")
+      .should("not.eq", 200);
+  });
+});
@@ -0,0 +1,59 @@
+import users from "../fixtures/users.json";
+
+const auth = { username: users.user.username, password: users.user.password };
+
+describe("Download history", () => {
+  beforeEach(() => {
+    cy.initialUsers();
+    cy.upload({ filename: "test-file.txt", owner: "user" }).as("upload");
+  });
+
+  it("Records each download of an upload", function () {
+    cy.request({
+      url: `/api/v1/uploads/${this.upload.id}/download`,
+      auth,
+      headers: { "User-Agent": "parcel-history-test" },
+    });
+
+    cy.login(users.user);
+    cy.request(`/uploads/${this.upload.id}/downloads`).then((response) => {
+      expect(response.status).to.eq(200);
+      expect(response.body).to.contain("parcel-history-test");
+      expect(response.body).to.contain(users.user.username);
+    });
+  });
+
+  it("Does not record partial downloads from within a file", function () {
+    cy.request({
+      url: `/api/v1/uploads/${this.upload.id}/download`,
+      auth,
+      headers: { Range: "bytes=10-19" },
+    });
+
+    cy.login(users.user);
+    cy.request(`/uploads/${this.upload.id}/downloads`)
+      .its("body")
+      .should("contain", "has not been downloaded yet");
+  });
+
+  it("Shows the history only to the owner", function () {
+    cy.login(users.admin);
+    cy.request({
+      url: `/uploads/${this.upload.id}/downloads`,
+      failOnStatusCode: false,
+    })
+      .its("status")
+      .should("eq", 403);
+  });
+
+  it("Shows all downloads to administrators", function () {
+    cy.request({
+      url: `/api/v1/uploads/${this.upload.id}/download`,
+      auth,
+    });
+
+    cy.login(users.admin);
+    cy.visit("/admin/downloads");
+    cy.get("table").last().should("contain.text", "test-file.txt");
+  });
+});
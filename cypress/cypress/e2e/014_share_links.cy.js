@@ -0,0 +1,134 @@
+import users from "../fixtures/users.json";
+
+const auth = { username: users.user.username, password: users.user.password };
+
+function yesterday() {
+  const date = new Date();
+  date.setDate(date.getDate() - 1);
+  return date.toISOString().slice(0, 10);
+}
+
+function openShareModal() {
+  cy.visit("/");
+  cy.get("#upload-list-refresh").click();
+  cy.get("#uploads-table .dropdown-button").click();
+  cy.get("a[hx-get$='/share']").click();
+  cy.get(".modal > .content").should("be.visible");
+}
+
+function createLink(name, limit) {
+  cy.get("#link_name").type(name);
+  if (limit) {
+    cy.get("#link_limit_check").check();
+    cy.get("#link_limit").clear().type(limit);
+  }
+
+  cy.get("#share-link-form button[type='submit']").click();
+  cy.get("#share-links").should("contain", name);
+
+  return cy
+    .get("#share-links tr")
+    .contains("tr", name)
+    .find("parcel-clipboard")
+    .invoke("attr", "value");
+}
+
+describe("Share links", () => {
+  beforeEach(() => {
+    cy.initialUsers();
+    cy.upload({ filename: "test-file.txt", owner: "user" }).as("upload");
+    cy.login(users.user);
+  });
+
+  it("Downloads a private upload through a share link", () => {
+    openShareModal();
+    createLink("Customer A").then((url) => {
+      cy.clearCookies();
+      cy.request(url).its("body").should("contain", "test-file.txt");
+      cy.request(`${url}/download`).its("status").should("eq", 200);
+    });
+
+    cy.login(users.user);
+    openShareModal();
+    cy.get("#share-links").contains("tr", "Customer A").should("contain", "1");
+  });
+
+  it("Stops a link once its limit is reached", () => {
+    openShareModal();
+    createLink("Customer B", "1").then((url) => {
+      cy.clearCookies();
+      cy.request(`${url}/download`).its("status").should("eq", 200);
+      cy.request({ url: `${url}/download`, failOnStatusCode: false })
+        .its("status")
+        .should("eq", 403);
+    });
+  });
+
+  it("Revokes one link without affecting the others", () => {
+    openShareModal();
+    createLink("Customer C").as("first");
+    createLink("Customer D").as("second");
+
+    cy.get("#share-links")
+      .contains("tr", "Customer C")
+      .find("button[hx-post$='/revoke']")
+      .click();
+    cy.get("#share-links").contains("tr", "Customer C").should("contain", "Revoked");
+
+    cy.clearCookies();
+    cy.get("@first").then((url) => {
+      cy.request({ url: `${url}/download`, failOnStatusCode: false })
+        .its("status")
+        .should("eq", 403);
+    });
+    cy.get("@second").then((url) => {
+      cy.request(`${url}/download`).its("status").should("eq", 200);
+    });
+  });
+
+  it("Applies the upload's own limit and expiry to every link", function () {
+    cy.request({
+      method: "PATCH",
+      url: `/api/v1/uploads/${this.upload.id}`,
+      auth,
+      body: { limit: 1 },
+    });
+
+    openShareModal();
+    createLink("Customer E").as("first");
+    createLink("Customer F").as("second");
+
+    cy.clearCookies();
+    cy.get("@first").then((url) => {
+      cy.request(`${url}/download`).its("status").should("eq", 200);
+    });
+
+    // The download through the first link used up the upload's only download.
+    cy.get("@second").then((url) => {
+      cy.request({ url: `${url}/download`, failOnStatusCode: false })
+        .its("status")
+        .should("eq", 403);
+    });
+
+    cy.request({
+      method: "POST",
+      url: `/api/v1/uploads/${this.upload.id}/reset`,
+      auth,
+    });
+    cy.get("@second").then((url) => {
+      cy.request(`${url}/download`).its("status").should("eq", 200);
+    });
+
+    cy.request({
+      method: "PATCH",
+      url: `/api/v1/uploads/${this.upload.id}`,
+      auth,
+      body: { limit: null, expiry_date: yesterday() },
+    });
+    cy.get("@second").then((url) => {
+      cy.request({ url: `${url}/download`, failOnStatusCode: false })
+        .its("status")
+        .should("eq", 403);
+    });
+  });
+});
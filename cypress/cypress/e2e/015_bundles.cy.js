@@ -0,0 +1,95 @@
+import users from "../fixtures/users.json";
+
+function uploadBundle(name) {
+  cy.visit("/");
+  cy.get("body").selectFile(
+    ["cypress/uploads/test-file.txt", "cypress/uploads/test-file-2.txt"],
+    { action: "drag-drop" },
+  );
+
+  // Wait for the upload to register
+  cy.wait(1000);
+
+  cy.get(".modal > .content").should("contain", "2 files");
+  cy.get("#upload-bundle-name").type(name);
+  cy.contains("button", "Upload file").should("be.enabled").click();
+  cy.get(".modal > .content").should("contain", "Upload complete");
+  cy.contains("button", "Finish").should("be.enabled").click();
+  cy.get(".modal > .content").should("not.exist");
+}
+
+function openBundle() {
+  cy.get("#uploads-table a[href^='/bundles/']").first().click();
+  cy.get("#bundle-view-container").should("be.visible");
+}
+
+describe("Bundles", () => {
+  beforeEach(() => {
+    cy.initialUsers();
+    cy.login(users.user);
+  });
+
+  it("Groups uploaded files into a bundle", () => {
+    uploadBundle("Holiday photos");
+    cy.get("#uploads-table a[href^='/bundles/']").should("have.length", 2);
+
+    openBundle();
+    cy.get("h1").should("contain", "Holiday photos");
+    cy.get("#bundle-files").should("contain", "test-file.txt");
+    cy.get("#bundle-files").should("contain", "test-file-2.txt");
+  });
+
+  it("Downloads every file in the bundle as a ZIP archive", () => {
+    uploadBundle("Archive");
+    openBundle();
+
+    cy.location("pathname").then((path) => {
+      cy.request(`${path}/download`).then((response) => {
+        expect(response.status).to.eq(200);
+        expect(response.headers["content-type"]).to.eq("application/zip");
+        expect(response.headers["content-disposition"]).to.contain("Archive.zip");
+        expect(response.body.slice(0, 2)).to.eq("PK");
+        expect(response.body).to.contain("test-file.txt");
+        expect(response.body).to.contain("test-file-2.txt");
+      });
+    });
+  });
+
+  it("Applies the bundle's own permissions and limits", () => {
+    uploadBundle("Limited");
+    openBundle();
+
+    cy.location("pathname").then((path) => {
+      cy.clearCookies();
+      cy.request({ url: `${path}/download`, failOnStatusCode: false })
+        .its("status")
+        .should("eq", 403);
+
+      cy.login(users.user);
+      cy.visit(path);
+      cy.contains("button", "Edit settings").click();
+      cy.get("#bundle-form #public").check();
+      cy.get("#bundle-form #limit_check").check();
+      cy.get("#bundle-form #limit").clear().type("1");
+      cy.contains("button", "Save changes").click();
+      cy.get("#bundle-view-container").should("contain", "Publicly accessible");
+
+      cy.clearCookies();
+      cy.request(`${path}/download`).its("status").should("eq", 200);
+      cy.request({ url: `${path}/download`, failOnStatusCode: false })
+        .its("status")
+        .should("eq", 403);
+    });
+  });
+
+  it("Keeps the files when the bundle is deleted", () => {
+    uploadBundle("Temporary");
+    openBundle();
+
+    cy.on("window:confirm", () => true);
+    cy.contains("button", "Delete bundle").click();
+    cy.location("pathname").should("eq", "/");
+    cy.get("#uploads-table").should("contain", "test-file.txt");
+    cy.get("#uploads-table a[href^='/bundles/']").should("not.exist");
+  });
+});
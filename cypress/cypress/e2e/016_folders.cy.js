@@ -0,0 +1,130 @@
+import users from "../fixtures/users.json";
+
+function createFolder(name) {
+  cy.get("#new-folder-button").click();
+  cy.get("#folder-form #folder-name").type(name);
+  cy.contains("button", "Create folder").click();
+  cy.get(".modal > .content").should("not.exist");
+  cy.get("#uploads-table").should("contain", name);
+}
+
+function uploadFile(file) {
+  cy.get("body").selectFile(file, { action: "drag-drop" });
+
+  // Wait for the upload to register
+  cy.wait(1000);
+
+  cy.contains("button", "Upload file").should("be.enabled").click();
+  cy.get(".modal > .content").should("contain", "Upload complete");
+  cy.contains("button", "Finish").should("be.enabled").click();
+  cy.get(".modal > .content").should("not.exist");
+}
+
+function openFolder(name) {
+  cy.get("#uploads-table").contains("a", name).click();
+  cy.get("#folder-breadcrumbs").should("contain", name);
+}
+
+describe("Folders", () => {
+  beforeEach(() => {
+    cy.initialUsers();
+    cy.login(users.user);
+    cy.visit("/");
+  });
+
+  it("Creates nested folders with breadcrumbs", () => {
+    createFolder("Documents");
+    openFolder("Documents");
+    cy.get("#uploads-table").should("contain", "This folder is empty");
+
+    createFolder("Invoices");
+    openFolder("Invoices");
+    cy.get("#folder-breadcrumbs").should("contain", "Documents");
+
+    cy.get("#folder-breadcrumbs").contains("a", "All files").click();
+    cy.get("#folder-breadcrumbs").should("not.exist");
+    cy.get("#uploads-table").should("contain", "Documents");
+    cy.get("#uploads-table").should("not.contain", "Invoices");
+  });
+
+  it("Puts uploads into the open folder", () => {
+    createFolder("Reports");
+    openFolder("Reports");
+    uploadFile("cypress/uploads/test-file.txt");
+    cy.get("#uploads-table").should("contain", "test-file.txt");
+
+    cy.visit("/");
+    cy.get("#uploads-table").should("contain", "Reports");
+    cy.get("#uploads-table").should("not.contain", "test-file.txt");
+
+    cy.get("#upload-search").type("test-file");
+    cy.get("#uploads-table").should("contain", "test-file.txt");
+  });
+
+  it("Moves selected uploads into a folder", () => {
+    uploadFile("cypress/uploads/test-file.txt");
+    createFolder("Archive");
+
+    cy.get("#uploads-table parcel-grouped-checkbox input[type='checkbox']")
+      .first()
+      .check();
+    cy.get("#move_selected").should("be.enabled").click();
+    cy.get("#move-form #move-folder").select("Archive");
+    cy.contains("#move-form button", "Move").click();
+
+    cy.get("#uploads-table").should("not.contain", "test-file.txt");
+    openFolder("Archive");
+    cy.get("#uploads-table").should("contain", "test-file.txt");
+  });
+
+  it("Shares a folder and the files in it", () => {
+    createFolder("Shared");
+    openFolder("Shared");
+    uploadFile("cypress/uploads/test-file.txt");
+
+    cy.visit("/");
+    cy.get("#uploads-table")
+      .contains(".uploads-table-row", "Shared")
+      .within(() => {
+        cy.get(".dropdown-button").click();
+        cy.contains("a", "Rename or share").click();
+      });
+    cy.get("#folder-form #folder-public").check();
+    cy.contains("button", "Save changes").click();
+    cy.get(".modal > .content").should("not.exist");
+
+    cy.get("#uploads-table")
+      .contains(".uploads-table-row", "Shared")
+      .find("parcel-clipboard")
+      .invoke("attr", "value")
+      .then((path) => {
+        cy.clearCookies();
+        cy.visit(path);
+        cy.get("#folder-contents").should("contain", "test-file.txt");
+        cy.get("#folder-contents a[href*='/files/']")
+          .first()
+          .invoke("attr", "href")
+          .then((href) => {
+            cy.request(href).its("status").should("eq", 200);
+          });
+      });
+  });
+
+  it("Keeps the files when a folder is deleted", () => {
+    createFolder("Temporary");
+    openFolder("Temporary");
+    uploadFile("cypress/uploads/test-file.txt");
+
+    cy.visit("/");
+    cy.on("window:confirm", () => true);
+    cy.get("#uploads-table")
+      .contains(".uploads-table-row", "Temporary")
+      .within(() => {
+        cy.get(".dropdown-button").click();
+        cy.contains("a", "Delete folder").click();
+      });
+
+    cy.get("#uploads-table").should("not.contain", "Temporary");
+    cy.get("#uploads-table").should("contain", "test-file.txt");
+  });
+});
@@ -0,0 +1,89 @@
+import users from "../fixtures/users.json";
+
+function uploadFile(file) {
+  cy.get("body").selectFile(file, { action: "drag-drop" });
+
+  // Wait for the upload to register
+  cy.wait(1000);
+
+  cy.contains("button", "Upload file").should("be.enabled").click();
+  cy.get(".modal > .content").should("contain", "Upload complete");
+  cy.contains("button", "Finish").should("be.enabled").click();
+  cy.get(".modal > .content").should("not.exist");
+}
+
+function tagUpload(filename, tags) {
+  cy.get("#uploads-table")
+    .contains(".uploads-table-row", filename)
+    .within(() => {
+      cy.get(".dropdown-button").click();
+      cy.contains("a", "Edit upload").click();
+    });
+
+  cy.get("#upload-form #tags").clear().type(tags);
+  cy.contains("button", "Save changes").click();
+  cy.get(".modal > .content").should("not.exist");
+}
+
+describe("Tags and filters", () => {
+  beforeEach(() => {
+    cy.initialUsers();
+    cy.login(users.user);
+    cy.visit("/");
+
+    uploadFile("cypress/uploads/test-file.txt");
+    uploadFile("cypress/uploads/test-file-2.txt");
+  });
+
+  it("Tags an upload and filters by the tag", () => {
+    tagUpload("test-file.txt", "Invoices, 2026, invoices");
+
+    cy.get("#uploads-table")
+      .contains(".uploads-table-row", "test-file.txt")
+      .find(".upload-tag")
+      .should("have.length", 2);
+
+    cy.get("#uploads-table")
+      .contains(".uploads-table-row", "test-file.txt")
+      .contains(".upload-tag", "invoices")
+      .click();
+
+    cy.get("#filter-tag").should("have.value", "invoices");
+    cy.get("#uploads-table").should("contain", "test-file.txt");
+    cy.get("#uploads-table").should("not.contain", "test-file-2.txt");
+
+    cy.get("#filter-clear").click();
+    cy.get("#uploads-table").should("contain", "test-file-2.txt");
+  });
+
+  it("Filters uploads by visibility", () => {
+    cy.get("#upload-filters-button").click();
+    cy.get("#filter-public").select("Public");
+    cy.get("#uploads-table").should("contain", "No uploads match these filters");
+
+    cy.get("#filter-public").select("Private");
+    cy.get("#uploads-table").should("contain", "test-file.txt");
+    cy.get("#uploads-table").should("contain", "test-file-2.txt");
+  });
+
+  it("Saves a filter as a tab", () => {
+    tagUpload("test-file-2.txt", "reports");
+
+    cy.get("#upload-filters-button").click();
+    cy.get("#filter-tag").type("reports{enter}");
+    cy.get("#uploads-table").should("not.contain", "test-file.txt");
+
+    cy.get("#filter-save").should("be.enabled").click();
+    cy.get("#filter-form #filter-name").type("Reports");
+    cy.contains("#filter-form button", "Save filter").click();
+
+    cy.get("#team-tab-list").contains(".tab", "Reports").click();
+    cy.get("#team-tab-list").contains(".tab.active", "Reports");
+    cy.get("#uploads-table").should("contain", "test-file-2.txt");
+    cy.get("#uploads-table").should("not.contain", "test-file.txt");
+
+    cy.on("window:confirm", () => true);
+    cy.get("#filter-delete").click();
+    cy.get("#team-tab-list").should("not.contain", "Reports");
+  });
+});
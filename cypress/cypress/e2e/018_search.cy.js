@@ -0,0 +1,61 @@
+import users from "../fixtures/users.json";
+
+function uploadFile(file) {
+  cy.get("body").selectFile(file, { action: "drag-drop" });
+
+  // Wait for the upload to register
+  cy.wait(1000);
+
+  cy.contains("button", "Upload file").should("be.enabled").click();
+  cy.get(".modal > .content").should("contain", "Upload complete");
+  cy.contains("button", "Finish").should("be.enabled").click();
+  cy.get(".modal > .content").should("not.exist");
+}
+
+describe("Search", () => {
+  beforeEach(() => {
+    cy.initialUsers();
+    cy.login(users.user);
+    cy.visit("/");
+
+    uploadFile("cypress/uploads/test-file.txt");
+    uploadFile("cypress/uploads/test-file-2.txt");
+
+    // Wait for the text of the uploads to be extracted
+    cy.wait(1000);
+  });
+
+  it("Finds uploads by the text of the file", () => {
+    cy.get("#upload-search").type("second");
+    cy.get("#uploads-table").should("contain", "test-file-2.txt");
+    cy.get("#uploads-table").should("not.contain", "test-file.txt");
+
+    cy.get("#uploads-table")
+      .contains(".uploads-table-row", "test-file-2.txt")
+      .find(".upload-snippet mark")
+      .should("contain", "second");
+  });
+
+  it("Matches the start of words", () => {
+    cy.get("#upload-search").type("cypr");
+    cy.get("#uploads-table").should("contain", "test-file.txt");
+    cy.get("#uploads-table").should("not.contain", "test-file-2.txt");
+  });
+
+  it("Finds uploads by tag", () => {
+    cy.get("#uploads-table")
+      .contains(".uploads-table-row", "test-file.txt")
+      .within(() => {
+        cy.get(".dropdown-button").click();
+        cy.contains("a", "Edit upload").click();
+      });
+
+    cy.get("#upload-form #tags").clear().type("invoices");
+    cy.contains("button", "Save changes").click();
+    cy.get(".modal > .content").should("not.exist");
+
+    cy.get("#upload-search").type("invoices");
+    cy.get("#uploads-table").should("contain", "test-file.txt");
+    cy.get("#uploads-table").should("not.contain", "test-file-2.txt");
+  });
+});
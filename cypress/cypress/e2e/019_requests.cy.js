@@ -0,0 +1,147 @@
+import users from "../fixtures/users.json";
+
+function openRequestsModal() {
+  cy.visit("/");
+  cy.get("#upload-requests-button").click();
+  cy.get(".modal > .content").should("be.visible");
+}
+
+function createRequest(name, options = {}) {
+  cy.get("#request_name").type(name);
+  if (options.maxFiles) {
+    cy.get("#request_max_files_check").check();
+    cy.get("#request_max_files").clear().type(options.maxFiles);
+  }
+
+  if (options.mimeTypes) {
+    cy.get("#request_mime_types_check").check();
+    cy.get("#request_mime_types").clear().type(options.mimeTypes);
+  }
+
+  cy.get("#upload-request-form button[type='submit']").click();
+  cy.get("#upload-requests").should("contain", name);
+
+  return cy
+    .get("#upload-requests tr")
+    .contains("tr", name)
+    .find("parcel-clipboard")
+    .invoke("attr", "value");
+}
+
+// Send a file to an upload request as the drop form does, but with the given content type, which
+// the drop form would take from the browser.
+function postFile(url, csrfToken, filename, contentType, content) {
+  const boundary = "parcel-test-boundary";
+  const body = [
+    `--${boundary}`,
+    'Content-Disposition: form-data; name="csrf_token"',
+    "",
+    csrfToken,
+    `--${boundary}`,
+    `Content-Disposition: form-data; name="file"; filename="${filename}"`,
+    `Content-Type: ${contentType}`,
+    "",
+    content,
+    `--${boundary}--`,
+    "",
+  ].join("\r\n");
+
+  return cy.request({
+    method: "POST",
+    url,
+    headers: { "Content-Type": `multipart/form-data; boundary=${boundary}` },
+    body,
+    failOnStatusCode: false,
+  });
+}
+
+describe("Upload requests", () => {
+  beforeEach(() => {
+    cy.initialUsers();
+    cy.login(users.user);
+  });
+
+  it("Receives files from someone who is not signed in", () => {
+    openRequestsModal();
+    createRequest("Supplier A", { maxFiles: "1" }).then((url) => {
+      cy.clearCookies();
+      cy.visit(url);
+      cy.get("h1").should("contain", "Supplier A");
+
+      cy.get("body").selectFile("cypress/uploads/test-file.txt", {
+        action: "drag-drop",
+      });
+
+      // Wait for the upload to register
+      cy.wait(1000);
+
+      cy.contains("button", "Upload file").should("be.enabled").click();
+      cy.get("parcel-drop-form").should("contain", "Upload complete");
+
+      cy.visit(url);
+      cy.get("#request-view-container").should(
+        "contain",
+        "This upload request has received all of its files",
+      );
+    });
+
+    cy.login(users.user);
+    cy.visit("/");
+    cy.get("#upload-list-refresh").click();
+    cy.get("#uploads-table")
+      .contains(".uploads-table-row", "test-file.txt")
+      .find(".icon-inbox")
+      .should("exist");
+  });
+
+  it("Refuses files of the wrong type", () => {
+    openRequestsModal();
+    createRequest("Supplier B", { mimeTypes: "image/*" }).then((url) => {
+      cy.clearCookies();
+      cy.visit(url);
+
+      cy.get("body").selectFile("cypress/uploads/test-file.txt", {
+        action: "drag-drop",
+      });
+
+      cy.wait(1000);
+
+      cy.get("parcel-drop-form").should("contain", "Only these types of file");
+      cy.contains("button", "Upload file").should("be.disabled");
+
+      // The type is found from the contents of the file, not the type the client claims.
+      cy.get("parcel-drop-form")
+        .invoke("attr", "csrf_token")
+        .then((csrfToken) => {
+          const content = "Not a photo\n";
+          postFile(url, csrfToken, "photo.png", "image/png", content).then(
+            (response) => {
+              expect(response.status).to.eq(415);
+              expect(response.body.error).to.include("text/plain");
+            },
+          );
+        });
+    });
+  });
+
+  it("Stops accepting files once revoked", () => {
+    openRequestsModal();
+    createRequest("Supplier C").as("url");
+
+    cy.get("#upload-requests")
+      .contains("tr", "Supplier C")
+      .find("button[hx-post$='/revoke']")
+      .click();
+    cy.get("#upload-requests").contains("tr", "Supplier C").should("contain", "Revoked");
+
+    cy.clearCookies();
+    cy.get("@url").then((url) => {
+      cy.visit(url);
+      cy.get("#request-view-container").should(
+        "contain",
+        "This upload request is no longer accepting files",
+      );
+      cy.get("parcel-drop-form").should("not.exist");
+    });
+  });
+});
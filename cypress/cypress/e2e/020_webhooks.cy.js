@@ -0,0 +1,97 @@
+import users from "../fixtures/users.json";
+
+const auth = { username: users.user.username, password: users.user.password };
+
+// Nothing listens on this port, so deliveries are recorded as failed attempts.
+const WEBHOOK_URL = "http://127.0.0.1:9/webhook";
+
+function openWebhooksModal() {
+  cy.visit("/");
+  cy.get("#webhooks-button").click();
+  cy.get(".modal > .content").should("be.visible");
+}
+
+function createWebhook(name, url = WEBHOOK_URL) {
+  cy.get("#webhook_name").type(name);
+  cy.get("#webhook_url").type(url);
+  cy.get("#webhook-form button[type='submit']").click();
+}
+
+describe("Webhooks", () => {
+  beforeEach(() => {
+    cy.initialUsers();
+    cy.login(users.user);
+  });
+
+  it("Adds and deletes a webhook", () => {
+    openWebhooksModal();
+    createWebhook("Build server");
+
+    cy.get("#webhooks")
+      .contains("tr", "Build server")
+      .should("contain", WEBHOOK_URL)
+      .and("contain", "upload.created")
+      .find("parcel-clipboard")
+      .invoke("attr", "value")
+      .should("match", /^[0-9a-f]{64}$/);
+
+    cy.get("#webhooks")
+      .contains("tr", "Build server")
+      .find("button[hx-delete]")
+      .click();
+    cy.get(".modal > .content").should(
+      "contain",
+      "You do not have any webhooks",
+    );
+  });
+
+  it("Rejects a webhook without events or with an unsupported URL", () => {
+    openWebhooksModal();
+    cy.get("#webhook-form input[name='events']").uncheck();
+    createWebhook("No events");
+    cy.get(".modal > .content").should(
+      "contain",
+      "Choose at least one event to send to the webhook",
+    );
+
+    cy.get("#webhook_name").clear();
+    cy.get("#webhook_url").clear();
+    createWebhook("FTP", "ftp://127.0.0.1/webhook");
+    cy.get(".modal > .content").should(
+      "contain",
+      "The URL of a webhook must use HTTP or HTTPS",
+    );
+    cy.get("#webhooks").should("not.exist");
+  });
+
+  it("Rejects a webhook on a private network", () => {
+    openWebhooksModal();
+    createWebhook("Metadata", "http://169.254.169.254/latest/meta-data");
+    cy.get(".modal > .content").should(
+      "contain",
+      "Webhooks cannot be sent to a private network",
+    );
+    cy.get("#webhooks").should("not.exist");
+  });
+
+  it("Records deliveries of upload events", () => {
+    openWebhooksModal();
+    createWebhook("Downloads");
+    cy.get("#webhooks").should("contain", "Downloads");
+
+    cy.upload({ filename: "test-file.txt", owner: "user" }).then((upload) => {
+      cy.request({ url: `/api/v1/uploads/${upload.id}/download`, auth })
+        .its("status")
+        .should("eq", 200);
+    });
+
+    openWebhooksModal();
+    cy.get("#webhooks")
+      .contains("tr", "Downloads")
+      .contains("button", "Deliveries")
+      .click();
+    cy.get("#webhook-deliveries")
+      .should("contain", "upload.downloaded")
+      .and("contain", "test-file.txt");
+  });
+});
@@ -0,0 +1,123 @@
+import users from "../fixtures/users.json";
+
+const auth = { username: users.user.username, password: users.user.password };
+
+// The web interface of a local SMTP sink, such as Mailpit, that the server sends email to. When this
+// is not set, only the notification settings are tested.
+const MAILPIT_URL = Cypress.env("MAILPIT_URL");
+
+function saveNotifications(email) {
+  cy.visit("/user/settings");
+  cy.get("#email").clear().type(email);
+  cy.get("#notifications-form button[type='submit']").click();
+  cy.get("#notifications-success").should(
+    "contain",
+    "Your notification settings have been updated",
+  );
+}
+
+// Email is sent in the background, so keep looking for it for a few seconds.
+function findEmail(to, subject, attempts = 20) {
+  return cy
+    .request(`${MAILPIT_URL}/api/v1/messages`)
+    .its("body.messages")
+    .then((messages) => {
+      const message = messages.find(
+        (message) =>
+          message.Subject.includes(subject) &&
+          message.To.some((recipient) => recipient.Address === to),
+      );
+
+      if (message || attempts <= 1) {
+        expect(message, `email to ${to} about ${subject}`).to.exist;
+        return message;
+      }
+
+      cy.wait(500);
+      return findEmail(to, subject, attempts - 1);
+    });
+}
+
+describe("Notifications", () => {
+  beforeEach(() => {
+    cy.initialUsers();
+
+    if (MAILPIT_URL) {
+      cy.request("DELETE", `${MAILPIT_URL}/api/v1/messages`);
+    }
+  });
+
+  it("Saves notification settings", () => {
+    cy.login(users.user);
+    saveNotifications("user@example.com");
+    cy.get("#email").should("have.value", "user@example.com");
+
+    cy.get("#notify_downloads").uncheck();
+    cy.get("#notifications-form button[type='submit']").click();
+    cy.get("#notify_downloads").should("not.be.checked");
+    cy.get("#notify_expiring").should("be.checked");
+
+    // Only administrators can be told about lockouts.
+    cy.get("#notify_lockouts").should("not.exist");
+  });
+
+  it("Rejects an invalid email address", () => {
+    cy.login(users.user);
+    cy.visit("/user/settings");
+    cy.get("#email").invoke("attr", "type", "text").type("not an address");
+    cy.get("#notifications-form button[type='submit']").click();
+    cy.get("#notifications-error").should(
+      "contain",
+      "The email address is not valid",
+    );
+  });
+
+  it("Sends an email when an upload is downloaded", function () {
+    if (!MAILPIT_URL) {
+      this.skip();
+    }
+
+    cy.login(users.user);
+    saveNotifications("user@example.com");
+
+    cy.upload({ filename: "test-file.txt", owner: "user" }).then((upload) => {
+      cy.request({
+        method: "PATCH",
+        url: `/api/v1/uploads/${upload.id}`,
+        auth,
+        body: { public: true },
+      });
+
+      cy.clearCookies();
+      cy.request(`/uploads/${upload.slug}/download`)
+        .its("status")
+        .should("eq", 200);
+    });
+
+    findEmail("user@example.com", "'test-file.txt' was downloaded");
+  });
+
+  it("Sends an email to administrators when an account is locked out", function () {
+    if (!MAILPIT_URL) {
+      this.skip();
+    }
+
+    cy.login(users.admin);
+    saveNotifications("admin@example.com");
+    cy.get("#notify_lockouts").should("be.checked");
+
+    // Use an account that does not exist, so that the lockout does not affect any other tests.
+    const username = `locked-${Date.now()}`;
+    for (let attempt = 0; attempt < 10; attempt++) {
+      cy.request({
+        url: "/api/v1/user",
+        auth: { username, password: "wrong-password" },
+        failOnStatusCode: false,
+      })
+        .its("status")
+        .should("eq", 401);
+    }
+
+    findEmail("admin@example.com", `The account '${username}' has been locked out`);
+  });
+});
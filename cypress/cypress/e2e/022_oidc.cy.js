@@ -0,0 +1,116 @@
+import users from "../fixtures/users.json";
+
+// Whether the server has been configured to use a local mock OpenID Connect provider, such as the
+// one run in CI. The provider signs in the user 'oidc-user', who is in the 'parcel-admins' group.
+// Existing users are expected to be linked by their email address, with `OIDC_LINK=email`.
+const OIDC = Cypress.env("OIDC");
+
+// The mock provider redirects straight back to Parcel, so the whole sign in can be followed by one
+// request, which ends on the page that sends the browser on to its destination.
+function signInWithOidc() {
+  cy.request("/user/signin/oidc").then((response) => {
+    expect(response.status).to.eq(200);
+    expect(response.body).to.contain("Signing you in");
+  });
+}
+
+describe("Single Sign-On", () => {
+  beforeEach(() => {
+    cy.initialUsers();
+  });
+
+  it("Shows the sign in button only when configured", () => {
+    cy.visit("/user/signin");
+    cy.get("#oidc-signin").should(OIDC ? "exist" : "not.exist");
+    cy.get("#signin-form").should("exist");
+  });
+
+  it("Does not start signing in when not configured", function () {
+    if (OIDC) {
+      this.skip();
+    }
+
+    cy.request({ url: "/user/signin/oidc", failOnStatusCode: false })
+      .its("status")
+      .should("eq", 404);
+  });
+
+  it("Rejects a callback without a pending sign in", function () {
+    if (!OIDC) {
+      this.skip();
+    }
+
+    cy.visit("/user/signin/oidc/callback?code=invalid&state=invalid");
+    cy.url().should("eq", Cypress.config().baseUrl + "/user/signin");
+    cy.get("#error").should("contain", "Your sign in has expired");
+  });
+
+  it("Creates an administrator from the groups claim", function () {
+    if (!OIDC) {
+      this.skip();
+    }
+
+    signInWithOidc();
+
+    cy.visit("/admin/users");
+    cy.url().should("eq", Cypress.config().baseUrl + "/admin/users");
+    cy.get("table").should("contain", "oidc-user");
+
+    // Signing in again finds the same user, rather than creating another.
+    cy.clearCookies();
+    signInWithOidc();
+
+    cy.visit("/admin/users");
+    cy.get("table tbody tr")
+      .filter(":contains('oidc-user')")
+      .should("have.length", 1);
+
+    cy.visit("/admin/audit");
+    cy.get("table").should("contain", "User provision");
+  });
+
+  it("Does not link a user by an email address that they entered", function () {
+    if (!OIDC) {
+      this.skip();
+    }
+
+    cy.login(users.user);
+    cy.visit("/user/settings");
+    cy.get("#email").clear().type("oidc-user@example.com");
+    cy.get("#notifications-form button[type='submit']").click();
+    cy.get("#notifications-success").should("exist");
+
+    cy.clearCookies();
+    signInWithOidc();
+
+    cy.visit("/user/settings");
+    cy.get("#username").should("have.value", "oidc-user");
+  });
+
+  it("Links a user by an email address given by an administrator", function () {
+    if (!OIDC) {
+      this.skip();
+    }
+
+    cy.login(users.admin);
+    cy.visit("/admin/users");
+    cy.get("button[hx-get='/admin/users/new']").click();
+    cy.get(".modal > .content #username").type("linked-user");
+    cy.get(".modal > .content #name").type("Linked User");
+    cy.get(".modal > .content #email").type("oidc-user@example.com");
+    cy.get(".modal > .content #password").type("parcel-test-linked-1234");
+    cy.get(".modal > .content #save-user-button").click();
+    cy.get("table").should("contain", "linked-user");
+
+    cy.clearCookies();
+    signInWithOidc();
+
+    cy.visit("/user/settings");
+    cy.get("#username").should("have.value", "linked-user");
+
+    // The user is in the admin group, but linking them does not make them an administrator.
+    cy.request({ url: "/admin/users", failOnStatusCode: false })
+      .its("status")
+      .should("eq", 403);
+  });
+});
@@ -0,0 +1,103 @@
+import users from "../fixtures/users.json";
+
+// Whether the server has been configured to use a local test LDAP server, such as the one run in
+// CI. The directory has the user 'fry' (with the password 'fry'), who is in the group 'ship_crew',
+// which is mapped to the team 'ship-crew'.
+const LDAP = Cypress.env("LDAP");
+
+function signIn(username, password) {
+  cy.visit("/user/signin");
+  cy.get("input[name=username]").type(username);
+  cy.get("input[name=password]").type(password);
+  cy.get("button[type=submit]").click();
+}
+
+describe("LDAP", () => {
+  beforeEach(function () {
+    if (!LDAP) {
+      this.skip();
+    }
+
+    cy.initialUsers();
+  });
+
+  it("Creates a user when they first sign in", () => {
+    signIn("fry", "fry");
+    cy.url().should("eq", Cypress.config().baseUrl + "/");
+
+    cy.visit("/user/settings");
+    cy.get("#username").should("have.value", "fry");
+    cy.get("#name").should("have.value", "Philip J. Fry");
+
+    // Signing in again finds the same user, rather than creating another.
+    cy.clearCookies();
+    signIn("fry", "fry");
+    cy.url().should("eq", Cypress.config().baseUrl + "/");
+
+    cy.clearCookies();
+    cy.login(users.admin);
+    cy.visit("/admin/users");
+    cy.get("table tbody tr")
+      .filter(":contains('fry')")
+      .should("have.length", 1);
+  });
+
+  it("Does not link an existing user with the same username", () => {
+    // A user of Parcel that happens to share a username with an entry in the directory.
+    const fry = { ...users.user, username: "fry", name: "Local Fry" };
+    cy.request("POST", "/debug/initial-users", [users.admin, fry]);
+
+    signIn("fry", "fry");
+    cy.url().should("eq", Cypress.config().baseUrl + "/user/signin");
+    cy.get("#error").should("contain", "Invalid username or password");
+
+    // The user still signs in with their own password.
+    signIn("fry", fry.password);
+    cy.url().should("eq", Cypress.config().baseUrl + "/");
+    cy.visit("/user/settings");
+    cy.get("#name").should("have.value", "Local Fry");
+  });
+
+  it("Requires an API token for users from the directory", () => {
+    signIn("fry", "fry");
+    cy.url().should("eq", Cypress.config().baseUrl + "/");
+
+    cy.request({
+      url: "/api/v1/user",
+      auth: { username: "fry", password: "fry" },
+      failOnStatusCode: false,
+    })
+      .its("status")
+      .should("eq", 401);
+  });
+
+  it("Rejects an invalid directory password", () => {
+    signIn("fry", "not-the-password");
+    cy.url().should("eq", Cypress.config().baseUrl + "/user/signin");
+    cy.get("#error").should("contain", "Invalid username or password");
+  });
+
+  it("Still signs in users with their own password", () => {
+    signIn(users.user.username, users.user.password);
+    cy.url().should("eq", Cypress.config().baseUrl + "/");
+  });
+
+  it("Synchronises teams with directory groups", () => {
+    signIn("fry", "fry");
+    cy.url().should("eq", Cypress.config().baseUrl + "/");
+
+    cy.request({ url: "/teams/ship-crew", failOnStatusCode: false })
+      .its("status")
+      .should("not.eq", 200);
+
+    cy.request("/debug/ldap-sync");
+
+    cy.visit("/teams/ship-crew");
+    cy.title().should("contain", "ship_crew Uploads");
+
+    cy.clearCookies();
+    cy.login(users.admin);
+    cy.visit("/admin/audit");
+    cy.get("table").should("contain", "Team sync");
+  });
+});
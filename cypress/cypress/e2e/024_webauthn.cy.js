@@ -0,0 +1,148 @@
+import users from "../fixtures/users.json";
+
+// WebAuthn needs an authenticator, so these tests use the virtual authenticator that Chromium makes
+// available over the DevTools protocol. The authenticator keeps passkeys and always verifies the
+// user, much like the platform authenticator of a laptop with a fingerprint reader.
+function sendCommand(command, params = {}) {
+  return Cypress.automation("remote:debugger:protocol", { command, params });
+}
+
+function signIn(username, password) {
+  cy.visit("/user/signin");
+  cy.get("input[name=username]").type(username);
+  cy.get("input[name=password]").type(password);
+  cy.get("button[type=submit]").click();
+}
+
+function registerKey(name) {
+  cy.visit("/user/settings");
+  cy.get("#webauthn-form").should("be.visible");
+  cy.get("#webauthn_name").type(name);
+  cy.get("#webauthn-form button[type=submit]").click();
+  cy.get("#webauthn-success").should(
+    "contain",
+    `The security key '${name}' has been registered`,
+  );
+}
+
+describe("WebAuthn", () => {
+  beforeEach(function () {
+    if (Cypress.browser.family !== "chromium") {
+      this.skip();
+    }
+
+    cy.initialUsers();
+
+    cy.wrap(
+      sendCommand("WebAuthn.enable").then(() =>
+        sendCommand("WebAuthn.addVirtualAuthenticator", {
+          options: {
+            protocol: "ctap2",
+            transport: "internal",
+            hasResidentKey: true,
+            hasUserVerification: true,
+            isUserVerified: true,
+          },
+        }),
+      ),
+    )
+      .its("authenticatorId")
+      .as("authenticatorId");
+  });
+
+  afterEach(function () {
+    if (this.authenticatorId) {
+      cy.wrap(
+        sendCommand("WebAuthn.removeVirtualAuthenticator", {
+          authenticatorId: this.authenticatorId,
+        }),
+      );
+    }
+  });
+
+  it("Registers a security key", () => {
+    cy.login(users.user);
+    registerKey("Test key");
+
+    cy.get("#webauthn table tbody tr").should("have.length", 1);
+    cy.get("#webauthn table tbody tr").first().should("contain", "Test key");
+    cy.get("#webauthn table tbody tr").first().should("contain", "Never");
+  });
+
+  it("Requires the security key after the password", () => {
+    cy.login(users.user);
+    registerKey("Test key");
+
+    cy.clearCookies();
+    signIn(users.user.username, users.user.password);
+    cy.url().should("eq", Cypress.config().baseUrl + "/user/signin/totp");
+    cy.get("#totp-form").should("not.exist");
+
+    cy.get("#webauthn-signin").click();
+    cy.url().should("eq", Cypress.config().baseUrl + "/");
+
+    cy.visit("/user/settings");
+    cy.get("#webauthn table tbody tr").first().should("not.contain", "Never");
+  });
+
+  it("Signs in with a passkey and no password", () => {
+    cy.login(users.user);
+    registerKey("Test key");
+
+    cy.clearCookies();
+    cy.visit("/user/signin");
+    cy.get("#passkey-signin").click();
+    cy.url().should("eq", Cypress.config().baseUrl + "/");
+
+    cy.visit("/user/settings");
+    cy.get("#username").should("have.value", users.user.username);
+  });
+
+  it("Removes a security key", () => {
+    cy.login(users.user);
+    registerKey("Test key");
+
+    cy.get("#webauthn table button[type=submit]").click();
+    cy.get("#webauthn-success").should(
+      "contain",
+      "The security key 'Test key' has been removed",
+    );
+    cy.get("#webauthn table").should("not.exist");
+
+    // Without a key, signing in only needs the password again.
+    cy.clearCookies();
+    signIn(users.user.username, users.user.password);
+    cy.url().should("eq", Cypress.config().baseUrl + "/");
+  });
+
+  it("Lets administrators reset the keys of a user", () => {
+    cy.login(users.user);
+    registerKey("Test key");
+
+    cy.clearCookies();
+    cy.login(users.admin);
+    cy.visit("/admin/users");
+
+    cy.contains(
+      "#user-list-container table > tbody > tr",
+      users.user.name,
+    ).within(() => {
+      cy.get("td:nth-child(12) .dropdown-button").click();
+      cy.get("a[title='Edit user']").click();
+    });
+
+    cy.get(".modal > .content #user-webauthn").should("contain", "Test key");
+    cy.get(".modal > .content #reset-webauthn-button").click();
+    cy.get(".modal > .content #user-webauthn").should(
+      "contain",
+      "This user has not registered any security keys or passkeys.",
+    );
+
+    cy.visit("/admin/audit");
+    cy.get("table").should("contain", "Webauthn reset");
+
+    cy.clearCookies();
+    signIn(users.user.username, users.user.password);
+    cy.url().should("eq", Cypress.config().baseUrl + "/");
+  });
+});
@@ -0,0 +1,211 @@
+import users from "../fixtures/users.json";
+
+const BASE32 = "ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+function decodeBase32(value) {
+  let bits = "";
+  for (const char of value.replace(/=+$/, "")) {
+    bits += BASE32.indexOf(char).toString(2).padStart(5, "0");
+  }
+
+  const bytes = new Uint8Array(Math.floor(bits.length / 8));
+  for (let i = 0; i < bytes.length; i++) {
+    bytes[i] = parseInt(bits.slice(i * 8, i * 8 + 8), 2);
+  }
+
+  return bytes;
+}
+
+// Generate the current TOTP code for a secret, in the same way as an authenticator app.
+async function generateTotp(secret) {
+  const key = await crypto.subtle.importKey(
+    "raw",
+    decodeBase32(secret),
+    { name: "HMAC", hash: "SHA-1" },
+    false,
+    ["sign"],
+  );
+
+  const counter = new DataView(new ArrayBuffer(8));
+  counter.setBigUint64(0, BigInt(Math.floor(Date.now() / 30000)));
+
+  const hmac = new Uint8Array(
+    await crypto.subtle.sign("HMAC", key, counter.buffer),
+  );
+  const offset = hmac[hmac.length - 1] & 0x0f;
+  const code =
+    (((hmac[offset] & 0x7f) << 24) |
+      (hmac[offset + 1] << 16) |
+      (hmac[offset + 2] << 8) |
+      hmac[offset + 3]) %
+    1000000;
+
+  return code.toString().padStart(6, "0");
+}
+
+function signIn(username, password) {
+  cy.visit("/user/signin");
+  cy.get("input[name=username]").type(username);
+  cy.get("input[name=password]").type(password);
+  cy.get("button[type=submit]").click();
+}
+
+function enableTotp() {
+  cy.visit("/user/settings");
+  cy.get("button[hx-get='/user/settings/totp']").first().click();
+  cy.get("#totp-form pre")
+    .invoke("text")
+    .then((secret) => cy.wrap(generateTotp(secret.trim())))
+    .then((code) => {
+      cy.get("#totp-form input[name=code]").type(code);
+      cy.get("#totp-form button[type=submit]").click();
+    });
+
+  cy.url().should("eq", Cypress.config().baseUrl + "/user/settings");
+  cy.get("#recovery-codes li").should("have.length", 10);
+  return cy
+    .get("#recovery-codes li")
+    .then((items) => items.toArray().map((item) => item.innerText.trim()));
+}
+
+function signInWithCode(code) {
+  signIn(users.user.username, users.user.password);
+  cy.url().should("eq", Cypress.config().baseUrl + "/user/signin/totp");
+  cy.get("#code").type(code);
+  cy.get("#totp-form button[type=submit]").click();
+}
+
+describe("TOTP recovery", () => {
+  beforeEach(() => {
+    cy.initialUsers();
+    cy.login(users.user);
+  });
+
+  it("Shows recovery codes when two-factor authentication is enabled", () => {
+    enableTotp().then((codes) => {
+      expect(new Set(codes).size).to.eq(10);
+      codes.forEach((code) =>
+        expect(code).to.match(/^[a-z0-9]{5}-[a-z0-9]{5}$/),
+      );
+    });
+
+    cy.get("#recovery-codes-form").should(
+      "contain",
+      "You have 10 unused recovery codes",
+    );
+
+    // The codes are only shown once.
+    cy.reload();
+    cy.get("#recovery-codes").should("not.exist");
+  });
+
+  it("Signs in with each recovery code only once", () => {
+    enableTotp().then((codes) => {
+      cy.clearCookies();
+      signInWithCode(codes[0].toUpperCase());
+      cy.url().should("eq", Cypress.config().baseUrl + "/");
+
+      cy.clearCookies();
+      signInWithCode(codes[0]);
+      cy.url().should("eq", Cypress.config().baseUrl + "/user/signin/totp");
+      cy.get("#error").should("contain", "already been used");
+
+      cy.get("#code").type(codes[1]);
+      cy.get("#totp-form button[type=submit]").click();
+      cy.url().should("eq", Cypress.config().baseUrl + "/");
+    });
+
+    cy.visit("/user/settings");
+    cy.get("#recovery-codes-form").should(
+      "contain",
+      "You have 8 unused recovery codes",
+    );
+  });
+
+  it("Does not take a mistyped TOTP code for a recovery code", () => {
+    enableTotp().then(() => {
+      cy.clearCookies();
+      signInWithCode("123 456");
+      cy.url().should("eq", Cypress.config().baseUrl + "/user/signin/totp");
+      cy.get("#error").should("contain", "sequence of six numbers");
+
+      cy.get("#code").type("12345a");
+      cy.get("#totp-form button[type=submit]").click();
+      cy.get("#error").should("contain", "sequence of six numbers");
+    });
+  });
+
+  it("Replaces recovery codes when they are regenerated", () => {
+    enableTotp().then((codes) => {
+      cy.get("#recovery-codes-form button[type=submit]").click();
+      cy.get("#recovery-codes li").should("have.length", 10);
+      cy.get("#recovery-codes").should("not.contain", codes[0]);
+
+      cy.clearCookies();
+      signInWithCode(codes[0]);
+      cy.get("#error").should("contain", "incorrect or has already been used");
+    });
+  });
+
+  it("Lets administrators reset two-factor authentication", () => {
+    enableTotp();
+
+    cy.get("#api_token_name").type("Script");
+    cy.get("#api-token-form button[type=submit]").click();
+    cy.get("#new-api-token input").invoke("val").as("token");
+
+    cy.clearCookies();
+    cy.login(users.admin);
+    cy.visit("/admin/users");
+
+    cy.contains(
+      "#user-list-container table > tbody > tr",
+      users.user.name,
+    ).within(() => {
+      cy.get("td:nth-child(12) .dropdown-button").click();
+      cy.get("a[title='Edit user']").click();
+    });
+
+    cy.get(".modal > .content #reset-totp-button").click();
+    cy.get(".modal > .content #user-totp").should(
+      "contain",
+      "will need to set it up again",
+    );
+
+    cy.visit("/admin/audit");
+    cy.get("table").should("contain", "Totp reset");
+
+    // The API cannot be used, with a password or a token, until it has been set up again.
+    cy.request({
+      url: "/api/v1/user",
+      auth: { username: users.user.username, password: users.user.password },
+      failOnStatusCode: false,
+    }).then((response) => {
+      expect(response.status).to.eq(403);
+      expect(response.body.error).to.include("Two-factor authentication");
+    });
+
+    cy.get("@token").then((token) => {
+      cy.request({
+        url: "/api/v1/user",
+        headers: { Authorization: `Bearer ${token}` },
+        failOnStatusCode: false,
+      })
+        .its("status")
+        .should("eq", 403);
+    });
+
+    // The user no longer needs a code, but must set up two-factor authentication again.
+    cy.clearCookies();
+    signIn(users.user.username, users.user.password);
+    cy.url().should("eq", Cypress.config().baseUrl + "/user/settings");
+    cy.get("#totp-required").should("exist");
+
+    cy.visit("/");
+    cy.url().should("eq", Cypress.config().baseUrl + "/user/settings");
+
+    enableTotp();
+    cy.visit("/");
+    cy.url().should("eq", Cypress.config().baseUrl + "/");
+  });
+});
@@ -0,0 +1,238 @@
+import users from "../fixtures/users.json";
+
+// The web interface of a local SMTP sink, such as Mailpit, that the server sends email to. When this
+// is not set, the forgotten password flow is not tested, as the server only offers it when it can
+// send email.
+const MAILPIT_URL = Cypress.env("MAILPIT_URL");
+
+function signIn(username, password) {
+  cy.visit("/user/signin");
+  cy.get("input[name=username]").type(username);
+  cy.get("input[name=password]").type(password);
+  cy.get("button[type=submit]").click();
+}
+
+function choosePassword(password, confirm = password) {
+  cy.get("#password").type(password);
+  cy.get("#confirm").type(confirm);
+  cy.get("#choose-password-form button[type=submit]").click();
+}
+
+function inviteUser(username, name) {
+  cy.login(users.admin);
+  cy.visit("/admin/users");
+  cy.get("button[hx-get='/admin/users/new']").click();
+  cy.get(".modal > .content #username").type(username);
+  cy.get(".modal > .content #name").type(name);
+  cy.get(".modal > .content #invite").check();
+  cy.get(".modal > .content #password").should("be.disabled");
+  cy.get(".modal > .content #save-user-button").click();
+
+  cy.get("#password-link").should("contain", "An invitation for");
+  return cy.get("#password-link parcel-clipboard").invoke("attr", "value");
+}
+
+function setEmail(email) {
+  cy.login(users.user);
+  cy.visit("/user/settings");
+  cy.get("#email").clear().type(email);
+  cy.get("#notifications-form button[type='submit']").click();
+  cy.clearCookies();
+}
+
+function forgotPassword(username) {
+  cy.visit("/user/signin");
+  cy.get("#forgot-password").click();
+  cy.get("#username").type(username);
+  cy.get("#forgot-password-form button[type=submit]").click();
+}
+
+function getResetPath(message) {
+  return cy
+    .request(`${MAILPIT_URL}/api/v1/message/${message.ID}`)
+    .its("body.Text")
+    .then((text) => text.match(/\/user\/password\/reset\/[0-9a-f]+/)[0]);
+}
+
+// Email is sent in the background, so keep looking for it for a few seconds.
+function findEmail(to, subject, attempts = 20) {
+  return cy
+    .request(`${MAILPIT_URL}/api/v1/messages`)
+    .its("body.messages")
+    .then((messages) => {
+      const message = messages.find(
+        (message) =>
+          message.Subject.includes(subject) &&
+          message.To.some((recipient) => recipient.Address === to),
+      );
+
+      if (message || attempts <= 1) {
+        expect(message, `email to ${to} about ${subject}`).to.exist;
+        return message;
+      }
+
+      cy.wait(500);
+      return findEmail(to, subject, attempts - 1);
+    });
+}
+
+describe("Invitations and password resets", () => {
+  beforeEach(() => {
+    cy.initialUsers();
+
+    if (MAILPIT_URL) {
+      cy.request("DELETE", `${MAILPIT_URL}/api/v1/messages`);
+    }
+  });
+
+  it("Invites a user to choose their own password", () => {
+    inviteUser("invited", "Invited User").then((url) => {
+      cy.clearCookies();
+      cy.visit(url);
+      cy.get("#choose-password-form").should("contain", "invited");
+
+      choosePassword("invited-password-1234", "something-else");
+      cy.get("#error").should("contain", "The passwords do not match");
+
+      choosePassword("invited-password-1234");
+      cy.url().should("eq", Cypress.config().baseUrl + "/user/settings");
+      cy.get("#success").should("contain", "Welcome to Parcel!");
+      cy.get("#totp-form").should("be.visible");
+
+      // The invitation can only be used once.
+      cy.clearCookies();
+      cy.visit(url);
+      cy.get("#error").should("contain", "This invitation has expired");
+      cy.get("#choose-password-form").should("not.exist");
+    });
+
+    signIn("invited", "invited-password-1234");
+    cy.url().should("eq", Cypress.config().baseUrl + "/");
+  });
+
+  it("Requires a password unless the user is invited", () => {
+    cy.login(users.admin);
+    cy.visit("/admin/users");
+    cy.get("button[hx-get='/admin/users/new']").click();
+    cy.get(".modal > .content #username").type("no-password");
+    cy.get(".modal > .content #name").type("No Password");
+    cy.get(".modal > .content #password")
+      .invoke("removeAttr", "required")
+      .invoke("removeAttr", "minlength");
+    cy.get(".modal > .content #save-user-button").click();
+    cy.get(".modal > .content #user-form").should(
+      "contain",
+      "Passwords must be at least eight characters in length",
+    );
+  });
+
+  it("Lets administrators create a password reset link", () => {
+    // Sign in once, so that the user is sent a password reset rather than a new invitation.
+    cy.login(users.user);
+    cy.visit("/");
+
+    cy.clearCookies();
+    cy.login(users.admin);
+    cy.visit("/admin/users");
+
+    cy.contains(
+      "#user-list-container table > tbody > tr",
+      users.user.name,
+    ).within(() => {
+      cy.get("td:nth-child(12) .dropdown-button").click();
+      cy.get("a[title='Edit user']").click();
+    });
+
+    cy.get(".modal > .content #password-link-button").click();
+    cy.get(".modal > .content #password-link").should(
+      "contain",
+      "to reset their password has been created",
+    );
+
+    cy.get(".modal > .content #password-link parcel-clipboard")
+      .invoke("attr", "value")
+      .then((url) => {
+        cy.visit("/admin/audit");
+        cy.get("table").should("contain", "Password link");
+
+        cy.clearCookies();
+        cy.visit(url);
+        choosePassword("new-user-password-1234");
+        cy.url().should("eq", Cypress.config().baseUrl + "/user/signin");
+        cy.get("#success").should("contain", "Your password has been changed");
+      });
+
+    signIn(users.user.username, users.user.password);
+    cy.get("#error").should("contain", "Invalid username or password");
+
+    signIn(users.user.username, "new-user-password-1234");
+    cy.url().should("eq", Cypress.config().baseUrl + "/");
+  });
+
+  it("Rejects an unknown link", () => {
+    cy.visit("/user/password/reset/not-a-real-token");
+    cy.get("#error").should("contain", "This link has expired");
+    cy.get("#choose-password-form").should("not.exist");
+  });
+
+  it("Sends a link to reset a forgotten password", function () {
+    if (!MAILPIT_URL) {
+      this.skip();
+    }
+
+    setEmail("user@example.com");
+    forgotPassword("user@example.com");
+    cy.get("#success").should("contain", "a link to reset its password");
+
+    findEmail("user@example.com", "Reset your Parcel password")
+      .then(getResetPath)
+      .then((path) => {
+        cy.visit(path);
+        choosePassword("forgotten-password-1234");
+        cy.url().should("eq", Cypress.config().baseUrl + "/user/signin");
+      });
+
+    signIn(users.user.username, "forgotten-password-1234");
+    cy.url().should("eq", Cypress.config().baseUrl + "/");
+  });
+
+  it("Keeps a password reset link that was just sent", function () {
+    if (!MAILPIT_URL) {
+      this.skip();
+    }
+
+    setEmail("user@example.com");
+    forgotPassword(users.user.username);
+    findEmail("user@example.com", "Reset your Parcel password").as("first");
+
+    // Asking again does not send another link, or stop the first one from working.
+    forgotPassword(users.user.username);
+    cy.get("#success").should("contain", "a link to reset its password");
+    cy.wait(2000);
+    cy.request(`${MAILPIT_URL}/api/v1/messages`)
+      .its("body.messages")
+      .should("have.length", 1);
+
+    cy.get("@first")
+      .then(getResetPath)
+      .then((path) => {
+        cy.visit(path);
+        choosePassword("forgotten-password-1234");
+        cy.url().should("eq", Cypress.config().baseUrl + "/user/signin");
+      });
+  });
+
+  it("Limits how many password reset links can be asked for", function () {
+    if (!MAILPIT_URL) {
+      this.skip();
+    }
+
+    for (let i = 0; i < 5; i++) {
+      forgotPassword(`nobody-${i}`);
+      cy.get("#success").should("exist");
+    }
+
+    forgotPassword("nobody-5");
+    cy.get("#error").should("contain", "Too many password resets");
+  });
+});
@@ -0,0 +1,196 @@
+import users from "../fixtures/users.json";
+
+const auth = { username: users.user.username, password: users.user.password };
+
+const MISSING_ID = "00000000-0000-0000-0000-000000000000";
+
+function expectError(response, status) {
+  expect(response.status).to.eq(status);
+  expect(response.headers["content-type"]).to.include("application/json");
+  expect(response.body.error).to.be.a("string").and.not.be.empty;
+}
+
+describe("JSON API", () => {
+  beforeEach(() => {
+    cy.initialUsers();
+    cy.upload({ filename: "test-file.txt", owner: "user" }).as("upload");
+  });
+
+  it("Gets the current user", () => {
+    cy.request({ url: "/api/v1/user", auth })
+      .its("body.username")
+      .should("eq", users.user.username);
+  });
+
+  it("Lists uploads", () => {
+    cy.request({ url: "/api/v1/uploads", auth }).then((response) => {
+      expect(response.status).to.eq(200);
+      expect(response.body.page).to.eq(0);
+      expect(response.body.uploads).to.have.length(1);
+      expect(response.body.uploads[0].filename).to.eq("test-file.txt");
+    });
+
+    cy.request({ url: "/api/v1/uploads?search=nothing-matches", auth })
+      .its("body.uploads")
+      .should("have.length", 0);
+  });
+
+  it("Gets an upload", function () {
+    cy.request({ url: `/api/v1/uploads/${this.upload.id}`, auth }).then(
+      (response) => {
+        expect(response.status).to.eq(200);
+        expect(response.body.id).to.eq(this.upload.id);
+        expect(response.body.filename).to.eq("test-file.txt");
+        expect(response.body.size).to.eq(69);
+        expect(response.body.public).to.be.false;
+        expect(response.body.has_password).to.be.false;
+      },
+    );
+  });
+
+  it("Edits an upload", function () {
+    cy.request({
+      method: "PATCH",
+      url: `/api/v1/uploads/${this.upload.id}`,
+      auth,
+      body: { filename: "renamed.txt", public: true, limit: 5 },
+    }).then((response) => {
+      expect(response.status).to.eq(200);
+      expect(response.body.filename).to.eq("renamed.txt");
+      expect(response.body.public).to.be.true;
+      expect(response.body.limit).to.eq(5);
+      expect(response.body.remaining).to.eq(5);
+    });
+
+    // A missing field is left alone, and a null clears it.
+    cy.request({
+      method: "PATCH",
+      url: `/api/v1/uploads/${this.upload.id}`,
+      auth,
+      body: { limit: null },
+    }).then((response) => {
+      expect(response.body.filename).to.eq("renamed.txt");
+      expect(response.body.limit).to.be.null;
+      expect(response.body.remaining).to.be.null;
+    });
+  });
+
+  it("Reports validation errors", function () {
+    cy.request({
+      method: "PATCH",
+      url: `/api/v1/uploads/${this.upload.id}`,
+      auth,
+      body: { filename: "" },
+      failOnStatusCode: false,
+    }).then((response) => {
+      expectError(response, 422);
+      expect(response.body.fields).to.have.property("filename");
+    });
+  });
+
+  it("Downloads an upload", function () {
+    cy.readFile("cypress/uploads/test-file.txt").then((content) => {
+      cy.request({
+        url: `/api/v1/uploads/${this.upload.id}/download`,
+        auth,
+      }).then((response) => {
+        expect(response.status).to.eq(200);
+        expect(response.headers["content-disposition"]).to.include(
+          "test-file.txt",
+        );
+        expect(response.body).to.eq(content);
+      });
+    });
+  });
+
+  it("Resets the remaining downloads of an upload", function () {
+    cy.request({
+      method: "PATCH",
+      url: `/api/v1/uploads/${this.upload.id}`,
+      auth,
+      body: { public: true, limit: 1 },
+    });
+
+    cy.request(`/uploads/${this.upload.slug}/download`)
+      .its("status")
+      .should("eq", 200);
+    cy.request({ url: `/api/v1/uploads/${this.upload.id}`, auth }).then(
+      (response) => {
+        expect(response.body.downloads).to.eq(1);
+        expect(response.body.remaining).to.eq(0);
+      },
+    );
+
+    cy.request({
+      method: "POST",
+      url: `/api/v1/uploads/${this.upload.id}/reset`,
+      auth,
+    }).then((response) => {
+      expect(response.status).to.eq(200);
+      expect(response.body.remaining).to.eq(1);
+    });
+  });
+
+  it("Deletes an upload", function () {
+    cy.request({
+      method: "DELETE",
+      url: `/api/v1/uploads/${this.upload.id}`,
+      auth,
+    })
+      .its("status")
+      .should("eq", 204);
+
+    cy.request({
+      url: `/api/v1/uploads/${this.upload.id}`,
+      auth,
+      failOnStatusCode: false,
+    }).then((response) => expectError(response, 404));
+  });
+
+  it("Returns errors as JSON", function () {
+    cy.request({ url: "/api/v1/uploads", failOnStatusCode: false }).then(
+      (response) => {
+        expectError(response, 401);
+        expect(response.headers["www-authenticate"]).to.include("Basic");
+      },
+    );
+
+    cy.request({
+      url: "/api/v1/uploads",
+      auth: { username: users.user.username, password: "wrong-password" },
+      failOnStatusCode: false,
+    }).then((response) => expectError(response, 401));
+
+    cy.request({
+      url: `/api/v1/uploads/${MISSING_ID}`,
+      auth,
+      failOnStatusCode: false,
+    }).then((response) => expectError(response, 404));
+
+    cy.request({
+      url: "/api/v1/uploads/not-an-id",
+      auth,
+      failOnStatusCode: false,
+    }).then((response) => expectError(response, 400));
+
+    cy.request({
+      method: "PATCH",
+      url: `/api/v1/uploads/${this.upload.id}`,
+      auth,
+      headers: { "Content-Type": "application/json" },
+      body: "{ not json",
+      failOnStatusCode: false,
+    }).then((response) => expectError(response, 400));
+
+    // Another user's private upload cannot be seen.
+    cy.upload({ filename: "test-file-2.txt", owner: "admin" }).then(
+      (upload) => {
+        cy.request({
+          url: `/api/v1/uploads/${upload.id}`,
+          auth,
+          failOnStatusCode: false,
+        }).then((response) => expectError(response, 403));
+      },
+    );
+  });
+});
@@ -0,0 +1,171 @@
+import users from "../fixtures/users.json";
+
+function createToken({
+  name,
+  team = "My personal uploads",
+  read = true,
+  upload = false,
+  del = false,
+}) {
+  cy.visit("/user/settings");
+  cy.get("#api_token_name").type(name);
+  cy.get("#api_token_team").select(team);
+
+  const scopes = { read, upload, delete: del };
+  for (const [scope, enabled] of Object.entries(scopes)) {
+    cy.get(`#api_token_can_${scope}`)[enabled ? "check" : "uncheck"]();
+  }
+
+  cy.get("#api-token-form button[type=submit]").click();
+  return cy.get("#new-api-token input").invoke("val");
+}
+
+function apiRequest(token, options) {
+  return cy.request({
+    ...options,
+    headers: { Authorization: `Bearer ${token}` },
+    failOnStatusCode: false,
+  });
+}
+
+function getTeamId(user, slug) {
+  return cy
+    .request({
+      url: "/api/v1/teams",
+      auth: { username: user.username, password: user.password },
+    })
+    .its("body")
+    .then((teams) => teams.find((team) => team.slug === slug).id);
+}
+
+describe("API tokens", () => {
+  beforeEach(() => {
+    cy.initialUsers();
+    cy.initialTeams();
+    cy.login(users.user);
+  });
+
+  it("Restricts a token to its scopes", () => {
+    cy.upload({ filename: "test-file.txt", owner: "user" }).then((upload) => {
+      createToken({ name: "Read only" }).then((token) => {
+        apiRequest(token, { url: `/api/v1/uploads/${upload.id}` })
+          .its("status")
+          .should("eq", 200);
+
+        apiRequest(token, {
+          method: "PATCH",
+          url: `/api/v1/uploads/${upload.id}`,
+          body: { filename: "renamed.txt" },
+        }).then((response) => {
+          expect(response.status).to.eq(403);
+          expect(response.body.error).to.include("does not allow");
+        });
+
+        apiRequest(token, {
+          method: "DELETE",
+          url: `/api/v1/uploads/${upload.id}`,
+        })
+          .its("status")
+          .should("eq", 403);
+      });
+    });
+  });
+
+  it("Restricts a token to its team", () => {
+    cy.upload({ filename: "test-file.txt", owner: "user" }).as("personal");
+    cy.upload({ filename: "test-file-2.txt", owner: "team-a" }).as("team");
+
+    createToken({ name: "Personal" }).then(function (token) {
+      apiRequest(token, { url: `/api/v1/uploads/${this.personal.id}` })
+        .its("status")
+        .should("eq", 200);
+      apiRequest(token, { url: `/api/v1/uploads/${this.team.id}` })
+        .its("status")
+        .should("eq", 403);
+    });
+
+    createToken({ name: "Team", team: "Uploads of team Team A" }).then(
+      function (token) {
+        apiRequest(token, { url: `/api/v1/uploads/${this.team.id}` })
+          .its("status")
+          .should("eq", 200);
+        apiRequest(token, { url: `/api/v1/uploads/${this.personal.id}` })
+          .its("status")
+          .should("eq", 403);
+      },
+    );
+  });
+
+  it("Transfers an upload with a token", () => {
+    // A second team that the user is not a member of.
+    cy.request("POST", "/debug/initial-teams", [
+      {
+        name: "Team B",
+        slug: "team-b",
+        members: [{ name: "admin", edit: true, delete: true, config: true }],
+      },
+    ]);
+
+    getTeamId(users.user, "team-a").as("teamA");
+    getTeamId(users.admin, "team-b").as("teamB");
+    cy.upload({ filename: "test-file.txt", owner: "user" }).as("upload");
+
+    // A token for the team cannot reach the user's own uploads.
+    createToken({
+      name: "Team",
+      team: "Uploads of team Team A",
+      upload: true,
+    }).then(function (token) {
+      apiRequest(token, {
+        method: "POST",
+        url: `/api/v1/uploads/${this.upload.id}/transfer`,
+        body: { team: this.teamA, action: "move" },
+      })
+        .its("status")
+        .should("eq", 403);
+    });
+
+    createToken({ name: "Personal", upload: true }).then(function (token) {
+      // The user must be a member of the team that the upload is sent to.
+      apiRequest(token, {
+        method: "POST",
+        url: `/api/v1/uploads/${this.upload.id}/transfer`,
+        body: { team: this.teamB, action: "move" },
+      })
+        .its("status")
+        .should("eq", 403);
+
+      apiRequest(token, {
+        method: "POST",
+        url: `/api/v1/uploads/${this.upload.id}/transfer`,
+        body: { team: this.teamA, action: "move" },
+      }).then((response) => {
+        expect(response.status).to.eq(200);
+        expect(response.body.owner_team).to.eq(this.teamA);
+        expect(response.body.owner_user).to.be.null;
+      });
+
+      apiRequest(token, { url: `/api/v1/uploads/${this.upload.id}` })
+        .its("status")
+        .should("eq", 404);
+    });
+  });
+
+  it("Rejects a revoked token", () => {
+    createToken({ name: "Script" }).then((token) => {
+      apiRequest(token, { url: "/api/v1/user" })
+        .its("body.username")
+        .should("eq", users.user.username);
+
+      cy.contains("#api-tokens tr", "Script").within(() => {
+        cy.get("button[title='Revoke this token']").click();
+      });
+      cy.contains("#api-tokens tr", "Script").should("contain", "Revoked");
+
+      apiRequest(token, { url: "/api/v1/user" }).then((response) => {
+        expect(response.status).to.eq(401);
+        expect(response.body.error).to.eq("The API token has been revoked");
+      });
+    });
+  });
+});
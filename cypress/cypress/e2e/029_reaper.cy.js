@@ -0,0 +1,103 @@
+import users from "../fixtures/users.json";
+
+// The retention policies that the server was started with. The reaper would interfere with the
+// other tests, so these tests are only run against a server that was started with these policies
+// and a short `REAPER_INTERVAL`.
+const EXPIRED_RETENTION = Cypress.env("EXPIRED_RETENTION");
+const EXHAUSTED_RETENTION = Cypress.env("EXHAUSTED_RETENTION");
+
+const auth = { username: users.user.username, password: users.user.password };
+
+function yesterday() {
+  const date = new Date();
+  date.setDate(date.getDate() - 1);
+  return date.toISOString().slice(0, 10);
+}
+
+function isListed(upload) {
+  return cy
+    .request({ url: "/api/v1/uploads", auth })
+    .then((response) =>
+      response.body.uploads.some((item) => item.id === upload.id),
+    );
+}
+
+function isDeleted(upload) {
+  return cy
+    .request({
+      url: `/api/v1/uploads/${upload.id}`,
+      auth,
+      failOnStatusCode: false,
+    })
+    .then((response) => response.status === 404);
+}
+
+function getBlob(upload) {
+  return cy.request(`/debug/blobs/${upload.blob}`).its("body");
+}
+
+// The reaper runs in the background, so keep checking for a few seconds.
+function waitFor(check, description, attempts = 20) {
+  return check().then((done) => {
+    if (done || attempts <= 1) {
+      expect(done, description).to.be.true;
+      return;
+    }
+
+    cy.wait(500);
+    waitFor(check, description, attempts - 1);
+  });
+}
+
+function expectRetention(policy, upload) {
+  if (policy === "keep") {
+    // Give the reaper a few chances to run.
+    cy.wait(3000);
+    isListed(upload).should("be.true");
+    getBlob(upload).should("deep.equal", { refs: 1, stored: true });
+  } else if (policy === "hide") {
+    waitFor(() => isListed(upload).then((listed) => !listed), "upload hidden");
+    isDeleted(upload).should("be.false");
+    getBlob(upload).should("deep.equal", { refs: 1, stored: true });
+  } else {
+    waitFor(() => isDeleted(upload), "upload deleted");
+    getBlob(upload).should("deep.equal", { refs: null, stored: false });
+  }
+}
+
+describe("Reaper", () => {
+  beforeEach(function () {
+    if (!EXPIRED_RETENTION || !EXHAUSTED_RETENTION) {
+      this.skip();
+    }
+
+    cy.initialUsers();
+  });
+
+  it("Applies the retention policy to expired uploads", () => {
+    cy.upload({ filename: "test-file.txt", owner: "user" }).then((upload) => {
+      cy.request({
+        method: "PATCH",
+        url: `/api/v1/uploads/${upload.id}`,
+        auth,
+        body: { expiry_date: yesterday() },
+      });
+
+      expectRetention(EXPIRED_RETENTION, upload);
+    });
+  });
+
+  it("Applies the retention policy to exhausted uploads", () => {
+    cy.upload({ filename: "test-file-2.txt", owner: "user" }).then((upload) => {
+      cy.request({
+        method: "PATCH",
+        url: `/api/v1/uploads/${upload.id}`,
+        auth,
+        body: { public: true, limit: 1 },
+      });
+
+      cy.request(`/uploads/${upload.slug}/download`);
+      expectRetention(EXHAUSTED_RETENTION, upload);
+    });
+  });
+});
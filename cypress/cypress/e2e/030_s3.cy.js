@@ -0,0 +1,150 @@
+import users from "../fixtures/users.json";
+
+// Whether the server was started with `--storage s3`, such as against a local MinIO server. Most of
+// the other tests work the same with either backend, but these check what ends up in the bucket.
+const S3 = Cypress.env("S3");
+
+const auth = { username: users.user.username, password: users.user.password };
+
+// Files larger than the 16 MiB part size are sent to the bucket in a multipart upload.
+const MIB = 1024 * 1024;
+const CHUNK_SIZE = 4 * MIB;
+const LARGE_SIZE = 17 * MIB;
+
+function upload(filename, chunks) {
+  const length = chunks.reduce((total, chunk) => total + chunk.length, 0);
+
+  return cy
+    .request({
+      method: "POST",
+      url: "/api/v1/tus",
+      auth,
+      headers: {
+        "Tus-Resumable": "1.0.0",
+        "Upload-Length": String(length),
+        "Upload-Metadata": `filename ${btoa(filename)}`,
+      },
+    })
+    .then((response) => {
+      const location = response.headers["location"];
+      let offset = 0;
+      let id = null;
+
+      for (const chunk of chunks) {
+        cy.request({
+          method: "PATCH",
+          url: location,
+          auth,
+          headers: {
+            "Tus-Resumable": "1.0.0",
+            "Upload-Offset": String(offset),
+            "Content-Type": "application/offset+octet-stream",
+          },
+          body: chunk,
+        }).then((response) => {
+          id = response.headers["parcel-upload"] || id;
+        });
+
+        offset += chunk.length;
+      }
+
+      return cy.then(() => id);
+    });
+}
+
+// The MIME type is found once the preview worker has fetched the file from the bucket.
+function waitForMimeType(id, attempts = 20) {
+  return cy
+    .request({ url: `/api/v1/uploads/${id}`, auth })
+    .its("body")
+    .then((upload) => {
+      if (upload.mime_type || attempts <= 1) {
+        expect(upload.mime_type, "MIME type of upload").to.eq("text/plain");
+        return upload;
+      }
+
+      cy.wait(500);
+      return waitForMimeType(id, attempts - 1);
+    });
+}
+
+function deleteUpload(upload) {
+  cy.request({ method: "DELETE", url: `/api/v1/uploads/${upload.id}`, auth })
+    .its("status")
+    .should("eq", 204);
+
+  cy.request(`/debug/blobs/${upload.hash}`)
+    .its("body")
+    .should("deep.equal", { refs: null, stored: false });
+}
+
+describe("S3 storage", () => {
+  beforeEach(function () {
+    if (!S3) {
+      this.skip();
+    }
+
+    cy.initialUsers();
+  });
+
+  it("Stores, fetches and deletes a small file", () => {
+    const content = "A file that is sent to the bucket in a single request.\n";
+
+    upload("small.txt", [content]).then((id) => {
+      waitForMimeType(id).then((upload) => {
+        expect(upload.size).to.eq(content.length);
+
+        cy.request(`/debug/blobs/${upload.hash}`)
+          .its("body")
+          .should("deep.equal", { refs: 1, stored: true });
+
+        cy.request({ url: `/api/v1/uploads/${id}/download`, auth })
+          .its("body")
+          .should("eq", content);
+
+        cy.request({
+          url: `/api/v1/uploads/${id}/download`,
+          auth,
+          headers: { Range: "bytes=2-5" },
+        })
+          .its("body")
+          .should("eq", "file");
+
+        deleteUpload(upload);
+      });
+    });
+  });
+
+  it("Stores, fetches and deletes a file in several parts", () => {
+    // Each chunk has its own letter, so that the parts can be told apart.
+    const chunks = [];
+    for (let offset = 0; offset < LARGE_SIZE; offset += CHUNK_SIZE) {
+      const letter = String.fromCharCode(97 + chunks.length);
+      chunks.push(letter.repeat(Math.min(CHUNK_SIZE, LARGE_SIZE - offset)));
+    }
+
+    upload("large.txt", chunks).then((id) => {
+      waitForMimeType(id).then((upload) => {
+        expect(upload.size).to.eq(LARGE_SIZE);
+
+        cy.request({ url: `/api/v1/uploads/${id}/download`, auth }).then(
+          (response) => {
+            expect(response.body.length).to.eq(LARGE_SIZE);
+            expect(response.body === chunks.join("")).to.be.true;
+          },
+        );
+
+        // A range across the end of the first part.
+        cy.request({
+          url: `/api/v1/uploads/${id}/download`,
+          auth,
+          headers: { Range: `bytes=${16 * MIB - 2}-${16 * MIB + 1}` },
+        })
+          .its("body")
+          .should("eq", "ddee");
+
+        deleteUpload(upload);
+      });
+    });
+  });
+});